@@ -0,0 +1,21 @@
+//! The types shared by the storage and the runtime of the virtual texturing system: pages, and
+//! the constants of their encoding in the shaders.
+
+pub mod shader_constants;
+
+mod page;
+
+pub use page::{PageId, UvRect};
+
+/// Identifies a virtual texture: its layer of the page table, and the [`PageId::texture_id`] of
+/// its pages.
+pub type VirtualTextureId = u8;
+
+#[macro_export]
+macro_rules! ensure {
+    ($cond:expr, $err:expr) => {
+        if !$cond {
+            return Err($err.into());
+        }
+    };
+}
@@ -0,0 +1,208 @@
+use crate::{shader_constants, VirtualTextureId};
+
+/// A rectangle of the texture in uv coordinates, from `min` (top left) to `max` (bottom right).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UvRect {
+    pub min: (f32, f32),
+    pub max: (f32, f32),
+}
+
+impl UvRect {
+    pub fn new(min: (f32, f32), max: (f32, f32)) -> Self {
+        Self { min, max }
+    }
+
+    /// The uv coordinates of the texture of `uv`, coordinates relative to the rectangle.
+    pub fn map(&self, uv: (f32, f32)) -> (f32, f32) {
+        (
+            self.min.0 + uv.0 * (self.max.0 - self.min.0),
+            self.min.1 + uv.1 * (self.max.1 - self.min.1),
+        )
+    }
+}
+
+/// A page of a virtual texture, with its coordinates in pages at its mip level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PageId {
+    page_x: u16,
+    page_y: u16,
+    mip_level: u8,
+    /// Identifies the virtual texture the page belongs to, 0 when there is only one.
+    texture_id: VirtualTextureId,
+}
+
+impl PageId {
+    /// The largest page coordinate that fits in the feedback encoding (12 bits), the last page of
+    /// the largest page table (see `Textures::MAX_PAGE_TABLE_SIZE` in `vt-runtime`).
+    pub const MAX_COORDINATE: u16 = (1 << 12) - 1;
+    /// The largest mip level that fits in the feedback encoding (4 bits), the last value being
+    /// reserved for [`PageId::INVALID_FEEDBACK`].
+    pub const MAX_MIP_LEVEL: u8 = shader_constants::FEEDBACK_MAX_MIP as u8;
+
+    pub fn new(mip_level: u8, page_x: u16, page_y: u16) -> Self {
+        Self::with_texture_id(0, mip_level, page_x, page_y)
+    }
+
+    /// A page of the virtual texture identified by `texture_id`.
+    pub fn with_texture_id(
+        texture_id: VirtualTextureId,
+        mip_level: u8,
+        page_x: u16,
+        page_y: u16,
+    ) -> Self {
+        Self {
+            page_x,
+            page_y,
+            mip_level,
+            texture_id,
+        }
+    }
+
+    pub fn mip_level(&self) -> u8 {
+        self.mip_level
+    }
+
+    pub fn x(&self) -> u16 {
+        self.page_x
+    }
+
+    pub fn y(&self) -> u16 {
+        self.page_y
+    }
+
+    pub fn texture_id(&self) -> VirtualTextureId {
+        self.texture_id
+    }
+
+    /// The page covering this one at the next coarser mip level.
+    ///
+    /// Returns `None` at [`PageId::MAX_MIP_LEVEL`]. The parent may still be past the last mip level
+    /// of the texture, see `TextureMetadata::mip_levels` in `vt-storage`.
+    pub fn parent(&self) -> Option<Self> {
+        (self.mip_level < Self::MAX_MIP_LEVEL).then(|| Self {
+            page_x: self.page_x / 2,
+            page_y: self.page_y / 2,
+            mip_level: self.mip_level + 1,
+            ..*self
+        })
+    }
+
+    /// Every coarser page covering this one, from the parent up to `max_mip_level`.
+    pub fn ancestors(&self, max_mip_level: u8) -> impl Iterator<Item = Self> {
+        std::iter::successors(self.parent(), Self::parent)
+            .take_while(move |page| page.mip_level <= max_mip_level)
+    }
+
+    /// The (up to) four pages covered by this one at the next finer mip level, none at mip level 0.
+    pub fn children(&self) -> impl Iterator<Item = Self> {
+        let page = *self;
+        (0..4)
+            .filter(move |_| page.mip_level > 0)
+            .map(move |child| Self {
+                page_x: page.page_x * 2 + child % 2,
+                page_y: page.page_y * 2 + child / 2,
+                mip_level: page.mip_level - 1,
+                ..page
+            })
+    }
+
+    /// The (up to) eight pages around this one at the same mip level, in a mip level of
+    /// `mip_dimensions` pages.
+    pub fn neighbors(&self, mip_dimensions: (u16, u16)) -> impl Iterator<Item = Self> {
+        let page = *self;
+        (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+            .filter(|&offset| offset != (0, 0))
+            .filter_map(move |(dx, dy): (i32, i32)| {
+                let x = page.page_x as i32 + dx;
+                let y = page.page_y as i32 + dy;
+                ((0..mip_dimensions.0 as i32).contains(&x)
+                    && (0..mip_dimensions.1 as i32).contains(&y))
+                .then_some(Self {
+                    page_x: x as u16,
+                    page_y: y as u16,
+                    ..page
+                })
+            })
+    }
+
+    /// Every page of a mip level of `mip_dimensions` pages intersecting `uv_rect`, in the order
+    /// of [`PageId`]s. The rectangle is clamped to the texture.
+    pub fn pages_covering(
+        uv_rect: UvRect,
+        mip_level: u8,
+        mip_dimensions: (u16, u16),
+    ) -> impl Iterator<Item = Self> {
+        let page_range = |min: f32, max: f32, pages: u16| {
+            let to_page = |uv: f32| ((uv * pages as f32).floor().max(0.0) as u16).min(pages - 1);
+            to_page(min)..=to_page(max.max(min))
+        };
+        let x_range = page_range(uv_rect.min.0, uv_rect.max.0, mip_dimensions.0);
+        let y_range = page_range(uv_rect.min.1, uv_rect.max.1, mip_dimensions.1);
+        y_range.flat_map(move |y| x_range.clone().map(move |x| Self::new(mip_level, x, y)))
+    }
+
+    /// The feedback value written by the shader when the page could not be computed.
+    pub const INVALID_FEEDBACK: [u8; 4] = [0xFF; 4];
+    /// The feedback value of texels no fragment wrote since the feedback texture was cleared,
+    /// see `Pipelines::FEEDBACK_CLEAR_COLOR` in `vt-runtime`.
+    pub const NO_REQUEST_FEEDBACK: [u8; 4] = [0, 0, 0, Self::INVALID_MIP_LEVEL];
+    /// The mip level of [`PageId::INVALID_FEEDBACK`] and [`PageId::NO_REQUEST_FEEDBACK`], never
+    /// used by a valid page.
+    const INVALID_MIP_LEVEL: u8 = shader_constants::FEEDBACK_INVALID_MIP as u8;
+
+    /// Decode a texel of the feedback texture, or `None` if it holds
+    /// [`PageId::INVALID_FEEDBACK`] or [`PageId::NO_REQUEST_FEEDBACK`].
+    pub fn from_feedback(bytes: &[u8]) -> Option<Self> {
+        let page = Self::from_bytes(bytes);
+        (page.mip_level != Self::INVALID_MIP_LEVEL).then_some(page)
+    }
+
+    /// Decode a page from the format of the feedback texture, see [`PageId::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        debug_assert!(bytes.len() == 4);
+
+        let page_x = (bytes[0] as u16) << 4 | (bytes[1] >> 4) as u16;
+        let page_y = ((bytes[1] & 0xF) as u16) << 8 | bytes[2] as u16;
+        let texture_id = bytes[3] >> 4;
+        let mip_level = bytes[3] & 0xF;
+        Self::with_texture_id(texture_id, mip_level, page_x, page_y)
+    }
+
+    /// Encode the page in the format of the feedback texture (Rgba8Uint):
+    /// (R: x high (8), G: x low (4) y high (4), B: y low (8), A: texture id (4) mip level (4)).
+    ///
+    /// ### Panics
+    ///
+    /// - If a coordinate is over [`PageId::MAX_COORDINATE`], the mip level is over
+    ///   [`PageId::MAX_MIP_LEVEL`] or the texture id is not below
+    ///   [`FEEDBACK_MAX_TEXTURES`](shader_constants::FEEDBACK_MAX_TEXTURES).
+    pub fn to_bytes(&self) -> [u8; 4] {
+        assert!(self.page_x <= Self::MAX_COORDINATE && self.page_y <= Self::MAX_COORDINATE);
+        assert!(self.mip_level <= Self::MAX_MIP_LEVEL);
+        assert!((self.texture_id as u32) < shader_constants::FEEDBACK_MAX_TEXTURES);
+        [
+            (self.page_x >> 4) as u8,
+            ((self.page_x & 0xF) << 4) as u8 | (self.page_y >> 8) as u8,
+            self.page_y as u8,
+            self.texture_id << 4 | self.mip_level,
+        ]
+    }
+}
+
+impl PartialOrd for PageId {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// PageIds are sorted by mip level, then by y, then by x, then by texture id.
+impl Ord for PageId {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.mip_level
+            .cmp(&other.mip_level)
+            .then(self.page_y.cmp(&other.page_y))
+            .then(self.page_x.cmp(&other.page_x))
+            .then(self.texture_id.cmp(&other.texture_id))
+    }
+}
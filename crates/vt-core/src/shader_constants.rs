@@ -0,0 +1,74 @@
+//! Constants shared by the Rust code and the shaders, declared once for both.
+//!
+//! Every block is also a WGSL source declaring its constants, prepended to the shaders that use
+//! them by `Pipelines` in `vt-runtime`. The Rust constants mirroring them (e.g.,
+//! `Textures::PAGE_TABLE_RESIDENT`) are defined from these, so that the two can not drift apart.
+
+macro_rules! shader_constants {
+    ($(#[$block_doc:meta])* $block:ident { $($(#[$doc:meta])* $name:ident = $value:literal;)* }) => {
+        $($(#[$doc])* pub const $name: u32 = $value;)*
+
+        $(#[$block_doc])*
+        pub const $block: &str = concat!(
+            $("const ", stringify!($name), ": u32 = ", stringify!($value), "u;\n",)*
+        );
+    };
+}
+
+shader_constants! {
+    /// The constants of the feedback encoding, declared by
+    /// `Pipelines::feedback_shader_snippet`.
+    FEEDBACK_WGSL {
+        /// The largest mip level written to the feedback, see
+        /// [`PageId::MAX_MIP_LEVEL`](crate::PageId::MAX_MIP_LEVEL).
+        FEEDBACK_MAX_MIP = 14;
+        /// The mip level of the invalid feedback texels.
+        FEEDBACK_INVALID_MIP = 15;
+        /// The number of virtual textures the feedback encoding identifies (4 bits), see
+        /// `Textures::MAX_VIRTUAL_TEXTURES`.
+        FEEDBACK_MAX_TEXTURES = 16;
+    }
+}
+
+shader_constants! {
+    /// The constants of the page table entries, declared by the virtual texture snippet.
+    PAGE_TABLE_WGSL {
+        /// Flag of the entries pointing to a resident page.
+        PAGE_TABLE_RESIDENT = 1;
+        /// Flag of the entries pointing to a page in the hot tier.
+        PAGE_TABLE_HOT = 2;
+        /// The shift of the scale of coarse pages in the flags.
+        PAGE_TABLE_SCALE_SHIFT = 2;
+        /// The mask of the scale of coarse pages once shifted.
+        PAGE_TABLE_SCALE_MASK = 3;
+        /// The shift of the array layer of the slot in the blue channel, above the mip level of
+        /// the page.
+        PAGE_TABLE_LAYER_SHIFT = 4;
+        /// The mask of the mip level of the page in the blue channel.
+        PAGE_TABLE_MIP_MASK = 15;
+    }
+}
+
+shader_constants! {
+    /// The constants of the feedback reduction shader.
+    FEEDBACK_REDUCTION_WGSL {
+        /// The size of the workgroups on each side.
+        FEEDBACK_REDUCTION_WORKGROUP_SIZE = 8;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn constants_are_valid_wgsl() {
+        let source = [
+            super::FEEDBACK_WGSL,
+            super::PAGE_TABLE_WGSL,
+            super::FEEDBACK_REDUCTION_WGSL,
+        ]
+        .concat();
+        let module = naga::front::wgsl::parse_str(&source).unwrap();
+        assert_eq!(module.constants.len(), 10);
+        assert!(source.contains("const PAGE_TABLE_SCALE_SHIFT: u32 = 2u;\n"));
+    }
+}
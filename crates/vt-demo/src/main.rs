@@ -0,0 +1,103 @@
+use std::{path::Path, sync::Arc};
+
+use virt_texture::{
+    config::VirtualTexturingConfig,
+    debug::DebugOverlayOptions,
+    draw::{DrawItem, Mesh},
+    pipelines::SamplingQuality,
+    power::PowerMode,
+    setup::{VirtualTexturingContext, WgpuContext},
+    vertex::FOUR_TRIANGLES,
+};
+use winit::{
+    event::{ElementState, Event, KeyEvent, WindowEvent},
+    keyboard::Key,
+};
+
+fn main() {
+    // The first argument is an optional path to a json configuration.
+    let config = std::env::args()
+        .nth(1)
+        .map(|path| VirtualTexturingConfig::load(Path::new(&path)).unwrap())
+        .unwrap_or_default();
+
+    let event_loop = winit::event_loop::EventLoop::new()
+        .expect("the event loop creation to succeed since we are on the main thread");
+    let window = winit::window::WindowBuilder::new()
+        .with_title("Virtual Texturing Demo")
+        .build(&event_loop)
+        .unwrap();
+
+    let wgpu_context = Arc::new(pollster::block_on(WgpuContext::new(window)));
+    let mut context = VirtualTexturingContext::from_config(wgpu_context, config);
+    let items = [DrawItem::new(Arc::new(Mesh::new(
+        &context.wgpu_context,
+        &FOUR_TRIANGLES,
+    )))];
+    // Save energy on battery, whatever the configuration says.
+    if PowerMode::detect() == Some(PowerMode::LowPower) {
+        context.set_power_mode(PowerMode::LowPower);
+    }
+    // Set by the C key, so that the next frame is captured by an attached frame debugger
+    // (RenderDoc, only available on the Vulkan and GL backends).
+    let mut capture_next_frame = false;
+
+    event_loop
+        .run(|event, target| {
+            if let Event::WindowEvent { event, .. } = event {
+                match event {
+                    WindowEvent::CloseRequested => target.exit(),
+                    WindowEvent::Resized(size) => context.resize(size),
+                    // 1, 2 and 3 switch between the sampling qualities to compare them, P toggles
+                    // the low power mode, O the debug overlay, C captures the next frame.
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                logical_key: Key::Character(key),
+                                state: ElementState::Pressed,
+                                ..
+                            },
+                        ..
+                    } => {
+                        match key.as_str() {
+                            "1" => context.set_sampling_quality(SamplingQuality::Nearest),
+                            "2" => context.set_sampling_quality(SamplingQuality::Linear),
+                            "3" => context.set_sampling_quality(SamplingQuality::Trilinear),
+                            "p" => context.set_power_mode(match context.config().power_mode {
+                                PowerMode::Performance => PowerMode::LowPower,
+                                PowerMode::LowPower => PowerMode::Performance,
+                            }),
+                            "o" => context.set_debug_overlay(match context.debug_overlay() {
+                                Some(_) => None,
+                                None => Some(DebugOverlayOptions::default()),
+                            }),
+                            "c" => capture_next_frame = true,
+                            _ => return,
+                        }
+                        context
+                            .wgpu_context
+                            .window
+                            .as_ref()
+                            .unwrap()
+                            .request_redraw();
+                    }
+                    WindowEvent::RedrawRequested => {
+                        println!("drawing");
+                        if capture_next_frame {
+                            context.wgpu_context.device.start_capture();
+                        }
+                        let frame = context.begin_frame(&items);
+                        let output = context
+                            .end_frame(frame, None)
+                            .expect("the context to have a surface");
+                        if std::mem::take(&mut capture_next_frame) {
+                            context.wgpu_context.device.stop_capture();
+                        }
+                        output.present();
+                    }
+                    _ => (),
+                }
+            }
+        })
+        .unwrap();
+}
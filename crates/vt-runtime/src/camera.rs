@@ -26,14 +26,17 @@ impl Camera {
         }
     }
 
-    fn view_proj_matrix(&self, projection: &CameraProjection) -> nalgebra::Matrix4<f32> {
+    /// The unit vector the camera looks along.
+    pub fn direction(&self) -> nalgebra::Vector3<f32> {
         let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
         let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        nalgebra::Vector3::new(cos_yaw * cos_pitch, sin_pitch, sin_yaw * cos_pitch)
+    }
 
+    fn view_proj_matrix(&self, projection: &CameraProjection) -> nalgebra::Matrix4<f32> {
         let view = nalgebra::Matrix4::look_at_rh(
             &self.position,
-            &(self.position
-                + nalgebra::Vector3::new(cos_yaw * cos_pitch, sin_pitch, sin_yaw * cos_pitch)),
+            &(self.position + self.direction()),
             &nalgebra::Vector3::y(),
         );
 
@@ -43,6 +46,27 @@ impl Camera {
 
 pub type CameraProjection = nalgebra::Perspective3<f32>;
 
+/// How fast a camera moves, measured over its last update by [`CameraModule::motion`], for
+/// [`PrefetchPolicy`](crate::streaming::PrefetchPolicy) to stream in where it is going.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CameraMotion {
+    /// World units per second.
+    pub velocity: nalgebra::Vector3<f32>,
+    /// The part of the velocity along the view direction, positive when moving forward.
+    pub forward_speed: f32,
+    /// Radians per second the view direction turns by.
+    pub angular_speed: f32,
+}
+
+impl CameraMotion {
+    /// Below these speeds, the camera is considered still.
+    const STILL_SPEED: f32 = 1e-4;
+
+    pub fn is_moving(&self) -> bool {
+        self.velocity.norm() > Self::STILL_SPEED || self.angular_speed > Self::STILL_SPEED
+    }
+}
+
 #[derive(Debug)]
 pub struct CameraController {
     amount_left: f32,
@@ -139,11 +163,7 @@ impl CameraController {
         self.rotate_vertical = 0.0;
 
         // Keep the camera's angle from going too high/low.
-        if camera.pitch < -SAFE_FRAC_PI_2 {
-            camera.pitch = -SAFE_FRAC_PI_2;
-        } else if camera.pitch > SAFE_FRAC_PI_2 {
-            camera.pitch = SAFE_FRAC_PI_2;
-        }
+        camera.pitch = camera.pitch.clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2);
     }
 }
 
@@ -152,6 +172,7 @@ pub struct CameraModule {
     pub camera: Camera,
     projection: CameraProjection,
     pub controller: CameraController,
+    motion: CameraMotion,
 }
 
 impl CameraModule {
@@ -164,11 +185,31 @@ impl CameraModule {
             camera,
             projection,
             controller,
+            motion: CameraMotion::default(),
         }
     }
 
+    /// Move the camera with its controller, and measure its [`CameraModule::motion`].
     pub fn update(&mut self, delta_time: Duration) {
+        let (position, yaw, pitch) = (self.camera.position, self.camera.yaw, self.camera.pitch);
         self.controller.update_camera(&mut self.camera, delta_time);
+        let dt = delta_time.as_secs_f32();
+        self.motion = match dt > 0.0 {
+            true => {
+                let velocity = (self.camera.position - position) / dt;
+                CameraMotion {
+                    velocity,
+                    forward_speed: velocity.dot(&self.camera.direction()),
+                    angular_speed: (self.camera.yaw - yaw).hypot(self.camera.pitch - pitch) / dt,
+                }
+            }
+            false => CameraMotion::default(),
+        };
+    }
+
+    /// The motion of the camera over the last [`CameraModule::update`].
+    pub fn motion(&self) -> CameraMotion {
+        self.motion
     }
 
     pub fn view_proj_matrix(&self) -> nalgebra::Matrix4<f32> {
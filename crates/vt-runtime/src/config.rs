@@ -0,0 +1,580 @@
+//! Serializable configuration of the virtual texturing system.
+
+use std::path::Path;
+
+use miniserde::{json::Value, Deserialize, MiniSerialize};
+use thiserror::Error;
+
+use crate::{
+    pipelines::{DepthMode, FeedbackMode, SamplingQuality, Tonemap},
+    power::PowerMode,
+    storage::{
+        anisotropic_border_size, ColorSpace, PageEncoding, TexelFormat, DEFAULT_PAGE_BORDER_SIZE,
+        DEFAULT_PAGE_SIZE,
+    },
+    streaming::StreamingConfig,
+};
+
+/// Every tunable of the virtual texturing system.
+///
+/// Configurations are stored as json, or as toml with the `toml` feature, so that they can be
+/// checked into projects and performance reports reproduced exactly. The fields missing from a
+/// stored configuration, such as the ones added after it was written, take their default value.
+#[derive(MiniSerialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VirtualTexturingConfig {
+    /// The number of pages on the side of the page table (must be a power of two, at most
+    /// [`Textures::MAX_PAGE_TABLE_SIZE`](crate::textures::Textures::MAX_PAGE_TABLE_SIZE)).
+    pub page_table_size: u32,
+    /// The number of virtual textures bound at once (e.g., a terrain and a decal atlas), each
+    /// with its own layer of the page table, in `1..=`[`Textures::MAX_VIRTUAL_TEXTURES`]. Their
+    /// pages share the slots of the physical textures, so they must have the layers of
+    /// [`VirtualTexturingConfig::layer_encodings`].
+    ///
+    /// [`Textures::MAX_VIRTUAL_TEXTURES`]: crate::textures::Textures::MAX_VIRTUAL_TEXTURES
+    pub virtual_textures: u32,
+    /// Keep two page tables, sampled on alternate frames, so that the entries written while a
+    /// frame is built only go to the table it does not sample, see
+    /// [`Textures::write_page_table_entry`](crate::textures::Textures::write_page_table_entry).
+    /// Takes twice the memory of the page table.
+    pub double_buffer_page_table: bool,
+    /// The encoding of every layer of the pages streamed in (see
+    /// [`TextureMetadata::layers`](crate::storage::TextureMetadata::layers)), with one physical
+    /// texture per layer.
+    pub layer_encodings: Vec<PageEncoding>,
+    /// The color space of every layer, in the order of
+    /// [`VirtualTexturingConfig::layer_encodings`], which sets the format of the views of its
+    /// physical texture. The layers past the list use [`ColorSpace::for_encoding`]. Must match
+    /// [`TextureLayer::color_space`](crate::storage::TextureLayer::color_space).
+    pub layer_color_spaces: Vec<ColorSpace>,
+    /// The format of the texels of the pages streamed in, only RGBA8 textures having block
+    /// compressed layers.
+    /// Must match
+    /// [`TextureMetadata::texel_format`](crate::storage::TextureMetadata::texel_format).
+    pub texel_format: TexelFormat,
+    /// Pack the texels of [`TexelFormat::Rgba16Float`] pages in `Rg11b10Float` physical
+    /// textures, which take half the memory of `Rgba16Float` ones, but have no alpha channel and
+    /// 6 bits of mantissa (5 for blue) instead of 10.
+    pub pack_hdr: bool,
+    /// The size of the side of the pages, borders included. Must match
+    /// [`TextureMetadata::page_size`](crate::storage::TextureMetadata::page_size).
+    pub page_size: u32,
+    /// Must match [`TextureMetadata::border_size`](crate::storage::TextureMetadata::border_size),
+    /// and be at least [`anisotropic_border_size`] of [`VirtualTexturingConfig::max_anisotropy`].
+    pub border_size: u32,
+    /// The largest ratio between the axes of the footprint of a pixel filtered by the linear and
+    /// trilinear sampling qualities, from 1 (no anisotropic filtering) to 16. Set along with the
+    /// border with [`VirtualTexturingConfig::with_max_anisotropy`].
+    pub max_anisotropy: u16,
+    /// The mip levels of the physical textures, at least 1. Past 1, every page is uploaded with
+    /// its own downsampled levels in the mip chain of its slot, and
+    /// [`SamplingQuality::Trilinear`] blends them instead of the pages of two mip levels, so that
+    /// a page replacing the one of another mip level does not pop.
+    ///
+    /// The border and the page size must stay whole on the coarsest level, see
+    /// [`VirtualTexturingConfig::max_physical_mip_levels`].
+    pub physical_mip_levels: u32,
+    pub feedback_mode: FeedbackMode,
+    /// See [`DepthMode`].
+    pub depth_mode: DepthMode,
+    /// The samples per pixel of the render pass, resolved to the render target: 1 to disable
+    /// multisampling, or 2, 4 or 8. Every device supports 4, but not all support 2 and 8. The
+    /// prepass is single sampled whatever the count, so multisampling requires
+    /// [`DepthMode::Separate`].
+    pub msaa_samples: u32,
+    /// The ratio between the sides of the prepass target and of the render target with
+    /// [`FeedbackMode::Separate`] and [`FeedbackMode::StorageBuffer`], in `(0, 1]`. Smaller
+    /// ratios reduce less feedback, but miss more of the pages covering few texels.
+    ///
+    /// Can be changed at runtime with
+    /// [`VirtualTexturingContext::set_prepass_ratio`](crate::setup::VirtualTexturingContext::set_prepass_ratio).
+    pub prepass_ratio: f32,
+    /// The feedback frames over which the requests accumulate in the feedback texture before it is
+    /// cleared to [`Pipelines::FEEDBACK_CLEAR_COLOR`](crate::pipelines::Pipelines::FEEDBACK_CLEAR_COLOR).
+    ///
+    /// With 1, every feedback frame only reads back the pages it requested. With `N`, the
+    /// texels left untouched by a frame keep the requests of the previous ones until the
+    /// target is cleared every `N` feedback frames, which helps coverage of thin geometry at a
+    /// low [`VirtualTexturingConfig::prepass_ratio`] but keeps requesting pages that went out of
+    /// view. With 0, the target is only cleared when it is created.
+    pub prepass_clear_interval: u32,
+    /// Build a Hi-Z pyramid of the depth of every frame, against which the prepass of
+    /// [`FeedbackMode::StorageBuffer`] drops the requests of the fragments hidden by others, which
+    /// it would otherwise request for scenes with a lot of overdraw. The depth test of the other
+    /// feedback modes already keeps the closest fragments only.
+    ///
+    /// Requires [`FeedbackMode::StorageBuffer`] and a single sample, see
+    /// [`HiZ`](crate::pipelines::HiZ).
+    pub hi_z: bool,
+    /// The quality bias of the feedback, see
+    /// [`VirtualTexturingContext::set_quality_bias`](crate::setup::VirtualTexturingContext::set_quality_bias).
+    pub lod_bias: f32,
+    /// Can be changed at runtime with
+    /// [`VirtualTexturingContext::set_sampling_quality`](crate::setup::VirtualTexturingContext::set_sampling_quality).
+    pub sampling_quality: SamplingQuality,
+    /// Multiplier applied to the sampled color, before the tone mapping.
+    pub exposure: f32,
+    pub tonemap: Tonemap,
+    /// The size of the physical textures of the cold tier, see [`PhysicalTextureConfig`].
+    pub physical_texture: PhysicalTextureConfig,
+    /// Splits the physical cache in two tiers when set, see [`HotCacheConfig`].
+    pub hot_cache: Option<HotCacheConfig>,
+    /// Can be changed at runtime, see [`PowerMode`].
+    pub power_mode: PowerMode,
+    /// The frames submitted whose work may still run on the GPU when a frame begins, at least 1.
+    /// [`VirtualTexturingContext::begin_frame`](crate::setup::VirtualTexturingContext::begin_frame)
+    /// waits for the oldest one past this, so fewer frames in flight bound the latency of the
+    /// feedback and of the page uploads at the cost of stalls.
+    pub max_frames_in_flight: u32,
+    /// The feedback readbacks that may be pending at once, from 1 to
+    /// [`StreamingHandle::FEEDBACK_READ_BUFFERS`](crate::streaming::StreamingHandle::FEEDBACK_READ_BUFFERS).
+    /// The feedback of a frame is skipped past this, instead of waiting for a readback.
+    pub max_feedback_in_flight: u32,
+    /// The budgets of the streaming work of every frame, see
+    /// [`StreamingHandle::stream_queued`](crate::streaming::StreamingHandle::stream_queued). Can
+    /// be changed at runtime with
+    /// [`VirtualTexturingContext::set_streaming_config`](crate::setup::VirtualTexturingContext::set_streaming_config).
+    pub streaming: StreamingConfig,
+    /// Fill the physical textures with a magenta and black checker at startup, so that sampling
+    /// a slot no page was written to is obvious instead of showing undefined memory.
+    pub debug_fill: bool,
+}
+
+/// The slots of the physical textures of the cold tier, which set their memory budget.
+///
+/// Every physical texture is a texture array of `layers` array layers of
+/// `page_slots_x * page_slots_y` slots of pages, so that the cache may grow past the largest 2D
+/// texture of the device. Slot `(x, y)` is in array layer `y / page_slots_y`, see
+/// [`Textures::slot_layout`](crate::textures::Textures::slot_layout).
+#[derive(MiniSerialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PhysicalTextureConfig {
+    /// The slots on each row of an array layer, up to 256.
+    pub page_slots_x: u32,
+    /// The slots on each column of an array layer, up to 256.
+    pub page_slots_y: u32,
+    /// The array layers, from 1 to
+    /// [`Textures::MAX_PHYSICAL_LAYERS`](crate::textures::Textures::MAX_PHYSICAL_LAYERS).
+    pub layers: u32,
+}
+
+impl PhysicalTextureConfig {
+    /// The number of slots of regular pages, in every array layer.
+    pub fn slot_count(&self) -> u32 {
+        self.page_slots_x * self.page_slots_y * self.layers
+    }
+
+    /// The size in bytes of a physical texture of pages of `page_size` texels of
+    /// `bytes_per_texel` (e.g., 4 for RGBA8, 1 for BC7), without its mip chain.
+    pub fn byte_size(&self, page_size: u32, bytes_per_texel: u32) -> u64 {
+        self.slot_count() as u64 * (page_size * page_size) as u64 * bytes_per_texel as u64
+    }
+}
+
+impl Default for PhysicalTextureConfig {
+    /// 4096 by 4096 texels with the default page size, 64MiB of RGBA8 texels.
+    fn default() -> Self {
+        Self {
+            page_slots_x: 32,
+            page_slots_y: 32,
+            layers: 1,
+        }
+    }
+}
+
+/// A small "hot" physical texture for the pages of the finest mip levels, which come and go as
+/// the camera moves, next to the main "cold" physical texture for the coarse mip levels, which
+/// rarely change.
+///
+/// Keeping the churn of the fine pages in their own texture avoids fragmenting the slots of the
+/// coarse ones. Every layer gets a texture in each tier.
+#[derive(MiniSerialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct HotCacheConfig {
+    /// The size of the side of the hot physical textures in texels (a multiple of the page size).
+    pub size: u32,
+    /// The coarsest mip level whose pages go to the hot tier, mip level 0 included.
+    pub max_mip: u8,
+}
+
+impl Default for HotCacheConfig {
+    fn default() -> Self {
+        Self {
+            size: 2048,
+            max_mip: 2,
+        }
+    }
+}
+
+impl Default for VirtualTexturingConfig {
+    fn default() -> Self {
+        Self {
+            page_table_size: 2048,
+            virtual_textures: 1,
+            double_buffer_page_table: false,
+            layer_encodings: vec![PageEncoding::Raw],
+            layer_color_spaces: Vec::new(),
+            texel_format: TexelFormat::Rgba8,
+            pack_hdr: false,
+            page_size: DEFAULT_PAGE_SIZE as u32,
+            border_size: DEFAULT_PAGE_BORDER_SIZE as u32,
+            max_anisotropy: 1,
+            physical_mip_levels: 1,
+            feedback_mode: FeedbackMode::Separate,
+            depth_mode: DepthMode::Separate,
+            msaa_samples: 1,
+            prepass_ratio: 0.1,
+            prepass_clear_interval: 1,
+            hi_z: false,
+            lod_bias: 0.0,
+            sampling_quality: SamplingQuality::Linear,
+            exposure: 1.0,
+            tonemap: Tonemap::None,
+            physical_texture: Default::default(),
+            hot_cache: None,
+            power_mode: PowerMode::Performance,
+            max_frames_in_flight: 2,
+            max_feedback_in_flight: 3,
+            streaming: Default::default(),
+            debug_fill: false,
+        }
+    }
+}
+
+impl VirtualTexturingConfig {
+    /// Parse a json configuration, the fields it is missing taking their value in
+    /// [`VirtualTexturingConfig::default`].
+    pub fn from_json(json: &str) -> Result<Self, ConfigError> {
+        Self::from_value(miniserde::json::from_str(json)?)
+    }
+
+    /// Parse a toml configuration, like [`VirtualTexturingConfig::from_json`], with the same
+    /// fields and values as json, `hot_cache` being omitted when it is `None`.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(toml: &str) -> Result<Self, ConfigError> {
+        Self::from_value(toml_to_json(toml.parse()?))
+    }
+
+    /// Merge `value` over the default configuration. `hot_cache` is merged over
+    /// [`HotCacheConfig::default`] when it is set, so that its missing fields take their default
+    /// value too.
+    fn from_value(value: Value) -> Result<Self, ConfigError> {
+        let sets_hot_cache =
+            matches!(&value, Value::Object(object) if object.contains_key("hot_cache"));
+        let default = Self {
+            hot_cache: sets_hot_cache.then(Default::default),
+            ..<Self as Default>::default()
+        };
+        let mut merged = miniserde::json::from_str(&default.to_json())?;
+        merge(&mut merged, value);
+        Ok(miniserde::json::from_str(&miniserde::json::to_string(
+            &merged,
+        ))?)
+    }
+
+    /// Filter with up to `max_anisotropy` anisotropic filtering, widening
+    /// [`VirtualTexturingConfig::border_size`] to [`anisotropic_border_size`] if needed. The
+    /// textures streamed in must be stored with that border (see
+    /// [`TextureMetadata::with_page_size`](crate::storage::TextureMetadata::with_page_size)).
+    pub fn with_max_anisotropy(mut self, max_anisotropy: u16) -> Self {
+        self.max_anisotropy = max_anisotropy;
+        self.border_size = self
+            .border_size
+            .max(anisotropic_border_size(max_anisotropy) as u32);
+        self
+    }
+
+    /// The most mip levels of the physical textures keeping a border of at least a texel, and
+    /// a page size that is a multiple of the size of compressed blocks, on their coarsest level.
+    pub fn max_physical_mip_levels(&self) -> u32 {
+        let border_levels = self.border_size.max(1).ilog2() + 1;
+        let page_levels = (self.page_size / 4).max(1).trailing_zeros() + 1;
+        border_levels.min(page_levels)
+    }
+
+    /// The color space of layer `layer`, see [`VirtualTexturingConfig::layer_color_spaces`].
+    pub fn layer_color_space(&self, layer: usize) -> ColorSpace {
+        self.layer_color_spaces
+            .get(layer)
+            .copied()
+            .unwrap_or_else(|| ColorSpace::for_encoding(self.layer_encodings[layer]))
+    }
+
+    pub fn to_json(&self) -> String {
+        miniserde::json::to_string(self)
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn to_toml(&self) -> String {
+        let value = miniserde::json::from_str(&self.to_json()).expect("the json to be valid");
+        let Some(toml::Value::Table(table)) = json_to_toml(value) else {
+            unreachable!("a configuration is a json object");
+        };
+        toml::to_string(&table).expect("the configuration to be serializable")
+    }
+
+    /// Load the configuration at `path`, as toml if it has the `toml` extension and the `toml`
+    /// feature is enabled, as json otherwise.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let contents = std::fs::read_to_string(path)?;
+        #[cfg(feature = "toml")]
+        if is_toml(path) {
+            return Self::from_toml(&contents);
+        }
+        Self::from_json(&contents)
+    }
+
+    /// Save the configuration to `path`, in the format [`VirtualTexturingConfig::load`] reads
+    /// it in.
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        #[cfg(feature = "toml")]
+        if is_toml(path) {
+            return Ok(std::fs::write(path, self.to_toml())?);
+        }
+        Ok(std::fs::write(path, self.to_json())?)
+    }
+}
+
+/// Replace the values of `base` by the ones of `value`, merging the objects of both field by
+/// field.
+fn merge(base: &mut Value, value: Value) {
+    match (base, value) {
+        (Value::Object(base), Value::Object(object)) => {
+            object
+                .into_iter()
+                .for_each(|(key, value)| match base.get_mut(&key) {
+                    Some(base) => merge(base, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                })
+        }
+        (base, value) => *base = value,
+    }
+}
+
+#[cfg(feature = "toml")]
+fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "toml")
+}
+
+#[cfg(feature = "toml")]
+fn toml_to_json(value: toml::Value) -> Value {
+    use miniserde::json::Number;
+
+    match value {
+        toml::Value::String(string) => Value::String(string),
+        toml::Value::Integer(integer) => Value::Number(match u64::try_from(integer) {
+            Ok(integer) => Number::U64(integer),
+            Err(_) => Number::I64(integer),
+        }),
+        toml::Value::Float(float) => Value::Number(Number::F64(float)),
+        toml::Value::Boolean(boolean) => Value::Bool(boolean),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(array) => Value::Array(array.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// `value` as toml, or `None` for `null`, which toml has no value for: the fields set to it are
+/// left out.
+#[cfg(feature = "toml")]
+fn json_to_toml(value: Value) -> Option<toml::Value> {
+    use miniserde::json::Number;
+
+    Some(match value {
+        Value::Null => return None,
+        Value::Bool(boolean) => toml::Value::Boolean(boolean),
+        Value::Number(Number::U64(integer)) => toml::Value::Integer(integer as i64),
+        Value::Number(Number::I64(integer)) => toml::Value::Integer(integer),
+        Value::Number(Number::F64(float)) => toml::Value::Float(float),
+        Value::String(string) => toml::Value::String(string),
+        Value::Array(array) => {
+            toml::Value::Array(array.into_iter().filter_map(json_to_toml).collect())
+        }
+        Value::Object(object) => toml::Value::Table(
+            object
+                .into_iter()
+                .filter_map(|(key, value)| Some((key, json_to_toml(value)?)))
+                .collect(),
+        ),
+    })
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("could not parse the configuration")]
+    Deserialization(#[from] miniserde::Error),
+    #[cfg(feature = "toml")]
+    #[error("could not parse the toml configuration: {0}")]
+    Toml(#[from] toml::de::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HotCacheConfig, PhysicalTextureConfig, VirtualTexturingConfig};
+    use crate::{
+        pipelines::{DepthMode, FeedbackMode, SamplingQuality, Tonemap},
+        power::PowerMode,
+        storage::{ColorSpace, PageEncoding},
+        streaming::StreamingConfig,
+    };
+
+    #[test]
+    fn config_round_trip() {
+        let config = VirtualTexturingConfig {
+            layer_encodings: vec![PageEncoding::Bc7, PageEncoding::Bc5],
+            layer_color_spaces: vec![ColorSpace::Linear],
+            pack_hdr: true,
+            feedback_mode: FeedbackMode::Interleaved,
+            depth_mode: DepthMode::ReusePrepass,
+            msaa_samples: 4,
+            prepass_ratio: 0.25,
+            prepass_clear_interval: 4,
+            hi_z: true,
+            virtual_textures: 3,
+            double_buffer_page_table: true,
+            lod_bias: -0.5,
+            page_size: 64,
+            border_size: 2,
+            max_anisotropy: 4,
+            physical_mip_levels: 2,
+            sampling_quality: SamplingQuality::Trilinear,
+            exposure: 2.0,
+            tonemap: Tonemap::Aces,
+            physical_texture: PhysicalTextureConfig {
+                page_slots_x: 16,
+                page_slots_y: 8,
+                layers: 3,
+            },
+            hot_cache: Some(HotCacheConfig::default()),
+            power_mode: PowerMode::LowPower,
+            max_frames_in_flight: 1,
+            max_feedback_in_flight: 2,
+            streaming: StreamingConfig {
+                max_uploads_per_frame: 16,
+                max_bytes_per_frame: 1 << 20,
+                max_io_micros: 2500,
+            },
+            debug_fill: true,
+            ..Default::default()
+        };
+        let parsed = VirtualTexturingConfig::from_json(&config.to_json()).unwrap();
+        assert_eq!(parsed, config);
+        assert_eq!(
+            (parsed.layer_color_space(0), parsed.layer_color_space(1)),
+            (ColorSpace::Linear, ColorSpace::Linear)
+        );
+    }
+
+    /// The border is only widened past the default for 8x and 16x anisotropic filtering.
+    #[test]
+    fn anisotropic_borders() {
+        let config = VirtualTexturingConfig::default().with_max_anisotropy(4);
+        assert_eq!((config.max_anisotropy, config.border_size), (4, 4));
+        let config = VirtualTexturingConfig::default().with_max_anisotropy(16);
+        assert_eq!(config.border_size, 10);
+        assert_eq!(config.with_max_anisotropy(8).border_size, 10);
+    }
+
+    #[test]
+    fn physical_mip_levels() {
+        let config = VirtualTexturingConfig::default();
+        assert_eq!(config.max_physical_mip_levels(), 3);
+        let config = VirtualTexturingConfig {
+            page_size: 8,
+            border_size: 2,
+            ..config
+        };
+        assert_eq!(config.max_physical_mip_levels(), 2);
+    }
+
+    #[test]
+    fn physical_texture_budget() {
+        let physical_texture = PhysicalTextureConfig::default();
+        assert_eq!(physical_texture.byte_size(128, 4), 64 << 20);
+        let physical_texture = PhysicalTextureConfig {
+            layers: 4,
+            ..physical_texture
+        };
+        assert_eq!(physical_texture.slot_count(), 4096);
+    }
+
+    #[test]
+    fn config_without_hot_cache() {
+        let json = VirtualTexturingConfig::default()
+            .to_json()
+            .replace(",\"hot_cache\":null", "");
+        assert!(!json.contains("hot_cache"));
+        let parsed = VirtualTexturingConfig::from_json(&json).unwrap();
+        assert_eq!(parsed.hot_cache, None);
+    }
+
+    /// The fields missing from older configurations take their default value.
+    #[test]
+    fn config_with_missing_fields() {
+        let json = r#"{"page_table_size":512,"physical_texture":{"layers":2},"streaming":{"max_io_micros":1000}}"#;
+        let parsed = VirtualTexturingConfig::from_json(json).unwrap();
+        assert_eq!(
+            parsed,
+            VirtualTexturingConfig {
+                page_table_size: 512,
+                physical_texture: PhysicalTextureConfig {
+                    layers: 2,
+                    ..Default::default()
+                },
+                streaming: StreamingConfig {
+                    max_io_micros: 1000,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        );
+        assert!(VirtualTexturingConfig::from_json(r#"{"page_table_size":"large"}"#).is_err());
+
+        let parsed = VirtualTexturingConfig::from_json(r#"{"hot_cache":{"size":4096}}"#).unwrap();
+        assert_eq!(
+            parsed.hot_cache,
+            Some(HotCacheConfig {
+                size: 4096,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn toml_round_trip() {
+        let config = VirtualTexturingConfig {
+            layer_encodings: vec![PageEncoding::Bc7, PageEncoding::Bc5],
+            lod_bias: -0.5,
+            tonemap: Tonemap::Aces,
+            hot_cache: Some(HotCacheConfig::default()),
+            ..Default::default()
+        };
+        let parsed = VirtualTexturingConfig::from_toml(&config.to_toml()).unwrap();
+        assert_eq!(parsed, config);
+        let default = VirtualTexturingConfig::default();
+        assert!(!default.to_toml().contains("hot_cache"));
+
+        let toml = "page_table_size = 512\nprepass_ratio = 1\n[physical_texture]\nlayers = 2\n";
+        let parsed = VirtualTexturingConfig::from_toml(toml).unwrap();
+        assert_eq!(
+            (
+                parsed.page_table_size,
+                parsed.prepass_ratio,
+                parsed.physical_texture.layers
+            ),
+            (512, 1.0, 2)
+        );
+        assert_eq!(
+            parsed.physical_texture.page_slots_x,
+            default.physical_texture.page_slots_x
+        );
+    }
+}
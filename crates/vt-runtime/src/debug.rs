@@ -0,0 +1,101 @@
+//! Offline debug artifacts, meant to be attached to bug reports, and on-screen views of the
+//! streaming state, see [`DebugOverlay`] and [`RequestHeatmap`], along with an egui panel behind
+//! the `egui` feature.
+
+#[cfg(any(test, feature = "image"))]
+use crate::setup::WgpuContext;
+
+#[cfg(feature = "image")]
+mod export;
+mod heatmap;
+mod overlay;
+#[cfg(feature = "egui")]
+mod panel;
+
+#[cfg(feature = "image")]
+pub use export::{export_page_table, export_streaming, DebugExportError};
+pub use heatmap::RequestHeatmap;
+pub use overlay::{DebugOverlay, DebugOverlayOptions};
+#[cfg(feature = "egui")]
+pub use panel::DebugPanel;
+
+/// Copy a mip level of an array layer of a texture to the CPU, blocking until the copy is done.
+///
+/// The texture must have the `COPY_SRC` usage and an uncompressed format. The rows of the
+/// output are tightly packed.
+#[cfg(any(test, feature = "image"))]
+pub(crate) fn read_texture(
+    context: &WgpuContext,
+    texture: &wgpu::Texture,
+    layer: u32,
+    mip_level: u32,
+) -> Result<Vec<u8>, wgpu::BufferAsyncError> {
+    let size = wgpu::Extent3d {
+        depth_or_array_layers: 1,
+        ..texture
+            .size()
+            .mip_level_size(mip_level, texture.dimension())
+    };
+    let bytes_per_texel = texture
+        .format()
+        .block_size(None)
+        .expect("the texture to be a color texture");
+    let unpadded_bytes_per_row = size.width * bytes_per_texel;
+    let padded_bytes_per_row =
+        unpadded_bytes_per_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+    let buffer = context.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("texture readback buffer"),
+        size: (padded_bytes_per_row * size.height) as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut command_encoder =
+        context
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("texture readback"),
+            });
+    command_encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level,
+            origin: wgpu::Origin3d {
+                x: 0,
+                y: 0,
+                z: layer,
+            },
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: None,
+            },
+        },
+        size,
+    );
+    context.queue.submit(Some(command_encoder.finish()));
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    buffer
+        .slice(..)
+        .map_async(wgpu::MapMode::Read, move |result| tx.send(result).unwrap());
+    context.device.poll(wgpu::Maintain::Wait);
+    rx.recv()
+        .expect("the map callback to be called once the device is polled")?;
+
+    let mapped = buffer.slice(..).get_mapped_range();
+    let texels = mapped
+        .chunks_exact(padded_bytes_per_row as usize)
+        .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+        .copied()
+        .collect();
+    drop(mapped);
+    buffer.unmap();
+
+    Ok(texels)
+}
@@ -0,0 +1,218 @@
+//! The debug artifacts written to disk, see
+//! [`debug_dump`](crate::setup::VirtualTexturingContext::debug_dump).
+
+use std::{fmt::Write as _, path::Path};
+
+use thiserror::Error;
+
+use super::read_texture;
+use crate::{
+    setup::WgpuContext,
+    streaming::StreamingHandle,
+    textures::{CacheTier, TextureHandle, Textures},
+};
+
+/// Color of page table entries that point to a coarser mip level than their own.
+const FALLBACK_COLOR: [u8; 4] = [128, 128, 128, 255];
+/// Color of page table entries that point to nothing, and of free slots.
+const EMPTY_COLOR: [u8; 4] = [0, 0, 0, 255];
+/// Color of the slots holding a synthetic page, see
+/// [`StreamingHandle::set_overzoom`](crate::streaming::StreamingHandle::set_overzoom).
+const SYNTHETIC_COLOR: [u8; 4] = [255, 128, 0, 255];
+
+#[derive(Error, Debug)]
+pub enum DebugExportError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("could not encode image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("could not map readback buffer: {0}")]
+    BufferAsync(#[from] wgpu::BufferAsyncError),
+}
+
+/// Write every mip level of the page table to `directory` as `page_table_{mip}.png`, and the ones
+/// of the layers of the other virtual textures as `page_table_{texture_id}_{mip}.png`. A double
+/// buffered page table is exported from its front copy.
+///
+/// Resident entries are colored by hashing the coordinates of the slot they point to, so that
+/// neighbouring entries sharing a slot have the same color. Entries falling back to a coarser mip level
+/// are grey, and empty entries are black.
+pub fn export_page_table(
+    context: &WgpuContext,
+    textures: &TextureHandle,
+    directory: &Path,
+) -> Result<(), DebugExportError> {
+    let TextureHandle(textures) = textures;
+    std::fs::create_dir_all(directory)?;
+    let page_table = textures.front_page_table();
+
+    let mip_levels = page_table.mip_level_count();
+    (0..textures.virtual_texture_count())
+        .flat_map(|layer| (0..mip_levels).map(move |mip| (layer, mip)))
+        .try_for_each(|(layer, mip)| {
+            let texels = read_texture(context, page_table, layer, mip)?;
+            let size = page_table
+                .size()
+                .mip_level_size(mip, page_table.dimension());
+            let colors = texels
+                .chunks_exact(4)
+                .flat_map(|entry| page_table_entry_color(textures, entry, mip))
+                .collect::<Vec<_>>();
+
+            image::RgbaImage::from_raw(size.width, size.height, colors)
+                .expect("the readback to have the size of the mip level")
+                .save(directory.join(match layer {
+                    0 => format!("page_table_{mip}.png"),
+                    _ => format!("page_table_{layer}_{mip}.png"),
+                }))?;
+            Ok(())
+        })
+}
+
+fn page_table_entry_color(textures: &Textures, entry: &[u8], mip: u32) -> [u8; 4] {
+    let [slot_x, slot_y, page, flags] = [entry[0], entry[1], entry[2], entry[3]];
+    if flags & Textures::PAGE_TABLE_RESIDENT == 0 {
+        return EMPTY_COLOR;
+    }
+    let page_mip = page & ((1 << Textures::PAGE_TABLE_LAYER_SHIFT) - 1);
+    if page_mip as u32 != mip {
+        return FALLBACK_COLOR;
+    }
+    let tier = match flags & Textures::PAGE_TABLE_HOT {
+        0 => CacheTier::Cold,
+        _ => CacheTier::Hot,
+    };
+    let array_layer = (page >> Textures::PAGE_TABLE_LAYER_SHIFT) as u32;
+    let rows = textures.slot_layout(tier).page_slots_y;
+    slot_color((slot_x as u32, array_layer * rows + slot_y as u32))
+}
+
+/// A color hashed from the coordinates of a slot, shared by the page table and cache occupancy
+/// images.
+fn slot_color((slot_x, slot_y): (u32, u32)) -> [u8; 4] {
+    let hash = slot_x
+        .wrapping_mul(73_856_093)
+        .wrapping_add(slot_y.wrapping_mul(19_349_663))
+        .wrapping_mul(2_654_435_761);
+    let [r, g, b, _] = hash.to_le_bytes();
+    // Keep the colors away from black and grey.
+    [r | 0x40, g | 0x40, b | 0x40, 255]
+}
+
+/// Write the state of the streaming thread to `directory`:
+/// - `cache_{tier}.png`: one pixel per slot of the physical textures of every tier, colored like
+///   the page table entries pointing to it (see [`export_page_table`]), black when free and orange
+///   when holding a synthetic page. The array layers are stacked from top to bottom.
+/// - `residency.csv`: the resident pages and their slot.
+/// - `stats_history.csv`: [`StreamingHandle::stats_history`].
+/// - `requests.csv`: [`StreamingHandle::request_traces`], frame 0 being the oldest.
+pub fn export_streaming(
+    textures: &TextureHandle,
+    streaming: &StreamingHandle,
+    directory: &Path,
+) -> Result<(), DebugExportError> {
+    let TextureHandle(textures) = textures;
+    std::fs::create_dir_all(directory)?;
+    let residency = streaming.residency();
+    let metadata = streaming.metadata();
+
+    [CacheTier::Cold, CacheTier::Hot]
+        .into_iter()
+        .filter(|&tier| !textures.tier_textures(tier).is_empty())
+        .try_for_each(|tier| {
+            let layout = textures.slot_layout(tier);
+            let mut image = image::RgbaImage::from_pixel(
+                layout.page_slots_x,
+                layout.page_slots_y * layout.layers,
+                image::Rgba(EMPTY_COLOR),
+            );
+            residency
+                .pages()
+                .filter(|(page, _)| textures.cache_tier(page.mip_level()) == tier)
+                .for_each(|(page, slot)| {
+                    let color = if residency.is_synthetic(page) {
+                        SYNTHETIC_COLOR
+                    } else {
+                        slot_color(slot)
+                    };
+                    let block = 1 << metadata.page_scale(page.mip_level());
+                    (0..block * block).for_each(|index| {
+                        image.put_pixel(
+                            slot.0 + index % block,
+                            slot.1 + index / block,
+                            image::Rgba(color),
+                        )
+                    });
+                });
+            let name = match tier {
+                CacheTier::Hot => "cache_hot.png",
+                CacheTier::Cold => "cache_cold.png",
+            };
+            image.save(directory.join(name))
+        })?;
+
+    let mut pages = residency.pages().collect::<Vec<_>>();
+    pages.sort_unstable();
+    let mut csv = String::from("texture_id,mip,x,y,slot_x,slot_y,synthetic\n");
+    pages.iter().for_each(|&(page, (slot_x, slot_y))| {
+        let synthetic = residency.is_synthetic(page);
+        writeln!(
+            csv,
+            "{},{},{},{},{slot_x},{slot_y},{synthetic}",
+            page.texture_id(),
+            page.mip_level(),
+            page.x(),
+            page.y()
+        )
+        .unwrap();
+    });
+    std::fs::write(directory.join("residency.csv"), csv)?;
+
+    let mut csv = String::from(
+        "frame,invalid_feedback_texels,dropped_feedback_requests,skipped_feedback_frames,uploaded_pages,requested_pages,missed_pages,evicted_pages,read_bytes,uploaded_bytes,staging_stalls,staging_stall_micros\n",
+    );
+    streaming
+        .stats_history()
+        .iter()
+        .enumerate()
+        .for_each(|(frame, stats)| {
+            writeln!(
+                csv,
+                "{frame},{},{},{},{},{},{},{},{},{},{},{}",
+                stats.invalid_feedback_texels,
+                stats.dropped_feedback_requests,
+                stats.skipped_feedback_frames,
+                stats.uploaded_pages,
+                stats.requested_pages,
+                stats.missed_pages,
+                stats.evicted_pages,
+                stats.read_bytes,
+                stats.uploaded_bytes,
+                stats.staging_stalls,
+                stats.staging_stall_micros
+            )
+            .unwrap();
+        });
+    std::fs::write(directory.join("stats_history.csv"), csv)?;
+
+    let mut csv = String::from("frame,texture_id,mip,x,y\n");
+    streaming
+        .request_traces()
+        .iter()
+        .enumerate()
+        .for_each(|(frame, pages)| {
+            pages.iter().for_each(|page| {
+                writeln!(
+                    csv,
+                    "{frame},{},{},{},{}",
+                    page.texture_id(),
+                    page.mip_level(),
+                    page.x(),
+                    page.y()
+                )
+                .unwrap();
+            })
+        });
+    std::fs::write(directory.join("requests.csv"), csv)?;
+    Ok(())
+}
@@ -0,0 +1,386 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use crate::{
+    pipelines::FragmentShader,
+    setup::WgpuContext,
+    streaming::{PageId, StreamingHandle},
+    textures::TextureHandle,
+};
+
+/// Mirrors `HeatmapUniforms` of `heatmap.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct HeatmapUniforms {
+    frames: u32,
+    mip_levels: u32,
+    /// The virtual texture and mip level drawn by [`RequestHeatmap::render`].
+    texture_id: u32,
+    mip: u32,
+}
+
+/// How often every page was requested by the feedback of the last frames, to choose the
+/// `lod_bias` and the size of the physical textures: the pages requested by most frames must fit
+/// in the cache, and the bias trades their number for sharpness.
+///
+/// The requests are accumulated from the [`StreamingHandle::request_traces`] by
+/// [`RequestHeatmap::update`], and drawn as a heatmap from blue (requested by a single frame) to
+/// red (requested by every frame):
+/// - Over screen space, by shading the draw items with [`RequestHeatmap::fragment_shader`].
+/// - Over virtual texture space, one mip level at a time, with [`RequestHeatmap::render`].
+pub struct RequestHeatmap {
+    /// The frames accumulated, at most [`StreamingHandle::HISTORY_LEN`].
+    frames: usize,
+    counts: HashMap<PageId, u32>,
+    accumulated_frames: usize,
+    /// The counts of the pages of every mip level of the page table, one array layer per virtual
+    /// texture.
+    counts_texture: wgpu::Texture,
+    /// The layers and mip levels of `counts_texture` holding counts.
+    written_levels: BTreeSet<(u32, u32)>,
+    counts_view: wgpu::TextureView,
+    uniforms: HeatmapUniforms,
+    uniforms_buffer: wgpu::Buffer,
+    bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    /// Binds the counts and the uniforms to [`RequestHeatmap::pipeline`].
+    bind_group: wgpu::BindGroup,
+    /// Draws [`RequestHeatmap::render`].
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl RequestHeatmap {
+    /// The distance from the heatmap to the edges of the target, in pixels.
+    const MARGIN: f32 = 8.0;
+
+    /// A heatmap of the requests of the last `frames` frames to the virtual textures of
+    /// `textures`, clamped to [`StreamingHandle::HISTORY_LEN`].
+    pub fn new(context: &WgpuContext, textures: &TextureHandle, frames: usize) -> Self {
+        let TextureHandle(textures) = textures;
+        let page_table = textures.front_page_table();
+        let counts_texture = context.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("request heatmap texture"),
+            size: wgpu::Extent3d {
+                depth_or_array_layers: textures.virtual_texture_count(),
+                ..page_table.size()
+            },
+            mip_level_count: page_table.mip_level_count(),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Uint,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let uniforms = HeatmapUniforms {
+            frames: 0,
+            mip_levels: page_table.mip_level_count(),
+            texture_id: 0,
+            mip: 0,
+        };
+        let uniforms_buffer = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("request heatmap uniforms buffer"),
+            size: std::mem::size_of::<HeatmapUniforms>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        context
+            .queue
+            .write_buffer(&uniforms_buffer, 0, bytemuck::bytes_of(&uniforms));
+
+        let bind_group_layout = context.resources.bind_group_layout(
+            &context.device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("request heatmap bind group layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            sample_type: wgpu::TextureSampleType::Uint,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+        let counts_view = counts_texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let bind_group =
+            Self::create_bind_group(context, &bind_group_layout, &counts_view, &uniforms_buffer);
+
+        let pipeline_layout =
+            context
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("request heatmap pipeline layout"),
+                    bind_group_layouts: &[&bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+        let shader = context
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("request heatmap shader"),
+                source: wgpu::ShaderSource::Wgsl(Self::shader_source(0).into()),
+            });
+        let pipeline = context
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("request heatmap pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_heatmap",
+                    buffers: &[],
+                },
+                primitive: Default::default(),
+                depth_stencil: None,
+                multisample: Default::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_heatmap_texture",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: context.surface_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+            });
+
+        Self {
+            frames: frames.min(StreamingHandle::HISTORY_LEN),
+            counts: HashMap::new(),
+            accumulated_frames: 0,
+            counts_texture,
+            written_levels: BTreeSet::new(),
+            counts_view,
+            uniforms,
+            uniforms_buffer,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    fn create_bind_group(
+        context: &WgpuContext,
+        layout: &wgpu::BindGroupLayout,
+        counts_view: &wgpu::TextureView,
+        uniforms_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("request heatmap bind group"),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(counts_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: uniforms_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+    }
+
+    /// `heatmap.wgsl`, with its bindings declared in `bind_group`.
+    fn shader_source(bind_group: u32) -> String {
+        format!(
+            "@group({bind_group}) @binding(0)\nvar heatmap_counts: texture_2d_array<u32>;\n\
+            @group({bind_group}) @binding(1)\nvar<uniform> heatmap: HeatmapUniforms;\n{}",
+            include_str!("../heatmap.wgsl")
+        )
+    }
+
+    /// Accumulate the pages requested by the last frames read back by `streaming`, replacing the
+    /// previous counts.
+    pub fn update(&mut self, context: &WgpuContext, streaming: &StreamingHandle) {
+        let traces = streaming.request_traces();
+        let recent = &traces[traces.len().saturating_sub(self.frames)..];
+        self.counts.clear();
+        recent.iter().flatten().for_each(|&page| {
+            *self.counts.entry(page).or_default() += 1;
+        });
+        self.accumulated_frames = recent.len();
+
+        let size = self.counts_texture.size();
+        let coarsest_mip = self.counts_texture.mip_level_count() - 1;
+        // Only the levels with counts are written, along with the ones written by the previous
+        // update to clear them.
+        let mut levels = BTreeMap::<(u32, u32), Vec<u32>>::new();
+        self.counts.iter().for_each(|(page, &count)| {
+            let layer = page.texture_id() as u32;
+            if layer >= size.depth_or_array_layers {
+                return;
+            }
+            let level = |mip: u32| vec![0u32; ((size.width >> mip) * (size.width >> mip)) as usize];
+            let mip = page.mip_level() as u32;
+            if mip > coarsest_mip {
+                // The pages coarser than the page table cover the whole coarsest level.
+                let counts = levels
+                    .entry((layer, coarsest_mip))
+                    .or_insert_with(|| level(coarsest_mip));
+                counts
+                    .iter_mut()
+                    .for_each(|texel| *texel = (*texel).max(count));
+                return;
+            }
+            let side = size.width >> mip;
+            if (page.x() as u32) < side && (page.y() as u32) < side {
+                let counts = levels.entry((layer, mip)).or_insert_with(|| level(mip));
+                let index = (page.y() as u32 * side + page.x() as u32) as usize;
+                counts[index] = counts[index].max(count);
+            }
+        });
+        let written = levels.keys().copied().collect::<BTreeSet<_>>();
+        let cleared = self
+            .written_levels
+            .difference(&written)
+            .map(|&(layer, mip)| {
+                let side = size.width >> mip;
+                ((layer, mip), vec![0u32; (side * side) as usize])
+            })
+            .collect::<Vec<_>>();
+        levels
+            .into_iter()
+            .chain(cleared)
+            .for_each(|((layer, mip), counts)| {
+                let side = size.width >> mip;
+                context.queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &self.counts_texture,
+                        mip_level: mip,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: layer,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    bytemuck::cast_slice(&counts),
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(side * 4),
+                        rows_per_image: None,
+                    },
+                    wgpu::Extent3d {
+                        width: side,
+                        height: side,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            });
+        self.written_levels = written;
+        self.uniforms.frames = self.accumulated_frames as u32;
+        self.write_uniforms(context);
+    }
+
+    fn write_uniforms(&self, context: &WgpuContext) {
+        context
+            .queue
+            .write_buffer(&self.uniforms_buffer, 0, bytemuck::bytes_of(&self.uniforms));
+    }
+
+    /// The frames accumulated by the last update, fewer than asked for while the streaming
+    /// handle has not read back as many.
+    pub fn accumulated_frames(&self) -> usize {
+        self.accumulated_frames
+    }
+
+    /// The frames requesting `page` among the accumulated ones.
+    pub fn count(&self, page: PageId) -> u32 {
+        self.counts.get(&page).copied().unwrap_or(0)
+    }
+
+    /// The distinct pages requested by the accumulated frames, to compare with the slots of the
+    /// physical textures.
+    pub fn requested_pages(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// The render pass shading of the heatmap over screen space, for
+    /// [`VirtualTexturingContext::set_fragment_shader`](crate::setup::VirtualTexturingContext::set_fragment_shader):
+    /// every fragment is colored by the requests of the page of the mip level it samples, over
+    /// the virtual texture in grey. [`FragmentShader::default`] restores the default shading.
+    pub fn fragment_shader(&self, context: &WgpuContext) -> FragmentShader {
+        let bind_group = Self::create_bind_group(
+            context,
+            &self.bind_group_layout,
+            &self.counts_view,
+            &self.uniforms_buffer,
+        );
+        FragmentShader {
+            source: [
+                Self::shader_source(3),
+                include_str!("../heatmap_fragment.wgsl").to_owned(),
+            ]
+            .concat(),
+            entry_point: "fs_heatmap".to_owned(),
+            bind_groups: vec![(Arc::clone(&self.bind_group_layout), bind_group)],
+        }
+    }
+
+    /// Draw the heatmap of mip level `mip` of the virtual texture `texture_id` over `view`, in
+    /// a square fitting a target of `target_size`. The pages never requested are transparent.
+    ///
+    /// Must be recorded after the render pass, which clears the target, e.g. to a command
+    /// encoder submitted after [`VirtualTexturingContext::end_frame`](crate::setup::VirtualTexturingContext::end_frame).
+    pub fn render(
+        &mut self,
+        context: &WgpuContext,
+        command_encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        target_size: winit::dpi::PhysicalSize<u32>,
+        texture_id: u8,
+        mip: u8,
+    ) {
+        let side = target_size.width.min(target_size.height) as f32 - 2.0 * Self::MARGIN;
+        if side < 1.0 {
+            return;
+        }
+        let size = self.counts_texture.size();
+        self.uniforms.texture_id = (texture_id as u32).min(size.depth_or_array_layers - 1);
+        self.uniforms.mip = (mip as u32).min(self.counts_texture.mip_level_count() - 1);
+        self.write_uniforms(context);
+
+        command_encoder.push_debug_group("request heatmap");
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("request heatmap render pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_viewport(Self::MARGIN, Self::MARGIN, side, side, 0.0, 1.0);
+        render_pass.draw(0..3, 0..1);
+        drop(render_pass);
+        command_encoder.pop_debug_group();
+    }
+}
@@ -0,0 +1,292 @@
+use crate::{
+    setup::WgpuContext,
+    shader_constants,
+    streaming::StreamingHandle,
+    textures::{CacheTier, TextureHandle},
+};
+
+/// What the [`DebugOverlay`] shows.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DebugOverlayOptions {
+    /// The virtual texture whose page table is shown.
+    pub texture_id: u8,
+    /// The mip level of the page table shown, clamped to the coarsest one. Every entry points to
+    /// the finest resident page covering it, so mip level 0 shows the mip level resident
+    /// everywhere.
+    pub page_table_mip: u8,
+}
+
+/// Draws the page table and the occupancy of the physical textures over the render target, to
+/// diagnose thrashing: pages evicted as soon as they are streamed in flicker between the colors
+/// of their mip level and of a coarser one.
+///
+/// In the bottom left corner, the page table panel colors every entry by the mip level of the
+/// page it points to, black when not resident. To its right, the occupancy panel has one texel
+/// per slot, the slots of the cold tier then those of the hot tier from top to bottom. Resident
+/// slots are colored like the pages they hold, synthetic pages are orange and free slots black.
+pub struct DebugOverlay {
+    pub options: DebugOverlayOptions,
+    pipeline: wgpu::RenderPipeline,
+    /// One texel per slot: 0 when free, the mip level of the page plus 1 when resident, and
+    /// [`DebugOverlay::SYNTHETIC_SLOT`] for synthetic pages.
+    occupancy_texture: wgpu::Texture,
+    /// The slots of the cold tier, the occupancy of the hot tier being below them.
+    cold_slots: (u32, u32),
+}
+
+impl DebugOverlay {
+    /// The occupancy texel of the slots holding a synthetic page.
+    const SYNTHETIC_SLOT: u8 = u8::MAX;
+    /// The distance from the panels to the edges of the target, and between them, in pixels.
+    const MARGIN: f32 = 8.0;
+
+    /// An overlay of `textures`, drawn to targets of the surface format of `context`.
+    pub fn new(
+        context: &WgpuContext,
+        textures: &TextureHandle,
+        options: DebugOverlayOptions,
+    ) -> Self {
+        let cold = textures.slot_layout(CacheTier::Cold);
+        let hot = textures.slot_layout(CacheTier::Hot);
+        let cold_slots = (cold.page_slots_x, cold.page_slots_y * cold.layers);
+        let occupancy_texture = context.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("debug overlay occupancy texture"),
+            size: wgpu::Extent3d {
+                width: cold_slots.0.max(hot.page_slots_x).max(1),
+                height: (cold_slots.1 + hot.page_slots_y * hot.layers).max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Uint,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Uint,
+            },
+            count: None,
+        };
+        let bind_group_layout = context.resources.bind_group_layout(
+            &context.device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("debug overlay bind group layout"),
+                entries: &[texture_entry(0), texture_entry(1)],
+            },
+        );
+        let pipeline_layout =
+            context
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("debug overlay pipeline layout"),
+                    bind_group_layouts: &[&bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+        let shader = context
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("debug overlay shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    [
+                        shader_constants::PAGE_TABLE_WGSL,
+                        include_str!("../debug_overlay.wgsl"),
+                    ]
+                    .concat()
+                    .into(),
+                ),
+            });
+        let pipeline = context
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("debug overlay pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_debug_overlay",
+                    buffers: &[],
+                },
+                primitive: Default::default(),
+                depth_stencil: None,
+                multisample: Default::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_debug_overlay",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: context.surface_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+            });
+
+        Self {
+            options,
+            pipeline,
+            occupancy_texture,
+            cold_slots,
+        }
+    }
+
+    /// Write the slots of the pages resident according to `streaming` to the occupancy panel.
+    pub fn update_occupancy(
+        &self,
+        context: &WgpuContext,
+        textures: &TextureHandle,
+        streaming: &StreamingHandle,
+    ) {
+        let size = self.occupancy_texture.size();
+        let mut occupancy = vec![0u8; (size.width * size.height) as usize];
+        let residency = streaming.residency();
+        residency.pages().for_each(|(page, slot)| {
+            let value = if residency.is_synthetic(page) {
+                Self::SYNTHETIC_SLOT
+            } else {
+                page.mip_level() + 1
+            };
+            let top = match textures.cache_tier(page.mip_level()) {
+                CacheTier::Cold => 0,
+                CacheTier::Hot => self.cold_slots.1,
+            };
+            let block = 1
+                << streaming
+                    .texture_metadata(page.texture_id())
+                    .page_scale(page.mip_level());
+            (0..block * block).for_each(|index| {
+                let (x, y) = (slot.0 + index % block, top + slot.1 + index / block);
+                if x < size.width && y < size.height {
+                    occupancy[(y * size.width + x) as usize] = value;
+                }
+            });
+        });
+        context.queue.write_texture(
+            self.occupancy_texture.as_image_copy(),
+            &occupancy,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size.width),
+                rows_per_image: None,
+            },
+            size,
+        );
+    }
+
+    /// Draw the panels over `view`, whose contents are kept, for a target of `target_size`.
+    pub fn render(
+        &self,
+        context: &WgpuContext,
+        command_encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        target_size: winit::dpi::PhysicalSize<u32>,
+        textures: &TextureHandle,
+    ) {
+        let (width, height) = (target_size.width as f32, target_size.height as f32);
+        let side = (width.min(height) / 3.0).floor();
+        let top = height - side - Self::MARGIN;
+        if side < 1.0 || top < 0.0 {
+            return;
+        }
+        let (occupancy_texels_x, occupancy_texels_y) = self.occupancy_size();
+        let occupancy_width = (side * occupancy_texels_x as f32 / occupancy_texels_y as f32)
+            .min(width - side - 3.0 * Self::MARGIN)
+            .floor();
+        let occupancy = (occupancy_width >= 1.0).then_some([
+            side + 2.0 * Self::MARGIN,
+            top,
+            occupancy_width,
+            side,
+        ]);
+        self.draw(
+            context,
+            command_encoder,
+            view,
+            textures,
+            Some([Self::MARGIN, top, side, side]),
+            occupancy,
+        );
+    }
+
+    /// The texels of the occupancy panel, one per slot.
+    pub(crate) fn occupancy_size(&self) -> (u32, u32) {
+        let size = self.occupancy_texture.size();
+        (size.width, size.height)
+    }
+
+    /// Draw the page table panel and the occupancy panel over the viewports `[x, y, width,
+    /// height]` of `view` they are given, keeping the rest of its contents.
+    pub(crate) fn draw(
+        &self,
+        context: &WgpuContext,
+        command_encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        textures: &TextureHandle,
+        page_table_viewport: Option<[f32; 4]>,
+        occupancy_viewport: Option<[f32; 4]>,
+    ) {
+        let TextureHandle(textures) = textures;
+        let page_table = textures.front_page_table();
+        let mip = (self.options.page_table_mip as u32).min(page_table.mip_level_count() - 1);
+        let layer = (self.options.texture_id as u32).min(textures.virtual_texture_count() - 1);
+        let page_table_view = page_table.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("debug overlay page table view"),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_mip_level: mip,
+            mip_level_count: Some(1),
+            base_array_layer: layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        });
+        let occupancy_view = self.occupancy_texture.create_view(&Default::default());
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("debug overlay bind group"),
+                layout: &self.pipeline.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&page_table_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&occupancy_view),
+                    },
+                ],
+            });
+
+        command_encoder.push_debug_group("debug overlay");
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("debug overlay render pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        [(page_table_viewport, 0..3), (occupancy_viewport, 3..6)]
+            .into_iter()
+            .for_each(|(viewport, vertices)| {
+                if let Some([x, y, width, height]) = viewport {
+                    render_pass.set_viewport(x, y, width, height, 0.0, 1.0);
+                    render_pass.draw(vertices, 0..1);
+                }
+            });
+        drop(render_pass);
+        command_encoder.pop_debug_group();
+    }
+}
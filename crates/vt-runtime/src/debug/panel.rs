@@ -0,0 +1,394 @@
+use crate::{
+    debug::{DebugOverlay, DebugOverlayOptions},
+    setup::{VirtualTexturingContext, WgpuContext},
+    streaming::{StreamingHandle, StreamingStats},
+    textures::{CacheTier, TextureHandle},
+};
+
+/// A texture of the surface format drawn to by the debug pipelines, and drawn by egui.
+struct Thumbnail {
+    texture: wgpu::Texture,
+    id: egui::TextureId,
+}
+
+impl Thumbnail {
+    fn new(context: &WgpuContext, renderer: &mut egui_wgpu::Renderer, label: &str) -> Self {
+        let texture = context.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: DebugPanel::THUMBNAIL_TEXELS,
+                height: DebugPanel::THUMBNAIL_TEXELS,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: context.surface_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let id = renderer.register_native_texture(
+            &context.device,
+            &texture.create_view(&Default::default()),
+            wgpu::FilterMode::Nearest,
+        );
+        Self { texture, id }
+    }
+
+    fn view(&self) -> wgpu::TextureView {
+        self.texture.create_view(&Default::default())
+    }
+}
+
+/// An egui panel of the streaming state, to tune the quality of the virtual texturing while the
+/// application runs: the [`StreamingStats`] of the current frame and since the start, sliders
+/// for the quality bias and the prepass ratio, and thumbnails of the prepass target, of the
+/// page table and of the physical textures.
+///
+/// The thumbnails of the prepass target, of the page table and of the occupancy of the physical
+/// textures are drawn by the debug pipelines, see [`DebugOverlay`], and the ones of the
+/// physical textures are their first array layer. The sizes of the physical textures are fixed
+/// once the context is created, so the cache can not be resized from the panel.
+pub struct DebugPanel {
+    overlay: DebugOverlay,
+    prepass: Thumbnail,
+    page_table: Thumbnail,
+    occupancy: Thumbnail,
+    /// The first array layer of the physical textures of every layer, cold tier first.
+    physical_textures: Vec<(CacheTier, egui::TextureId)>,
+}
+
+impl DebugPanel {
+    /// The side of the textures of the thumbnails drawn on the GPU.
+    pub const THUMBNAIL_TEXELS: u32 = 256;
+    /// The side of the thumbnails in the panel, in points.
+    pub const THUMBNAIL_POINTS: f32 = 128.0;
+    /// The prepass ratios of the slider, which are fine enough for the largest targets.
+    pub const PREPASS_RATIO_RANGE: std::ops::RangeInclusive<f32> = 0.1..=1.0;
+
+    /// A panel of `context`, whose thumbnails are registered with `renderer`.
+    pub fn new(context: &VirtualTexturingContext, renderer: &mut egui_wgpu::Renderer) -> Self {
+        let wgpu_context = &context.wgpu_context;
+        let textures = context.textures();
+        let TextureHandle(physical) = &textures;
+        let physical_textures = [CacheTier::Cold, CacheTier::Hot]
+            .into_iter()
+            .flat_map(|tier| {
+                physical
+                    .tier_textures(tier)
+                    .iter()
+                    .zip(&physical.layer_view_formats)
+                    .map(move |(texture, &format)| (tier, texture, format))
+            })
+            .map(|(tier, texture, format)| {
+                let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("debug panel physical texture view"),
+                    format: Some(format),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    mip_level_count: Some(1),
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                });
+                let id = renderer.register_native_texture(
+                    &wgpu_context.device,
+                    &view,
+                    wgpu::FilterMode::Linear,
+                );
+                (tier, id)
+            })
+            .collect();
+
+        Self {
+            overlay: DebugOverlay::new(wgpu_context, &textures, DebugOverlayOptions::default()),
+            prepass: Thumbnail::new(wgpu_context, renderer, "debug panel prepass thumbnail"),
+            page_table: Thumbnail::new(wgpu_context, renderer, "debug panel page table thumbnail"),
+            occupancy: Thumbnail::new(wgpu_context, renderer, "debug panel occupancy thumbnail"),
+            physical_textures,
+        }
+    }
+
+    /// Draw the thumbnails of the current frame with `command_encoder`, after the prepass of
+    /// the frame. The occupancy is only updated when given a streaming handle.
+    pub fn update(
+        &self,
+        context: &VirtualTexturingContext,
+        command_encoder: &mut wgpu::CommandEncoder,
+        streaming: Option<&StreamingHandle>,
+    ) {
+        let wgpu_context = &context.wgpu_context;
+        let textures = context.textures();
+        if let Some(streaming) = streaming {
+            self.overlay
+                .update_occupancy(wgpu_context, &textures, streaming);
+        }
+        context.render_debug_prepass(command_encoder, &self.prepass.view());
+        let side = Self::THUMBNAIL_TEXELS as f32;
+        let full = Some([0.0, 0.0, side, side]);
+        self.overlay.draw(
+            wgpu_context,
+            command_encoder,
+            &self.page_table.view(),
+            &textures,
+            full,
+            None,
+        );
+        self.overlay.draw(
+            wgpu_context,
+            command_encoder,
+            &self.occupancy.view(),
+            &textures,
+            None,
+            full,
+        );
+    }
+
+    /// Show the panel in `ui`, applying the changes of the sliders to `context`. The stats are
+    /// only shown when given a streaming handle, the ones of the frame being counted since
+    /// [`StreamingHandle::reset_frame_stats`].
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        context: &mut VirtualTexturingContext,
+        streaming: Option<&StreamingHandle>,
+    ) {
+        if let Some(streaming) = streaming {
+            let (frame, total) = (streaming.frame_stats(), streaming.stats());
+            egui::Grid::new("virtual texturing stats")
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("");
+                    ui.label("frame");
+                    ui.label("total");
+                    ui.end_row();
+                    stat_rows(&frame)
+                        .into_iter()
+                        .zip(stat_rows(&total))
+                        .for_each(|((name, frame), (_, total))| {
+                            ui.label(name);
+                            ui.label(frame.to_string());
+                            ui.label(total.to_string());
+                            ui.end_row();
+                        });
+                });
+            ui.separator();
+        }
+
+        let mut quality_bias = context.quality_bias();
+        let slider = egui::Slider::new(
+            &mut quality_bias,
+            VirtualTexturingContext::QUALITY_BIAS_RANGE,
+        )
+        .text("quality bias");
+        if ui.add(slider).changed() {
+            context.set_quality_bias(quality_bias);
+        }
+        let mut prepass_ratio = context.config().prepass_ratio;
+        let slider =
+            egui::Slider::new(&mut prepass_ratio, Self::PREPASS_RATIO_RANGE).text("prepass ratio");
+        if ui.add(slider).changed() {
+            context.set_prepass_ratio(prepass_ratio);
+        }
+        ui.separator();
+
+        let options = &mut self.overlay.options;
+        ui.horizontal(|ui| {
+            let texture_count = context.textures().0.virtual_texture_count();
+            ui.add(
+                egui::DragValue::new(&mut options.texture_id)
+                    .clamp_range(0..=texture_count.saturating_sub(1))
+                    .prefix("texture "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut options.page_table_mip)
+                    .clamp_range(0..=u8::MAX)
+                    .prefix("mip "),
+            );
+        });
+        let prepass_size = context.pipelines.prepass_texture.size();
+        let (occupancy_x, occupancy_y) = self.overlay.occupancy_size();
+        ui.horizontal_wrapped(|ui| {
+            thumbnail(
+                ui,
+                "prepass",
+                self.prepass.id,
+                prepass_size.width,
+                prepass_size.height,
+            );
+            thumbnail(ui, "page table", self.page_table.id, 1, 1);
+            thumbnail(ui, "occupancy", self.occupancy.id, occupancy_x, occupancy_y);
+        });
+        ui.horizontal_wrapped(|ui| {
+            self.physical_textures
+                .iter()
+                .enumerate()
+                .for_each(|(index, &(tier, id))| {
+                    let name = match tier {
+                        CacheTier::Cold => format!("cold {index}"),
+                        CacheTier::Hot => format!("hot {index}"),
+                    };
+                    thumbnail(ui, &name, id, 1, 1);
+                })
+        });
+    }
+
+    /// Unregister the thumbnails from `renderer`, once the panel is no longer drawn.
+    pub fn free_textures(&self, renderer: &mut egui_wgpu::Renderer) {
+        [self.prepass.id, self.page_table.id, self.occupancy.id]
+            .iter()
+            .chain(self.physical_textures.iter().map(|(_, id)| id))
+            .for_each(|id| renderer.free_texture(id));
+    }
+}
+
+/// The names and values of the stats shown by the panel.
+fn stat_rows(stats: &StreamingStats) -> [(&'static str, u64); 12] {
+    [
+        ("uploaded pages", stats.uploaded_pages),
+        ("requested pages", stats.requested_pages),
+        ("missed pages", stats.missed_pages),
+        ("evicted pages", stats.evicted_pages),
+        ("read bytes", stats.read_bytes),
+        ("uploaded bytes", stats.uploaded_bytes),
+        ("staging stalls", stats.staging_stalls),
+        ("staging stall micros", stats.staging_stall_micros),
+        ("invalid feedback texels", stats.invalid_feedback_texels),
+        ("dropped feedback requests", stats.dropped_feedback_requests),
+        ("skipped feedback frames", stats.skipped_feedback_frames),
+        ("feedback in flight", stats.feedback_in_flight),
+    ]
+}
+
+/// Show the texture `id` of `width` by `height` texels scaled to fit a thumbnail, with its name
+/// below it.
+fn thumbnail(ui: &mut egui::Ui, name: &str, id: egui::TextureId, width: u32, height: u32) {
+    let (width, height) = (width.max(1) as f32, height.max(1) as f32);
+    let scale = DebugPanel::THUMBNAIL_POINTS / width.max(height);
+    ui.vertical(|ui| {
+        ui.image((id, egui::vec2(width * scale, height * scale)));
+        ui.label(name);
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::DebugPanel;
+    use crate::{
+        debug::read_texture, storage::TextureMetadata, streaming::PageId,
+        test_support::StreamingFixture,
+    };
+
+    /// The thumbnails are drawn on the GPU and shown by the panel, along with the stats and the
+    /// sliders, and the egui renderer draws the panel with them.
+    #[test]
+    fn debug_panel() {
+        let wgpu_context = crate::headless_or_skip!(320, 240);
+        let StreamingFixture {
+            mut context,
+            storage,
+            mut streaming,
+            temp_dir: _temp_dir,
+        } = StreamingFixture::new(
+            wgpu_context,
+            TextureMetadata::from_mip(1, 4),
+            Default::default(),
+        );
+        let page_size = storage.metadata().page_size() as usize;
+        streaming.upload_page(
+            PageId::new(0, 0, 0),
+            (1, 0),
+            &vec![255; page_size * page_size * 4],
+        );
+        let wgpu_context = std::sync::Arc::clone(&context.wgpu_context);
+        let mut renderer =
+            egui_wgpu::Renderer::new(&wgpu_context.device, wgpu_context.surface_format, None, 1);
+        let mut panel = DebugPanel::new(&context, &mut renderer);
+        let frame = context.begin_frame(&[]);
+        context.end_frame(frame, Some(&mut streaming));
+
+        let mut command_encoder = wgpu_context
+            .device
+            .create_command_encoder(&Default::default());
+        panel.update(&context, &mut command_encoder, Some(&streaming));
+        wgpu_context.queue.submit(Some(command_encoder.finish()));
+        // The slot of the page is drawn, and the last slot is free.
+        let (slots_x, slots_y) = panel.overlay.occupancy_size();
+        let texels = read_texture(&wgpu_context, &panel.occupancy.texture, 0, 0).unwrap();
+        let texel = |x: f32, y: f32| {
+            let scale = DebugPanel::THUMBNAIL_TEXELS as f32;
+            let (x, y) = (
+                (x / slots_x as f32 * scale) as usize,
+                (y / slots_y as f32 * scale) as usize,
+            );
+            let index = (y * DebugPanel::THUMBNAIL_TEXELS as usize + x) * 4;
+            texels[index..index + 4].to_vec()
+        };
+        assert_ne!(
+            texel(1.5, 0.5),
+            texel(slots_x as f32 - 0.5, slots_y as f32 - 0.5)
+        );
+
+        let egui_context = egui::Context::default();
+        let output = egui_context.run(Default::default(), |egui_context| {
+            egui::CentralPanel::default().show(egui_context, |ui| {
+                panel.ui(ui, &mut context, Some(&streaming))
+            });
+        });
+        let primitives = egui_context.tessellate(output.shapes, output.pixels_per_point);
+        let shown = primitives
+            .iter()
+            .filter_map(|primitive| match &primitive.primitive {
+                egui::epaint::Primitive::Mesh(mesh) => Some(mesh.texture_id),
+                egui::epaint::Primitive::Callback(_) => None,
+            })
+            .collect::<HashSet<_>>();
+        assert!([panel.prepass.id, panel.page_table.id, panel.occupancy.id]
+            .iter()
+            .chain(panel.physical_textures.iter().map(|(_, id)| id))
+            .all(|id| shown.contains(id)));
+
+        output.textures_delta.set.iter().for_each(|(id, delta)| {
+            renderer.update_texture(&wgpu_context.device, &wgpu_context.queue, *id, delta)
+        });
+        let screen = egui_wgpu::renderer::ScreenDescriptor {
+            size_in_pixels: [320, 240],
+            pixels_per_point: output.pixels_per_point,
+        };
+        let mut command_encoder = wgpu_context
+            .device
+            .create_command_encoder(&Default::default());
+        let buffers = renderer.update_buffers(
+            &wgpu_context.device,
+            &wgpu_context.queue,
+            &mut command_encoder,
+            &primitives,
+            &screen,
+        );
+        let target = wgpu_context
+            .offscreen_target
+            .as_ref()
+            .unwrap()
+            .create_view(&Default::default());
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &target,
+                resolve_target: None,
+                ops: Default::default(),
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        renderer.render(&mut render_pass, &primitives, &screen);
+        drop(render_pass);
+        wgpu_context
+            .queue
+            .submit(buffers.into_iter().chain(Some(command_encoder.finish())));
+        panel.free_textures(&mut renderer);
+        assert!(renderer.texture(&panel.prepass.id).is_none());
+    }
+}
@@ -0,0 +1,65 @@
+// The panels of `debug::DebugOverlay`, each drawn as a triangle covering its viewport, from
+// vertices 0 to 2 for the page table panel and 3 to 5 for the occupancy one (not by instance,
+// since GL ignores the first instance of draws). The `PAGE_TABLE_*` constants are prepended to
+// this file.
+
+struct OverlayInterpolators {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    // 0 for the page table panel, 1 for the occupancy panel.
+    @location(1) @interpolate(flat) panel: u32,
+}
+
+// A mip level of the page table of the virtual texture shown.
+@group(0) @binding(0)
+var overlay_page_table: texture_2d<u32>;
+// See `DebugOverlay::occupancy_texture`.
+@group(0) @binding(1)
+var overlay_occupancy: texture_2d<u32>;
+
+const OVERLAY_SYNTHETIC_SLOT: u32 = 255u;
+const OVERLAY_OPACITY: f32 = 0.85;
+
+@vertex
+fn vs_debug_overlay(@builtin(vertex_index) index: u32) -> OverlayInterpolators {
+    var out: OverlayInterpolators;
+    let vertex_index = index % 3u;
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    out.panel = index / 3u;
+    return out;
+}
+
+// A hue per mip level, the hues of neighbouring levels being far apart. Repeats every 8 levels.
+fn overlay_mip_color(mip: u32) -> vec3<f32> {
+    let hue = fract(f32(mip) * 0.375);
+    let k = fract(vec3<f32>(hue) + vec3<f32>(0.0, 2.0 / 3.0, 1.0 / 3.0)) * 6.0;
+    return clamp(abs(k - 3.0) - 1.0, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// The coordinates of the texel under `uv` of a texture of `size`.
+fn overlay_texel(size: vec2<u32>, uv: vec2<f32>) -> vec2<u32> {
+    return min(vec2<u32>(uv * vec2<f32>(size)), size - 1u);
+}
+
+@fragment
+fn fs_debug_overlay(in: OverlayInterpolators) -> @location(0) vec4<f32> {
+    var color = vec3<f32>(0.0);
+    if in.panel == 0u {
+        let texel = overlay_texel(textureDimensions(overlay_page_table), in.uv);
+        let entry = textureLoad(overlay_page_table, texel, 0);
+        if (entry.a & PAGE_TABLE_RESIDENT) != 0u {
+            color = overlay_mip_color(entry.b & PAGE_TABLE_MIP_MASK);
+        }
+    } else {
+        let texel = overlay_texel(textureDimensions(overlay_occupancy), in.uv);
+        let slot = textureLoad(overlay_occupancy, texel, 0).r;
+        if slot == OVERLAY_SYNTHETIC_SLOT {
+            color = vec3<f32>(1.0, 0.5, 0.0);
+        } else if slot != 0u {
+            color = overlay_mip_color(slot - 1u);
+        }
+    }
+    return vec4<f32>(color, OVERLAY_OPACITY);
+}
@@ -0,0 +1,95 @@
+//! The meshes drawn by the prepass and the render pass every frame.
+
+use std::{ops::RangeInclusive, sync::Arc};
+
+use wgpu::util::DeviceExt;
+
+use crate::{setup::WgpuContext, streaming::UvRect, vertex::Vertex};
+
+/// Vertices uploaded once, to be drawn by many [`DrawItem`]s.
+pub struct Mesh {
+    /// A triangle list of [`Vertex`], see [`Vertex::BUFFER_LAYOUT`].
+    pub vertex_buffer: wgpu::Buffer,
+    pub vertex_count: u32,
+}
+
+impl Mesh {
+    pub fn new(context: &WgpuContext, vertices: &[Vertex]) -> Self {
+        Self {
+            vertex_buffer: context
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("mesh vertex buffer"),
+                    contents: bytemuck::cast_slice(vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                }),
+            vertex_count: vertices.len() as u32,
+        }
+    }
+}
+
+/// A mesh drawn by a frame, see
+/// [`VirtualTexturingContext::begin_frame`](crate::setup::VirtualTexturingContext::begin_frame).
+#[derive(Clone)]
+pub struct DrawItem {
+    pub mesh: Arc<Mesh>,
+    /// From the space of the mesh to world space (Default: identity).
+    pub transform: nalgebra::Matrix4<f32>,
+    /// The virtual texture of the mesh (see
+    /// [`PageId::texture_id`](crate::streaming::PageId::texture_id)), available to the shaders as
+    /// `draw.texture_id` (Default: 0). The built-in passes sample and request the pages of the
+    /// layer of the page table of that virtual texture, see
+    /// [`StreamingHandle::register_texture`](crate::streaming::StreamingHandle::register_texture).
+    pub texture_id: u8,
+    /// The mip levels the feedback of the mesh may request, clamped to the coarsest one (Default:
+    /// all of them). Spends the streaming budget where it matters: distant scenery never needs
+    /// its finest levels, and an item inspected up close always wants its finest one.
+    ///
+    /// Only the requests are clamped, the render pass still samples the mip level of every
+    /// fragment, falling back to the resident levels.
+    pub mip_range: RangeInclusive<u8>,
+    /// The rect of the virtual texture the render pass clamps the uv coordinates of the mesh to,
+    /// shrunk by half a texel of the mip level sampled so that bilinear taps stay within it
+    /// (Default: `None`, not clamped). Keeps the images of an atlas from bleeding into each
+    /// other, see [`TextureMetadata::uv_clamp`](crate::storage::TextureMetadata::uv_clamp).
+    pub uv_clamp: Option<UvRect>,
+}
+
+impl DrawItem {
+    pub fn new(mesh: Arc<Mesh>) -> Self {
+        Self {
+            mesh,
+            transform: nalgebra::Matrix4::identity(),
+            texture_id: 0,
+            mip_range: 0..=u8::MAX,
+            uv_clamp: None,
+        }
+    }
+
+    pub fn with_transform(mut self, transform: nalgebra::Matrix4<f32>) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn with_texture_id(mut self, texture_id: u8) -> Self {
+        self.texture_id = texture_id;
+        self
+    }
+
+    /// See [`DrawItem::mip_range`].
+    ///
+    /// ### Panics
+    ///
+    /// - If `mip_range` is empty.
+    pub fn with_mip_range(mut self, mip_range: RangeInclusive<u8>) -> Self {
+        assert!(!mip_range.is_empty(), "the mip range must not be empty");
+        self.mip_range = mip_range;
+        self
+    }
+
+    /// See [`DrawItem::uv_clamp`].
+    pub fn with_uv_clamp(mut self, uv_clamp: UvRect) -> Self {
+        self.uv_clamp = Some(uv_clamp);
+        self
+    }
+}
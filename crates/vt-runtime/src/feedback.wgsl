@@ -0,0 +1,108 @@
+// Feedback snippet, shared by the prepass and by user shaders that produce the feedback as an
+// extra render target of their own pass. The `feedback` binding and the `FEEDBACK_*` constants
+// (see `shader_constants::FEEDBACK_WGSL`) are declared by `Pipelines::feedback_shader_snippet`,
+// which prepends them to this file.
+//
+// feedback.lod_bias = log2(feedback_attachement_width / window_width) + dynamic_lod_bias
+
+// Mirrors `pipelines::FeedbackUniforms`.
+struct FeedbackUniforms {
+    lod_bias: f32,
+    // Size of the side of a page in texels, borders included.
+    page_size: u32,
+    border_size: u32,
+    // Number of pages on the side of the page table.
+    page_table_size: u32,
+    // See `VirtualTexturingConfig::max_anisotropy`.
+    max_anisotropy: u32,
+    // See `VirtualTexturingConfig::physical_mip_levels`.
+    physical_mip_levels: u32,
+    // See `VirtualTexturingConfig::virtual_textures`.
+    virtual_textures: u32,
+}
+
+// From the uv, calculate the page index and mip level of the virtual texture `texture_id` (the
+// `draw.texture_id` of the item drawn, see `transform.wgsl`).
+// Output Format: Rgba8Uint -> (R: page_x_high (8), G: page_x_low (4) page_y_high (4),
+//                              B: page_y_low (8), A: texture_id (4) mip_level (4))
+//
+// Reminder: pages are `feedback.page_size` texels wide, of which `feedback.border_size` texels on
+// each side are padding.
+//
+// Degenerate uvs (NaN or infinite) and derivatives, and texture ids past the last virtual texture,
+// produce `FEEDBACK_INVALID`, which the CPU ignores. Uvs out of [0, 1] are clamped to the edge of
+// the texture.
+fn virtual_texture_feedback(uv: vec2<f32>, texture_id: u32) -> vec4<u32> {
+    return virtual_texture_feedback_in_range(uv, texture_id, vec2<u32>(0u, FEEDBACK_MAX_MIP));
+}
+
+// The feedback of `virtual_texture_feedback`, with the mip level clamped to the inclusive
+// `mip_range` (the `draw.min_mip` and `draw.max_mip` of the item drawn, see `DrawItem::mip_range`).
+fn virtual_texture_feedback_in_range(
+    uv: vec2<f32>,
+    texture_id: u32,
+    mip_range: vec2<u32>,
+) -> vec4<u32> {
+    let virtual_texture_page_width = feedback.page_table_size;
+    let texel_width_per_page = feedback.page_size - 2u * feedback.border_size;
+    let virtual_texture_texel_width = texel_width_per_page * virtual_texture_page_width;
+
+    let tex_coords = uv * f32(virtual_texture_texel_width);
+
+    let dx = dpdx(tex_coords);
+    let dy = dpdy(tex_coords);
+    let px = dot(dx, dx);
+    let py = dot(dy, dy);
+
+    let max_lod = 0.5 * log2(max(px, py)); // log2(sqrt(...)) == 0.5 * log2(...)
+    let min_lod = 0.5 * log2(min(px, py));
+
+    // Anisotropic filtering takes taps along the major axis, so the level only has to resolve the
+    // minor axis, as long as it is at most `max_anisotropy` times shorter. Matches
+    // `virtual_texture_lod`.
+    let aniso_lod = max_lod - min(max_lod - min_lod, log2(f32(feedback.max_anisotropy)));
+    let desired_lod = aniso_lod + feedback.lod_bias;
+    if !is_finite(uv.x) || !is_finite(uv.y) || is_nan(desired_lod)
+        || texture_id >= feedback.virtual_textures {
+        return FEEDBACK_INVALID;
+    }
+
+    // The last mip level of the page table, also keeping the mip away from the sentinel value.
+    let max_mip = min(firstLeadingBit(virtual_texture_page_width), FEEDBACK_MAX_MIP);
+    let range = min(mip_range, vec2<u32>(max_mip));
+    let mip = clamp(u32(round(clamp(desired_lod, 0.0, f32(max_mip)))), range.x, range.y);
+    // The coordinates of the page at its own mip level.
+    let page_coords = min(
+        vec2<u32>(clamp(uv, vec2<f32>(0.0), vec2<f32>(1.0)) * f32(virtual_texture_page_width)),
+        vec2<u32>(virtual_texture_page_width - 1u),
+    ) >> vec2<u32>(mip);
+
+    return feedback_to_rgba(page_coords, mip, texture_id);
+}
+
+// Written for texels whose feedback could not be computed. Its mip level (`FEEDBACK_INVALID_MIP`)
+// is never valid.
+const FEEDBACK_INVALID: vec4<u32> = vec4<u32>(255u);
+
+// False for NaN and infinite values, whose exponent bits are all set. Tested on the bits, since
+// comparisons of floats may be folded away by fast-math compilers.
+fn is_finite(value: f32) -> bool {
+    return (bitcast<u32>(value) & 0x7f800000u) != 0x7f800000u;
+}
+
+// True for NaN values, whose exponent bits are all set and mantissa is not 0, see `is_finite`.
+fn is_nan(value: f32) -> bool {
+    let bits = bitcast<u32>(value);
+    return (bits & 0x7f800000u) == 0x7f800000u && (bits & 0x007fffffu) != 0u;
+}
+
+// Output Format: Rgba8Uint -> (R: page_x_high (8), G: page_x_low (4) page_y_high (4),
+//                              B: page_y_low (8), A: texture_id (4) mip_level (4))
+fn feedback_to_rgba(page_coords: vec2<u32>, mip: u32, texture_id: u32) -> vec4<u32> {
+    let r = page_coords.x >> 4u; // upper 8 bits of 12 bits int
+    let g = ((page_coords.x & 0xFu) << 4u) | (page_coords.y >> 8u);
+    let b = page_coords.y & 0xFFu; // lower 8 bits of 12 bits int
+    let a = (texture_id << 4u) | mip;
+
+    return vec4<u32>(r, g, b, a);
+}
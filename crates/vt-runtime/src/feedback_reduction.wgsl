@@ -0,0 +1,36 @@
+// Reduction of the feedback texture to the list of distinct pages it requests, so that the CPU
+// only reads back a few kilobytes instead of the whole texture.
+//
+// The `FEEDBACK_*` constants and `feedback_requests.wgsl` are added by
+// `Pipelines::feedback_reduction_shader`.
+
+// Mirrors `pipelines::FeedbackUniforms`.
+struct FeedbackUniforms {
+    lod_bias: f32,
+    page_size: u32,
+    border_size: u32,
+    page_table_size: u32,
+    // See `VirtualTexturingConfig::max_anisotropy`.
+    max_anisotropy: u32,
+    // See `VirtualTexturingConfig::physical_mip_levels`.
+    physical_mip_levels: u32,
+    // See `VirtualTexturingConfig::virtual_textures`.
+    virtual_textures: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> feedback: FeedbackUniforms;
+@group(0) @binding(1)
+var feedback_texture: texture_2d<u32>;
+@group(0) @binding(2)
+var<storage, read_write> requested_bits: array<atomic<u32>>;
+@group(0) @binding(3)
+var<storage, read_write> requests: FeedbackRequests;
+
+@compute @workgroup_size(FEEDBACK_REDUCTION_WORKGROUP_SIZE, FEEDBACK_REDUCTION_WORKGROUP_SIZE)
+fn cs_reduce_feedback(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= textureDimensions(feedback_texture)) {
+        return;
+    }
+    request_feedback_page(textureLoad(feedback_texture, id.xy, 0));
+}
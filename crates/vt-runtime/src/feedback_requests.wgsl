@@ -0,0 +1,58 @@
+// Recording of the distinct pages requested by the feedback, shared by the feedback reduction and
+// by the prepass writing its requests to storage buffers (see `FeedbackMode::StorageBuffer`).
+//
+// Every feedback texel sets the bit of its page in `requested_bits`, which holds one bit per page
+// of every mip level. The first texel to set a bit appends its page to `requests.pages`.
+//
+// The `feedback` uniforms, the `requested_bits` and `requests` bindings and the `FEEDBACK_*`
+// constants are declared by the shaders this file is appended to.
+
+// Read by `streaming::FeedbackRequests::decode`.
+struct FeedbackRequests {
+    // Number of distinct pages requested, which may exceed the length of `pages`.
+    count: atomic<u32>,
+    // Texels holding `FEEDBACK_INVALID`, or a page out of the page table.
+    invalid_texels: atomic<u32>,
+    // The feedback texels of the pages, packed in little endian order (R in the lowest byte).
+    pages: array<u32>,
+}
+
+// Record the page requested by the feedback texel `texel`, see `virtual_texture_feedback`.
+fn request_feedback_page(texel: vec4<u32>) {
+    let mip = texel.a & 0xFu;
+    let texture_id = texel.a >> 4u;
+    let x = (texel.r << 4u) | (texel.g >> 4u);
+    let y = ((texel.g & 0xFu) << 8u) | texel.b;
+    let side = max(feedback.page_table_size >> mip, 1u);
+    // Texels cleared to the "no request" value hold the invalid mip level with zero coordinates,
+    // and are not counted as invalid.
+    if mip == FEEDBACK_INVALID_MIP && any(texel != vec4<u32>(255u)) {
+        return;
+    }
+    if mip == FEEDBACK_INVALID_MIP || mip > firstLeadingBit(feedback.page_table_size) || x >= side || y >= side || texture_id >= feedback.virtual_textures {
+        atomicAdd(&requests.invalid_texels, 1u);
+        return;
+    }
+
+    // The page tables of the virtual textures are stored one after the other, and their mip
+    // levels one after the other, from the finest.
+    var index = y * side + x;
+    var texture_pages = 0u;
+    for (var level = 0u; level <= firstLeadingBit(feedback.page_table_size); level++) {
+        let level_side = feedback.page_table_size >> level;
+        if level < mip {
+            index += level_side * level_side;
+        }
+        texture_pages += level_side * level_side;
+    }
+    index += texture_id * texture_pages;
+    let bit = 1u << (index & 31u);
+    if (atomicOr(&requested_bits[index >> 5u], bit) & bit) != 0u {
+        return;
+    }
+
+    let slot = atomicAdd(&requests.count, 1u);
+    if slot < arrayLength(&requests.pages) {
+        requests.pages[slot] = texel.r | (texel.g << 8u) | (texel.b << 16u) | (texel.a << 24u);
+    }
+}
@@ -0,0 +1,7 @@
+// The default fragment shader of the render pass, see `pipelines::FragmentShader`.
+
+@fragment
+fn fs_render(in: RenderInterpolators) -> @location(0) vec4<f32> {
+    let uv = virtual_texture_clamp_uv(in.tex_coords, in.uv_clamp);
+    return apply_color_transform(sample_virtual(uv, in.texture_id));
+}
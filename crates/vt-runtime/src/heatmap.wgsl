@@ -0,0 +1,60 @@
+// The heatmap of `debug::RequestHeatmap`. The `heatmap_counts` (texture_2d_array<u32>, the
+// requests of every page, one array layer per virtual texture) and `heatmap` bindings are declared
+// by `RequestHeatmap::shader_source`, which prepends them to this file.
+
+// Mirrors `debug::heatmap::HeatmapUniforms`.
+struct HeatmapUniforms {
+    // The frames accumulated, which requested every page at most once each.
+    frames: u32,
+    // The mip levels of `heatmap_counts`, not `textureNumLevels` which GL backends do not all
+    // support.
+    mip_levels: u32,
+    // The virtual texture and mip level drawn by `fs_heatmap_texture`.
+    texture_id: u32,
+    mip: u32,
+}
+
+// The frames requesting the page of `mip` covering `uv` in the virtual texture `texture_id`.
+fn heatmap_count(uv: vec2<f32>, texture_id: u32, mip: u32) -> u32 {
+    let level = min(mip, heatmap.mip_levels - 1u);
+    let size = textureDimensions(heatmap_counts, level);
+    let coords = min(vec2<u32>(clamp(uv, vec2<f32>(0.0), vec2<f32>(1.0)) * vec2<f32>(size)), size - 1u);
+    return textureLoad(heatmap_counts, coords, i32(texture_id), i32(level)).r;
+}
+
+// From blue for the pages requested by a single frame, through green and yellow, to red for the
+// ones requested by every frame. Transparent for the pages never requested.
+fn heatmap_color(count: u32) -> vec4<f32> {
+    if count == 0u {
+        return vec4<f32>(0.0);
+    }
+    let t = f32(count) / f32(max(heatmap.frames, 1u));
+    let color = clamp(
+        vec3<f32>(1.5) - abs(vec3<f32>(4.0 * t) - vec3<f32>(3.0, 2.0, 1.0)),
+        vec3<f32>(0.0),
+        vec3<f32>(1.0),
+    );
+    return vec4<f32>(color, 1.0);
+}
+
+struct HeatmapInterpolators {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// A triangle covering the viewport of `RequestHeatmap::render`.
+@vertex
+fn vs_heatmap(@builtin(vertex_index) vertex_index: u32) -> HeatmapInterpolators {
+    var out: HeatmapInterpolators;
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// The heatmap over virtual texture space, of `heatmap.mip` of `heatmap.texture_id`.
+@fragment
+fn fs_heatmap_texture(in: HeatmapInterpolators) -> @location(0) vec4<f32> {
+    let heat = heatmap_color(heatmap_count(in.uv, heatmap.texture_id, heatmap.mip));
+    return vec4<f32>(heat.rgb, heat.a * 0.85);
+}
@@ -0,0 +1,11 @@
+
+// The heatmap over screen space, see `RequestHeatmap::fragment_shader`: the requests of the page
+// of the mip level sampled by the fragment, over the virtual texture in grey.
+@fragment
+fn fs_heatmap(in: RenderInterpolators) -> @location(0) vec4<f32> {
+    let mip = u32(round(virtual_texture_lod(in.tex_coords)));
+    let color = sample_virtual(in.tex_coords, in.texture_id);
+    let heat = heatmap_color(heatmap_count(in.tex_coords, in.texture_id, mip));
+    let grey = vec3<f32>(dot(color.rgb, vec3<f32>(0.2126, 0.7152, 0.0722)) * 0.5);
+    return vec4<f32>(mix(grey, heat.rgb, heat.a * 0.75), 1.0);
+}
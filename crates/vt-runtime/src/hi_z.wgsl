@@ -0,0 +1,51 @@
+// Construction of the Hi-Z pyramid, see `pipelines::HiZ`.
+//
+// `cs_hi_z_depth` copies the depth of the render pass to level 0, and `cs_hi_z_downsample`
+// writes every next level with the farthest depth of the texels of the previous one it covers.
+// `HI_Z_REVERSE_Z` is prepended by `HiZ::build_shader`.
+
+fn hi_z_farthest(a: f32, b: f32) -> f32 {
+    if HI_Z_REVERSE_Z {
+        return min(a, b);
+    }
+    return max(a, b);
+}
+
+@group(0) @binding(0)
+var depth_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var source_level: texture_2d<f32>;
+@group(0) @binding(2)
+var target_level: texture_storage_2d<r32float, write>;
+
+@compute @workgroup_size(8, 8)
+fn cs_hi_z_depth(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= textureDimensions(target_level)) {
+        return;
+    }
+    let depth = textureLoad(depth_texture, id.xy, 0).r;
+    textureStore(target_level, id.xy, vec4(depth, 0.0, 0.0, 0.0));
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_hi_z_downsample(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(target_level);
+    if any(id.xy >= size) {
+        return;
+    }
+    // The last texel of a level also covers the last texel of an odd previous level.
+    let source_size = textureDimensions(source_level);
+    let first = 2u * id.xy;
+    let odd = (source_size & vec2(1u)) == vec2(1u);
+    let last = min(
+        first + select(vec2(1u), vec2(2u), odd & (id.xy == size - 1u)),
+        source_size - 1u,
+    );
+    var depth = textureLoad(source_level, first, 0).r;
+    for (var y = first.y; y <= last.y; y++) {
+        for (var x = first.x; x <= last.x; x++) {
+            depth = hi_z_farthest(depth, textureLoad(source_level, vec2(x, y), 0).r);
+        }
+    }
+    textureStore(target_level, id.xy, vec4(depth, 0.0, 0.0, 0.0));
+}
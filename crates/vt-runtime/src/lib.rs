@@ -0,0 +1,23 @@
+//! The wgpu side of the virtual texturing system: the pipelines, the page table and physical
+//! textures, and the streaming of their pages from the storage.
+
+pub mod camera;
+pub mod config;
+pub mod debug;
+pub mod draw;
+pub mod pipelines;
+pub mod power;
+pub mod profiler;
+#[cfg(feature = "image")]
+pub mod scene;
+pub mod setup;
+pub mod streaming;
+#[cfg(any(test, feature = "test-support"))]
+#[doc(hidden)]
+pub mod test_support;
+pub mod texture_generation;
+pub mod textures;
+pub mod vertex;
+
+pub use vt_core::{ensure, shader_constants};
+pub use vt_storage as storage;
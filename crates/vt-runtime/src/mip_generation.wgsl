@@ -0,0 +1,167 @@
+// Generation of the ancestors of pages written at runtime, see
+// `StreamingHandle::generate_ancestor_mips`.
+//
+// `cs_generate_page` averages the 2x2 texels of the children of every page into the mip level 0
+// of its slot, borders included, `cs_downsample_slot` then averages the mip levels of the slot.
+// The texels are written to `output` as packed RGBA8, one row of a level every
+// `COPY_BYTES_PER_ROW_ALIGNMENT` bytes, to be copied to the physical textures.
+
+// Mirrors `streaming::mip_generation::MipGenerationUniforms`.
+struct MipGenerationUniforms {
+    // The texels of a page without its borders, on a side.
+    page_stride: u32,
+    border_size: u32,
+    // The side of the slots, level 0 of the slot mip chain.
+    slot_size: u32,
+    // 1 if the texels are sRGB, averaged in linear light.
+    srgb: u32,
+    // 1 if the physical textures are loaded as sRGB texels, in the linear format.
+    decode_loads: u32,
+    _padding: u32,
+    // The texels of the mip level of the children, whose edges are clamped to.
+    extent: vec2<u32>,
+    // The words of the output of a page, every level of its slot mip chain included.
+    page_words: u32,
+    // The level of the slot mip chain written by `cs_downsample_slot`.
+    level: u32,
+    // The offset and row length in words of the level read by `cs_downsample_slot`, and of the
+    // level written.
+    source_offset: u32,
+    source_row: u32,
+    target_offset: u32,
+    target_row: u32,
+}
+
+// Mirrors `streaming::mip_generation::MipGenerationJob`. The slots hold the origin texel of the
+// slot and its array layer, and flags in `w`: `SLOT_RESIDENT` and `SLOT_HOT`.
+struct MipGenerationJob {
+    // The page generated, in `xy`.
+    page: vec4<u32>,
+    // The current slot of the page, whose texels are kept where no child is resident.
+    parent: vec4<u32>,
+    // The children from `2 * page - 1` to `2 * page + 2`, row by row, the outer ones being the
+    // children of the neighbouring pages sampled by the borders.
+    children: array<vec4<u32>, 16>,
+}
+
+const SLOT_RESIDENT: u32 = 1u;
+const SLOT_HOT: u32 = 2u;
+
+@group(0) @binding(0)
+var<uniform> uniforms: MipGenerationUniforms;
+@group(0) @binding(1)
+var<storage, read> jobs: array<MipGenerationJob>;
+@group(0) @binding(2)
+var<storage, read_write> output: array<u32>;
+@group(0) @binding(3)
+var cold_texture: texture_2d_array<f32>;
+@group(0) @binding(4)
+var hot_texture: texture_2d_array<f32>;
+
+fn srgb_to_linear(color: vec4<f32>) -> vec4<f32> {
+    let rgb = select(
+        pow((color.rgb + 0.055) / 1.055, vec3(2.4)),
+        color.rgb / 12.92,
+        color.rgb <= vec3(0.04045),
+    );
+    return vec4(rgb, color.a);
+}
+
+fn linear_to_srgb(color: vec4<f32>) -> vec4<f32> {
+    let rgb = clamp(color.rgb, vec3(0.0), vec3(1.0));
+    let encoded = select(
+        1.055 * pow(rgb, vec3(1.0 / 2.4)) - 0.055,
+        rgb * 12.92,
+        rgb <= vec3(0.0031308),
+    );
+    return vec4(encoded, color.a);
+}
+
+fn load_slot(slot: vec4<u32>, texel: vec2<u32>) -> vec4<f32> {
+    let coords = vec2<i32>(slot.xy + texel);
+    var color: vec4<f32>;
+    if (slot.w & SLOT_HOT) != 0u {
+        color = textureLoad(hot_texture, coords, i32(slot.z), 0);
+    } else {
+        color = textureLoad(cold_texture, coords, i32(slot.z), 0);
+    }
+    if uniforms.decode_loads != 0u {
+        return srgb_to_linear(color);
+    }
+    return color;
+}
+
+// The texel `texel` of the mip level of the children, clamped to the edges of the texture.
+// Texels of the outer children that are not resident are clamped to the children of the page
+// instead, and the ones of the children of the page that are not resident are read from the
+// current slot of the page, or are transparent.
+fn child_texel(job: u32, texel: vec2<i32>) -> vec4<f32> {
+    let stride = i32(uniforms.page_stride);
+    let page = vec2<i32>(jobs[job].page.xy);
+    var clamped = clamp(texel, vec2(0), vec2<i32>(uniforms.extent) - 1);
+    var grid = clamped / stride - (2 * page - 1);
+    var slot = jobs[job].children[grid.y * 4 + grid.x];
+    if (slot.w & SLOT_RESIDENT) == 0u && (any(grid < vec2(1)) || any(grid > vec2(2))) {
+        let first = 2 * page * stride;
+        let last = min(first + 2 * stride, vec2<i32>(uniforms.extent)) - 1;
+        clamped = clamp(clamped, first, last);
+        grid = clamped / stride - (2 * page - 1);
+        slot = jobs[job].children[grid.y * 4 + grid.x];
+    }
+    let border = vec2(i32(uniforms.border_size));
+    if (slot.w & SLOT_RESIDENT) != 0u {
+        let local = clamped - (2 * page - 1 + grid) * stride + border;
+        return load_slot(slot, vec2<u32>(local));
+    }
+    let parent = jobs[job].parent;
+    if (parent.w & SLOT_RESIDENT) != 0u {
+        return load_slot(parent, vec2<u32>(clamped / 2 - page * stride + border));
+    }
+    return vec4(0.0);
+}
+
+fn pack_texel(color: vec4<f32>) -> u32 {
+    if uniforms.srgb != 0u {
+        return pack4x8unorm(linear_to_srgb(color));
+    }
+    return pack4x8unorm(color);
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_generate_page(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= vec2(uniforms.slot_size)) {
+        return;
+    }
+    let job = id.z;
+    let page = vec2<i32>(jobs[job].page.xy);
+    // The texel of the page in its mip level, negative in the top left border.
+    let texel = page * i32(uniforms.page_stride) + vec2<i32>(id.xy)
+        - i32(uniforms.border_size);
+    let color = (child_texel(job, 2 * texel) + child_texel(job, 2 * texel + vec2(1, 0))
+        + child_texel(job, 2 * texel + vec2(0, 1)) + child_texel(job, 2 * texel + 1)) / 4.0;
+    output[job * uniforms.page_words + id.y * uniforms.target_row + id.x] = pack_texel(color);
+}
+
+fn source_texel(job: u32, texel: vec2<u32>) -> vec4<f32> {
+    let index = job * uniforms.page_words + uniforms.source_offset + texel.y * uniforms.source_row
+        + texel.x;
+    let color = unpack4x8unorm(output[index]);
+    if uniforms.srgb != 0u {
+        return srgb_to_linear(color);
+    }
+    return color;
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_downsample_slot(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= vec2(uniforms.slot_size >> uniforms.level)) {
+        return;
+    }
+    let job = id.z;
+    let texel = 2u * id.xy;
+    let color = (source_texel(job, texel) + source_texel(job, texel + vec2(1u, 0u))
+        + source_texel(job, texel + vec2(0u, 1u)) + source_texel(job, texel + 1u)) / 4.0;
+    let index = job * uniforms.page_words + uniforms.target_offset + id.y * uniforms.target_row
+        + id.x;
+    output[index] = pack_texel(color);
+}
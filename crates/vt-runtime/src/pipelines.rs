@@ -0,0 +1,1732 @@
+use std::{num::NonZeroU64, sync::Arc};
+
+use miniserde::{Deserialize, MiniSerialize};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::CameraModule, draw::DrawItem, setup::WgpuContext, shader_constants, textures::Textures,
+};
+
+mod hi_z;
+mod registry;
+
+pub use hi_z::{HiZ, HiZUniforms};
+pub use registry::ResourceRegistry;
+
+/// How the feedback (the page requests) is produced every frame.
+#[derive(MiniSerialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FeedbackMode {
+    /// The crate runs its own prepass over the geometry in a scaled-down target.
+    ///
+    /// A full resolution target with a coarse shading rate would give better coverage of small
+    /// triangles for the same fragment cost, but `wgpu` does not expose variable rate shading.
+    #[default]
+    Separate,
+    /// The feedback is produced as an extra render target of a pass owned by the user (usually
+    /// their depth prepass), so geometry is submitted only once.
+    ///
+    /// The user's fragment shader must include [`Pipelines::feedback_shader_snippet`] and write
+    /// `virtual_texture_feedback(uv, draw.texture_id)` to the target described by
+    /// [`Pipelines::feedback_target_state`]. Since all render targets of a pass must have the same
+    /// size, the feedback texture is allocated at the window size. Its vertex shader can place the
+    /// draw items like the render pass does with [`Pipelines::transform_shader_snippet`].
+    ///
+    /// The target must be loaded with
+    /// [`VirtualTexturingContext::feedback_load_op`](crate::setup::VirtualTexturingContext::feedback_load_op)
+    /// to follow [`VirtualTexturingConfig::prepass_clear_interval`](crate::config::VirtualTexturingConfig::prepass_clear_interval).
+    Interleaved,
+    /// The crate runs its own prepass like [`FeedbackMode::Separate`], but its fragment shader
+    /// records the distinct pages requested straight to [`Textures::feedback_requests_buffer`]
+    /// with atomics, without a feedback texture to reduce. The size of the requests read back
+    /// does not depend on the resolution of the prepass.
+    ///
+    /// The requests do not accumulate over
+    /// [`VirtualTexturingConfig::prepass_clear_interval`](crate::config::VirtualTexturingConfig::prepass_clear_interval)
+    /// frames. Writing to storage buffers disables the early depth test, so the fragments hidden
+    /// by the ones drawn before them request their pages too.
+    ///
+    /// Requires [`wgpu::DownlevelFlags::FRAGMENT_WRITABLE_STORAGE`] in
+    /// [`WgpuContext::downlevel_flags`]. The requests of the hidden fragments are dropped with
+    /// [`VirtualTexturingConfig::hi_z`](crate::config::VirtualTexturingConfig::hi_z).
+    StorageBuffer,
+}
+
+/// Where the render pass of the crate gets its depth from.
+#[derive(MiniSerialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DepthMode {
+    /// The render pass clears and writes its own depth texture.
+    #[default]
+    Separate,
+    /// The prepass runs at full resolution on every frame and doubles as the depth prepass of
+    /// the render pass, which keeps its depth and only shades the closest fragments, so that the
+    /// scene is not depth tested twice and hidden fragments do not sample the virtual texture.
+    /// The feedback is still reduced on feedback frames only.
+    ///
+    /// Requires [`FeedbackMode::Separate`], and ignores
+    /// [`VirtualTexturingConfig::prepass_ratio`](crate::config::VirtualTexturingConfig::prepass_ratio).
+    ReusePrepass,
+}
+
+/// How the prepass and the render pass rasterize and depth test the draw items, to match the
+/// conventions of the application drawing the same meshes, see
+/// [`VirtualTexturingContext::from_config_with_options`](crate::setup::VirtualTexturingContext::from_config_with_options).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineOptions {
+    /// The format of the depth textures of both passes.
+    pub depth_format: wgpu::TextureFormat,
+    /// Depth decreases away from the camera, for projections mapping the far plane to 0: the
+    /// depth textures are cleared to 0 and the greatest depth is kept. The projection of the
+    /// [`CameraModule`] is reversed to match (see [`PipelineOptions::camera_uniforms`]).
+    pub reverse_z: bool,
+    pub cull_mode: Option<wgpu::Face>,
+    pub front_face: wgpu::FrontFace,
+    /// Modes other than `Fill` need the `POLYGON_MODE_*` features of the device.
+    pub polygon_mode: wgpu::PolygonMode,
+}
+
+impl Default for PipelineOptions {
+    /// `Depth32Float` depth, increasing away from the camera, and counter-clockwise front faces
+    /// with the back faces culled.
+    fn default() -> Self {
+        Self {
+            depth_format: wgpu::TextureFormat::Depth32Float,
+            reverse_z: false,
+            cull_mode: Some(wgpu::Face::Back),
+            front_face: wgpu::FrontFace::Ccw,
+            polygon_mode: wgpu::PolygonMode::Fill,
+        }
+    }
+}
+
+impl PipelineOptions {
+    /// The primitive state of the prepass and render pipelines.
+    pub fn primitive_state(&self) -> wgpu::PrimitiveState {
+        wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: self.front_face,
+            cull_mode: self.cull_mode,
+            unclipped_depth: false,
+            polygon_mode: self.polygon_mode,
+            conservative: false,
+        }
+    }
+
+    /// The comparison keeping the closest fragments, or the ones as close as the depth already
+    /// written with `or_equal`.
+    pub fn depth_compare(&self, or_equal: bool) -> wgpu::CompareFunction {
+        match (self.reverse_z, or_equal) {
+            (false, false) => wgpu::CompareFunction::Less,
+            (false, true) => wgpu::CompareFunction::LessEqual,
+            (true, false) => wgpu::CompareFunction::Greater,
+            (true, true) => wgpu::CompareFunction::GreaterEqual,
+        }
+    }
+
+    /// The depth of the far plane, which the depth textures are cleared to.
+    pub fn depth_clear_value(&self) -> f32 {
+        if self.reverse_z {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    /// The uniforms of `view_proj`, a projection mapping the far plane to 1 such as the one of
+    /// [`CameraModule::view_proj_matrix`], with the depth of its clip space reversed (`z`
+    /// becoming `w - z`) with [`PipelineOptions::reverse_z`].
+    ///
+    /// Only the depth changes, so the prepass requests the same pages either way.
+    pub fn camera_uniforms(&self, mut view_proj: nalgebra::Matrix4<f32>) -> CameraUniforms {
+        if self.reverse_z {
+            let w = view_proj.row(3).clone_owned();
+            let z = view_proj.row(2).clone_owned();
+            view_proj.set_row(2, &(w - z));
+        }
+        CameraUniforms {
+            view_proj: view_proj.into(),
+        }
+    }
+}
+
+/// How the render pass filters the pages of the physical texture.
+///
+/// Every quality has its own render pipeline, so switching at runtime is free.
+#[derive(MiniSerialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SamplingQuality {
+    /// Point sampling of the closest mip level.
+    Nearest,
+    /// Bilinear filtering within the page of the closest mip level.
+    #[default]
+    Linear,
+    /// Bilinear filtering of the two closest mip levels, blended together.
+    Trilinear,
+}
+
+impl SamplingQuality {
+    pub const ALL: [SamplingQuality; 3] = [
+        SamplingQuality::Nearest,
+        SamplingQuality::Linear,
+        SamplingQuality::Trilinear,
+    ];
+
+    /// The function of the virtual texture snippet filtering with this quality.
+    fn sample_function(self) -> &'static str {
+        match self {
+            SamplingQuality::Nearest => "virtual_texture_sample_nearest",
+            SamplingQuality::Linear => "virtual_texture_sample_linear",
+            SamplingQuality::Trilinear => "virtual_texture_sample_trilinear",
+        }
+    }
+}
+
+/// The fragment shader of the render pass, to shade the virtual texture with custom lighting or
+/// materials, see
+/// [`VirtualTexturingContext::set_fragment_shader`](crate::setup::VirtualTexturingContext::set_fragment_shader).
+///
+/// The source is appended to the WGSL of the render pass, which provides:
+///
+/// - The `RenderInterpolators` of the vertex shader: the `tex_coords` and `texture_id` of the
+///   fragment, its world space `normal`, and the `uv_clamp` of the item drawn (see
+///   [`DrawItem::uv_clamp`]), applied by `virtual_texture_clamp_uv(uv, in.uv_clamp)`.
+/// - `sample_virtual(uv: vec2<f32>, texture_id: u32) -> vec4<f32>`, which samples the first layer
+///   of the virtual texture with the [`SamplingQuality`] of the pipeline. The other layers are
+///   sampled with the functions of the virtual texture snippet, see
+///   [`Pipelines::sampling_shader_source`].
+/// - `apply_color_transform(color: vec4<f32>) -> vec4<f32>`, the exposure and tone mapping of
+///   [`ColorTransform`].
+/// - The `camera` and `draw` uniforms of [`Pipelines::transform_shader_snippet`].
+///
+/// The source is compiled once per [`SamplingQuality`]. Its entry point takes the
+/// `RenderInterpolators` and returns the color at location 0.
+pub struct FragmentShader {
+    pub source: String,
+    pub entry_point: String,
+    /// The resources of the source, bound to the groups following the camera bind group (from
+    /// group 3), in order.
+    pub bind_groups: Vec<(Arc<wgpu::BindGroupLayout>, wgpu::BindGroup)>,
+}
+
+impl Default for FragmentShader {
+    /// Samples the virtual texture, and applies the color transform.
+    fn default() -> Self {
+        Self {
+            source: include_str!("fragment.wgsl").to_owned(),
+            entry_point: "fs_render".to_owned(),
+            bind_groups: Vec::new(),
+        }
+    }
+}
+
+/// The tone mapping curve applied to the color of the render pass, after the exposure.
+///
+/// Without one, colors brighter than 1 are clipped, which is only fine for LDR content.
+#[derive(MiniSerialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Tonemap {
+    #[default]
+    None,
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve.
+    Aces,
+}
+
+/// The final color transform of the render pass, read by `shader.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ColorTransform {
+    pub exposure: f32,
+    /// A [`Tonemap`] as a `u32`.
+    pub tonemap: u32,
+}
+
+impl ColorTransform {
+    pub fn new(exposure: f32, tonemap: Tonemap) -> Self {
+        Self {
+            exposure,
+            tonemap: tonemap as u32,
+        }
+    }
+}
+
+/// The uniforms read by the feedback snippet, see [`Pipelines::feedback_shader_snippet`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FeedbackUniforms {
+    /// Must stay the first field, only it is written when the bias changes, see
+    /// [`VirtualTexturingContext::effective_lod_bias`].
+    ///
+    /// [`VirtualTexturingContext::effective_lod_bias`]: crate::setup::VirtualTexturingContext::effective_lod_bias
+    pub lod_bias: f32,
+    pub page_size: u32,
+    pub border_size: u32,
+    pub page_table_size: u32,
+    /// See [`VirtualTexturingConfig::max_anisotropy`](crate::config::VirtualTexturingConfig::max_anisotropy).
+    pub max_anisotropy: u32,
+    /// See [`VirtualTexturingConfig::physical_mip_levels`](crate::config::VirtualTexturingConfig::physical_mip_levels).
+    pub physical_mip_levels: u32,
+    /// See [`VirtualTexturingConfig::virtual_textures`](crate::config::VirtualTexturingConfig::virtual_textures).
+    pub virtual_textures: u32,
+    /// Pads the struct to a multiple of 16 bytes, the size of the uniform blocks of some
+    /// backends.
+    pub _padding: u32,
+}
+
+/// The camera of both passes, read by the vertex shaders of `prepass.wgsl` and `shader.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniforms {
+    /// Column major, see [`CameraModule::view_proj_matrix`].
+    pub view_proj: [[f32; 4]; 4],
+}
+
+impl From<&CameraModule> for CameraUniforms {
+    fn from(camera: &CameraModule) -> Self {
+        Self {
+            view_proj: camera.view_proj_matrix().into(),
+        }
+    }
+}
+
+/// The transform and texture of a [`DrawItem`], read by the vertex shaders of `prepass.wgsl`
+/// and `shader.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DrawUniforms {
+    /// Column major, see [`DrawItem::transform`].
+    pub model: [[f32; 4]; 4],
+    pub texture_id: u32,
+    /// See [`DrawItem::mip_range`].
+    pub min_mip: u32,
+    pub max_mip: u32,
+    /// Aligns `uv_clamp` like its WGSL counterpart.
+    pub _padding: u32,
+    /// The min then max of [`DrawItem::uv_clamp`], or of a rect larger than any uv if it is
+    /// `None`.
+    pub uv_clamp: [f32; 4],
+}
+
+impl From<&DrawItem> for DrawUniforms {
+    fn from(item: &DrawItem) -> Self {
+        Self {
+            model: item.transform.into(),
+            texture_id: item.texture_id as u32,
+            min_mip: *item.mip_range.start() as u32,
+            max_mip: *item.mip_range.end() as u32,
+            _padding: 0,
+            uv_clamp: item
+                .uv_clamp
+                .map_or([f32::MIN, f32::MIN, f32::MAX, f32::MAX], |rect| {
+                    [rect.min.0, rect.min.1, rect.max.0, rect.max.1]
+                }),
+        }
+    }
+}
+
+pub struct Pipelines {
+    pub prepass_pipeline: wgpu::RenderPipeline,
+    /// One render pipeline per [`SamplingQuality`], see [`Pipelines::render_pipeline`].
+    pub render_pipelines: [wgpu::RenderPipeline; 3],
+    /// The bind groups of the [`FragmentShader`] of the render pipelines, bound from group 3.
+    pub fragment_bind_groups: Vec<wgpu::BindGroup>,
+    /// The size of the render target, see [`Pipelines::resize`].
+    pub target_size: winit::dpi::PhysicalSize<u32>,
+    /// The rasterization and depth test of both passes.
+    pub options: PipelineOptions,
+    /// The ratio of the prepass target, see [`Pipelines::set_prepass_ratio`].
+    pub prepass_ratio: f32,
+    /// The feedback texture, scaled down from the render target with [`FeedbackMode::Separate`].
+    /// A single texel with [`FeedbackMode::StorageBuffer`], which writes none.
+    pub prepass_texture: wgpu::Texture,
+    /// A single texel with [`DepthMode::ReusePrepass`], whose prepass writes
+    /// [`Pipelines::render_depth_texture`], see [`Pipelines::prepass_depth_target`].
+    pub prepass_depth_texture: wgpu::Texture,
+    /// Multisampled with [`VirtualTexturingConfig::msaa_samples`](crate::config::VirtualTexturingConfig::msaa_samples).
+    pub render_depth_texture: wgpu::Texture,
+    /// The multisampled color target of the render pass, which resolves it to the view rendered
+    /// to. `None` with a single sample.
+    pub msaa_texture: Option<wgpu::Texture>,
+    /// The meshes drawn by the following passes, see [`Pipelines::upload_draw_items`].
+    pub draw_items: Vec<DrawItem>,
+    /// The [`DrawUniforms`] of every draw item, [`Pipelines::draw_uniforms_stride`] bytes apart.
+    pub draw_uniforms_buffer: wgpu::Buffer,
+    /// The distance between the uniforms of consecutive draw items, a multiple of the minimum
+    /// uniform buffer offset alignment of the device.
+    pub draw_uniforms_stride: u64,
+    pub feedback_uniforms_buffer: wgpu::Buffer,
+    pub feedback_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    pub feedback_bind_group: wgpu::BindGroup,
+    /// Visible to fragment and compute shaders, see
+    /// [`Pipelines::virtual_texture_compute_shader_snippet`].
+    pub virtual_texture_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    /// One per page table, see [`Pipelines::virtual_texture_bind_group`].
+    pub virtual_texture_bind_groups: Vec<wgpu::BindGroup>,
+    pub color_transform_buffer: wgpu::Buffer,
+    pub color_transform_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    pub color_transform_bind_group: wgpu::BindGroup,
+    /// Holds the [`CameraUniforms`], the identity (reversed with [`PipelineOptions::reverse_z`])
+    /// until the first [`VirtualTexturingContext::update_camera`](crate::setup::VirtualTexturingContext::update_camera).
+    pub camera_buffer: wgpu::Buffer,
+    pub camera_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    /// Binds the [`CameraUniforms`], and the [`DrawUniforms`] of a draw item with a dynamic offset
+    /// (see [`Pipelines::draw_uniforms_offset`]). Bound to group 1 of the prepass and to group 2
+    /// of the render pass, before the bind groups of the user.
+    pub camera_bind_group: wgpu::BindGroup,
+    /// Reduces the feedback texture to the distinct pages it requests, see
+    /// [`VirtualTexturingContext::reduce_feedback`](crate::setup::VirtualTexturingContext::reduce_feedback).
+    pub feedback_reduction_pipeline: wgpu::ComputePipeline,
+    pub feedback_reduction_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    /// Binds the feedback texture, so it is recreated along with it.
+    pub feedback_reduction_bind_group: wgpu::BindGroup,
+    /// One bit per page of every mip level, cleared before every reduction.
+    pub requested_pages_buffer: wgpu::Buffer,
+    /// The prepass recording the requests to storage buffers, with
+    /// [`FeedbackMode::StorageBuffer`] only.
+    pub prepass_storage_pipeline: Option<wgpu::RenderPipeline>,
+    /// Binds [`Pipelines::requested_pages_buffer`] and [`Textures::feedback_requests_buffer`] to
+    /// group 2 of [`Pipelines::prepass_storage_pipeline`].
+    pub feedback_storage_bind_group: Option<wgpu::BindGroup>,
+    /// The depth pyramid the storage prepass tests its fragments against, with
+    /// [`VirtualTexturingConfig::hi_z`](crate::config::VirtualTexturingConfig::hi_z) only.
+    pub hi_z: Option<HiZ>,
+    #[cfg(any(debug_assertions, feature = "egui"))]
+    pub debug_prepass_pipeline: wgpu::RenderPipeline,
+}
+
+impl Pipelines {
+    /// The format of the feedback texture.
+    pub const FEEDBACK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Uint;
+    /// The value the feedback texture is cleared to, [`PageId::NO_REQUEST_FEEDBACK`], which the
+    /// reduction skips without counting it as an invalid texel.
+    ///
+    /// [`PageId::NO_REQUEST_FEEDBACK`]: crate::streaming::PageId::NO_REQUEST_FEEDBACK
+    pub const FEEDBACK_CLEAR_COLOR: wgpu::Color = wgpu::Color {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: shader_constants::FEEDBACK_INVALID_MIP as f64,
+    };
+    /// The binding of the physical texture of the first layer in the virtual texture bind group.
+    const FIRST_LAYER_BINDING: u32 = 4;
+    /// The number of distinct pages the feedback reduction reports per frame. The pages past this
+    /// are dropped, and requested again by the next frames.
+    pub const MAX_FEEDBACK_REQUESTS: u32 = 4096;
+    /// The size of the counters before the pages in the feedback requests buffer.
+    pub const FEEDBACK_REQUESTS_HEADER_SIZE: u64 = 2 * std::mem::size_of::<u32>() as u64;
+    pub const FEEDBACK_REQUESTS_BUFFER_SIZE: u64 = Self::FEEDBACK_REQUESTS_HEADER_SIZE
+        + Self::MAX_FEEDBACK_REQUESTS as u64 * std::mem::size_of::<u32>() as u64;
+    /// The size of the workgroups of the feedback reduction, on each side.
+    pub const FEEDBACK_REDUCTION_WORKGROUP_SIZE: u32 =
+        shader_constants::FEEDBACK_REDUCTION_WORKGROUP_SIZE;
+
+    /// WGSL source providing `virtual_texture_feedback(uv: vec2<f32>, texture_id: u32) ->
+    /// vec4<u32>`, to be prepended to a shader that outputs the feedback. Texture ids at or past
+    /// [`VirtualTexturingConfig::virtual_textures`](crate::config::VirtualTexturingConfig::virtual_textures)
+    /// request no page. `virtual_texture_feedback_in_range(uv, texture_id, mip_range: vec2<u32>)`
+    /// clamps the mip level requested, see [`DrawItem::mip_range`].
+    ///
+    /// The snippet reads [`FeedbackUniforms`] from binding 0 of `bind_group`, which must be bound
+    /// to [`Pipelines::feedback_bind_group`].
+    pub fn feedback_shader_snippet(bind_group: u32) -> String {
+        format!(
+            "@group({bind_group}) @binding(0)\nvar<uniform> feedback: FeedbackUniforms;\n\n{}\n{}\n",
+            shader_constants::FEEDBACK_WGSL,
+            include_str!("feedback.wgsl")
+        )
+    }
+
+    /// WGSL source providing the `virtual_texture_sample_*(layer: texture_2d_array<f32>, hot_layer:
+    /// texture_2d_array<f32>, uv: vec2<f32>, texture_id: u32) -> vec4<f32>` functions, where `layer`
+    /// and `hot_layer` are `vt_layer_{i}` and `vt_hot_layer_{i}` for a layer `i` below `layers`,
+    /// and `virtual_texture_sample_normal` for BC5 normal layers. `texture_id` selects the layer of
+    /// the page table, all virtual textures sharing the physical textures.
+    ///
+    /// Without a hot tier, `vt_hot_layer_{i}` is bound to the same texture as `vt_layer_{i}`.
+    ///
+    /// The snippet reads the page table and the physical textures from `bind_group`, which must be
+    /// bound to [`Pipelines::virtual_texture_bind_group`].
+    fn virtual_texture_shader_snippet(bind_group: u32, layers: usize) -> String {
+        let layer_bindings = (0..layers)
+            .map(|layer| {
+                format!(
+                    "@group({bind_group}) @binding({})\nvar vt_layer_{layer}: texture_2d_array<f32>;\n\
+                    @group({bind_group}) @binding({})\nvar vt_hot_layer_{layer}: texture_2d_array<f32>;\n",
+                    Self::FIRST_LAYER_BINDING as usize + layer,
+                    Self::FIRST_LAYER_BINDING as usize + layers + layer,
+                )
+            })
+            .collect::<String>();
+        format!(
+            "@group({bind_group}) @binding(0)\nvar vt_page_table: texture_2d_array<u32>;\n\
+            @group({bind_group}) @binding(1)\nvar vt_nearest_sampler: sampler;\n\
+            @group({bind_group}) @binding(2)\nvar vt_linear_sampler: sampler;\n\
+            @group({bind_group}) @binding(3)\nvar<uniform> vt: VirtualTextureUniforms;\n\
+            {layer_bindings}\n{}\n{}\n",
+            shader_constants::PAGE_TABLE_WGSL,
+            include_str!("virtual_texture.wgsl")
+        )
+    }
+
+    /// WGSL source providing `sample_virtual(uv: vec2<f32>, texture_id: u32) -> vec4<f32>`, which
+    /// samples the first layer of the virtual texture with `quality`: it looks the page up in the
+    /// page table, falling back to the coarser resident pages, and remaps the uv into the page
+    /// within its borders. The functions of the snippet sampling the other layers `i` below
+    /// `layers` take `vt_layer_{i}` and `vt_hot_layer_{i}`, e.g.,
+    /// `virtual_texture_sample_linear(vt_layer_1, vt_hot_layer_1, uv, texture_id)`.
+    ///
+    /// This lets other renderers sample the virtual texture in their own fragment shaders, with
+    /// the same results as the render pass. Filtering needs derivatives, see
+    /// [`Pipelines::virtual_texture_compute_shader_snippet`] for the other stages. The pages are
+    /// only streamed in for the fragments of a pass producing the feedback, see
+    /// [`FeedbackMode::Interleaved`].
+    ///
+    /// The snippet reads the page table and the physical textures from `bind_group`, whose layout
+    /// has the [`Pipelines::virtual_texture_bind_group_layout_entries`] (the
+    /// [`ResourceRegistry`] of the context returns [`Pipelines::virtual_texture_bind_group_layout`]
+    /// for them) and which must be bound to [`Pipelines::virtual_texture_bind_group`].
+    pub fn sampling_shader_source(
+        bind_group: u32,
+        layers: usize,
+        quality: SamplingQuality,
+    ) -> String {
+        format!(
+            "{}\nfn sample_virtual(uv: vec2<f32>, texture_id: u32) -> vec4<f32> {{\n    \
+            return {}(vt_layer_0, vt_hot_layer_0, uv, texture_id);\n}}\n",
+            Self::virtual_texture_shader_snippet(bind_group, layers),
+            quality.sample_function(),
+        )
+    }
+
+    /// The entries of [`Pipelines::virtual_texture_bind_group_layout`] for `layers` physical
+    /// textures, visible to fragment and compute shaders: the page table, the nearest and linear
+    /// samplers, the uniforms, and the cold then hot physical textures of every layer.
+    pub fn virtual_texture_bind_group_layout_entries(
+        layers: usize,
+    ) -> Vec<wgpu::BindGroupLayoutEntry> {
+        // Compute shaders of the user read the virtual texture too, see
+        // `Pipelines::virtual_texture_compute_shader_snippet`.
+        let visibility = wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE;
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2Array,
+                sample_type,
+            },
+            count: None,
+        };
+        let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        [
+            texture_entry(0, wgpu::TextureSampleType::Uint),
+            sampler_entry(1),
+            sampler_entry(2),
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: NonZeroU64::new(
+                        std::mem::size_of::<FeedbackUniforms>() as u64
+                    ),
+                },
+                count: None,
+            },
+        ]
+        .into_iter()
+        .chain((0..2 * layers as u32).map(|layer| {
+            texture_entry(
+                Self::FIRST_LAYER_BINDING + layer,
+                wgpu::TextureSampleType::Float { filterable: true },
+            )
+        }))
+        .collect()
+    }
+
+    /// WGSL source providing the functions of the virtual texture snippet usable without
+    /// derivatives, for compute shaders reading the virtual texture (e.g., the albedo and height
+    /// layers of a terrain):
+    ///
+    /// - `virtual_texture_sample_level(layer, hot_layer, uv: vec2<f32>, texture_id: u32, mip: u32)
+    ///   -> vec4<f32>`,
+    ///   with the `vt_layer_{i}` and `vt_hot_layer_{i}` of a layer `i` below `layers`.
+    /// - `virtual_texture_is_resident(uv: vec2<f32>, texture_id: u32, mip: u32) -> bool`.
+    /// - `virtual_texture_physical_texel` and `virtual_texture_unpack_normal`.
+    ///
+    /// The snippet reads the page table and the physical textures from `bind_group`, which must be
+    /// bound to [`Pipelines::virtual_texture_bind_group`] in a pipeline layout including
+    /// [`Pipelines::virtual_texture_bind_group_layout`], visible to compute shaders. Only the pages
+    /// requested by the feedback of the frames are resident.
+    pub fn virtual_texture_compute_shader_snippet(bind_group: u32, layers: usize) -> String {
+        Self::virtual_texture_shader_snippet(bind_group, layers)
+            + include_str!("virtual_texture_compute.wgsl")
+    }
+
+    /// WGSL source providing `transform_position(position: vec3<f32>) -> vec4<f32>`, which
+    /// transforms a vertex of the item drawn to clip space, and the `draw.texture_id` of the item.
+    /// To be prepended to the vertex shaders drawing [`Pipelines::draw_items`], such as the pass
+    /// producing the feedback with [`FeedbackMode::Interleaved`].
+    ///
+    /// The snippet reads [`CameraUniforms`] and [`DrawUniforms`] from `bind_group`, which must be
+    /// bound to [`Pipelines::camera_bind_group`] with [`Pipelines::draw_uniforms_offset`] for
+    /// every item.
+    pub fn transform_shader_snippet(bind_group: u32) -> String {
+        format!(
+            "@group({bind_group}) @binding(0)\nvar<uniform> camera: Camera;\n\
+            @group({bind_group}) @binding(1)\nvar<uniform> draw: Draw;\n\n{}\n",
+            include_str!("transform.wgsl")
+        )
+    }
+
+    /// WGSL source of the feedback reduction, with the constants it shares with the feedback.
+    fn feedback_reduction_shader() -> String {
+        [
+            shader_constants::FEEDBACK_WGSL,
+            shader_constants::FEEDBACK_REDUCTION_WGSL,
+            include_str!("feedback_requests.wgsl"),
+            include_str!("feedback_reduction.wgsl"),
+        ]
+        .concat()
+    }
+
+    /// WGSL source of the prepass with [`FeedbackMode::StorageBuffer`], with the occlusion test
+    /// of [`HiZ`] if `hi_z` is set.
+    fn prepass_storage_shader(options: &PipelineOptions, hi_z: bool) -> String {
+        let mut source = [
+            &Self::feedback_shader_snippet(0),
+            &Self::transform_shader_snippet(1),
+            include_str!("prepass.wgsl"),
+            include_str!("feedback_requests.wgsl"),
+            include_str!("prepass_storage.wgsl"),
+        ]
+        .concat();
+        if hi_z {
+            source += &HiZ::reverse_z_constant(options);
+            source += include_str!("prepass_hi_z.wgsl");
+        }
+        source
+    }
+
+    /// The size in bytes of the bitset holding one bit per page of every mip level of
+    /// `virtual_textures` page tables of `page_table_size` pages on the side.
+    fn requested_pages_buffer_size(page_table_size: u32, virtual_textures: u32) -> u64 {
+        let pages = (0..=page_table_size.ilog2())
+            .map(|mip| (page_table_size as u64 >> mip).pow(2))
+            .sum::<u64>();
+        (pages * virtual_textures as u64).div_ceil(u32::BITS as u64)
+            * std::mem::size_of::<u32>() as u64
+    }
+
+    /// The bind group of the page table sampled by the frame being built (see
+    /// [`Textures::front_page_table`]) and of the physical textures, to bind anew every frame
+    /// with [`VirtualTexturingConfig::double_buffer_page_table`](crate::config::VirtualTexturingConfig::double_buffer_page_table).
+    pub fn virtual_texture_bind_group(&self, textures: &Textures) -> &wgpu::BindGroup {
+        &self.virtual_texture_bind_groups[textures.front_page_table_index()]
+    }
+
+    /// The render pipeline filtering the physical texture with `quality`.
+    pub fn render_pipeline(&self, quality: SamplingQuality) -> &wgpu::RenderPipeline {
+        &self.render_pipelines[quality as usize]
+    }
+
+    /// The color target state for the feedback output of a pipeline, see [`FeedbackMode::Interleaved`].
+    pub fn feedback_target_state() -> wgpu::ColorTargetState {
+        wgpu::ColorTargetState {
+            format: Self::FEEDBACK_FORMAT,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+        }
+    }
+
+    /// ### Panics
+    ///
+    /// - If the [`PipelineOptions::depth_format`] can not be multisampled with
+    ///   [`Textures::msaa_samples`] (see [`WgpuContext::supports_sample_count`]).
+    pub fn new(
+        context: &WgpuContext,
+        textures: &Textures,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        options: PipelineOptions,
+    ) -> Self {
+        assert!(
+            context.supports_sample_count(options.depth_format, textures.msaa_samples),
+            "{} samples per texel are not supported for the depth format {:?}",
+            textures.msaa_samples,
+            options.depth_format
+        );
+        let prepass_shader = context
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("prepass.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(
+                    (Self::feedback_shader_snippet(0)
+                        + &Self::transform_shader_snippet(1)
+                        + include_str!("prepass.wgsl"))
+                    .into(),
+                ),
+            });
+
+        let feedback_bind_group_layout_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(std::mem::size_of::<FeedbackUniforms>() as u64),
+            },
+            count: None,
+        };
+        let feedback_bind_group_layout = context.resources.bind_group_layout(
+            &context.device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("feedback bind group layout"),
+                entries: &[feedback_bind_group_layout_entry],
+            },
+        );
+        let feedback_uniforms = FeedbackUniforms {
+            lod_bias: 0.0,
+            page_size: textures.page_size,
+            border_size: textures.border_size,
+            page_table_size: textures.page_table_texture.width(),
+            max_anisotropy: textures.max_anisotropy as u32,
+            physical_mip_levels: textures.physical_mip_levels,
+            virtual_textures: textures.virtual_texture_count(),
+            _padding: 0,
+        };
+        let feedback_uniforms_buffer =
+            context
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("feedback uniforms buffer"),
+                    contents: bytemuck::bytes_of(&feedback_uniforms),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                });
+        let feedback_bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("feedback bind group"),
+                layout: &feedback_bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: feedback_uniforms_buffer.as_entire_binding(),
+                }],
+            });
+
+        let virtual_texture_bind_group_layout = context.resources.bind_group_layout(
+            &context.device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("virtual texture bind group layout"),
+                entries: &Self::virtual_texture_bind_group_layout_entries(
+                    textures.physical_textures.len(),
+                ),
+            },
+        );
+        // Anisotropic filtering needs every filter to be linear.
+        let sampler = |filter_mode, anisotropy_clamp| {
+            context.resources.sampler(
+                &context.device,
+                &wgpu::SamplerDescriptor {
+                    label: Some("physical texture sampler"),
+                    mag_filter: filter_mode,
+                    min_filter: filter_mode,
+                    mipmap_filter: filter_mode,
+                    anisotropy_clamp,
+                    ..Default::default()
+                },
+            )
+        };
+        let (nearest_sampler, linear_sampler) = (
+            sampler(wgpu::FilterMode::Nearest, 1),
+            sampler(wgpu::FilterMode::Linear, textures.max_anisotropy),
+        );
+        let hot_textures = if textures.hot_physical_textures.is_empty() {
+            &textures.physical_textures
+        } else {
+            &textures.hot_physical_textures
+        };
+        // The cold views of every layer, then the hot ones.
+        let layer_views = textures
+            .physical_textures
+            .iter()
+            .zip(&textures.layer_view_formats)
+            .chain(hot_textures.iter().zip(&textures.layer_view_formats))
+            .map(|(texture, &format)| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    format: Some(format),
+                    dimension: Some(wgpu::TextureViewDimension::D2Array),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+        // One bind group per page table, see `Pipelines::virtual_texture_bind_group`.
+        let virtual_texture_bind_groups = std::iter::once(&textures.page_table_texture)
+            .chain(&textures.back_page_table_texture)
+            .map(|page_table| {
+                let page_table_view = page_table.create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(wgpu::TextureViewDimension::D2Array),
+                    ..Default::default()
+                });
+                let virtual_texture_entries = [
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&page_table_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&nearest_sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&linear_sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: feedback_uniforms_buffer.as_entire_binding(),
+                    },
+                ]
+                .into_iter()
+                .chain(layer_views.iter().zip(Self::FIRST_LAYER_BINDING..).map(
+                    |(view, binding)| wgpu::BindGroupEntry {
+                        binding,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                ))
+                .collect::<Vec<_>>();
+                context
+                    .device
+                    .create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: Some("virtual texture bind group"),
+                        layout: &virtual_texture_bind_group_layout,
+                        entries: &virtual_texture_entries,
+                    })
+            })
+            .collect();
+
+        let camera_bind_group_layout = context.resources.bind_group_layout(
+            &context.device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("camera bind group layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: NonZeroU64::new(
+                                std::mem::size_of::<CameraUniforms>() as u64,
+                            ),
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: NonZeroU64::new(
+                                std::mem::size_of::<DrawUniforms>() as u64
+                            ),
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+        let camera_buffer = context
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("camera buffer"),
+                contents: bytemuck::bytes_of(
+                    &options.camera_uniforms(nalgebra::Matrix4::identity()),
+                ),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+        let draw_uniforms_stride = (std::mem::size_of::<DrawUniforms>() as u64)
+            .next_multiple_of(context.device.limits().min_uniform_buffer_offset_alignment as u64);
+        let draw_uniforms_buffer = Self::create_draw_uniforms_buffer(context, draw_uniforms_stride);
+        let camera_bind_group = Self::create_camera_bind_group(
+            context,
+            &camera_bind_group_layout,
+            &camera_buffer,
+            &draw_uniforms_buffer,
+        );
+
+        let prepass_bind_group_layouts: Vec<&wgpu::BindGroupLayout> = [
+            &[&*feedback_bind_group_layout, &*camera_bind_group_layout],
+            bind_group_layouts,
+        ]
+        .concat();
+        let prepass_pipeline_layout =
+            context
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("prepass pipeline layout"),
+                    push_constant_ranges: &[],
+                    bind_group_layouts: &prepass_bind_group_layouts[..],
+                });
+
+        let prepass_pipeline =
+            context
+                .device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("prepass pipeline"),
+                    layout: Some(&prepass_pipeline_layout),
+                    primitive: options.primitive_state(),
+                    vertex: wgpu::VertexState {
+                        module: &prepass_shader,
+                        entry_point: "vs_prepass",
+                        buffers: &[super::vertex::Vertex::BUFFER_LAYOUT],
+                    },
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: options.depth_format,
+                        depth_write_enabled: true,
+                        depth_compare: options.depth_compare(false),
+                        stencil: Default::default(),
+                        bias: Default::default(),
+                    }),
+                    multisample: wgpu::MultisampleState::default(),
+                    fragment: Some(wgpu::FragmentState {
+                        module: &prepass_shader,
+                        entry_point: "fs_prepass",
+                        targets: &[Some(Self::feedback_target_state())],
+                    }),
+                    multiview: None,
+                });
+
+        let target_size = context.window_size;
+        let prepass_ratio = textures.prepass_ratio;
+        let (prepass_texture, prepass_depth_texture, render_depth_texture, msaa_texture) =
+            Self::create_targets(context, textures, &options, prepass_ratio, target_size);
+
+        let color_transform_bind_group_layout = context.resources.bind_group_layout(
+            &context.device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("color transform bind group layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(
+                            std::mem::size_of::<ColorTransform>() as u64
+                        ),
+                    },
+                    count: None,
+                }],
+            },
+        );
+        let color_transform_buffer =
+            context
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("color transform buffer"),
+                    contents: bytemuck::bytes_of(&ColorTransform::new(1.0, Tonemap::None)),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                });
+        let color_transform_bind_group =
+            context
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("color transform bind group"),
+                    layout: &color_transform_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: color_transform_buffer.as_entire_binding(),
+                    }],
+                });
+
+        let feedback_reduction_shader =
+            context
+                .device
+                .create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: Some("feedback_reduction.wgsl"),
+                    source: wgpu::ShaderSource::Wgsl(Self::feedback_reduction_shader().into()),
+                });
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let feedback_reduction_bind_group_layout = context.resources.bind_group_layout(
+            &context.device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("feedback reduction bind group layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ..feedback_bind_group_layout_entry
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Uint,
+                        },
+                        count: None,
+                    },
+                    storage_entry(2),
+                    storage_entry(3),
+                ],
+            },
+        );
+        let requested_pages_buffer = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("requested pages buffer"),
+            size: Self::requested_pages_buffer_size(
+                textures.page_table_texture.width(),
+                textures.virtual_texture_count(),
+            ),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let feedback_reduction_bind_group = Self::create_feedback_reduction_bind_group(
+            context,
+            textures,
+            &feedback_reduction_bind_group_layout,
+            &feedback_uniforms_buffer,
+            &prepass_texture,
+            &requested_pages_buffer,
+        );
+        let feedback_reduction_pipeline_layout =
+            context
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("feedback reduction pipeline layout"),
+                    bind_group_layouts: &[&feedback_reduction_bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+        let feedback_reduction_pipeline =
+            context
+                .device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("feedback reduction pipeline"),
+                    layout: Some(&feedback_reduction_pipeline_layout),
+                    module: &feedback_reduction_shader,
+                    entry_point: "cs_reduce_feedback",
+                });
+
+        let hi_z = textures.hi_z.then(|| {
+            HiZ::new(
+                context,
+                &options,
+                &render_depth_texture,
+                &prepass_depth_texture,
+            )
+        });
+        let (prepass_storage_pipeline, feedback_storage_bind_group) = match textures.feedback_mode {
+            FeedbackMode::StorageBuffer => {
+                let (pipeline, bind_group) = Self::create_prepass_storage_pipeline(
+                    context,
+                    textures,
+                    &options,
+                    &[&feedback_bind_group_layout, &camera_bind_group_layout],
+                    &requested_pages_buffer,
+                );
+                (Some(pipeline), Some(bind_group))
+            }
+            FeedbackMode::Separate | FeedbackMode::Interleaved => (None, None),
+        };
+
+        let render_bind_group_layouts: Vec<&wgpu::BindGroupLayout> = [
+            &[
+                &*virtual_texture_bind_group_layout,
+                &*color_transform_bind_group_layout,
+                &*camera_bind_group_layout,
+            ],
+            bind_group_layouts,
+        ]
+        .concat();
+        let render_pipelines = Self::create_render_pipelines(
+            context,
+            textures,
+            &options,
+            &render_bind_group_layouts,
+            &FragmentShader::default(),
+        );
+
+        #[cfg(any(debug_assertions, feature = "egui"))]
+        let debug_prepass_pipeline = {
+            let bind_group_layout = context.resources.bind_group_layout(
+                &context.device,
+                &wgpu::BindGroupLayoutDescriptor {
+                    label: Some("debug prepass texture bind group layout"),
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Uint,
+                        },
+                        count: None,
+                    }],
+                },
+            );
+
+            let pipeline_layout =
+                context
+                    .device
+                    .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: Some("debug prepass pipeline layout"),
+                        bind_group_layouts: &[&bind_group_layout],
+                        push_constant_ranges: &[],
+                    });
+
+            context
+                .device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("debug prepass pipeline"),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &prepass_shader,
+                        entry_point: "vs_debug_prepass",
+                        buffers: &[],
+                    },
+                    primitive: PipelineOptions::default().primitive_state(),
+                    depth_stencil: None,
+                    multisample: Default::default(),
+                    fragment: Some(wgpu::FragmentState {
+                        module: &prepass_shader,
+                        entry_point: "fs_debug_prepass",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: context.surface_format,
+                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    multiview: None,
+                })
+        };
+
+        Self {
+            draw_items: Vec::new(),
+            draw_uniforms_buffer,
+            draw_uniforms_stride,
+            prepass_pipeline,
+            render_pipelines,
+            fragment_bind_groups: Vec::new(),
+            target_size,
+            options,
+            prepass_ratio,
+            prepass_texture,
+            prepass_depth_texture,
+            render_depth_texture,
+            msaa_texture,
+            feedback_bind_group_layout,
+            feedback_bind_group,
+            feedback_uniforms_buffer,
+            virtual_texture_bind_group_layout,
+            virtual_texture_bind_groups,
+            color_transform_buffer,
+            color_transform_bind_group_layout,
+            color_transform_bind_group,
+            camera_buffer,
+            camera_bind_group_layout,
+            camera_bind_group,
+            feedback_reduction_pipeline,
+            feedback_reduction_bind_group_layout,
+            feedback_reduction_bind_group,
+            requested_pages_buffer,
+            prepass_storage_pipeline,
+            feedback_storage_bind_group,
+            hi_z,
+            #[cfg(any(debug_assertions, feature = "egui"))]
+            debug_prepass_pipeline,
+        }
+    }
+
+    /// WGSL source of the render pass filtering with `quality` and shading with `source`, see
+    /// [`FragmentShader`].
+    fn render_shader_source(layers: usize, quality: SamplingQuality, source: &str) -> String {
+        [
+            &Self::sampling_shader_source(0, layers, quality),
+            &Self::transform_shader_snippet(2),
+            include_str!("shader.wgsl"),
+            source,
+        ]
+        .concat()
+    }
+
+    /// The render pipeline of every [`SamplingQuality`], shading with `fragment_shader`.
+    fn create_render_pipelines(
+        context: &WgpuContext,
+        textures: &Textures,
+        options: &PipelineOptions,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        fragment_shader: &FragmentShader,
+    ) -> [wgpu::RenderPipeline; 3] {
+        let pipeline_layout =
+            context
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("render pipeline layout"),
+                    bind_group_layouts,
+                    push_constant_ranges: &[],
+                });
+        // The render pass only keeps the fragments the prepass found closest when they share its
+        // depth, see `DepthMode::ReusePrepass`.
+        let reuse_prepass = textures.depth_mode == DepthMode::ReusePrepass;
+        let depth_stencil = wgpu::DepthStencilState {
+            format: options.depth_format,
+            depth_write_enabled: !reuse_prepass,
+            depth_compare: options.depth_compare(reuse_prepass),
+            stencil: Default::default(),
+            bias: Default::default(),
+        };
+        SamplingQuality::ALL.map(|quality| {
+            let shader = context
+                .device
+                .create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: Some(&format!("{quality:?} render shader")),
+                    source: wgpu::ShaderSource::Wgsl(
+                        Self::render_shader_source(
+                            textures.physical_textures.len(),
+                            quality,
+                            &fragment_shader.source,
+                        )
+                        .into(),
+                    ),
+                });
+            context
+                .device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(&format!("{quality:?} render pipeline")),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_render",
+                        buffers: &[super::vertex::Vertex::BUFFER_LAYOUT],
+                    },
+                    primitive: options.primitive_state(),
+                    depth_stencil: Some(depth_stencil.clone()),
+                    multisample: wgpu::MultisampleState {
+                        count: textures.msaa_samples,
+                        ..Default::default()
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: &fragment_shader.entry_point,
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: context.surface_format,
+                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    multiview: None,
+                })
+        })
+    }
+
+    /// Recreate the render pipelines to shade with `fragment_shader`, see
+    /// [`VirtualTexturingContext::set_fragment_shader`](crate::setup::VirtualTexturingContext::set_fragment_shader).
+    /// The pipelines are kept if the shader is invalid.
+    pub fn set_fragment_shader(
+        &mut self,
+        context: &WgpuContext,
+        textures: &Textures,
+        fragment_shader: FragmentShader,
+    ) -> Result<(), wgpu::Error> {
+        let bind_group_layouts: Vec<&wgpu::BindGroupLayout> = [
+            &*self.virtual_texture_bind_group_layout,
+            &*self.color_transform_bind_group_layout,
+            &*self.camera_bind_group_layout,
+        ]
+        .into_iter()
+        .chain(
+            fragment_shader
+                .bind_groups
+                .iter()
+                .map(|(layout, _)| &**layout),
+        )
+        .collect();
+        context
+            .device
+            .push_error_scope(wgpu::ErrorFilter::Validation);
+        let render_pipelines = Self::create_render_pipelines(
+            context,
+            textures,
+            &self.options,
+            &bind_group_layouts,
+            &fragment_shader,
+        );
+        if let Some(error) = pollster::block_on(context.device.pop_error_scope()) {
+            return Err(error);
+        }
+        self.render_pipelines = render_pipelines;
+        self.fragment_bind_groups = fragment_shader
+            .bind_groups
+            .into_iter()
+            .map(|(_, bind_group)| bind_group)
+            .collect();
+        Ok(())
+    }
+
+    /// Recreate the textures that have the size of the render target for a target of `size`.
+    ///
+    /// See [`VirtualTexturingContext::resize`](crate::setup::VirtualTexturingContext::resize),
+    /// which also reconfigures the surface and the level of detail bias.
+    pub fn resize(
+        &mut self,
+        context: &WgpuContext,
+        textures: &Textures,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        (
+            self.prepass_texture,
+            self.prepass_depth_texture,
+            self.render_depth_texture,
+            self.msaa_texture,
+        ) = Self::create_targets(context, textures, &self.options, self.prepass_ratio, size);
+        self.feedback_reduction_bind_group = Self::create_feedback_reduction_bind_group(
+            context,
+            textures,
+            &self.feedback_reduction_bind_group_layout,
+            &self.feedback_uniforms_buffer,
+            &self.prepass_texture,
+            &self.requested_pages_buffer,
+        );
+        if let Some(hi_z) = &mut self.hi_z {
+            hi_z.resize(
+                context,
+                &self.render_depth_texture,
+                &self.prepass_depth_texture,
+            );
+        }
+        self.target_size = size;
+    }
+
+    /// Recreate the prepass targets with the ratio `prepass_ratio`, see
+    /// [`VirtualTexturingContext::set_prepass_ratio`](crate::setup::VirtualTexturingContext::set_prepass_ratio).
+    pub fn set_prepass_ratio(
+        &mut self,
+        context: &WgpuContext,
+        textures: &Textures,
+        prepass_ratio: f32,
+    ) {
+        self.prepass_ratio = prepass_ratio;
+        self.resize(context, textures, self.target_size);
+    }
+
+    /// Set the meshes drawn by the following passes, writing their [`DrawUniforms`] with the
+    /// queue. The uniforms buffer grows to fit the items, and keeps its size afterwards.
+    pub fn upload_draw_items(&mut self, context: &WgpuContext, items: &[DrawItem]) {
+        let size = items.len().max(1) as u64 * self.draw_uniforms_stride;
+        if size > self.draw_uniforms_buffer.size() {
+            self.draw_uniforms_buffer = Self::create_draw_uniforms_buffer(
+                context,
+                size.next_power_of_two().max(self.draw_uniforms_stride),
+            );
+            self.camera_bind_group = Self::create_camera_bind_group(
+                context,
+                &self.camera_bind_group_layout,
+                &self.camera_buffer,
+                &self.draw_uniforms_buffer,
+            );
+        }
+
+        let mut uniforms = vec![0; size as usize];
+        items.iter().enumerate().for_each(|(index, item)| {
+            let offset = index * self.draw_uniforms_stride as usize;
+            uniforms[offset..offset + std::mem::size_of::<DrawUniforms>()]
+                .copy_from_slice(bytemuck::bytes_of(&DrawUniforms::from(item)));
+        });
+        context
+            .queue
+            .write_buffer(&self.draw_uniforms_buffer, 0, &uniforms);
+        self.draw_items = items.to_vec();
+    }
+
+    /// The dynamic offset of [`Pipelines::camera_bind_group`] for the draw item at `index` in
+    /// [`Pipelines::draw_items`].
+    pub fn draw_uniforms_offset(&self, index: usize) -> u32 {
+        (index as u64 * self.draw_uniforms_stride) as u32
+    }
+
+    fn create_draw_uniforms_buffer(context: &WgpuContext, size: u64) -> wgpu::Buffer {
+        context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("draw uniforms buffer"),
+            size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_camera_bind_group(
+        context: &WgpuContext,
+        layout: &wgpu::BindGroupLayout,
+        camera_buffer: &wgpu::Buffer,
+        draw_uniforms_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("camera bind group"),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: camera_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: draw_uniforms_buffer,
+                            offset: 0,
+                            size: NonZeroU64::new(std::mem::size_of::<DrawUniforms>() as u64),
+                        }),
+                    },
+                ],
+            })
+    }
+
+    /// The view to attach as the feedback render target, see [`FeedbackMode::Interleaved`].
+    pub fn feedback_view(&self) -> wgpu::TextureView {
+        self.prepass_texture
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    /// The depth texture written by the prepass, shared with the render pass with
+    /// [`DepthMode::ReusePrepass`].
+    pub fn prepass_depth_target(&self, depth_mode: DepthMode) -> &wgpu::Texture {
+        match depth_mode {
+            DepthMode::Separate => &self.prepass_depth_texture,
+            DepthMode::ReusePrepass => &self.render_depth_texture,
+        }
+    }
+
+    /// The prepass color and depth textures, and the depth texture and multisampled color target
+    /// of the render pass, for a render target of `size`. The prepass targets are scaled down by
+    /// `prepass_ratio` with [`FeedbackMode::Separate`] or [`FeedbackMode::StorageBuffer`] and
+    /// [`DepthMode::Separate`].
+    fn create_targets(
+        context: &WgpuContext,
+        textures: &Textures,
+        options: &PipelineOptions,
+        prepass_ratio: f32,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> (
+        wgpu::Texture,
+        wgpu::Texture,
+        wgpu::Texture,
+        Option<wgpu::Texture>,
+    ) {
+        let create_texture = |label, (width, height): (u32, u32), sample_count, format, usage| {
+            context.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: width.max(1),
+                    height: height.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            })
+        };
+        let prepass_size = match (textures.feedback_mode, textures.depth_mode) {
+            (FeedbackMode::Separate | FeedbackMode::StorageBuffer, DepthMode::Separate) => (
+                (size.width as f32 * prepass_ratio) as u32,
+                (size.height as f32 * prepass_ratio) as u32,
+            ),
+            _ => (size.width, size.height),
+        };
+        let prepass_depth_size = match textures.depth_mode {
+            DepthMode::Separate => prepass_size,
+            DepthMode::ReusePrepass => (1, 1),
+        };
+        let feedback_size = match textures.feedback_mode {
+            FeedbackMode::StorageBuffer => (1, 1),
+            FeedbackMode::Separate | FeedbackMode::Interleaved => prepass_size,
+        };
+        (
+            create_texture(
+                "prepass texture",
+                feedback_size,
+                1,
+                Self::FEEDBACK_FORMAT,
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            ),
+            create_texture(
+                "prepass depth texture",
+                prepass_depth_size,
+                1,
+                options.depth_format,
+                wgpu::TextureUsages::RENDER_ATTACHMENT,
+            ),
+            create_texture(
+                "render depth texture",
+                (size.width, size.height),
+                textures.msaa_samples,
+                options.depth_format,
+                match textures.hi_z {
+                    // Read by the construction of the pyramid.
+                    true => {
+                        wgpu::TextureUsages::RENDER_ATTACHMENT
+                            | wgpu::TextureUsages::TEXTURE_BINDING
+                    }
+                    false => wgpu::TextureUsages::RENDER_ATTACHMENT,
+                },
+            ),
+            (textures.msaa_samples > 1).then(|| {
+                create_texture(
+                    "msaa texture",
+                    (size.width, size.height),
+                    textures.msaa_samples,
+                    context.surface_format,
+                    wgpu::TextureUsages::RENDER_ATTACHMENT,
+                )
+            }),
+        )
+    }
+
+    /// The prepass of [`FeedbackMode::StorageBuffer`], bound to the feedback and camera bind groups
+    /// of `bind_group_layouts`, and its bind group of the requests buffers. The prepass also binds
+    /// [`HiZ::bind_group`] to group 3 with [`Textures::hi_z`].
+    fn create_prepass_storage_pipeline(
+        context: &WgpuContext,
+        textures: &Textures,
+        options: &PipelineOptions,
+        bind_group_layouts: &[&wgpu::BindGroupLayout; 2],
+        requested_pages_buffer: &wgpu::Buffer,
+    ) -> (wgpu::RenderPipeline, wgpu::BindGroup) {
+        let shader = context
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("prepass_storage.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(
+                    Self::prepass_storage_shader(options, textures.hi_z).into(),
+                ),
+            });
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = context.resources.bind_group_layout(
+            &context.device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("feedback storage bind group layout"),
+                entries: &[storage_entry(0), storage_entry(1)],
+            },
+        );
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("feedback storage bind group"),
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: requested_pages_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: textures.feedback_requests_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+        let hi_z_bind_group_layout = HiZ::bind_group_layout(context);
+        let mut layouts = vec![
+            bind_group_layouts[0],
+            bind_group_layouts[1],
+            &*bind_group_layout,
+        ];
+        if textures.hi_z {
+            layouts.push(&hi_z_bind_group_layout);
+        }
+        let layout = context
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("prepass storage pipeline layout"),
+                bind_group_layouts: &layouts,
+                push_constant_ranges: &[],
+            });
+        let pipeline = context
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("prepass storage pipeline"),
+                layout: Some(&layout),
+                primitive: options.primitive_state(),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_prepass",
+                    buffers: &[super::vertex::Vertex::BUFFER_LAYOUT],
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: options.depth_format,
+                    depth_write_enabled: true,
+                    depth_compare: options.depth_compare(false),
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: match textures.hi_z {
+                        true => "fs_prepass_storage_hi_z",
+                        false => "fs_prepass_storage",
+                    },
+                    targets: &[],
+                }),
+                multiview: None,
+            });
+        (pipeline, bind_group)
+    }
+
+    fn create_feedback_reduction_bind_group(
+        context: &WgpuContext,
+        textures: &Textures,
+        layout: &wgpu::BindGroupLayout,
+        feedback_uniforms_buffer: &wgpu::Buffer,
+        prepass_texture: &wgpu::Texture,
+        requested_pages_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        let feedback_view = prepass_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("feedback reduction bind group"),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: feedback_uniforms_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&feedback_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: requested_pages_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: textures.feedback_requests_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CameraUniforms, FragmentShader, Pipelines, SamplingQuality};
+    use crate::camera::{Camera, CameraModule, CameraProjection};
+
+    fn validate(source: &str) {
+        let module = naga::front::wgsl::parse_str(source)
+            .unwrap_or_else(|error| panic!("{}", error.emit_to_string(source)));
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::empty(),
+        )
+        .validate(&module)
+        .unwrap();
+    }
+
+    #[test]
+    fn prepass_shader_is_valid() {
+        validate(
+            &(Pipelines::feedback_shader_snippet(0)
+                + &Pipelines::transform_shader_snippet(1)
+                + include_str!("prepass.wgsl")),
+        );
+    }
+
+    #[test]
+    fn render_shader_is_valid() {
+        SamplingQuality::ALL.into_iter().for_each(|quality| {
+            validate(&Pipelines::render_shader_source(
+                2,
+                quality,
+                &FragmentShader::default().source,
+            ))
+        });
+    }
+
+    /// The snippet binds anywhere in the pipelines of other renderers.
+    #[test]
+    fn sampling_shader_is_valid() {
+        SamplingQuality::ALL.into_iter().for_each(|quality| {
+            validate(
+                &(Pipelines::sampling_shader_source(2, 2, quality)
+                    + "@fragment\n\
+                    fn fs_main(@location(0) uv: vec2<f32>) -> @location(0) vec4<f32> {\n\
+                        let normal = virtual_texture_sample_normal(vt_layer_1, vt_hot_layer_1, uv, 0u);\n\
+                        return sample_virtual(uv, 0u) * max(normal.z, 0.0);\n\
+                    }\n"),
+            )
+        });
+    }
+
+    #[test]
+    fn virtual_texture_compute_shader_is_valid() {
+        validate(
+            &(Pipelines::virtual_texture_compute_shader_snippet(1, 2)
+                + "@group(0) @binding(0)\nvar<storage, read_write> heights: array<f32>;\n\n\
+                @compute @workgroup_size(8, 8)\n\
+                fn erode(@builtin(global_invocation_id) id: vec3<u32>) {\n\
+                    let uv = vec2<f32>(id.xy) / 64.0;\n\
+                    if virtual_texture_is_resident(uv, 0u, 2u) {\n\
+                        heights[id.y * 64u + id.x] = \
+                            virtual_texture_sample_level(vt_layer_1, vt_hot_layer_1, uv, 0u, 2u).r;\n\
+                    }\n\
+                }\n"),
+        );
+    }
+
+    #[test]
+    fn feedback_reduction_shader_is_valid() {
+        validate(&Pipelines::feedback_reduction_shader());
+    }
+
+    #[test]
+    fn requested_pages_bitset_size() {
+        // 4x4 + 2x2 + 1x1 pages, in one word.
+        assert_eq!(Pipelines::requested_pages_buffer_size(4, 1), 4);
+        // 1365 pages in 43 words.
+        assert_eq!(Pipelines::requested_pages_buffer_size(32, 1), 43 * 4);
+        // 21 pages per texture, in 11 words for 16 textures.
+        assert_eq!(Pipelines::requested_pages_buffer_size(4, 16), 11 * 4);
+    }
+
+    /// The matrix is uploaded column major, as WGSL expects it.
+    #[test]
+    fn camera_uniforms_layout() {
+        let camera = CameraModule::from_parts(
+            Camera::new(nalgebra::Point3::new(1.0, 2.0, 3.0), 0.5, 0.25),
+            CameraProjection::new(1.5, 1.0, 0.1, 100.0),
+            Default::default(),
+        );
+        let matrix = camera.view_proj_matrix();
+        let uniforms = CameraUniforms::from(&camera);
+        (0..4).for_each(|column| {
+            (0..4).for_each(|row| {
+                assert_eq!(uniforms.view_proj[column][row], matrix[(row, column)]);
+            })
+        });
+    }
+}
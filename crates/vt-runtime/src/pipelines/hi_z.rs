@@ -0,0 +1,371 @@
+use std::{num::NonZeroU64, sync::Arc};
+
+use crate::{pipelines::PipelineOptions, setup::WgpuContext};
+
+/// The uniforms of the occlusion test of the prepass, see `prepass_hi_z.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct HiZUniforms {
+    /// The texels of the render depth on the side of a texel of the prepass.
+    pub scale: [f32; 2],
+    /// 0 until the render depth holds the depth of a frame, see [`HiZ::depth_rendered`].
+    pub enabled: u32,
+    /// The level of the pyramid read by the prepass, see [`HiZ::level`].
+    pub level: u32,
+}
+
+/// A hierarchical depth (Hi-Z) pyramid of the depth of the previous frame, which the prepass of
+/// [`FeedbackMode::StorageBuffer`](super::FeedbackMode::StorageBuffer) tests its fragments
+/// against to drop the requests of the hidden ones, see
+/// [`VirtualTexturingConfig::hi_z`](crate::config::VirtualTexturingConfig::hi_z).
+///
+/// Level 0 holds the depth of the render pass, and every next level the farthest depth of the
+/// texels of the previous one it covers, up to the level whose texels cover a texel of the
+/// prepass. The pyramid is built from the depth the render pass left at the start of the prepass,
+/// so a surface coming into view behind another only requests its pages a frame late.
+pub struct HiZ {
+    /// R32Float, at the size of the render target.
+    pub texture: wgpu::Texture,
+    pub uniforms_buffer: wgpu::Buffer,
+    /// Binds the uniforms and the pyramid to group 3 of the prepass, see
+    /// [`HiZ::bind_group_layout`].
+    pub bind_group: wgpu::BindGroup,
+    /// Whether the render depth holds the depth of a frame, instead of the zeroes it is created
+    /// with. The prepass does not test its fragments until then.
+    pub depth_rendered: bool,
+    depth_pipeline: wgpu::ComputePipeline,
+    downsample_pipeline: wgpu::ComputePipeline,
+    depth_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    downsample_bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    /// One per level, from the depth for level 0 and from the previous level for the next ones.
+    level_bind_groups: Vec<wgpu::BindGroup>,
+    /// See [`HiZUniforms::scale`].
+    scale: [f32; 2],
+}
+
+impl HiZ {
+    /// The size of the workgroups of the construction of the pyramid, on each side.
+    const WORKGROUP_SIZE: u32 = 8;
+
+    /// The layout of [`HiZ::bind_group`], shared with the prepass pipeline.
+    pub fn bind_group_layout(context: &WgpuContext) -> Arc<wgpu::BindGroupLayout> {
+        context.resources.bind_group_layout(
+            &context.device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("hi-z bind group layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: NonZeroU64::new(
+                                std::mem::size_of::<HiZUniforms>() as u64
+                            ),
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        )
+    }
+
+    /// WGSL declaring `HI_Z_REVERSE_Z` for the depth convention of `options`.
+    pub fn reverse_z_constant(options: &PipelineOptions) -> String {
+        format!("const HI_Z_REVERSE_Z: bool = {};\n", options.reverse_z)
+    }
+
+    /// The pyramid of `depth_texture`, the single sampled depth of the render pass, for the
+    /// prepass writing `prepass_depth_texture`.
+    pub fn new(
+        context: &WgpuContext,
+        options: &PipelineOptions,
+        depth_texture: &wgpu::Texture,
+        prepass_depth_texture: &wgpu::Texture,
+    ) -> Self {
+        let shader = context
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("hi_z.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(
+                    (Self::reverse_z_constant(options) + include_str!("../hi_z.wgsl")).into(),
+                ),
+            });
+        let target_entry = wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: wgpu::TextureFormat::R32Float,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let depth_bind_group_layout = context.resources.bind_group_layout(
+            &context.device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("hi-z depth bind group layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        // Loaded as a float texture, since GLSL has no loads of depth textures.
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    target_entry,
+                ],
+            },
+        );
+        let downsample_bind_group_layout = context.resources.bind_group_layout(
+            &context.device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("hi-z downsample bind group layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    target_entry,
+                ],
+            },
+        );
+        let create_pipeline = |label, layout: &wgpu::BindGroupLayout, entry_point| {
+            let pipeline_layout =
+                context
+                    .device
+                    .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: Some(label),
+                        bind_group_layouts: &[layout],
+                        push_constant_ranges: &[],
+                    });
+            context
+                .device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+        };
+        let depth_pipeline = create_pipeline(
+            "hi-z depth pipeline",
+            &depth_bind_group_layout,
+            "cs_hi_z_depth",
+        );
+        let downsample_pipeline = create_pipeline(
+            "hi-z downsample pipeline",
+            &downsample_bind_group_layout,
+            "cs_hi_z_downsample",
+        );
+        let uniforms_buffer = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("hi-z uniforms buffer"),
+            size: std::mem::size_of::<HiZUniforms>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let (texture, bind_group, level_bind_groups, scale) = Self::create_pyramid(
+            context,
+            &depth_bind_group_layout,
+            &downsample_bind_group_layout,
+            &uniforms_buffer,
+            depth_texture,
+            prepass_depth_texture,
+        );
+        Self {
+            texture,
+            uniforms_buffer,
+            bind_group,
+            depth_rendered: false,
+            depth_pipeline,
+            downsample_pipeline,
+            depth_bind_group_layout,
+            downsample_bind_group_layout,
+            level_bind_groups,
+            scale,
+        }
+    }
+
+    /// Recreate the pyramid for the depth textures recreated by
+    /// [`Pipelines::resize`](super::Pipelines::resize), whose depth is not rendered yet.
+    pub fn resize(
+        &mut self,
+        context: &WgpuContext,
+        depth_texture: &wgpu::Texture,
+        prepass_depth_texture: &wgpu::Texture,
+    ) {
+        (
+            self.texture,
+            self.bind_group,
+            self.level_bind_groups,
+            self.scale,
+        ) = Self::create_pyramid(
+            context,
+            &self.depth_bind_group_layout,
+            &self.downsample_bind_group_layout,
+            &self.uniforms_buffer,
+            depth_texture,
+            prepass_depth_texture,
+        );
+        self.depth_rendered = false;
+    }
+
+    /// The level read by the prepass, the first whose texels are at least as large as the
+    /// texels of the prepass, within the levels of the render target.
+    pub fn level(&self) -> u32 {
+        self.texture.mip_level_count() - 1
+    }
+
+    /// Write the uniforms with the queue, and record the construction of the pyramid if the
+    /// render depth holds the depth of a frame.
+    pub fn build(&self, context: &WgpuContext, command_encoder: &mut wgpu::CommandEncoder) {
+        let uniforms = HiZUniforms {
+            scale: self.scale,
+            enabled: self.depth_rendered as u32,
+            level: self.level(),
+        };
+        context
+            .queue
+            .write_buffer(&self.uniforms_buffer, 0, bytemuck::bytes_of(&uniforms));
+        if !self.depth_rendered {
+            return;
+        }
+
+        command_encoder.push_debug_group("hi-z");
+        let mut compute_pass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("hi-z pass"),
+            timestamp_writes: None,
+        });
+        self.level_bind_groups
+            .iter()
+            .enumerate()
+            .for_each(|(level, bind_group)| {
+                compute_pass.set_pipeline(match level {
+                    0 => &self.depth_pipeline,
+                    _ => &self.downsample_pipeline,
+                });
+                compute_pass.set_bind_group(0, bind_group, &[]);
+                let size = self
+                    .texture
+                    .size()
+                    .mip_level_size(level as u32, wgpu::TextureDimension::D2);
+                compute_pass.dispatch_workgroups(
+                    size.width.div_ceil(Self::WORKGROUP_SIZE),
+                    size.height.div_ceil(Self::WORKGROUP_SIZE),
+                    1,
+                );
+            });
+        drop(compute_pass);
+        command_encoder.pop_debug_group();
+    }
+
+    /// The pyramid texture, its bind group of the prepass, the bind groups of its levels and
+    /// the scale of the prepass.
+    fn create_pyramid(
+        context: &WgpuContext,
+        depth_bind_group_layout: &wgpu::BindGroupLayout,
+        downsample_bind_group_layout: &wgpu::BindGroupLayout,
+        uniforms_buffer: &wgpu::Buffer,
+        depth_texture: &wgpu::Texture,
+        prepass_depth_texture: &wgpu::Texture,
+    ) -> (
+        wgpu::Texture,
+        wgpu::BindGroup,
+        Vec<wgpu::BindGroup>,
+        [f32; 2],
+    ) {
+        let size = depth_texture.size();
+        let scale = [
+            size.width as f32 / prepass_depth_texture.width() as f32,
+            size.height as f32 / prepass_depth_texture.height() as f32,
+        ];
+        let max_levels = size.max_mips(wgpu::TextureDimension::D2);
+        let level = (scale[0].max(scale[1]).log2().ceil().max(0.0) as u32).min(max_levels - 1);
+        let texture = context.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("hi-z texture"),
+            size,
+            mip_level_count: level + 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let level_view = |level| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+        };
+        let depth_view = depth_texture.create_view(&wgpu::TextureViewDescriptor {
+            aspect: wgpu::TextureAspect::DepthOnly,
+            ..Default::default()
+        });
+        let level_bind_groups = (0..=level)
+            .map(|level| {
+                let (layout, source_binding, source_view) = match level {
+                    0 => (depth_bind_group_layout, 0, &depth_view),
+                    _ => (downsample_bind_group_layout, 1, &level_view(level - 1)),
+                };
+                context
+                    .device
+                    .create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: Some("hi-z level bind group"),
+                        layout,
+                        entries: &[
+                            wgpu::BindGroupEntry {
+                                binding: source_binding,
+                                resource: wgpu::BindingResource::TextureView(source_view),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 2,
+                                resource: wgpu::BindingResource::TextureView(&level_view(level)),
+                            },
+                        ],
+                    })
+            })
+            .collect();
+        let bind_group = context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("hi-z bind group"),
+                layout: &Self::bind_group_layout(context),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: uniforms_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(
+                            &texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                        ),
+                    },
+                ],
+            });
+        (texture, bind_group, level_bind_groups, scale)
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+};
+
+/// The bind group layouts and samplers of a device, shared by descriptor between the
+/// [`Pipelines`](super::Pipelines), the debug pipelines and the user's own pipelines.
+///
+/// Asking twice for the same descriptor returns the same object, so that pipelines created from
+/// equal layouts accept each other's bind groups. Labels are not part of the descriptor: the first
+/// label asked for is kept. The registry only keeps weak references, so objects whose handles
+/// are all dropped are recreated the next time they are asked for.
+#[derive(Default)]
+pub struct ResourceRegistry {
+    bind_group_layouts:
+        Mutex<HashMap<Vec<wgpu::BindGroupLayoutEntry>, Weak<wgpu::BindGroupLayout>>>,
+    samplers: Mutex<HashMap<SamplerKey, Weak<wgpu::Sampler>>>,
+}
+
+impl ResourceRegistry {
+    /// The bind group layout of `descriptor`, created with `device` if no live one has its
+    /// entries.
+    pub fn bind_group_layout(
+        &self,
+        device: &wgpu::Device,
+        descriptor: &wgpu::BindGroupLayoutDescriptor,
+    ) -> Arc<wgpu::BindGroupLayout> {
+        let mut layouts = self.bind_group_layouts.lock().unwrap();
+        get_or_create(&mut layouts, descriptor.entries.to_vec(), || {
+            device.create_bind_group_layout(descriptor)
+        })
+    }
+
+    /// The sampler of `descriptor`, created with `device` if no live one has its parameters.
+    pub fn sampler(
+        &self,
+        device: &wgpu::Device,
+        descriptor: &wgpu::SamplerDescriptor,
+    ) -> Arc<wgpu::Sampler> {
+        let mut samplers = self.samplers.lock().unwrap();
+        get_or_create(&mut samplers, SamplerKey::new(descriptor), || {
+            device.create_sampler(descriptor)
+        })
+    }
+
+    /// The number of live bind group layouts.
+    pub fn bind_group_layout_count(&self) -> usize {
+        live_count(&self.bind_group_layouts.lock().unwrap())
+    }
+
+    /// The number of live samplers.
+    pub fn sampler_count(&self) -> usize {
+        live_count(&self.samplers.lock().unwrap())
+    }
+}
+
+/// The object of `key`, or a new one from `create`, forgetting the dropped objects.
+fn get_or_create<K: std::hash::Hash + Eq, T>(
+    objects: &mut HashMap<K, Weak<T>>,
+    key: K,
+    create: impl FnOnce() -> T,
+) -> Arc<T> {
+    if let Some(object) = objects.get(&key).and_then(Weak::upgrade) {
+        return object;
+    }
+    objects.retain(|_, object| object.strong_count() > 0);
+    let object = Arc::new(create());
+    objects.insert(key, Arc::downgrade(&object));
+    object
+}
+
+fn live_count<K, T>(objects: &HashMap<K, Weak<T>>) -> usize {
+    objects
+        .values()
+        .filter(|object| object.strong_count() > 0)
+        .count()
+}
+
+/// The parameters of a [`wgpu::SamplerDescriptor`] without its label, with the clamps as bits
+/// since floats are not hashable.
+#[derive(PartialEq, Eq, Hash)]
+struct SamplerKey {
+    address_modes: [wgpu::AddressMode; 3],
+    filters: [wgpu::FilterMode; 3],
+    lod_clamps: [u32; 2],
+    compare: Option<wgpu::CompareFunction>,
+    anisotropy_clamp: u16,
+    border_color: Option<wgpu::SamplerBorderColor>,
+}
+
+impl SamplerKey {
+    fn new(descriptor: &wgpu::SamplerDescriptor) -> Self {
+        Self {
+            address_modes: [
+                descriptor.address_mode_u,
+                descriptor.address_mode_v,
+                descriptor.address_mode_w,
+            ],
+            filters: [
+                descriptor.mag_filter,
+                descriptor.min_filter,
+                descriptor.mipmap_filter,
+            ],
+            lod_clamps: [
+                descriptor.lod_min_clamp.to_bits(),
+                descriptor.lod_max_clamp.to_bits(),
+            ],
+            compare: descriptor.compare,
+            anisotropy_clamp: descriptor.anisotropy_clamp,
+            border_color: descriptor.border_color,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::{pipelines::Pipelines, setup::VirtualTexturingContext};
+
+    #[test]
+    fn shares_objects_by_descriptor() {
+        let context = crate::headless_or_skip!(4, 4);
+        let resources = &context.resources;
+        let entries = [wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        }];
+        let layout = |label, entries| {
+            resources.bind_group_layout(
+                &context.device,
+                &wgpu::BindGroupLayoutDescriptor { label, entries },
+            )
+        };
+        let first = layout(Some("first"), &entries);
+        assert!(Arc::ptr_eq(&first, &layout(Some("second"), &entries)));
+        assert!(!Arc::ptr_eq(&first, &layout(None, &[])));
+
+        let linear = wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        };
+        let sampler = resources.sampler(&context.device, &linear);
+        assert!(Arc::ptr_eq(
+            &sampler,
+            &resources.sampler(&context.device, &linear)
+        ));
+        assert!(!Arc::ptr_eq(
+            &sampler,
+            &resources.sampler(&context.device, &Default::default())
+        ));
+        assert_eq!(resources.sampler_count(), 1);
+
+        let count = resources.bind_group_layout_count();
+        drop(first);
+        assert_eq!(resources.bind_group_layout_count(), count - 1);
+    }
+
+    /// Other renderers get the virtual texture layout of the pipelines from its entries.
+    #[test]
+    fn shares_virtual_texture_layout() {
+        let wgpu_context = crate::headless_or_skip!(4, 4);
+        let context =
+            VirtualTexturingContext::from_config(Arc::new(wgpu_context), Default::default());
+        let wgpu_context = &context.wgpu_context;
+        let layout = wgpu_context.resources.bind_group_layout(
+            &wgpu_context.device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &Pipelines::virtual_texture_bind_group_layout_entries(
+                    context.textures.physical_textures.len(),
+                ),
+            },
+        );
+        assert!(Arc::ptr_eq(
+            &layout,
+            &context.pipelines.virtual_texture_bind_group_layout
+        ));
+    }
+}
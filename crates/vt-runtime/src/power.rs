@@ -0,0 +1,140 @@
+//! Power modes trading streaming throughput for energy, for laptops and handhelds.
+
+#[cfg(any(target_os = "linux", test))]
+use std::path::Path;
+
+use miniserde::{Deserialize, MiniSerialize};
+
+/// How much work the streaming does every frame.
+///
+/// Can be changed at runtime with
+/// [`VirtualTexturingContext::set_power_mode`](crate::setup::VirtualTexturingContext::set_power_mode)
+/// and [`StreamingHandle::set_power_mode`](crate::streaming::StreamingHandle::set_power_mode).
+#[derive(MiniSerialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PowerMode {
+    /// Request and stream pages every frame, to converge as fast as possible.
+    #[default]
+    Performance,
+    /// Produce the feedback less often, keep pages resident longer and upload fewer pages at
+    /// once, so that the disk and the bus are not kept busy every frame. Pages take longer to
+    /// appear when the camera moves.
+    LowPower,
+}
+
+/// The streaming parameters of a [`PowerMode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamingPolicy {
+    /// The feedback is produced and read back once every `feedback_interval` frames.
+    pub feedback_interval: u32,
+    /// The frames a page stays resident after it was last requested, before it can be evicted.
+    pub keep_alive_frames: u32,
+    /// The pages uploaded at most between two feedback readbacks, see
+    /// [`StreamingHandle::upload_budget`](crate::streaming::StreamingHandle::upload_budget).
+    pub max_uploads_per_feedback: u32,
+    /// Queue the pages expected to be requested soon, see
+    /// [`StreamingHandle::queue_prefetch`](crate::streaming::StreamingHandle::queue_prefetch).
+    pub prefetch: bool,
+}
+
+impl PowerMode {
+    pub fn streaming_policy(self) -> StreamingPolicy {
+        match self {
+            PowerMode::Performance => StreamingPolicy {
+                feedback_interval: 1,
+                keep_alive_frames: 60,
+                max_uploads_per_feedback: 64,
+                prefetch: true,
+            },
+            PowerMode::LowPower => StreamingPolicy {
+                feedback_interval: 4,
+                keep_alive_frames: 600,
+                max_uploads_per_feedback: 16,
+                prefetch: false,
+            },
+        }
+    }
+
+    /// The mode matching the power state of the device: [`PowerMode::LowPower`] when running on
+    /// battery or when the power saving profile of the platform is selected.
+    ///
+    /// Returns `None` on unsupported platforms and on devices reporting no power supply. Only
+    /// Linux is supported, through `/sys`. This reads a few files, so it should be polled every
+    /// few seconds at most.
+    pub fn detect() -> Option<PowerMode> {
+        #[cfg(target_os = "linux")]
+        return detect_sysfs(Path::new("/sys"));
+        #[cfg(not(target_os = "linux"))]
+        return None;
+    }
+}
+
+/// [`PowerMode::detect`] from the sysfs mounted at `root`.
+#[cfg(any(target_os = "linux", test))]
+fn detect_sysfs(root: &Path) -> Option<PowerMode> {
+    let read = |path: &Path| {
+        std::fs::read_to_string(path)
+            .ok()
+            .map(|content| content.trim().to_owned())
+    };
+    if read(&root.join("firmware/acpi/platform_profile")).as_deref() == Some("low-power") {
+        return Some(PowerMode::LowPower);
+    }
+
+    let (mut on_mains, mut on_battery) = (None, false);
+    for supply in std::fs::read_dir(root.join("class/power_supply")).ok()? {
+        let supply = supply.ok()?.path();
+        match read(&supply.join("type")).as_deref() {
+            Some("Mains") => {
+                let online = read(&supply.join("online")).as_deref() == Some("1");
+                on_mains = Some(on_mains.unwrap_or(false) || online);
+            }
+            Some("Battery") => {
+                on_battery |= read(&supply.join("status")).as_deref() == Some("Discharging")
+            }
+            _ => (),
+        }
+    }
+    match (on_mains, on_battery) {
+        (Some(false), _) | (_, true) => Some(PowerMode::LowPower),
+        (Some(true), false) => Some(PowerMode::Performance),
+        (None, false) => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::{fixture::TempDir, prelude::*};
+
+    use super::{detect_sysfs, PowerMode};
+
+    #[test]
+    fn detect_power_state() {
+        let temp_dir = TempDir::new().unwrap();
+        assert_eq!(detect_sysfs(temp_dir.path()), None);
+
+        let supplies = temp_dir.child("class/power_supply");
+        supplies.child("AC/type").write_str("Mains\n").unwrap();
+        supplies.child("AC/online").write_str("1\n").unwrap();
+        supplies.child("BAT0/type").write_str("Battery\n").unwrap();
+        supplies
+            .child("BAT0/status")
+            .write_str("Charging\n")
+            .unwrap();
+        assert_eq!(detect_sysfs(temp_dir.path()), Some(PowerMode::Performance));
+
+        supplies.child("AC/online").write_str("0\n").unwrap();
+        supplies
+            .child("BAT0/status")
+            .write_str("Discharging\n")
+            .unwrap();
+        assert_eq!(detect_sysfs(temp_dir.path()), Some(PowerMode::LowPower));
+
+        supplies.child("AC/online").write_str("1\n").unwrap();
+        supplies.child("BAT0/status").write_str("Full\n").unwrap();
+        temp_dir
+            .child("firmware/acpi/platform_profile")
+            .write_str("low-power\n")
+            .unwrap();
+        assert_eq!(detect_sysfs(temp_dir.path()), Some(PowerMode::LowPower));
+    }
+}
@@ -0,0 +1,93 @@
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+}
+
+struct PrepassInterpolators {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) texture_id: u32,
+    @location(2) @interpolate(flat) mip_range: vec2<u32>,
+}
+
+@vertex
+fn vs_prepass(in: VertexInput) -> PrepassInterpolators {
+    var out: PrepassInterpolators;
+    out.position = transform_position(in.position);
+    out.uv = in.uv;
+    out.texture_id = draw.texture_id;
+    out.mip_range = vec2<u32>(draw.min_mip, draw.max_mip);
+    return out;
+}
+
+@fragment
+fn fs_prepass(in: PrepassInterpolators) -> @location(0) vec4<u32> {
+    return virtual_texture_feedback_in_range(in.uv, in.texture_id, in.mip_range);
+}
+
+// ==============
+// Debug Prepass
+// ==============
+
+// Render the contents of the feedback buffer to the screen.
+
+struct DebugInterpolators {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+// meant to be called with 3 vertex indices: 0, 1, 2
+// draws one large triangle over the clip space like this:
+// (the asterisks represent the clip space bounds)
+//-1,1           1,1
+// ---------------------------------
+// |              *              .
+// |              *           .
+// |              *        .
+// |              *      .
+// |              *    . 
+// |              * .
+// |***************
+// |            . 1,-1 
+// |          .
+// |       .
+// |     .
+// |   .
+// |.
+@vertex
+fn vs_debug_prepass(@builtin(vertex_index) vertex_index: u32) -> DebugInterpolators {
+    var result: DebugInterpolators;
+    let x = i32(vertex_index) / 2;
+    let y = i32(vertex_index) & 1;
+    let tc = vec2<f32>(
+        f32(x) * 2.0,
+        f32(y) * 2.0
+    );
+    result.position = vec4<f32>(
+        tc.x * 2.0 - 1.0,
+        1.0 - tc.y * 2.0,
+        0.0,
+        1.0
+    );
+    result.tex_coords = tc;
+    return result;
+}
+
+@group(0) @binding(0)
+var debug_tex: texture_2d<u32>;
+
+@fragment
+fn fs_debug_prepass(in: DebugInterpolators) -> @location(0) vec4<f32> {
+    let texture_dims = vec2<f32>(textureDimensions(debug_tex));
+    let texture_index = vec2<u32>(in.tex_coords * texture_dims);
+    let texel = textureLoad(debug_tex, texture_index, 0);
+
+    let color = vec4<f32>(
+        f32(texel.x) / 255.,
+        f32(texel.y) / 255.,
+        f32(texel.z) / 255.,
+        1.0,
+    );
+    return color;
+}
@@ -0,0 +1,60 @@
+// Occlusion test of the prepass recording its requests to storage buffers, against the Hi-Z
+// pyramid of the previous frame (see `pipelines::HiZ`). Appended to `prepass_storage.wgsl` by
+// `Pipelines::prepass_storage_shader`, after `HI_Z_REVERSE_Z`.
+
+// Mirrors `pipelines::hi_z::HiZUniforms`.
+struct HiZUniforms {
+    // The texels of the render depth on the side of a texel of the prepass.
+    scale: vec2<f32>,
+    // 0 until the render depth holds the depth of a frame.
+    enabled: u32,
+    // The level of the pyramid read, whose texels are at least as large as `scale`.
+    level: u32,
+}
+
+// The depths of a fragment and of the pyramid differ by the rasterization of the same surface at
+// other positions and resolutions.
+const HI_Z_DEPTH_EPSILON: f32 = 0.0001;
+
+@group(3) @binding(0)
+var<uniform> hi_z: HiZUniforms;
+@group(3) @binding(1)
+var hi_z_texture: texture_2d<f32>;
+
+// Whether the fragment at `position` is behind the farthest depth rendered over its texel of
+// the prepass, the two or fewer texels on each side of the pyramid it covers.
+fn hi_z_occluded(position: vec4<f32>) -> bool {
+    if hi_z.enabled == 0u {
+        return false;
+    }
+    let last_texel = textureDimensions(hi_z_texture, hi_z.level) - 1u;
+    let texel = floor(position.xy);
+    let first = min(vec2<u32>(texel * hi_z.scale) >> vec2(hi_z.level), last_texel);
+    let last = min(
+        vec2<u32>(max(ceil((texel + 1.0) * hi_z.scale) - 1.0, vec2(0.0))) >> vec2(hi_z.level),
+        last_texel,
+    );
+    var farthest = textureLoad(hi_z_texture, first, i32(hi_z.level)).r;
+    for (var y = first.y; y <= last.y; y++) {
+        for (var x = first.x; x <= last.x; x++) {
+            let depth = textureLoad(hi_z_texture, vec2(x, y), i32(hi_z.level)).r;
+            if HI_Z_REVERSE_Z {
+                farthest = min(farthest, depth);
+            } else {
+                farthest = max(farthest, depth);
+            }
+        }
+    }
+    if HI_Z_REVERSE_Z {
+        return position.z < farthest - HI_Z_DEPTH_EPSILON;
+    }
+    return position.z > farthest + HI_Z_DEPTH_EPSILON;
+}
+
+@fragment
+fn fs_prepass_storage_hi_z(in: PrepassInterpolators) {
+    if hi_z_occluded(in.position) {
+        return;
+    }
+    request_feedback_page(virtual_texture_feedback_in_range(in.uv, in.texture_id, in.mip_range));
+}
@@ -0,0 +1,13 @@
+// Prepass recording its requests straight to the requests buffers, see
+// `FeedbackMode::StorageBuffer`. Appended to `prepass.wgsl` and `feedback_requests.wgsl` by
+// `Pipelines::prepass_storage_shader`.
+
+@group(2) @binding(0)
+var<storage, read_write> requested_bits: array<atomic<u32>>;
+@group(2) @binding(1)
+var<storage, read_write> requests: FeedbackRequests;
+
+@fragment
+fn fs_prepass_storage(in: PrepassInterpolators) {
+    request_feedback_page(virtual_texture_feedback_in_range(in.uv, in.texture_id, in.mip_range));
+}
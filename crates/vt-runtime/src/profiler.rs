@@ -0,0 +1,324 @@
+//! GPU timing of the passes of a frame with timestamp queries, see
+//! [`VirtualTexturingContext::enable_profiler`](crate::setup::VirtualTexturingContext::enable_profiler).
+
+use std::{
+    sync::{
+        atomic::{AtomicU32, AtomicU8, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// The states of the read buffer of the [`Profiler`] being mapped.
+const PENDING: u8 = 0;
+const MAPPED: u8 = 1;
+const FAILED: u8 = 2;
+
+/// The work of a frame timed by the [`Profiler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfiledPass {
+    Prepass,
+    FeedbackReduction,
+    /// The copy of the feedback requests to a read buffer, see
+    /// [`StreamingHandle::submit_feedback`](crate::streaming::StreamingHandle::submit_feedback).
+    FeedbackCopy,
+    Render,
+}
+
+impl ProfiledPass {
+    const ALL: [ProfiledPass; 4] = [
+        ProfiledPass::Prepass,
+        ProfiledPass::FeedbackReduction,
+        ProfiledPass::FeedbackCopy,
+        ProfiledPass::Render,
+    ];
+
+    /// The index of the timestamp written at the beginning of the pass, the one written at its
+    /// end being the next.
+    fn query_index(self) -> u32 {
+        self as u32 * 2
+    }
+}
+
+/// The GPU time of the passes of a frame, `None` for the passes the frame did not record.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PassTimings {
+    pub prepass: Option<Duration>,
+    pub feedback_reduction: Option<Duration>,
+    pub feedback_copy: Option<Duration>,
+    pub render: Option<Duration>,
+}
+
+impl PassTimings {
+    pub fn get(&self, pass: ProfiledPass) -> Option<Duration> {
+        match pass {
+            ProfiledPass::Prepass => self.prepass,
+            ProfiledPass::FeedbackReduction => self.feedback_reduction,
+            ProfiledPass::FeedbackCopy => self.feedback_copy,
+            ProfiledPass::Render => self.render,
+        }
+    }
+
+    fn get_mut(&mut self, pass: ProfiledPass) -> &mut Option<Duration> {
+        match pass {
+            ProfiledPass::Prepass => &mut self.prepass,
+            ProfiledPass::FeedbackReduction => &mut self.feedback_reduction,
+            ProfiledPass::FeedbackCopy => &mut self.feedback_copy,
+            ProfiledPass::Render => &mut self.render,
+        }
+    }
+
+    /// The time of every pass recorded by the frame.
+    pub fn total(&self) -> Duration {
+        ProfiledPass::ALL
+            .iter()
+            .filter_map(|&pass| self.get(pass))
+            .sum()
+    }
+}
+
+/// Writes timestamps around the passes of every frame, and reads them back without blocking.
+///
+/// The timings of a frame are available once its work completed on the GPU, a few frames after
+/// it was submitted, see [`Profiler::timings`]. The frames submitted while the timestamps of an
+/// earlier one are read back are not timed.
+pub struct Profiler {
+    query_set: wgpu::QuerySet,
+    /// The timestamps resolved by the frame, a pair per pass, copied to `read_buffer`.
+    resolve_buffer: wgpu::Buffer,
+    read_buffer: wgpu::Buffer,
+    /// Nanoseconds per tick of the timestamps.
+    period: f32,
+    /// The passes of the current frame with timestamps, by bit of [`ProfiledPass`].
+    written: AtomicU32,
+    /// The passes whose timestamps are in `read_buffer`, `None` when it is not in use.
+    read_passes: Option<u32>,
+    /// Whether the read back was requested, once the frame copying to `read_buffer` is submitted.
+    mapping: bool,
+    /// [`PENDING`] until `read_buffer` is mapped, then [`MAPPED`], or [`FAILED`] if it could not
+    /// be.
+    map_state: Arc<AtomicU8>,
+    timings: Option<PassTimings>,
+}
+
+impl Profiler {
+    const QUERY_COUNT: u32 = ProfiledPass::ALL.len() as u32 * 2;
+    const BUFFER_SIZE: wgpu::BufferAddress =
+        Self::QUERY_COUNT as wgpu::BufferAddress * wgpu::QUERY_SIZE as wgpu::BufferAddress;
+
+    /// A profiler of the passes submitted to `queue`, `None` if `device` does not enable
+    /// [`wgpu::Features::TIMESTAMP_QUERY`].
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("pass timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: Self::QUERY_COUNT,
+        });
+        let buffer = |label, size, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        Some(Self {
+            query_set,
+            resolve_buffer: buffer(
+                "timestamp resolve buffer",
+                ProfiledPass::ALL.len() as u64 * wgpu::QUERY_RESOLVE_BUFFER_ALIGNMENT,
+                wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            ),
+            read_buffer: buffer(
+                "timestamp read buffer",
+                Self::BUFFER_SIZE,
+                wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            ),
+            period: queue.get_timestamp_period(),
+            written: AtomicU32::new(0),
+            read_passes: None,
+            mapping: false,
+            map_state: Arc::default(),
+            timings: None,
+        })
+    }
+
+    /// The timings of the last frame read back, `None` until one is.
+    pub fn timings(&self) -> Option<PassTimings> {
+        self.timings
+    }
+
+    /// The timestamps of `pass` written at its beginning and end by a render pass.
+    pub fn render_pass_timestamp_writes(
+        &self,
+        pass: ProfiledPass,
+    ) -> wgpu::RenderPassTimestampWrites<'_> {
+        self.written(pass);
+        wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(pass.query_index()),
+            end_of_pass_write_index: Some(pass.query_index() + 1),
+        }
+    }
+
+    /// The timestamps of `pass` written at its beginning and end by a compute pass.
+    pub fn compute_pass_timestamp_writes(
+        &self,
+        pass: ProfiledPass,
+    ) -> wgpu::ComputePassTimestampWrites<'_> {
+        self.written(pass);
+        wgpu::ComputePassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(pass.query_index()),
+            end_of_pass_write_index: Some(pass.query_index() + 1),
+        }
+    }
+
+    /// Time the commands `record` records to `command_encoder` outside of passes as `pass`.
+    pub fn time_commands<T>(
+        &self,
+        pass: ProfiledPass,
+        command_encoder: &mut wgpu::CommandEncoder,
+        record: impl FnOnce(&mut wgpu::CommandEncoder) -> T,
+    ) -> T {
+        self.written(pass);
+        command_encoder.write_timestamp(&self.query_set, pass.query_index());
+        let result = record(command_encoder);
+        command_encoder.write_timestamp(&self.query_set, pass.query_index() + 1);
+        result
+    }
+
+    fn written(&self, pass: ProfiledPass) {
+        self.written.fetch_or(1 << pass as u32, Ordering::Relaxed);
+    }
+
+    /// Resolve the timestamps of the frame to the read buffer, if it is not in use, to be read
+    /// back once `command_encoder` is submitted (see [`Profiler::frame_submitted`]). Reads the
+    /// timestamps of an earlier frame first if they are mapped.
+    pub fn resolve(&mut self, command_encoder: &mut wgpu::CommandEncoder) {
+        self.read_mapped();
+        let written = self.written.swap(0, Ordering::Relaxed);
+        if written == 0 || self.read_passes.is_some() {
+            return;
+        }
+        // Only the timestamps written are resolved, each pair to an offset of its own since
+        // resolves must be aligned to `QUERY_RESOLVE_BUFFER_ALIGNMENT`.
+        ProfiledPass::ALL
+            .iter()
+            .filter(|&&pass| written & (1 << pass as u32) != 0)
+            .for_each(|&pass| {
+                let resolve_offset = pass as u64 * wgpu::QUERY_RESOLVE_BUFFER_ALIGNMENT;
+                let pair_size = 2 * wgpu::QUERY_SIZE as u64;
+                command_encoder.resolve_query_set(
+                    &self.query_set,
+                    pass.query_index()..pass.query_index() + 2,
+                    &self.resolve_buffer,
+                    resolve_offset,
+                );
+                command_encoder.copy_buffer_to_buffer(
+                    &self.resolve_buffer,
+                    resolve_offset,
+                    &self.read_buffer,
+                    pass as u64 * pair_size,
+                    pair_size,
+                );
+            });
+        self.read_passes = Some(written);
+    }
+
+    /// Map the read buffer, once the frame resolving the timestamps to it is submitted.
+    pub fn frame_submitted(&mut self) {
+        if self.read_passes.is_none() || self.mapping {
+            return;
+        }
+        self.mapping = true;
+        let map_state = Arc::clone(&self.map_state);
+        self.read_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                if let Err(error) = &result {
+                    log::error!("could not map the timestamp read buffer: {error}");
+                }
+                map_state.store(
+                    if result.is_ok() { MAPPED } else { FAILED },
+                    Ordering::Release,
+                );
+            });
+    }
+
+    /// Decode the timestamps of the read buffer once mapped, and release it. The timestamps are
+    /// dropped if it could not be mapped, for the next frames to be timed.
+    fn read_mapped(&mut self) {
+        let Some(passes) = self.read_passes else {
+            return;
+        };
+        match self.map_state.swap(PENDING, Ordering::Acquire) {
+            PENDING => return,
+            FAILED => {
+                self.read_passes = None;
+                self.mapping = false;
+                return;
+            }
+            _ => (),
+        }
+        let timestamps = self
+            .read_buffer
+            .slice(..)
+            .get_mapped_range()
+            .chunks_exact(wgpu::QUERY_SIZE as usize)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        self.read_buffer.unmap();
+        self.timings = Some(decode_timings(&timestamps, passes, self.period));
+        self.read_passes = None;
+        self.mapping = false;
+    }
+}
+
+/// The timings of `passes`, by bit of [`ProfiledPass`], from their pairs of `timestamps` in
+/// ticks of `period` nanoseconds.
+fn decode_timings(timestamps: &[u64], passes: u32, period: f32) -> PassTimings {
+    let mut timings = PassTimings::default();
+    ProfiledPass::ALL
+        .iter()
+        .filter(|&&pass| passes & (1 << pass as u32) != 0)
+        .for_each(|&pass| {
+            let index = pass.query_index() as usize;
+            let ticks = timestamps[index + 1].saturating_sub(timestamps[index]);
+            *timings.get_mut(pass) =
+                Some(Duration::from_nanos((ticks as f64 * period as f64) as u64));
+        });
+    timings
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{decode_timings, PassTimings, ProfiledPass};
+
+    /// Only the passes of the bitmask are decoded, their ticks scaled by the period, and
+    /// timestamps going backwards count as no time.
+    #[test]
+    fn decode_pass_timings() {
+        let timestamps = [100, 400, 7, 7, 1000, 900, 2000, 2010];
+        let passes = 1 << ProfiledPass::Prepass as u32
+            | 1 << ProfiledPass::FeedbackCopy as u32
+            | 1 << ProfiledPass::Render as u32;
+        let timings = decode_timings(&timestamps, passes, 2.5);
+        assert_eq!(
+            timings,
+            PassTimings {
+                prepass: Some(Duration::from_nanos(750)),
+                feedback_reduction: None,
+                feedback_copy: Some(Duration::ZERO),
+                render: Some(Duration::from_nanos(25)),
+            }
+        );
+        assert_eq!(timings.total(), Duration::from_nanos(775));
+        assert_eq!(decode_timings(&timestamps, 0, 1.0), PassTimings::default());
+    }
+}
@@ -0,0 +1,351 @@
+//! A procedural scene and its virtual texture, sized by parameters, as a standardized heavy
+//! workload for performance work.
+//!
+//! The scene is a terrain grid whose uvs span its own image in the texture, and props scattered on
+//! it, each textured by one of many small images packed next to the terrain (see
+//! [`AtlasBuilder`]). Everything is derived from [`StressSceneParams::seed`], so that two runs
+//! with the same parameters draw and stream the same pages.
+
+use std::sync::Arc;
+
+use nalgebra::{Matrix4, Vector3};
+
+use crate::{
+    draw::{DrawItem, Mesh},
+    setup::WgpuContext,
+    storage::{TextureMetadata, TextureStorageError},
+    streaming::UvRect,
+    texture_generation::{Atlas, AtlasBuilder},
+    vertex::Vertex,
+};
+
+/// The name of the terrain image in the atlas of a [`StressScene`].
+pub const TERRAIN_IMAGE: &str = "terrain";
+
+/// The size of a [`StressScene`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StressSceneParams {
+    /// The quads on each side of the terrain grid, one world unit wide each.
+    pub terrain_quads: u32,
+    /// The texels on each side of the terrain image.
+    pub terrain_size: u32,
+    /// The height of the highest hills, in world units.
+    pub terrain_height: f32,
+    pub prop_count: u32,
+    /// The distinct prop images, each shared by the props of one mesh.
+    pub prop_images: u32,
+    /// The texels on each side of the prop images.
+    pub prop_image_size: u32,
+    pub seed: u64,
+}
+
+/// A texture of 64x64 pages of the default size, with half a million triangles on the terrain,
+/// and 24 thousand on the props.
+impl Default for StressSceneParams {
+    fn default() -> Self {
+        Self {
+            terrain_quads: 512,
+            terrain_size: 3072,
+            terrain_height: 24.0,
+            prop_count: 2048,
+            prop_images: 64,
+            prop_image_size: 256,
+            seed: 0,
+        }
+    }
+}
+
+/// A prop of a [`StressScene`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Prop {
+    /// The index of the mesh of the prop in [`StressScene::prop_meshes`].
+    pub mesh: usize,
+    /// From the space of the mesh to world space.
+    pub transform: Matrix4<f32>,
+}
+
+/// A scene generated by [`StressScene::generate`].
+pub struct StressScene {
+    /// The texture of the scene, holding the terrain image and every prop image.
+    pub atlas: Atlas,
+    /// A triangle list `terrain_quads` wide on x and z, centered on the origin, with y up.
+    pub terrain: Vec<Vertex>,
+    /// A unit cube per prop image, with every face textured by the whole image.
+    pub prop_meshes: Vec<Vec<Vertex>>,
+    pub props: Vec<Prop>,
+}
+
+impl StressScene {
+    /// Generate the scene and write its texture to a new texture storage in `directory`, with
+    /// the page size, encoding and compression of `metadata` (see [`AtlasBuilder::build`]).
+    ///
+    /// ### Panics
+    ///
+    /// - If there are props without prop images, or if a side of the terrain is empty.
+    pub fn generate(
+        params: &StressSceneParams,
+        metadata: TextureMetadata,
+        directory: Option<&str>,
+    ) -> Result<Self, TextureStorageError> {
+        assert!(params.terrain_quads > 0 && params.terrain_size > 0);
+        assert!(params.prop_count == 0 || params.prop_images > 0);
+        let mut builder = AtlasBuilder::new().with_padding(1);
+        builder.add_image(TERRAIN_IMAGE, terrain_image(params));
+        (0..params.prop_images).for_each(|index| {
+            builder.add_image(prop_image_name(index), prop_image(params, index));
+        });
+        let atlas = builder.build(metadata, directory, image::imageops::FilterType::Triangle)?;
+
+        let terrain = terrain_mesh(params, atlas.uv_rects[TERRAIN_IMAGE]);
+        let prop_meshes = (0..params.prop_images)
+            .map(|index| cube_mesh(atlas.uv_rects[&prop_image_name(index)]))
+            .collect();
+        let mut random = SplitMix64(params.seed);
+        let half_side = params.terrain_quads as f32 / 2.0;
+        let props = (0..params.prop_count)
+            .map(|_| {
+                let (x, z) = (random.next_f32(), random.next_f32());
+                let scale = 0.5 + random.next_f32() * 1.5;
+                let yaw = random.next_f32() * std::f32::consts::TAU;
+                let y = terrain_height(params, x, z) + scale / 2.0;
+                Prop {
+                    mesh: random.next() as usize % params.prop_images as usize,
+                    transform: Matrix4::new_translation(&Vector3::new(
+                        x * 2.0 * half_side - half_side,
+                        y,
+                        z * 2.0 * half_side - half_side,
+                    )) * Matrix4::from_euler_angles(0.0, yaw, 0.0)
+                        * Matrix4::new_scaling(scale),
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            atlas,
+            terrain,
+            prop_meshes,
+            props,
+        })
+    }
+
+    /// Upload the meshes, and draw the terrain then every prop.
+    pub fn draw_items(&self, context: &WgpuContext) -> Vec<DrawItem> {
+        let prop_meshes = self
+            .prop_meshes
+            .iter()
+            .map(|vertices| Arc::new(Mesh::new(context, vertices)))
+            .collect::<Vec<_>>();
+        std::iter::once(DrawItem::new(Arc::new(Mesh::new(context, &self.terrain))))
+            .chain(self.props.iter().map(|prop| {
+                DrawItem::new(Arc::clone(&prop_meshes[prop.mesh])).with_transform(prop.transform)
+            }))
+            .collect()
+    }
+}
+
+pub fn prop_image_name(index: u32) -> String {
+    format!("prop_{index}")
+}
+
+/// A small and fast pseudo random generator, so that scenes are reproducible across platforms.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// In `[0, 1)`.
+    fn next_f32(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// Value noise in `[0, 1]` with `octaves` octaves, the first one with a lattice cell per unit.
+fn value_noise(seed: u64, x: f32, y: f32, octaves: u32) -> f32 {
+    let lattice = |x: i64, y: i64, octave: u32| {
+        let hash = (x as u64)
+            .wrapping_mul(0x8da6_b343)
+            .wrapping_add((y as u64).wrapping_mul(0xd816_3841))
+            .wrapping_add(seed ^ octave as u64);
+        SplitMix64(hash).next_f32()
+    };
+    let (mut sum, mut amplitude, mut total) = (0.0, 1.0, 0.0);
+    (0..octaves).for_each(|octave| {
+        let scale = (1 << octave) as f32;
+        let (x, y) = (x * scale, y * scale);
+        let (cell_x, cell_y) = (x.floor(), y.floor());
+        // Smoothstep between the corners of the cell.
+        let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+        let (tx, ty) = (smooth(x - cell_x), smooth(y - cell_y));
+        let (cell_x, cell_y) = (cell_x as i64, cell_y as i64);
+        let top =
+            lattice(cell_x, cell_y, octave) * (1.0 - tx) + lattice(cell_x + 1, cell_y, octave) * tx;
+        let bottom = lattice(cell_x, cell_y + 1, octave) * (1.0 - tx)
+            + lattice(cell_x + 1, cell_y + 1, octave) * tx;
+        sum += amplitude * (top * (1.0 - ty) + bottom * ty);
+        total += amplitude;
+        amplitude /= 2.0;
+    });
+    sum / total
+}
+
+/// The height of the terrain at `(u, v)` in `[0, 1]`, in world units.
+fn terrain_height(params: &StressSceneParams, u: f32, v: f32) -> f32 {
+    // A hill every 32 quads.
+    let hills = params.terrain_quads as f32 / 32.0;
+    value_noise(params.seed, u * hills, v * hills, 4) * params.terrain_height
+}
+
+/// Grass, rock and snow by height, with noise at the scale of the texels, so that every mip
+/// level has detail and no two pages are the same.
+fn terrain_image(params: &StressSceneParams) -> image::RgbaImage {
+    let size = params.terrain_size;
+    image::RgbaImage::from_fn(size, size, |x, y| {
+        let (u, v) = (x as f32 / size as f32, y as f32 / size as f32);
+        let height = terrain_height(params, u, v) / params.terrain_height.max(f32::EPSILON);
+        let detail = value_noise(params.seed ^ 1, x as f32 / 4.0, y as f32 / 4.0, 3);
+        let base = match height {
+            height if height < 0.45 => [70.0, 120.0, 50.0],
+            height if height < 0.7 => [120.0, 110.0, 100.0],
+            _ => [235.0, 235.0, 240.0],
+        };
+        let shade = 0.75 + 0.5 * detail;
+        let [r, g, b] = base.map(|channel: f32| (channel * shade).min(255.0) as u8);
+        image::Rgba([r, g, b, 255])
+    })
+}
+
+/// Stripes of a color and orientation of its own.
+fn prop_image(params: &StressSceneParams, index: u32) -> image::RgbaImage {
+    let mut random = SplitMix64(params.seed ^ ((index as u64 + 1) << 32));
+    let color = [0; 3].map(|_: u8| 64 + (random.next() % 192) as u8);
+    let period = 4 + random.next() % 28;
+    let diagonal = random.next().is_multiple_of(2);
+    let size = params.prop_image_size;
+    image::RgbaImage::from_fn(size, size, |x, y| {
+        let position = if diagonal { x + y } else { x } as u64;
+        let [r, g, b] = if (position / period).is_multiple_of(2) {
+            color
+        } else {
+            color.map(|channel| channel / 3)
+        };
+        image::Rgba([r, g, b, 255])
+    })
+}
+
+fn terrain_mesh(params: &StressSceneParams, uv_rect: UvRect) -> Vec<Vertex> {
+    let quads = params.terrain_quads;
+    let half_side = quads as f32 / 2.0;
+    let vertex = |x: u32, z: u32| {
+        let (u, v) = (x as f32 / quads as f32, z as f32 / quads as f32);
+        let height = |u: f32, v: f32| terrain_height(params, u, v);
+        // Central differences over a quad.
+        let step = 1.0 / quads as f32;
+        let normal = Vector3::new(
+            height(u - step, v) - height(u + step, v),
+            2.0,
+            height(u, v - step) - height(u, v + step),
+        )
+        .normalize();
+        let (u_atlas, v_atlas) = uv_rect.map((u, v));
+        Vertex::new(
+            [x as f32 - half_side, height(u, v), z as f32 - half_side],
+            normal.into(),
+            [u_atlas, v_atlas],
+        )
+    };
+    (0..quads)
+        .flat_map(|z| (0..quads).map(move |x| (x, z)))
+        .flat_map(|(x, z)| {
+            let corners = [
+                vertex(x, z),
+                vertex(x, z + 1),
+                vertex(x + 1, z),
+                vertex(x + 1, z + 1),
+            ];
+            [0, 1, 2, 2, 1, 3].map(|corner| corners[corner])
+        })
+        .collect()
+}
+
+/// A cube of side 1 centered on the origin.
+fn cube_mesh(uv_rect: UvRect) -> Vec<Vertex> {
+    let axes = [Vector3::x(), Vector3::y(), Vector3::z()];
+    (0..6)
+        .flat_map(|face| {
+            let normal = axes[face / 2] * if face % 2 == 0 { 1.0 } else { -1.0 };
+            let tangent = axes[(face / 2 + 1) % 3];
+            let bitangent = normal.cross(&tangent);
+            let corners = [(0.0, 0.0), (0.0, 1.0), (1.0, 0.0), (1.0, 1.0)].map(|(u, v)| {
+                let position = normal / 2.0 + tangent * (u - 0.5) + bitangent * (v - 0.5);
+                let (u_atlas, v_atlas) = uv_rect.map((u, v));
+                Vertex::new(position.into(), normal.into(), [u_atlas, v_atlas])
+            });
+            [0, 1, 2, 2, 1, 3].map(|corner| corners[corner])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::fixture::TempDir;
+
+    use super::{value_noise, StressScene, StressSceneParams, TERRAIN_IMAGE};
+    use crate::storage::TextureMetadata;
+
+    fn params() -> StressSceneParams {
+        StressSceneParams {
+            terrain_quads: 4,
+            terrain_size: 48,
+            prop_count: 10,
+            prop_images: 3,
+            prop_image_size: 12,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn generate_stress_scene() {
+        let temp_dir = TempDir::new().unwrap();
+        let generate = |name: &str| {
+            StressScene::generate(
+                &params(),
+                TextureMetadata::from_dimensions((1, 1), 4).with_page_size(32, 2),
+                Some(temp_dir.path().join(name).to_str().unwrap()),
+            )
+            .unwrap()
+        };
+        let scene = generate("a");
+        assert_eq!(scene.terrain.len(), 4 * 4 * 6);
+        assert_eq!(scene.prop_meshes.len(), 3);
+        assert!(scene.prop_meshes.iter().all(|mesh| mesh.len() == 36));
+        assert_eq!(scene.props.len(), 10);
+        assert!(scene.props.iter().all(|prop| prop.mesh < 3));
+        assert_eq!(scene.atlas.uv_rects.len(), 4);
+        assert!(scene.atlas.uv_rects.contains_key(TERRAIN_IMAGE));
+        // The props stand on the terrain, inside of it.
+        assert!(scene.props.iter().all(|prop| {
+            let position = prop.transform.column(3);
+            position.x.abs() <= 2.0 && position.z.abs() <= 2.0 && position.y > 0.0
+        }));
+
+        // The same seed generates the same scene.
+        assert_eq!(generate("b").props, scene.props);
+    }
+
+    #[test]
+    fn noise_is_smooth() {
+        let samples = (0..100)
+            .map(|index| value_noise(0, index as f32 / 100.0, 0.3, 2))
+            .collect::<Vec<_>>();
+        assert!(samples.iter().all(|sample| (0.0..=1.0).contains(sample)));
+        assert!(samples
+            .windows(2)
+            .all(|pair| (pair[0] - pair[1]).abs() < 0.1));
+    }
+}
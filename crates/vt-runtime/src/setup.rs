@@ -0,0 +1,1943 @@
+use std::{
+    collections::VecDeque,
+    f32,
+    ops::RangeInclusive,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use wgpu::util::DeviceExt;
+
+#[cfg(feature = "image")]
+use crate::debug::DebugExportError;
+use crate::{
+    camera::CameraModule,
+    config::VirtualTexturingConfig,
+    debug::{DebugOverlay, DebugOverlayOptions},
+    draw::DrawItem,
+    pipelines::{
+        ColorTransform, DepthMode, FeedbackMode, FragmentShader, PipelineOptions, Pipelines,
+        ResourceRegistry, SamplingQuality, Tonemap,
+    },
+    power::PowerMode,
+    profiler::{PassTimings, ProfiledPass, Profiler},
+    storage::TextureMetadata,
+    streaming::{StreamingConfig, StreamingHandle},
+    textures::{MetadataMismatch, TextureHandle, Textures},
+};
+#[cfg(feature = "image")]
+use std::path::Path;
+
+pub struct WgpuContext {
+    /// `None` when the device is owned by another renderer, see [`WgpuContext::from_raw`].
+    pub surface: Option<wgpu::Surface>,
+    /// The format of the render target.
+    pub surface_format: wgpu::TextureFormat,
+    pub window: Option<winit::window::Window>,
+    /// The render target of headless contexts, see [`WgpuContext::headless`].
+    pub offscreen_target: Option<wgpu::Texture>,
+    /// The size of the render target when the context was created, see
+    /// [`VirtualTexturingContext::target_size`] for the current one.
+    pub window_size: winit::dpi::PhysicalSize<u32>,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    /// The downlevel capabilities of the adapter of the device, empty for the devices of other
+    /// renderers unless set by them (see [`WgpuContext::from_raw`]).
+    pub downlevel_flags: wgpu::DownlevelFlags,
+    /// The adapter of the device, to check the formats supported. `None` for the devices of other
+    /// renderers unless set by them (see [`WgpuContext::from_raw`]).
+    pub adapter: Option<wgpu::Adapter>,
+    /// The bind group layouts and samplers of the device, shared with the pipelines of the user.
+    pub resources: ResourceRegistry,
+}
+
+impl WgpuContext {
+    pub async fn new(window: winit::window::Window) -> Self {
+        let window_size = window.inner_size();
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+        let surface = unsafe { instance.create_surface(&window) }.unwrap();
+        let context = Self::with_surface(&instance, surface, window_size)
+            .await
+            .unwrap();
+        Self {
+            window: Some(window),
+            ..context
+        }
+    }
+
+    /// A context rendering to a window the caller owns, such as the window of an engine written
+    /// in another language (see `vt_context_create_with_window` in the `ffi` module of
+    /// `virt-texture`), whose surface is `size` texels large until
+    /// [`VirtualTexturingContext::resize`] is called.
+    ///
+    /// Returns `None` if the surface could not be created, or if no adapter can present to it.
+    ///
+    /// ### Safety
+    ///
+    /// The window must stay valid until the context is dropped.
+    pub async unsafe fn from_window_handle(
+        window: &(impl raw_window_handle::HasRawWindowHandle + raw_window_handle::HasRawDisplayHandle),
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+        let surface = instance.create_surface(window).ok()?;
+        Self::with_surface(&instance, surface, size).await
+    }
+
+    /// A context presenting to `surface`, with a device of an adapter compatible with it.
+    async fn with_surface(
+        instance: &wgpu::Instance,
+        surface: wgpu::Surface,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Option<Self> {
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter: false,
+                compatible_surface: Some(&surface),
+            })
+            .await?;
+        println!("Adapter features: {:?}", adapter.features());
+
+        let surface_format = surface
+            .get_capabilities(&adapter)
+            .formats
+            .iter()
+            .copied()
+            .find(|f| f.is_srgb())?;
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: adapter.features()
+                        & (wgpu::Features::TEXTURE_COMPRESSION_BC
+                            | wgpu::Features::TIMESTAMP_QUERY),
+                    limits: wgpu::Limits::default(),
+                },
+                None,
+            )
+            .await
+            .ok()?;
+
+        let context = Self {
+            surface: Some(surface),
+            surface_format,
+            window: None,
+            offscreen_target: None,
+            window_size: size,
+            device,
+            queue,
+            downlevel_flags: adapter.get_downlevel_capabilities().flags,
+            adapter: Some(adapter),
+            resources: Default::default(),
+        };
+        context.configure_surface(size);
+        Some(context)
+    }
+
+    /// Configure the surface for a window of `size`, if the context has one.
+    pub fn configure_surface(&self, size: winit::dpi::PhysicalSize<u32>) {
+        let Some(surface) = &self.surface else {
+            return;
+        };
+        surface.configure(
+            &self.device,
+            &wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                format: self.surface_format,
+                width: size.width,
+                height: size.height,
+                present_mode: wgpu::PresentMode::Fifo,
+                alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+                view_formats: vec![],
+            },
+        );
+    }
+
+    /// Use the device of an existing renderer, which renders to targets of `target_format` and
+    /// `target_size` itself.
+    ///
+    /// Without a surface, the virtual texture is rendered with
+    /// [`VirtualTexturingContext::render_to_view`]. The device should enable
+    /// [`wgpu::Features::TEXTURE_COMPRESSION_BC`] when supported, so that block compressed pages
+    /// are not decoded on the CPU, and [`wgpu::Features::TIMESTAMP_QUERY`] to profile the passes
+    /// (see [`VirtualTexturingContext::enable_profiler`]). Set [`WgpuContext::downlevel_flags`] from the adapter to allow
+    /// the physical textures to be viewed in other formats than their own, and
+    /// [`WgpuContext::adapter`] to check the sample counts of the configuration.
+    pub fn from_raw(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        target_format: wgpu::TextureFormat,
+        target_size: winit::dpi::PhysicalSize<u32>,
+    ) -> Self {
+        Self {
+            surface: None,
+            surface_format: target_format,
+            window: None,
+            offscreen_target: None,
+            window_size: target_size,
+            device,
+            queue,
+            downlevel_flags: wgpu::DownlevelFlags::empty(),
+            adapter: None,
+            resources: Default::default(),
+        }
+    }
+
+    /// A context without window nor surface, rendering to an offscreen texture of `width` by
+    /// `height` texels (see [`WgpuContext::offscreen_target`]), for tests, benchmarks and server
+    /// side rendering.
+    ///
+    /// Returns `None` if no adapter is available.
+    pub async fn headless(width: u32, height: u32) -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter: false,
+                compatible_surface: None,
+            })
+            .await?;
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: adapter.features()
+                        & (wgpu::Features::TEXTURE_COMPRESSION_BC
+                            | wgpu::Features::TIMESTAMP_QUERY),
+                    limits: wgpu::Limits::default(),
+                },
+                None,
+            )
+            .await
+            .ok()?;
+
+        let target_format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let offscreen_target = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: target_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        Some(Self {
+            offscreen_target: Some(offscreen_target),
+            downlevel_flags: adapter.get_downlevel_capabilities().flags,
+            adapter: Some(adapter),
+            ..Self::from_raw(
+                device,
+                queue,
+                target_format,
+                winit::dpi::PhysicalSize::new(width, height),
+            )
+        })
+    }
+
+    /// Whether textures of `format` can be multisampled with `samples` samples by the adapter,
+    /// assumed without one (see [`WgpuContext::adapter`]).
+    pub fn supports_sample_count(&self, format: wgpu::TextureFormat, samples: u32) -> bool {
+        self.adapter.as_ref().is_none_or(|adapter| {
+            adapter
+                .get_texture_format_features(format)
+                .flags
+                .sample_count_supported(samples)
+        })
+    }
+
+    /// Copy the offscreen target to the CPU, blocking until the copy is done.
+    ///
+    /// ### Panics
+    ///
+    /// - If the context is not headless, see [`WgpuContext::headless`].
+    #[cfg(feature = "image")]
+    pub fn read_offscreen_target(&self) -> Result<image::RgbaImage, wgpu::BufferAsyncError> {
+        let target = self
+            .offscreen_target
+            .as_ref()
+            .expect("the context to be headless");
+        let texels = crate::debug::read_texture(self, target, 0, 0)?;
+        Ok(
+            image::RgbaImage::from_raw(target.width(), target.height(), texels)
+                .expect("the readback to have the size of the target"),
+        )
+    }
+
+    /// The next texture of the surface.
+    ///
+    /// ### Panics
+    ///
+    /// - If the context has no surface (see [`WgpuContext::from_raw`]).
+    pub fn current_surface_texture(&self) -> wgpu::SurfaceTexture {
+        self.surface
+            .as_ref()
+            .expect("the context to have a surface, use `render_to_view` otherwise")
+            .get_current_texture()
+            .unwrap()
+    }
+}
+
+/// The commands of a frame, between [`VirtualTexturingContext::begin_frame`] and
+/// [`VirtualTexturingContext::end_frame`].
+pub struct Frame {
+    /// Records the commands of the frame. With [`FeedbackMode::Interleaved`], the pass producing
+    /// the feedback must be recorded here.
+    pub command_encoder: wgpu::CommandEncoder,
+}
+
+pub struct VirtualTexturingContext {
+    pub wgpu_context: Arc<WgpuContext>,
+    /// Shared with the consumers through [`VirtualTexturingContext::textures`].
+    pub(crate) textures: Arc<Textures>,
+    pub pipelines: Pipelines,
+    config: VirtualTexturingConfig,
+    /// The frames started since the creation, to produce the feedback once every
+    /// [`StreamingPolicy::feedback_interval`](crate::power::StreamingPolicy::feedback_interval).
+    frame_index: u64,
+    /// The feedback frames since the feedback texture was created, to clear it every
+    /// [`VirtualTexturingConfig::prepass_clear_interval`].
+    feedback_frames: u64,
+    /// The submissions of the last [`VirtualTexturingConfig::max_frames_in_flight`] frames, oldest
+    /// first, to wait for.
+    frame_submissions: VecDeque<wgpu::SubmissionIndex>,
+    /// The frames whose work completed on the GPU, counted by the queue once the work submitted
+    /// with them is done.
+    completed_frames: Arc<AtomicU64>,
+    submitted_frames: u64,
+    /// See [`VirtualTexturingContext::enable_profiler`].
+    profiler: Option<Profiler>,
+    /// See [`VirtualTexturingContext::set_debug_overlay`].
+    debug_overlay: Option<DebugOverlay>,
+}
+
+impl VirtualTexturingContext {
+    /// The biases accepted by [`VirtualTexturingContext::set_quality_bias`], in mip levels.
+    pub const QUALITY_BIAS_RANGE: RangeInclusive<f32> = -4.0..=4.0;
+
+    /// Creates the textures and pipelines described by the configuration.
+    pub fn from_config(wgpu_context: Arc<WgpuContext>, config: VirtualTexturingConfig) -> Self {
+        Self::from_config_with_options(wgpu_context, config, Default::default())
+    }
+
+    /// Creates the textures and pipelines described by the configuration, rasterizing and depth
+    /// testing the draw items with `options`.
+    pub fn from_config_with_options(
+        wgpu_context: Arc<WgpuContext>,
+        config: VirtualTexturingConfig,
+        options: PipelineOptions,
+    ) -> Self {
+        assert!(config.max_frames_in_flight > 0);
+        let textures = Arc::new(Textures::new(&wgpu_context, &config));
+        let pipelines = Pipelines::new(&wgpu_context, &textures, &[], options);
+        let mut context = Self {
+            wgpu_context,
+            textures,
+            pipelines,
+            config,
+            frame_index: 0,
+            feedback_frames: 0,
+            frame_submissions: VecDeque::new(),
+            completed_frames: Arc::default(),
+            submitted_frames: 0,
+            profiler: None,
+            debug_overlay: None,
+        };
+
+        context.set_quality_bias(context.config.lod_bias);
+        let mut command_encoder =
+            context
+                .wgpu_context
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("color transform"),
+                });
+        context.set_color_transform(
+            context.config.exposure,
+            context.config.tonemap,
+            &mut command_encoder,
+        );
+        context
+            .wgpu_context
+            .queue
+            .submit(Some(command_encoder.finish()));
+
+        context
+    }
+
+    /// Creates the textures and pipelines described by the configuration, for the virtual
+    /// textures of `metadata` in the order of their ids, see [`Textures::validate_metadata`].
+    pub fn from_metadata(
+        wgpu_context: Arc<WgpuContext>,
+        config: VirtualTexturingConfig,
+        metadata: &[&TextureMetadata],
+    ) -> Result<Self, MetadataMismatch> {
+        Textures::validate_metadata(&config, metadata)?;
+        Ok(Self::from_config(wgpu_context, config))
+    }
+
+    /// A handle to the textures of the context, to stream pages to them (see
+    /// [`StreamingHandle::new`](crate::streaming::StreamingHandle::new)) and bind them.
+    pub fn textures(&self) -> TextureHandle {
+        TextureHandle(Arc::clone(&self.textures))
+    }
+
+    /// The configuration currently in use, including the changes made at runtime.
+    pub fn config(&self) -> &VirtualTexturingConfig {
+        &self.config
+    }
+
+    /// The size of the render target, which is the size of the window unless resized with
+    /// [`VirtualTexturingContext::resize`].
+    pub fn target_size(&self) -> winit::dpi::PhysicalSize<u32> {
+        self.pipelines.target_size
+    }
+
+    /// Resize the render target to `new_size`, to be called on
+    /// [`WindowEvent::Resized`](winit::event::WindowEvent::Resized).
+    ///
+    /// The surface is reconfigured, the feedback and depth textures are recreated, and the level of
+    /// detail bias is updated for the new ratio of the feedback texture (see
+    /// [`VirtualTexturingContext::effective_lod_bias`]). The feedback read buffers
+    /// only hold the reduced requests, so they keep their size. Empty sizes (e.g., of a minimized
+    /// window) are ignored.
+    ///
+    /// ### Panics
+    ///
+    /// - If the context is headless, since the offscreen target can not be resized.
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        assert!(
+            self.wgpu_context.offscreen_target.is_none(),
+            "the offscreen target of headless contexts can not be resized"
+        );
+        if new_size.width == 0 || new_size.height == 0 || new_size == self.target_size() {
+            return;
+        }
+
+        self.wgpu_context.configure_surface(new_size);
+        self.pipelines
+            .resize(&self.wgpu_context, &self.textures, new_size);
+        self.feedback_frames = 0;
+        self.write_lod_bias();
+    }
+
+    /// Set the ratio between the sides of the prepass target and of the render target, see
+    /// [`VirtualTexturingConfig::prepass_ratio`]. Has no effect with [`FeedbackMode::Interleaved`]
+    /// or [`DepthMode::ReusePrepass`].
+    ///
+    /// ### Panics
+    ///
+    /// - If `prepass_ratio` is not in `(0, 1]`.
+    pub fn set_prepass_ratio(&mut self, prepass_ratio: f32) {
+        assert!(prepass_ratio > 0.0 && prepass_ratio <= 1.0);
+        self.config.prepass_ratio = prepass_ratio;
+        self.pipelines
+            .set_prepass_ratio(&self.wgpu_context, &self.textures, prepass_ratio);
+        self.feedback_frames = 0;
+        self.write_lod_bias();
+    }
+
+    /// Bias the mip levels requested by the feedback of the following frames by `bias` levels,
+    /// clamped to [`VirtualTexturingContext::QUALITY_BIAS_RANGE`]. Negative biases request finer
+    /// pages, sharper but streaming more of them, and positive biases coarser ones.
+    ///
+    /// The bias is relative to the mip levels sampled by the render pass: the feedback texture
+    /// being smaller than the render target, its own ratio is added to it on the GPU (see
+    /// [`VirtualTexturingContext::effective_lod_bias`]), and updated along with the prepass ratio
+    /// and the size of the target.
+    pub fn set_quality_bias(&mut self, bias: f32) {
+        self.config.lod_bias = bias.clamp(
+            *Self::QUALITY_BIAS_RANGE.start(),
+            *Self::QUALITY_BIAS_RANGE.end(),
+        );
+        self.write_lod_bias();
+    }
+
+    /// The bias of [`VirtualTexturingContext::set_quality_bias`], clamped.
+    pub fn quality_bias(&self) -> f32 {
+        self.config.lod_bias
+    }
+
+    /// The level of detail bias of the feedback on the GPU: the quality bias, and the log2 of the
+    /// ratio between the widths of the target producing the feedback and of the render target.
+    pub fn effective_lod_bias(&self) -> f32 {
+        let feedback_width = self
+            .pipelines
+            .prepass_depth_target(self.textures.depth_mode)
+            .width();
+        let feedback_ratio = feedback_width as f32 / self.target_size().width as f32;
+        self.config.lod_bias + feedback_ratio.log2()
+    }
+
+    /// Write [`VirtualTexturingContext::effective_lod_bias`] to the feedback uniforms with the
+    /// queue.
+    fn write_lod_bias(&self) {
+        self.wgpu_context.queue.write_buffer(
+            &self.pipelines.feedback_uniforms_buffer,
+            0,
+            bytemuck::bytes_of(&self.effective_lod_bias()),
+        );
+    }
+
+    /// Set the exposure and tone mapping curve applied to the color of the following render passes.
+    pub fn set_color_transform(
+        &mut self,
+        exposure: f32,
+        tonemap: Tonemap,
+        command_encoder: &mut wgpu::CommandEncoder,
+    ) {
+        self.config.exposure = exposure;
+        self.config.tonemap = tonemap;
+        let color_transform_stg =
+            self.wgpu_context
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("color transform stg"),
+                    contents: bytemuck::bytes_of(&ColorTransform::new(exposure, tonemap)),
+                    usage: wgpu::BufferUsages::COPY_SRC,
+                });
+        command_encoder.copy_buffer_to_buffer(
+            &color_transform_stg,
+            0,
+            &self.pipelines.color_transform_buffer,
+            0,
+            std::mem::size_of::<ColorTransform>() as wgpu::BufferAddress,
+        );
+    }
+
+    /// Draw the following passes from the view of `camera`, its depth reversed with
+    /// [`PipelineOptions::reverse_z`].
+    ///
+    /// The camera is written with the queue, so it applies to the next submitted frame.
+    pub fn update_camera(&self, camera: &CameraModule) {
+        self.wgpu_context.queue.write_buffer(
+            &self.pipelines.camera_buffer,
+            0,
+            bytemuck::bytes_of(
+                &self
+                    .pipelines
+                    .options
+                    .camera_uniforms(camera.view_proj_matrix()),
+            ),
+        );
+    }
+
+    /// Filter the physical texture with `quality` in the following render passes.
+    pub fn set_sampling_quality(&mut self, quality: SamplingQuality) {
+        self.config.sampling_quality = quality;
+    }
+
+    /// Shade the following render passes with `fragment_shader`, recreating the render pipelines.
+    /// [`FragmentShader::default`] restores the default shading.
+    ///
+    /// ### Errors
+    ///
+    /// - If the source of the shader does not compile, or does not match its bind groups, in
+    ///   which case the previous shader is kept.
+    pub fn set_fragment_shader(
+        &mut self,
+        fragment_shader: FragmentShader,
+    ) -> Result<(), wgpu::Error> {
+        self.pipelines
+            .set_fragment_shader(&self.wgpu_context, &self.textures, fragment_shader)
+    }
+
+    /// Produce and read back the feedback with the policy of `mode`, see
+    /// [`VirtualTexturingConfig::power_mode`]. The streaming handle must be switched as well, with
+    /// [`StreamingHandle::set_power_mode`].
+    pub fn set_power_mode(&mut self, mode: PowerMode) {
+        self.config.power_mode = mode;
+    }
+
+    /// Cap the streaming work of every frame with `config`, see
+    /// [`VirtualTexturingConfig::streaming`], applied to the streaming handle by
+    /// [`VirtualTexturingContext::end_frame`].
+    pub fn set_streaming_config(&mut self, config: StreamingConfig) {
+        self.config.streaming = config;
+    }
+
+    /// Let `count` frames run on the GPU at once, see
+    /// [`VirtualTexturingConfig::max_frames_in_flight`].
+    ///
+    /// ### Panics
+    ///
+    /// - If `count` is 0.
+    pub fn set_max_frames_in_flight(&mut self, count: u32) {
+        assert!(count > 0);
+        self.config.max_frames_in_flight = count;
+        while self.frame_submissions.len() > count as usize {
+            self.frame_submissions.pop_front();
+        }
+    }
+
+    /// The frames submitted by [`VirtualTexturingContext::end_frame`] whose work has not
+    /// completed on the GPU yet, as of the last poll of the device.
+    pub fn frames_in_flight(&self) -> u64 {
+        self.submitted_frames - self.completed_frames.load(Ordering::Acquire)
+    }
+
+    /// Wait until fewer than [`VirtualTexturingConfig::max_frames_in_flight`] frames are in
+    /// flight, for the oldest one of them if needed.
+    fn wait_for_frames_in_flight(&mut self) {
+        self.wgpu_context.device.poll(wgpu::Maintain::Poll);
+        if self.frames_in_flight() < self.config.max_frames_in_flight as u64 {
+            return;
+        }
+        // The submissions kept are the last `max_frames_in_flight` ones, all in flight.
+        let oldest = self
+            .frame_submissions
+            .pop_front()
+            .expect("the frames in flight to be kept");
+        self.wgpu_context
+            .device
+            .poll(wgpu::Maintain::WaitForSubmissionIndex(oldest));
+    }
+
+    /// Time the prepass, the feedback reduction and copy, and the render pass of the following
+    /// frames on the GPU, see [`VirtualTexturingContext::pass_timings`]. Returns `false` if the
+    /// device does not enable [`wgpu::Features::TIMESTAMP_QUERY`], which the adapters of
+    /// [`WgpuContext::new`] and [`WgpuContext::headless`] enable when they support it.
+    pub fn enable_profiler(&mut self) -> bool {
+        if self.profiler.is_none() {
+            self.profiler = Profiler::new(&self.wgpu_context.device, &self.wgpu_context.queue);
+        }
+        self.profiler.is_some()
+    }
+
+    /// Stop timing the passes of the following frames.
+    pub fn disable_profiler(&mut self) {
+        self.profiler = None;
+    }
+
+    /// Draw a [`DebugOverlay`] of the page table and of the occupancy of the physical textures
+    /// over the frames rendered by [`VirtualTexturingContext::end_frame`], or stop drawing it with
+    /// `None`. The occupancy is only updated by the frames given a streaming handle.
+    pub fn set_debug_overlay(&mut self, options: Option<DebugOverlayOptions>) {
+        match (&mut self.debug_overlay, options) {
+            (Some(overlay), Some(options)) => overlay.options = options,
+            (None, Some(options)) => {
+                self.debug_overlay = Some(DebugOverlay::new(
+                    &self.wgpu_context,
+                    &self.textures(),
+                    options,
+                ))
+            }
+            (_, None) => self.debug_overlay = None,
+        }
+    }
+
+    /// The options of the overlay drawn, see [`VirtualTexturingContext::set_debug_overlay`].
+    pub fn debug_overlay(&self) -> Option<DebugOverlayOptions> {
+        self.debug_overlay.as_ref().map(|overlay| overlay.options)
+    }
+
+    /// The profiler enabled by [`VirtualTexturingContext::enable_profiler`], to time the pass
+    /// producing the feedback with [`FeedbackMode::Interleaved`] as [`ProfiledPass::Prepass`].
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// The GPU time of the passes of the last frame read back, see [`Profiler::timings`].
+    pub fn pass_timings(&self) -> Option<PassTimings> {
+        self.profiler.as_ref()?.timings()
+    }
+
+    /// Whether the current frame produces and reads back the feedback, once every
+    /// [`StreamingPolicy::feedback_interval`](crate::power::StreamingPolicy::feedback_interval)
+    /// frames. With [`FeedbackMode::Interleaved`], the user's pass may skip writing the feedback
+    /// on the other frames.
+    pub fn is_feedback_frame(&self) -> bool {
+        let interval = self.config.power_mode.streaming_policy().feedback_interval;
+        self.frame_index.is_multiple_of(interval as u64)
+    }
+
+    /// How the pass producing the feedback of the current frame loads the feedback texture:
+    /// cleared to [`Pipelines::FEEDBACK_CLEAR_COLOR`] once every
+    /// [`VirtualTexturingConfig::prepass_clear_interval`] feedback frames, and on the first one
+    /// after the texture is (re)created, or loaded to accumulate the requests otherwise.
+    pub fn feedback_load_op(&self) -> wgpu::LoadOp<wgpu::Color> {
+        let interval = self.config.prepass_clear_interval as u64;
+        let clear = match interval {
+            0 => self.feedback_frames == 0,
+            _ => self.feedback_frames.is_multiple_of(interval),
+        };
+        if clear {
+            wgpu::LoadOp::Clear(Pipelines::FEEDBACK_CLEAR_COLOR)
+        } else {
+            wgpu::LoadOp::Load
+        }
+    }
+
+    /// Start a frame drawing `items`, recording the prepass with [`FeedbackMode::Separate`] and
+    /// [`FeedbackMode::StorageBuffer`] on feedback frames (see [`VirtualTexturingContext::is_feedback_frame`]), and on every frame
+    /// with [`DepthMode::ReusePrepass`].
+    ///
+    /// Blocks until fewer than [`VirtualTexturingConfig::max_frames_in_flight`] frames are in
+    /// flight.
+    pub fn begin_frame(&mut self, items: &[DrawItem]) -> Frame {
+        self.wait_for_frames_in_flight();
+        let mut command_encoder =
+            self.wgpu_context
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("frame"),
+                });
+        match (self.textures.feedback_mode, self.textures.depth_mode) {
+            (FeedbackMode::Separate, DepthMode::ReusePrepass) => {
+                self.prepass(&mut command_encoder, items)
+            }
+            (FeedbackMode::Separate | FeedbackMode::StorageBuffer, _)
+                if self.is_feedback_frame() =>
+            {
+                self.prepass(&mut command_encoder, items)
+            }
+            _ => self.upload_draw_items(items),
+        }
+        Frame { command_encoder }
+    }
+
+    /// Record the rest of the frame after the feedback is produced, submit it and return the
+    /// surface texture to present, or `None` for headless contexts which render to
+    /// [`WgpuContext::offscreen_target`].
+    ///
+    /// In order: the feedback is reduced (except with [`FeedbackMode::StorageBuffer`]), read back by `streaming` (see
+    /// [`StreamingHandle::submit_feedback`], with
+    /// [`VirtualTexturingConfig::max_feedback_in_flight`] and
+    /// [`VirtualTexturingConfig::streaming`]), the virtual texture is rendered, and
+    /// the debug overlay is drawn over it (see
+    /// [`VirtualTexturingContext::set_debug_overlay`]). The feedback is
+    /// only reduced and read back on feedback frames. The pages staged by `streaming` (see
+    /// [`StreamingHandle::flush_uploads`]) and the page table entries written through the queue
+    /// are flushed before the submission, so the render pass samples them.
+    ///
+    /// ### Panics
+    ///
+    /// - If the context has neither a surface nor an offscreen target, see
+    ///   [`WgpuContext::from_raw`].
+    pub fn end_frame(
+        &mut self,
+        mut frame: Frame,
+        mut streaming: Option<&mut StreamingHandle>,
+    ) -> Option<wgpu::SurfaceTexture> {
+        if self.is_feedback_frame() {
+            if self.textures.feedback_mode != FeedbackMode::StorageBuffer {
+                self.reduce_feedback(&mut frame.command_encoder);
+            }
+            if let Some(streaming) = streaming.as_deref_mut() {
+                streaming.set_max_feedback_in_flight(self.config.max_feedback_in_flight as usize);
+                streaming.set_streaming_config(self.config.streaming);
+                match &self.profiler {
+                    Some(profiler) => profiler.time_commands(
+                        ProfiledPass::FeedbackCopy,
+                        &mut frame.command_encoder,
+                        |command_encoder| streaming.submit_feedback(command_encoder),
+                    ),
+                    None => streaming.submit_feedback(&mut frame.command_encoder),
+                }
+            }
+            self.feedback_frames += 1;
+        }
+        self.frame_index += 1;
+        let output = match &self.wgpu_context.offscreen_target {
+            Some(target) => {
+                let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+                self.render_to_view(&mut frame.command_encoder, &view);
+                None
+            }
+            None => Some(self.render(&mut frame.command_encoder)),
+        };
+        if let Some(hi_z) = &mut self.pipelines.hi_z {
+            hi_z.depth_rendered = true;
+        }
+        if let Some(overlay) = &self.debug_overlay {
+            if let Some(streaming) = streaming.as_deref() {
+                overlay.update_occupancy(&self.wgpu_context, &self.textures(), streaming);
+            }
+            let view = match (&self.wgpu_context.offscreen_target, &output) {
+                (Some(target), _) => target.create_view(&Default::default()),
+                (None, Some(output)) => output.texture.create_view(&Default::default()),
+                (None, None) => unreachable!("the frame to be rendered to a target"),
+            };
+            overlay.render(
+                &self.wgpu_context,
+                &mut frame.command_encoder,
+                &view,
+                self.target_size(),
+                &self.textures(),
+            );
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.resolve(&mut frame.command_encoder);
+        }
+        if let Some(streaming) = streaming.as_deref() {
+            streaming.flush_uploads();
+        }
+        let submission = self
+            .wgpu_context
+            .queue
+            .submit(Some(frame.command_encoder.finish()));
+        if let Some(profiler) = &mut self.profiler {
+            profiler.frame_submitted();
+        }
+        let completed_frames = Arc::clone(&self.completed_frames);
+        self.wgpu_context.queue.on_submitted_work_done(move || {
+            completed_frames.fetch_add(1, Ordering::Release);
+        });
+        self.submitted_frames += 1;
+        self.textures.flip_page_tables(&self.wgpu_context.queue);
+        self.frame_submissions.push_back(submission);
+        if self.frame_submissions.len() > self.config.max_frames_in_flight as usize {
+            self.frame_submissions.pop_front();
+        }
+        output
+    }
+
+    /// Set the meshes drawn by the following passes, see [`Pipelines::upload_draw_items`].
+    ///
+    /// [`VirtualTexturingContext::prepass`] does this already. It must be called directly when
+    /// the feedback is produced by the user's own pass (see
+    /// [`FeedbackMode::Interleaved`]).
+    pub fn upload_draw_items(&mut self, items: &[DrawItem]) {
+        self.pipelines.upload_draw_items(&self.wgpu_context, items);
+    }
+
+    /// Record the prepass over `items`, see [`FeedbackMode`].
+    ///
+    /// With [`FeedbackMode::StorageBuffer`], the requests buffers are cleared first, and the
+    /// requests are ready to be read back after the prepass, without [`VirtualTexturingContext::reduce_feedback`].
+    /// The [`HiZ`](crate::pipelines::HiZ) pyramid is built first with
+    /// [`VirtualTexturingConfig::hi_z`], from the depth of the previous frame.
+    pub fn prepass(&mut self, command_encoder: &mut wgpu::CommandEncoder, items: &[DrawItem]) {
+        self.upload_draw_items(items);
+
+        let prepass_view = self.pipelines.feedback_view();
+        let prepass_depth_view = self
+            .pipelines
+            .prepass_depth_target(self.textures.depth_mode)
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let storage = self
+            .pipelines
+            .prepass_storage_pipeline
+            .as_ref()
+            .zip(self.pipelines.feedback_storage_bind_group.as_ref());
+
+        command_encoder.push_debug_group("prepass");
+        if storage.is_some() {
+            self.clear_feedback_requests(command_encoder);
+        }
+        if let Some(hi_z) = &self.pipelines.hi_z {
+            hi_z.build(&self.wgpu_context, command_encoder);
+        }
+        let color_attachments = [Some(wgpu::RenderPassColorAttachment {
+            view: &prepass_view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: self.feedback_load_op(),
+                store: wgpu::StoreOp::Store,
+            },
+        })];
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("prepass render pass"),
+            color_attachments: match storage {
+                Some(_) => &[],
+                None => &color_attachments,
+            },
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &prepass_depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.pipelines.options.depth_clear_value()),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: self
+                .profiler
+                .as_ref()
+                .map(|profiler| profiler.render_pass_timestamp_writes(ProfiledPass::Prepass)),
+            occlusion_query_set: None,
+        });
+        match storage {
+            Some((pipeline, bind_group)) => {
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(2, bind_group, &[]);
+                if let Some(hi_z) = &self.pipelines.hi_z {
+                    render_pass.set_bind_group(3, &hi_z.bind_group, &[]);
+                }
+            }
+            None => render_pass.set_pipeline(&self.pipelines.prepass_pipeline),
+        }
+        render_pass.set_bind_group(0, &self.pipelines.feedback_bind_group, &[]);
+        self.draw_items(&mut render_pass, 1);
+        drop(render_pass);
+        command_encoder.pop_debug_group();
+    }
+
+    /// Reduce the feedback texture to the distinct pages it requests, in
+    /// [`Textures::feedback_requests_buffer`].
+    ///
+    /// Must be recorded after the pass producing the feedback, before the requests are read back
+    /// with [`StreamingHandle::submit_feedback`](crate::streaming::StreamingHandle::submit_feedback).
+    pub fn reduce_feedback(&self, command_encoder: &mut wgpu::CommandEncoder) {
+        command_encoder.push_debug_group("feedback reduction");
+        self.clear_feedback_requests(command_encoder);
+
+        let workgroup_size = Pipelines::FEEDBACK_REDUCTION_WORKGROUP_SIZE;
+        let mut compute_pass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("feedback reduction pass"),
+            timestamp_writes: self.profiler.as_ref().map(|profiler| {
+                profiler.compute_pass_timestamp_writes(ProfiledPass::FeedbackReduction)
+            }),
+        });
+        compute_pass.set_pipeline(&self.pipelines.feedback_reduction_pipeline);
+        compute_pass.set_bind_group(0, &self.pipelines.feedback_reduction_bind_group, &[]);
+        compute_pass.dispatch_workgroups(
+            self.pipelines
+                .prepass_texture
+                .width()
+                .div_ceil(workgroup_size),
+            self.pipelines
+                .prepass_texture
+                .height()
+                .div_ceil(workgroup_size),
+            1,
+        );
+        drop(compute_pass);
+        command_encoder.pop_debug_group();
+    }
+
+    /// Clear the requested pages and the counters of the requests, before they are recorded.
+    fn clear_feedback_requests(&self, command_encoder: &mut wgpu::CommandEncoder) {
+        command_encoder.clear_buffer(&self.pipelines.requested_pages_buffer, 0, None);
+        command_encoder.clear_buffer(
+            &self.textures.feedback_requests_buffer,
+            0,
+            wgpu::BufferSize::new(Pipelines::FEEDBACK_REQUESTS_HEADER_SIZE),
+        );
+    }
+
+    /// Render the virtual texture to the next texture of the surface.
+    ///
+    /// ### Panics
+    ///
+    /// - If the context has no surface, see [`WgpuContext::from_raw`].
+    pub fn render(&self, command_encoder: &mut wgpu::CommandEncoder) -> wgpu::SurfaceTexture {
+        let output = self.wgpu_context.current_surface_texture();
+        self.render_to_view(
+            command_encoder,
+            &output
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default()),
+        );
+        output
+    }
+
+    /// Render the virtual texture to `view`, which must have the format and size of the context.
+    pub fn render_to_view(
+        &self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+    ) {
+        let depth_view = &self
+            .pipelines
+            .render_depth_texture
+            .create_view(&Default::default());
+        let msaa_view = self
+            .pipelines
+            .msaa_texture
+            .as_ref()
+            .map(|texture| texture.create_view(&Default::default()));
+        let depth_load = match self.textures.depth_mode {
+            DepthMode::Separate => wgpu::LoadOp::Clear(self.pipelines.options.depth_clear_value()),
+            DepthMode::ReusePrepass => wgpu::LoadOp::Load,
+        };
+
+        command_encoder.push_debug_group(&format!(
+            "render ({:?} sampling)",
+            self.config.sampling_quality
+        ));
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("render pass"),
+            color_attachments: &[Some(match &msaa_view {
+                // The samples are only needed until they are resolved.
+                Some(msaa_view) => wgpu::RenderPassColorAttachment {
+                    view: msaa_view,
+                    resolve_target: Some(view),
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                        store: wgpu::StoreOp::Discard,
+                    },
+                },
+                None => wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                        store: wgpu::StoreOp::Store,
+                    },
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: depth_load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: self
+                .profiler
+                .as_ref()
+                .map(|profiler| profiler.render_pass_timestamp_writes(ProfiledPass::Render)),
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_pipeline(self.pipelines.render_pipeline(self.config.sampling_quality));
+        render_pass.set_bind_group(
+            0,
+            self.pipelines.virtual_texture_bind_group(&self.textures),
+            &[],
+        );
+        render_pass.set_bind_group(1, &self.pipelines.color_transform_bind_group, &[]);
+        self.pipelines
+            .fragment_bind_groups
+            .iter()
+            .enumerate()
+            .for_each(|(index, bind_group)| {
+                render_pass.set_bind_group(3 + index as u32, bind_group, &[])
+            });
+        self.draw_items(&mut render_pass, 2);
+        drop(render_pass);
+        command_encoder.pop_debug_group();
+    }
+
+    /// Draw every item of [`Pipelines::draw_items`], binding [`Pipelines::camera_bind_group`] to
+    /// `camera_group` with the uniforms of each item.
+    fn draw_items<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_group: u32) {
+        self.pipelines
+            .draw_items
+            .iter()
+            .enumerate()
+            .for_each(|(index, item)| {
+                render_pass.set_bind_group(
+                    camera_group,
+                    &self.pipelines.camera_bind_group,
+                    &[self.pipelines.draw_uniforms_offset(index)],
+                );
+                render_pass.set_vertex_buffer(0, item.mesh.vertex_buffer.slice(..));
+                render_pass.insert_debug_marker(&format!(
+                    "draw item {index} ({} vertices)",
+                    item.mesh.vertex_count
+                ));
+                render_pass.draw(0..item.mesh.vertex_count, 0..1);
+            });
+    }
+
+    /// Write every mip level of the page table to `directory` as color-coded PNGs.
+    ///
+    /// See [`debug::export_page_table`](crate::debug::export_page_table).
+    #[cfg(feature = "image")]
+    pub fn export_page_table(&self, directory: &Path) -> Result<(), DebugExportError> {
+        crate::debug::export_page_table(&self.wgpu_context, &self.textures(), directory)
+    }
+
+    /// Write everything needed to diagnose a streaming issue to `directory`, the artifact to
+    /// attach to bug reports:
+    /// - `config.json`: the current configuration.
+    /// - `page_table_{mip}.png`: see [`debug::export_page_table`](crate::debug::export_page_table).
+    /// - With `streaming`, the cache occupancy, residency, stats history and request traces, see
+    ///   [`debug::export_streaming`](crate::debug::export_streaming).
+    #[cfg(feature = "image")]
+    pub fn debug_dump(
+        &self,
+        directory: &Path,
+        streaming: Option<&StreamingHandle>,
+    ) -> Result<(), DebugExportError> {
+        self.export_page_table(directory)?;
+        std::fs::write(directory.join("config.json"), self.config.to_json())?;
+        if let Some(streaming) = streaming {
+            crate::debug::export_streaming(&self.textures(), streaming, directory)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(debug_assertions)]
+    pub fn debug_prepass_render(
+        &self,
+        command_encoder: &mut wgpu::CommandEncoder,
+    ) -> wgpu::SurfaceTexture {
+        let output = self.wgpu_context.current_surface_texture();
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.render_debug_prepass(command_encoder, &view);
+        output
+    }
+
+    /// Draw the requests of the prepass target over the whole of `view`, a texture of the
+    /// surface format.
+    #[cfg(any(debug_assertions, feature = "egui"))]
+    pub(crate) fn render_debug_prepass(
+        &self,
+        command_encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+    ) {
+        let texture_bind_group =
+            self.wgpu_context
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("debug prepass texture bind group"),
+                    layout: &self
+                        .pipelines
+                        .debug_prepass_pipeline
+                        .get_bind_group_layout(0),
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(
+                            &self
+                                .pipelines
+                                .prepass_texture
+                                .create_view(&Default::default()),
+                        ),
+                    }],
+                });
+
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("debug prepass render pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_pipeline(&self.pipelines.debug_prepass_pipeline);
+        render_pass.set_bind_group(0, &texture_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use assert_fs::{fixture::TempDir, prelude::*};
+    use wgpu::util::DeviceExt;
+
+    use super::{VirtualTexturingContext, WgpuContext};
+    use crate::{
+        camera::{Camera, CameraModule, CameraProjection},
+        config::VirtualTexturingConfig,
+        debug::{DebugOverlayOptions, RequestHeatmap},
+        draw::{DrawItem, Mesh},
+        pipelines::{DepthMode, FeedbackMode, FragmentShader, PipelineOptions},
+        power::PowerMode,
+        storage::{TextureMetadata, TextureStorage},
+        streaming::{PageId, StreamingHandle},
+        test_support::{render_feedback, StreamingFixture},
+        vertex::FOUR_TRIANGLES,
+    };
+
+    fn four_triangles(context: &VirtualTexturingContext) -> Vec<DrawItem> {
+        vec![DrawItem::new(Arc::new(Mesh::new(
+            &context.wgpu_context,
+            &FOUR_TRIANGLES,
+        )))]
+    }
+
+    #[test]
+    fn headless_frame() {
+        let wgpu_context = crate::headless_or_skip!(320, 240);
+        let mut context =
+            VirtualTexturingContext::from_config(Arc::new(wgpu_context), Default::default());
+        let frame = context.begin_frame(&four_triangles(&context));
+        assert!(context.end_frame(frame, None).is_none());
+
+        // Nothing is resident, so the triangles are transparent over the white background.
+        let image = context.wgpu_context.read_offscreen_target().unwrap();
+        assert_eq!(image.dimensions(), (320, 240));
+        assert!(image.pixels().all(|pixel| pixel.0 == [255; 4]));
+    }
+
+    /// The passes of a frame are timed once its timestamps are read back, a frame later.
+    #[test]
+    fn profile_passes() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let mut context =
+            VirtualTexturingContext::from_config(Arc::new(wgpu_context), Default::default());
+        if !context.enable_profiler() {
+            assert!(context.pass_timings().is_none());
+            crate::test_support::skip_without_features(wgpu::Features::TIMESTAMP_QUERY);
+            return;
+        }
+        let items = four_triangles(&context);
+        (0..2).for_each(|_| {
+            let frame = context.begin_frame(&items);
+            context.end_frame(frame, None);
+            context.wgpu_context.device.poll(wgpu::Maintain::Wait);
+        });
+        let timings = context
+            .pass_timings()
+            .expect("the first frame to be read back");
+        assert!(timings.prepass.is_some() && timings.render.is_some());
+        assert!(timings.feedback_reduction.is_some());
+        // Without streaming, the feedback is not copied.
+        assert_eq!(timings.feedback_copy, None);
+
+        context.disable_profiler();
+        assert!(context.pass_timings().is_none());
+    }
+
+    /// The render pass shades the same fragments when it reuses the depth of the prepass, which
+    /// runs on every frame at the size of the render target.
+    #[test]
+    fn reuse_prepass_depth() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let wgpu_context = Arc::new(wgpu_context);
+        let render = |depth_mode| {
+            let config = VirtualTexturingConfig {
+                depth_mode,
+                ..Default::default()
+            };
+            let mut context =
+                VirtualTexturingContext::from_config(Arc::clone(&wgpu_context), config);
+            context.set_power_mode(PowerMode::LowPower);
+            for _ in 0..2 {
+                let frame = context.begin_frame(&four_triangles(&context));
+                context.end_frame(frame, None);
+            }
+            assert!(!context.is_feedback_frame());
+            (
+                context.pipelines.prepass_texture.width(),
+                wgpu_context.read_offscreen_target().unwrap(),
+            )
+        };
+        let (separate_width, separate) = render(DepthMode::Separate);
+        let (reuse_width, reuse) = render(DepthMode::ReusePrepass);
+        assert_eq!((separate_width, reuse_width), (6, 64));
+        assert_eq!(separate, reuse);
+    }
+
+    /// The render pass resolves its samples to the target, and only the prepass is single
+    /// sampled.
+    #[test]
+    fn multisampled_render() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let config = VirtualTexturingConfig {
+            msaa_samples: 4,
+            ..Default::default()
+        };
+        let mut context = VirtualTexturingContext::from_config(Arc::new(wgpu_context), config);
+        let frame = context.begin_frame(&four_triangles(&context));
+        context.end_frame(frame, None);
+        let image = context.wgpu_context.read_offscreen_target().unwrap();
+        assert!(image.pixels().all(|pixel| pixel.0 == [255; 4]));
+
+        let pipelines = &mut context.pipelines;
+        pipelines.resize(
+            &context.wgpu_context,
+            &context.textures,
+            winit::dpi::PhysicalSize::new(32, 16),
+        );
+        let msaa_texture = pipelines.msaa_texture.as_ref().unwrap();
+        assert_eq!((msaa_texture.width(), msaa_texture.height()), (32, 16));
+        assert_eq!(msaa_texture.sample_count(), 4);
+        assert_eq!(pipelines.render_depth_texture.sample_count(), 4);
+        assert_eq!(pipelines.prepass_texture.sample_count(), 1);
+        assert_eq!(pipelines.prepass_depth_texture.sample_count(), 1);
+    }
+
+    /// A fragment shader of the user shades the triangles with its own bind group, and still
+    /// samples the virtual texture.
+    #[test]
+    fn custom_fragment_shader() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let mut context =
+            VirtualTexturingContext::from_config(Arc::new(wgpu_context), Default::default());
+        let device = &context.wgpu_context.device;
+        let layout = context.wgpu_context.resources.bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("tint bind group layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("tint buffer"),
+            contents: bytemuck::bytes_of(&[1.0f32, 0.0, 0.0, 1.0]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("tint bind group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        context
+            .set_fragment_shader(FragmentShader {
+                source: "@group(3) @binding(0)\nvar<uniform> tint: vec4<f32>;\n\n\
+                @fragment\n\
+                fn fs_tint(in: RenderInterpolators) -> @location(0) vec4<f32> {\n\
+                    let color = sample_virtual(in.tex_coords, in.texture_id);\n\
+                    return apply_color_transform(vec4<f32>(color.rgb + tint.rgb, 1.0));\n\
+                }\n"
+                .to_owned(),
+                entry_point: "fs_tint".to_owned(),
+                bind_groups: vec![(layout, bind_group)],
+            })
+            .unwrap();
+        // An invalid shader is reported, and the previous one kept.
+        assert!(context
+            .set_fragment_shader(FragmentShader {
+                source: "@fragment\nfn fs_broken() -> @location(0) vec4<f32> { return 1; }\n"
+                    .to_owned(),
+                entry_point: "fs_broken".to_owned(),
+                bind_groups: Vec::new(),
+            })
+            .is_err());
+        let frame = context.begin_frame(&four_triangles(&context));
+        context.end_frame(frame, None);
+
+        let image = context.wgpu_context.read_offscreen_target().unwrap();
+        assert!(image.pixels().any(|pixel| pixel.0 == [255, 0, 0, 255]));
+        assert!(image.pixels().any(|pixel| pixel.0 == [255; 4]));
+    }
+
+    /// The triangles are culled with the front face of the options.
+    #[test]
+    fn pipeline_options_cull() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let wgpu_context = Arc::new(wgpu_context);
+        let drawn = |options| {
+            let mut context = VirtualTexturingContext::from_config_with_options(
+                Arc::clone(&wgpu_context),
+                Default::default(),
+                options,
+            );
+            context.set_fragment_shader(FragmentShader {
+                source:
+                    "@fragment\nfn fs_red(in: RenderInterpolators) -> @location(0) vec4<f32> {\n\
+                    return vec4<f32>(1.0, 0.0, 0.0, 1.0);\n}\n"
+                        .to_owned(),
+                entry_point: "fs_red".to_owned(),
+                bind_groups: Vec::new(),
+            })
+            .unwrap();
+            let frame = context.begin_frame(&four_triangles(&context));
+            context.end_frame(frame, None);
+            let image = wgpu_context.read_offscreen_target().unwrap();
+            image.pixels().any(|pixel| pixel.0 == [255, 0, 0, 255])
+        };
+        assert!(drawn(PipelineOptions::default()));
+        let clockwise = PipelineOptions {
+            front_face: wgpu::FrontFace::Cw,
+            ..Default::default()
+        };
+        assert!(!drawn(clockwise));
+        assert!(drawn(PipelineOptions {
+            cull_mode: None,
+            ..clockwise
+        }));
+    }
+
+    /// A reversed depth buffer draws and requests the same as a regular one: the quad in front
+    /// hides the one behind it, drawn after it, in both passes.
+    #[test]
+    fn reverse_z() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let wgpu_context = Arc::new(wgpu_context);
+        let temp_dir = TempDir::new().unwrap();
+        let storage = TextureStorage::new(
+            TextureMetadata::from_mip(1, 4),
+            Some(temp_dir.path().to_str().unwrap()),
+            None,
+        )
+        .unwrap();
+        let camera = CameraModule::from_parts(
+            Camera::new(
+                nalgebra::Point3::new(0.0, 0.0, 2.0),
+                -std::f32::consts::FRAC_PI_2,
+                0.0,
+            ),
+            CameraProjection::new(1.0, 1.5, 0.1, 100.0),
+            Default::default(),
+        );
+        let render = |depth_mode, reverse_z| {
+            let config = VirtualTexturingConfig {
+                virtual_textures: 2,
+                depth_mode,
+                ..Default::default()
+            };
+            let options = PipelineOptions {
+                reverse_z,
+                ..Default::default()
+            };
+            let mut context = VirtualTexturingContext::from_config_with_options(
+                Arc::clone(&wgpu_context),
+                config,
+                options,
+            );
+            context.set_fragment_shader(FragmentShader {
+                source: "@fragment\nfn fs_id(in: RenderInterpolators) -> @location(0) vec4<f32> {\n\
+                    return vec4<f32>(f32(in.texture_id == 0u), f32(in.texture_id == 1u), 0.0, 1.0);\n}\n"
+                    .to_owned(),
+                entry_point: "fs_id".to_owned(),
+                bind_groups: Vec::new(),
+            })
+            .unwrap();
+            context.update_camera(&camera);
+            let mesh = Arc::new(Mesh::new(&context.wgpu_context, &FOUR_TRIANGLES));
+            let items = [
+                DrawItem::new(Arc::clone(&mesh))
+                    .with_transform(nalgebra::Matrix4::new_scaling(0.5)),
+                DrawItem::new(mesh)
+                    .with_transform(nalgebra::Matrix4::new_translation(&-nalgebra::Vector3::z()))
+                    .with_texture_id(1),
+            ];
+            let mut streaming = StreamingHandle::new(
+                Arc::clone(&context.wgpu_context),
+                context.textures(),
+                storage.reader(),
+            );
+            let mut requests = render_feedback(&mut context, &mut streaming, &items, 1).concat();
+            requests.sort_unstable();
+            requests.dedup();
+            (wgpu_context.read_offscreen_target().unwrap(), requests)
+        };
+        for depth_mode in [DepthMode::Separate, DepthMode::ReusePrepass] {
+            let (image, requests) = render(depth_mode, false);
+            assert!(image.pixels().any(|pixel| pixel.0 == [255, 0, 0, 255]));
+            assert!(image.pixels().any(|pixel| pixel.0 == [0, 255, 0, 255]));
+            assert!(!requests.is_empty());
+            assert_eq!(render(depth_mode, true), (image, requests));
+        }
+    }
+
+    /// The effective bias follows the ratio of the prepass, and the quality bias is clamped.
+    #[test]
+    fn quality_bias() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let config = VirtualTexturingConfig {
+            prepass_ratio: 0.25,
+            ..Default::default()
+        };
+        let mut context = VirtualTexturingContext::from_config(Arc::new(wgpu_context), config);
+        assert_eq!(context.effective_lod_bias(), -2.0);
+        context.set_quality_bias(10.0);
+        assert_eq!(context.quality_bias(), 4.0);
+        assert_eq!(context.effective_lod_bias(), 2.0);
+        context.set_prepass_ratio(0.5);
+        assert_eq!(context.effective_lod_bias(), 3.0);
+        context.set_quality_bias(-0.5);
+        assert_eq!(context.effective_lod_bias(), -1.5);
+    }
+
+    /// The requests accumulate over the clear interval, counted in feedback frames.
+    #[test]
+    fn clear_feedback_every_interval() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let config = VirtualTexturingConfig {
+            prepass_clear_interval: 2,
+            ..Default::default()
+        };
+        let mut context = VirtualTexturingContext::from_config(Arc::new(wgpu_context), config);
+        let mut clears = Vec::new();
+        for _ in 0..4 {
+            clears.push(matches!(context.feedback_load_op(), wgpu::LoadOp::Clear(_)));
+            let frame = context.begin_frame(&[]);
+            context.end_frame(frame, None);
+        }
+        assert_eq!(clears, [true, false, true, false]);
+
+        context.set_prepass_ratio(0.5);
+        assert!(matches!(context.feedback_load_op(), wgpu::LoadOp::Clear(_)));
+    }
+
+    /// The triangles are minified far past the finest mip levels, which they request anyway.
+    #[test]
+    fn clamp_requested_mips() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let StreamingFixture {
+            mut context,
+            mut streaming,
+            temp_dir: _temp_dir,
+            ..
+        } = StreamingFixture::new(
+            wgpu_context,
+            TextureMetadata::from_mip(1, 4),
+            Default::default(),
+        );
+        let items = four_triangles(&context)
+            .into_iter()
+            .map(|item| item.with_mip_range(0..=1))
+            .collect::<Vec<_>>();
+        let requests = render_feedback(&mut context, &mut streaming, &items, 1).concat();
+        assert!(!requests.is_empty());
+        assert!(requests.iter().all(|page| page.mip_level() == 1));
+    }
+
+    /// The prepass writing its requests to storage buffers requests the same pages as the
+    /// reduction of the feedback texture.
+    #[test]
+    fn storage_buffer_feedback() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        if !wgpu_context
+            .downlevel_flags
+            .contains(wgpu::DownlevelFlags::FRAGMENT_WRITABLE_STORAGE)
+        {
+            crate::test_support::skip_without_downlevel_flags(
+                wgpu::DownlevelFlags::FRAGMENT_WRITABLE_STORAGE,
+            );
+            return;
+        }
+        let wgpu_context = Arc::new(wgpu_context);
+        let requests = |feedback_mode| {
+            let config = VirtualTexturingConfig {
+                feedback_mode,
+                prepass_ratio: 1.0,
+                ..Default::default()
+            };
+            let StreamingFixture {
+                mut context,
+                mut streaming,
+                temp_dir: _temp_dir,
+                ..
+            } = StreamingFixture::new(
+                Arc::clone(&wgpu_context),
+                TextureMetadata::from_mip(4, 4),
+                config,
+            );
+            let items = four_triangles(&context);
+            let mut requests = render_feedback(&mut context, &mut streaming, &items, 1).concat();
+            requests.sort_unstable();
+            requests.dedup();
+            (requests, context.pipelines.prepass_texture.width())
+        };
+
+        let (separate, _) = requests(FeedbackMode::Separate);
+        let (storage_buffer, feedback_width) = requests(FeedbackMode::StorageBuffer);
+        assert!(!separate.is_empty());
+        assert_eq!(separate, storage_buffer);
+        assert_eq!(feedback_width, 1);
+    }
+
+    /// A triangle of virtual texture 1 drawn behind the triangles of virtual texture 0 requests
+    /// its pages from the storage buffer prepass, unless the Hi-Z pyramid drops them.
+    #[test]
+    fn hi_z_drops_hidden_requests() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        if !wgpu_context
+            .downlevel_flags
+            .contains(wgpu::DownlevelFlags::FRAGMENT_WRITABLE_STORAGE)
+        {
+            crate::test_support::skip_without_downlevel_flags(
+                wgpu::DownlevelFlags::FRAGMENT_WRITABLE_STORAGE,
+            );
+            return;
+        }
+        let wgpu_context = Arc::new(wgpu_context);
+        let requests = |hi_z| {
+            let config = VirtualTexturingConfig {
+                feedback_mode: FeedbackMode::StorageBuffer,
+                virtual_textures: 2,
+                prepass_ratio: 0.5,
+                hi_z,
+                ..Default::default()
+            };
+            let StreamingFixture {
+                mut context,
+                mut streaming,
+                temp_dir: _temp_dir,
+                ..
+            } = StreamingFixture::new(
+                Arc::clone(&wgpu_context),
+                TextureMetadata::from_mip(4, 4),
+                config,
+            );
+            context.update_camera(&CameraModule::from_parts(
+                Camera::new(
+                    nalgebra::Point3::new(0.0, 0.0, 1.0),
+                    -std::f32::consts::FRAC_PI_2,
+                    0.0,
+                ),
+                CameraProjection::new(1.0, 1.5, 0.1, 100.0),
+                Default::default(),
+            ));
+            // Within the top left triangle on screen, and drawn first.
+            let hidden = DrawItem::new(Arc::new(Mesh::new(
+                &context.wgpu_context,
+                &FOUR_TRIANGLES[..3],
+            )))
+            .with_transform(
+                nalgebra::Matrix4::new_translation(&nalgebra::Vector3::new(-0.75, 0.75, -0.5))
+                    * nalgebra::Matrix4::new_scaling(0.2),
+            )
+            .with_texture_id(1);
+            let items = [vec![hidden], four_triangles(&context)].concat();
+
+            // The feedback of the first frame, whose prepass runs before any depth is rendered,
+            // and of the second one.
+            render_feedback(&mut context, &mut streaming, &items, 2).swap_remove(1)
+        };
+
+        let hidden_texture =
+            |requests: &[PageId]| requests.iter().any(|page| page.texture_id() == 1);
+        let without_hi_z = requests(false);
+        assert!(hidden_texture(&without_hi_z));
+        let with_hi_z = requests(true);
+        assert!(!with_hi_z.is_empty());
+        assert!(!hidden_texture(&with_hi_z));
+    }
+
+    #[test]
+    fn resize_targets() {
+        let wgpu_context = crate::headless_or_skip!(320, 240);
+        let mut context = VirtualTexturingContext::from_config(
+            Arc::new(WgpuContext {
+                offscreen_target: None,
+                ..wgpu_context
+            }),
+            Default::default(),
+        );
+        context.resize(winit::dpi::PhysicalSize::new(640, 200));
+        context.resize(winit::dpi::PhysicalSize::new(0, 0));
+
+        assert_eq!(
+            context.target_size(),
+            winit::dpi::PhysicalSize::new(640, 200)
+        );
+        let pipelines = &context.pipelines;
+        assert_eq!(
+            pipelines.prepass_texture.size(),
+            pipelines.prepass_depth_texture.size()
+        );
+        assert_eq!(
+            (
+                pipelines.prepass_texture.width(),
+                pipelines.prepass_texture.height()
+            ),
+            (64, 20)
+        );
+        assert_eq!(
+            (
+                pipelines.render_depth_texture.width(),
+                pipelines.render_depth_texture.height()
+            ),
+            (640, 200)
+        );
+
+        context.set_prepass_ratio(0.25);
+        assert_eq!(
+            (
+                context.pipelines.prepass_texture.width(),
+                context.pipelines.prepass_texture.height()
+            ),
+            (160, 50)
+        );
+    }
+
+    /// Every item is drawn with its own uniforms, and the uniforms buffer grows to fit them.
+    #[test]
+    fn draw_many_items() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let mut context =
+            VirtualTexturingContext::from_config(Arc::new(wgpu_context), Default::default());
+        // The bits of the virtual texture of the item in the color channels.
+        context
+            .set_fragment_shader(FragmentShader {
+                source: "@fragment\nfn fs_id(in: RenderInterpolators) -> @location(0) vec4<f32> {\n\
+                    return vec4<f32>(vec3<f32>(vec3<u32>(in.texture_id) >> vec3<u32>(0u, 1u, 2u) & vec3<u32>(1u)), 1.0);\n}\n"
+                    .to_owned(),
+                entry_point: "fs_id".to_owned(),
+                bind_groups: Vec::new(),
+            })
+            .unwrap();
+        // A row of items a fifth of the target wide, centered on x = -0.8, -0.4, 0.0, 0.4, 0.8.
+        let item = four_triangles(&context).remove(0);
+        let items = (0..5)
+            .map(|index| {
+                item.clone()
+                    .with_transform(
+                        nalgebra::Matrix4::new_translation(&nalgebra::Vector3::new(
+                            index as f32 * 0.4 - 0.8,
+                            0.0,
+                            0.0,
+                        )) * nalgebra::Matrix4::new_scaling(0.2),
+                    )
+                    .with_texture_id(index)
+            })
+            .collect::<Vec<_>>();
+        let frame = context.begin_frame(&items);
+        assert!(context.end_frame(frame, None).is_none());
+
+        let pipelines = &context.pipelines;
+        assert_eq!(pipelines.draw_items.len(), 5);
+        assert!(pipelines.draw_uniforms_buffer.size() >= 5 * pipelines.draw_uniforms_stride);
+        assert_eq!(
+            pipelines.draw_uniforms_offset(3) as u64,
+            3 * pipelines.draw_uniforms_stride
+        );
+        // Within the top left triangle of every item.
+        let image = context.wgpu_context.read_offscreen_target().unwrap();
+        let colors = [1, 14, 27, 40, 52].map(|x| image.get_pixel(x, 27).0);
+        assert_eq!(
+            colors,
+            [
+                [0, 0, 0, 255],
+                [255, 0, 0, 255],
+                [0, 255, 0, 255],
+                [255, 255, 0, 255],
+                [0, 0, 255, 255],
+            ]
+        );
+    }
+
+    /// In low power mode, the feedback is only read back every fourth frame.
+    #[test]
+    fn low_power_feedback_interval() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let StreamingFixture {
+            mut context,
+            mut streaming,
+            temp_dir: _temp_dir,
+            ..
+        } = StreamingFixture::new(
+            wgpu_context,
+            TextureMetadata::from_mip(1, 4),
+            Default::default(),
+        );
+        context.set_power_mode(PowerMode::LowPower);
+        streaming.set_power_mode(PowerMode::LowPower);
+        assert_eq!(streaming.upload_budget(), 16);
+
+        let items = four_triangles(&context);
+        let feedback_frames = (0..9)
+            .filter(|_| {
+                let is_feedback_frame = context.is_feedback_frame();
+                let frame = context.begin_frame(&items);
+                context.end_frame(frame, Some(&mut streaming));
+                is_feedback_frame
+            })
+            .count();
+        assert_eq!(feedback_frames, 3);
+        assert_eq!(streaming.stats_history().len(), 3);
+    }
+
+    /// With a single frame in flight, every frame waits for the previous one.
+    #[test]
+    fn limit_frames_in_flight() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let config = VirtualTexturingConfig {
+            max_frames_in_flight: 1,
+            ..Default::default()
+        };
+        let mut context = VirtualTexturingContext::from_config(Arc::new(wgpu_context), config);
+        let items = four_triangles(&context);
+        (0..4).for_each(|_| {
+            let frame = context.begin_frame(&items);
+            assert_eq!(context.frames_in_flight(), 0);
+            context.end_frame(frame, None);
+            assert!(context.frames_in_flight() <= 1);
+        });
+
+        context.set_max_frames_in_flight(3);
+        let frame = context.begin_frame(&items);
+        context.end_frame(frame, None);
+        assert!(context.frames_in_flight() <= 3);
+    }
+
+    #[test]
+    fn debug_dump_bundle() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let StreamingFixture {
+            mut context,
+            storage,
+            mut streaming,
+            temp_dir,
+        } = StreamingFixture::new(
+            wgpu_context,
+            TextureMetadata::from_mip(1, 4),
+            Default::default(),
+        );
+        let page_size = storage.metadata().page_size() as usize;
+        streaming.upload_page(
+            PageId::new(0, 0, 0),
+            (1, 0),
+            &vec![255; page_size * page_size * 4],
+        );
+        let frame = context.begin_frame(&four_triangles(&context));
+        context.end_frame(frame, Some(&mut streaming));
+
+        let dump = temp_dir.child("dump");
+        context.debug_dump(dump.path(), Some(&streaming)).unwrap();
+        ["config.json", "page_table_0.png", "requests.csv"]
+            .iter()
+            .for_each(|file| {
+                dump.child(file).assert(predicates::path::exists());
+            });
+        let lines = |file| std::fs::read_to_string(dump.child(file).path()).unwrap();
+        assert_eq!(
+            lines("residency.csv").lines().nth(1),
+            Some("0,0,0,0,1,0,false")
+        );
+        assert_eq!(lines("stats_history.csv").lines().count(), 2);
+
+        let cache = image::open(dump.child("cache_cold.png").path())
+            .unwrap()
+            .into_rgba8();
+        assert_eq!(cache.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_ne!(cache.get_pixel(1, 0).0, [0, 0, 0, 255]);
+    }
+
+    /// The overlay is drawn over the frames while it is enabled, with the slots of the resident
+    /// pages in their own color.
+    #[test]
+    fn debug_overlay() {
+        let wgpu_context = crate::headless_or_skip!(320, 240);
+        let StreamingFixture {
+            mut context,
+            storage,
+            mut streaming,
+            temp_dir: _temp_dir,
+        } = StreamingFixture::new(
+            wgpu_context,
+            TextureMetadata::from_mip(1, 4),
+            Default::default(),
+        );
+        let page_size = storage.metadata().page_size() as usize;
+        streaming.upload_page(
+            PageId::new(0, 0, 0),
+            (1, 0),
+            &vec![255; page_size * page_size * 4],
+        );
+        context.set_debug_overlay(Some(DebugOverlayOptions::default()));
+        assert_eq!(
+            context.debug_overlay(),
+            Some(DebugOverlayOptions::default())
+        );
+        let frame = context.begin_frame(&[]);
+        context.end_frame(frame, Some(&mut streaming));
+
+        // The panels are 80 pixels high, 8 pixels above the bottom. The occupancy panel of the
+        // 32 by 32 slots starts 16 pixels right of the page table one, its top left texel being
+        // the slot of the page.
+        let image = context.wgpu_context.read_offscreen_target().unwrap();
+        let page_table = image.get_pixel(48, 190).0;
+        let slot = image.get_pixel(99, 153).0;
+        assert_ne!(page_table, [255; 4]);
+        assert_ne!(slot, [255; 4]);
+        // Free slots are black, the slot of the page is not.
+        assert_ne!(slot, image.get_pixel(175, 231).0);
+
+        context.set_debug_overlay(None);
+        let frame = context.begin_frame(&[]);
+        context.end_frame(frame, Some(&mut streaming));
+        let image = context.wgpu_context.read_offscreen_target().unwrap();
+        assert!(image.pixels().all(|pixel| pixel.0 == [255; 4]));
+    }
+
+    /// The heatmap counts the frames requesting every page, and draws them over both spaces.
+    #[test]
+    fn request_heatmap() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let StreamingFixture {
+            mut context,
+            mut streaming,
+            temp_dir: _temp_dir,
+            ..
+        } = StreamingFixture::new(
+            wgpu_context,
+            TextureMetadata::from_mip(1, 4),
+            VirtualTexturingConfig {
+                page_table_size: 2,
+                // Single layer textures are not arrays on GL, where array views of them read zeros.
+                virtual_textures: 2,
+                ..Default::default()
+            },
+        );
+        let mut heatmap = RequestHeatmap::new(&context.wgpu_context, &context.textures(), 2);
+        let items = four_triangles(&context);
+        render_feedback(&mut context, &mut streaming, &items, 3);
+        heatmap.update(&context.wgpu_context, &streaming);
+        assert_eq!(heatmap.accumulated_frames(), 2);
+        let requests = streaming.request_traces().concat();
+        assert!(heatmap.requested_pages() > 0);
+        assert!(requests.iter().all(|&page| heatmap.count(page) <= 2));
+
+        context
+            .set_fragment_shader(heatmap.fragment_shader(&context.wgpu_context))
+            .unwrap();
+        let frame = context.begin_frame(&items);
+        context.end_frame(frame, Some(&mut streaming));
+        // The single page requested is requested by every frame, red at half intensity, mixed
+        // at 0.75 over the black of the texels that are not resident: 165 once sRGB encoded.
+        let close = |pixel: [u8; 4], expected: [u8; 4]| {
+            pixel.iter().zip(expected).all(|(&a, b)| a.abs_diff(b) <= 1)
+        };
+        let image = context.wgpu_context.read_offscreen_target().unwrap();
+        assert!(close(image.get_pixel(4, 4).0, [165, 0, 0, 255]));
+        assert_eq!(image.get_pixel(32, 32).0, [255; 4]);
+        // Over virtual texture space, after the frame is rendered.
+        let mut command_encoder = context
+            .wgpu_context
+            .device
+            .create_command_encoder(&Default::default());
+        let view = context
+            .wgpu_context
+            .offscreen_target
+            .as_ref()
+            .unwrap()
+            .create_view(&Default::default());
+        heatmap.render(
+            &context.wgpu_context,
+            &mut command_encoder,
+            &view,
+            context.target_size(),
+            0,
+            1,
+        );
+        context
+            .wgpu_context
+            .queue
+            .submit(Some(command_encoder.finish()));
+        // The same red, with an opacity of 0.85 over the white background, within the margin.
+        let image = context.wgpu_context.read_offscreen_target().unwrap();
+        assert!(close(image.get_pixel(32, 32).0, [200, 108, 108, 255]));
+        assert!(close(image.get_pixel(4, 4).0, [165, 0, 0, 255]));
+    }
+}
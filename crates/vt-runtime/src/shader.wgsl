@@ -0,0 +1,65 @@
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+}
+
+struct RenderInterpolators {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) @interpolate(flat) texture_id: u32,
+    // In world space, not renormalized after the interpolation.
+    @location(2) normal: vec3<f32>,
+    // See `DrawItem::uv_clamp` and `virtual_texture_clamp_uv`.
+    @location(3) @interpolate(flat) uv_clamp: vec4<f32>,
+};
+
+@vertex
+fn vs_render(in: VertexInput) -> RenderInterpolators {
+    var result: RenderInterpolators;
+    result.position = transform_position(in.position);
+    result.tex_coords = in.uv;
+    result.texture_id = draw.texture_id;
+    result.normal = (draw.model * vec4<f32>(in.normal, 0.0)).xyz;
+    result.uv_clamp = draw.uv_clamp;
+    return result;
+}
+
+// Mirrors `pipelines::ColorTransform`.
+struct ColorTransform {
+    exposure: f32,
+    // 0: none, 1: reinhard, 2: aces, see `pipelines::Tonemap`.
+    tonemap: u32,
+}
+
+@group(1) @binding(0)
+var<uniform> color_transform: ColorTransform;
+
+// Narkowicz's fit of the ACES filmic curve.
+fn tonemap_aces(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Applied to the linear color before it is written to the (sRGB) surface, which does the gamma
+// encoding. HDR texels may be negative once filtered, which the curves do not expect.
+fn apply_color_transform(color: vec4<f32>) -> vec4<f32> {
+    let exposed = max(color.rgb, vec3<f32>(0.0)) * color_transform.exposure;
+    var mapped: vec3<f32>;
+    switch color_transform.tonemap {
+        case 1u: {
+            mapped = exposed / (exposed + vec3<f32>(1.0));
+        }
+        case 2u: {
+            mapped = tonemap_aces(exposed);
+        }
+        default: {
+            mapped = exposed;
+        }
+    }
+    return vec4<f32>(mapped, color.a);
+}
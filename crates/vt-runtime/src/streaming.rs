@@ -0,0 +1,2253 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{Receiver, Sender},
+        Arc, Condvar, Mutex, OnceLock, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use crate::{
+    camera::CameraMotion,
+    config::PhysicalTextureConfig,
+    pipelines::Pipelines,
+    power::{PowerMode, StreamingPolicy},
+    setup::WgpuContext,
+    storage::{
+        decode_page, downsample_page, encode_page, pack_rg11b10, pad_page, PageReader, PageSource,
+        TexelFormat, TextureMetadata, TextureStorageError,
+    },
+    textures::{CacheTier, TextureHandle, Textures, VirtualTextureId},
+};
+
+mod budget;
+mod events;
+mod mip_generation;
+mod prefetch;
+mod residency;
+mod slots;
+mod staging;
+
+pub use budget::{StreamedPage, StreamingConfig};
+pub use events::{Severity, StreamingEvent};
+pub use mip_generation::{GeneratedPage, MipGenerationError};
+pub use prefetch::{PrefetchPolicy, VelocityPrefetch};
+pub use residency::{ResidencyMap, ResidencyReport, ResidencySnapshotError};
+pub use slots::SlotAllocator;
+pub use vt_core::{PageId, UvRect};
+
+/// A snapshot of the counters of the streaming thread, see [`StreamingHandle::stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StreamingStats {
+    /// Feedback texels holding the invalid sentinel since the start, written by the shader for
+    /// degenerate uvs and derivatives.
+    pub invalid_feedback_texels: u64,
+    /// Distinct pages requested past [`Pipelines::MAX_FEEDBACK_REQUESTS`] in a frame since the
+    /// start. They are requested again by the following frames.
+    pub dropped_feedback_requests: u64,
+    /// Frames whose feedback was not read back because every read buffer was still in flight,
+    /// see [`StreamingHandle::submit_feedback`].
+    pub skipped_feedback_frames: u64,
+    /// Pages written to the physical textures since the start.
+    pub uploaded_pages: u64,
+    /// Pages requested by the feedback read back since the start, counted once per frame.
+    pub requested_pages: u64,
+    /// Requested pages that were not resident when their feedback was read back, the misses of
+    /// the cache.
+    pub missed_pages: u64,
+    /// Pages evicted with [`StreamingHandle::evict_page`] since the start.
+    pub evicted_pages: u64,
+    /// Bytes of the pages read from storage by [`StreamingHandle::stream_page`] since the
+    /// start, decompressed.
+    pub read_bytes: u64,
+    /// Bytes written to the physical textures since the start, every layer and mip level of the
+    /// slots included.
+    pub uploaded_bytes: u64,
+    /// Writes to the physical textures that waited on the GPU for a staging buffer since the
+    /// start, every chunk of the staging belt being full or in flight (see
+    /// [`StreamingHandle::flush_uploads`]).
+    pub staging_stalls: u64,
+    /// Microseconds waited by the [`StreamingStats::staging_stalls`] since the start.
+    pub staging_stall_micros: u64,
+    /// Feedback readbacks in flight when the stats were taken. Not a counter, so it is kept as
+    /// is by [`StreamingStats::since`].
+    pub feedback_in_flight: u64,
+}
+
+impl StreamingStats {
+    /// The counters from `earlier` to these stats, such as the ones of a frame (see
+    /// [`StreamingHandle::frame_stats`]).
+    pub fn since(&self, earlier: &StreamingStats) -> StreamingStats {
+        StreamingStats {
+            invalid_feedback_texels: self.invalid_feedback_texels - earlier.invalid_feedback_texels,
+            dropped_feedback_requests: self.dropped_feedback_requests
+                - earlier.dropped_feedback_requests,
+            skipped_feedback_frames: self.skipped_feedback_frames - earlier.skipped_feedback_frames,
+            uploaded_pages: self.uploaded_pages - earlier.uploaded_pages,
+            requested_pages: self.requested_pages - earlier.requested_pages,
+            missed_pages: self.missed_pages - earlier.missed_pages,
+            evicted_pages: self.evicted_pages - earlier.evicted_pages,
+            read_bytes: self.read_bytes - earlier.read_bytes,
+            uploaded_bytes: self.uploaded_bytes - earlier.uploaded_bytes,
+            staging_stalls: self.staging_stalls - earlier.staging_stalls,
+            staging_stall_micros: self.staging_stall_micros - earlier.staging_stall_micros,
+            feedback_in_flight: self.feedback_in_flight,
+        }
+    }
+
+    /// The share of the requested pages that were resident, `None` if no page was requested.
+    pub fn hit_ratio(&self) -> Option<f32> {
+        (self.requested_pages > 0)
+            .then(|| 1.0 - self.missed_pages as f32 / self.requested_pages as f32)
+    }
+}
+
+#[derive(Default)]
+struct StreamingCounters {
+    invalid_feedback_texels: AtomicU64,
+    dropped_feedback_requests: AtomicU64,
+    skipped_feedback_frames: AtomicU64,
+    uploaded_pages: AtomicU64,
+    requested_pages: AtomicU64,
+    missed_pages: AtomicU64,
+    evicted_pages: AtomicU64,
+    read_bytes: AtomicU64,
+    uploaded_bytes: AtomicU64,
+    staging_stalls: AtomicU64,
+    staging_stall_micros: AtomicU64,
+}
+
+impl StreamingCounters {
+    /// Count the pages requested by a frame, and the ones of them missing from `residency`.
+    fn record_requests(&self, residency: &ResidencyMap, pages: &[PageId]) {
+        let missed = pages
+            .iter()
+            .filter(|&&page| !residency.is_resident(page))
+            .count();
+        self.requested_pages
+            .fetch_add(pages.len() as u64, Ordering::Relaxed);
+        self.missed_pages
+            .fetch_add(missed as u64, Ordering::Relaxed);
+    }
+}
+
+/// A buffer the feedback requests are copied to, to be mapped and read by the streaming thread.
+struct FeedbackReadBuffer {
+    buffer: wgpu::Buffer,
+    /// Set from the copy until the streaming thread unmaps the buffer.
+    in_flight: AtomicBool,
+}
+
+/// A change to the page table, deferred to [`StreamingHandle::flush_uploads`].
+enum PageTableUpdate {
+    /// [`Textures::map_page`] the stored page to the entry.
+    Map(PageId, [u8; 4]),
+    /// [`Textures::unmap_page`] the stored page.
+    Unmap(PageId),
+}
+
+/// Push `value` to the back of `history`, dropping the oldest values past
+/// [`StreamingHandle::HISTORY_LEN`].
+fn push_bounded<T>(history: &mut VecDeque<T>, value: T) {
+    if history.len() == StreamingHandle::HISTORY_LEN {
+        history.pop_front();
+    }
+    history.push_back(value);
+}
+
+/// The index of the first of `count` buffers that is not in flight, starting from `start`.
+fn next_free_buffer(
+    count: usize,
+    start: usize,
+    in_flight: impl Fn(usize) -> bool,
+) -> Option<usize> {
+    (0..count)
+        .map(|offset| (start + offset) % count)
+        .find(|&index| !in_flight(index))
+}
+
+/// The distinct pages requested by a frame, read back from
+/// [`Pipelines::feedback_requests_buffer`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct FeedbackRequests {
+    pages: Vec<PageId>,
+    invalid_texels: u32,
+    /// The requests that did not fit in the buffer.
+    dropped: u32,
+}
+
+impl FeedbackRequests {
+    /// Decode the content of the buffer written by `feedback_requests.wgsl`.
+    fn decode(bytes: &[u8]) -> Self {
+        let word =
+            |index: usize| u32::from_le_bytes(bytes[index * 4..index * 4 + 4].try_into().unwrap());
+        let count = word(0);
+        let pages = &bytes[Pipelines::FEEDBACK_REQUESTS_HEADER_SIZE as usize..];
+        let stored = count.min((pages.len() / std::mem::size_of::<u32>()) as u32);
+        Self {
+            pages: pages
+                .chunks_exact(std::mem::size_of::<u32>())
+                .take(stored as usize)
+                .map(PageId::from_bytes)
+                .collect(),
+            invalid_texels: word(1),
+            dropped: count - stored,
+        }
+    }
+}
+
+/// The page uploaded to every slot of each cache tier, with the RGBA8 texels of its first layer.
+type CpuCopies = HashMap<(CacheTier, (u32, u32)), (PageId, Arc<[u8]>)>;
+
+pub struct StreamingHandle {
+    context: Arc<WgpuContext>,
+    counters: Arc<StreamingCounters>,
+    textures: Arc<Textures>,
+    /// The source of the pages of every registered virtual texture, indexed by
+    /// [`VirtualTextureId`].
+    texture_storage: Vec<Arc<dyn PageSource>>,
+    /// The readers of the virtual textures set with [`StreamingHandle::set_page_reader`], by
+    /// texture id.
+    page_readers: Vec<Option<Box<dyn PageReader>>>,
+    /// The slots of the pages submitted to a page reader by [`StreamingHandle::stream_queued`]
+    /// whose reads are still in flight, collected by its next calls.
+    submitted_reads: Mutex<HashMap<PageId, (u32, u32)>>,
+    residency: Arc<RwLock<ResidencyMap>>,
+    feedback_read_buffers: Arc<[FeedbackReadBuffer]>,
+    /// The buffer copied to by the last call to [`StreamingHandle::submit_feedback`], mapped by
+    /// the next call once the copy is submitted.
+    copied_read_buffer: Option<usize>,
+    next_read_buffer: usize,
+    /// See [`StreamingHandle::set_max_feedback_in_flight`].
+    max_feedback_in_flight: usize,
+    /// Synthesize the missing pages from their ancestors, see [`StreamingHandle::set_overzoom`].
+    overzoom: bool,
+    policy: StreamingPolicy,
+    /// See [`StreamingHandle::set_streaming_config`].
+    streaming_config: StreamingConfig,
+    /// The pages queued by [`StreamingHandle::queue_requests`], not streamed in yet.
+    request_queue: Mutex<budget::RequestQueue>,
+    /// See [`StreamingHandle::set_prefetch_policy`].
+    prefetch_policy: Mutex<Box<dyn PrefetchPolicy>>,
+    /// The first layer of the page uploaded to every slot as RGBA8 texels, kept when enabled
+    /// with [`StreamingHandle::set_cpu_copies`].
+    cpu_copies: Option<Mutex<CpuCopies>>,
+    /// The pages written since the last [`StreamingHandle::flush_uploads`].
+    staging: Mutex<staging::StagingBelt>,
+    /// The page table updates since the last [`StreamingHandle::flush_uploads`], in order,
+    /// written after the copies of the staging belt so that no entry points to a slot before
+    /// its page lands in it.
+    page_table_updates: Mutex<Vec<PageTableUpdate>>,
+    /// Sends the index of the mapped read buffers to the streaming thread.
+    sender: Sender<usize>,
+    /// [`StreamingStats::uploaded_pages`] at the last call to
+    /// [`StreamingHandle::submit_feedback`], to annotate the uploads of every frame and count
+    /// them against [`StreamingHandle::upload_budget`].
+    annotated_uploaded_pages: u64,
+    /// The stats at the last [`StreamingHandle::HISTORY_LEN`] calls to
+    /// [`StreamingHandle::submit_feedback`], oldest first.
+    stats_history: VecDeque<StreamingStats>,
+    /// The stats at the last call to [`StreamingHandle::reset_frame_stats`].
+    frame_start_stats: StreamingStats,
+    /// The pages requested by the last [`StreamingHandle::HISTORY_LEN`] feedback readbacks,
+    /// oldest first, recorded by the streaming thread.
+    request_traces: Arc<Mutex<VecDeque<Vec<PageId>>>>,
+    /// Notified by the streaming thread once a trace is recorded and its read buffer is free
+    /// again, see [`StreamingHandle::wait_feedback`].
+    feedback_read: Arc<Condvar>,
+    events: events::EventSender,
+    event_receiver: Receiver<StreamingEvent>,
+    /// See [`StreamingHandle::generate_ancestor_mips`].
+    mip_generation: OnceLock<mip_generation::MipGenerationPipelines>,
+}
+
+impl StreamingHandle {
+    /// The number of feedback read buffers, so that the readback of a frame can be in flight
+    /// while the next ones are rendered.
+    pub const FEEDBACK_READ_BUFFERS: usize = 3;
+    /// The number of frames kept by [`StreamingHandle::stats_history`] and
+    /// [`StreamingHandle::request_traces`].
+    pub const HISTORY_LEN: usize = 120;
+    /// The size of the staging buffers the pages are written to before they are copied to the
+    /// physical textures, 64 pages of 128 by 128 RGBA8 texels.
+    pub const STAGING_CHUNK_SIZE: u64 = 4 << 20;
+    /// The number of staging buffers, past which the writes wait for the GPU to be done with one
+    /// (see [`StreamingStats::staging_stalls`]).
+    pub const STAGING_CHUNKS: usize = 4;
+
+    /// Stream `storage` in as the virtual texture 0, see [`StreamingHandle::register_texture`]
+    /// for the others. `storage` is usually a [`TextureReader`](crate::storage::TextureReader),
+    /// or any other [`PageSource`].
+    ///
+    /// ### Panics
+    ///
+    /// - If the pages of `storage` do not have the page and border sizes of `textures`, which
+    ///   are the ones the shaders sample with.
+    pub fn new(
+        context: Arc<WgpuContext>,
+        textures: TextureHandle,
+        storage: impl PageSource + 'static,
+    ) -> Self {
+        let TextureHandle(textures) = textures;
+        assert_page_sizes(storage.metadata(), &textures);
+        let (tx, rx) = std::sync::mpsc::channel::<usize>();
+        let feedback_read_buffers = (0..Self::FEEDBACK_READ_BUFFERS)
+            .map(|_| FeedbackReadBuffer {
+                buffer: context.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("feedback read buffer"),
+                    size: Pipelines::FEEDBACK_REQUESTS_BUFFER_SIZE,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+                in_flight: AtomicBool::new(false),
+            })
+            .collect::<Arc<[_]>>();
+        let counters = Arc::<StreamingCounters>::default();
+        // Not a strong reference, so that the buffers, and the instance with them, are not
+        // dropped by the thread after the handle.
+        let move_buffers = Arc::downgrade(&feedback_read_buffers);
+        let move_counters = Arc::clone(&counters);
+        let residency = Arc::<RwLock<ResidencyMap>>::default();
+        let move_residency = Arc::clone(&residency);
+        let request_traces = Arc::<Mutex<VecDeque<Vec<PageId>>>>::default();
+        let move_request_traces = Arc::clone(&request_traces);
+        let feedback_read = Arc::<Condvar>::default();
+        let move_feedback_read = Arc::clone(&feedback_read);
+        let (events, event_receiver) = events::channel();
+        let move_events = events.clone();
+        let slots = textures.slot_count(CacheTier::Cold) + textures.slot_count(CacheTier::Hot);
+        std::thread::spawn(move || {
+            // Stops when the handle and the pending map callbacks are dropped.
+            for index in rx {
+                let Some(buffers) = move_buffers.upgrade() else {
+                    break;
+                };
+                let read_buffer = &buffers[index];
+                let requests =
+                    FeedbackRequests::decode(&read_buffer.buffer.slice(..).get_mapped_range());
+                read_buffer.buffer.unmap();
+                move_counters
+                    .invalid_feedback_texels
+                    .fetch_add(requests.invalid_texels as u64, Ordering::Relaxed);
+                move_counters
+                    .dropped_feedback_requests
+                    .fetch_add(requests.dropped as u64, Ordering::Relaxed);
+                move_counters.record_requests(&move_residency.read().unwrap(), &requests.pages);
+                let requested = requests.pages.len();
+                // The pages are streamed in from the traces by the caller, see
+                // `StreamingHandle::queue_requests` and `StreamingHandle::stream_queued`.
+                let mut traces = move_request_traces.lock().unwrap();
+                push_bounded(&mut traces, requests.pages);
+                // Freed with the trace recorded, so that `StreamingHandle::wait_feedback` sees
+                // both at once.
+                read_buffer.in_flight.store(false, Ordering::Release);
+                drop(traces);
+                move_feedback_read.notify_all();
+                if requests.dropped > 0 {
+                    move_events.send(StreamingEvent::FeedbackRequestsDropped {
+                        dropped: requests.dropped,
+                    });
+                }
+                if requested > slots as usize {
+                    move_events.send(StreamingEvent::CachePressure { requested, slots });
+                }
+            }
+        });
+
+        Self {
+            context,
+            counters,
+            streaming_config: textures.streaming_config,
+            textures,
+            sender: tx,
+            feedback_read_buffers,
+            copied_read_buffer: None,
+            next_read_buffer: 0,
+            max_feedback_in_flight: Self::FEEDBACK_READ_BUFFERS,
+            overzoom: false,
+            policy: PowerMode::default().streaming_policy(),
+            request_queue: Default::default(),
+            prefetch_policy: Mutex::new(Box::<VelocityPrefetch>::default()),
+            cpu_copies: None,
+            staging: Mutex::new(staging::StagingBelt::new(
+                Self::STAGING_CHUNK_SIZE,
+                Self::STAGING_CHUNKS,
+            )),
+            page_table_updates: Mutex::default(),
+            annotated_uploaded_pages: 0,
+            stats_history: VecDeque::with_capacity(Self::HISTORY_LEN),
+            frame_start_stats: StreamingStats::default(),
+            request_traces,
+            feedback_read,
+            events,
+            event_receiver,
+            mip_generation: OnceLock::new(),
+            texture_storage: vec![Arc::new(storage)],
+            page_readers: Vec::new(),
+            submitted_reads: Mutex::default(),
+            residency,
+        }
+    }
+
+    /// Record the readback of the pages requested by the frame, to be called once per frame
+    /// after [`VirtualTexturingContext::reduce_feedback`](crate::setup::VirtualTexturingContext::reduce_feedback).
+    ///
+    /// `command_encoder` must be submitted before the next call, which maps the buffer copied to
+    /// (a buffer cannot be mapped before the copy is submitted). The streaming thread is woken
+    /// up once the mapping completes, without ever blocking the caller. If
+    /// [`StreamingHandle::set_max_feedback_in_flight`] read buffers are still in flight, the
+    /// feedback of the frame is skipped (see [`StreamingStats::skipped_feedback_frames`]).
+    ///
+    /// The pages uploaded since the previous call are copied by submissions of their own (see
+    /// [`StreamingHandle::flush_uploads`]), so they do not show up in frame captures with the
+    /// commands of the frame. A debug marker with their number is recorded instead.
+    pub fn submit_feedback(&mut self, command_encoder: &mut wgpu::CommandEncoder) {
+        let uploaded_pages = self.counters.uploaded_pages.load(Ordering::Relaxed);
+        command_encoder.insert_debug_marker(&format!(
+            "{} pages uploaded",
+            uploaded_pages - self.annotated_uploaded_pages
+        ));
+        self.annotated_uploaded_pages = uploaded_pages;
+        let stats = self.stats();
+        push_bounded(&mut self.stats_history, stats);
+
+        if let Some(index) = self.copied_read_buffer.take() {
+            let sender = self.sender.clone();
+            let events = self.events.clone();
+            let buffers = Arc::downgrade(&self.feedback_read_buffers);
+            self.feedback_read_buffers[index]
+                .buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| match result {
+                    // Only fails if the streaming thread panicked.
+                    Ok(()) => sender.send(index).unwrap_or_default(),
+                    Err(error) => {
+                        // The buffer is not mapped, so it is free for the next readbacks.
+                        if let Some(buffers) = buffers.upgrade() {
+                            buffers[index].in_flight.store(false, Ordering::Release);
+                        }
+                        log::error!("could not map the feedback read buffer: {error}");
+                        events.send(StreamingEvent::FeedbackReadbackFailed {
+                            error: error.to_string(),
+                        });
+                    }
+                });
+        }
+        // Run the callbacks of the mappings that completed.
+        self.context.device.poll(wgpu::Maintain::Poll);
+
+        let buffers = &self.feedback_read_buffers;
+        let in_flight = buffers
+            .iter()
+            .filter(|buffer| buffer.in_flight.load(Ordering::Acquire))
+            .count();
+        let free_buffer = next_free_buffer(buffers.len(), self.next_read_buffer, |index| {
+            buffers[index].in_flight.load(Ordering::Acquire)
+        });
+        let Some(index) = free_buffer.filter(|_| in_flight < self.max_feedback_in_flight) else {
+            self.counters
+                .skipped_feedback_frames
+                .fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        let read_buffer = &self.feedback_read_buffers[index];
+        read_buffer.in_flight.store(true, Ordering::Release);
+        command_encoder.push_debug_group(&format!("feedback copy to read buffer {index}"));
+        command_encoder.copy_buffer_to_buffer(
+            &self.textures.feedback_requests_buffer,
+            0,
+            &read_buffer.buffer,
+            0,
+            Pipelines::FEEDBACK_REQUESTS_BUFFER_SIZE,
+        );
+        command_encoder.pop_debug_group();
+        self.copied_read_buffer = Some(index);
+        self.next_read_buffer = (index + 1) % self.feedback_read_buffers.len();
+    }
+
+    /// Read back the feedback of at most `count` frames at once, see
+    /// [`VirtualTexturingConfig::max_feedback_in_flight`](crate::config::VirtualTexturingConfig::max_feedback_in_flight),
+    /// which [`VirtualTexturingContext::end_frame`](crate::setup::VirtualTexturingContext::end_frame)
+    /// applies (Default: [`StreamingHandle::FEEDBACK_READ_BUFFERS`]).
+    ///
+    /// ### Panics
+    ///
+    /// - If `count` is not in `1..=FEEDBACK_READ_BUFFERS`.
+    pub fn set_max_feedback_in_flight(&mut self, count: usize) {
+        assert!((1..=Self::FEEDBACK_READ_BUFFERS).contains(&count));
+        self.max_feedback_in_flight = count;
+    }
+
+    /// The counters since the start, see [`StreamingHandle::frame_stats`] for the ones of the
+    /// current frame.
+    pub fn stats(&self) -> StreamingStats {
+        let counter = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        StreamingStats {
+            invalid_feedback_texels: counter(&self.counters.invalid_feedback_texels),
+            dropped_feedback_requests: counter(&self.counters.dropped_feedback_requests),
+            skipped_feedback_frames: counter(&self.counters.skipped_feedback_frames),
+            uploaded_pages: counter(&self.counters.uploaded_pages),
+            requested_pages: counter(&self.counters.requested_pages),
+            missed_pages: counter(&self.counters.missed_pages),
+            evicted_pages: counter(&self.counters.evicted_pages),
+            read_bytes: counter(&self.counters.read_bytes),
+            uploaded_bytes: counter(&self.counters.uploaded_bytes),
+            staging_stalls: counter(&self.counters.staging_stalls),
+            staging_stall_micros: counter(&self.counters.staging_stall_micros),
+            feedback_in_flight: self
+                .feedback_read_buffers
+                .iter()
+                .filter(|buffer| buffer.in_flight.load(Ordering::Acquire))
+                .count() as u64,
+        }
+    }
+
+    /// The counters since the last call to [`StreamingHandle::reset_frame_stats`], or since the
+    /// start.
+    pub fn frame_stats(&self) -> StreamingStats {
+        self.stats().since(&self.frame_start_stats)
+    }
+
+    /// Start counting [`StreamingHandle::frame_stats`] from zero again, to be called once per
+    /// frame to tune the lod bias and the cache sizes from the stats of single frames.
+    pub fn reset_frame_stats(&mut self) {
+        self.frame_start_stats = self.stats();
+    }
+
+    /// Stream `storage` in as the next virtual texture, whose id is returned. Its pages are
+    /// requested by the draw items of that [`DrawItem::texture_id`](crate::draw::DrawItem::texture_id),
+    /// are mapped by their own layer of the page table, and share the slots of the physical
+    /// textures with the other virtual textures.
+    ///
+    /// ### Panics
+    ///
+    /// - If the pages of `storage` do not have the page and border sizes of `textures`.
+    /// - If `storage` does not have the layers and texel format of the virtual texture 0, which
+    ///   the physical textures were created for.
+    /// - If the page table has no layer left, see
+    ///   [`VirtualTexturingConfig::virtual_textures`](crate::config::VirtualTexturingConfig::virtual_textures).
+    pub fn register_texture(&mut self, storage: impl PageSource + 'static) -> VirtualTextureId {
+        let metadata = storage.metadata();
+        assert_page_sizes(metadata, &self.textures);
+        let first = self.texture_storage[0].metadata();
+        assert!(
+            metadata.texel_format() == first.texel_format()
+                && metadata.layers().len() == first.layers().len()
+                && metadata
+                    .layers()
+                    .iter()
+                    .zip(first.layers())
+                    .all(|(layer, first)| layer.encoding == first.encoding),
+            "the layers of the virtual textures must match the physical textures"
+        );
+        let texture_id = self.texture_storage.len() as u32;
+        assert!(
+            texture_id < self.textures.virtual_texture_count(),
+            "the page table has a layer for {} virtual textures",
+            self.textures.virtual_texture_count()
+        );
+        self.texture_storage.push(Arc::new(storage));
+        texture_id as VirtualTextureId
+    }
+
+    /// Read the pages of the virtual texture `texture_id` streamed in by
+    /// [`StreamingHandle::stream_queued`] with `reader` instead of its source, so that the reads
+    /// of a frame are in flight at once (e.g., with a
+    /// [`ThreadedPageReader`](crate::storage::ThreadedPageReader)). `reader` must read the pages
+    /// of the source of the texture. Overzoomed textures (see
+    /// [`StreamingHandle::set_overzoom`]) are still read from their source.
+    ///
+    /// ### Panics
+    ///
+    /// - If no virtual texture is registered with `texture_id`.
+    pub fn set_page_reader(
+        &mut self,
+        texture_id: VirtualTextureId,
+        reader: impl PageReader + 'static,
+    ) {
+        assert!((texture_id as usize) < self.texture_storage.len());
+        assert_page_sizes(reader.metadata(), &self.textures);
+        if self.page_readers.len() <= texture_id as usize {
+            self.page_readers
+                .resize_with(texture_id as usize + 1, || None);
+        }
+        self.page_readers[texture_id as usize] = Some(Box::new(reader));
+    }
+
+    fn page_reader(&self, texture_id: VirtualTextureId) -> Option<&dyn PageReader> {
+        if self.overzoom {
+            return None;
+        }
+        self.page_readers.get(texture_id as usize)?.as_deref()
+    }
+
+    /// The number of virtual textures registered, including the virtual texture 0.
+    pub fn texture_count(&self) -> usize {
+        self.texture_storage.len()
+    }
+
+    /// The metadata of the virtual texture 0, the one [`StreamingHandle::residency_of`] and
+    /// [`StreamingHandle::sample_cpu`] query.
+    pub fn metadata(&self) -> &TextureMetadata {
+        self.texture_storage[0].metadata()
+    }
+
+    /// The metadata of the virtual texture `texture_id`.
+    ///
+    /// ### Panics
+    ///
+    /// - If no virtual texture is registered with `texture_id`.
+    pub fn texture_metadata(&self, texture_id: VirtualTextureId) -> &TextureMetadata {
+        self.reader(texture_id).metadata()
+    }
+
+    fn reader(&self, texture_id: VirtualTextureId) -> &dyn PageSource {
+        self.texture_storage
+            .get(texture_id as usize)
+            .map(Arc::as_ref)
+            .unwrap_or_else(|| panic!("no virtual texture is registered with the id {texture_id}"))
+    }
+
+    /// The events since the last call, oldest first, to be drained every frame. Past
+    /// 1024 events, the next ones are dropped until the events are drained.
+    pub fn drain_events(&self) -> impl Iterator<Item = StreamingEvent> + '_ {
+        self.event_receiver.try_iter()
+    }
+
+    /// The stats of the last [`StreamingHandle::HISTORY_LEN`] frames, oldest first.
+    pub fn stats_history(&self) -> &VecDeque<StreamingStats> {
+        &self.stats_history
+    }
+
+    /// The pages requested by the feedback of the last [`StreamingHandle::HISTORY_LEN`] frames
+    /// read back, oldest first.
+    pub fn request_traces(&self) -> Vec<Vec<PageId>> {
+        self.request_traces
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Block until the feedback of the frames before the last one is read back, and recorded in
+    /// [`StreamingHandle::request_traces`]. The feedback of the last frame is only mapped by the
+    /// next call to [`StreamingHandle::submit_feedback`].
+    ///
+    /// For the tests and tools stepping through frames, which can not wait on the streaming
+    /// thread otherwise.
+    pub fn wait_feedback(&self) {
+        self.context.device.poll(wgpu::Maintain::Wait);
+        let pending = |index: usize| {
+            Some(index) != self.copied_read_buffer
+                && self.feedback_read_buffers[index]
+                    .in_flight
+                    .load(Ordering::Acquire)
+        };
+        let traces = self.request_traces.lock().unwrap();
+        let _traces = self
+            .feedback_read
+            .wait_while(traces, |_| {
+                (0..self.feedback_read_buffers.len()).any(pending)
+            })
+            .unwrap();
+    }
+
+    /// A copy of the residency map of the streaming thread.
+    pub fn residency(&self) -> ResidencyMap {
+        self.residency.read().unwrap().clone()
+    }
+
+    /// The residency of the pages of mip level `mip` of the virtual texture 0 covering `uv_rect`,
+    /// according to the residency map of the streaming thread.
+    ///
+    /// Pages falling back to a coarser resident page count as missing. On coarse mip levels, the
+    /// coarse pages are counted (see [`TextureMetadata::stored_page`]).
+    ///
+    /// [`TextureMetadata::stored_page`]: crate::storage::TextureMetadata::stored_page
+    pub fn residency_of(&self, uv_rect: UvRect, mip: u8) -> ResidencyReport {
+        let metadata = self.metadata();
+        let mut pages = PageId::pages_covering(uv_rect, mip, metadata.mip_dimensions(mip))
+            .map(|page| metadata.stored_page(page))
+            .collect::<Vec<_>>();
+        pages.sort_unstable();
+        pages.dedup();
+        self.residency.read().unwrap().report(pages)
+    }
+
+    /// Synthesize the pages missing from storage (see
+    /// [`TextureMetadata::is_missing`](crate::storage::TextureMetadata::is_missing)) by
+    /// upsampling their closest ancestor with data when they are streamed in, for map-style data
+    /// that lacks fine resolution in some regions (Default: disabled).
+    pub fn set_overzoom(&mut self, enabled: bool) {
+        self.overzoom = enabled;
+    }
+
+    /// Keep a CPU copy of the first layer of the pages uploaded from now on, so that
+    /// [`StreamingHandle::sample_cpu`] does not read them from storage (Default: disabled).
+    ///
+    /// A copy is kept per slot of the physical textures, so they take as much memory as the
+    /// physical textures of the first layer, decoded. Disabling drops the copies.
+    pub fn set_cpu_copies(&mut self, enabled: bool) {
+        self.cpu_copies = enabled.then(Default::default);
+    }
+
+    /// The texel of the first layer of the virtual texture 0 at `uv` on mip level `mip` as RGBA8,
+    /// without filtering, for gameplay queries on the texture that is rendered (e.g., the terrain
+    /// type under the player).
+    ///
+    /// The texel is read from the CPU copy of its page if it is resident and its copy is kept
+    /// (see [`StreamingHandle::set_cpu_copies`]), and from storage otherwise, which blocks on
+    /// the disk. Uvs out of `[0, 1]` are clamped to the edge of the texture.
+    ///
+    /// Returns `None` for non finite uvs, for mip levels past the coarsest one, and if the page
+    /// could not be read.
+    pub fn sample_cpu(&self, uv: (f32, f32), mip: u8) -> Option<[u8; 4]> {
+        let metadata = self.metadata();
+        if mip > metadata.mip_levels() || !uv.0.is_finite() || !uv.1.is_finite() {
+            return None;
+        }
+
+        // The texel of the mip level, without borders.
+        let page_stride = metadata.page_stride() as u32;
+        let texel = |uv: f32, pages: u16| {
+            let size = pages as u32 * page_stride;
+            ((uv.clamp(0.0, 1.0) * size as f32) as u32).min(size - 1)
+        };
+        let (width, height) = metadata.mip_dimensions(mip);
+        let (x, y) = (texel(uv.0, width), texel(uv.1, height));
+        let page = metadata.stored_page(PageId::new(
+            mip,
+            (x / page_stride) as u16,
+            (y / page_stride) as u16,
+        ));
+
+        let stored_stride = page_stride << metadata.page_scale(mip);
+        let border_size = metadata.border_size_at(mip) as u32;
+        let (page_x, page_y) = (
+            x - page.x() as u32 * stored_stride + border_size,
+            y - page.y() as u32 * stored_stride + border_size,
+        );
+        let page_size = metadata.page_size_at(mip) as usize;
+        let index = (page_y as usize * page_size + page_x as usize) * 4;
+        let read_texel = |rgba: &[u8]| rgba[index..index + 4].try_into().unwrap();
+
+        if let Some(copy) = self.cpu_copy(page) {
+            return Some(read_texel(&copy));
+        }
+        let data = self.texture_storage[0]
+            .read_page(page)
+            .map_err(|error| self.page_read_failed(page, &error))
+            .ok()?;
+        Some(read_texel(&first_layer_rgba(metadata, mip, &data)))
+    }
+
+    /// The CPU copy of `page`, if it is resident and kept.
+    fn cpu_copy(&self, page: PageId) -> Option<Arc<[u8]>> {
+        let copies = self.cpu_copies.as_ref()?.lock().unwrap();
+        let slot = self.residency.read().unwrap().slot(page)?;
+        let tier = self.textures.cache_tier(page.mip_level());
+        copies
+            .get(&(tier, slot))
+            .filter(|(copied_page, _)| *copied_page == page)
+            .map(|(_, copy)| Arc::clone(copy))
+    }
+
+    /// Stream with the policy of `mode`, see [`PowerMode::streaming_policy`] (Default:
+    /// [`PowerMode::Performance`]). The context producing the feedback must be switched as
+    /// well, with
+    /// [`VirtualTexturingContext::set_power_mode`](crate::setup::VirtualTexturingContext::set_power_mode).
+    pub fn set_power_mode(&mut self, mode: PowerMode) {
+        self.policy = mode.streaming_policy();
+    }
+
+    pub fn policy(&self) -> StreamingPolicy {
+        self.policy
+    }
+
+    /// The pages that can still be uploaded before the next call to
+    /// [`StreamingHandle::submit_feedback`], see [`StreamingPolicy::max_uploads_per_feedback`].
+    pub fn upload_budget(&self) -> u32 {
+        let uploaded =
+            self.counters.uploaded_pages.load(Ordering::Relaxed) - self.annotated_uploaded_pages;
+        (self.policy.max_uploads_per_feedback as u64).saturating_sub(uploaded) as u32
+    }
+
+    /// Write the resident pages to `path`, to warm the cache up from them with
+    /// [`StreamingHandle::load_residency`] on the next startup or level load. The synthetic
+    /// pages are left out, see [`ResidencyMap::snapshot`].
+    pub fn save_residency(&self, path: impl AsRef<Path>) -> Result<(), ResidencySnapshotError> {
+        let snapshot = self.residency.read().unwrap().snapshot();
+        std::fs::write(path, snapshot)?;
+        Ok(())
+    }
+
+    /// Queue the pages saved to `path` by [`StreamingHandle::save_residency`], to be streamed
+    /// in by [`StreamingHandle::stream_queued`] like requested pages, coarse ones first. Returns
+    /// the number of pages queued, the pages out of the registered virtual textures (e.g.,
+    /// after the texture was imported again smaller) and the resident ones being skipped.
+    ///
+    /// The pages are dropped from the queue unless requested within
+    /// [`StreamingPolicy::keep_alive_frames`], so a stale snapshot only costs the budget of a
+    /// few frames. To stream them in before the first frame, raise the budget with
+    /// [`StreamingHandle::set_streaming_config`] for a call to
+    /// [`StreamingHandle::stream_queued`].
+    pub fn load_residency(&self, path: impl AsRef<Path>) -> Result<usize, ResidencySnapshotError> {
+        let mut pages = ResidencyMap::decode_snapshot(&std::fs::read(path)?)?;
+        let residency = self.residency.read().unwrap();
+        pages.retain(|&page| self.in_texture(page) && !residency.is_resident(page));
+        self.queue_requests(&pages);
+        Ok(pages.len())
+    }
+
+    /// Whether `page` is a page of a registered virtual texture.
+    fn in_texture(&self, page: PageId) -> bool {
+        let Some(storage) = self.texture_storage.get(page.texture_id() as usize) else {
+            return false;
+        };
+        let metadata = storage.metadata();
+        page.mip_level() <= metadata.mip_levels() && {
+            let (width, height) = metadata.mip_dimensions(page.mip_level());
+            page.x() < width && page.y() < height
+        }
+    }
+
+    /// Cap the streaming work of every call to [`StreamingHandle::stream_queued`], see
+    /// [`VirtualTexturingConfig::streaming`](crate::config::VirtualTexturingConfig::streaming),
+    /// which [`VirtualTexturingContext::end_frame`](crate::setup::VirtualTexturingContext::end_frame)
+    /// applies (Default: the one of the context of `textures`).
+    pub fn set_streaming_config(&mut self, config: StreamingConfig) {
+        self.streaming_config = config;
+    }
+
+    pub fn streaming_config(&self) -> StreamingConfig {
+        self.streaming_config
+    }
+
+    /// Queue `pages` to be streamed in by [`StreamingHandle::stream_queued`], usually the last
+    /// of [`StreamingHandle::request_traces`]. The pages already queued keep their priority.
+    pub fn queue_requests(&self, pages: &[PageId]) {
+        self.request_queue.lock().unwrap().request(pages);
+    }
+
+    /// Pick the pages queued by [`StreamingHandle::queue_prefetch`] with `policy` (Default:
+    /// [`VelocityPrefetch::default`]).
+    pub fn set_prefetch_policy(&mut self, policy: impl PrefetchPolicy + 'static) {
+        self.prefetch_policy = Mutex::new(Box::new(policy));
+    }
+
+    /// Queue the pages the [`PrefetchPolicy`] expects to be requested soon, given the pages
+    /// `requested` by the latest feedback and the motion of the camera (see
+    /// [`CameraModule::motion`](crate::camera::CameraModule::motion)), to be called once per
+    /// feedback after [`StreamingHandle::queue_requests`]. Returns the number of pages queued.
+    ///
+    /// The prefetched pages are streamed in by [`StreamingHandle::stream_queued`] after the
+    /// requested ones, within the same budget, and are dropped if they do not fit in the frame.
+    /// Nothing is prefetched when [`StreamingPolicy::prefetch`] is off, as with
+    /// [`PowerMode::LowPower`].
+    pub fn queue_prefetch(&self, requested: &[PageId], camera: &CameraMotion) -> usize {
+        if !self.policy.prefetch {
+            return 0;
+        }
+        let mut pages = self
+            .prefetch_policy
+            .lock()
+            .unwrap()
+            .prefetch(requested, camera);
+        let residency = self.residency.read().unwrap();
+        pages.retain(|&page| {
+            self.in_texture(page) && !residency.is_resident(page) && !requested.contains(&page)
+        });
+        self.request_queue.lock().unwrap().prefetch(&pages);
+        pages.len()
+    }
+
+    /// The pages queued and not streamed in yet.
+    pub fn queued_requests(&self) -> usize {
+        self.request_queue.lock().unwrap().len()
+    }
+
+    /// Stream the queued pages in, and map them, within the budget of a frame (see
+    /// [`StreamingHandle::set_streaming_config`]), to be called once per frame. Returns the
+    /// pages streamed, failed reads included.
+    ///
+    /// The coarse pages go first. The pages left over are carried over to the next frames, their
+    /// priority raised by a mip level for every frame they wait, and are dropped once they are
+    /// not requested again for [`StreamingPolicy::keep_alive_frames`]. `allocate` returns the
+    /// slot of every page, or `None` to keep the remaining pages queued when no slot can be
+    /// freed. The pages resident already are skipped.
+    ///
+    /// The pages of the textures with a [`PageReader`] (see
+    /// [`StreamingHandle::set_page_reader`]) are submitted to it as they are picked, their size
+    /// on disk counting against the bytes budget, and uploaded as their reads complete, waiting
+    /// on them for what is left of [`StreamingConfig::max_io_time`]. The reads still in flight
+    /// are returned by the next calls once they complete, their slots staying allocated in the
+    /// meantime, so that a slow source does not stall the frame.
+    pub fn stream_queued(
+        &self,
+        mut allocate: impl FnMut(PageId) -> Option<(u32, u32)>,
+    ) -> Vec<StreamedPage> {
+        let config = self.streaming_config;
+        let max_uploads = config.max_uploads_per_frame.min(self.upload_budget()) as usize;
+        let first_uploaded_bytes = self.counters.uploaded_bytes.load(Ordering::Relaxed);
+        let start = Instant::now();
+        let mut submitted = self.submitted_reads.lock().unwrap();
+        // The reads completed since the previous call.
+        let mut streamed = self.collect_reads(&mut submitted, Duration::ZERO);
+        let mut queue = self.request_queue.lock().unwrap();
+        // The pages submitted to a page reader by this call, with their bytes on disk.
+        let mut submitted_pages = 0;
+        let mut submitted_bytes = 0;
+        for page in queue.by_priority() {
+            let uploaded_bytes =
+                self.counters.uploaded_bytes.load(Ordering::Relaxed) - first_uploaded_bytes;
+            if streamed.len() + submitted_pages >= max_uploads
+                || uploaded_bytes + submitted_bytes >= config.max_bytes_per_frame
+                || start.elapsed() >= config.max_io_time()
+            {
+                break;
+            }
+            if self.residency.read().unwrap().is_resident(page) || submitted.contains_key(&page) {
+                queue.remove(page);
+                continue;
+            }
+            let Some(slot) = allocate(page) else {
+                break;
+            };
+            queue.remove(page);
+            if let Some(reader) = self.page_reader(page.texture_id()) {
+                reader.submit(&[page]);
+                submitted.insert(page, slot);
+                submitted_pages += 1;
+                submitted_bytes += reader.metadata().page_byte_size_at(page.mip_level()) as u64;
+                continue;
+            }
+            let result = self.stream_page(page, slot);
+            if result.is_ok() {
+                self.map_page(page, slot);
+            }
+            streamed.push(StreamedPage { page, slot, result });
+        }
+        queue.carry_over(self.policy.keep_alive_frames);
+        drop(queue);
+
+        while !submitted.is_empty() {
+            let timeout = config.max_io_time().saturating_sub(start.elapsed());
+            let reads = self.collect_reads(&mut submitted, timeout);
+            let timed_out = reads.is_empty();
+            streamed.extend(reads);
+            if timed_out || start.elapsed() >= config.max_io_time() {
+                break;
+            }
+        }
+        streamed
+    }
+
+    /// Upload and map the reads of the pages in `submitted` completed by their page readers,
+    /// waiting up to `timeout` for one if none is, and fail the ones lost by their reader.
+    fn collect_reads(
+        &self,
+        submitted: &mut HashMap<PageId, (u32, u32)>,
+        timeout: Duration,
+    ) -> Vec<StreamedPage> {
+        if submitted.is_empty() {
+            return Vec::new();
+        }
+        let readers = || {
+            self.page_readers
+                .iter()
+                .flatten()
+                .filter(|reader| reader.in_flight() > 0)
+        };
+        let mut reads = readers()
+            .flat_map(|reader| reader.completed())
+            .collect::<Vec<_>>();
+        if reads.is_empty() && !timeout.is_zero() {
+            let deadline = Instant::now() + timeout;
+            reads = readers()
+                .flat_map(|reader| {
+                    reader
+                        .wait_completed_timeout(deadline.saturating_duration_since(Instant::now()))
+                })
+                .collect();
+        }
+        let mut streamed = reads
+            .into_iter()
+            .filter_map(|read| {
+                let slot = submitted.remove(&read.page)?;
+                let result = self.upload_read(read.page, slot, read.data);
+                if result.is_ok() {
+                    self.map_page(read.page, slot);
+                }
+                Some(StreamedPage {
+                    page: read.page,
+                    slot,
+                    result,
+                })
+            })
+            .collect::<Vec<_>>();
+        // The reads lost by the readers fail, for their slots to be freed.
+        submitted.retain(|&page, &mut slot| {
+            let lost = self
+                .page_reader(page.texture_id())
+                .is_none_or(|reader| reader.in_flight() == 0);
+            if lost {
+                streamed.push(StreamedPage {
+                    page,
+                    slot,
+                    result: Err(std::io::Error::other("the page reader lost the read").into()),
+                });
+            }
+            !lost
+        });
+        streamed
+    }
+
+    /// Upload `page`, read from storage, to `slot`, or report its read error.
+    fn upload_read(
+        &self,
+        page_id: PageId,
+        slot: (u32, u32),
+        page: Result<Vec<u8>, TextureStorageError>,
+    ) -> Result<(), TextureStorageError> {
+        let page = page.inspect_err(|error| self.page_read_failed(page_id, error))?;
+        self.page_read(&page);
+        self.upload_page(page_id, slot, &page);
+        Ok(())
+    }
+
+    /// Read `page_id` from storage and upload it to `slot`, see [`StreamingHandle::upload_page`].
+    ///
+    /// With overzoom enabled, missing pages are synthesized and recorded as synthetic in the
+    /// residency map, so that they can be replaced if real data is added later.
+    ///
+    /// Errors are also reported as [`StreamingEvent::PageReadFailed`].
+    pub fn stream_page(
+        &self,
+        page_id: PageId,
+        slot: (u32, u32),
+    ) -> Result<(), TextureStorageError> {
+        let storage = self.reader(page_id.texture_id());
+        if !self.overzoom {
+            return self.upload_read(page_id, slot, storage.read_page(page_id));
+        }
+
+        let page = storage
+            .read_page_overzoomed(page_id)
+            .inspect_err(|error| self.page_read_failed(page_id, error))?;
+        self.page_read(&page.data);
+        self.write_page(page_id, slot, &page.data);
+        let mut residency = self.residency.write().unwrap();
+        if page.synthetic {
+            residency.insert_synthetic(page_id, slot);
+        } else {
+            residency.insert(page_id, slot);
+        }
+        self.events.send(StreamingEvent::PageLoaded {
+            page: page_id,
+            slot,
+            synthetic: page.synthetic,
+        });
+        Ok(())
+    }
+
+    /// Stream every page of the mip levels of `texture_id` from `first_mip` to the coarsest one
+    /// in, blocking until they are read, to the slots of the cold tier allocated from `slots`,
+    /// and point every entry of the page table to them, the entries of the finer mip levels
+    /// falling back to the page of `first_mip` covering them (see [`Textures::map_pages`]).
+    /// Returns the number of pages streamed in.
+    ///
+    /// Meant to be called once the virtual texture is registered, before the first frame: the
+    /// coarse mip levels only take a few pages, and with them every uv samples something from
+    /// the first frame, the feedback only streaming in the finer pages. The slots of the mip
+    /// tail should never be freed.
+    ///
+    /// ### Panics
+    ///
+    /// - If the pages of `first_mip` go to the hot tier (see [`Textures::cache_tier`]), or if no
+    ///   virtual texture is registered with `texture_id`.
+    pub fn preload_mip_tail(
+        &self,
+        texture_id: VirtualTextureId,
+        first_mip: u8,
+        slots: &mut SlotAllocator,
+    ) -> Result<usize, PreloadError> {
+        assert_eq!(self.textures.cache_tier(first_mip), CacheTier::Cold);
+        let metadata = self.texture_metadata(texture_id);
+        let first_mip = first_mip.min(metadata.mip_levels());
+        let mut tail_slots = HashMap::new();
+        (first_mip..=metadata.mip_levels())
+            .rev()
+            .flat_map(|mip| {
+                let (width, height) = metadata.page_grid(mip);
+                (0..height).flat_map(move |y| {
+                    (0..width).map(move |x| PageId::with_texture_id(texture_id, mip, x, y))
+                })
+            })
+            .try_for_each(|page| {
+                let slot = slots
+                    .allocate(metadata.page_scale(page.mip_level()))
+                    .ok_or(PreloadError::NoFreeSlot(page))?;
+                self.stream_page(page, slot)?;
+                tail_slots.insert(page, slot);
+                Ok::<_, PreloadError>(())
+            })?;
+
+        // Mapped after the copies of the pages, like by `StreamingHandle::map_page`.
+        self.flush_uploads();
+        self.textures.map_pages(
+            &self.context.queue,
+            texture_id,
+            tail_slots.iter().map(|(&page, &slot)| {
+                let mip = page.mip_level();
+                let entry = self.textures.page_table_entry(
+                    slot,
+                    mip,
+                    metadata.page_scale(mip),
+                    CacheTier::Cold,
+                );
+                (page, entry)
+            }),
+        );
+        Ok(tail_slots.len())
+    }
+
+    /// Stream the resident pages of `pages` in again to the slot they are in, for sources whose
+    /// content changed (see [`TemporalPageSource::set_frame`](crate::storage::TemporalPageSource::set_frame)).
+    /// Their page table entries are unchanged. Returns the number of pages streamed in, the
+    /// pages that are not resident being skipped.
+    pub fn refresh_pages(&self, pages: &[PageId]) -> Result<usize, TextureStorageError> {
+        pages.iter().try_fold(0, |refreshed, &page| {
+            let Some(slot) = self.residency.read().unwrap().slot(page) else {
+                return Ok(refreshed);
+            };
+            self.stream_page(page, slot)?;
+            Ok(refreshed + 1)
+        })
+    }
+
+    /// The slots of the physical textures holding the pages of mip level `mip`, see
+    /// [`Textures::slot_layout`].
+    pub fn slot_layout(&self, mip: u8) -> PhysicalTextureConfig {
+        self.textures.slot_layout(self.textures.cache_tier(mip))
+    }
+
+    /// Point the page table to `page_id` in `slot`, where it was streamed in (see
+    /// [`StreamingHandle::stream_page`]), at its mip level and at the finer ones falling back to
+    /// it, see [`Textures::map_page`].
+    ///
+    /// The entries are written by the next [`StreamingHandle::flush_uploads`], after the copies
+    /// of the pages.
+    pub fn map_page(&self, page_id: PageId, slot: (u32, u32)) {
+        let metadata = self.texture_metadata(page_id.texture_id());
+        let mip = page_id.mip_level();
+        let entry = self.textures.page_table_entry(
+            slot,
+            mip,
+            metadata.page_scale(mip),
+            self.textures.cache_tier(mip),
+        );
+        self.page_table_updates
+            .lock()
+            .unwrap()
+            .push(PageTableUpdate::Map(metadata.stored_page(page_id), entry));
+    }
+
+    /// Remove `page_id` from the residency map and the page table, its entries falling back to
+    /// the finest mapped page covering it (see [`Textures::unmap_page`]). Returns the slot it was
+    /// in, to be freed, or `None` if it was not resident.
+    ///
+    /// Like [`StreamingHandle::map_page`], the entries are written by the next
+    /// [`StreamingHandle::flush_uploads`].
+    pub fn evict_page(&self, page_id: PageId) -> Option<(u32, u32)> {
+        let stored_page = self
+            .texture_metadata(page_id.texture_id())
+            .stored_page(page_id);
+        self.page_table_updates
+            .lock()
+            .unwrap()
+            .push(PageTableUpdate::Unmap(stored_page));
+        let slot = self.residency.write().unwrap().remove(page_id);
+        if slot.is_some() {
+            self.counters.evicted_pages.fetch_add(1, Ordering::Relaxed);
+        }
+        slot
+    }
+
+    /// Write a page table entry with the queue of the handle, see
+    /// [`Textures::write_page_table_entry`].
+    pub fn write_page_table_entry(
+        &self,
+        texture_id: VirtualTextureId,
+        mip: u8,
+        coords: (u32, u32),
+        entry: [u8; 4],
+    ) {
+        self.textures
+            .write_page_table_entry(&self.context.queue, texture_id, mip, coords, entry);
+    }
+
+    /// Submit the copies of the pages written since the previous call from the staging belt to
+    /// the physical textures, then write the page table entries mapped and evicted since, to be
+    /// called before the frames sampling them are submitted.
+    /// [`VirtualTexturingContext::end_frame`](crate::setup::VirtualTexturingContext::end_frame)
+    /// calls it before submitting the frame.
+    ///
+    /// The pages are written to a bounded ring of staging buffers, reused once the GPU is done
+    /// with their copies. When every buffer is full or in flight, the writes flush the copies
+    /// and block until one is free (see [`StreamingStats::staging_stalls`]).
+    pub fn flush_uploads(&self) {
+        let mut staging = self.staging.lock().unwrap();
+        staging.flush(&self.context);
+        // Written by the queue at the next submission, after the copies just submitted.
+        let updates = std::mem::take(&mut *self.page_table_updates.lock().unwrap());
+        updates.into_iter().for_each(|update| match update {
+            PageTableUpdate::Map(page, entry) => {
+                self.textures.map_page(&self.context.queue, page, entry)
+            }
+            PageTableUpdate::Unmap(page) => self.textures.unmap_page(&self.context.queue, page),
+        });
+    }
+
+    fn staging_stalled(&self, stall: Duration) {
+        self.counters.staging_stalls.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .staging_stall_micros
+            .fetch_add(stall.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn page_read(&self, page: &[u8]) {
+        self.counters
+            .read_bytes
+            .fetch_add(page.len() as u64, Ordering::Relaxed);
+    }
+
+    fn page_read_failed(&self, page: PageId, error: &TextureStorageError) {
+        log::warn!("could not read {page:?}: {error}");
+        self.events.send(StreamingEvent::PageReadFailed {
+            page,
+            error: error.to_string(),
+        });
+    }
+
+    /// Upload `page_id` read from storage to the slot at (`slot_x`, `slot_y`) in the physical
+    /// textures of its cache tier, one layer per physical texture, and record it in the residency
+    /// map.
+    ///
+    /// Coarse pages take the block of slots starting at `slot` (see [`SlotAllocator`]), `slot_y`
+    /// spanning the array layers of the physical textures (see [`Textures::slot_location`]).
+    /// Block compressed layers are decoded on the CPU if their physical texture is not block
+    /// compressed.
+    pub fn upload_page(&self, page_id: PageId, slot: (u32, u32), page: &[u8]) {
+        self.write_page(page_id, slot, page);
+        self.residency.write().unwrap().insert(page_id, slot);
+        self.events.send(StreamingEvent::PageLoaded {
+            page: page_id,
+            slot,
+            synthetic: false,
+        });
+    }
+
+    /// Write `page` to `slot`, at the center of the slot if the border of its mip level is
+    /// narrower (see [`TextureMetadata::with_mip_border_sizes`](crate::storage::TextureMetadata::with_mip_border_sizes)).
+    ///
+    /// With [`Textures::physical_mip_levels`], the page is also downsampled to the mip chain of
+    /// its slot, block compressed layers being encoded again on the CPU.
+    fn write_page(&self, page_id: PageId, slot: (u32, u32), page: &[u8]) {
+        let metadata = self.texture_metadata(page_id.texture_id());
+        let slot_size = metadata.page_size() as u32;
+        let page_size = metadata.page_size_at(page_id.mip_level()) as u32;
+        let inset = metadata.border_inset(page_id.mip_level()) as u32;
+        let tier = self.textures.cache_tier(page_id.mip_level());
+        let (slot_x, slot_y, array_layer) = self.textures.slot_location(tier, slot);
+
+        metadata
+            .layers()
+            .iter()
+            .zip(metadata.split_layers(page_id.mip_level(), page))
+            .zip(self.textures.tier_textures(tier))
+            .for_each(|((layer, layer_page), physical_texture)| {
+                let format = physical_texture.format();
+                let block_size = format
+                    .block_size(None)
+                    .expect("the physical texture to be a color texture");
+                let write_level = |mip_level: u32, offset: u32, size: u32, data: &[u8]| {
+                    let (block_width, _) = format.block_dimensions();
+                    self.counters
+                        .uploaded_bytes
+                        .fetch_add(data.len() as u64, Ordering::Relaxed);
+                    let stall = self.staging.lock().unwrap().write_texture(
+                        &self.context,
+                        wgpu::ImageCopyTexture {
+                            texture: physical_texture,
+                            mip_level,
+                            origin: wgpu::Origin3d {
+                                x: ((slot_x * slot_size) >> mip_level) + offset,
+                                y: ((slot_y * slot_size) >> mip_level) + offset,
+                                z: array_layer,
+                            },
+                            aspect: wgpu::TextureAspect::All,
+                        },
+                        data,
+                        size / block_width * block_size,
+                        wgpu::Extent3d {
+                            width: size,
+                            height: size,
+                            depth_or_array_layers: 1,
+                        },
+                    );
+                    if let Some(stall) = stall {
+                        self.staging_stalled(stall);
+                    }
+                };
+
+                let texels = (!format.is_compressed() || self.textures.physical_mip_levels > 1)
+                    .then(|| decode_page(layer.encoding, layer_page, page_size as usize));
+                match &texels {
+                    Some(texels) if !format.is_compressed() => write_level(
+                        0,
+                        inset,
+                        page_size,
+                        &physical_texels(texels.clone(), metadata.texel_format(), format),
+                    ),
+                    _ => write_level(0, inset, page_size, layer_page),
+                }
+
+                // The levels are downsampled from the whole slot, so that they stay aligned to
+                // its blocks when the border is narrower.
+                let Some(texels) = texels else { return };
+                let texel_format = metadata.texel_format();
+                let mut level_size = page_size + 2 * inset;
+                let mut level = pad_page(
+                    &texels,
+                    page_size as usize,
+                    inset as usize,
+                    texel_format.bytes_per_texel() as usize,
+                );
+                (1..self.textures.physical_mip_levels).for_each(|mip_level| {
+                    level = downsample_page(
+                        &level,
+                        level_size as usize,
+                        texel_format,
+                        layer.color_space(),
+                    );
+                    level_size /= 2;
+                    let data = if format.is_compressed() {
+                        encode_page(layer.encoding, &level, level_size as usize)
+                    } else {
+                        physical_texels(level.clone(), texel_format, format)
+                    };
+                    write_level(mip_level, 0, level_size, &data);
+                });
+            });
+        self.counters.uploaded_pages.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(copies) = &self.cpu_copies {
+            let copy = first_layer_rgba(metadata, page_id.mip_level(), page).into();
+            copies.lock().unwrap().insert((tier, slot), (page_id, copy));
+        }
+    }
+}
+
+/// The errors of [`StreamingHandle::preload_mip_tail`].
+#[derive(Error, Debug)]
+pub enum PreloadError {
+    #[error("could not stream the mip tail in: {0}")]
+    Storage(#[from] TextureStorageError),
+    #[error("no slot is free for {0:?}")]
+    NoFreeSlot(PageId),
+}
+
+/// ### Panics
+///
+/// - If the pages of `metadata` do not have the page and border sizes of `textures`, which are
+///   the ones the shaders sample with.
+fn assert_page_sizes(metadata: &TextureMetadata, textures: &Textures) {
+    assert_eq!(
+        (metadata.page_size() as u32, metadata.border_size() as u32),
+        (textures.page_size, textures.border_size),
+        "the page and border sizes of the texture must match the configuration"
+    );
+}
+
+/// The first layer of a page of mip level `mip` read from storage, decoded to RGBA8 texels.
+fn first_layer_rgba(metadata: &TextureMetadata, mip: u8, page: &[u8]) -> Vec<u8> {
+    let layer = metadata.layers()[0].encoding;
+    let layer_page = metadata.split_layers(mip, page)[0];
+    let texels = decode_page(layer, layer_page, metadata.page_size_at(mip) as usize);
+    metadata.texel_format().to_rgba8(texels)
+}
+
+/// The texels of an uncompressed physical texture of `format` from decoded texels of
+/// `texel_format`: RGBA16F texels packed for `Rg11b10Float`, the first channels of RGBA8 texels
+/// for the textures with fewer channels, and the texels as they are otherwise.
+pub(crate) fn physical_texels(
+    texels: Vec<u8>,
+    texel_format: TexelFormat,
+    format: wgpu::TextureFormat,
+) -> Vec<u8> {
+    match (texel_format, format) {
+        (_, wgpu::TextureFormat::Rg11b10Float) => pack_rg11b10(&texels),
+        (TexelFormat::Rgba8, wgpu::TextureFormat::Rg8Unorm) => keep_channels(texels, 2),
+        (TexelFormat::Rgba8, wgpu::TextureFormat::R8Unorm) => keep_channels(texels, 1),
+        _ => texels,
+    }
+}
+
+/// Keep the first `channels` channels of RGBA8 texels, for the physical textures with fewer
+/// channels (e.g., `Rg8Unorm` for BC5 layers).
+fn keep_channels(rgba: Vec<u8>, channels: usize) -> Vec<u8> {
+    if channels == 4 {
+        return rgba;
+    }
+    rgba.chunks_exact(4)
+        .flat_map(|texel| &texel[..channels])
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::{
+        keep_channels, next_free_buffer, physical_texels, FeedbackRequests, PageId, PrefetchPolicy,
+        ResidencySnapshotError, SlotAllocator, StreamingConfig, StreamingEvent, StreamingHandle,
+        UvRect,
+    };
+    use crate::{
+        camera::CameraMotion,
+        config::{PhysicalTextureConfig, VirtualTexturingConfig},
+        debug::read_texture,
+        pipelines::Pipelines,
+        power::PowerMode,
+        setup::VirtualTexturingContext,
+        storage::{
+            downsample_page, ColorSpace, PageSource, TexelFormat, TextureMetadata, TextureStorage,
+            TextureStorageError, ThreadedPageReader,
+        },
+        test_support::StreamingFixture,
+        textures::{CacheTier, Textures},
+    };
+
+    #[test]
+    fn invalid_feedback_is_ignored() {
+        assert_eq!(PageId::from_feedback(&PageId::INVALID_FEEDBACK), None);
+        assert_eq!(PageId::from_feedback(&PageId::NO_REQUEST_FEEDBACK), None);
+        assert_eq!(
+            PageId::from_feedback(&[0, 0, 0, 3]),
+            Some(PageId::new(3, 0, 0))
+        );
+    }
+
+    #[test]
+    fn feedback_encoding_round_trip() {
+        [
+            (0, 0, 0, 0),
+            (1, 3, 1234, 4095),
+            (15, 14, 4095, 1),
+            (7, 7, 64, 2048),
+        ]
+        .iter()
+        .for_each(|&(texture_id, mip, x, y)| {
+            let page = PageId::with_texture_id(texture_id, mip, x, y);
+            assert_eq!(PageId::from_bytes(&page.to_bytes()), page);
+        });
+        // x = 0b1010_1010_1010, y = 0b0011_0011_0011
+        assert_eq!(
+            PageId::with_texture_id(2, 5, 0xAAA, 0x333).to_bytes(),
+            [0b1010_1010, 0b1010_0011, 0b0011_0011, 0b0010_0101]
+        );
+    }
+
+    #[test]
+    fn page_hierarchy() {
+        let page = PageId::new(1, 3, 2);
+        assert_eq!(page.parent(), Some(PageId::new(2, 1, 1)));
+        assert_eq!(
+            page.children().collect::<Vec<_>>(),
+            [(6, 4), (7, 4), (6, 5), (7, 5)].map(|(x, y)| PageId::new(0, x, y))
+        );
+        assert_eq!(PageId::new(0, 0, 0).children().count(), 0);
+        assert_eq!(page.ancestors(3).count(), 2);
+        assert_eq!(PageId::new(0, 0, 0).neighbors((4, 4)).count(), 3);
+        assert_eq!(PageId::new(0, 3, 1).neighbors((4, 4)).count(), 5);
+        assert_eq!(page.neighbors((8, 8)).count(), 8);
+    }
+
+    #[test]
+    fn pages_covering_rect() {
+        let pages = PageId::pages_covering(UvRect::new((0.3, 0.0), (0.6, 0.2)), 1, (4, 4))
+            .collect::<Vec<_>>();
+        assert_eq!(pages, [PageId::new(1, 1, 0), PageId::new(1, 2, 0)]);
+        let clamped = PageId::pages_covering(UvRect::new((-1.0, -1.0), (2.0, 2.0)), 0, (2, 2));
+        assert_eq!(clamped.count(), 4);
+    }
+
+    #[test]
+    fn decode_feedback_requests() {
+        let pages = [PageId::new(0, 5, 9), PageId::new(2, 1, 0)];
+        let mut buffer = vec![0; Pipelines::FEEDBACK_REQUESTS_BUFFER_SIZE as usize];
+        buffer[..4].copy_from_slice(&(Pipelines::MAX_FEEDBACK_REQUESTS + 3).to_le_bytes());
+        buffer[4..8].copy_from_slice(&7u32.to_le_bytes());
+        buffer[8..12].copy_from_slice(&pages[0].to_bytes());
+        buffer[12..16].copy_from_slice(&pages[1].to_bytes());
+
+        let requests = FeedbackRequests::decode(&buffer);
+        assert_eq!(
+            requests.pages.len(),
+            Pipelines::MAX_FEEDBACK_REQUESTS as usize
+        );
+        assert_eq!(requests.pages[..2], pages);
+        assert_eq!(requests.invalid_texels, 7);
+        assert_eq!(requests.dropped, 3);
+    }
+
+    #[test]
+    fn keep_rg_channels() {
+        let rgba = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(keep_channels(rgba.clone(), 2), [1, 2, 5, 6]);
+        assert_eq!(keep_channels(rgba.clone(), 4), rgba);
+        assert_eq!(
+            physical_texels(
+                rgba.clone(),
+                TexelFormat::Rgba8,
+                wgpu::TextureFormat::Rg8Unorm
+            ),
+            [1, 2, 5, 6]
+        );
+        // Two channel texels are already in the format of the physical texture.
+        assert_eq!(
+            physical_texels(
+                rgba.clone(),
+                TexelFormat::Rg8,
+                wgpu::TextureFormat::Rg8Unorm
+            ),
+            rgba
+        );
+        assert_eq!(
+            physical_texels(
+                rgba.clone(),
+                TexelFormat::Rgba16Float,
+                wgpu::TextureFormat::Rgba16Float
+            ),
+            rgba
+        );
+        assert_eq!(
+            physical_texels(
+                rgba,
+                TexelFormat::Rgba16Float,
+                wgpu::TextureFormat::Rg11b10Float
+            )
+            .len(),
+            4
+        );
+    }
+
+    /// Pages are written with their downsampled levels to the mip chain of their slot.
+    #[test]
+    fn upload_physical_mip_levels() {
+        let page = (0..4 * 4)
+            .flat_map(|index| [index as u8 % 4 * 10, index as u8 / 4 * 10, 0, 255])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            downsample_page(&page, 4, TexelFormat::Rgba8, ColorSpace::Linear),
+            [5, 5, 0, 255, 25, 5, 0, 255, 5, 25, 0, 255, 25, 25, 0, 255]
+        );
+
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let config = VirtualTexturingConfig {
+            page_size: 8,
+            border_size: 2,
+            page_table_size: 2,
+            physical_mip_levels: 2,
+            ..Default::default()
+        };
+        assert_eq!(config.max_physical_mip_levels(), 2);
+        let StreamingFixture {
+            context,
+            streaming,
+            temp_dir: _temp_dir,
+            ..
+        } = StreamingFixture::new(
+            wgpu_context,
+            TextureMetadata::from_mip(0, 4).with_page_size(8, 2),
+            config,
+        );
+        let physical_texture = &context.textures.physical_textures[0];
+        assert_eq!(physical_texture.mip_level_count(), 2);
+        let page = (0..8 * 8)
+            .flat_map(|index| [index as u8 % 8 * 20, index as u8 / 8 * 20, 0, 255])
+            .collect::<Vec<_>>();
+        streaming.upload_page(PageId::new(0, 0, 0), (1, 0), &page);
+        streaming.flush_uploads();
+        assert_eq!(streaming.stats().uploaded_pages, 1);
+
+        // The slot (1, 0) covers the texels from (4, 0) to (8, 4) of the level 1.
+        let level = downsample_page(&page, 8, TexelFormat::Rgba8, ColorSpace::Srgb);
+        let texels = read_texture(&context.wgpu_context, physical_texture, 0, 1).unwrap();
+        let level_width = physical_texture.width() as usize / 2;
+        (0..4).for_each(|y| {
+            let start = (y * level_width + 4) * 4;
+            assert_eq!(
+                texels[start..start + 4 * 4],
+                level[y * 4 * 4..(y + 1) * 4 * 4]
+            );
+        });
+    }
+
+    /// The frame stats count from the last reset, and the requests of pages that are not
+    /// resident are misses.
+    #[test]
+    fn frame_stats() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let config = VirtualTexturingConfig {
+            page_size: 8,
+            border_size: 2,
+            page_table_size: 2,
+            ..Default::default()
+        };
+        let StreamingFixture {
+            context: _context,
+            mut streaming,
+            temp_dir: _temp_dir,
+            ..
+        } = StreamingFixture::new(
+            wgpu_context,
+            TextureMetadata::from_mip(0, 4).with_page_size(8, 2),
+            config,
+        );
+        let page = PageId::new(0, 0, 0);
+        streaming.upload_page(page, (1, 0), &[200; 8 * 8 * 4]);
+        streaming.counters.record_requests(
+            &streaming.residency.read().unwrap(),
+            &[page, PageId::new(0, 1, 0)],
+        );
+        let stats = streaming.stats();
+        assert_eq!((stats.uploaded_pages, stats.uploaded_bytes), (1, 8 * 8 * 4));
+        assert_eq!((stats.requested_pages, stats.missed_pages), (2, 1));
+        assert_eq!(stats.hit_ratio(), Some(0.5));
+
+        streaming.reset_frame_stats();
+        assert_eq!(streaming.evict_page(page), Some((1, 0)));
+        assert_eq!(streaming.evict_page(page), None);
+        let frame_stats = streaming.frame_stats();
+        assert_eq!(
+            (frame_stats.uploaded_pages, frame_stats.evicted_pages),
+            (0, 1)
+        );
+        assert_eq!(frame_stats.hit_ratio(), None);
+    }
+
+    /// Refreshed pages are read from their source again to the slot they are in.
+    #[test]
+    fn refresh_pages() {
+        struct CountingSource {
+            metadata: TextureMetadata,
+            reads: AtomicUsize,
+        }
+        impl PageSource for CountingSource {
+            fn metadata(&self) -> &TextureMetadata {
+                &self.metadata
+            }
+
+            fn read_page(&self, page: PageId) -> Result<Vec<u8>, TextureStorageError> {
+                self.reads.fetch_add(1, Ordering::Relaxed);
+                Ok(vec![0; self.metadata.page_byte_size_at(page.mip_level())])
+            }
+        }
+
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let config = VirtualTexturingConfig {
+            page_size: 8,
+            border_size: 2,
+            page_table_size: 2,
+            ..Default::default()
+        };
+        let context = VirtualTexturingContext::from_config(Arc::new(wgpu_context), config);
+        let source = Arc::new(CountingSource {
+            metadata: TextureMetadata::from_mip(1, 4).with_page_size(8, 2),
+            reads: AtomicUsize::new(0),
+        });
+        let streaming = StreamingHandle::new(
+            Arc::clone(&context.wgpu_context),
+            context.textures(),
+            Arc::clone(&source),
+        );
+        let page = PageId::new(0, 1, 0);
+        streaming.stream_page(page, (1, 0)).unwrap();
+        assert_eq!(
+            streaming
+                .refresh_pages(&[page, PageId::new(0, 0, 1)])
+                .unwrap(),
+            1
+        );
+        assert_eq!(source.reads.load(Ordering::Relaxed), 2);
+        assert_eq!(streaming.residency().slot(page), Some((1, 0)));
+    }
+
+    /// HDR pages are uploaded as half floats, or packed to RG11B10 floats.
+    #[test]
+    fn upload_hdr_pages() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let wgpu_context = Arc::new(wgpu_context);
+        // Every channel of the page is 1.0, which is 0x3C00 as a half float, and has the exponent
+        // 15 and no mantissa in the 11 and 10 bit floats.
+        let one = 0x3C00u16.to_le_bytes();
+        let packed_one = 0x3C0 | 0x3C0 << 11 | 0x1E0 << 22;
+        [
+            (false, wgpu::TextureFormat::Rgba16Float, [one; 4].concat()),
+            (
+                true,
+                wgpu::TextureFormat::Rg11b10Float,
+                u32::to_le_bytes(packed_one).to_vec(),
+            ),
+        ]
+        .into_iter()
+        .for_each(|(pack_hdr, format, texel)| {
+            let config = VirtualTexturingConfig {
+                page_size: 8,
+                border_size: 2,
+                page_table_size: 2,
+                physical_mip_levels: 2,
+                texel_format: TexelFormat::Rgba16Float,
+                pack_hdr,
+                debug_fill: true,
+                ..Default::default()
+            };
+            let StreamingFixture {
+                context,
+                streaming,
+                temp_dir: _temp_dir,
+                ..
+            } = StreamingFixture::new(
+                Arc::clone(&wgpu_context),
+                TextureMetadata::from_mip(0, 8).with_page_size(8, 2),
+                config,
+            );
+            let physical_texture = &context.textures.physical_textures[0];
+            assert_eq!(physical_texture.format(), format);
+            streaming.upload_page(PageId::new(0, 0, 0), (1, 0), &one.repeat(8 * 8 * 4));
+            streaming.flush_uploads();
+            assert_eq!(streaming.stats().uploaded_pages, 1);
+
+            let texels = read_texture(&context.wgpu_context, physical_texture, 0, 0).unwrap();
+            let texel_at = |x: usize, y: usize| {
+                let start = (y * physical_texture.width() as usize + x) * texel.len();
+                &texels[start..start + texel.len()]
+            };
+            assert_eq!((texel_at(8, 0), texel_at(15, 7)), (&texel[..], &texel[..]));
+        });
+    }
+
+    /// Single and two channel pages are uploaded to physical textures of as many channels.
+    #[test]
+    fn upload_narrow_pages() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let wgpu_context = Arc::new(wgpu_context);
+        [
+            (TexelFormat::R8, wgpu::TextureFormat::R8Unorm),
+            (TexelFormat::Rg8, wgpu::TextureFormat::Rg8Unorm),
+        ]
+        .into_iter()
+        .for_each(|(texel_format, format)| {
+            let config = VirtualTexturingConfig {
+                page_size: 8,
+                border_size: 2,
+                page_table_size: 2,
+                physical_mip_levels: 2,
+                texel_format,
+                debug_fill: true,
+                ..Default::default()
+            };
+            let StreamingFixture {
+                context,
+                streaming,
+                temp_dir: _temp_dir,
+                ..
+            } = StreamingFixture::new(
+                Arc::clone(&wgpu_context),
+                TextureMetadata::from_mip(0, texel_format.bytes_per_texel()).with_page_size(8, 2),
+                config,
+            );
+            let physical_texture = &context.textures.physical_textures[0];
+            assert_eq!(physical_texture.format(), format);
+            let channels = texel_format.channels();
+            let page = (0..8 * 8 * channels)
+                .map(|index| index as u8)
+                .collect::<Vec<_>>();
+            streaming.upload_page(PageId::new(0, 0, 0), (1, 0), &page);
+            streaming.flush_uploads();
+            assert_eq!(streaming.stats().uploaded_pages, 1);
+
+            let texels = read_texture(&context.wgpu_context, physical_texture, 0, 0).unwrap();
+            let row_len = physical_texture.width() as usize * channels;
+            (0..8).for_each(|y| {
+                let start = y * row_len + 8 * channels;
+                assert_eq!(
+                    texels[start..start + 8 * channels],
+                    page[y * 8 * channels..(y + 1) * 8 * channels]
+                );
+            });
+        });
+    }
+
+    /// Slots past the rows of an array layer go to the next ones.
+    #[test]
+    fn upload_to_array_layers() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let config = VirtualTexturingConfig {
+            page_size: 8,
+            border_size: 2,
+            page_table_size: 2,
+            physical_texture: PhysicalTextureConfig {
+                page_slots_x: 2,
+                page_slots_y: 2,
+                layers: 3,
+            },
+            ..Default::default()
+        };
+        let StreamingFixture {
+            context,
+            streaming,
+            temp_dir: _temp_dir,
+            ..
+        } = StreamingFixture::new(
+            wgpu_context,
+            TextureMetadata::from_mip(0, 4).with_page_size(8, 2),
+            config,
+        );
+        let textures = &context.textures;
+        assert_eq!(textures.slot_count(CacheTier::Cold), 12);
+        assert_eq!(textures.physical_textures[0].depth_or_array_layers(), 3);
+        assert_eq!(
+            textures.page_table_entry((1, 5), 0, 0, CacheTier::Cold),
+            [
+                1,
+                1,
+                2 << Textures::PAGE_TABLE_LAYER_SHIFT,
+                Textures::PAGE_TABLE_RESIDENT
+            ]
+        );
+        streaming.upload_page(PageId::new(0, 0, 0), (1, 5), &[200; 8 * 8 * 4]);
+        streaming.flush_uploads();
+        let texels =
+            read_texture(&context.wgpu_context, &textures.physical_textures[0], 2, 0).unwrap();
+        let texel = |x: usize, y: usize| texels[(y * 16 + x) * 4];
+        assert_eq!((texel(8, 8), texel(15, 15), texel(7, 8)), (200, 200, 0));
+    }
+
+    /// The pages of every registered virtual texture share the slots of the physical textures.
+    #[test]
+    fn register_virtual_textures() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let config = VirtualTexturingConfig {
+            page_size: 8,
+            border_size: 2,
+            page_table_size: 2,
+            virtual_textures: 2,
+            ..Default::default()
+        };
+        let StreamingFixture {
+            context,
+            mut streaming,
+            temp_dir,
+            ..
+        } = StreamingFixture::new(
+            wgpu_context,
+            TextureMetadata::from_mip(1, 4).with_page_size(8, 2),
+            config,
+        );
+        assert_eq!(context.textures.virtual_texture_count(), 2);
+        let decals = TextureStorage::new(
+            TextureMetadata::from_mip(0, 4).with_page_size(8, 2),
+            Some(temp_dir.path().join("decals").to_str().unwrap()),
+            None,
+        )
+        .unwrap();
+        assert_eq!(streaming.register_texture(decals.reader()), 1);
+        assert_eq!(streaming.texture_count(), 2);
+        assert_eq!(streaming.texture_metadata(1).mip_levels(), 0);
+        assert_eq!(streaming.metadata().mip_levels(), 1);
+
+        let page = [200; 8 * 8 * 4];
+        let decal_page = PageId::with_texture_id(1, 0, 0, 0);
+        streaming.upload_page(PageId::new(0, 0, 0), (0, 0), &page);
+        streaming.upload_page(decal_page, (1, 0), &page);
+        context.wgpu_context.device.poll(wgpu::Maintain::Wait);
+        let residency = streaming.residency();
+        assert_eq!(residency.slot(PageId::new(0, 0, 0)), Some((0, 0)));
+        assert_eq!(residency.slot(decal_page), Some((1, 0)));
+    }
+
+    /// Every entry of the page table samples the mip tail once it is preloaded.
+    #[test]
+    fn preload_mip_tail() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let config = VirtualTexturingConfig {
+            page_size: 8,
+            border_size: 2,
+            page_table_size: 4,
+            ..Default::default()
+        };
+        // 4x4 pages of 4 texels and a border of 2.
+        let StreamingFixture {
+            context,
+            streaming,
+            temp_dir: _temp_dir,
+            ..
+        } = StreamingFixture::filled(
+            wgpu_context,
+            TextureMetadata::from_mip(2, 4).with_page_size(8, 2),
+            config,
+            &[90; 4],
+        );
+        let textures = &context.textures;
+        let mut slots = SlotAllocator::with_layout(textures.slot_layout(CacheTier::Cold));
+        assert_eq!(streaming.preload_mip_tail(0, 1, &mut slots).unwrap(), 5);
+        assert_eq!(slots.used_slots(), 5);
+        let residency = streaming.residency();
+        assert_eq!(residency.slot(PageId::new(2, 0, 0)), Some((0, 0)));
+
+        let page_table = &textures.page_table_texture;
+        let entry = |mip, (x, y): (usize, usize)| {
+            let side = 4 >> mip;
+            let texels = read_texture(&context.wgpu_context, page_table, 0, mip).unwrap();
+            texels[(y * side as usize + x) * 4..][..4].to_vec()
+        };
+        let slot = residency.slot(PageId::new(1, 1, 1)).unwrap();
+        let fine_entry = textures.page_table_entry(slot, 1, 0, CacheTier::Cold);
+        assert_eq!(entry(0, (3, 3)), fine_entry);
+        assert_eq!(entry(1, (1, 1)), fine_entry);
+
+        // The page table is written after the copies of the pages mapped, when they are flushed.
+        let (page, slot) = (PageId::new(0, 3, 3), slots.allocate(0).unwrap());
+        streaming.stream_page(page, slot).unwrap();
+        streaming.map_page(page, slot);
+        assert_eq!(entry(0, (3, 3)), fine_entry);
+        streaming.flush_uploads();
+        let page_entry = textures.page_table_entry(slot, 0, 0, CacheTier::Cold);
+        assert_eq!(entry(0, (3, 3)), page_entry);
+        assert_eq!(streaming.evict_page(page), Some(slot));
+        assert_eq!(entry(0, (3, 3)), page_entry);
+        streaming.flush_uploads();
+        assert_eq!(entry(0, (3, 3)), fine_entry);
+    }
+
+    /// The pages of a texture with a page reader are read concurrently, within the budget, and
+    /// uploaded and mapped before `stream_queued` returns when their reads complete in time.
+    #[test]
+    fn stream_through_page_reader() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        // The budgets of the configuration of the context apply to the handles created from it.
+        let config = VirtualTexturingConfig {
+            page_size: 8,
+            border_size: 2,
+            page_table_size: 4,
+            streaming: StreamingConfig {
+                max_uploads_per_frame: 3,
+                max_io_micros: 10_000_000,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let StreamingFixture {
+            context,
+            storage,
+            mut streaming,
+            temp_dir: _temp_dir,
+        } = StreamingFixture::filled(
+            wgpu_context,
+            TextureMetadata::from_mip(2, 4).with_page_size(8, 2),
+            config,
+            &[90; 4],
+        );
+        streaming.set_page_reader(0, ThreadedPageReader::new(storage.reader(), 4));
+        let mut slots = SlotAllocator::with_layout(context.textures.slot_layout(CacheTier::Cold));
+        let pages = (0..4).map(|x| PageId::new(0, x, 0)).collect::<Vec<_>>();
+        streaming.queue_requests(&pages);
+        streaming.queue_requests(&[PageId::new(1, 5, 0)]);
+        let streamed = streaming.stream_queued(|_| slots.allocate(0));
+        assert_eq!(streamed.len(), 3);
+        let out_of_bounds = streamed
+            .iter()
+            .find(|streamed| streamed.page == PageId::new(1, 5, 0))
+            .unwrap();
+        assert!(out_of_bounds.result.is_err());
+        let residency = streaming.residency();
+        assert!(streamed
+            .iter()
+            .filter(|streamed| streamed.result.is_ok())
+            .all(|streamed| residency.slot(streamed.page) == Some(streamed.slot)));
+        assert_eq!(streaming.stats().uploaded_pages, 2);
+        assert_eq!(streaming.queued_requests(), 2);
+    }
+
+    /// A source reading slower than a frame.
+    struct SlowSource<S>(S);
+
+    impl<S: PageSource> PageSource for SlowSource<S> {
+        fn metadata(&self) -> &TextureMetadata {
+            self.0.metadata()
+        }
+
+        fn read_page(&self, page: PageId) -> Result<Vec<u8>, TextureStorageError> {
+            std::thread::sleep(Duration::from_millis(300));
+            self.0.read_page(page)
+        }
+    }
+
+    /// The reads of a slow page reader do not stall `stream_queued` past `max_io_time`, and are
+    /// uploaded by the later calls once they complete.
+    #[test]
+    fn slow_page_reader_does_not_stall() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let config = VirtualTexturingConfig {
+            page_size: 8,
+            border_size: 2,
+            page_table_size: 4,
+            ..Default::default()
+        };
+        let StreamingFixture {
+            context,
+            storage,
+            mut streaming,
+            temp_dir: _temp_dir,
+        } = StreamingFixture::filled(
+            wgpu_context,
+            TextureMetadata::from_mip(2, 4).with_page_size(8, 2),
+            config,
+            &[90; 4],
+        );
+        streaming.set_page_reader(0, ThreadedPageReader::new(SlowSource(storage.reader()), 2));
+        streaming.set_streaming_config(StreamingConfig {
+            max_io_micros: 5000,
+            ..Default::default()
+        });
+        let mut slots = SlotAllocator::with_layout(context.textures.slot_layout(CacheTier::Cold));
+        let pages = [PageId::new(0, 0, 0), PageId::new(0, 1, 0)];
+        streaming.queue_requests(&pages);
+
+        let start = std::time::Instant::now();
+        assert!(streaming.stream_queued(|_| slots.allocate(0)).is_empty());
+        assert!(start.elapsed() < Duration::from_millis(200));
+        assert_eq!(streaming.queued_requests(), 0);
+        assert_eq!(slots.used_slots(), 2);
+        // Queued again while in flight, without being submitted twice.
+        streaming.queue_requests(&pages);
+
+        let mut streamed = Vec::new();
+        while streamed.len() < pages.len() {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            std::thread::sleep(Duration::from_millis(20));
+            streamed.extend(streaming.stream_queued(|_| slots.allocate(0)));
+        }
+        assert_eq!(streamed.len(), pages.len());
+        assert_eq!(slots.used_slots(), 2);
+        let residency = streaming.residency();
+        assert!(streamed.iter().all(|streamed| streamed.result.is_ok()
+            && residency.slot(streamed.page) == Some(streamed.slot)));
+    }
+
+    /// The pages left over by the budget of a frame are streamed in by the next ones.
+    #[test]
+    fn budgeted_streaming() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let config = VirtualTexturingConfig {
+            page_size: 8,
+            border_size: 2,
+            page_table_size: 4,
+            ..Default::default()
+        };
+        let StreamingFixture {
+            context,
+            storage,
+            mut streaming,
+            temp_dir,
+        } = StreamingFixture::filled(
+            wgpu_context,
+            TextureMetadata::from_mip(2, 4).with_page_size(8, 2),
+            config,
+            &[90; 4],
+        );
+        let mut slots = SlotAllocator::with_layout(context.textures.slot_layout(CacheTier::Cold));
+        let mut stream_queued = |streaming: &StreamingHandle| {
+            streaming
+                .stream_queued(|_| slots.allocate(0))
+                .into_iter()
+                .map(|streamed| {
+                    streamed.result.unwrap();
+                    streamed.page
+                })
+                .collect::<Vec<_>>()
+        };
+
+        streaming.set_streaming_config(StreamingConfig {
+            max_uploads_per_frame: 2,
+            ..Default::default()
+        });
+        let fine = [PageId::new(0, 0, 0), PageId::new(0, 1, 0)];
+        streaming.queue_requests(&[fine[1], PageId::new(1, 0, 0), fine[0], PageId::new(2, 0, 0)]);
+        assert_eq!(
+            stream_queued(&streaming),
+            [PageId::new(2, 0, 0), PageId::new(1, 0, 0)]
+        );
+        assert_eq!(streaming.queued_requests(), 2);
+        assert_eq!(stream_queued(&streaming), fine);
+        assert!(streaming.residency().is_resident(fine[1]));
+
+        // A page is started while the bytes budget is not spent.
+        streaming.set_streaming_config(StreamingConfig {
+            max_bytes_per_frame: 1,
+            ..Default::default()
+        });
+        streaming.queue_requests(&[fine[0], PageId::new(0, 2, 0), PageId::new(0, 3, 0)]);
+        assert_eq!(stream_queued(&streaming), [PageId::new(0, 2, 0)]);
+        streaming.set_streaming_config(StreamingConfig {
+            max_io_micros: 0,
+            ..Default::default()
+        });
+        assert!(stream_queued(&streaming).is_empty());
+        assert_eq!(streaming.queued_requests(), 1);
+
+        // The prefetched pages out of the texture, resident or requested are not queued.
+        struct Fixed(Vec<PageId>);
+        impl PrefetchPolicy for Fixed {
+            fn prefetch(&mut self, _: &[PageId], _: &CameraMotion) -> Vec<PageId> {
+                self.0.clone()
+            }
+        }
+        let requested = PageId::new(0, 1, 1);
+        streaming.set_prefetch_policy(Fixed(vec![
+            PageId::new(0, 0, 1),
+            PageId::new(0, 4, 0),
+            PageId::new(3, 0, 0),
+            PageId::with_texture_id(1, 0, 0, 0),
+            fine[0],
+            requested,
+        ]));
+        assert_eq!(
+            streaming.queue_prefetch(&[requested], &CameraMotion::default()),
+            1
+        );
+        streaming.set_power_mode(PowerMode::LowPower);
+        assert_eq!(
+            streaming.queue_prefetch(&[requested], &CameraMotion::default()),
+            0
+        );
+
+        // A new handle is warmed up with the pages resident in the snapshot of this one.
+        let snapshot = temp_dir.path().join("residency.vtrs");
+        streaming.save_residency(&snapshot).unwrap();
+        let warm = StreamingHandle::new(
+            Arc::clone(&context.wgpu_context),
+            context.textures(),
+            storage.reader(),
+        );
+        assert_eq!(warm.load_residency(&snapshot).unwrap(), 5);
+        let mut slots = SlotAllocator::with_layout(context.textures.slot_layout(CacheTier::Cold));
+        warm.stream_queued(|_| slots.allocate(0));
+        let (resident, warmed) = (streaming.residency(), warm.residency());
+        assert_eq!(warmed.len(), 5);
+        assert!(resident.pages().all(|(page, _)| warmed.is_resident(page)));
+        assert_eq!(warm.load_residency(&snapshot).unwrap(), 0);
+        assert!(matches!(
+            warm.load_residency(temp_dir.path().join("missing.vtrs")),
+            Err(ResidencySnapshotError::IoError(_))
+        ));
+    }
+
+    #[test]
+    fn read_buffers_round_robin() {
+        let in_flight = [true, false, true];
+        assert_eq!(next_free_buffer(3, 0, |index| in_flight[index]), Some(1));
+        assert_eq!(next_free_buffer(3, 2, |index| in_flight[index]), Some(1));
+        assert_eq!(next_free_buffer(3, 0, |_| true), None);
+    }
+
+    /// Texels are read from storage, or from the CPU copies of the resident pages.
+    #[test]
+    fn sample_texels_on_cpu() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        // 2x2 pages of 6 texels and a border of 1, where texel (x, y) is [x, y, 0, 255].
+        let texels = (0..14 * 14)
+            .flat_map(|index| [index as u8 % 14, index as u8 / 14, 0, 255])
+            .collect::<Vec<_>>();
+        let config = VirtualTexturingConfig {
+            page_size: 8,
+            border_size: 1,
+            page_table_size: 2,
+            ..Default::default()
+        };
+        let StreamingFixture {
+            mut streaming,
+            temp_dir: _temp_dir,
+            ..
+        } = StreamingFixture::imported(
+            wgpu_context,
+            TextureMetadata::from_mip(1, 4).with_page_size(8, 1),
+            config,
+            image::imageops::FilterType::Nearest,
+            &texels,
+        );
+
+        assert_eq!(streaming.sample_cpu((0.0, 0.0), 0), Some([1, 1, 0, 255]));
+        assert_eq!(streaming.sample_cpu((0.99, 0.5), 0), Some([12, 7, 0, 255]));
+        assert_eq!(streaming.sample_cpu((2.0, -1.0), 0), Some([12, 1, 0, 255]));
+        assert!(streaming.sample_cpu((0.5, 0.5), 1).is_some());
+        assert_eq!(streaming.sample_cpu((0.5, 0.5), 2), None);
+        assert_eq!(streaming.sample_cpu((f32::NAN, 0.5), 0), None);
+
+        streaming.set_cpu_copies(true);
+        streaming.upload_page(PageId::new(0, 1, 0), (0, 0), &[200; 8 * 8 * 4]);
+        assert_eq!(streaming.sample_cpu((0.99, 0.0), 0), Some([200; 4]));
+        assert_eq!(streaming.sample_cpu((0.0, 0.0), 0), Some([1, 1, 0, 255]));
+    }
+
+    #[test]
+    fn report_streaming_events() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let StreamingFixture {
+            storage,
+            streaming,
+            temp_dir: _temp_dir,
+            ..
+        } = StreamingFixture::new(
+            wgpu_context,
+            TextureMetadata::from_mip(1, 4),
+            Default::default(),
+        );
+
+        let page_size = storage.metadata().page_size() as usize;
+        streaming.upload_page(
+            PageId::new(0, 1, 0),
+            (2, 0),
+            &vec![0; page_size * page_size * 4],
+        );
+        assert!(streaming.stream_page(PageId::new(0, 5, 5), (3, 0)).is_err());
+        let events = streaming.drain_events().collect::<Vec<_>>();
+        assert_eq!(
+            events[0],
+            StreamingEvent::PageLoaded {
+                page: PageId::new(0, 1, 0),
+                slot: (2, 0),
+                synthetic: false,
+            }
+        );
+        assert!(matches!(
+            events[1],
+            StreamingEvent::PageReadFailed { page, .. } if page == PageId::new(0, 5, 5)
+        ));
+        assert_eq!(streaming.drain_events().count(), 0);
+    }
+}
@@ -0,0 +1,166 @@
+//! Budgets capping the streaming work of a frame, and the queue of the requests carried over to
+//! the next frames once the budget is spent.
+
+use std::{collections::HashMap, time::Duration};
+
+use miniserde::{Deserialize, MiniSerialize};
+use vt_core::PageId;
+
+use crate::storage::TextureStorageError;
+
+/// How much streaming a frame does at most, see
+/// [`StreamingHandle::stream_queued`](crate::streaming::StreamingHandle::stream_queued).
+///
+/// A page is only started while the budget is not spent, so the last page of a frame can go
+/// over it. Stored in
+/// [`VirtualTexturingConfig::streaming`](crate::config::VirtualTexturingConfig::streaming).
+#[derive(MiniSerialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamingConfig {
+    /// The pages streamed in per frame, further capped by
+    /// [`StreamingHandle::upload_budget`](crate::streaming::StreamingHandle::upload_budget).
+    pub max_uploads_per_frame: u32,
+    /// The bytes written to the physical textures per frame, see
+    /// [`StreamingStats::uploaded_bytes`](crate::streaming::StreamingStats::uploaded_bytes).
+    pub max_bytes_per_frame: u64,
+    /// The time spent reading and uploading pages per frame in microseconds, see
+    /// [`StreamingConfig::max_io_time`].
+    pub max_io_micros: u64,
+}
+
+impl StreamingConfig {
+    /// The time spent reading and uploading pages per frame.
+    pub fn max_io_time(&self) -> Duration {
+        Duration::from_micros(self.max_io_micros)
+    }
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        Self {
+            max_uploads_per_frame: 64,
+            max_bytes_per_frame: 8 << 20,
+            max_io_micros: 4000,
+        }
+    }
+}
+
+/// A page streamed in by
+/// [`StreamingHandle::stream_queued`](crate::streaming::StreamingHandle::stream_queued).
+#[derive(Debug)]
+pub struct StreamedPage {
+    pub page: PageId,
+    /// The slot allocated to the page, to be freed if `result` is an error.
+    pub slot: (u32, u32),
+    pub result: Result<(), TextureStorageError>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct QueuedPage {
+    /// The frames the page waited for, raising its priority.
+    age: u32,
+    /// The frames since the page was last requested.
+    unrequested_frames: u32,
+    /// Queued by a [`PrefetchPolicy`](crate::streaming::PrefetchPolicy) and not requested.
+    prefetched: bool,
+}
+
+/// The pages requested and not streamed in yet, by priority: the coarse pages first, so that the
+/// finer ones never wait on the pages they fall back to, each frame waited counting as a mip
+/// level. The prefetched pages go after the requested ones, and are only kept for a frame.
+#[derive(Debug, Default)]
+pub(crate) struct RequestQueue {
+    pages: HashMap<PageId, QueuedPage>,
+}
+
+impl RequestQueue {
+    /// Queue `pages`, keeping the age of the ones already queued.
+    pub fn request(&mut self, pages: &[PageId]) {
+        pages.iter().for_each(|&page| {
+            let queued = self.pages.entry(page).or_default();
+            queued.unrequested_frames = 0;
+            queued.prefetched = false;
+        });
+    }
+
+    /// Queue `pages` after the requested ones, unless they are queued already.
+    pub fn prefetch(&mut self, pages: &[PageId]) {
+        pages.iter().for_each(|&page| {
+            self.pages.entry(page).or_insert(QueuedPage {
+                prefetched: true,
+                ..Default::default()
+            });
+        });
+    }
+
+    /// The queued pages, highest priority first.
+    pub fn by_priority(&self) -> Vec<PageId> {
+        let mut pages = self.pages.keys().copied().collect::<Vec<_>>();
+        pages.sort_unstable_by_key(|page| {
+            let queued = self.pages[page];
+            let priority = page.mip_level() as u32 + queued.age;
+            (
+                queued.prefetched,
+                std::cmp::Reverse(priority),
+                std::cmp::Reverse(page.mip_level()),
+                *page,
+            )
+        });
+        pages
+    }
+
+    pub fn remove(&mut self, page: PageId) {
+        self.pages.remove(&page);
+    }
+
+    /// Age the pages carried over to the next frame, dropping the ones not requested for more
+    /// than `keep_frames` frames.
+    pub fn carry_over(&mut self, keep_frames: u32) {
+        self.pages.retain(|_, page| {
+            page.age += 1;
+            page.unrequested_frames += 1;
+            page.unrequested_frames <= keep_frames && !page.prefetched
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use vt_core::PageId;
+
+    use super::RequestQueue;
+
+    /// Coarse pages go first, unless the fine ones were carried over for longer.
+    #[test]
+    fn age_carried_over_requests() {
+        let mut queue = RequestQueue::default();
+        let (fine, coarse) = (PageId::new(0, 1, 1), PageId::new(2, 0, 0));
+        queue.request(&[fine, coarse]);
+        assert_eq!(queue.by_priority(), [coarse, fine]);
+        queue.remove(coarse);
+        (0..3).for_each(|_| {
+            queue.carry_over(2);
+            queue.request(&[fine]);
+        });
+        queue.request(&[coarse]);
+        assert_eq!(queue.by_priority(), [fine, coarse]);
+
+        // Pages not requested again are dropped after `keep_frames` frames.
+        queue.carry_over(2);
+        queue.carry_over(2);
+        assert_eq!(queue.len(), 2);
+        queue.carry_over(2);
+        assert_eq!(queue.len(), 0);
+
+        // Prefetched pages go last, and are dropped at the end of the frame unless requested.
+        let prefetched = PageId::new(3, 0, 0);
+        queue.prefetch(&[prefetched, coarse]);
+        queue.request(&[fine, coarse]);
+        assert_eq!(queue.by_priority(), [coarse, fine, prefetched]);
+        queue.carry_over(2);
+        assert_eq!(queue.by_priority(), [coarse, fine]);
+    }
+}
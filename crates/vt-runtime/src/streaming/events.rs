@@ -0,0 +1,93 @@
+//! Events of the streaming, for the application to report what goes wrong in the background.
+
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
+
+use crate::streaming::PageId;
+
+/// How much attention a [`StreamingEvent`] needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    /// The streaming keeps up, but the rendering is degraded.
+    Warning,
+    /// Some data could not be streamed in.
+    Error,
+}
+
+/// An event of the streaming, see [`StreamingHandle::drain_events`](crate::streaming::StreamingHandle::drain_events).
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamingEvent {
+    /// A page was written to `slot`, upsampled from an ancestor if `synthetic` (see
+    /// [`StreamingHandle::set_overzoom`](crate::streaming::StreamingHandle::set_overzoom)).
+    PageLoaded {
+        page: PageId,
+        slot: (u32, u32),
+        synthetic: bool,
+    },
+    /// A page could not be read from storage or decompressed.
+    PageReadFailed { page: PageId, error: String },
+    /// The feedback of a frame could not be read back.
+    FeedbackReadbackFailed { error: String },
+    /// A frame requested `dropped` distinct pages past
+    /// [`Pipelines::MAX_FEEDBACK_REQUESTS`](crate::pipelines::Pipelines::MAX_FEEDBACK_REQUESTS),
+    /// which are requested again by the next frames.
+    FeedbackRequestsDropped { dropped: u32 },
+    /// A frame requested more pages than the physical textures have slots, so visible pages
+    /// have to be evicted.
+    CachePressure { requested: usize, slots: u32 },
+}
+
+impl StreamingEvent {
+    pub fn severity(&self) -> Severity {
+        match self {
+            StreamingEvent::PageLoaded { .. } => Severity::Info,
+            StreamingEvent::FeedbackRequestsDropped { .. }
+            | StreamingEvent::CachePressure { .. } => Severity::Warning,
+            StreamingEvent::PageReadFailed { .. }
+            | StreamingEvent::FeedbackReadbackFailed { .. } => Severity::Error,
+        }
+    }
+}
+
+/// A bounded channel of events, shared by the handle and the streaming thread.
+///
+/// Events are dropped while the channel is full, so that an application that never drains them
+/// does not grow it without bounds.
+pub(super) fn channel() -> (EventSender, Receiver<StreamingEvent>) {
+    let (sender, receiver) = std::sync::mpsc::sync_channel(EventSender::CAPACITY);
+    (EventSender(sender), receiver)
+}
+
+#[derive(Clone)]
+pub(super) struct EventSender(SyncSender<StreamingEvent>);
+
+impl EventSender {
+    /// The events kept until they are drained.
+    const CAPACITY: usize = 1024;
+
+    pub(super) fn send(&self, event: StreamingEvent) {
+        match self.0.try_send(event) {
+            Ok(()) | Err(TrySendError::Disconnected(_)) => (),
+            Err(TrySendError::Full(event)) => log::debug!("streaming event dropped: {event:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{channel, EventSender, Severity, StreamingEvent};
+
+    #[test]
+    fn full_channel_drops_events() {
+        let (sender, receiver) = channel();
+        (0..EventSender::CAPACITY + 10).for_each(|dropped| {
+            sender.send(StreamingEvent::FeedbackRequestsDropped {
+                dropped: dropped as u32,
+            })
+        });
+        let events = receiver.try_iter().collect::<Vec<_>>();
+        assert_eq!(events.len(), EventSender::CAPACITY);
+        assert_eq!(events[0].severity(), Severity::Warning);
+        assert!(Severity::Error > Severity::Warning);
+    }
+}
@@ -0,0 +1,887 @@
+//! Generation of the ancestor pages of content written at runtime on the GPU, see
+//! [`StreamingHandle::generate_ancestor_mips`].
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use thiserror::Error;
+use wgpu::util::DeviceExt;
+
+use super::{PageId, SlotAllocator, StreamingEvent, StreamingHandle};
+use crate::{
+    setup::WgpuContext,
+    storage::{encode_page, ColorSpace},
+    textures::{CacheTier, VirtualTextureId},
+};
+
+/// Mirrors `MipGenerationUniforms` in `mip_generation.wgsl`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct MipGenerationUniforms {
+    page_stride: u32,
+    border_size: u32,
+    slot_size: u32,
+    srgb: u32,
+    decode_loads: u32,
+    _padding: u32,
+    extent: [u32; 2],
+    page_words: u32,
+    level: u32,
+    source_offset: u32,
+    source_row: u32,
+    target_offset: u32,
+    target_row: u32,
+}
+
+/// Mirrors `MipGenerationJob` in `mip_generation.wgsl`: the page generated, its current slot and
+/// the slots of its children and of the children of its neighbours.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct MipGenerationJob {
+    page: [u32; 4],
+    parent: [u32; 4],
+    children: [[u32; 4]; 16],
+}
+
+/// The pipelines of `mip_generation.wgsl`, created on the first call to
+/// [`StreamingHandle::generate_ancestor_mips`].
+pub(super) struct MipGenerationPipelines {
+    generate_pipeline: wgpu::ComputePipeline,
+    downsample_pipeline: wgpu::ComputePipeline,
+}
+
+impl MipGenerationPipelines {
+    const WORKGROUP_SIZE: u32 = 8;
+    /// Set in the `w` component of the slots of the jobs.
+    const SLOT_RESIDENT: u32 = 1;
+    const SLOT_HOT: u32 = 2;
+
+    fn new(context: &WgpuContext) -> Self {
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2Array,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let bind_group_layout = context.resources.bind_group_layout(
+            &context.device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("mip generation bind group layout"),
+                entries: &[
+                    buffer_entry(0, wgpu::BufferBindingType::Uniform),
+                    buffer_entry(1, wgpu::BufferBindingType::Storage { read_only: true }),
+                    buffer_entry(2, wgpu::BufferBindingType::Storage { read_only: false }),
+                    texture_entry(3),
+                    texture_entry(4),
+                ],
+            },
+        );
+        let pipeline_layout =
+            context
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("mip generation pipeline layout"),
+                    bind_group_layouts: &[&bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+        let shader = context
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("mip generation shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("../mip_generation.wgsl").into()),
+            });
+        let pipeline = |label, entry_point| {
+            context
+                .device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+        };
+        Self {
+            generate_pipeline: pipeline("mip generation pipeline", "cs_generate_page"),
+            downsample_pipeline: pipeline("slot mip generation pipeline", "cs_downsample_slot"),
+        }
+    }
+}
+
+/// A page generated by [`StreamingHandle::generate_ancestor_mips`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratedPage {
+    pub page: PageId,
+    /// The slot of the cold tier the page was written to.
+    pub slot: (u32, u32),
+    /// The page in the layout of the pages of its source, every layer encoded like the source,
+    /// to be inserted into a [`RuntimePageSource`](crate::storage::RuntimePageSource) so that it
+    /// is streamed in again once evicted.
+    pub data: Vec<u8>,
+}
+
+/// The errors of [`StreamingHandle::generate_ancestor_mips`].
+#[derive(Error, Debug)]
+pub enum MipGenerationError {
+    #[error("the pages cannot be generated to physical textures of {0:?}, only RGBA8 ones")]
+    UnsupportedFormat(wgpu::TextureFormat),
+    #[error(
+        "borders of {border_size} texels are wider than half of the page stride of {page_stride}"
+    )]
+    BorderTooWide { border_size: u32, page_stride: u32 },
+    #[error("mip level {0} goes to the hot tier, the pages are only generated to the cold one")]
+    HotTier(u8),
+    #[error("no slot is free for {0:?}")]
+    NoFreeSlot(PageId),
+    #[error("could not read the generated pages back: {0}")]
+    Readback(#[from] wgpu::BufferAsyncError),
+}
+
+/// The offset and row length in words of every level of the slot mip chain of a page in the
+/// output of the shader, and the words of the whole chain.
+fn output_levels(slot_size: u32, levels: u32) -> (Vec<(u32, u32)>, u32) {
+    let mut words = 0;
+    let levels = (0..levels)
+        .map(|level| {
+            let size = slot_size >> level;
+            let row = size.next_multiple_of(
+                wgpu::COPY_BYTES_PER_ROW_ALIGNMENT / std::mem::size_of::<u32>() as u32,
+            );
+            let offset = words;
+            words += row * size;
+            (offset, row)
+        })
+        .collect();
+    (levels, words)
+}
+
+impl StreamingHandle {
+    /// Generate the ancestors of `pages`, written at runtime without their coarser mip levels
+    /// (e.g., decal bakes and user images uploaded with [`StreamingHandle::upload_page`]), up to
+    /// `last_mip`, by averaging their resident children on the GPU. Blocks until they are
+    /// generated and read back.
+    ///
+    /// The generated pages are written to their slot if they are resident, or to a slot of the
+    /// cold tier allocated from `slots` otherwise, recorded in the residency map and mapped by
+    /// the page table, so that the content takes part in the mip selection like streamed pages.
+    /// Their borders are averaged from the children of the neighbouring pages, clamped to the
+    /// children of the page where they are not resident. The quarters of a page whose children
+    /// are not resident keep its current content, or are transparent.
+    ///
+    /// Returns the generated pages, finest first, to be written to the source of the texture, see
+    /// [`RuntimePageSource`](crate::storage::RuntimePageSource). The pages are only generated up
+    /// to the first mip level of coarse pages.
+    ///
+    /// ### Errors
+    ///
+    /// - [`MipGenerationError::UnsupportedFormat`] for physical textures that are not
+    ///   `Rgba8Unorm` or `Rgba8UnormSrgb`, such as block compressed ones.
+    /// - [`MipGenerationError::BorderTooWide`] if the border of the pages is wider than half of
+    ///   their stride.
+    /// - [`MipGenerationError::HotTier`] if the generated mip levels go to the hot tier (see
+    ///   [`Textures::cache_tier`]).
+    /// - [`MipGenerationError::NoFreeSlot`] if `slots` runs out, the slots allocated so far
+    ///   being freed.
+    ///
+    /// [`Textures::cache_tier`]: crate::textures::Textures::cache_tier
+    pub fn generate_ancestor_mips(
+        &self,
+        pages: &[PageId],
+        last_mip: u8,
+        slots: &mut SlotAllocator,
+    ) -> Result<Vec<GeneratedPage>, MipGenerationError> {
+        // The pages are downsampled from the physical textures, their staged uploads included.
+        self.flush_uploads();
+        let mut textures = BTreeMap::<VirtualTextureId, BTreeSet<PageId>>::new();
+        pages.iter().for_each(|&page| {
+            textures.entry(page.texture_id()).or_default().insert(page);
+        });
+        textures
+            .into_iter()
+            .try_fold(Vec::new(), |mut generated, (texture_id, pages)| {
+                generated.extend(self.generate_texture_mips(texture_id, pages, last_mip, slots)?);
+                Ok(generated)
+            })
+    }
+
+    fn generate_texture_mips(
+        &self,
+        texture_id: VirtualTextureId,
+        pages: BTreeSet<PageId>,
+        last_mip: u8,
+        slots: &mut SlotAllocator,
+    ) -> Result<Vec<GeneratedPage>, MipGenerationError> {
+        let textures = &self.textures;
+        if let Some(format) = [CacheTier::Cold, CacheTier::Hot]
+            .iter()
+            .flat_map(|&tier| textures.tier_textures(tier))
+            .map(wgpu::Texture::format)
+            .find(|&format| format.remove_srgb_suffix() != wgpu::TextureFormat::Rgba8Unorm)
+        {
+            return Err(MipGenerationError::UnsupportedFormat(format));
+        }
+        let metadata = self.texture_metadata(texture_id);
+        let (slot_size, border_size) = (textures.page_size, textures.border_size);
+        let page_stride = metadata.page_stride() as u32;
+        if 2 * border_size > page_stride {
+            return Err(MipGenerationError::BorderTooWide {
+                border_size,
+                page_stride,
+            });
+        }
+        let Some(first_mip) = pages.iter().map(PageId::mip_level).min() else {
+            return Ok(Vec::new());
+        };
+        let last_mip = last_mip.min(metadata.mip_levels());
+        if first_mip >= last_mip || metadata.page_scale(first_mip + 1) != 0 {
+            return Ok(Vec::new());
+        }
+        if textures.cache_tier(first_mip + 1) == CacheTier::Hot {
+            return Err(MipGenerationError::HotTier(first_mip + 1));
+        }
+
+        let context = &self.context;
+        let pipelines = self
+            .mip_generation
+            .get_or_init(|| MipGenerationPipelines::new(context));
+        let layers = metadata.layers();
+        let (levels, page_words) = output_levels(slot_size, textures.physical_mip_levels);
+        let views = [CacheTier::Cold, CacheTier::Hot].map(|tier| {
+            let tier_textures = match textures.tier_textures(tier) {
+                [] => textures.tier_textures(CacheTier::Cold),
+                tier_textures => tier_textures,
+            };
+            tier_textures
+                .iter()
+                .map(|texture| {
+                    texture.create_view(&wgpu::TextureViewDescriptor {
+                        label: Some("mip generation physical texture view"),
+                        dimension: Some(wgpu::TextureViewDimension::D2Array),
+                        base_mip_level: 0,
+                        mip_level_count: Some(1),
+                        ..Default::default()
+                    })
+                })
+                .collect::<Vec<_>>()
+        });
+        let slot_origin = |tier, slot| {
+            let (x, y, layer) = textures.slot_location(tier, slot);
+            let hot = match tier {
+                CacheTier::Hot => MipGenerationPipelines::SLOT_HOT,
+                CacheTier::Cold => 0,
+            };
+            [
+                x * slot_size,
+                y * slot_size,
+                layer,
+                MipGenerationPipelines::SLOT_RESIDENT | hot,
+            ]
+        };
+
+        let mut command_encoder =
+            context
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("mip generation"),
+                });
+        // The pages generated by every level with their slot and output buffers, one per layer.
+        let mut outputs = Vec::<(Vec<(PageId, (u32, u32))>, Vec<wgpu::Buffer>)>::new();
+        // The slots of the pages generated so far, read as children by the next levels, and only
+        // inserted into the residency map once their work is submitted and read back.
+        let mut generated_slots = HashMap::<PageId, (u32, u32)>::new();
+        // The slots allocated from `slots`, freed if the generation fails.
+        let mut allocated = Vec::new();
+        let free_allocated = |slots: &mut SlotAllocator, allocated: &[(u32, u32)]| {
+            allocated.iter().for_each(|&slot| slots.free(slot, 0));
+        };
+        let mut previous = pages
+            .iter()
+            .filter(|page| page.mip_level() == first_mip)
+            .copied()
+            .collect::<BTreeSet<_>>();
+        for mip in first_mip + 1..=last_mip {
+            if metadata.page_scale(mip) != 0 {
+                break;
+            }
+            let parents = previous
+                .iter()
+                .filter_map(PageId::parent)
+                .collect::<BTreeSet<_>>();
+            let child_tier = textures.cache_tier(mip - 1);
+            let (grid_width, grid_height) = metadata.page_grid(mip - 1);
+            let mut generated = Vec::new();
+            let jobs = parents
+                .iter()
+                .map(|&parent| {
+                    let residency = self.residency.read().unwrap();
+                    let current_slot = residency.slot(parent);
+                    let children = std::array::from_fn(|index| {
+                        let (x, y) = (
+                            (2 * parent.x() as i32 - 1 + index as i32 % 4),
+                            (2 * parent.y() as i32 - 1 + index as i32 / 4),
+                        );
+                        if x < 0 || y < 0 || x >= grid_width as i32 || y >= grid_height as i32 {
+                            return [0; 4];
+                        }
+                        let child =
+                            PageId::with_texture_id(texture_id, mip - 1, x as u16, y as u16);
+                        generated_slots
+                            .get(&child)
+                            .copied()
+                            .or_else(|| residency.slot(child))
+                            .map_or([0; 4], |slot| slot_origin(child_tier, slot))
+                    });
+                    drop(residency);
+                    let slot = match current_slot {
+                        Some(slot) => slot,
+                        None => {
+                            let slot = slots
+                                .allocate(0)
+                                .ok_or(MipGenerationError::NoFreeSlot(parent))?;
+                            allocated.push(slot);
+                            slot
+                        }
+                    };
+                    generated.push((parent, slot));
+                    Ok(MipGenerationJob {
+                        page: [parent.x() as u32, parent.y() as u32, 0, 0],
+                        parent: current_slot
+                            .map_or([0; 4], |slot| slot_origin(CacheTier::Cold, slot)),
+                        children,
+                    })
+                })
+                .collect::<Result<Vec<_>, MipGenerationError>>();
+            let jobs = match jobs {
+                Ok(jobs) => jobs,
+                Err(error) => {
+                    free_allocated(slots, &allocated);
+                    return Err(error);
+                }
+            };
+
+            let jobs_buffer =
+                context
+                    .device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("mip generation jobs buffer"),
+                        contents: bytemuck::cast_slice(&jobs),
+                        usage: wgpu::BufferUsages::STORAGE,
+                    });
+            let (width, height) = metadata.mip_dimensions(mip - 1);
+            let level_buffers = layers
+                .iter()
+                .enumerate()
+                .map(|(layer_index, layer)| {
+                    let output = context.device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("mip generation output buffer"),
+                        size: (jobs.len() as u32 * page_words * 4) as u64,
+                        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                        mapped_at_creation: false,
+                    });
+                    let uniforms = MipGenerationUniforms {
+                        page_stride,
+                        border_size,
+                        slot_size,
+                        srgb: (layer.color_space() == ColorSpace::Srgb) as u32,
+                        // Without view formats, sRGB layers are in the sRGB format.
+                        decode_loads: (layer.color_space() == ColorSpace::Srgb
+                            && !textures.physical_textures[layer_index].format().is_srgb())
+                            as u32,
+                        extent: [width as u32 * page_stride, height as u32 * page_stride],
+                        page_words,
+                        target_row: levels[0].1,
+                        ..Default::default()
+                    };
+                    let bind_group = |uniforms: MipGenerationUniforms| {
+                        let uniforms_buffer =
+                            context
+                                .device
+                                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                                    label: Some("mip generation uniforms buffer"),
+                                    contents: bytemuck::bytes_of(&uniforms),
+                                    usage: wgpu::BufferUsages::UNIFORM,
+                                });
+                        context
+                            .device
+                            .create_bind_group(&wgpu::BindGroupDescriptor {
+                                label: Some("mip generation bind group"),
+                                layout: &pipelines.generate_pipeline.get_bind_group_layout(0),
+                                entries: &[
+                                    wgpu::BindGroupEntry {
+                                        binding: 0,
+                                        resource: uniforms_buffer.as_entire_binding(),
+                                    },
+                                    wgpu::BindGroupEntry {
+                                        binding: 1,
+                                        resource: jobs_buffer.as_entire_binding(),
+                                    },
+                                    wgpu::BindGroupEntry {
+                                        binding: 2,
+                                        resource: output.as_entire_binding(),
+                                    },
+                                    wgpu::BindGroupEntry {
+                                        binding: 3,
+                                        resource: wgpu::BindingResource::TextureView(
+                                            &views[0][layer_index],
+                                        ),
+                                    },
+                                    wgpu::BindGroupEntry {
+                                        binding: 4,
+                                        resource: wgpu::BindingResource::TextureView(
+                                            &views[1][layer_index],
+                                        ),
+                                    },
+                                ],
+                            })
+                    };
+
+                    let generate_bind_group = bind_group(uniforms);
+                    let downsample_bind_groups = (1..levels.len())
+                        .map(|level| {
+                            bind_group(MipGenerationUniforms {
+                                level: level as u32,
+                                source_offset: levels[level - 1].0,
+                                source_row: levels[level - 1].1,
+                                target_offset: levels[level].0,
+                                target_row: levels[level].1,
+                                ..uniforms
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    let workgroups =
+                        |size: u32| size.div_ceil(MipGenerationPipelines::WORKGROUP_SIZE);
+                    let mut compute_pass =
+                        command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                            label: Some("mip generation pass"),
+                            timestamp_writes: None,
+                        });
+                    compute_pass.set_pipeline(&pipelines.generate_pipeline);
+                    compute_pass.set_bind_group(0, &generate_bind_group, &[]);
+                    compute_pass.dispatch_workgroups(
+                        workgroups(slot_size),
+                        workgroups(slot_size),
+                        jobs.len() as u32,
+                    );
+                    compute_pass.set_pipeline(&pipelines.downsample_pipeline);
+                    downsample_bind_groups
+                        .iter()
+                        .enumerate()
+                        .for_each(|(index, bind_group)| {
+                            let size = slot_size >> (index + 1);
+                            compute_pass.set_bind_group(0, bind_group, &[]);
+                            compute_pass.dispatch_workgroups(
+                                workgroups(size),
+                                workgroups(size),
+                                jobs.len() as u32,
+                            );
+                        });
+                    drop(compute_pass);
+
+                    // Written before the next level reads the pages as children.
+                    let physical_texture = &textures.physical_textures[layer_index];
+                    generated.iter().enumerate().for_each(|(job, &(_, slot))| {
+                        let (x, y, array_layer) = textures.slot_location(CacheTier::Cold, slot);
+                        levels
+                            .iter()
+                            .enumerate()
+                            .for_each(|(level, &(offset, row))| {
+                                let size = slot_size >> level;
+                                command_encoder.copy_buffer_to_texture(
+                                    wgpu::ImageCopyBuffer {
+                                        buffer: &output,
+                                        layout: wgpu::ImageDataLayout {
+                                            offset: ((job as u32 * page_words + offset) * 4) as u64,
+                                            bytes_per_row: Some(row * 4),
+                                            rows_per_image: None,
+                                        },
+                                    },
+                                    wgpu::ImageCopyTexture {
+                                        texture: physical_texture,
+                                        mip_level: level as u32,
+                                        origin: wgpu::Origin3d {
+                                            x: (x * slot_size) >> level,
+                                            y: (y * slot_size) >> level,
+                                            z: array_layer,
+                                        },
+                                        aspect: wgpu::TextureAspect::All,
+                                    },
+                                    wgpu::Extent3d {
+                                        width: size,
+                                        height: size,
+                                        depth_or_array_layers: 1,
+                                    },
+                                );
+                            });
+                    });
+                    output
+                })
+                .collect::<Vec<_>>();
+
+            generated_slots.extend(generated.iter().copied());
+            outputs.push((generated, level_buffers));
+            previous = parents;
+            previous.extend(pages.iter().filter(|page| page.mip_level() == mip));
+        }
+
+        // Read back the mip level 0 of the slots, to be written to the source.
+        let read_buffers = outputs
+            .iter()
+            .map(|(_, level_buffers)| {
+                level_buffers
+                    .iter()
+                    .map(|output| {
+                        let read_buffer = context.device.create_buffer(&wgpu::BufferDescriptor {
+                            label: Some("mip generation read buffer"),
+                            size: output.size(),
+                            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                            mapped_at_creation: false,
+                        });
+                        command_encoder.copy_buffer_to_buffer(
+                            output,
+                            0,
+                            &read_buffer,
+                            0,
+                            output.size(),
+                        );
+                        read_buffer
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        context.queue.submit(Some(command_encoder.finish()));
+        let mapped = Arc::new(AtomicBool::new(true));
+        read_buffers.iter().flatten().for_each(|read_buffer| {
+            let mapped = Arc::clone(&mapped);
+            read_buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    if result.is_err() {
+                        mapped.store(false, Ordering::Release);
+                    }
+                });
+        });
+        context.device.poll(wgpu::Maintain::Wait);
+        if !mapped.load(Ordering::Acquire) {
+            free_allocated(slots, &allocated);
+            return Err(MipGenerationError::Readback(wgpu::BufferAsyncError));
+        }
+        let mut residency = self.residency.write().unwrap();
+        generated_slots.iter().for_each(|(&page, &slot)| {
+            residency.insert(page, slot);
+        });
+        drop(residency);
+
+        let uploaded_bytes = levels
+            .iter()
+            .enumerate()
+            .map(|(level, _)| ((slot_size >> level) * (slot_size >> level) * 4) as u64)
+            .sum::<u64>()
+            * layers.len() as u64;
+        let generated = outputs
+            .iter()
+            .zip(&read_buffers)
+            .flat_map(|((generated, _), read_buffers)| {
+                let layer_words = read_buffers
+                    .iter()
+                    .map(|read_buffer| {
+                        let words = bytemuck::cast_slice::<_, u32>(
+                            &read_buffer.slice(..).get_mapped_range(),
+                        )
+                        .to_vec();
+                        read_buffer.unmap();
+                        words
+                    })
+                    .collect::<Vec<_>>();
+                generated
+                    .iter()
+                    .enumerate()
+                    .map(|(job, &(page, slot))| {
+                        let mip = page.mip_level();
+                        let page_size = metadata.page_size_at(mip) as u32;
+                        let inset = metadata.border_inset(mip) as u32;
+                        let data = layers
+                            .iter()
+                            .zip(&layer_words)
+                            .flat_map(|(layer, words)| {
+                                let texels = (0..page_size * page_size)
+                                    .flat_map(|index| {
+                                        let (x, y) =
+                                            (index % page_size + inset, index / page_size + inset);
+                                        words[(job as u32 * page_words + y * levels[0].1 + x)
+                                            as usize]
+                                            .to_le_bytes()
+                                    })
+                                    .collect::<Vec<_>>();
+                                encode_page(layer.encoding, &texels, page_size as usize)
+                            })
+                            .collect();
+                        GeneratedPage { page, slot, data }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        generated.iter().for_each(|generated| {
+            self.map_page(generated.page, generated.slot);
+            self.counters.uploaded_pages.fetch_add(1, Ordering::Relaxed);
+            self.counters
+                .uploaded_bytes
+                .fetch_add(uploaded_bytes, Ordering::Relaxed);
+            self.events.send(StreamingEvent::PageLoaded {
+                page: generated.page,
+                slot: generated.slot,
+                synthetic: false,
+            });
+        });
+        Ok(generated)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use assert_fs::fixture::TempDir;
+
+    use super::{output_levels, MipGenerationError};
+    use crate::{
+        config::{HotCacheConfig, PhysicalTextureConfig, VirtualTexturingConfig},
+        debug::read_texture,
+        setup::VirtualTexturingContext,
+        storage::{PageSource, RuntimePageSource, TextureMetadata, TextureStorage},
+        streaming::{PageId, SlotAllocator, StreamingHandle},
+        textures::CacheTier,
+    };
+
+    #[test]
+    fn output_rows_are_aligned() {
+        let (levels, words) = output_levels(136, 3);
+        assert_eq!(
+            levels,
+            [(0, 192), (192 * 136, 128), (192 * 136 + 128 * 68, 64)]
+        );
+        assert_eq!(words, 192 * 136 + 128 * 68 + 64 * 34);
+    }
+
+    /// The parent of four pages written at runtime averages them, its borders clamped to them at
+    /// the edges of the texture, and is mapped and streamed in again from the runtime source.
+    #[test]
+    fn generate_ancestor_mips() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let temp_dir = TempDir::new().unwrap();
+        // 2x2 pages of 4 texels and a border of 2.
+        let storage = TextureStorage::new(
+            TextureMetadata::from_mip(1, 4).with_page_size(8, 2),
+            Some(temp_dir.path().to_str().unwrap()),
+            None,
+        )
+        .unwrap();
+        let config = VirtualTexturingConfig {
+            page_size: 8,
+            border_size: 2,
+            page_table_size: 2,
+            physical_mip_levels: 2,
+            // Single layer textures are not arrays on GL, where array views of them read zeros.
+            physical_texture: PhysicalTextureConfig {
+                page_slots_x: 4,
+                page_slots_y: 4,
+                layers: 2,
+            },
+            ..Default::default()
+        };
+        let context = VirtualTexturingContext::from_config(Arc::new(wgpu_context), config);
+        let textures = &context.textures;
+        let source = Arc::new(RuntimePageSource::new(storage.reader()));
+        let streaming = StreamingHandle::new(
+            Arc::clone(&context.wgpu_context),
+            context.textures(),
+            Arc::clone(&source),
+        );
+        let mut slots = SlotAllocator::with_layout(textures.slot_layout(CacheTier::Cold));
+        let colors = [
+            [255, 0, 0, 255],
+            [0, 255, 0, 255],
+            [0, 0, 255, 255],
+            [255; 4],
+        ];
+        let pages = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(x, y)| PageId::new(0, x, y));
+        pages.iter().zip(colors).for_each(|(&page, color)| {
+            let data = color.repeat(8 * 8);
+            streaming.upload_page(page, slots.allocate(0).unwrap(), &data);
+            source.insert_page(page, data);
+        });
+
+        let generated = streaming
+            .generate_ancestor_mips(&pages, 1, &mut slots)
+            .unwrap();
+        assert_eq!(generated.len(), 1);
+        let parent = &generated[0];
+        assert_eq!(parent.page, PageId::new(1, 0, 0));
+        let texel = |x: usize, y: usize| &parent.data[(y * 8 + x) * 4..][..4];
+        assert_eq!(texel(2, 2), colors[0]);
+        assert_eq!(texel(5, 2), colors[1]);
+        assert_eq!(texel(2, 5), colors[2]);
+        assert_eq!(texel(7, 7), colors[3]);
+        assert_eq!(texel(0, 0), colors[0]);
+        assert_eq!(streaming.residency().slot(parent.page), Some(parent.slot));
+
+        let (x, y, layer) = textures.slot_location(CacheTier::Cold, parent.slot);
+        let physical = read_texture(
+            &context.wgpu_context,
+            &textures.physical_textures[0],
+            layer,
+            0,
+        )
+        .unwrap();
+        let width = textures.physical_textures[0].width() as usize;
+        let physical_texel = |tx: u32, ty: u32| {
+            let index = ((y * 8 + ty) as usize * width + (x * 8 + tx) as usize) * 4;
+            &physical[index..index + 4]
+        };
+        assert_eq!(physical_texel(5, 5), colors[3]);
+        let level = read_texture(
+            &context.wgpu_context,
+            &textures.physical_textures[0],
+            layer,
+            1,
+        )
+        .unwrap();
+        let index = ((y * 4 + 1) as usize * width / 2 + (x * 4 + 1) as usize) * 4;
+        assert_eq!(level[index..index + 4], colors[0]);
+
+        source.insert_page(parent.page, parent.data.clone());
+        assert_eq!(source.read_page(parent.page).unwrap(), parent.data);
+    }
+
+    /// Running out of slots partway through the mip chain frees the slots already allocated, and
+    /// leaves the residency map untouched.
+    #[test]
+    fn roll_back_without_free_slots() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let temp_dir = TempDir::new().unwrap();
+        // 4x4 pages of 4 texels and a border of 2.
+        let storage = TextureStorage::new(
+            TextureMetadata::from_mip(2, 4).with_page_size(8, 2),
+            Some(temp_dir.path().to_str().unwrap()),
+            None,
+        )
+        .unwrap();
+        let config = VirtualTexturingConfig {
+            page_size: 8,
+            border_size: 2,
+            page_table_size: 4,
+            physical_mip_levels: 2,
+            physical_texture: PhysicalTextureConfig {
+                page_slots_x: 4,
+                page_slots_y: 4,
+                layers: 2,
+            },
+            ..Default::default()
+        };
+        let context = VirtualTexturingContext::from_config(Arc::new(wgpu_context), config);
+        let source = Arc::new(RuntimePageSource::new(storage.reader()));
+        let streaming = StreamingHandle::new(
+            Arc::clone(&context.wgpu_context),
+            context.textures(),
+            Arc::clone(&source),
+        );
+        let mut slots = SlotAllocator::with_layout(context.textures.slot_layout(CacheTier::Cold));
+        let pages = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(x, y)| PageId::new(0, x, y));
+        pages.iter().for_each(|&page| {
+            streaming.upload_page(page, slots.allocate(0).unwrap(), &[255; 8 * 8 * 4]);
+        });
+        // A single slot left, for the parent of the pages but not for their grandparent.
+        let mut taken = Vec::new();
+        while let Some(slot) = slots.allocate(0) {
+            taken.push(slot);
+        }
+        slots.free(taken.pop().unwrap(), 0);
+        let used_slots = slots.used_slots();
+
+        let error = streaming
+            .generate_ancestor_mips(&pages, 2, &mut slots)
+            .unwrap_err();
+        assert!(matches!(error, MipGenerationError::NoFreeSlot(page) if page.mip_level() == 2));
+        assert_eq!(slots.used_slots(), used_slots);
+        assert_eq!(streaming.residency().slot(PageId::new(1, 0, 0)), None);
+    }
+
+    /// Ancestors going to the hot tier and borders wider than half of the page stride are
+    /// reported as errors, without allocating slots.
+    #[test]
+    fn reject_unsupported_layouts() {
+        let wgpu_context = Arc::new(crate::headless_or_skip!(64, 64));
+        let temp_dir = TempDir::new().unwrap();
+        let pages = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(x, y)| PageId::new(0, x, y));
+        let generate = |page_size: u32, border_size: u32, hot_cache: Option<HotCacheConfig>| {
+            let storage = TextureStorage::new(
+                TextureMetadata::from_mip(1, 4)
+                    .with_page_size(page_size as u16, border_size as u16),
+                Some(
+                    temp_dir
+                        .path()
+                        .join(page_size.to_string())
+                        .to_str()
+                        .unwrap(),
+                ),
+                None,
+            )
+            .unwrap();
+            let config = VirtualTexturingConfig {
+                page_size,
+                border_size,
+                page_table_size: 2,
+                physical_mip_levels: 2,
+                physical_texture: PhysicalTextureConfig {
+                    page_slots_x: 4,
+                    page_slots_y: 4,
+                    layers: 2,
+                },
+                hot_cache,
+                ..Default::default()
+            };
+            let context = VirtualTexturingContext::from_config(Arc::clone(&wgpu_context), config);
+            let streaming = StreamingHandle::new(
+                Arc::clone(&context.wgpu_context),
+                context.textures(),
+                Arc::new(RuntimePageSource::new(storage.reader())),
+            );
+            let mut slots =
+                SlotAllocator::with_layout(context.textures.slot_layout(CacheTier::Cold));
+            let error = streaming
+                .generate_ancestor_mips(&pages, 1, &mut slots)
+                .unwrap_err();
+            assert_eq!(slots.used_slots(), 0);
+            error
+        };
+
+        let error = generate(8, 2, Some(Default::default()));
+        assert!(matches!(error, MipGenerationError::HotTier(1)));
+        let error = generate(16, 6, None);
+        assert!(matches!(
+            error,
+            MipGenerationError::BorderTooWide {
+                border_size: 6,
+                page_stride: 4
+            }
+        ));
+    }
+}
@@ -0,0 +1,161 @@
+//! Heuristics requesting the pages the view is about to need, before the feedback does.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use vt_core::{PageId, VirtualTextureId};
+
+use crate::camera::CameraMotion;
+
+/// Picks the pages to stream in ahead of the feedback, see
+/// [`StreamingHandle::queue_prefetch`](crate::streaming::StreamingHandle::queue_prefetch).
+///
+/// [`VelocityPrefetch`] is the default. Applications knowing more about their camera, such as
+/// the path of a cutscene, implement their own.
+pub trait PrefetchPolicy: Send {
+    /// The pages to prefetch given the pages `requested` by the latest feedback and the motion
+    /// of the camera since the previous call. The pages requested, resident or out of the
+    /// texture are filtered out by the caller.
+    fn prefetch(&mut self, requested: &[PageId], camera: &CameraMotion) -> Vec<PageId>;
+}
+
+/// Extrapolates the view over the pages of every mip level while the camera moves: the pages
+/// requested are shifted by as much as their center moved since the previous feedback, times
+/// `frames_ahead`, which requests the pages just outside the view where it is going. Moving
+/// forward also requests the children of the finest pages requested, one mip level finer.
+#[derive(Debug, Clone)]
+pub struct VelocityPrefetch {
+    /// The feedback readbacks the view is extrapolated by.
+    pub frames_ahead: f32,
+    /// The most pages prefetched per call.
+    pub max_pages: usize,
+    /// The center of the pages requested of every virtual texture and mip level, at the last
+    /// call.
+    centers: HashMap<(VirtualTextureId, u8), (f32, f32)>,
+}
+
+impl VelocityPrefetch {
+    pub fn new(frames_ahead: f32, max_pages: usize) -> Self {
+        Self {
+            frames_ahead,
+            max_pages,
+            centers: HashMap::new(),
+        }
+    }
+}
+
+impl Default for VelocityPrefetch {
+    fn default() -> Self {
+        Self::new(8.0, 32)
+    }
+}
+
+impl PrefetchPolicy for VelocityPrefetch {
+    fn prefetch(&mut self, requested: &[PageId], camera: &CameraMotion) -> Vec<PageId> {
+        let mut levels = BTreeMap::<(VirtualTextureId, u8), Vec<PageId>>::new();
+        requested.iter().for_each(|&page| {
+            levels
+                .entry((page.texture_id(), page.mip_level()))
+                .or_default()
+                .push(page);
+        });
+        let centers = levels
+            .iter()
+            .map(|(&level, pages)| {
+                let count = pages.len() as f32;
+                let (x, y) = pages.iter().fold((0.0, 0.0), |(x, y), page| {
+                    (x + page.x() as f32, y + page.y() as f32)
+                });
+                (level, (x / count, y / count))
+            })
+            .collect::<HashMap<_, _>>();
+        let previous_centers = std::mem::replace(&mut self.centers, centers);
+        if !camera.is_moving() {
+            return Vec::new();
+        }
+
+        let requested = requested.iter().copied().collect::<HashSet<_>>();
+        let mut prefetched = Vec::new();
+        let mut push = |page: PageId| {
+            if !requested.contains(&page) && !prefetched.contains(&page) {
+                prefetched.push(page);
+            }
+        };
+        levels.iter().for_each(|(level, pages)| {
+            let (Some(previous), Some(center)) =
+                (previous_centers.get(level), self.centers.get(level))
+            else {
+                return;
+            };
+            let shift = |axis: f32, previous: f32| ((axis - previous) * self.frames_ahead).round();
+            let (dx, dy) = (shift(center.0, previous.0), shift(center.1, previous.1));
+            if (dx, dy) == (0.0, 0.0) {
+                return;
+            }
+            pages.iter().for_each(|page| {
+                let (x, y) = (page.x() as f32 + dx, page.y() as f32 + dy);
+                if (0.0..=PageId::MAX_COORDINATE as f32).contains(&x)
+                    && (0.0..=PageId::MAX_COORDINATE as f32).contains(&y)
+                {
+                    push(PageId::with_texture_id(
+                        page.texture_id(),
+                        page.mip_level(),
+                        x as u16,
+                        y as u16,
+                    ));
+                }
+            });
+        });
+        if camera.forward_speed > 0.0 {
+            let mut finest = BTreeMap::<VirtualTextureId, u8>::new();
+            levels.keys().for_each(|&(texture_id, mip)| {
+                finest.entry(texture_id).or_insert(mip);
+            });
+            levels
+                .iter()
+                .filter(|((texture_id, mip), _)| finest[texture_id] == *mip)
+                .flat_map(|(_, pages)| pages)
+                .flat_map(PageId::children)
+                .for_each(&mut push);
+        }
+        prefetched.truncate(self.max_pages);
+        prefetched
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use vt_core::PageId;
+
+    use super::{PrefetchPolicy, VelocityPrefetch};
+    use crate::camera::CameraMotion;
+
+    /// The view is extrapolated along the shift of the pages requested while the camera moves,
+    /// and refined when it moves forward.
+    #[test]
+    fn prefetch_ahead_of_the_view() {
+        let mut policy = VelocityPrefetch::new(2.0, 32);
+        let still = CameraMotion::default();
+        let strafing = CameraMotion {
+            velocity: nalgebra::Vector3::x(),
+            ..Default::default()
+        };
+        let view = |x| [PageId::new(1, x, 0), PageId::new(1, x + 1, 0)];
+        assert!(policy.prefetch(&view(0), &strafing).is_empty());
+        assert!(policy.prefetch(&view(1), &still).is_empty());
+        assert_eq!(
+            policy.prefetch(&view(2), &strafing),
+            [PageId::new(1, 4, 0), PageId::new(1, 5, 0)]
+        );
+
+        let forward = CameraMotion {
+            forward_speed: 1.0,
+            ..strafing
+        };
+        let coarse = PageId::new(2, 0, 0);
+        let prefetched = policy.prefetch(&[view(2)[0], view(2)[1], coarse], &forward);
+        assert_eq!(prefetched.len(), 8);
+        assert!(prefetched
+            .iter()
+            .all(|page| page.mip_level() == 0 && (4..8).contains(&page.x())));
+    }
+}
@@ -0,0 +1,261 @@
+//! CPU side record of the pages resident in the physical textures.
+//!
+//! Snapshot layout (little endian), see [`ResidencyMap::snapshot`]:
+//! - `SNAPSHOT_MAGIC`, then the format version as a `u32`.
+//! - The number of pages as a `u32`, followed by every page encoded with [`PageId::to_bytes`].
+
+use std::collections::{HashMap, HashSet};
+
+use thiserror::Error;
+
+use crate::{ensure, streaming::PageId};
+
+const SNAPSHOT_MAGIC: [u8; 4] = *b"VTRS";
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// The errors of [`StreamingHandle::save_residency`](crate::streaming::StreamingHandle::save_residency)
+/// and [`StreamingHandle::load_residency`](crate::streaming::StreamingHandle::load_residency).
+#[derive(Error, Debug)]
+pub enum ResidencySnapshotError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("the file is not a residency snapshot of this version")]
+    InvalidSnapshot,
+}
+
+/// The pages resident in the physical textures, and the slot each of them occupies.
+///
+/// Kept in sync with the page table by the streaming thread, so that it can be queried without
+/// reading the page table back from the GPU.
+#[derive(Debug, Default, Clone)]
+pub struct ResidencyMap {
+    slots: HashMap<PageId, (u32, u32)>,
+    /// The resident pages upsampled from an ancestor, see
+    /// [`StreamingHandle::stream_page`](crate::streaming::StreamingHandle::stream_page).
+    synthetic: HashSet<PageId>,
+}
+
+impl ResidencyMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `page` as resident in `slot`, returning the slot it previously occupied.
+    pub fn insert(&mut self, page: PageId, slot: (u32, u32)) -> Option<(u32, u32)> {
+        self.synthetic.remove(&page);
+        self.slots.insert(page, slot)
+    }
+
+    /// Record `page` as resident in `slot` with synthetic data, until it is inserted again with
+    /// [`ResidencyMap::insert`].
+    pub fn insert_synthetic(&mut self, page: PageId, slot: (u32, u32)) -> Option<(u32, u32)> {
+        self.synthetic.insert(page);
+        self.slots.insert(page, slot)
+    }
+
+    /// Record `page` as evicted, returning the slot it occupied.
+    pub fn remove(&mut self, page: PageId) -> Option<(u32, u32)> {
+        self.synthetic.remove(&page);
+        self.slots.remove(&page)
+    }
+
+    pub fn is_resident(&self, page: PageId) -> bool {
+        self.slots.contains_key(&page)
+    }
+
+    pub fn is_synthetic(&self, page: PageId) -> bool {
+        self.synthetic.contains(&page)
+    }
+
+    /// The resident pages with synthetic data, to be streamed in again when real data is added.
+    pub fn synthetic_pages(&self) -> impl Iterator<Item = PageId> + '_ {
+        self.synthetic.iter().copied()
+    }
+
+    /// Every resident page and its slot, in no particular order.
+    pub fn pages(&self) -> impl Iterator<Item = (PageId, (u32, u32))> + '_ {
+        self.slots.iter().map(|(&page, &slot)| (page, slot))
+    }
+
+    pub fn slot(&self, page: PageId) -> Option<(u32, u32)> {
+        self.slots.get(&page).copied()
+    }
+
+    /// The number of resident pages.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// The resident pages with real data, coarsest first, encoded to be streamed in again on
+    /// startup. The slots are not kept, the pages being allocated new ones.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut pages = self
+            .slots
+            .keys()
+            .filter(|page| !self.synthetic.contains(page))
+            .copied()
+            .collect::<Vec<_>>();
+        pages.sort_unstable_by_key(|&page| (std::cmp::Reverse(page.mip_level()), page));
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(pages.len() as u32).to_le_bytes());
+        pages
+            .iter()
+            .for_each(|page| bytes.extend_from_slice(&page.to_bytes()));
+        bytes
+    }
+
+    /// Decode the pages of a [`ResidencyMap::snapshot`].
+    pub fn decode_snapshot(bytes: &[u8]) -> Result<Vec<PageId>, ResidencySnapshotError> {
+        ensure!(
+            bytes.len() >= 12 && bytes[..4] == SNAPSHOT_MAGIC,
+            ResidencySnapshotError::InvalidSnapshot
+        );
+        let word = |index: usize| u32::from_le_bytes(bytes[index..index + 4].try_into().unwrap());
+        let pages = &bytes[12..];
+        ensure!(
+            word(4) == SNAPSHOT_VERSION && pages.len() == word(8) as usize * 4,
+            ResidencySnapshotError::InvalidSnapshot
+        );
+        Ok(pages.chunks_exact(4).map(PageId::from_bytes).collect())
+    }
+
+    /// Count the resident pages among `pages`.
+    pub fn report(&self, pages: impl IntoIterator<Item = PageId>) -> ResidencyReport {
+        let (resident, total) = pages.into_iter().fold((0, 0), |(resident, total), page| {
+            (resident + self.is_resident(page) as u32, total + 1)
+        });
+        ResidencyReport::from_counts(resident, total - resident)
+    }
+}
+
+/// The residency of a set of pages, see
+/// [`StreamingHandle::residency_of`](crate::streaming::StreamingHandle::residency_of).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResidencyReport {
+    /// Every page is resident (also the case of an empty set).
+    FullyResident {
+        pages: u32,
+    },
+    PartiallyResident {
+        resident: u32,
+        missing: u32,
+    },
+    NonResident {
+        pages: u32,
+    },
+}
+
+impl ResidencyReport {
+    fn from_counts(resident: u32, missing: u32) -> Self {
+        match (resident, missing) {
+            (pages, 0) => ResidencyReport::FullyResident { pages },
+            (0, pages) => ResidencyReport::NonResident { pages },
+            (resident, missing) => ResidencyReport::PartiallyResident { resident, missing },
+        }
+    }
+
+    pub fn is_fully_resident(&self) -> bool {
+        matches!(self, ResidencyReport::FullyResident { .. })
+    }
+
+    /// The number of resident pages.
+    pub fn resident(&self) -> u32 {
+        match *self {
+            ResidencyReport::FullyResident { pages } => pages,
+            ResidencyReport::PartiallyResident { resident, .. } => resident,
+            ResidencyReport::NonResident { .. } => 0,
+        }
+    }
+
+    /// The number of pages that are not resident.
+    pub fn missing(&self) -> u32 {
+        match *self {
+            ResidencyReport::FullyResident { .. } => 0,
+            ResidencyReport::PartiallyResident { missing, .. } => missing,
+            ResidencyReport::NonResident { pages } => pages,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ResidencyMap, ResidencyReport, ResidencySnapshotError};
+    use crate::streaming::{PageId, UvRect};
+
+    #[test]
+    fn report_rect_residency() {
+        let mut residency = ResidencyMap::new();
+        residency.insert(PageId::new(1, 0, 0), (0, 0));
+        residency.insert(PageId::new(1, 1, 0), (1, 0));
+        let report = |residency: &ResidencyMap, min, max| {
+            residency.report(PageId::pages_covering(UvRect::new(min, max), 1, (2, 2)))
+        };
+
+        assert_eq!(
+            report(&residency, (0.0, 0.0), (1.0, 0.4)),
+            ResidencyReport::FullyResident { pages: 2 }
+        );
+        assert_eq!(
+            report(&residency, (0.0, 0.0), (1.0, 1.0)),
+            ResidencyReport::PartiallyResident {
+                resident: 2,
+                missing: 2
+            }
+        );
+        assert_eq!(
+            report(&residency, (0.0, 0.6), (0.4, 1.0)),
+            ResidencyReport::NonResident { pages: 1 }
+        );
+
+        residency.remove(PageId::new(1, 1, 0));
+        assert_eq!(report(&residency, (0.0, 0.0), (1.0, 0.4)).missing(), 1);
+    }
+
+    #[test]
+    fn replace_synthetic_page() {
+        let mut residency = ResidencyMap::new();
+        let page = PageId::new(0, 3, 2);
+        residency.insert_synthetic(page, (0, 0));
+        assert!(residency.is_resident(page));
+        assert!(residency.is_synthetic(page));
+        assert_eq!(residency.synthetic_pages().collect::<Vec<_>>(), [page]);
+
+        residency.insert(page, (1, 0));
+        assert!(!residency.is_synthetic(page));
+        assert_eq!(residency.slot(page), Some((1, 0)));
+
+        residency.insert_synthetic(page, (1, 0));
+        residency.remove(page);
+        assert_eq!(residency.synthetic_pages().count(), 0);
+    }
+
+    /// Snapshots hold the resident pages with real data, coarsest first.
+    #[test]
+    fn snapshot_round_trip() {
+        let mut residency = ResidencyMap::new();
+        let pages = [
+            PageId::with_texture_id(1, 3, 2, 1),
+            PageId::new(2, 0, 0),
+            PageId::new(0, 4095, 7),
+        ];
+        pages.iter().for_each(|&page| {
+            residency.insert(page, (0, 0));
+        });
+        residency.insert_synthetic(PageId::new(1, 0, 0), (1, 0));
+        let snapshot = residency.snapshot();
+        assert_eq!(
+            ResidencyMap::decode_snapshot(&snapshot).unwrap(),
+            [pages[0], pages[1], pages[2]]
+        );
+        assert!(matches!(
+            ResidencyMap::decode_snapshot(&snapshot[..snapshot.len() - 1]),
+            Err(ResidencySnapshotError::InvalidSnapshot)
+        ));
+        assert!(ResidencyMap::decode_snapshot(b"VTPK").is_err());
+    }
+}
@@ -0,0 +1,137 @@
+//! Allocation of the slots of the physical texture.
+
+use crate::config::PhysicalTextureConfig;
+
+/// The free and used slots of a physical texture, whose slot `(x, y)` is in array layer
+/// `y / page_slots_y` of its layout (see
+/// [`Textures::slot_location`](crate::textures::Textures::slot_location)).
+///
+/// Coarse pages (see
+/// [`TextureMetadata::with_coarse_pages`](crate::storage::TextureMetadata::with_coarse_pages))
+/// take an aligned block of `2^scale * 2^scale` slots within an array layer. Regular pages are
+/// allocated from the start of the texture and coarse ones from the end, so that evicting regular
+/// pages does not break up the blocks of coarse pages.
+#[derive(Debug, Clone)]
+pub struct SlotAllocator {
+    layout: PhysicalTextureConfig,
+    used: Vec<bool>,
+}
+
+impl SlotAllocator {
+    /// The slots of a single array layer of `slots_per_side * slots_per_side` slots.
+    pub fn new(slots_per_side: u32) -> Self {
+        Self::with_layout(PhysicalTextureConfig {
+            page_slots_x: slots_per_side,
+            page_slots_y: slots_per_side,
+            layers: 1,
+        })
+    }
+
+    /// The slots of the physical textures of a tier, see
+    /// [`Textures::slot_layout`](crate::textures::Textures::slot_layout).
+    pub fn with_layout(layout: PhysicalTextureConfig) -> Self {
+        Self {
+            layout,
+            used: vec![false; layout.slot_count() as usize],
+        }
+    }
+
+    /// Allocate the block of slots of a page of `scale` (see
+    /// [`TextureMetadata::page_scale`](crate::storage::TextureMetadata::page_scale)), returning
+    /// its first slot, or `None` if no block is free.
+    pub fn allocate(&mut self, scale: u8) -> Option<(u32, u32)> {
+        let block = 1 << scale;
+        let (rows, blocks_x, blocks_y) = (
+            self.layout.page_slots_y,
+            self.layout.page_slots_x / block,
+            self.layout.page_slots_y / block,
+        );
+        let blocks_per_layer = blocks_x * blocks_y;
+        let mut blocks = (0..blocks_per_layer * self.layout.layers).map(|index| {
+            let (layer, index) = (index / blocks_per_layer, index % blocks_per_layer);
+            (
+                index % blocks_x * block,
+                layer * rows + index / blocks_x * block,
+            )
+        });
+        let slot = if scale == 0 {
+            blocks.find(|&slot| self.is_free(slot, block))
+        } else {
+            blocks.rev().find(|&slot| self.is_free(slot, block))
+        }?;
+        self.set_used(slot, block, true);
+        Some(slot)
+    }
+
+    /// Free the block of slots allocated with `scale` at `slot`.
+    pub fn free(&mut self, slot: (u32, u32), scale: u8) {
+        self.set_used(slot, 1 << scale, false);
+    }
+
+    /// The number of slots in use.
+    pub fn used_slots(&self) -> usize {
+        self.used.iter().filter(|&&used| used).count()
+    }
+
+    fn block_indices(&self, (x, y): (u32, u32), block: u32) -> impl Iterator<Item = usize> + '_ {
+        (y..y + block).flat_map(move |y| {
+            (x..x + block).map(move |x| (y * self.layout.page_slots_x + x) as usize)
+        })
+    }
+
+    fn is_free(&self, slot: (u32, u32), block: u32) -> bool {
+        self.block_indices(slot, block)
+            .all(|index| !self.used[index])
+    }
+
+    fn set_used(&mut self, slot: (u32, u32), block: u32, used: bool) {
+        let indices = self.block_indices(slot, block).collect::<Vec<_>>();
+        indices
+            .into_iter()
+            .for_each(|index| self.used[index] = used);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SlotAllocator;
+    use crate::config::PhysicalTextureConfig;
+
+    #[test]
+    fn allocate_coarse_blocks() {
+        let mut slots = SlotAllocator::new(4);
+        assert_eq!(slots.allocate(0), Some((0, 0)));
+        assert_eq!(slots.allocate(1), Some((2, 2)));
+        assert_eq!(slots.allocate(1), Some((0, 2)));
+        assert_eq!(slots.allocate(0), Some((1, 0)));
+        // The top right block has a free slot, but the top left one does not.
+        assert_eq!(slots.allocate(1), Some((2, 0)));
+        assert_eq!(slots.allocate(1), None);
+        assert_eq!(slots.used_slots(), 14);
+
+        slots.free((2, 2), 1);
+        assert_eq!(slots.allocate(1), Some((2, 2)));
+        assert_eq!(slots.allocate(2), None);
+        assert_eq!(slots.allocate(0), Some((0, 1)));
+    }
+
+    /// Blocks stay within an array layer, whose slots may not be a multiple of the block.
+    #[test]
+    fn allocate_across_layers() {
+        let mut slots = SlotAllocator::with_layout(PhysicalTextureConfig {
+            page_slots_x: 4,
+            page_slots_y: 3,
+            layers: 2,
+        });
+        assert_eq!(slots.allocate(1), Some((2, 3)));
+        assert_eq!(slots.allocate(1), Some((0, 3)));
+        assert_eq!(slots.allocate(1), Some((2, 0)));
+        assert_eq!(slots.allocate(1), Some((0, 0)));
+        assert_eq!(slots.allocate(1), None);
+        // The last row of each layer is only left to regular pages.
+        assert_eq!(slots.allocate(0), Some((0, 2)));
+        (0..7).for_each(|_| assert!(slots.allocate(0).is_some()));
+        assert_eq!(slots.allocate(0), None);
+        assert_eq!(slots.used_slots(), 24);
+    }
+}
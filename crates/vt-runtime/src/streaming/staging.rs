@@ -0,0 +1,263 @@
+//! A ring of staging buffers the pages are written to on their way to the physical textures, so
+//! that the uploads of every frame reuse the same buffers instead of allocating their own.
+
+use std::{
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::setup::WgpuContext;
+
+/// The states of a [`StagingChunk`] being mapped again after its copies.
+const PENDING: u8 = 0;
+const MAPPED: u8 = 1;
+const FAILED: u8 = 2;
+
+/// A buffer of the belt, mapped while its bytes are written, and unmapped from the submission of
+/// its copies until they complete.
+struct StagingChunk {
+    buffer: wgpu::Buffer,
+    /// The end of the bytes written since the chunk was mapped.
+    offset: u64,
+    /// [`PENDING`] until the chunk is mapped again after its copies, then [`MAPPED`], or
+    /// [`FAILED`] if it could not be.
+    state: Arc<AtomicU8>,
+}
+
+impl StagingChunk {
+    fn new(device: &wgpu::Device, size: u64) -> Self {
+        Self {
+            buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("page staging buffer"),
+                size,
+                usage: wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: true,
+            }),
+            offset: 0,
+            state: Arc::new(AtomicU8::new(MAPPED)),
+        }
+    }
+
+    /// Map the chunk again, once the copies submitted from it complete.
+    fn recall(&mut self) {
+        let state = Arc::new(AtomicU8::new(PENDING));
+        self.state = Arc::clone(&state);
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Write, move |result| {
+                state.store(
+                    if result.is_ok() { MAPPED } else { FAILED },
+                    Ordering::Release,
+                );
+            });
+    }
+}
+
+/// Stages the writes to textures in a bounded set of chunks reused from frame to frame, like
+/// [`wgpu::util::StagingBelt`], and records their copies to be submitted at once by
+/// [`StagingBelt::flush`].
+///
+/// Once `max_chunks` chunks are full or in flight, a write flushes the staged copies and blocks
+/// until the GPU is done with a chunk: the streaming slows down to the pace of the GPU instead of
+/// allocating more memory.
+pub(crate) struct StagingBelt {
+    chunk_size: u64,
+    max_chunks: usize,
+    /// Mapped chunks, written to by the copies of `encoder`.
+    active: Vec<StagingChunk>,
+    /// Mapped chunks, empty.
+    free: Vec<StagingChunk>,
+    /// Chunks whose copies are submitted, not mapped yet.
+    in_flight: Vec<StagingChunk>,
+    /// The copies staged since the last flush.
+    encoder: Option<wgpu::CommandEncoder>,
+}
+
+impl StagingBelt {
+    /// A belt of at most `max_chunks` chunks of `chunk_size` bytes, allocated as they are needed.
+    /// Writes larger than `chunk_size` take a chunk of their own size.
+    ///
+    /// ### Panics
+    ///
+    /// - If `max_chunks` is 0, or if `chunk_size` is not a multiple of
+    ///   [`wgpu::COPY_BUFFER_ALIGNMENT`].
+    pub fn new(chunk_size: u64, max_chunks: usize) -> Self {
+        assert!(max_chunks > 0);
+        assert_eq!(chunk_size % wgpu::COPY_BUFFER_ALIGNMENT, 0);
+        Self {
+            chunk_size,
+            max_chunks,
+            active: Vec::new(),
+            free: Vec::new(),
+            in_flight: Vec::new(),
+            encoder: None,
+        }
+    }
+
+    /// Stage `data`, rows of `bytes_per_row` bytes, and record its copy to `texture`, as
+    /// [`wgpu::Queue::write_texture`] would write it. Returns how long the write waited on
+    /// a chunk to be free, if the belt was full.
+    pub fn write_texture(
+        &mut self,
+        context: &WgpuContext,
+        texture: wgpu::ImageCopyTexture,
+        data: &[u8],
+        bytes_per_row: u32,
+        size: wgpu::Extent3d,
+    ) -> Option<Duration> {
+        // The rows are padded for the copies, which keeps every offset aligned as well.
+        let stride = bytes_per_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let rows = data.len() as u32 / bytes_per_row;
+        let staged_size = stride as u64 * rows as u64;
+        let (index, stall) = self.allocate(context, staged_size);
+
+        let chunk = &mut self.active[index];
+        let offset = chunk.offset;
+        chunk
+            .buffer
+            .slice(offset..offset + staged_size)
+            .get_mapped_range_mut()
+            .chunks_exact_mut(stride as usize)
+            .zip(data.chunks_exact(bytes_per_row as usize))
+            .for_each(|(staged, row)| staged[..row.len()].copy_from_slice(row));
+        chunk.offset += staged_size;
+
+        let encoder = self.encoder.get_or_insert_with(|| {
+            context
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("page upload encoder"),
+                })
+        });
+        encoder.copy_buffer_to_texture(
+            wgpu::ImageCopyBuffer {
+                buffer: &chunk.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset,
+                    bytes_per_row: Some(stride),
+                    rows_per_image: None,
+                },
+            },
+            texture,
+            size,
+        );
+        stall
+    }
+
+    /// Submit the copies staged since the last flush, and recall their chunks for the next
+    /// writes once the copies complete.
+    pub fn flush(&mut self, context: &WgpuContext) {
+        let Some(encoder) = self.encoder.take() else {
+            return;
+        };
+        self.active.iter().for_each(|chunk| chunk.buffer.unmap());
+        context.queue.submit(Some(encoder.finish()));
+        self.in_flight
+            .extend(self.active.drain(..).map(|mut chunk| {
+                chunk.recall();
+                chunk
+            }));
+    }
+
+    fn chunk_count(&self) -> usize {
+        self.active.len() + self.free.len() + self.in_flight.len()
+    }
+
+    /// The index in `active` of a chunk with `size` bytes left, blocking until one is free if
+    /// the belt is full, with the time waited.
+    fn allocate(&mut self, context: &WgpuContext, size: u64) -> (usize, Option<Duration>) {
+        if let Some(index) = self
+            .active
+            .iter()
+            .position(|chunk| chunk.offset + size <= chunk.buffer.size())
+        {
+            return (index, None);
+        }
+
+        context.device.poll(wgpu::Maintain::Poll);
+        self.collect_recalled();
+        let mut stall = None;
+        if self.free.is_empty() && self.chunk_count() >= self.max_chunks {
+            let start = Instant::now();
+            self.flush(context);
+            while self.free.is_empty() && self.chunk_count() >= self.max_chunks {
+                context.device.poll(wgpu::Maintain::Wait);
+                self.collect_recalled();
+            }
+            stall = Some(start.elapsed());
+        }
+        let chunk = match self.free.pop() {
+            Some(chunk) if chunk.buffer.size() >= size => chunk,
+            // Dropped for a larger one.
+            _ => StagingChunk::new(&context.device, self.chunk_size.max(size)),
+        };
+        self.active.push(chunk);
+        (self.active.len() - 1, stall)
+    }
+
+    /// Move the chunks mapped again to `free`, dropping the ones that could not be.
+    fn collect_recalled(&mut self) {
+        std::mem::take(&mut self.in_flight)
+            .into_iter()
+            .for_each(|mut chunk| match chunk.state.load(Ordering::Acquire) {
+                PENDING => self.in_flight.push(chunk),
+                MAPPED => {
+                    chunk.offset = 0;
+                    self.free.push(chunk);
+                }
+                _ => log::warn!("a page staging buffer could not be mapped again"),
+            });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StagingBelt;
+    use crate::debug::read_texture;
+
+    /// A write to a full belt waits for the previous copies, and every write lands in the
+    /// texture once flushed.
+    #[test]
+    fn stall_when_full() {
+        let context = crate::headless_or_skip!(64, 64);
+        let size = wgpu::Extent3d {
+            width: 8,
+            height: 8,
+            depth_or_array_layers: 1,
+        };
+        let texture = context.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let copy = |y| wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d { x: 0, y, z: 0 },
+            aspect: wgpu::TextureAspect::All,
+        };
+        let half = wgpu::Extent3d { height: 4, ..size };
+        // A single chunk holding one half of the texture, its rows padded to 256 bytes.
+        let mut belt = StagingBelt::new(4 * 256, 1);
+        assert!(belt
+            .write_texture(&context, copy(0), &[100; 8 * 4 * 4], 8 * 4, half)
+            .is_none());
+        assert!(belt
+            .write_texture(&context, copy(4), &[200; 8 * 4 * 4], 8 * 4, half)
+            .is_some());
+        assert_eq!(belt.chunk_count(), 1);
+        belt.flush(&context);
+
+        let texels = read_texture(&context, &texture, 0, 0).unwrap();
+        let texel = |x: usize, y: usize| texels[(y * 8 + x) * 4];
+        assert_eq!((texel(7, 3), texel(0, 4), texel(7, 7)), (100, 200, 200));
+    }
+}
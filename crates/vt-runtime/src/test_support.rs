@@ -0,0 +1,196 @@
+//! Fixtures of the tests running on a GPU, shared with the integration tests and the crates
+//! depending on this one through the `test-support` feature.
+//!
+//! The tests needing an adapter skip when there is none, or when it lacks a feature they need,
+//! each skip reported with the name of the test and the count of the ones skipped so far.
+//! Setting [`REQUIRE_ADAPTER`] fails them instead, so that a machine expected to have an adapter
+//! can not skip them silently.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use assert_fs::fixture::TempDir;
+
+use crate::{
+    config::VirtualTexturingConfig,
+    draw::DrawItem,
+    setup::{VirtualTexturingContext, WgpuContext},
+    storage::{TextureMetadata, TextureStorage},
+    streaming::{PageId, StreamingHandle},
+};
+
+/// The environment variable failing the tests without an adapter, or without the features they
+/// need, instead of skipping them.
+pub const REQUIRE_ADAPTER: &str = "VT_REQUIRE_ADAPTER";
+
+/// The tests of the process skipped for lack of an adapter or of a feature.
+static SKIPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// Report the current test as skipped for lack of an adapter.
+///
+/// ### Panics
+///
+/// - If [`REQUIRE_ADAPTER`] is set.
+pub fn skip_without_adapter() {
+    skip("no adapter available");
+}
+
+/// Report the current test as skipped because the adapter does not support `features`.
+///
+/// ### Panics
+///
+/// - If [`REQUIRE_ADAPTER`] is set.
+pub fn skip_without_features(features: wgpu::Features) {
+    skip(&format!("the adapter does not support {features:?}"));
+}
+
+/// Report the current test as skipped because the adapter lacks the downlevel capabilities
+/// `flags`.
+///
+/// ### Panics
+///
+/// - If [`REQUIRE_ADAPTER`] is set.
+pub fn skip_without_downlevel_flags(flags: wgpu::DownlevelFlags) {
+    skip(&format!("the adapter does not support {flags:?}"));
+}
+
+fn skip(reason: &str) {
+    let thread = std::thread::current();
+    let test = thread.name().unwrap_or("test");
+    if std::env::var_os(REQUIRE_ADAPTER).is_some() {
+        panic!("{test}: {reason}, and {REQUIRE_ADAPTER} is set");
+    }
+    let skipped = SKIPPED.fetch_add(1, Ordering::Relaxed) + 1;
+    eprintln!(
+        "skipping {test}: {reason} ({skipped} skipped so far, set {REQUIRE_ADAPTER} to fail \
+         instead)"
+    );
+}
+
+/// A headless context rendering to a `width` by `height` target, or `None` once the test is
+/// reported as skipped by [`skip_without_adapter`].
+pub fn headless(width: u32, height: u32) -> Option<WgpuContext> {
+    let context = pollster::block_on(WgpuContext::headless(width, height));
+    if context.is_none() {
+        skip_without_adapter();
+    }
+    context
+}
+
+/// The headless context of [`headless`], returning from the test if there is no adapter.
+#[macro_export]
+macro_rules! headless_or_skip {
+    ($width:expr, $height:expr) => {
+        match $crate::test_support::headless($width, $height) {
+            Some(context) => context,
+            None => return,
+        }
+    };
+}
+
+/// The frames rendered by [`render_feedback`] before giving up on the feedback.
+const MAX_FEEDBACK_FRAMES: usize = 16;
+
+/// Render frames of `items` until the feedback of `frames` of them is read back, and return the
+/// request traces of these frames (see [`StreamingHandle::request_traces`]).
+///
+/// ### Panics
+///
+/// - If the feedback of fewer frames is read back within [`MAX_FEEDBACK_FRAMES`] frames.
+pub fn render_feedback(
+    context: &mut VirtualTexturingContext,
+    streaming: &mut StreamingHandle,
+    items: &[DrawItem],
+    frames: usize,
+) -> Vec<Vec<PageId>> {
+    for _ in 0..MAX_FEEDBACK_FRAMES {
+        let traces = streaming.request_traces();
+        if traces.len() >= frames {
+            return traces;
+        }
+        let frame = context.begin_frame(items);
+        context.end_frame(frame, Some(streaming));
+        streaming.wait_feedback();
+    }
+    panic!("the feedback of {frames} frames was not read back within {MAX_FEEDBACK_FRAMES} frames");
+}
+
+/// A context of the tests streaming pages from a storage in a temporary directory.
+pub struct StreamingFixture {
+    pub context: VirtualTexturingContext,
+    pub storage: TextureStorage,
+    pub streaming: StreamingHandle,
+    /// Removed with the fixture.
+    pub temp_dir: TempDir,
+}
+
+impl StreamingFixture {
+    /// A context of `config` streaming from an empty storage of `metadata`.
+    pub fn new(
+        wgpu_context: impl Into<Arc<WgpuContext>>,
+        metadata: TextureMetadata,
+        config: VirtualTexturingConfig,
+    ) -> Self {
+        Self::with_storage(wgpu_context, config, |path| {
+            TextureStorage::new(metadata, Some(path), None).unwrap()
+        })
+    }
+
+    /// A context of `config` streaming from a storage of `metadata` whose texels are all
+    /// `texel`.
+    pub fn filled(
+        wgpu_context: impl Into<Arc<WgpuContext>>,
+        metadata: TextureMetadata,
+        config: VirtualTexturingConfig,
+        texel: &[u8],
+    ) -> Self {
+        let (width, height) = metadata.texel_dimensions();
+        let texels = texel.repeat((width * height) as usize);
+        Self::imported(
+            wgpu_context,
+            metadata,
+            config,
+            image::imageops::FilterType::Triangle,
+            &texels,
+        )
+    }
+
+    /// A context of `config` streaming from a storage of `metadata` importing `texels`, filtered
+    /// by `filter` into the coarser mip levels.
+    pub fn imported(
+        wgpu_context: impl Into<Arc<WgpuContext>>,
+        metadata: TextureMetadata,
+        config: VirtualTexturingConfig,
+        filter: image::imageops::FilterType,
+        texels: &[u8],
+    ) -> Self {
+        Self::with_storage(wgpu_context, config, |path| {
+            let mut storage = TextureStorage::new(metadata, Some(path), None).unwrap();
+            storage.import_texture(filter, texels).unwrap();
+            storage
+        })
+    }
+
+    fn with_storage(
+        wgpu_context: impl Into<Arc<WgpuContext>>,
+        config: VirtualTexturingConfig,
+        storage: impl FnOnce(&str) -> TextureStorage,
+    ) -> Self {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = storage(temp_dir.path().to_str().unwrap());
+        let context = VirtualTexturingContext::from_config(wgpu_context.into(), config);
+        let streaming = StreamingHandle::new(
+            Arc::clone(&context.wgpu_context),
+            context.textures(),
+            storage.reader(),
+        );
+        Self {
+            context,
+            storage,
+            streaming,
+            temp_dir,
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Packing of many textures in a single virtual texture.
+
+#[cfg(feature = "image")]
+mod atlas;
+
+#[cfg(feature = "image")]
+pub use atlas::{Atlas, AtlasBuilder, SubtextureFiltering};
+
+/// The dimensions of a texture to add to the Virtual Texture.
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct TextureDims {
+    extent: wgpu::Extent3d,
+}
+
+impl TextureDims {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            extent: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        }
+    }
+}
+
+/// Ordered by `height`, then `width` if `height` is equal.
+impl PartialOrd for TextureDims {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TextureDims {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.extent
+            .height
+            .cmp(&other.extent.height)
+            .then(self.extent.width.cmp(&other.extent.width))
+    }
+}
+
+/// Offset of a subtexture on a Virtual Texture.
+pub type UvOffset = (u32, u32);
+
+/// Where the textures are placed in the Virtual Texture, see [`create_virt_texture`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VirtualTextureLayout {
+    /// A power of two, at least as wide as the widest texture.
+    pub width: u32,
+    /// The bottom of the highest texture.
+    pub height: u32,
+    /// The offset of the top left corner of every texture, in texels, in the order of the
+    /// textures.
+    pub offsets: Vec<UvOffset>,
+}
+
+/// A section of the skyline: the top of the textures placed below it, from `begin` to `end`.
+struct SkylineSection {
+    begin: u32,
+    end: u32,
+    height: u32,
+}
+
+/// This function creates a Virtual Texture from the given Textures, packing them without overlap
+/// with the skyline bottom-left heuristic.
+///
+/// The width is the smallest power of two fitting the area of the textures in a square, or the
+/// widest texture. The textures are placed from the highest to the lowest, each one where its
+/// top is the lowest, so the height grows as needed.
+pub fn create_virt_texture(textures: &[TextureDims]) -> VirtualTextureLayout {
+    let min_area = textures
+        .iter()
+        .map(|dims| dims.extent.width as u64 * dims.extent.height as u64)
+        .sum::<u64>();
+    let widest = textures.iter().map(|dims| dims.extent.width).max();
+    let virtual_texture_width = ((min_area as f64).sqrt().ceil() as u32)
+        .max(widest.unwrap_or(0))
+        .max(1)
+        .next_power_of_two();
+
+    // The skyline is initially like this:
+    //      |                     |
+    //      |                     |
+    // (0,0)|_____________________|(virtual_texture_width, 0)
+    let mut skyline = vec![SkylineSection {
+        begin: 0,
+        end: virtual_texture_width,
+        height: 0,
+    }];
+
+    let mut order = (0..textures.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| textures[b].cmp(&textures[a]));
+    let mut offsets = vec![(0, 0); textures.len()];
+    for index in order {
+        let (width, height) = (textures[index].extent.width, textures[index].extent.height);
+        // The lowest position starting at the beginning of a section, the leftmost on ties.
+        let (begin, bottom) = skyline
+            .iter()
+            .filter(|section| section.begin + width <= virtual_texture_width)
+            .map(|section| {
+                let end = section.begin + width;
+                let bottom = skyline
+                    .iter()
+                    .filter(|other| other.begin < end && other.end > section.begin)
+                    .map(|other| other.height)
+                    .max()
+                    .expect("the section itself to be below the texture");
+                (section.begin, bottom)
+            })
+            .min_by_key(|&(begin, bottom)| (bottom, begin))
+            .expect("the first section to fit every texture");
+        offsets[index] = (begin, bottom);
+        place(&mut skyline, begin, begin + width, bottom + height);
+    }
+
+    VirtualTextureLayout {
+        width: virtual_texture_width,
+        height: skyline
+            .iter()
+            .map(|section| section.height)
+            .max()
+            .unwrap_or(0),
+        offsets,
+    }
+}
+
+/// Raise the skyline to `height` from `begin` to `end`, merging the sections of equal height.
+fn place(skyline: &mut Vec<SkylineSection>, begin: u32, end: u32, height: u32) {
+    let mut sections = Vec::with_capacity(skyline.len() + 2);
+    let mut inserted = false;
+    for section in skyline.drain(..) {
+        if section.begin < begin {
+            sections.push(SkylineSection {
+                end: section.end.min(begin),
+                ..section
+            });
+        }
+        if section.begin < end && section.end > begin && !inserted {
+            sections.push(SkylineSection { begin, end, height });
+            inserted = true;
+        }
+        if section.end > end {
+            sections.push(SkylineSection {
+                begin: section.begin.max(end),
+                ..section
+            });
+        }
+    }
+
+    for section in sections {
+        match skyline.last_mut() {
+            Some(last) if last.height == section.height => last.end = section.end,
+            _ => skyline.push(section),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{create_virt_texture, TextureDims, VirtualTextureLayout};
+
+    fn dims(dimensions: &[(u32, u32)]) -> Vec<TextureDims> {
+        dimensions
+            .iter()
+            .map(|&(width, height)| TextureDims::new(width, height))
+            .collect()
+    }
+
+    #[test]
+    fn pack_squares() {
+        assert_eq!(
+            create_virt_texture(&dims(&[(2, 2); 4])),
+            VirtualTextureLayout {
+                width: 4,
+                height: 4,
+                offsets: vec![(0, 0), (2, 0), (0, 2), (2, 2)],
+            }
+        );
+    }
+
+    /// The highest textures are placed first, and the height grows past the width if needed.
+    #[test]
+    fn pack_by_height() {
+        assert_eq!(
+            create_virt_texture(&dims(&[(4, 1), (2, 2), (2, 3)])),
+            VirtualTextureLayout {
+                width: 4,
+                height: 4,
+                offsets: vec![(0, 3), (2, 0), (0, 0)],
+            }
+        );
+        assert_eq!(
+            create_virt_texture(&dims(&[(1, 8), (1, 8)])),
+            VirtualTextureLayout {
+                width: 4,
+                height: 8,
+                offsets: vec![(0, 0), (1, 0)],
+            }
+        );
+        // The second texture does not fit next to the first one.
+        assert_eq!(
+            create_virt_texture(&dims(&[(3, 3), (2, 2)])),
+            VirtualTextureLayout {
+                width: 4,
+                height: 5,
+                offsets: vec![(0, 0), (0, 3)],
+            }
+        );
+    }
+
+    #[test]
+    fn pack_wide_texture() {
+        let layout = create_virt_texture(&dims(&[(8, 1), (1, 1)]));
+        assert_eq!((layout.width, layout.height), (8, 2));
+        assert_eq!(create_virt_texture(&[]).offsets, vec![]);
+    }
+
+    #[test]
+    fn packings_do_not_overlap() {
+        let textures = dims(
+            &(0..40u32)
+                .map(|index| (1 + index * 7 % 13, 1 + index * 5 % 11))
+                .collect::<Vec<_>>(),
+        );
+        let layout = create_virt_texture(&textures);
+        let rects = textures
+            .iter()
+            .zip(&layout.offsets)
+            .map(|(dims, &(x, y))| (x, y, x + dims.extent.width, y + dims.extent.height))
+            .collect::<Vec<_>>();
+        rects.iter().enumerate().for_each(|(index, a)| {
+            assert!(a.2 <= layout.width && a.3 <= layout.height);
+            rects[index + 1..].iter().for_each(|b| {
+                assert!(a.2 <= b.0 || b.2 <= a.0 || a.3 <= b.1 || b.3 <= a.1);
+            });
+        });
+    }
+}
@@ -0,0 +1,342 @@
+//! Merging of many images in a single texture storage.
+
+use std::{collections::HashMap, io::Read, path::Path};
+
+use crate::{
+    storage::{AtlasRect, FitOperation, TextureMetadata, TextureStorage, TextureStorageError},
+    streaming::UvRect,
+    texture_generation::{create_virt_texture, TextureDims},
+};
+
+/// Packs images with [`create_virt_texture`] and writes them to a single [`TextureStorage`], so
+/// that many meshes are textured by one virtual texture.
+#[derive(Default)]
+pub struct AtlasBuilder {
+    images: Vec<(String, image::RgbaImage, SubtextureFiltering)>,
+    padding: u32,
+}
+
+/// How the filtering of an image of an atlas is kept from bleeding into its neighbours, recorded
+/// in its [`AtlasRect`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SubtextureFiltering {
+    /// The texels repeating the edges of the image on each side, `None` for the padding of the
+    /// builder (see [`AtlasBuilder::with_padding`]).
+    pub gutter: Option<u32>,
+    /// Clamp the uv coordinates of the image to its rect in the shaders, see
+    /// [`TextureMetadata::uv_clamp`].
+    pub clamp: bool,
+}
+
+/// A texture storage built by [`AtlasBuilder::build`].
+pub struct Atlas {
+    pub storage: TextureStorage,
+    /// Where every image is in the texture, by name. Remap the uv coordinates of the meshes
+    /// textured by an image with [`UvRect::map`].
+    ///
+    /// The images are also recorded in the metadata of the texture, see
+    /// [`TextureMetadata::uv_transform`].
+    pub uv_rects: HashMap<String, UvRect>,
+}
+
+impl AtlasBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Extend every image by `padding` texels on each side, repeating its edges, so that
+    /// filtering and the coarse mip levels do not bleed the neighbouring images in (Default: 0),
+    /// unless the image has a gutter of its own (see [`SubtextureFiltering::gutter`]).
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Add an image to the atlas, replacing the previous image of the same name.
+    pub fn add_image(&mut self, name: impl Into<String>, image: image::RgbaImage) -> &mut Self {
+        self.add_image_with_filtering(name, image, SubtextureFiltering::default())
+    }
+
+    /// [`AtlasBuilder::add_image`], with the `filtering` of the image.
+    pub fn add_image_with_filtering(
+        &mut self,
+        name: impl Into<String>,
+        image: image::RgbaImage,
+        filtering: SubtextureFiltering,
+    ) -> &mut Self {
+        let name = name.into();
+        match self.images.iter_mut().find(|(other, ..)| *other == name) {
+            Some((_, previous, previous_filtering)) => {
+                *previous = image;
+                *previous_filtering = filtering;
+            }
+            None => self.images.push((name, image, filtering)),
+        }
+        self
+    }
+
+    /// The gutter of an image added with `filtering`.
+    fn gutter(&self, filtering: SubtextureFiltering) -> u32 {
+        filtering.gutter.unwrap_or(self.padding)
+    }
+
+    /// Add the image file at `path`, named after the file without its extension.
+    pub fn add_image_file(&mut self, path: &Path) -> Result<&mut Self, TextureStorageError> {
+        let image = image::open(path)?.into_rgba8();
+        let name = path
+            .file_stem()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        Ok(self.add_image(name, image))
+    }
+
+    /// Packs the images and writes them to a new texture storage in `directory` (see
+    /// [`TextureStorage::new`]).
+    ///
+    /// `metadata` sets the page size, the encoding and the compression of the texture, which is
+    /// sized to the smallest square fitting the packed images, and records them (see
+    /// [`TextureMetadata::with_atlas`]). The texels outside of the images are transparent black.
+    pub fn build(
+        &self,
+        metadata: TextureMetadata,
+        directory: Option<&str>,
+        filter_mode: image::imageops::FilterType,
+    ) -> Result<Atlas, TextureStorageError> {
+        let dims = self
+            .images
+            .iter()
+            .map(|(_, image, filtering)| {
+                let gutter = self.gutter(*filtering);
+                TextureDims::new(image.width() + 2 * gutter, image.height() + 2 * gutter)
+            })
+            .collect::<Vec<_>>();
+        let layout = create_virt_texture(&dims);
+        let border_size = metadata.border_size() as u32;
+        let side = layout.width.max(layout.height) + 2 * border_size;
+        let metadata = metadata.with_texel_dimensions((side, side), FitOperation::Pad);
+
+        let rects = self
+            .images
+            .iter()
+            .zip(&layout.offsets)
+            .map(|((name, image, filtering), &(x, y))| {
+                let gutter = self.gutter(*filtering);
+                AtlasRect {
+                    name: name.clone(),
+                    x: x + gutter,
+                    y: y + gutter,
+                    width: image.width(),
+                    height: image.height(),
+                    gutter: (gutter > 0).then_some(gutter),
+                    clamp: filtering.clamp.then_some(true),
+                }
+            })
+            .collect();
+        let metadata = metadata.with_atlas(rects);
+        let uv_rects = self
+            .images
+            .iter()
+            .map(|(name, ..)| {
+                let uv_rect = metadata
+                    .uv_rect(name)
+                    .expect("every image to be in the atlas");
+                (name.clone(), uv_rect)
+            })
+            .collect();
+
+        let (width, height) = metadata.texel_dimensions();
+        let reader = AtlasReader {
+            images: self
+                .images
+                .iter()
+                .zip(&layout.offsets)
+                .map(|((_, image, filtering), &(x, y))| {
+                    let gutter = self.gutter(*filtering);
+                    (image, (x + border_size, y + border_size), gutter)
+                })
+                .collect(),
+            width,
+            rows_left: height,
+            next_row: 0,
+            row: Vec::new(),
+            position: 0,
+        };
+        let mut storage = TextureStorage::new(metadata, directory, None)?;
+        storage.import_texture(filter_mode, reader)?;
+        Ok(Atlas { storage, uv_rects })
+    }
+}
+
+/// Reads the texels of the atlas one row at a time, in the layout of the input of
+/// [`TextureStorage::import_texture`].
+struct AtlasReader<'a> {
+    /// Every image, with the top left corner of its gutter in the texture and its gutter.
+    images: Vec<(&'a image::RgbaImage, (u32, u32), u32)>,
+    width: u32,
+    rows_left: u32,
+    next_row: u32,
+    row: Vec<u8>,
+    /// Position of the next byte to read in `row`.
+    position: usize,
+}
+
+impl AtlasReader<'_> {
+    fn fill_next_row(&mut self) {
+        let y = self.next_row;
+        self.row.clear();
+        self.row.resize(self.width as usize * 4, 0);
+        self.images
+            .iter()
+            .for_each(|&(image, (left, top), gutter)| {
+                let padded = (image.width() + 2 * gutter, image.height() + 2 * gutter);
+                if image.width() == 0 || image.height() == 0 || y < top || y >= top + padded.1 {
+                    return;
+                }
+                let image_y = (y - top).saturating_sub(gutter).min(image.height() - 1);
+                (0..padded.0).for_each(|x| {
+                    let image_x = x.saturating_sub(gutter).min(image.width() - 1);
+                    let start = (left + x) as usize * 4;
+                    self.row[start..start + 4]
+                        .copy_from_slice(&image.get_pixel(image_x, image_y).0);
+                });
+            });
+        self.next_row += 1;
+        self.rows_left -= 1;
+        self.position = 0;
+    }
+}
+
+impl Read for AtlasReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == self.row.len() {
+            if self.rows_left == 0 {
+                return Ok(0);
+            }
+            self.fill_next_row();
+        }
+        let len = buf.len().min(self.row.len() - self.position);
+        buf[..len].copy_from_slice(&self.row[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::{fixture::TempDir, prelude::*};
+    use vt_core::UvRect;
+
+    use super::{AtlasBuilder, SubtextureFiltering};
+    use crate::{
+        storage::{TextureMetadata, TextureStorage},
+        streaming::PageId,
+    };
+
+    #[test]
+    fn build_atlas() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.child("atlas");
+        let mut builder = AtlasBuilder::new().with_padding(1);
+        builder
+            .add_image(
+                "red",
+                image::RgbaImage::from_pixel(6, 6, image::Rgba([255, 0, 0, 255])),
+            )
+            .add_image(
+                "blue",
+                image::RgbaImage::from_pixel(4, 2, image::Rgba([0, 0, 255, 255])),
+            );
+        // Pages of 16 texels with 2 texel borders fit the 8x8 padded red image in one page.
+        let atlas = builder
+            .build(
+                TextureMetadata::from_dimensions((1, 1), 4).with_page_size(16, 2),
+                Some(path.path().to_str().unwrap()),
+                image::imageops::FilterType::Nearest,
+            )
+            .unwrap();
+        assert_eq!(atlas.storage.metadata().texel_dimensions(), (28, 28));
+
+        // The red image is the highest, so it is placed first, at the top left corner.
+        let red = atlas.uv_rects["red"];
+        assert_eq!(red.min, (1.0 / 24.0, 1.0 / 24.0));
+        assert_eq!(red.max, (7.0 / 24.0, 7.0 / 24.0));
+        let blue = atlas.uv_rects["blue"];
+        assert_eq!(blue.min, (9.0 / 24.0, 1.0 / 24.0));
+        assert_eq!(red.map((1.0, 1.0)), red.max);
+
+        // The layout is saved with the metadata.
+        let loaded = TextureStorage::load(Some(path.path().to_str().unwrap()), None).unwrap();
+        assert_eq!(
+            loaded.metadata().atlas_rects(),
+            atlas.storage.metadata().atlas_rects()
+        );
+        assert_eq!(
+            loaded.metadata().uv_transform("blue"),
+            Some(((4.0 / 24.0, 2.0 / 24.0), (9.0 / 24.0, 1.0 / 24.0)))
+        );
+        assert_eq!(loaded.metadata().uv_transform("green"), None);
+
+        let page = atlas.storage.read_page(PageId::new(0, 0, 0)).unwrap();
+        let texel = |x: usize, y: usize| &page[(y * 16 + x) * 4..(y * 16 + x) * 4 + 4];
+        // The outer border, then the padding and the image.
+        assert_eq!(texel(1, 1), [0, 0, 0, 0]);
+        assert_eq!(texel(2, 2), [255, 0, 0, 255]);
+        assert_eq!(texel(4, 4), [255, 0, 0, 255]);
+        assert_eq!(texel(11, 3), [0, 0, 255, 255]);
+        assert_eq!(texel(11, 7), [0, 0, 0, 0]);
+    }
+
+    /// Images keep their own gutter and clamp, recorded in the metadata.
+    #[test]
+    fn per_image_filtering() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.child("atlas");
+        let mut builder = AtlasBuilder::new();
+        builder
+            .add_image_with_filtering(
+                "red",
+                image::RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 0, 255])),
+                SubtextureFiltering {
+                    gutter: Some(2),
+                    clamp: false,
+                },
+            )
+            .add_image_with_filtering(
+                "blue",
+                image::RgbaImage::from_pixel(4, 2, image::Rgba([0, 0, 255, 255])),
+                SubtextureFiltering {
+                    gutter: None,
+                    clamp: true,
+                },
+            );
+        let atlas = builder
+            .build(
+                TextureMetadata::from_dimensions((1, 1), 4).with_page_size(16, 2),
+                Some(path.path().to_str().unwrap()),
+                image::imageops::FilterType::Nearest,
+            )
+            .unwrap();
+        let loaded = TextureStorage::load(Some(path.path().to_str().unwrap()), None).unwrap();
+        let rects = loaded.metadata().atlas_rects();
+        assert_eq!(
+            (rects[0].x, rects[0].gutter, rects[0].clamp),
+            (2, Some(2), None)
+        );
+        assert_eq!(
+            (rects[1].x, rects[1].gutter, rects[1].clamp),
+            (8, None, Some(true))
+        );
+        assert_eq!(loaded.metadata().uv_clamp("red"), None);
+        assert_eq!(
+            loaded.metadata().uv_clamp("blue"),
+            Some(UvRect::new((8.0 / 24.0, 0.0), (12.0 / 24.0, 2.0 / 24.0)))
+        );
+
+        let page = atlas.storage.read_page(PageId::new(0, 0, 0)).unwrap();
+        let texel = |x: usize, y: usize| &page[(y * 16 + x) * 4..(y * 16 + x) * 4 + 4];
+        // The gutter of the red image, then the blue image without one.
+        assert_eq!(texel(2, 2), [255, 0, 0, 255]);
+        assert_eq!(texel(9, 2), [255, 0, 0, 255]);
+        assert_eq!(texel(10, 2), [0, 0, 255, 255]);
+        assert_eq!(texel(10, 4), [0, 0, 0, 0]);
+    }
+}
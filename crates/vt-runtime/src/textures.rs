@@ -0,0 +1,1168 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+use crate::{
+    config::{PhysicalTextureConfig, VirtualTexturingConfig},
+    pipelines::{DepthMode, FeedbackMode, Pipelines},
+    setup::WgpuContext,
+    shader_constants,
+    storage::{
+        anisotropic_border_size, encode_page, rgba8_to_half, ColorSpace, PageEncoding, TexelFormat,
+        TextureMetadata,
+    },
+    streaming::{physical_texels, PageId, StreamingConfig},
+};
+use mip_chain::{ChangedEntries, PageTableMipChain};
+use thiserror::Error;
+
+pub use vt_core::VirtualTextureId;
+
+mod mip_chain;
+
+pub struct Textures {
+    pub feedback_mode: FeedbackMode,
+    pub depth_mode: DepthMode,
+    /// See [`VirtualTexturingConfig::msaa_samples`].
+    pub msaa_samples: u32,
+    /// The initial ratio of the prepass target, see [`VirtualTexturingConfig::prepass_ratio`].
+    pub prepass_ratio: f32,
+    /// The initial budgets of the streaming handles, see [`VirtualTexturingConfig::streaming`].
+    pub streaming_config: StreamingConfig,
+    /// See [`VirtualTexturingConfig::hi_z`].
+    pub hi_z: bool,
+    /// The size of the side of the pages in the physical texture, borders included.
+    pub page_size: u32,
+    pub border_size: u32,
+    /// See [`VirtualTexturingConfig::max_anisotropy`].
+    pub max_anisotropy: u16,
+    /// See [`VirtualTexturingConfig::physical_mip_levels`].
+    pub physical_mip_levels: u32,
+    /// Rgba8Uint entries: (R: slot_x, G: slot_y in the array layer, B: mip level of the page in
+    /// the slot and array layer of the slot, A: flags and page scale), see
+    /// [`Textures::page_table_entry`]. One array layer per virtual texture,
+    /// indexed by [`VirtualTextureId`].
+    ///
+    /// An entry whose page mip level is coarser than its own level falls back to an ancestor page.
+    /// Every entry covered by a coarse page points to the first slot of its block.
+    pub page_table_texture: wgpu::Texture,
+    /// The second page table of [`VirtualTexturingConfig::double_buffer_page_table`], sampled on
+    /// alternate frames with [`Textures::page_table_texture`].
+    pub back_page_table_texture: Option<wgpu::Texture>,
+    /// The index of the page table sampled by the frame being built, 0 for
+    /// [`Textures::page_table_texture`], see [`Textures::flip_page_tables`].
+    front_page_table: AtomicUsize,
+    /// The entries written to the back page table since the last flip, written again to the other
+    /// table once it is the back one.
+    page_table_writes: Mutex<Vec<PageTableWrite>>,
+    /// The entries of the page table of every virtual texture, as written by
+    /// [`Textures::map_page`], created on the first page mapped.
+    page_table_chains: Mutex<Vec<Option<PageTableMipChain>>>,
+    /// One physical texture per layer of the pages, see
+    /// [`TextureMetadata::layers`](crate::storage::TextureMetadata::layers). Each is a texture
+    /// array laid out by [`Textures::physical_texture`].
+    ///
+    /// A page occupies the same slot in every physical texture, so a single page table lookup
+    /// serves every layer.
+    pub physical_textures: Vec<wgpu::Texture>,
+    /// See [`VirtualTexturingConfig::physical_texture`].
+    pub physical_texture: PhysicalTextureConfig,
+    /// The format of the views of the physical textures of every layer, in both tiers.
+    ///
+    /// The textures are created in the linear variant of the format, and viewed as sRGB for the
+    /// layers in [`ColorSpace::Srgb`] when the device supports views of other formats.
+    pub layer_view_formats: Vec<wgpu::TextureFormat>,
+    /// The physical textures of the hot tier, one per layer, empty without
+    /// [`VirtualTexturingConfig::hot_cache`]. Texture arrays of a single array layer.
+    pub hot_physical_textures: Vec<wgpu::Texture>,
+    /// The coarsest mip level of the pages in the hot tier.
+    pub hot_cache_max_mip: Option<u8>,
+    /// The distinct pages requested by the feedback, written by the feedback reduction and read
+    /// back by the streaming thread. [`Pipelines::FEEDBACK_REQUESTS_BUFFER_SIZE`] bytes long.
+    pub feedback_requests_buffer: wgpu::Buffer,
+}
+
+/// A shared handle to the [`Textures`] of a
+/// [`VirtualTexturingContext`](crate::setup::VirtualTexturingContext), cheap to clone, see
+/// [`VirtualTexturingContext::textures`](crate::setup::VirtualTexturingContext::textures).
+///
+/// Consumers go through its methods instead of the fields of the textures, so that their layout
+/// (texture arrays, cache tiers, double buffered page tables) can change without breaking them.
+#[derive(Clone)]
+pub struct TextureHandle(pub(crate) Arc<Textures>);
+
+impl TextureHandle {
+    /// The pages the feedback can request: the side of mip level 0 of the page table in width
+    /// and height, and the virtual textures in depth.
+    pub fn feedback_extent(&self) -> wgpu::Extent3d {
+        self.0.page_table_texture.size()
+    }
+
+    /// The pages that can be resident at once, in the slots of every cache tier, coarse pages
+    /// taking several slots.
+    pub fn cache_page_capacity(&self) -> u32 {
+        self.0.slot_count(CacheTier::Cold) + self.0.slot_count(CacheTier::Hot)
+    }
+
+    /// The bind group of the virtual textures for the frame being built, laid out by
+    /// [`Pipelines::virtual_texture_bind_group_layout`], see
+    /// [`Pipelines::virtual_texture_bind_group`].
+    pub fn bind_group<'a>(&self, pipelines: &'a Pipelines) -> &'a wgpu::BindGroup {
+        pipelines.virtual_texture_bind_group(&self.0)
+    }
+
+    /// See [`Textures::page_table_entry`].
+    pub fn page_table_entry(
+        &self,
+        slot: (u32, u32),
+        mip: u8,
+        scale: u8,
+        tier: CacheTier,
+    ) -> [u8; 4] {
+        self.0.page_table_entry(slot, mip, scale, tier)
+    }
+
+    /// See [`Textures::slot_layout`].
+    pub fn slot_layout(&self, tier: CacheTier) -> PhysicalTextureConfig {
+        self.0.slot_layout(tier)
+    }
+
+    /// See [`Textures::cache_tier`].
+    pub fn cache_tier(&self, mip: u8) -> CacheTier {
+        self.0.cache_tier(mip)
+    }
+}
+
+/// A rectangle of entries written to a page table, see [`Textures::write_page_table_entry`].
+#[derive(Debug, Clone)]
+struct PageTableWrite {
+    texture_id: VirtualTextureId,
+    mip: u8,
+    origin: (u32, u32),
+    size: (u32, u32),
+    /// Row by row.
+    entries: Vec<[u8; 4]>,
+}
+
+/// The tier of the physical cache holding a page, see
+/// [`HotCacheConfig`](crate::config::HotCacheConfig).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CacheTier {
+    Hot,
+    Cold,
+}
+
+impl Textures {
+    /// Flag set in the alpha channel of page table entries pointing to a resident page.
+    pub const PAGE_TABLE_RESIDENT: u8 = shader_constants::PAGE_TABLE_RESIDENT as u8;
+    /// Flag set in the alpha channel of page table entries pointing to a page in the hot tier.
+    pub const PAGE_TABLE_HOT: u8 = shader_constants::PAGE_TABLE_HOT as u8;
+    /// The shift of the scale of coarse pages in the alpha channel of page table entries (see
+    /// [`CoarsePages`](crate::storage::CoarsePages)), which takes the two bits above the flags.
+    pub const PAGE_TABLE_SCALE_SHIFT: u8 = shader_constants::PAGE_TABLE_SCALE_SHIFT as u8;
+    /// The shift of the array layer of the slot in the blue channel of page table entries, whose
+    /// lower bits hold the mip level of the page.
+    pub const PAGE_TABLE_LAYER_SHIFT: u8 = shader_constants::PAGE_TABLE_LAYER_SHIFT as u8;
+    /// The most array layers of the physical textures, which fit above the mip level of the page
+    /// in the page table entries.
+    pub const MAX_PHYSICAL_LAYERS: u32 = 1 << (8 - Self::PAGE_TABLE_LAYER_SHIFT);
+    /// The number of virtual textures the feedback can tell apart, see
+    /// [`VirtualTexturingConfig::virtual_textures`].
+    pub const MAX_VIRTUAL_TEXTURES: VirtualTextureId =
+        shader_constants::FEEDBACK_MAX_TEXTURES as VirtualTextureId;
+    /// The largest page table whose pages fit in the feedback encoding, see
+    /// [`PageId::MAX_COORDINATE`](crate::streaming::PageId::MAX_COORDINATE).
+    pub const MAX_PAGE_TABLE_SIZE: u32 = crate::streaming::PageId::MAX_COORDINATE as u32 + 1;
+
+    /// The number of virtual textures of the page table, see
+    /// [`VirtualTexturingConfig::virtual_textures`].
+    pub fn virtual_texture_count(&self) -> u32 {
+        self.page_table_texture.depth_or_array_layers()
+    }
+
+    /// The index of the page table sampled by the frames built until the next
+    /// [`Textures::flip_page_tables`]: 0 for [`Textures::page_table_texture`], 1 for
+    /// [`Textures::back_page_table_texture`].
+    pub fn front_page_table_index(&self) -> usize {
+        self.front_page_table.load(Ordering::Acquire)
+    }
+
+    /// The page table sampled by the frames built until the next [`Textures::flip_page_tables`].
+    pub fn front_page_table(&self) -> &wgpu::Texture {
+        match (&self.back_page_table_texture, self.front_page_table_index()) {
+            (Some(back), 1) => back,
+            _ => &self.page_table_texture,
+        }
+    }
+
+    /// The page table written to by [`Textures::write_page_table_entry`], the front one when the
+    /// page table is not double buffered.
+    pub fn back_page_table(&self) -> &wgpu::Texture {
+        match (&self.back_page_table_texture, self.front_page_table_index()) {
+            (Some(back), 0) => back,
+            _ => &self.page_table_texture,
+        }
+    }
+
+    /// Write `entry` (see [`Textures::page_table_entry`]) at `coords` of mip level `mip` of the
+    /// page table of `texture_id`, through `queue`.
+    ///
+    /// With [`VirtualTexturingConfig::double_buffer_page_table`], the entry is written to the back
+    /// page table, which the frame being built does not sample, so the streaming thread can write
+    /// entries with its own queue writes at any time, without going through the encoder of the
+    /// frame. The entry is sampled from the frame after the next [`Textures::flip_page_tables`].
+    ///
+    /// The entry is written as is, without updating the entries of the other mip levels like
+    /// [`Textures::map_page`] does.
+    ///
+    /// ### Panics
+    ///
+    /// - If `coords` are out of mip level `mip`, or `texture_id` has no layer of the page table.
+    pub fn write_page_table_entry(
+        &self,
+        queue: &wgpu::Queue,
+        texture_id: VirtualTextureId,
+        mip: u8,
+        coords: (u32, u32),
+        entry: [u8; 4],
+    ) {
+        let side = self.page_table_texture.width() >> mip;
+        assert!(coords.0 < side && coords.1 < side);
+        assert!((texture_id as u32) < self.virtual_texture_count());
+        self.write_back_page_table(
+            queue,
+            PageTableWrite {
+                texture_id,
+                mip,
+                origin: coords,
+                size: (1, 1),
+                entries: vec![entry],
+            },
+        );
+    }
+
+    /// Write to the back page table, and record the write for the other table.
+    fn write_back_page_table(&self, queue: &wgpu::Queue, write: PageTableWrite) {
+        // Held while writing, so that a flip cannot happen between the choice of the table and
+        // the recording of the write.
+        let mut writes = self.page_table_writes.lock().unwrap();
+        write_entries(queue, self.back_page_table(), &write);
+        if self.back_page_table_texture.is_some() {
+            writes.push(write);
+        }
+    }
+
+    /// Point the entries of the page table covered by the stored page `page` (see
+    /// [`TextureMetadata::stored_page`](crate::storage::TextureMetadata::stored_page)) to
+    /// `entry` (see [`Textures::page_table_entry`]), at its mip level and at the finer ones not
+    /// covered by a finer mapped page, so that every entry points to the finest mapped page
+    /// covering it. Pages of the mip levels coarser than the page table cover all of it.
+    ///
+    /// The entries changed are written like by [`Textures::write_page_table_entry`], one
+    /// rectangle per mip level.
+    ///
+    /// ### Panics
+    ///
+    /// - If the texture of `page` has no layer of the page table.
+    pub fn map_page(&self, queue: &wgpu::Queue, page: PageId, entry: [u8; 4]) {
+        self.update_page_table_chain(queue, page.texture_id(), |chain| chain.map(page, entry));
+    }
+
+    /// Point the entries pointing to `page`, mapped by [`Textures::map_page`], to the finest
+    /// mapped page covering it instead, or mark them as not resident if there is none. Does
+    /// nothing if `page` is not mapped.
+    ///
+    /// ### Panics
+    ///
+    /// - If the texture of `page` has no layer of the page table.
+    pub fn unmap_page(&self, queue: &wgpu::Queue, page: PageId) {
+        self.update_page_table_chain(queue, page.texture_id(), |chain| chain.unmap(page));
+    }
+
+    /// [`Textures::map_page`] every page of `pages` of `texture_id` at once, writing every mip
+    /// level changed whole to every page table like [`Textures::write_page_table_level`], which
+    /// is faster when the pages cover most of the page table.
+    ///
+    /// ### Panics
+    ///
+    /// - If `texture_id` has no layer of the page table, or a page is of another texture.
+    pub fn map_pages(
+        &self,
+        queue: &wgpu::Queue,
+        texture_id: VirtualTextureId,
+        pages: impl IntoIterator<Item = (PageId, [u8; 4])>,
+    ) {
+        self.with_page_table_chain(texture_id, |chain| {
+            let mut changed_levels = pages
+                .into_iter()
+                .flat_map(|(page, entry)| {
+                    assert_eq!(page.texture_id(), texture_id);
+                    chain.map(page, entry)
+                })
+                .map(|rectangle| rectangle.mip)
+                .collect::<Vec<_>>();
+            changed_levels.sort_unstable();
+            changed_levels.dedup();
+            changed_levels.into_iter().for_each(|mip| {
+                self.write_page_table_level(queue, texture_id, mip, chain.level(mip))
+            });
+        });
+    }
+
+    /// Update the entries of `texture_id` with `update`, and write the rectangles it changed.
+    fn update_page_table_chain(
+        &self,
+        queue: &wgpu::Queue,
+        texture_id: VirtualTextureId,
+        update: impl FnOnce(&mut PageTableMipChain) -> Vec<ChangedEntries>,
+    ) {
+        // The chain stays locked while writing, so that the rectangles of concurrent updates
+        // are written in the order they were changed in.
+        self.with_page_table_chain(texture_id, |chain| {
+            update(chain).into_iter().for_each(|rectangle| {
+                let entries = chain.entries(rectangle);
+                self.write_back_page_table(
+                    queue,
+                    PageTableWrite {
+                        texture_id,
+                        mip: rectangle.mip,
+                        origin: rectangle.origin,
+                        size: rectangle.size,
+                        entries,
+                    },
+                );
+            })
+        })
+    }
+
+    fn with_page_table_chain<T>(
+        &self,
+        texture_id: VirtualTextureId,
+        f: impl FnOnce(&mut PageTableMipChain) -> T,
+    ) -> T {
+        assert!((texture_id as u32) < self.virtual_texture_count());
+        let mut chains = self.page_table_chains.lock().unwrap();
+        chains.resize_with(self.virtual_texture_count() as usize, || None);
+        let chain = chains[texture_id as usize]
+            .get_or_insert_with(|| PageTableMipChain::new(self.page_table_texture.width()));
+        f(chain)
+    }
+
+    /// Write the `entries` of every texel of mip level `mip` of the page table of `texture_id` to
+    /// every page table, so that they are sampled from the next frame built.
+    ///
+    /// Meant to fill the page table at startup (e.g., by [`Textures::map_pages`]): the entries
+    /// are written as is, without updating the entries of [`Textures::map_page`], and the entries
+    /// written by [`Textures::write_page_table_entry`] since the last flip are written
+    /// again over these ones by the next [`Textures::flip_page_tables`].
+    ///
+    /// ### Panics
+    ///
+    /// - If `entries` is not the size of mip level `mip`, or `texture_id` has no layer of the page
+    ///   table.
+    pub fn write_page_table_level(
+        &self,
+        queue: &wgpu::Queue,
+        texture_id: VirtualTextureId,
+        mip: u8,
+        entries: &[[u8; 4]],
+    ) {
+        let side = self.page_table_texture.width() >> mip;
+        assert_eq!(entries.len(), (side * side) as usize);
+        assert!((texture_id as u32) < self.virtual_texture_count());
+        std::iter::once(&self.page_table_texture)
+            .chain(&self.back_page_table_texture)
+            .for_each(|page_table| {
+                queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: page_table,
+                        mip_level: mip as u32,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: texture_id as u32,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    bytemuck::cast_slice(entries),
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(side * 4),
+                        rows_per_image: None,
+                    },
+                    wgpu::Extent3d {
+                        width: side,
+                        height: side,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            });
+    }
+
+    /// Swap the front and back page tables, to be called once per frame after its submission
+    /// ([`VirtualTexturingContext::end_frame`](crate::setup::VirtualTexturingContext::end_frame)
+    /// does). The entries written since the last flip are written again to the new back page
+    /// table, so that both tables catch up. Does nothing when the page table is not double
+    /// buffered.
+    ///
+    /// The queue orders the writes after the frames already submitted, which keep sampling the
+    /// table they were built with.
+    pub fn flip_page_tables(&self, queue: &wgpu::Queue) {
+        if self.back_page_table_texture.is_none() {
+            return;
+        }
+        let mut writes = self.page_table_writes.lock().unwrap();
+        self.front_page_table.fetch_xor(1, Ordering::AcqRel);
+        let back = self.back_page_table();
+        writes
+            .drain(..)
+            .for_each(|write| write_entries(queue, back, &write));
+    }
+
+    /// The page table entry of a resident page of mip level `mip` and `scale` (see
+    /// [`TextureMetadata::page_scale`](crate::storage::TextureMetadata::page_scale)), whose block
+    /// starts at `slot` in the physical textures of `tier`.
+    pub fn page_table_entry(
+        &self,
+        slot: (u32, u32),
+        mip: u8,
+        scale: u8,
+        tier: CacheTier,
+    ) -> [u8; 4] {
+        debug_assert!(scale <= crate::storage::CoarsePages::MAX_SCALE);
+        debug_assert!(mip < 1 << Self::PAGE_TABLE_LAYER_SHIFT);
+        let tier_flag = match tier {
+            CacheTier::Hot => Self::PAGE_TABLE_HOT,
+            CacheTier::Cold => 0,
+        };
+        let (x, y, layer) = self.slot_location(tier, slot);
+        [
+            x as u8,
+            y as u8,
+            mip | (layer as u8) << Self::PAGE_TABLE_LAYER_SHIFT,
+            Self::PAGE_TABLE_RESIDENT | tier_flag | scale << Self::PAGE_TABLE_SCALE_SHIFT,
+        ]
+    }
+
+    /// The slots of the physical textures of `tier`. The hot tier has a single array layer.
+    pub fn slot_layout(&self, tier: CacheTier) -> PhysicalTextureConfig {
+        match (tier, self.hot_physical_textures.first()) {
+            (CacheTier::Hot, Some(texture)) => PhysicalTextureConfig {
+                page_slots_x: texture.width() / self.page_size,
+                page_slots_y: texture.height() / self.page_size,
+                layers: 1,
+            },
+            (CacheTier::Hot, None) => PhysicalTextureConfig {
+                page_slots_x: 0,
+                page_slots_y: 0,
+                layers: 0,
+            },
+            (CacheTier::Cold, _) => self.physical_texture,
+        }
+    }
+
+    /// The coordinates of `slot` in its array layer of the physical textures of `tier`, and
+    /// that array layer.
+    pub fn slot_location(&self, tier: CacheTier, (x, y): (u32, u32)) -> (u32, u32, u32) {
+        let rows = self.slot_layout(tier).page_slots_y;
+        (x, y % rows, y / rows)
+    }
+
+    /// Creates the textures used by the virtual texturing system.
+    ///
+    /// Each physical texture uses the block compressed format matching the encoding of its layer
+    /// when the device supports it. Otherwise, pages are decompressed before being uploaded, BC5
+    /// layers to a two channel texture. The layers of HDR textures use a float format, see
+    /// [`VirtualTexturingConfig::pack_hdr`].
+    ///
+    /// The page size of the configuration must match the one of the textures streamed in.
+    ///
+    /// Residency always goes through the page table: wgpu exposes no sparse (partially resident)
+    /// textures whose tiles could be mapped directly, on any backend.
+    ///
+    /// ### Panics
+    ///
+    /// - If [`VirtualTexturingConfig::depth_mode`] reuses the prepass without
+    ///   [`FeedbackMode::Separate`].
+    /// - If [`VirtualTexturingConfig::feedback_mode`] is [`FeedbackMode::StorageBuffer`] without
+    ///   [`wgpu::DownlevelFlags::FRAGMENT_WRITABLE_STORAGE`] in
+    ///   [`WgpuContext::downlevel_flags`].
+    /// - If [`VirtualTexturingConfig::hi_z`] is set without [`FeedbackMode::StorageBuffer`], or
+    ///   with a multisampled render pass.
+    /// - If [`VirtualTexturingConfig::msaa_samples`] is not 1, 2, 4 or 8, is not supported for the
+    ///   format of the render target (see [`WgpuContext::supports_sample_count`]), or multisamples
+    ///   the render pass with [`DepthMode::ReusePrepass`].
+    /// - If [`VirtualTexturingConfig::max_anisotropy`] is not in `1..=16`, or if the border is
+    ///   too narrow for it.
+    /// - If [`VirtualTexturingConfig::physical_mip_levels`] is not in
+    ///   `1..=`[`VirtualTexturingConfig::max_physical_mip_levels`].
+    /// - If a layer cannot hold texels of [`VirtualTexturingConfig::texel_format`] (see
+    ///   [`TexelFormat::supports_encoding`]).
+    /// - If the page table is larger than [`Textures::MAX_PAGE_TABLE_SIZE`], or if
+    ///   [`VirtualTexturingConfig::virtual_textures`] is not in
+    ///   `1..=`[`Textures::MAX_VIRTUAL_TEXTURES`].
+    /// - If the array layers of [`VirtualTexturingConfig::physical_texture`] are larger than the
+    ///   device supports or than 256 slots on a side, or if its layers are not in
+    ///   `1..=`[`Textures::MAX_PHYSICAL_LAYERS`].
+    ///
+    /// Every slot is filled with a checker when [`VirtualTexturingConfig::debug_fill`] is set.
+    pub fn new(context: &WgpuContext, config: &VirtualTexturingConfig) -> Self {
+        let feedback_mode = config.feedback_mode;
+        assert!(
+            feedback_mode == FeedbackMode::Separate || config.depth_mode == DepthMode::Separate
+        );
+        assert!(
+            feedback_mode != FeedbackMode::StorageBuffer
+                || context
+                    .downlevel_flags
+                    .contains(wgpu::DownlevelFlags::FRAGMENT_WRITABLE_STORAGE),
+            "the storage buffer feedback requires fragment shaders writing to storage buffers"
+        );
+        assert!(
+            !config.hi_z
+                || (feedback_mode == FeedbackMode::StorageBuffer && config.msaa_samples == 1)
+        );
+        assert!([1, 2, 4, 8].contains(&config.msaa_samples));
+        assert!(
+            context.supports_sample_count(context.surface_format, config.msaa_samples),
+            "{} samples per texel are not supported for the render target format {:?}",
+            config.msaa_samples,
+            context.surface_format
+        );
+        assert!(config.msaa_samples == 1 || config.depth_mode == DepthMode::Separate);
+        assert!(config.prepass_ratio > 0.0 && config.prepass_ratio <= 1.0);
+        assert!((1..=16).contains(&config.max_anisotropy));
+        assert!(config.border_size >= anisotropic_border_size(config.max_anisotropy) as u32);
+        assert!((1..=config.max_physical_mip_levels()).contains(&config.physical_mip_levels));
+        assert!(config
+            .layer_encodings
+            .iter()
+            .all(|&encoding| config.texel_format.supports_encoding(encoding)));
+        let virtual_texture_page_wide = config.page_table_size;
+        let max_side_len = context.device.limits().max_texture_dimension_2d;
+        assert!(virtual_texture_page_wide.is_power_of_two());
+        assert!(virtual_texture_page_wide <= Self::MAX_PAGE_TABLE_SIZE);
+        assert!((1..=Self::MAX_VIRTUAL_TEXTURES as u32).contains(&config.virtual_textures));
+        debug_assert!(virtual_texture_page_wide <= max_side_len);
+        let create_page_table = |label| {
+            context.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: virtual_texture_page_wide,
+                    height: virtual_texture_page_wide,
+                    depth_or_array_layers: config.virtual_textures,
+                },
+                mip_level_count: Self::page_table_mip_levels(virtual_texture_page_wide),
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Uint,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            })
+        };
+        let page_table_texture = create_page_table("Page table texture");
+        let back_page_table_texture = config
+            .double_buffer_page_table
+            .then(|| create_page_table("Back page table texture"));
+        let layer_view_formats = config
+            .layer_encodings
+            .iter()
+            .enumerate()
+            .map(|(layer, &encoding)| match config.texel_format {
+                TexelFormat::Rgba16Float if config.pack_hdr => wgpu::TextureFormat::Rg11b10Float,
+                TexelFormat::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+                TexelFormat::R8 => wgpu::TextureFormat::R8Unorm,
+                TexelFormat::Rg8 => wgpu::TextureFormat::Rg8Unorm,
+                TexelFormat::Rgba8 => {
+                    physical_view_format(context, encoding, config.layer_color_space(layer))
+                }
+            })
+            .collect::<Vec<_>>();
+        let supports_view_formats = context
+            .downlevel_flags
+            .contains(wgpu::DownlevelFlags::VIEW_FORMATS);
+        let physical_texture = config.physical_texture;
+        assert!((1..=256).contains(&physical_texture.page_slots_x));
+        assert!((1..=256).contains(&physical_texture.page_slots_y));
+        assert!((1..=Self::MAX_PHYSICAL_LAYERS).contains(&physical_texture.layers));
+        assert!(physical_texture.layers <= context.device.limits().max_texture_array_layers);
+        let (width, height) = (
+            physical_texture.page_slots_x * config.page_size,
+            physical_texture.page_slots_y * config.page_size,
+        );
+        assert!(width <= max_side_len && height <= max_side_len);
+        let create_physical_textures = |label, size| {
+            config
+                .layer_encodings
+                .iter()
+                .zip(&layer_view_formats)
+                .map(|(&encoding, &view_format)| {
+                    let (format, view_formats) = if supports_view_formats {
+                        (view_format.remove_srgb_suffix(), &[view_format][..])
+                    } else {
+                        (view_format, &[][..])
+                    };
+                    let texture = context.device.create_texture(&wgpu::TextureDescriptor {
+                        label: Some(label),
+                        size,
+                        mip_level_count: config.physical_mip_levels,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format,
+                        usage: wgpu::TextureUsages::TEXTURE_BINDING
+                            | wgpu::TextureUsages::COPY_DST
+                            | wgpu::TextureUsages::COPY_SRC,
+                        view_formats,
+                    });
+                    if config.debug_fill {
+                        (0..config.physical_mip_levels).for_each(|mip_level| {
+                            fill_debug_pattern(
+                                context,
+                                &texture,
+                                encoding,
+                                config.page_size,
+                                mip_level,
+                            );
+                        });
+                    }
+                    texture
+                })
+                .collect::<Vec<_>>()
+        };
+        let physical_textures = create_physical_textures(
+            "Physical texture",
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: physical_texture.layers,
+            },
+        );
+        let hot_physical_textures = config.hot_cache.map_or_else(Vec::new, |hot_cache| {
+            assert!(hot_cache.size.is_multiple_of(config.page_size));
+            let side_len = hot_cache.size.min(max_side_len);
+            create_physical_textures(
+                "Hot physical texture",
+                wgpu::Extent3d {
+                    width: side_len,
+                    height: side_len,
+                    depth_or_array_layers: 1,
+                },
+            )
+        });
+        let feedback_requests_buffer = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("feedback requests buffer"),
+            size: Pipelines::FEEDBACK_REQUESTS_BUFFER_SIZE,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            feedback_mode,
+            depth_mode: config.depth_mode,
+            msaa_samples: config.msaa_samples,
+            prepass_ratio: config.prepass_ratio,
+            streaming_config: config.streaming,
+            hi_z: config.hi_z,
+            page_size: config.page_size,
+            border_size: config.border_size,
+            max_anisotropy: config.max_anisotropy,
+            physical_mip_levels: config.physical_mip_levels,
+            page_table_texture,
+            back_page_table_texture,
+            front_page_table: AtomicUsize::new(0),
+            page_table_writes: Mutex::default(),
+            page_table_chains: Mutex::default(),
+            physical_textures,
+            physical_texture,
+            layer_view_formats,
+            hot_physical_textures,
+            hot_cache_max_mip: config.hot_cache.map(|hot_cache| hot_cache.max_mip),
+            feedback_requests_buffer,
+        }
+    }
+
+    /// Creates the textures of `config` for the virtual textures of `metadata`, in the order of
+    /// their ids, after checking that the page table and the physical textures fit their pages
+    /// (see [`Textures::validate_metadata`]).
+    ///
+    /// ### Panics
+    ///
+    /// - In the cases of [`Textures::new`].
+    pub fn from_metadata(
+        context: &WgpuContext,
+        config: &VirtualTexturingConfig,
+        metadata: &[&TextureMetadata],
+    ) -> Result<Self, MetadataMismatch> {
+        Self::validate_metadata(config, metadata)?;
+        Ok(Self::new(context, config))
+    }
+
+    /// Check that the textures of `config` can hold the virtual textures of `metadata`, in the
+    /// order of their ids: the page table has a layer for each of them and is as wide as their
+    /// mip level 0, every mip level of the page table is stored, and the pages are stored with
+    /// the sizes and the texel format of the physical textures.
+    ///
+    /// The page table has no 1x1 level, so the coarsest level of a full mip chain is only
+    /// streamed in, never looked up.
+    pub fn validate_metadata(
+        config: &VirtualTexturingConfig,
+        metadata: &[&TextureMetadata],
+    ) -> Result<(), MetadataMismatch> {
+        if metadata.len() > config.virtual_textures as usize {
+            return Err(MetadataMismatch::TextureCount {
+                textures: metadata.len(),
+                virtual_textures: config.virtual_textures,
+            });
+        }
+        let page_table_mip_levels = Self::page_table_mip_levels(config.page_table_size);
+        metadata
+            .iter()
+            .enumerate()
+            .try_for_each(|(texture_id, metadata)| {
+                let texture_id = texture_id as VirtualTextureId;
+                let stored = (metadata.page_size() as u32, metadata.border_size() as u32);
+                let configured = (config.page_size, config.border_size);
+                if stored != configured {
+                    return Err(MetadataMismatch::PageSize {
+                        texture_id,
+                        stored,
+                        configured,
+                    });
+                }
+                if metadata.texel_format() != config.texel_format {
+                    return Err(MetadataMismatch::TexelFormat {
+                        texture_id,
+                        stored: metadata.texel_format(),
+                        configured: config.texel_format,
+                    });
+                }
+                // The uvs span the page table, so a narrower texture would only cover part of it.
+                let (width, height) = metadata.mip_dimensions(0);
+                if width.max(height) as u32 != config.page_table_size {
+                    return Err(MetadataMismatch::PageCount {
+                        texture_id,
+                        pages: (width, height),
+                        page_table_size: config.page_table_size,
+                    });
+                }
+                let mip_levels = metadata.mip_levels() as u32 + 1;
+                if mip_levels < page_table_mip_levels {
+                    return Err(MetadataMismatch::MipLevels {
+                        texture_id,
+                        stored: mip_levels,
+                        page_table: page_table_mip_levels,
+                    });
+                }
+                Ok(())
+            })
+    }
+
+    /// The mip levels of a page table of `page_table_size` pages on the side, down to 2 pages.
+    pub fn page_table_mip_levels(page_table_size: u32) -> u32 {
+        page_table_size.ilog2()
+    }
+
+    /// The number of slots of regular pages in the physical textures of `tier`.
+    pub fn slot_count(&self, tier: CacheTier) -> u32 {
+        self.slot_layout(tier).slot_count()
+    }
+
+    /// The tier of the physical cache holding the pages of mip level `mip`.
+    pub fn cache_tier(&self, mip: u8) -> CacheTier {
+        match self.hot_cache_max_mip {
+            Some(max_mip) if mip <= max_mip => CacheTier::Hot,
+            _ => CacheTier::Cold,
+        }
+    }
+
+    /// The physical textures of `tier`, one per layer.
+    pub fn tier_textures(&self, tier: CacheTier) -> &[wgpu::Texture] {
+        match tier {
+            CacheTier::Hot => &self.hot_physical_textures,
+            CacheTier::Cold => &self.physical_textures,
+        }
+    }
+}
+
+/// The side of the squares of the debug checker in texels, a multiple of the block size so that
+/// every block is of a single color.
+const DEBUG_SQUARE_SIZE: usize = 8;
+const DEBUG_COLORS: [[u8; 4]; 2] = [[255, 0, 255, 255], [0, 0, 0, 255]];
+
+/// A page of `page_size * page_size` RGBA8 texels, checkered with [`DEBUG_COLORS`].
+///
+/// Magenta reads the same in RGBA and BGRA physical textures, and as red in two channel ones.
+fn debug_pattern_page(page_size: usize) -> Vec<u8> {
+    (0..page_size * page_size)
+        .flat_map(|index| {
+            let (x, y) = (index % page_size, index / page_size);
+            DEBUG_COLORS[(x / DEBUG_SQUARE_SIZE + y / DEBUG_SQUARE_SIZE) % 2]
+        })
+        .collect()
+}
+
+/// Write [`debug_pattern_page`] to every slot of `mip_level` of `physical_texture`, one row of
+/// slots of an array layer at a time.
+fn fill_debug_pattern(
+    context: &WgpuContext,
+    physical_texture: &wgpu::Texture,
+    encoding: PageEncoding,
+    page_size: u32,
+    mip_level: u32,
+) {
+    let page_size = page_size >> mip_level;
+    let format = physical_texture.format();
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format
+        .block_size(None)
+        .expect("the physical texture to be a color texture");
+    let page = debug_pattern_page(page_size as usize);
+    let page = if format.is_compressed() {
+        encode_page(encoding, &page, page_size as usize)
+    } else if matches!(
+        format,
+        wgpu::TextureFormat::Rgba16Float | wgpu::TextureFormat::Rg11b10Float
+    ) {
+        physical_texels(rgba8_to_half(&page), TexelFormat::Rgba16Float, format)
+    } else {
+        physical_texels(page, TexelFormat::Rgba8, format)
+    };
+
+    let slots_x = (physical_texture.width() >> mip_level) / page_size;
+    let slots_y = (physical_texture.height() >> mip_level) / page_size;
+    let page_bytes_per_row = (page_size / block_width * block_size) as usize;
+    let slot_row = page
+        .chunks_exact(page_bytes_per_row)
+        .flat_map(|row| row.repeat(slots_x as usize))
+        .collect::<Vec<_>>();
+    let rows = (0..physical_texture.depth_or_array_layers())
+        .flat_map(|layer| (0..slots_y).map(move |slot_y| (layer, slot_y)));
+    rows.for_each(|(layer, slot_y)| {
+        context.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: physical_texture,
+                mip_level,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: slot_y * page_size,
+                    z: layer,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &slot_row,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(page_bytes_per_row as u32 * slots_x),
+                rows_per_image: Some(page_size / block_height),
+            },
+            wgpu::Extent3d {
+                width: slots_x * page_size,
+                height: page_size,
+                depth_or_array_layers: 1,
+            },
+        );
+    });
+}
+
+/// Write a rectangle of page table entries to `page_table`.
+fn write_entries(queue: &wgpu::Queue, page_table: &wgpu::Texture, write: &PageTableWrite) {
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: page_table,
+            mip_level: write.mip as u32,
+            origin: wgpu::Origin3d {
+                x: write.origin.0,
+                y: write.origin.1,
+                z: write.texture_id as u32,
+            },
+            aspect: wgpu::TextureAspect::All,
+        },
+        bytemuck::cast_slice(&write.entries),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(write.size.0 * 4),
+            rows_per_image: None,
+        },
+        wgpu::Extent3d {
+            width: write.size.0,
+            height: write.size.1,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+/// The differences between the virtual textures stored and the textures of a configuration, see
+/// [`Textures::validate_metadata`].
+#[derive(Error, Debug, PartialEq)]
+pub enum MetadataMismatch {
+    #[error(
+        "{textures} textures are stored, but the page table only has {virtual_textures} layers"
+    )]
+    TextureCount {
+        textures: usize,
+        virtual_textures: u32,
+    },
+    #[error(
+        "the pages of texture {texture_id} are stored with (page, border) sizes {stored:?}, \
+        but the physical textures have {configured:?}"
+    )]
+    PageSize {
+        texture_id: VirtualTextureId,
+        stored: (u32, u32),
+        configured: (u32, u32),
+    },
+    #[error(
+        "the texels of texture {texture_id} are stored as {stored:?}, but the physical textures \
+        hold {configured:?}"
+    )]
+    TexelFormat {
+        texture_id: VirtualTextureId,
+        stored: TexelFormat,
+        configured: TexelFormat,
+    },
+    #[error(
+        "texture {texture_id} is {} by {} pages, but the page table is {page_table_size} pages \
+        wide",
+        pages.0,
+        pages.1
+    )]
+    PageCount {
+        texture_id: VirtualTextureId,
+        pages: (u16, u16),
+        page_table_size: u32,
+    },
+    #[error(
+        "texture {texture_id} stores {stored} mip levels, but the page table has {page_table}"
+    )]
+    MipLevels {
+        texture_id: VirtualTextureId,
+        stored: u32,
+        page_table: u32,
+    },
+}
+
+/// The format sampling the pages of a layer with `encoding` in `color_space`, block compressed
+/// when the device supports it.
+///
+/// Raw layers are not viewed in the format of the surface, whose channels may be in another
+/// order than the texels of the pages.
+fn physical_view_format(
+    context: &WgpuContext,
+    page_encoding: PageEncoding,
+    color_space: ColorSpace,
+) -> wgpu::TextureFormat {
+    let supports_bc = context
+        .device
+        .features()
+        .contains(wgpu::Features::TEXTURE_COMPRESSION_BC);
+    let format = match page_encoding {
+        PageEncoding::Bc7 if supports_bc => wgpu::TextureFormat::Bc7RgbaUnorm,
+        // BC5 has no sRGB variant: normals are linear and only need two channels, see
+        // `virtual_texture_unpack_normal`.
+        PageEncoding::Bc5 if supports_bc => return wgpu::TextureFormat::Bc5RgUnorm,
+        PageEncoding::Bc5 => return wgpu::TextureFormat::Rg8Unorm,
+        _ => wgpu::TextureFormat::Rgba8Unorm,
+    };
+    match color_space {
+        ColorSpace::Srgb => format.add_srgb_suffix(),
+        ColorSpace::Linear => format,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{
+        debug_pattern_page, CacheTier, MetadataMismatch, Textures, DEBUG_COLORS, DEBUG_SQUARE_SIZE,
+    };
+    use crate::{
+        config::{HotCacheConfig, VirtualTexturingConfig},
+        debug::read_texture,
+        setup::VirtualTexturingContext,
+        storage::{decode_page, encode_page, PageEncoding, TexelFormat, TextureMetadata},
+    };
+
+    /// The sample counts the adapter does not support for the render target fail on creation,
+    /// instead of on the first frame.
+    #[test]
+    fn unsupported_sample_count() {
+        let context = crate::headless_or_skip!(64, 64);
+        assert!(context.supports_sample_count(context.surface_format, 1));
+        [2, 4, 8]
+            .into_iter()
+            .filter(|&samples| !context.supports_sample_count(context.surface_format, samples))
+            .for_each(|msaa_samples| {
+                let config = VirtualTexturingConfig {
+                    msaa_samples,
+                    ..Default::default()
+                };
+                let created = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    Textures::new(&context, &config)
+                }));
+                let message = created.err().unwrap();
+                assert!(message
+                    .downcast_ref::<String>()
+                    .unwrap()
+                    .contains("samples per texel are not supported"));
+            });
+    }
+
+    #[test]
+    fn debug_pattern_survives_block_compression() {
+        let page_size = 32;
+        let page = debug_pattern_page(page_size);
+        let texel = |page: &[u8], x: usize, y: usize| {
+            let start = (y * page_size + x) * 4;
+            [
+                page[start],
+                page[start + 1],
+                page[start + 2],
+                page[start + 3],
+            ]
+        };
+        assert_eq!(texel(&page, 0, 0), DEBUG_COLORS[0]);
+        assert_eq!(texel(&page, DEBUG_SQUARE_SIZE, 0), DEBUG_COLORS[1]);
+        assert_eq!(
+            texel(&page, DEBUG_SQUARE_SIZE, DEBUG_SQUARE_SIZE),
+            DEBUG_COLORS[0]
+        );
+
+        let decoded = decode_page(
+            PageEncoding::Bc7,
+            &encode_page(PageEncoding::Bc7, &page, page_size),
+            page_size,
+        );
+        // The p-bits of BC7 mode 6 round magenta and black by one at most.
+        assert!(decoded
+            .iter()
+            .zip(&page)
+            .all(|(decoded, texel)| decoded.abs_diff(*texel) <= 1));
+    }
+
+    #[test]
+    fn texture_handle() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let config = VirtualTexturingConfig {
+            page_size: 8,
+            border_size: 2,
+            page_table_size: 16,
+            virtual_textures: 2,
+            hot_cache: Some(HotCacheConfig {
+                size: 32,
+                max_mip: 0,
+            }),
+            ..Default::default()
+        };
+        let context = VirtualTexturingContext::from_config(Arc::new(wgpu_context), config);
+        let textures = context.textures();
+        assert_eq!(
+            textures.feedback_extent(),
+            wgpu::Extent3d {
+                width: 16,
+                height: 16,
+                depth_or_array_layers: 2,
+            }
+        );
+        assert_eq!(textures.cache_page_capacity(), 32 * 32 + 4 * 4);
+        assert_eq!(textures.cache_tier(1), CacheTier::Cold);
+    }
+
+    /// Entries go to the table the frame does not sample, and reach both tables once flipped.
+    #[test]
+    fn double_buffered_page_table() {
+        let wgpu_context = crate::headless_or_skip!(64, 64);
+        let config = VirtualTexturingConfig {
+            page_table_size: 4,
+            double_buffer_page_table: true,
+            ..Default::default()
+        };
+        let mut context = VirtualTexturingContext::from_config(Arc::new(wgpu_context), config);
+        assert_eq!(context.pipelines.virtual_texture_bind_groups.len(), 2);
+        let wgpu_context = Arc::clone(&context.wgpu_context);
+        let textures = Arc::clone(&context.textures);
+        let entry = textures.page_table_entry((3, 1), 1, 0, CacheTier::Cold);
+        textures.write_page_table_entry(&wgpu_context.queue, 0, 1, (1, 0), entry);
+        let entry_of =
+            |page_table| read_texture(&wgpu_context, page_table, 0, 1).unwrap()[4..8].to_vec();
+        assert_eq!(entry_of(textures.front_page_table()), [0; 4]);
+        assert_eq!(entry_of(textures.back_page_table()), entry);
+
+        let frame = context.begin_frame(&[]);
+        context.end_frame(frame, None);
+        assert_eq!(textures.front_page_table_index(), 1);
+        assert_eq!(entry_of(textures.front_page_table()), entry);
+        assert_eq!(entry_of(textures.back_page_table()), entry);
+    }
+
+    #[test]
+    fn validate_metadata() {
+        let config = VirtualTexturingConfig {
+            page_table_size: 32,
+            virtual_textures: 2,
+            ..Default::default()
+        };
+        let texture = |mip_levels| {
+            TextureMetadata::from_mip(mip_levels, 4)
+                .with_page_size(config.page_size as u16, config.border_size as u16)
+        };
+        let validate =
+            |metadata: &[&TextureMetadata]| Textures::validate_metadata(&config, metadata);
+
+        // 6 stored levels, the 1x1 one having no level in the page table.
+        let full = texture(5);
+        assert_eq!(validate(&[&full, &full]), Ok(()));
+        assert_eq!(
+            validate(&[&full, &full, &full]),
+            Err(MetadataMismatch::TextureCount {
+                textures: 3,
+                virtual_textures: 2
+            })
+        );
+        assert_eq!(
+            validate(&[&full, &texture(4)]),
+            Err(MetadataMismatch::PageCount {
+                texture_id: 1,
+                pages: (16, 16),
+                page_table_size: 32
+            })
+        );
+        // Written by a tool that does not store the coarse levels.
+        let truncated: TextureMetadata = miniserde::json::from_str(
+            &miniserde::json::to_string(&full).replace("\"mip_levels\":5", "\"mip_levels\":3"),
+        )
+        .unwrap();
+        assert_eq!(
+            validate(&[&truncated]),
+            Err(MetadataMismatch::MipLevels {
+                texture_id: 0,
+                stored: 4,
+                page_table: 5
+            })
+        );
+        let narrow_border =
+            TextureMetadata::from_mip(5, 4).with_page_size(config.page_size as u16, 1);
+        assert_eq!(
+            validate(&[&narrow_border]),
+            Err(MetadataMismatch::PageSize {
+                texture_id: 0,
+                stored: (config.page_size, 1),
+                configured: (config.page_size, config.border_size)
+            })
+        );
+        assert_eq!(
+            validate(&[&TextureMetadata::from_mip(5, 8)
+                .with_page_size(config.page_size as u16, config.border_size as u16)]),
+            Err(MetadataMismatch::TexelFormat {
+                texture_id: 0,
+                stored: TexelFormat::Rgba16Float,
+                configured: TexelFormat::Rgba8
+            })
+        );
+    }
+}
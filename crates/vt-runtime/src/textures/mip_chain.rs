@@ -0,0 +1,251 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{shader_constants, streaming::PageId};
+
+/// The entries of every mip level of the page table of a virtual texture, kept on the CPU so
+/// that the entries changed by mapping or unmapping a page can be computed without reading the
+/// page table back.
+///
+/// Every entry of mip level `l` points to the finest resident page of a mip level at least `l`
+/// covering it, so that the shader finds the page to sample, or its fallback, with a single
+/// lookup at the level it samples.
+pub(crate) struct PageTableMipChain {
+    /// The side of mip level 0.
+    size: u32,
+    /// The entries of every mip level of the page table, row by row.
+    levels: Vec<Vec<[u8; 4]>>,
+    /// The entries of the resident pages, by stored page (see
+    /// [`TextureMetadata::stored_page`](crate::storage::TextureMetadata::stored_page)).
+    pages: HashMap<PageId, [u8; 4]>,
+    /// The entries of the resident pages of the mip levels coarser than the page table, which
+    /// each cover the whole texture, by mip level.
+    coarse_pages: BTreeMap<u8, [u8; 4]>,
+}
+
+/// The rectangle of entries of a mip level changed by [`PageTableMipChain::map`] or
+/// [`PageTableMipChain::unmap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ChangedEntries {
+    pub mip: u8,
+    pub origin: (u32, u32),
+    pub size: (u32, u32),
+}
+
+impl PageTableMipChain {
+    /// An empty page table of `size * size` entries at mip level 0, with `size.ilog2()` mip
+    /// levels (see [`Textures::page_table_mip_levels`](super::Textures::page_table_mip_levels)).
+    pub fn new(size: u32) -> Self {
+        Self {
+            size,
+            levels: (0..super::Textures::page_table_mip_levels(size))
+                .map(|mip| vec![[0; 4]; ((size >> mip) * (size >> mip)) as usize])
+                .collect(),
+            pages: HashMap::new(),
+            coarse_pages: BTreeMap::new(),
+        }
+    }
+
+    /// The entries of mip level `mip`, row by row.
+    pub fn level(&self, mip: u8) -> &[[u8; 4]] {
+        &self.levels[mip as usize]
+    }
+
+    /// The entries of `rectangle`, row by row.
+    pub fn entries(&self, rectangle: ChangedEntries) -> Vec<[u8; 4]> {
+        let side = self.size >> rectangle.mip;
+        let level = self.level(rectangle.mip);
+        let (x, y) = rectangle.origin;
+        (y..y + rectangle.size.1)
+            .flat_map(|y| {
+                let start = (y * side + x) as usize;
+                level[start..start + rectangle.size.0 as usize]
+                    .iter()
+                    .copied()
+            })
+            .collect()
+    }
+
+    /// Point the entries covered by the stored page `page` to `entry` (see
+    /// [`Textures::page_table_entry`](super::Textures::page_table_entry)), at its mip level and
+    /// at the finer ones where no finer page is resident. Returns the rectangles changed, one per
+    /// mip level.
+    pub fn map(&mut self, page: PageId, entry: [u8; 4]) -> Vec<ChangedEntries> {
+        let mip = page.mip_level();
+        self.unmap(page);
+        if mip as usize >= self.levels.len() {
+            self.coarse_pages.insert(mip, entry);
+        } else {
+            self.pages.insert(page, entry);
+        }
+        let rectangles = self.covered(page, entry);
+        rectangles.iter().for_each(|&rectangle| {
+            self.update(rectangle, |current| {
+                (!is_resident(current) || entry_mip(current) > mip).then_some(entry)
+            })
+        });
+        rectangles
+    }
+
+    /// Point the entries pointing to the stored page `page` to the finest resident page covering
+    /// it instead, or mark them as not resident if there is none. Returns the rectangles changed,
+    /// one per mip level, none if the page was not mapped.
+    pub fn unmap(&mut self, page: PageId) -> Vec<ChangedEntries> {
+        let mip = page.mip_level();
+        let entry = if mip as usize >= self.levels.len() {
+            self.coarse_pages.remove(&mip)
+        } else {
+            self.pages.remove(&page)
+        };
+        let Some(entry) = entry else {
+            return Vec::new();
+        };
+        let rectangles = self.covered(page, entry);
+        // The finest page covering the page is the one of the entry of its parent, which only
+        // points to pages of coarser mip levels.
+        let fallback = rectangles
+            .last()
+            .filter(|rectangle| rectangle.mip == mip)
+            .and_then(|rectangle| self.parent_entry(mip, rectangle.origin))
+            .or_else(|| self.coarse_fallback(mip))
+            .unwrap_or([0; 4]);
+        rectangles.iter().for_each(|&rectangle| {
+            self.update(rectangle, |current| {
+                (is_resident(current) && entry_mip(current) == mip).then_some(fallback)
+            })
+        });
+        rectangles
+    }
+
+    /// The entry of the parent of the entry at `origin` of mip level `mip`, pointing to a page
+    /// of mip level `mip + 1` or coarser.
+    fn parent_entry(&self, mip: u8, origin: (u32, u32)) -> Option<[u8; 4]> {
+        let parent_mip = mip + 1;
+        let level = self.levels.get(parent_mip as usize)?;
+        let side = self.size >> parent_mip;
+        let entry = level[((origin.1 >> 1) * side + (origin.0 >> 1)) as usize];
+        is_resident(entry).then_some(entry)
+    }
+
+    /// The entry of the finest resident page coarser than both `mip` and the page table.
+    fn coarse_fallback(&self, mip: u8) -> Option<[u8; 4]> {
+        self.coarse_pages
+            .range(mip + 1..)
+            .next()
+            .map(|(_, &entry)| entry)
+    }
+
+    /// The rectangles of entries covered by the stored page `page` of `entry`, at its mip level,
+    /// or the coarsest one of the page table, and at every finer one.
+    fn covered(&self, page: PageId, entry: [u8; 4]) -> Vec<ChangedEntries> {
+        let mip = page.mip_level();
+        let scale = (entry[3] >> super::Textures::PAGE_TABLE_SCALE_SHIFT)
+            & shader_constants::PAGE_TABLE_SCALE_MASK as u8;
+        let (x, y, side) = (
+            (page.x() as u32) << scale,
+            (page.y() as u32) << scale,
+            1u32 << scale,
+        );
+        (0..self.levels.len().min(mip as usize + 1) as u8)
+            .filter_map(|level| {
+                let shift = (mip - level) as u32;
+                let level_side = self.size >> level;
+                let origin = (x << shift, y << shift);
+                if origin.0 >= level_side || origin.1 >= level_side {
+                    return None;
+                }
+                let size = (
+                    (side << shift).min(level_side - origin.0),
+                    (side << shift).min(level_side - origin.1),
+                );
+                Some(ChangedEntries {
+                    mip: level,
+                    origin,
+                    size,
+                })
+            })
+            .collect()
+    }
+
+    /// Replace the entries of `rectangle` for which `replace` returns an entry.
+    fn update(&mut self, rectangle: ChangedEntries, replace: impl Fn([u8; 4]) -> Option<[u8; 4]>) {
+        let side = self.size >> rectangle.mip;
+        let level = &mut self.levels[rectangle.mip as usize];
+        let (x, y) = rectangle.origin;
+        (y..y + rectangle.size.1).for_each(|y| {
+            let start = (y * side + x) as usize;
+            level[start..start + rectangle.size.0 as usize]
+                .iter_mut()
+                .for_each(|current| {
+                    if let Some(entry) = replace(*current) {
+                        *current = entry;
+                    }
+                })
+        });
+    }
+}
+
+fn is_resident(entry: [u8; 4]) -> bool {
+    entry[3] & super::Textures::PAGE_TABLE_RESIDENT != 0
+}
+
+/// The mip level of the page an entry points to.
+fn entry_mip(entry: [u8; 4]) -> u8 {
+    entry[2] & shader_constants::PAGE_TABLE_MIP_MASK as u8
+}
+
+#[cfg(test)]
+mod test {
+    use super::PageTableMipChain;
+    use crate::streaming::PageId;
+
+    /// An entry pointing to a page of `mip` in slot `slot`.
+    fn entry(slot: u8, mip: u8) -> [u8; 4] {
+        [slot, 0, mip, 1]
+    }
+
+    #[test]
+    fn finest_resident_page() {
+        let mut chain = PageTableMipChain::new(4);
+        chain.map(PageId::new(2, 0, 0), entry(1, 2));
+        assert!(chain.level(0).iter().all(|&e| e == entry(1, 2)));
+
+        let changed = chain.map(PageId::new(1, 1, 0), entry(2, 1));
+        assert_eq!(changed.len(), 2);
+        assert_eq!(
+            chain.level(1),
+            [entry(1, 2), entry(2, 1), entry(1, 2), entry(1, 2)]
+        );
+        assert_eq!(chain.level(0)[2], entry(2, 1));
+        assert_eq!(chain.level(0)[1], entry(1, 2));
+
+        // A coarser page does not replace the finer one.
+        chain.map(PageId::new(0, 3, 0), entry(3, 0));
+        chain.unmap(PageId::new(2, 0, 0));
+        chain.map(PageId::new(2, 0, 0), entry(4, 2));
+        assert_eq!(chain.level(0)[3], entry(3, 0));
+        assert_eq!(chain.level(0)[2], entry(2, 1));
+        assert_eq!(chain.level(0)[0], entry(4, 2));
+
+        // Unmapped entries fall back to the page covering them.
+        chain.unmap(PageId::new(0, 3, 0));
+        assert_eq!(chain.level(0)[3], entry(2, 1));
+        chain.unmap(PageId::new(1, 1, 0));
+        assert!(chain.level(0).iter().all(|&e| e == entry(4, 2)));
+        chain.unmap(PageId::new(2, 0, 0));
+        assert!(chain.level(0).iter().all(|&e| e == [0; 4]));
+    }
+
+    /// Pages coarser than the page table and coarse pages cover several entries of their level.
+    #[test]
+    fn coarse_pages() {
+        let mut chain = PageTableMipChain::new(8);
+        chain.map(PageId::new(3, 0, 0), entry(1, 3));
+        let coarse = [2, 0, 1, 1 | 1 << 2];
+        chain.map(PageId::new(1, 0, 0), coarse);
+        assert_eq!(chain.level(1)[..3], [coarse, coarse, entry(1, 3)]);
+        assert_eq!(chain.level(1)[4..6], [coarse; 2]);
+        assert_eq!(chain.level(0)[3..5], [coarse, entry(1, 3)]);
+        chain.unmap(PageId::new(1, 0, 0));
+        assert!(chain.level(1).iter().all(|&e| e == entry(1, 3)));
+    }
+}
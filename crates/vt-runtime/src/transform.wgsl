@@ -0,0 +1,24 @@
+// Transform snippet, shared by the prepass, the render pass, and user shaders drawing the
+// `Pipelines::draw_items`. The `camera` and `draw` bindings are declared by
+// `Pipelines::transform_shader_snippet`, which prepends them to this file.
+
+// Mirrors `pipelines::CameraUniforms`.
+struct Camera {
+    view_proj: mat4x4<f32>,
+}
+
+// Mirrors `pipelines::DrawUniforms`, bound with the dynamic offset of the item drawn.
+struct Draw {
+    model: mat4x4<f32>,
+    texture_id: u32,
+    // See `DrawItem::mip_range`.
+    min_mip: u32,
+    max_mip: u32,
+    // See `DrawItem::uv_clamp`, the min in `xy` and the max in `zw`.
+    uv_clamp: vec4<f32>,
+}
+
+// The clip space position of a vertex of the item drawn.
+fn transform_position(position: vec3<f32>) -> vec4<f32> {
+    return camera.view_proj * draw.model * vec4<f32>(position, 1.0);
+}
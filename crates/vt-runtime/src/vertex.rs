@@ -1,3 +1,9 @@
+#[cfg(feature = "gltf")]
+mod gltf;
+
+#[cfg(feature = "gltf")]
+pub use gltf::{load_gltf, GltfError, GltfMesh};
+
 /// Four triangles, in the top left, top right, bottom left, and bottom right quadrants of the screen.
 /// position: [x, y, z] with (-1, -1) being bottom left and (1, 1) being top right
 /// tex_coords: [u, v] with (0, 0) being top left and (1, 1) being bottom right
@@ -69,7 +75,7 @@ pub const FOUR_TRIANGLES: [Vertex; 12] = [
 ];
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
     position: [f32; 3],
     normal: [f32; 3],
@@ -77,6 +83,14 @@ pub struct Vertex {
 }
 
 impl Vertex {
+    pub const fn new(position: [f32; 3], normal: [f32; 3], tex_coords: [f32; 2]) -> Self {
+        Self {
+            position,
+            normal,
+            tex_coords,
+        }
+    }
+
     const ATTRIBUTES: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
         0 => Float32x3,
         1 => Float32x3,
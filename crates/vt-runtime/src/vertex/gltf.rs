@@ -0,0 +1,279 @@
+//! Loading of glTF 2.0 scenes (`.gltf` with external or embedded buffers, and `.glb`) into
+//! [`Vertex`] lists with the [`gltf`] crate, to test virtual texturing on real scenes.
+//!
+//! Only the geometry is loaded: the materials are only used to assign the virtual texture of
+//! every primitive, see [`GltfMesh::texture_id`].
+
+use std::path::Path;
+
+use miniserde::Deserialize;
+use thiserror::Error;
+
+use crate::{ensure, vertex::Vertex};
+
+/// A primitive of a mesh of the scene, with the transform of its node applied.
+#[derive(Debug, Clone)]
+pub struct GltfMesh {
+    pub name: Option<String>,
+    /// A triangle list, indices resolved, without its triangles of zero area. Primitives without
+    /// normals get flat normals, and primitives without texture coordinates get zeros.
+    pub vertices: Vec<Vertex>,
+    /// The index of the material of the primitive in the file.
+    pub material: Option<usize>,
+    /// The virtual texture sampled by the primitive (see
+    /// [`PageId::texture_id`](crate::streaming::PageId::texture_id)), from the
+    /// `"extras": { "virtual_texture": id }` of its material, 0 by default.
+    pub texture_id: u8,
+}
+
+#[derive(Error, Debug)]
+pub enum GltfError {
+    #[error("could not load the glTF file: {0}")]
+    Gltf(#[from] ::gltf::Error),
+    #[error("could not parse the extras of a material")]
+    Deserialization(#[from] miniserde::Error),
+    #[error("invalid glTF file: {0}")]
+    Invalid(&'static str),
+    #[error("unsupported glTF feature: {0}")]
+    Unsupported(String),
+}
+
+#[derive(Deserialize)]
+struct MaterialExtras {
+    virtual_texture: Option<u8>,
+}
+
+/// Load every mesh of the default scene of the glTF file at `path`, one [`GltfMesh`] per
+/// primitive and per node instancing it. Files without scenes have their meshes loaded as is.
+///
+/// ### Errors
+///
+/// - If the file or its buffers could not be read, or if it is not valid glTF.
+/// - If a node is its own ancestor, or a primitive is not a triangle list.
+pub fn load_gltf(path: &Path) -> Result<Vec<GltfMesh>, GltfError> {
+    let ::gltf::Gltf { document, blob } = ::gltf::Gltf::open(path)?;
+    let buffers = ::gltf::import_buffers(&document, path.parent(), blob)?;
+    let loader = Loader { buffers };
+
+    let mut meshes = Vec::new();
+    match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => scene.nodes().try_for_each(|node| {
+            loader.load_node(
+                node,
+                nalgebra::Matrix4::identity(),
+                &mut Vec::new(),
+                &mut meshes,
+            )
+        })?,
+        None => document.meshes().try_for_each(|mesh| {
+            loader.load_mesh(mesh, &nalgebra::Matrix4::identity(), &mut meshes)
+        })?,
+    }
+    Ok(meshes)
+}
+
+struct Loader {
+    buffers: Vec<::gltf::buffer::Data>,
+}
+
+impl Loader {
+    /// Load `node` and its descendants, `ancestors` holding the indices of the nodes above it.
+    fn load_node(
+        &self,
+        node: ::gltf::Node,
+        parent_transform: nalgebra::Matrix4<f32>,
+        ancestors: &mut Vec<usize>,
+        meshes: &mut Vec<GltfMesh>,
+    ) -> Result<(), GltfError> {
+        ensure!(
+            !ancestors.contains(&node.index()),
+            GltfError::Invalid("a node is its own ancestor")
+        );
+        let matrix = node.transform().matrix();
+        let transform =
+            parent_transform * nalgebra::Matrix4::from_column_slice(bytemuck::cast_slice(&matrix));
+        if let Some(mesh) = node.mesh() {
+            self.load_mesh(mesh, &transform, meshes)?;
+        }
+        ancestors.push(node.index());
+        node.children()
+            .try_for_each(|child| self.load_node(child, transform, ancestors, meshes))?;
+        ancestors.pop();
+        Ok(())
+    }
+
+    fn load_mesh(
+        &self,
+        mesh: ::gltf::Mesh,
+        transform: &nalgebra::Matrix4<f32>,
+        meshes: &mut Vec<GltfMesh>,
+    ) -> Result<(), GltfError> {
+        let normal_transform = transform
+            .fixed_view::<3, 3>(0, 0)
+            .try_inverse()
+            .unwrap_or_else(nalgebra::Matrix3::identity)
+            .transpose();
+
+        mesh.primitives().try_for_each(|primitive| {
+            let mode = primitive.mode();
+            ensure!(
+                mode == ::gltf::mesh::Mode::Triangles,
+                GltfError::Unsupported(format!("primitive mode {mode:?}"))
+            );
+            let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+            let positions = reader
+                .read_positions()
+                .ok_or(GltfError::Invalid("a primitive has no positions"))?
+                .collect::<Vec<_>>();
+            let normals = reader.read_normals().map(Iterator::collect::<Vec<_>>);
+            let tex_coords = reader
+                .read_tex_coords(0)
+                .map(|tex_coords| tex_coords.into_f32().collect::<Vec<_>>());
+            let indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().map(|index| index as usize).collect(),
+                None => (0..positions.len()).collect::<Vec<_>>(),
+            };
+            ensure!(
+                indices.iter().all(|&index| index < positions.len()),
+                GltfError::Invalid("a vertex index is out of bounds")
+            );
+            ensure!(
+                normals
+                    .as_ref()
+                    .is_none_or(|normals| normals.len() == positions.len())
+                    && tex_coords
+                        .as_ref()
+                        .is_none_or(|tex_coords| tex_coords.len() == positions.len()),
+                GltfError::Invalid("the attributes of a primitive have different counts")
+            );
+
+            let (normals, tex_coords) = (normals.as_deref(), tex_coords.as_deref());
+            let vertices = indices
+                .chunks_exact(3)
+                .filter_map(|triangle| {
+                    let corners = triangle
+                        .iter()
+                        .map(|&index| transform.transform_point(&positions[index].into()))
+                        .collect::<Vec<_>>();
+                    // Triangles of zero area cover no fragment, and have no normal.
+                    let flat_normal = (corners[1] - corners[0])
+                        .cross(&(corners[2] - corners[0]))
+                        .try_normalize(0.0)?;
+                    let vertices = triangle.iter().zip(corners).map(move |(&index, position)| {
+                        let normal = normals
+                            .and_then(|normals| {
+                                (normal_transform * nalgebra::Vector3::from(normals[index]))
+                                    .try_normalize(0.0)
+                            })
+                            .unwrap_or(flat_normal);
+                        let tex_coords =
+                            tex_coords.map_or([0.0; 2], |tex_coords| tex_coords[index]);
+                        Vertex::new(position.into(), normal.into(), tex_coords)
+                    });
+                    Some(vertices.collect::<Vec<_>>())
+                })
+                .flatten()
+                .collect();
+
+            let texture_id = match primitive.material().extras() {
+                Some(extras) => miniserde::json::from_str::<MaterialExtras>(extras.get())?
+                    .virtual_texture
+                    .unwrap_or(0),
+                None => 0,
+            };
+            meshes.push(GltfMesh {
+                name: mesh.name().map(str::to_owned),
+                vertices,
+                material: primitive.material().index(),
+                texture_id,
+            });
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::{fixture::TempDir, prelude::*};
+
+    use super::{load_gltf, GltfError};
+    use crate::vertex::Vertex;
+
+    /// A triangle of the x-y plane with indices and texture coordinates, but no normals, moved
+    /// by its node.
+    const TRIANGLE: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "mesh": 0, "translation": [0, 0, -1] }],
+        "meshes": [{
+            "name": "triangle",
+            "primitives": [{
+                "attributes": { "POSITION": 0, "TEXCOORD_0": 1 },
+                "indices": 2,
+                "material": 0
+            }]
+        }],
+        "materials": [{ "name": "terrain", "extras": { "virtual_texture": 2 } }],
+        "accessors": [
+            {
+                "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                "min": [0, 0, 0], "max": [1, 1, 0]
+            },
+            { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2" },
+            { "bufferView": 2, "componentType": 5123, "count": 3, "type": "SCALAR" }
+        ],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 36, "byteLength": 24 },
+            { "buffer": 0, "byteOffset": 60, "byteLength": 6 }
+        ],
+        "buffers": [{
+            "byteLength": 68,
+            "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAABAAIAAAA="
+        }]
+    }"#;
+
+    #[test]
+    fn load_embedded_triangle() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.child("triangle.gltf");
+        path.write_str(TRIANGLE).unwrap();
+
+        let meshes = load_gltf(path.path()).unwrap();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].name.as_deref(), Some("triangle"));
+        assert_eq!((meshes[0].material, meshes[0].texture_id), (Some(0), 2));
+        let expected = [
+            ([0.0, 0.0, -1.0], [0.0, 0.0]),
+            ([1.0, 0.0, -1.0], [1.0, 0.0]),
+            ([0.0, 1.0, -1.0], [0.0, 1.0]),
+        ]
+        .map(|(position, tex_coords)| Vertex::new(position, [0.0, 0.0, 1.0], tex_coords));
+        assert_eq!(meshes[0].vertices, expected);
+    }
+
+    /// Nodes that are their own ancestor are rejected instead of recursing forever, and the
+    /// triangles of zero area are dropped instead of getting NaN normals.
+    #[test]
+    fn reject_cycles_and_degenerate_triangles() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.child("cycle.gltf");
+        let cycle = TRIANGLE.replace(
+            r#""nodes": [{ "mesh": 0, "translation": [0, 0, -1] }]"#,
+            r#""nodes": [{ "mesh": 0, "children": [1] }, { "children": [0] }]"#,
+        );
+        path.write_str(&cycle).unwrap();
+        assert!(matches!(load_gltf(path.path()), Err(GltfError::Invalid(_))));
+
+        // The second and third indices are the same.
+        let path = temp_dir.child("degenerate.gltf");
+        path.write_str(&TRIANGLE.replace("AAABAAIA", "AAABAAEA"))
+            .unwrap();
+        let meshes = load_gltf(path.path()).unwrap();
+        assert!(meshes[0].vertices.is_empty());
+    }
+}
@@ -0,0 +1,264 @@
+// Sampling of the virtual texture through the page table. The bindings and the `PAGE_TABLE_*`
+// constants (see `shader_constants::PAGE_TABLE_WGSL`) are declared by
+// `Pipelines::virtual_texture_shader_snippet`, which prepends them to this file:
+//
+// - vt_page_table: texture_2d_array<u32>, see `Textures::page_table_texture`, with one layer per
+//   virtual texture. The sampling functions look up the layer of their `texture_id` (the
+//   `draw.texture_id` of the item drawn, see `transform.wgsl`).
+// - vt_nearest_sampler, vt_linear_sampler: sampler.
+// - vt: VirtualTextureUniforms.
+// - vt_layer_0, vt_layer_1, ...: texture_2d_array<f32>, the physical texture of every layer,
+//   which is passed to the sampling functions. Every layer has the same layout, so the page table
+//   lookup is the same for all of them.
+// - vt_hot_layer_0, vt_hot_layer_1, ...: texture_2d_array<f32>, the physical textures of the hot
+//   tier, passed along with the layer of the same index. The page table entry tells which one is
+//   sampled, and in which array layer.
+
+// Same layout as `pipelines::FeedbackUniforms`, the buffer is shared with the feedback pass.
+struct VirtualTextureUniforms {
+    feedback_lod_bias: f32,
+    page_size: u32,
+    border_size: u32,
+    page_table_size: u32,
+    max_anisotropy: u32,
+    physical_mip_levels: u32,
+    virtual_textures: u32,
+}
+
+// The coarsest mip level of the page table, which has log2(page_table_size) levels (see
+// `Textures::new`). Not `textureNumLevels`, which GL backends do not all support.
+fn virtual_texture_max_mip() -> u32 {
+    return max(firstLeadingBit(vt.page_table_size), 1u) - 1u;
+}
+
+// The derivatives of the texel coordinates of `uv` at mip level 0, in the columns.
+//
+// Must be called in uniform control flow.
+fn virtual_texture_texel_derivatives(uv: vec2<f32>) -> mat2x2<f32> {
+    let texel_width_per_page = vt.page_size - 2u * vt.border_size;
+    let tex_coords = uv * f32(texel_width_per_page * vt.page_table_size);
+    return mat2x2<f32>(dpdx(tex_coords), dpdy(tex_coords));
+}
+
+// Level of detail of the texel `derivatives`, in mip levels of the page table. With anisotropic
+// filtering, the level only resolves the minor axis of the footprint, at most
+// `vt.max_anisotropy` times shorter than the major one, like the feedback does.
+fn virtual_texture_derivatives_lod(derivatives: mat2x2<f32>) -> f32 {
+    let px = dot(derivatives[0], derivatives[0]);
+    let py = dot(derivatives[1], derivatives[1]);
+    let major_lod = 0.5 * log2(max(px, py));
+    let minor_lod = 0.5 * log2(min(px, py));
+    let lod = major_lod - min(major_lod - minor_lod, log2(f32(vt.max_anisotropy)));
+    // NaN derivatives sample the finest mip level.
+    if lod != lod {
+        return 0.0;
+    }
+    let max_lod = f32(virtual_texture_max_mip());
+    return clamp(lod, 0.0, max_lod);
+}
+
+// Level of detail of the virtual texture at `uv`, in mip levels of the page table.
+//
+// Must be called in uniform control flow, since it uses derivatives.
+fn virtual_texture_lod(uv: vec2<f32>) -> f32 {
+    return virtual_texture_derivatives_lod(virtual_texture_texel_derivatives(uv));
+}
+
+// `uv` clamped to `rect` (the min in `xy` and the max in `zw`, see `DrawItem::uv_clamp`), shrunk
+// by half a texel of the coarser of the two mip levels blended at `uv`, so that the bilinear taps
+// stay within the rect. Rects thinner than a texel clamp to their center.
+//
+// Must be called in uniform control flow, since it uses derivatives.
+fn virtual_texture_clamp_uv(uv: vec2<f32>, rect: vec4<f32>) -> vec2<f32> {
+    let lod = virtual_texture_lod(uv);
+    let texel_width = f32((vt.page_size - 2u * vt.border_size) * vt.page_table_size);
+    let half_texel = 0.5 * exp2(ceil(lod)) / texel_width;
+    let center = 0.5 * (rect.xy + rect.zw);
+    let low = min(rect.xy + half_texel, center);
+    let high = max(rect.zw - half_texel, center);
+    return clamp(uv, low, high);
+}
+
+// The texel coordinates in the physical textures of `uv`, looked up at `mip` in the page table
+// of `texture_id`, in `xy`, within the array layer `w`. `z` is 1 if the page is in the hot tier,
+// and 0 if it is in the cold one.
+//
+// Returns a negative coordinate if no page covering `uv` is resident, or if `uv` is NaN. Uvs
+// out of [0, 1] are clamped to the edge of the texture.
+fn virtual_texture_physical_texel(uv: vec2<f32>, texture_id: u32, mip: u32) -> vec4<f32> {
+    let texel = virtual_texture_page_texel(uv, texture_id, mip);
+    return vec4<f32>(texel.coords, f32(texel.hot), f32(texel.layer));
+}
+
+// A texel of the physical textures, see `virtual_texture_page_texel`.
+struct VirtualTexturePageTexel {
+    // Negative if no page is resident.
+    coords: vec2<f32>,
+    // The array layer of the slot.
+    layer: u32,
+    hot: bool,
+    // The mip level of the page, which may be coarser than the one looked up.
+    mip: u32,
+}
+
+// `virtual_texture_physical_texel`, with the mip level of the page.
+fn virtual_texture_page_texel(uv: vec2<f32>, texture_id: u32, mip: u32) -> VirtualTexturePageTexel {
+    let missing = VirtualTexturePageTexel(vec2<f32>(-1.0), 0u, false, 0u);
+    if uv.x != uv.x || uv.y != uv.y {
+        return missing;
+    }
+    let mip_size = max(vt.page_table_size >> mip, 1u);
+    let clamped_uv = clamp(uv, vec2<f32>(0.0), vec2<f32>(1.0));
+    let page_coords = min(vec2<u32>(clamped_uv * f32(mip_size)), vec2<u32>(mip_size - 1u));
+    let entry = textureLoad(vt_page_table, page_coords, i32(texture_id), i32(mip));
+    if (entry.a & PAGE_TABLE_RESIDENT) == 0u {
+        return missing;
+    }
+
+    // The entry may point to a coarser page than its own level, and to a coarse page covering
+    // `1 << scale` pages of its level on each side.
+    let scale = (entry.a >> PAGE_TABLE_SCALE_SHIFT) & PAGE_TABLE_SCALE_MASK;
+    let page_mip = entry.b & PAGE_TABLE_MIP_MASK;
+    let page_mip_size = max(vt.page_table_size >> page_mip, 1u);
+    // Not `fract`, which would wrap around at the right and bottom edges of the texture.
+    let page_origin = vec2<f32>((page_coords >> vec2<u32>(page_mip - mip + scale)) << vec2<u32>(scale));
+    let in_page = min((clamped_uv * f32(page_mip_size) - page_origin) / f32(1u << scale), vec2<f32>(1.0));
+    let stride = f32((vt.page_size - 2u * vt.border_size) << scale);
+    let texel = vec2<f32>(entry.rg * vt.page_size + vt.border_size) + in_page * stride;
+    let hot = (entry.a & PAGE_TABLE_HOT) != 0u;
+    return VirtualTexturePageTexel(texel, entry.b >> PAGE_TABLE_LAYER_SHIFT, hot, page_mip);
+}
+
+fn virtual_texture_sample_mip(
+    layer: texture_2d_array<f32>,
+    hot_layer: texture_2d_array<f32>,
+    uv: vec2<f32>,
+    texture_id: u32,
+    mip: u32,
+    vt_sampler: sampler,
+) -> vec4<f32> {
+    let texel = virtual_texture_page_texel(uv, texture_id, mip);
+    if texel.coords.x < 0.0 {
+        return vec4<f32>(0.0);
+    }
+    if texel.hot {
+        let hot_uv = texel.coords / vec2<f32>(textureDimensions(hot_layer));
+        return textureSampleLevel(hot_layer, vt_sampler, hot_uv, texel.layer, 0.0);
+    }
+    let cold_uv = texel.coords / vec2<f32>(textureDimensions(layer));
+    return textureSampleLevel(layer, vt_sampler, cold_uv, texel.layer, 0.0);
+}
+
+// Filtering of the page at `mip` with the texel `derivatives` of `uv` at mip level 0 (see
+// `virtual_texture_texel_derivatives`), scaled to the mip level of the page, which lets the
+// linear sampler filter anisotropically.
+fn virtual_texture_sample_mip_grad(
+    layer: texture_2d_array<f32>,
+    hot_layer: texture_2d_array<f32>,
+    uv: vec2<f32>,
+    texture_id: u32,
+    mip: u32,
+    derivatives: mat2x2<f32>,
+) -> vec4<f32> {
+    let texel = virtual_texture_page_texel(uv, texture_id, mip);
+    if texel.coords.x < 0.0 {
+        return vec4<f32>(0.0);
+    }
+    let page_derivatives = derivatives * (1.0 / f32(1u << texel.mip));
+    if texel.hot {
+        let size = vec2<f32>(textureDimensions(hot_layer));
+        return textureSampleGrad(
+            hot_layer,
+            vt_linear_sampler,
+            texel.coords / size,
+            texel.layer,
+            page_derivatives[0] / size,
+            page_derivatives[1] / size,
+        );
+    }
+    let size = vec2<f32>(textureDimensions(layer));
+    return textureSampleGrad(
+        layer,
+        vt_linear_sampler,
+        texel.coords / size,
+        texel.layer,
+        page_derivatives[0] / size,
+        page_derivatives[1] / size,
+    );
+}
+
+// Point sampling of the closest mip level.
+fn virtual_texture_sample_nearest(
+    layer: texture_2d_array<f32>,
+    hot_layer: texture_2d_array<f32>,
+    uv: vec2<f32>,
+    texture_id: u32,
+) -> vec4<f32> {
+    let lod = virtual_texture_lod(uv);
+    let mip = u32(round(lod));
+    return virtual_texture_sample_mip(layer, hot_layer, uv, texture_id, mip, vt_nearest_sampler);
+}
+
+// Bilinear (or anisotropic) sampling within the page of the closest mip level. The borders of
+// the pages make the filtering seamless across pages. With `vt.physical_mip_levels`, the sampler
+// blends in the mip chain of the slot where the page is finer than the level of detail.
+fn virtual_texture_sample_linear(
+    layer: texture_2d_array<f32>,
+    hot_layer: texture_2d_array<f32>,
+    uv: vec2<f32>,
+    texture_id: u32,
+) -> vec4<f32> {
+    let derivatives = virtual_texture_texel_derivatives(uv);
+    let mip = u32(round(virtual_texture_derivatives_lod(derivatives)));
+    return virtual_texture_sample_mip_grad(layer, hot_layer, uv, texture_id, mip, derivatives);
+}
+
+// Bilinear (or anisotropic) sampling of the two closest mip levels, blended together.
+//
+// With `vt.physical_mip_levels`, the page of the finer level is blended with the mip chain of its
+// slot by the sampler, since the page of the coarser level would pop between the frames it is
+// resident and the ones it is not. The level of detail of the sampler is the one of the texel
+// derivatives past the mip level of the page.
+fn virtual_texture_sample_trilinear(
+    layer: texture_2d_array<f32>,
+    hot_layer: texture_2d_array<f32>,
+    uv: vec2<f32>,
+    texture_id: u32,
+) -> vec4<f32> {
+    let derivatives = virtual_texture_texel_derivatives(uv);
+    let lod = virtual_texture_derivatives_lod(derivatives);
+    let max_mip = virtual_texture_max_mip();
+    let fine_mip = u32(floor(lod));
+    if vt.physical_mip_levels > 1u {
+        return virtual_texture_sample_mip_grad(layer, hot_layer, uv, texture_id, fine_mip, derivatives);
+    }
+    let coarse_mip = min(fine_mip + 1u, max_mip);
+    let fine = virtual_texture_sample_mip_grad(layer, hot_layer, uv, texture_id, fine_mip, derivatives);
+    let coarse = virtual_texture_sample_mip_grad(layer, hot_layer, uv, texture_id, coarse_mip, derivatives);
+    return mix(fine, coarse, fract(lod));
+}
+
+// The tangent space normal stored in the red and green channels of a BC5 (or two channel) layer,
+// with its Z reconstructed. `sample` is the result of any of the sampling functions for a resident
+// page.
+fn virtual_texture_unpack_normal(sample: vec4<f32>) -> vec3<f32> {
+    let xy = sample.rg * 2.0 - 1.0;
+    let z = sqrt(saturate(1.0 - dot(xy, xy)));
+    return vec3<f32>(xy, z);
+}
+
+// Bilinear sampling of a BC5 normal layer, see `virtual_texture_unpack_normal`. Non resident pages
+// give a flat normal.
+fn virtual_texture_sample_normal(
+    layer: texture_2d_array<f32>,
+    hot_layer: texture_2d_array<f32>,
+    uv: vec2<f32>,
+    texture_id: u32,
+) -> vec3<f32> {
+    let mip = u32(round(virtual_texture_lod(uv)));
+    if virtual_texture_physical_texel(uv, texture_id, mip).x < 0.0 {
+        return vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let sample = virtual_texture_sample_mip(layer, hot_layer, uv, texture_id, mip, vt_linear_sampler);
+    return virtual_texture_unpack_normal(sample);
+}
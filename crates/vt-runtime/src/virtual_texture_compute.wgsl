@@ -0,0 +1,23 @@
+// Sampling of the virtual texture from compute shaders, which have no derivatives to pick the
+// level of detail from. Prepended by `Pipelines::virtual_texture_compute_shader_snippet`, after
+// `virtual_texture.wgsl` whose functions it builds on.
+
+// Bilinear sampling of `uv` in the virtual texture `texture_id` at `mip`, clamped to the coarsest
+// mip level. Falls back to the coarser resident pages like the other sampling functions, and gives
+// transparent black if no page covering `uv` is resident.
+fn virtual_texture_sample_level(
+    layer: texture_2d_array<f32>,
+    hot_layer: texture_2d_array<f32>,
+    uv: vec2<f32>,
+    texture_id: u32,
+    mip: u32,
+) -> vec4<f32> {
+    let clamped_mip = min(mip, virtual_texture_max_mip());
+    return virtual_texture_sample_mip(layer, hot_layer, uv, texture_id, clamped_mip, vt_linear_sampler);
+}
+
+// Whether a page of `texture_id` covering `uv` at `mip`, or one of its ancestors, is resident.
+fn virtual_texture_is_resident(uv: vec2<f32>, texture_id: u32, mip: u32) -> bool {
+    let clamped_mip = min(mip, virtual_texture_max_mip());
+    return virtual_texture_physical_texel(uv, texture_id, clamped_mip).x >= 0.0;
+}
@@ -0,0 +1,103 @@
+//! The full loop of the streaming over a static view, driven as an application drives it.
+
+use std::sync::Arc;
+
+use vt_runtime::{
+    camera::{Camera, CameraModule, CameraProjection},
+    config::{PhysicalTextureConfig, VirtualTexturingConfig},
+    draw::{DrawItem, Mesh},
+    storage::TextureMetadata,
+    streaming::{SlotAllocator, StreamingHandle},
+    test_support::StreamingFixture,
+    textures::CacheTier,
+    vertex::FOUR_TRIANGLES,
+};
+
+/// The frames the residency must converge within.
+const MAX_FRAMES: usize = 60;
+
+/// The pages requested are streamed in until they are all resident, and then the view stops
+/// requesting and uploading pages.
+#[test]
+fn converge_residency() {
+    let wgpu_context = vt_runtime::headless_or_skip!(64, 64);
+    let color = [200, 100, 50, 255];
+    // Two virtual textures and two array layers, so that the GL backend creates array textures
+    // for the page table and the physical textures.
+    let config = VirtualTexturingConfig {
+        page_table_size: 4,
+        virtual_textures: 2,
+        page_size: 32,
+        border_size: 4,
+        prepass_ratio: 1.0,
+        physical_texture: PhysicalTextureConfig {
+            page_slots_x: 4,
+            page_slots_y: 4,
+            layers: 2,
+        },
+        ..Default::default()
+    };
+    let StreamingFixture {
+        mut context,
+        mut streaming,
+        temp_dir: _temp_dir,
+        ..
+    } = StreamingFixture::filled(
+        wgpu_context,
+        TextureMetadata::from_mip(2, 4).with_page_size(32, 4),
+        config,
+        &color,
+    );
+    context.update_camera(&CameraModule::from_parts(
+        Camera::new(
+            nalgebra::Point3::new(0.0, 0.0, 1.0),
+            -std::f32::consts::FRAC_PI_2,
+            0.0,
+        ),
+        CameraProjection::new(1.0, 1.5, 0.1, 100.0),
+        Default::default(),
+    ));
+    let mut slots = SlotAllocator::with_layout(context.textures().slot_layout(CacheTier::Cold));
+    let items = [DrawItem::new(Arc::new(Mesh::new(
+        &context.wgpu_context,
+        &FOUR_TRIANGLES,
+    )))];
+
+    // Every frame queues the pages of the latest feedback, and streams them in within the
+    // budget of the frame. The requests are of the ideal mip level whatever is resident, so the
+    // residency has converged once a feedback only requests resident pages. Returns the pages
+    // streamed in, once a feedback was read back.
+    let mut frame = |streaming: &mut StreamingHandle, slots: &mut SlotAllocator| {
+        let commands = context.begin_frame(&items);
+        context.end_frame(commands, Some(streaming));
+        streaming.wait_feedback();
+        let requests = streaming.request_traces().pop()?;
+        streaming.queue_requests(&requests);
+        let streamed = streaming.stream_queued(|_| slots.allocate(0));
+        assert!(streamed.iter().all(|page| page.result.is_ok()));
+        Some(streamed.len())
+    };
+    assert!(
+        (0..MAX_FRAMES).any(|_| frame(&mut streaming, &mut slots) == Some(0)),
+        "the residency did not converge within {MAX_FRAMES} frames"
+    );
+
+    let requested = streaming.request_traces().pop().unwrap();
+    assert!(!requested.is_empty());
+    // The view is close enough for some of the pages of mip level 0 to be requested.
+    assert!(requested.iter().any(|page| page.mip_level() == 0));
+    let residency = streaming.residency();
+    assert!(requested.iter().all(|&page| residency.is_resident(page)));
+    assert_eq!(streaming.queued_requests(), 0);
+
+    let stats = streaming.stats();
+    (0..3).for_each(|_| assert_eq!(frame(&mut streaming, &mut slots), Some(0)));
+    let since = streaming.stats().since(&stats);
+    assert_eq!((since.uploaded_pages, since.missed_pages), (0, 0));
+    assert!(since.requested_pages > 0);
+
+    // The top left triangle samples the texture.
+    let image = context.wgpu_context.read_offscreen_target().unwrap();
+    let texel = image.get_pixel(10, 10).0;
+    assert!(texel.iter().zip(color).all(|(&a, b)| a.abs_diff(b) <= 2));
+}
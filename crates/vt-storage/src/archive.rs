@@ -0,0 +1,181 @@
+//! Single-file packed texture archives.
+//!
+//! Layout (little endian):
+//! - `MAGIC`, then the format version as a `u32`.
+//! - The length of the metadata as a `u32`, followed by the metadata as json.
+//! - The number of pages as a `u64`, followed by an `(offset: u64, length: u64)` entry per page, in
+//!   the order of `TextureMetadata::page_index`.
+//! - The pages, each starting at an offset aligned to `PAGE_ALIGNMENT`.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use vt_core::ensure;
+
+use crate::{
+    reader::row_file_path, TextureMetadata, TextureReader, TextureStorage, TextureStorageError,
+};
+
+const MAGIC: [u8; 4] = *b"VTPK";
+const VERSION: u32 = 1;
+/// Alignment of pages in the archive, matching the usual page size of file systems.
+const PAGE_ALIGNMENT: u64 = 4096;
+const TABLE_ENTRY_SIZE: usize = 2 * std::mem::size_of::<u64>();
+
+pub(crate) struct PackedArchive {
+    file: File,
+    /// (offset, length) of every page.
+    pages: Vec<(u64, u64)>,
+}
+
+impl PackedArchive {
+    fn open(path: &Path) -> Result<(Self, TextureMetadata), TextureStorageError> {
+        let mut file = File::open(path)?;
+        let (pages, metadata) = read_index(|buffer| Ok(file.read_exact(buffer)?))?;
+        Ok((Self { file, pages }, metadata))
+    }
+
+    pub(crate) fn read_page(&self, index: usize) -> Result<Vec<u8>, TextureStorageError> {
+        let (offset, length) = self.pages[index];
+        let mut page = vec![0; length as usize];
+        read_exact_at(&self.file, &mut page, offset)?;
+        Ok(page)
+    }
+}
+
+/// Read the metadata and the `(offset, length)` of every page from the start of an archive,
+/// `read_exact` filling its buffer with the next bytes of the archive.
+pub(crate) fn read_index(
+    mut read_exact: impl FnMut(&mut [u8]) -> Result<(), TextureStorageError>,
+) -> Result<(Vec<(u64, u64)>, TextureMetadata), TextureStorageError> {
+    let mut header = [0; 12];
+    read_exact(&mut header)?;
+    ensure!(header[..4] == MAGIC, TextureStorageError::InvalidArchive);
+    ensure!(
+        u32::from_le_bytes(header[4..8].try_into().unwrap()) == VERSION,
+        TextureStorageError::InvalidArchive
+    );
+
+    let metadata_len = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    let mut metadata = vec![0; metadata_len as usize];
+    read_exact(&mut metadata)?;
+    let metadata: TextureMetadata = miniserde::json::from_str(
+        std::str::from_utf8(&metadata).map_err(|_| TextureStorageError::InvalidArchive)?,
+    )?;
+
+    let mut page_count = [0; 8];
+    read_exact(&mut page_count)?;
+    let page_count = u64::from_le_bytes(page_count) as usize;
+    ensure!(
+        page_count == metadata.page_count(),
+        TextureStorageError::InvalidArchive
+    );
+
+    let mut table = vec![0; page_count * TABLE_ENTRY_SIZE];
+    read_exact(&mut table)?;
+    let pages = table
+        .chunks_exact(TABLE_ENTRY_SIZE)
+        .map(|entry| {
+            (
+                u64::from_le_bytes(entry[..8].try_into().unwrap()),
+                u64::from_le_bytes(entry[8..].try_into().unwrap()),
+            )
+        })
+        .collect();
+    Ok((pages, metadata))
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buffer: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buffer, offset)
+}
+
+#[cfg(not(unix))]
+fn read_exact_at(mut file: &File, buffer: &mut [u8], offset: u64) -> std::io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buffer)
+}
+
+impl TextureStorage {
+    /// Pack every page of the texture into a single archive at `path`, and read pages from it from
+    /// now on.
+    ///
+    /// The row files are deleted once the archive is written. A packed texture can not be written
+    /// to anymore.
+    pub fn pack(&mut self, path: &Path) -> Result<(), TextureStorageError> {
+        ensure!(self.reader.archive.is_none(), TextureStorageError::Packed);
+
+        let metadata = miniserde::json::to_string(&self.metadata());
+        let page_count = self.metadata().page_count();
+        let table_start = (12 + metadata.len() + 8) as u64;
+        let data_start =
+            (table_start + (page_count * TABLE_ENTRY_SIZE) as u64).next_multiple_of(PAGE_ALIGNMENT);
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&MAGIC)?;
+        file.write_all(&VERSION.to_le_bytes())?;
+        file.write_all(&(metadata.len() as u32).to_le_bytes())?;
+        file.write_all(metadata.as_bytes())?;
+        file.write_all(&(page_count as u64).to_le_bytes())?;
+
+        let mut table = Vec::with_capacity(page_count * TABLE_ENTRY_SIZE);
+        let mut offset = data_start;
+        file.seek(SeekFrom::Start(offset))?;
+        for mip in 0..=self.metadata().mip_levels {
+            let (width, height) = self.metadata().page_grid(mip);
+            for y in 0..height {
+                for x in 0..width {
+                    let page = self.reader.read_stored_page(mip, x, y)?;
+                    let padding =
+                        (page.len() as u64).next_multiple_of(PAGE_ALIGNMENT) - page.len() as u64;
+                    file.write_all(&page)?;
+                    file.write_all(&vec![0; padding as usize])?;
+
+                    table.extend_from_slice(&offset.to_le_bytes());
+                    table.extend_from_slice(&(page.len() as u64).to_le_bytes());
+                    offset += page.len() as u64 + padding;
+                }
+            }
+        }
+        file.seek(SeekFrom::Start(table_start))?;
+        file.write_all(&table)?;
+        file.flush()?;
+        drop(file);
+
+        for mip in 0..=self.metadata().mip_levels {
+            let (_, height) = self.metadata().page_grid(mip);
+            for row in 0..height {
+                std::fs::remove_file(row_file_path(&self.reader.directory, (mip, row)))?;
+            }
+        }
+
+        let (archive, _) = PackedArchive::open(path)?;
+        self.reader.archive = Some(Arc::new(archive));
+        log::debug!("packed {} pages into {}", page_count, path.display());
+        Ok(())
+    }
+
+    /// Load a texture from an archive written by [`TextureStorage::pack`].
+    pub fn load_packed(path: &Path) -> Result<Self, TextureStorageError> {
+        let (archive, metadata) = PackedArchive::open(path)?;
+        Ok(Self {
+            reader: TextureReader {
+                directory: path.parent().unwrap_or(Path::new("")).into(),
+                metadata: Arc::new(metadata),
+                archive: Some(Arc::new(archive)),
+                row_files: Default::default(),
+            },
+            metadata_path: Default::default(),
+            #[cfg(feature = "image")]
+            pending_rows: Default::default(),
+            #[cfg(feature = "image")]
+            pending_coarse_rows: Default::default(),
+            #[cfg(feature = "image")]
+            detect_missing_pages: false,
+        })
+    }
+}
@@ -0,0 +1,77 @@
+//! The layout of the images packed in a texture by an `AtlasBuilder` (in `vt-runtime`), kept in
+//! the metadata.
+
+use miniserde::{Deserialize, MiniSerialize};
+
+use vt_core::UvRect;
+
+use crate::TextureMetadata;
+
+/// An image of an atlas, in texels of the texture without its outer border (the space of the uv
+/// coordinates).
+#[derive(MiniSerialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AtlasRect {
+    pub name: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// The texels repeating the edges of the image on each side of the rect, so that bilinear
+    /// taps near its edges do not reach the neighbouring images (`None` for none).
+    pub gutter: Option<u32>,
+    /// Whether the render pass clamps the uv coordinates of the image to the rect, minus half a
+    /// texel of the mip level sampled, see [`TextureMetadata::uv_clamp`] (`None` for no clamp).
+    /// Unlike the gutter, the clamp also holds at the coarse mip levels, whose texels span
+    /// several images.
+    pub clamp: Option<bool>,
+}
+
+impl TextureMetadata {
+    /// Record the images packed in the texture, saved with the metadata.
+    pub fn with_atlas(mut self, rects: Vec<AtlasRect>) -> Self {
+        self.atlas = Some(rects);
+        self
+    }
+
+    /// The images packed in the texture, empty if it is not an atlas.
+    pub fn atlas_rects(&self) -> &[AtlasRect] {
+        self.atlas.as_deref().unwrap_or_default()
+    }
+
+    /// Where the image `name` of the atlas is in the texture.
+    pub fn uv_rect(&self, name: &str) -> Option<UvRect> {
+        let rect = self.atlas_rects().iter().find(|rect| rect.name == name)?;
+        let ((x, y), (width, height)) = self.atlas_uv_transform(rect);
+        Some(UvRect::new((x, y), (x + width, y + height)))
+    }
+
+    /// The rect the uv coordinates of the image `name` are clamped to by the shaders, `None` if
+    /// the image does not clamp (see [`AtlasRect::clamp`]). Set it as the
+    /// `DrawItem::uv_clamp` (in `vt-runtime`) of the meshes textured by the image.
+    pub fn uv_clamp(&self, name: &str) -> Option<UvRect> {
+        let rect = self.atlas_rects().iter().find(|rect| rect.name == name)?;
+        if !rect.clamp.unwrap_or(false) {
+            return None;
+        }
+        self.uv_rect(name)
+    }
+
+    /// The `(scale, offset)` remapping the uv coordinates of a mesh textured by the image `name`
+    /// to the ones of the atlas, as `uv * scale + offset`.
+    pub fn uv_transform(&self, name: &str) -> Option<((f32, f32), (f32, f32))> {
+        let rect = self.atlas_rects().iter().find(|rect| rect.name == name)?;
+        let (offset, scale) = self.atlas_uv_transform(rect);
+        Some((scale, offset))
+    }
+
+    /// The uv coordinates of the top left corner of `rect`, and its size in uv.
+    fn atlas_uv_transform(&self, rect: &AtlasRect) -> ((f32, f32), (f32, f32)) {
+        let (width, height) = self.texel_dimensions();
+        let border_size = 2 * self.border_size() as u32;
+        let (width, height) = ((width - border_size) as f32, (height - border_size) as f32);
+        (
+            (rect.x as f32 / width, rect.y as f32 / height),
+            (rect.width as f32 / width, rect.height as f32 / height),
+        )
+    }
+}
@@ -0,0 +1,278 @@
+//! Block compression of pages (BC7 and BC5).
+//!
+//! Only BC7 mode 6 (one subset, RGBA endpoints with p-bits, 4-bit indices) is produced by the
+//! encoder, which keeps it simple while still giving good results on smooth texture content.
+//! The decoder only needs to understand what the encoder writes.
+
+use crate::PageEncoding;
+
+/// Size of the side of a block in texels.
+pub const BLOCK_SIZE: usize = 4;
+/// Size of an encoded block in bytes, for both BC7 and BC5.
+pub const BLOCK_BYTES: usize = 16;
+
+const BC7_WEIGHTS: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// Encode a page made of `page_size * page_size` RGBA8 texels.
+///
+/// Returns the page untouched if the encoding is [`PageEncoding::Raw`].
+pub fn encode_page(encoding: PageEncoding, page: &[u8], page_size: usize) -> Vec<u8> {
+    let encode_block: fn(&[[u8; 4]; 16], &mut [u8]) = match encoding {
+        PageEncoding::Raw => return page.to_vec(),
+        PageEncoding::Bc7 => encode_bc7_block,
+        PageEncoding::Bc5 => encode_bc5_block,
+    };
+    debug_assert!(page.len() == page_size * page_size * 4);
+    debug_assert!(page_size.is_multiple_of(BLOCK_SIZE));
+
+    let blocks_per_side = page_size / BLOCK_SIZE;
+    let mut output = vec![0; blocks_per_side * blocks_per_side * BLOCK_BYTES];
+    output
+        .chunks_exact_mut(BLOCK_BYTES)
+        .enumerate()
+        .for_each(|(index, block)| {
+            let texels = gather_block(
+                page,
+                page_size,
+                index % blocks_per_side,
+                index / blocks_per_side,
+            );
+            encode_block(&texels, block);
+        });
+    output
+}
+
+/// Decode a page back to `page_size * page_size` RGBA8 texels.
+///
+/// Used when the adapter does not support block compressed textures.
+pub fn decode_page(encoding: PageEncoding, data: &[u8], page_size: usize) -> Vec<u8> {
+    let decode_block: fn(&[u8]) -> [[u8; 4]; 16] = match encoding {
+        PageEncoding::Raw => return data.to_vec(),
+        PageEncoding::Bc7 => decode_bc7_block,
+        PageEncoding::Bc5 => decode_bc5_block,
+    };
+
+    let blocks_per_side = page_size / BLOCK_SIZE;
+    let mut output = vec![0; page_size * page_size * 4];
+    data.chunks_exact(BLOCK_BYTES)
+        .enumerate()
+        .for_each(|(index, block)| {
+            let texels = decode_block(block);
+            let (block_x, block_y) = (index % blocks_per_side, index / blocks_per_side);
+            for (texel_index, texel) in texels.iter().enumerate() {
+                let x = block_x * BLOCK_SIZE + texel_index % BLOCK_SIZE;
+                let y = block_y * BLOCK_SIZE + texel_index / BLOCK_SIZE;
+                let start = (y * page_size + x) * 4;
+                output[start..start + 4].copy_from_slice(texel);
+            }
+        });
+    output
+}
+
+fn gather_block(page: &[u8], page_size: usize, block_x: usize, block_y: usize) -> [[u8; 4]; 16] {
+    let mut texels = [[0; 4]; 16];
+    for (texel_index, texel) in texels.iter_mut().enumerate() {
+        let x = block_x * BLOCK_SIZE + texel_index % BLOCK_SIZE;
+        let y = block_y * BLOCK_SIZE + texel_index / BLOCK_SIZE;
+        let start = (y * page_size + x) * 4;
+        texel.copy_from_slice(&page[start..start + 4]);
+    }
+    texels
+}
+
+/// Little endian bit writer over a 128 bit block.
+struct BitWriter(u128, u32);
+
+impl BitWriter {
+    fn write(&mut self, value: u32, bits: u32) {
+        self.0 |= (value as u128 & ((1 << bits) - 1)) << self.1;
+        self.1 += bits;
+    }
+}
+
+fn read_bits(block: u128, offset: u32, bits: u32) -> u32 {
+    ((block >> offset) & ((1 << bits) - 1)) as u32
+}
+
+/// Quantize an endpoint to 7 bits per channel plus a shared p-bit, picking the p-bit with the
+/// smallest error.
+fn quantize_endpoint(color: [u8; 4]) -> ([u32; 4], u32) {
+    (0..2)
+        .map(|p_bit| {
+            let mut error = 0;
+            let quantized = color.map(|c| {
+                let q = ((c as i32 - p_bit + 1) >> 1).clamp(0, 127);
+                error += (((q << 1) | p_bit) - c as i32).pow(2);
+                q as u32
+            });
+            (quantized, p_bit as u32, error)
+        })
+        .min_by_key(|(_, _, error)| *error)
+        .map(|(quantized, p_bit, _)| (quantized, p_bit))
+        .unwrap()
+}
+
+fn bc7_interpolate(e0: [u32; 4], e1: [u32; 4], weight: u32) -> [u8; 4] {
+    std::array::from_fn(|c| (((64 - weight) * e0[c] + weight * e1[c] + 32) >> 6) as u8)
+}
+
+fn encode_bc7_block(texels: &[[u8; 4]; 16], output: &mut [u8]) {
+    let mut min = [u8::MAX; 4];
+    let mut max = [u8::MIN; 4];
+    texels.iter().for_each(|texel| {
+        (0..4).for_each(|c| {
+            min[c] = min[c].min(texel[c]);
+            max[c] = max[c].max(texel[c]);
+        })
+    });
+
+    let mut endpoints = [quantize_endpoint(min), quantize_endpoint(max)];
+    let expand = |(q, p): ([u32; 4], u32)| q.map(|c| (c << 1) | p);
+    let (e0, e1) = (expand(endpoints[0]), expand(endpoints[1]));
+
+    let palette: [[u8; 4]; 16] = std::array::from_fn(|i| bc7_interpolate(e0, e1, BC7_WEIGHTS[i]));
+    let mut indices = texels.map(|texel| {
+        (0..16)
+            .min_by_key(|&i| {
+                (0..4)
+                    .map(|c| (palette[i][c] as i32 - texel[c] as i32).pow(2))
+                    .sum::<i32>()
+            })
+            .unwrap() as u32
+    });
+
+    // The anchor index is stored with one bit less, so its most significant bit must be 0.
+    if indices[0] >= 8 {
+        endpoints.swap(0, 1);
+        indices.iter_mut().for_each(|i| *i = 15 - *i);
+    }
+
+    let mut writer = BitWriter(0, 0);
+    writer.write(1 << 6, 7);
+    (0..4).for_each(|c| {
+        writer.write(endpoints[0].0[c], 7);
+        writer.write(endpoints[1].0[c], 7);
+    });
+    writer.write(endpoints[0].1, 1);
+    writer.write(endpoints[1].1, 1);
+    indices
+        .iter()
+        .enumerate()
+        .for_each(|(i, &index)| writer.write(index, if i == 0 { 3 } else { 4 }));
+    debug_assert!(writer.1 == 128);
+
+    output.copy_from_slice(&writer.0.to_le_bytes());
+}
+
+fn decode_bc7_block(block: &[u8]) -> [[u8; 4]; 16] {
+    let block = u128::from_le_bytes(block.try_into().unwrap());
+    // Anything else than mode 6 was not written by us.
+    if read_bits(block, 0, 7) != 1 << 6 {
+        return [[0; 4]; 16];
+    }
+
+    let p0 = read_bits(block, 63, 1);
+    let p1 = read_bits(block, 64, 1);
+    let e0: [u32; 4] = std::array::from_fn(|c| (read_bits(block, 7 + c as u32 * 14, 7) << 1) | p0);
+    let e1: [u32; 4] = std::array::from_fn(|c| (read_bits(block, 14 + c as u32 * 14, 7) << 1) | p1);
+
+    let mut offset = 65;
+    std::array::from_fn(|i| {
+        let bits = if i == 0 { 3 } else { 4 };
+        let index = read_bits(block, offset, bits);
+        offset += bits;
+        bc7_interpolate(e0, e1, BC7_WEIGHTS[index as usize])
+    })
+}
+
+/// Encode a single channel as a BC4 block, in 8 bytes.
+fn encode_bc4_block(values: [u8; 16], output: &mut [u8]) {
+    let red_0 = *values.iter().max().unwrap();
+    let red_1 = *values.iter().min().unwrap();
+    let range = (red_0 - red_1) as u32;
+
+    let mut indices = 0_u64;
+    values.iter().enumerate().for_each(|(i, &value)| {
+        // Position on the line from red_0 (0) to red_1 (7).
+        let position = ((red_0 - value) as u32 * 7 + range / 2)
+            .checked_div(range)
+            .unwrap_or(0);
+        let index = match position {
+            0 => 0,
+            7 => 1,
+            position => position + 1,
+        };
+        indices |= (index as u64) << (i * 3);
+    });
+
+    output[0] = red_0;
+    output[1] = red_1;
+    output[2..8].copy_from_slice(&indices.to_le_bytes()[..6]);
+}
+
+fn decode_bc4_block(block: &[u8]) -> [u8; 16] {
+    let (red_0, red_1) = (block[0] as u32, block[1] as u32);
+    let mut index_bytes = [0; 8];
+    index_bytes[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(index_bytes);
+
+    std::array::from_fn(|i| {
+        let index = ((indices >> (i * 3)) & 0b111) as u32;
+        match index {
+            0 => red_0 as u8,
+            1 => red_1 as u8,
+            _ if red_0 > red_1 => (((8 - index) * red_0 + (index - 1) * red_1) / 7) as u8,
+            // Our encoder never produces the 6 value palette, but it is part of the format.
+            6 => 0,
+            7 => 255,
+            _ => (((6 - index) * red_0 + (index - 1) * red_1) / 5) as u8,
+        }
+    })
+}
+
+fn encode_bc5_block(texels: &[[u8; 4]; 16], output: &mut [u8]) {
+    encode_bc4_block(texels.map(|texel| texel[0]), &mut output[..8]);
+    encode_bc4_block(texels.map(|texel| texel[1]), &mut output[8..]);
+}
+
+fn decode_bc5_block(block: &[u8]) -> [[u8; 4]; 16] {
+    let red = decode_bc4_block(&block[..8]);
+    let green = decode_bc4_block(&block[8..]);
+    std::array::from_fn(|i| [red[i], green[i], 0, u8::MAX])
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_page, encode_page, PageEncoding};
+
+    fn gradient_page(page_size: usize) -> Vec<u8> {
+        (0..page_size * page_size)
+            .flat_map(|i| {
+                let (x, y) = ((i % page_size) as u8, (i / page_size) as u8);
+                [x, y, x / 2 + y / 2, 255 - x]
+            })
+            .collect()
+    }
+
+    fn max_error(encoding: PageEncoding, channels: usize) -> u8 {
+        let page = gradient_page(128);
+        let encoded = encode_page(encoding, &page, 128);
+        assert_eq!(encoded.len(), 128 * 128);
+        let decoded = decode_page(encoding, &encoded, 128);
+        page.chunks_exact(4)
+            .zip(decoded.chunks_exact(4))
+            .flat_map(|(a, b)| (0..channels).map(move |c| a[c].abs_diff(b[c])))
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn bc7_round_trip() {
+        assert!(max_error(PageEncoding::Bc7, 4) <= 4);
+    }
+
+    #[test]
+    fn bc5_round_trip() {
+        assert!(max_error(PageEncoding::Bc5, 2) <= 1);
+    }
+}
@@ -0,0 +1,205 @@
+//! Reuse of the textures already imported from an unchanged image, so that tools can import on
+//! every run without baking the texture again.
+
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use crate::{
+    reader::row_file_path, FitOperation, TextureMetadata, TextureStorage, TextureStorageError,
+};
+
+/// How [`TextureStorage::import_or_load`] imports an image file.
+#[derive(Clone)]
+pub struct ImportOptions {
+    /// The page size, encoding, etc. of the texture, which is sized to the image with `fit` (see
+    /// [`TextureMetadata::with_texel_dimensions`]).
+    pub metadata: TextureMetadata,
+    pub fit: FitOperation,
+    pub filter_mode: image::imageops::FilterType,
+    /// The directory of the texture, see [`TextureStorage::new`].
+    pub directory: Option<String>,
+}
+
+impl ImportOptions {
+    pub fn new(
+        metadata: TextureMetadata,
+        fit: FitOperation,
+        filter_mode: image::imageops::FilterType,
+    ) -> Self {
+        Self {
+            metadata,
+            fit,
+            filter_mode,
+            directory: None,
+        }
+    }
+
+    pub fn with_directory(mut self, directory: impl Into<String>) -> Self {
+        self.directory = Some(directory.into());
+        self
+    }
+}
+
+impl TextureMetadata {
+    /// The hash of the image and of the options the texture was imported with by
+    /// [`TextureStorage::import_or_load`], in hexadecimal.
+    pub fn source_hash(&self) -> Option<&str> {
+        self.source_hash.as_deref()
+    }
+}
+
+impl TextureStorage {
+    /// Load the texture in the directory of `options` if it was imported from the same image with
+    /// the same options, and import the image at `source_path` otherwise (see
+    /// [`TextureStorage::import_image`]).
+    ///
+    /// The image and the options are hashed, and the hash is saved in the metadata once the
+    /// import completes (see [`TextureMetadata::source_hash`]), so that an interrupted import is
+    /// done again. A texture imported from another image or with other options is deleted before
+    /// the import: its row and metadata files, the other files of the directory are kept.
+    pub fn import_or_load(
+        source_path: &Path,
+        options: &ImportOptions,
+    ) -> Result<Self, TextureStorageError> {
+        let dimensions = image::image_dimensions(source_path)?;
+        let metadata = options
+            .metadata
+            .clone()
+            .with_texel_dimensions(dimensions, options.fit);
+        let hash = source_hash(source_path, &metadata, options)?;
+
+        let directory = options.directory.as_deref();
+        if let Ok(storage) = Self::load(directory, None) {
+            if storage.metadata().source_hash() == Some(hash.as_str()) {
+                log::debug!(
+                    "reusing the texture imported from {}",
+                    source_path.display()
+                );
+                return Ok(storage);
+            }
+            storage.remove_files()?;
+        }
+
+        let mut storage = Self::new(metadata, directory, None)?;
+        storage.import_image(source_path, options.fit, options.filter_mode)?;
+        storage.metadata_mut().source_hash = Some(hash);
+        storage.save_metadata()?;
+        Ok(storage)
+    }
+
+    /// Delete the files written for the texture, the rows written so far and the metadata.
+    fn remove_files(self) -> Result<(), TextureStorageError> {
+        let remove = |path: &Path| match std::fs::remove_file(path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        };
+        for mip in 0..=self.metadata().mip_levels {
+            let (_, rows) = self.metadata().page_grid(mip);
+            for row in 0..rows {
+                remove(&row_file_path(&self.reader.directory, (mip, row)))?;
+            }
+        }
+        remove(&self.metadata_path)?;
+        Ok(())
+    }
+}
+
+/// The 64 bit FNV-1a hash of the image and of the options, which is stable across builds unlike
+/// the hashers of the standard library.
+fn source_hash(
+    source_path: &Path,
+    metadata: &TextureMetadata,
+    options: &ImportOptions,
+) -> Result<String, TextureStorageError> {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    let hash_bytes = |hash: u64, bytes: &[u8]| {
+        bytes
+            .iter()
+            .fold(hash, |hash, &byte| (hash ^ byte as u64).wrapping_mul(PRIME))
+    };
+
+    let mut source = BufReader::new(File::open(source_path)?);
+    let mut buffer = vec![0; 1 << 16];
+    let mut hash = OFFSET_BASIS;
+    loop {
+        let read = source.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hash = hash_bytes(hash, &buffer[..read]);
+    }
+    let options = format!(
+        "{}{:?}{:?}",
+        miniserde::json::to_string(metadata),
+        options.fit,
+        options.filter_mode
+    );
+    Ok(format!("{:016x}", hash_bytes(hash, options.as_bytes())))
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::{fixture::TempDir, prelude::*};
+
+    use super::ImportOptions;
+    use crate::{FitOperation, TextureMetadata, TextureStorage};
+
+    #[test]
+    fn reuse_unchanged_import() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.child("source.png");
+        let directory = temp_dir.child("texture");
+        let save_image = |value: u8| {
+            image::RgbaImage::from_pixel(40, 40, image::Rgba([value, 0, 0, 255]))
+                .save(source.path())
+                .unwrap()
+        };
+        save_image(10);
+        let options = ImportOptions::new(
+            TextureMetadata::from_mip(0, 4).with_page_size(32, 4),
+            FitOperation::Pad,
+            image::imageops::FilterType::Nearest,
+        )
+        .with_directory(directory.path().to_str().unwrap());
+
+        let storage = TextureStorage::import_or_load(source.path(), &options).unwrap();
+        let hash = storage.metadata().source_hash().unwrap().to_owned();
+        assert_eq!(storage.metadata().dimensions, (2, 2));
+
+        // The texture is loaded as is, with the files written since.
+        let marker = directory.child("marker");
+        marker.touch().unwrap();
+        let storage = TextureStorage::import_or_load(source.path(), &options).unwrap();
+        assert_eq!(storage.metadata().source_hash(), Some(hash.as_str()));
+        marker.assert(predicates::path::exists());
+
+        // Other options or another image import again.
+        let resized = ImportOptions {
+            filter_mode: image::imageops::FilterType::Triangle,
+            ..options.clone()
+        };
+        let storage = TextureStorage::import_or_load(source.path(), &resized).unwrap();
+        assert_ne!(storage.metadata().source_hash(), Some(hash.as_str()));
+
+        // Only the files of the texture are deleted, the rows it no longer has included.
+        let larger_pages = ImportOptions {
+            metadata: TextureMetadata::from_mip(0, 4).with_page_size(64, 4),
+            ..options.clone()
+        };
+        directory.child("0-1").assert(predicates::path::exists());
+        let storage = TextureStorage::import_or_load(source.path(), &larger_pages).unwrap();
+        assert_eq!(storage.metadata().dimensions, (1, 1));
+        directory.child("0-1").assert(predicates::path::missing());
+        marker.assert(predicates::path::exists());
+
+        save_image(20);
+        let storage = TextureStorage::import_or_load(source.path(), &options).unwrap();
+        assert_ne!(storage.metadata().source_hash(), Some(hash.as_str()));
+        let page = storage.read_page(vt_core::PageId::new(0, 0, 0)).unwrap();
+        assert_eq!(&page[(4 * 32 + 4) * 4..][..4], [20, 0, 0, 255]);
+    }
+}
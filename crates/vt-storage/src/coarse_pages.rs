@@ -0,0 +1,249 @@
+//! Coarse mip levels stored in pages covering several regular pages, so that the long tail of
+//! small mip levels of very large textures takes fewer files and fewer page entries.
+
+use miniserde::{Deserialize, MiniSerialize};
+
+use vt_core::PageId;
+
+use crate::TextureMetadata;
+#[cfg(feature = "image")]
+use crate::{TextureStorage, TextureStorageError};
+
+/// The mip levels stored in coarse pages, see [`TextureMetadata::with_coarse_pages`].
+#[derive(MiniSerialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoarsePages {
+    /// The finest mip level stored in coarse pages.
+    pub first_mip: u8,
+    /// The log2 of the number of regular pages on each side of a coarse page.
+    pub scale: u8,
+}
+
+impl CoarsePages {
+    /// The largest scale, limited by the bits of the page table entries (see
+    /// [`PAGE_TABLE_SCALE_SHIFT`](vt_core::shader_constants::PAGE_TABLE_SCALE_SHIFT)).
+    pub const MAX_SCALE: u8 = vt_core::shader_constants::PAGE_TABLE_SCALE_MASK as u8;
+}
+
+/// A coarse row being assembled from the regular rows of the mip generator.
+#[cfg(feature = "image")]
+pub(super) struct PendingCoarseRow {
+    /// The texels of the coarse row, for every layer.
+    layers: Vec<Vec<u8>>,
+    /// The number of regular rows copied in.
+    rows: u16,
+}
+
+impl TextureMetadata {
+    /// Store the mip levels from `first_mip` in pages covering `2^scale * 2^scale` regular pages,
+    /// with a single border around them. Must be called after [`TextureMetadata::with_page_size`].
+    ///
+    /// Coarse pages take `2^scale * 2^scale` slots of the physical texture (see
+    /// `SlotAllocator` in `vt-runtime`), and are addressed by their
+    /// coordinates in [`TextureMetadata::page_grid`] (see [`TextureMetadata::stored_page`]).
+    ///
+    /// ### Panics
+    ///
+    /// - If `first_mip` is 0, since missing pages are only detected on regular pages.
+    /// - If `scale` is 0 or bigger than [`CoarsePages::MAX_SCALE`].
+    /// - If the size of the coarse pages is not a multiple of 4 (the size of compressed blocks).
+    pub fn with_coarse_pages(mut self, first_mip: u8, scale: u8) -> Self {
+        assert!(first_mip > 0);
+        assert!((1..=CoarsePages::MAX_SCALE).contains(&scale));
+        self.coarse_pages = Some(CoarsePages { first_mip, scale });
+        assert!(self.page_size_at(first_mip).is_multiple_of(4));
+        self
+    }
+
+    pub fn coarse_pages(&self) -> Option<CoarsePages> {
+        self.coarse_pages
+    }
+
+    /// The log2 of the number of regular pages on each side of the pages of mip level `mip`, 0
+    /// for regular pages.
+    pub fn page_scale(&self, mip: u8) -> u8 {
+        match self.coarse_pages {
+            Some(coarse_pages) if mip >= coarse_pages.first_mip => coarse_pages.scale,
+            _ => 0,
+        }
+    }
+
+    /// The number of pages stored on each side of a mip level, which is
+    /// [`TextureMetadata::mip_dimensions`] for regular pages.
+    pub fn page_grid(&self, mip: u8) -> (u16, u16) {
+        let (width, height) = self.mip_dimensions(mip);
+        let pages_per_side = 1 << self.page_scale(mip);
+        (
+            width.div_ceil(pages_per_side),
+            height.div_ceil(pages_per_side),
+        )
+    }
+
+    /// The page stored for `page`, a page of [`TextureMetadata::mip_dimensions`] such as the
+    /// pages requested by the feedback.
+    pub fn stored_page(&self, page: PageId) -> PageId {
+        let scale = self.page_scale(page.mip_level());
+        PageId::with_texture_id(
+            page.texture_id(),
+            page.mip_level(),
+            page.x() >> scale,
+            page.y() >> scale,
+        )
+    }
+}
+
+#[cfg(feature = "image")]
+impl TextureStorage {
+    /// Copy a regular row in its coarse row, and write the coarse row once all of its regular
+    /// rows are copied. The coarse pages past the bottom of the texture are left transparent.
+    ///
+    /// The regular rows overlap by their borders, which are only copied at the top and bottom of
+    /// the coarse row, since they hold the same texels as the interiors of the rows next to
+    /// them.
+    pub(super) fn write_coarse_row_layers(
+        &mut self,
+        mip: u8,
+        row: u16,
+        layers: &[&[u8]],
+    ) -> Result<(), TextureStorageError> {
+        let scale = self.metadata().page_scale(mip);
+        let page_size = self.metadata().page_size() as usize;
+        let border_size = self.metadata().border_size() as usize;
+        let row_byte_size = layers[0].len() / page_size;
+        let coarse_row = row >> scale;
+        let (_, height) = self.metadata().mip_dimensions(mip);
+        let row_count = (height - (coarse_row << scale)).min(1 << scale);
+
+        let index = row % (1 << scale);
+        let first_texel_row = if index == 0 { 0 } else { border_size };
+        let last_texel_row = if index == row_count - 1 {
+            page_size
+        } else {
+            page_size - border_size
+        };
+        let copied = first_texel_row * row_byte_size..last_texel_row * row_byte_size;
+        let offset = index as usize * self.metadata().page_stride() as usize * row_byte_size;
+
+        let coarse_row_byte_size =
+            self.metadata().generated_page_size_at(mip) as usize * row_byte_size;
+        let pending = self
+            .pending_coarse_rows
+            .entry((mip, coarse_row))
+            .or_insert_with(|| PendingCoarseRow {
+                layers: vec![vec![0; coarse_row_byte_size]; layers.len()],
+                rows: 0,
+            });
+        pending
+            .layers
+            .iter_mut()
+            .zip(layers)
+            .for_each(|(coarse, layer)| {
+                coarse[offset + copied.start..offset + copied.end]
+                    .copy_from_slice(&layer[copied.clone()])
+            });
+        pending.rows += 1;
+
+        if pending.rows == row_count {
+            let pending = self.pending_coarse_rows.remove(&(mip, coarse_row)).unwrap();
+            let layers = pending.layers.iter().map(Vec::as_slice).collect::<Vec<_>>();
+            self.write_page_row(mip, coarse_row, &layers)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::{fixture::TempDir, prelude::*};
+    use vt_core::PageId;
+
+    use crate::{PageCompression, TextureMetadata, TextureStorage};
+
+    #[test]
+    fn coarse_page_grid() {
+        let metadata = TextureMetadata::from_mip(3, 4)
+            .with_page_size(32, 2)
+            .with_coarse_pages(2, 1);
+        assert_eq!(metadata.page_grid(1), (4, 4));
+        assert_eq!(metadata.page_grid(2), (1, 1));
+        assert_eq!(metadata.page_grid(3), (1, 1));
+        assert_eq!(metadata.page_grid(40), (1, 1));
+        assert_eq!(metadata.page_size_at(1), 32);
+        assert_eq!(metadata.page_size_at(2), 60);
+        assert_eq!(metadata.page_count(), 64 + 16 + 1 + 1);
+        assert_eq!(
+            metadata.stored_page(PageId::new(2, 1, 1)),
+            PageId::new(2, 0, 0)
+        );
+        assert_eq!(
+            metadata.stored_page(PageId::new(1, 3, 2)),
+            PageId::new(1, 3, 2)
+        );
+    }
+
+    /// The coarse pages hold the texels of the regular pages they cover.
+    #[test]
+    fn import_coarse_pages() {
+        let temp_dir = TempDir::new().unwrap();
+        let texel_width = 4 * 28 + 2 * 2;
+        let texture = (0..texel_width * texel_width)
+            .flat_map(|texel| {
+                [
+                    (texel % texel_width) as u8,
+                    (texel / texel_width) as u8,
+                    0,
+                    255,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let import = |name, metadata: TextureMetadata| {
+            let path = temp_dir.child(name);
+            let mut storage =
+                TextureStorage::new(metadata, Some(path.path().to_str().unwrap()), None).unwrap();
+            storage
+                .import_texture(image::imageops::FilterType::Nearest, &texture[..])
+                .unwrap();
+            storage
+        };
+        let regular = import(
+            "regular",
+            TextureMetadata::from_mip(2, 4).with_page_size(32, 2),
+        );
+        let coarse = import(
+            "coarse",
+            TextureMetadata::from_mip(2, 4)
+                .with_page_size(32, 2)
+                .with_compression(PageCompression::Zstd)
+                .with_coarse_pages(1, 1),
+        );
+
+        let coarse_page = coarse.read_page(PageId::new(1, 0, 0)).unwrap();
+        assert_eq!(coarse_page.len(), 60 * 60 * 4);
+        // The rows of a page without its top and bottom borders, which only match between the
+        // rows of pages of mip level 0.
+        let content_rows = |page: &[u8], page_width: usize, (x, y): (usize, usize)| {
+            (2..30)
+                .flat_map(|row| {
+                    let start = ((y + row) * page_width + x) * 4;
+                    page[start..start + 32 * 4].to_vec()
+                })
+                .collect::<Vec<_>>()
+        };
+        [(0, 0), (1, 0), (0, 1), (1, 1)].iter().for_each(|&(x, y)| {
+            let page = regular
+                .read_page(PageId::new(1, x as u16, y as u16))
+                .unwrap();
+            assert_eq!(
+                content_rows(&coarse_page, 60, (x * 28, y * 28)),
+                content_rows(&page, 32, (0, 0))
+            );
+        });
+
+        let coarsest = coarse.read_page(PageId::new(2, 0, 0)).unwrap();
+        let page = regular.read_page(PageId::new(2, 0, 0)).unwrap();
+        assert_eq!(
+            content_rows(&coarsest, 60, (0, 0)),
+            content_rows(&page, 32, (0, 0))
+        );
+        assert!(coarsest[(59 * 60 + 59) * 4..].iter().all(|&byte| byte == 0));
+    }
+}
@@ -0,0 +1,258 @@
+//! The color space of the texels of the layers, in which their mip levels are filtered.
+//!
+//! Averaging sRGB encoded texels darkens the mip levels of contrasted textures, so the texels of
+//! sRGB layers are converted to linear light before they are downsampled, and back after.
+
+use std::sync::OnceLock;
+
+use miniserde::{Deserialize, MiniSerialize};
+
+#[cfg(feature = "image")]
+use crate::hdr::resize_half_texels;
+use crate::{hdr::downsample_half_page, PageEncoding, TexelFormat, TextureLayer, TextureMetadata};
+
+/// How the color channels of a layer are encoded. Alpha is always linear.
+#[derive(MiniSerialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// sRGB encoded colors, sampled through sRGB views of the physical textures, which decode
+    /// them to linear light.
+    Srgb,
+    /// Data stored as is (e.g., normals, masks, heights).
+    Linear,
+}
+
+impl ColorSpace {
+    /// The color space of the layers that do not set one: linear for BC5 normals, sRGB otherwise.
+    pub fn for_encoding(encoding: PageEncoding) -> Self {
+        match encoding {
+            PageEncoding::Bc5 => Self::Linear,
+            PageEncoding::Raw | PageEncoding::Bc7 => Self::Srgb,
+        }
+    }
+}
+
+impl TextureLayer {
+    /// Store the texels of the layer in `color_space` (Default: [`ColorSpace::for_encoding`]).
+    pub fn with_color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = Some(color_space);
+        self
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+            .unwrap_or_else(|| ColorSpace::for_encoding(self.encoding))
+    }
+}
+
+impl TextureMetadata {
+    /// Store the texels of a single layer texture in `color_space`, see
+    /// [`TextureLayer::with_color_space`] for layered ones.
+    ///
+    /// Textures written before color spaces have their mip levels filtered in sRGB, but are
+    /// sampled the same as [`ColorSpace::Srgb`].
+    pub fn with_color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = Some(color_space);
+        self
+    }
+}
+
+fn srgb_to_linear_table() -> &'static [f32; 256] {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        std::array::from_fn(|value| {
+            let value = value as f32 / 255.0;
+            if value <= 0.04045 {
+                value / 12.92
+            } else {
+                ((value + 0.055) / 1.055).powf(2.4)
+            }
+        })
+    })
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let encoded = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+/// Resize `texels` of `texel_format` and `size` to `new_size`, filtering the color channels of
+/// sRGB texels in linear light.
+#[cfg(feature = "image")]
+pub(super) fn resize_texels(
+    texels: &[u8],
+    size: (u32, u32),
+    new_size: (u32, u32),
+    filter_mode: image::imageops::FilterType,
+    texel_format: TexelFormat,
+    color_space: ColorSpace,
+) -> Box<[u8]> {
+    use image::{imageops::resize, ImageBuffer, Luma, LumaA, Rgba};
+    match (texel_format, color_space) {
+        (TexelFormat::Rgba16Float, _) => resize_half_texels(texels, size, new_size, filter_mode),
+        (TexelFormat::R8, _) => resize_as::<Luma<u8>>(texels, size, new_size, filter_mode),
+        (TexelFormat::Rg8, _) => resize_as::<LumaA<u8>>(texels, size, new_size, filter_mode),
+        (TexelFormat::Rgba8, ColorSpace::Linear) => {
+            resize_as::<Rgba<u8>>(texels, size, new_size, filter_mode)
+        }
+        (TexelFormat::Rgba8, ColorSpace::Srgb) => {
+            let table = srgb_to_linear_table();
+            let linear = texels
+                .chunks_exact(4)
+                .flat_map(|texel| {
+                    [
+                        table[texel[0] as usize],
+                        table[texel[1] as usize],
+                        table[texel[2] as usize],
+                        texel[3] as f32 / 255.0,
+                    ]
+                })
+                .collect::<Vec<_>>();
+            let image = ImageBuffer::<Rgba<f32>, _>::from_raw(size.0, size.1, linear).unwrap();
+            resize(&image, new_size.0, new_size.1, filter_mode)
+                .into_raw()
+                .chunks_exact(4)
+                .flat_map(|texel| {
+                    [
+                        linear_to_srgb(texel[0]),
+                        linear_to_srgb(texel[1]),
+                        linear_to_srgb(texel[2]),
+                        (texel[3].clamp(0.0, 1.0) * 255.0).round() as u8,
+                    ]
+                })
+                .collect()
+        }
+    }
+}
+
+/// Resize 8 bit `texels` of `size` to `new_size` as they are, as pixels of type `P`, whose
+/// channels match the ones of the texels.
+#[cfg(feature = "image")]
+fn resize_as<P: image::Pixel<Subpixel = u8> + 'static>(
+    texels: &[u8],
+    size: (u32, u32),
+    new_size: (u32, u32),
+    filter_mode: image::imageops::FilterType,
+) -> Box<[u8]> {
+    let image = image::ImageBuffer::<P, &[u8]>::from_raw(size.0, size.1, texels).unwrap();
+    image::imageops::resize(&image, new_size.0, new_size.1, filter_mode)
+        .into_raw()
+        .into_boxed_slice()
+}
+
+/// Average the 2x2 texels of a page of `texel_format` and `page_size` texels, to half its size,
+/// in the linear light of sRGB texels.
+pub fn downsample_page(
+    page: &[u8],
+    page_size: usize,
+    texel_format: TexelFormat,
+    color_space: ColorSpace,
+) -> Vec<u8> {
+    if texel_format == TexelFormat::Rgba16Float {
+        return downsample_half_page(page, page_size);
+    }
+    let table = srgb_to_linear_table();
+    let channels = texel_format.channels();
+    let half_size = page_size / 2;
+    (0..half_size * half_size * channels)
+        .map(|index| {
+            let (channel, index) = (index % channels, index / channels);
+            let (x, y) = (index % half_size * 2, index / half_size * 2);
+            let texel = |x: usize, y: usize| page[(y * page_size + x) * channels + channel];
+            let texels = [
+                texel(x, y),
+                texel(x + 1, y),
+                texel(x, y + 1),
+                texel(x + 1, y + 1),
+            ];
+            if color_space == ColorSpace::Srgb && channel < 3 {
+                let sum = texels
+                    .iter()
+                    .map(|&value| table[value as usize])
+                    .sum::<f32>();
+                linear_to_srgb(sum / 4.0)
+            } else {
+                let sum = texels.iter().map(|&value| value as u32).sum::<u32>();
+                ((sum + 2) / 4) as u8
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{downsample_page, linear_to_srgb, resize_texels, srgb_to_linear_table, ColorSpace};
+    use crate::{PageEncoding, TexelFormat, TextureLayer, TextureMetadata};
+
+    #[test]
+    fn srgb_round_trip() {
+        let table = srgb_to_linear_table();
+        assert!((0..=255).all(|value| linear_to_srgb(table[value as usize]) == value));
+    }
+
+    /// Black and white average to the sRGB encoding of half the light, not to 128.
+    #[test]
+    fn filter_in_linear_light() {
+        let page = [0, 0, 0, 0, 255, 255, 255, 255].repeat(2);
+        assert_eq!(
+            downsample_page(&page, 2, TexelFormat::Rgba8, ColorSpace::Srgb),
+            [188, 188, 188, 128]
+        );
+        assert_eq!(
+            downsample_page(&page, 2, TexelFormat::Rgba8, ColorSpace::Linear),
+            [128, 128, 128, 128]
+        );
+        let resized = resize_texels(
+            &page,
+            (2, 2),
+            (1, 1),
+            image::imageops::FilterType::Triangle,
+            TexelFormat::Rgba8,
+            ColorSpace::Srgb,
+        );
+        assert_eq!(&resized[..], [188, 188, 188, 128]);
+    }
+
+    /// Single and two channel texels are filtered channel by channel.
+    #[test]
+    fn filter_narrow_texels() {
+        let page = [0, 10, 100, 30].repeat(2);
+        assert_eq!(
+            downsample_page(&page, 2, TexelFormat::Rg8, ColorSpace::Linear),
+            [50, 20]
+        );
+        let resized = resize_texels(
+            &page[..4],
+            (2, 2),
+            (1, 1),
+            image::imageops::FilterType::Triangle,
+            TexelFormat::R8,
+            ColorSpace::Linear,
+        );
+        assert_eq!(&resized[..], [35]);
+    }
+
+    #[test]
+    fn layer_color_spaces() {
+        let layers = TextureMetadata::from_mip(0, 4)
+            .with_layers(vec![
+                TextureLayer::new("albedo", PageEncoding::Bc7),
+                TextureLayer::new("normals", PageEncoding::Bc5),
+                TextureLayer::new("masks", PageEncoding::Raw).with_color_space(ColorSpace::Linear),
+            ])
+            .layers();
+        assert_eq!(
+            layers
+                .iter()
+                .map(TextureLayer::color_space)
+                .collect::<Vec<_>>(),
+            [ColorSpace::Srgb, ColorSpace::Linear, ColorSpace::Linear]
+        );
+        let metadata = TextureMetadata::from_mip(0, 4).with_color_space(ColorSpace::Linear);
+        assert_eq!(metadata.layers()[0].color_space(), ColorSpace::Linear);
+    }
+}
@@ -0,0 +1,279 @@
+//! Import of images whose sides are not a power of two pages.
+
+#[cfg(feature = "image")]
+use std::io::Read;
+
+use crate::TextureMetadata;
+#[cfg(feature = "image")]
+use crate::{color_space::resize_texels, TextureStorage, TextureStorageError};
+
+/// How an image is fit into a texture whose sides are a power of two pages.
+///
+/// Like the input of [`TextureStorage::import_texture`], the image is considered to include the
+/// outer border of the texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FitOperation {
+    /// Extend the image to the next power of two pages on each side. The added texels are
+    /// transparent black, on the right and bottom of the image.
+    Pad,
+    /// Keep the top left part of the image that fits in the previous power of two pages.
+    Crop,
+    /// Resample the image to the closest power of two pages on each side.
+    ///
+    /// The whole image is loaded in memory for this.
+    Resize,
+}
+
+impl FitOperation {
+    /// The number of pages on a side of `texels` texels, with pages of `page_stride` texels
+    /// without borders.
+    fn fit_pages(self, texels: u32, page_stride: u32, border_size: u32) -> u16 {
+        let pages = texels.saturating_sub(2 * border_size).max(1) as f32 / page_stride as f32;
+        let pages = match self {
+            FitOperation::Pad => (pages.ceil() as u32).next_power_of_two(),
+            FitOperation::Crop => 1 << (pages as u32).max(1).ilog2(),
+            FitOperation::Resize => 1 << pages.log2().round().max(0.0) as u32,
+        };
+        pages.min(TextureMetadata::MAX_TEXTURE_SIZE as u32) as u16
+    }
+}
+
+impl TextureMetadata {
+    /// Creates a texture that will hold an image of `texel_dimensions` once fit with `fit`.
+    ///
+    /// The pages have the default size, use [`TextureMetadata::with_texel_dimensions`] after
+    /// [`TextureMetadata::with_page_size`] for other sizes.
+    pub fn from_texel_dimensions(
+        texel_dimensions: (u32, u32),
+        fit: FitOperation,
+        bytes_per_texel: u8,
+    ) -> Self {
+        Self::from_dimensions((1, 1), bytes_per_texel).with_texel_dimensions(texel_dimensions, fit)
+    }
+
+    /// Size the texture to hold an image of `texel_dimensions` once fit with `fit`, with the
+    /// current page size.
+    pub fn with_texel_dimensions(
+        mut self,
+        texel_dimensions: (u32, u32),
+        fit: FitOperation,
+    ) -> Self {
+        let page_stride = self.page_stride() as u32;
+        let border_size = self.border_size() as u32;
+        self.dimensions = (
+            fit.fit_pages(texel_dimensions.0, page_stride, border_size),
+            fit.fit_pages(texel_dimensions.1, page_stride, border_size),
+        );
+        self.mip_levels = self.dimensions.0.max(self.dimensions.1).ilog2() as u8;
+        self
+    }
+
+    /// The size of the texture in texels, outer border included.
+    pub fn texel_dimensions(&self) -> (u32, u32) {
+        let page_stride = self.page_stride() as u32;
+        let border_size = self.border_size() as u32;
+        (
+            self.dimensions.0 as u32 * page_stride + 2 * border_size,
+            self.dimensions.1 as u32 * page_stride + 2 * border_size,
+        )
+    }
+}
+
+#[cfg(feature = "image")]
+impl TextureStorage {
+    /// Import an image of `source_dimensions` texels from a [`Read`] stream of bytes, fitting it to
+    /// the dimensions of the texture with `fit`.
+    ///
+    /// Except for [`FitOperation::Resize`], the image is streamed row by row like in
+    /// [`TextureStorage::import_texture`].
+    pub fn import_fitted_texture(
+        &mut self,
+        filter_mode: image::imageops::FilterType,
+        fit: FitOperation,
+        source_dimensions: (u32, u32),
+        byte_stream: impl Read,
+    ) -> Result<(), TextureStorageError> {
+        self.import_fitted_layers(filter_mode, fit, source_dimensions, vec![byte_stream])
+    }
+
+    /// Import every layer of a layered texture like [`TextureStorage::import_layers`], fitting
+    /// the images of `source_dimensions` texels to the dimensions of the texture with `fit`.
+    ///
+    /// [`FitOperation::Resize`] filters the texels of each layer in its color space (see
+    /// [`TextureLayer::color_space`](crate::TextureLayer::color_space)).
+    pub fn import_fitted_layers(
+        &mut self,
+        filter_mode: image::imageops::FilterType,
+        fit: FitOperation,
+        source_dimensions: (u32, u32),
+        byte_streams: Vec<impl Read>,
+    ) -> Result<(), TextureStorageError> {
+        let bytes_per_texel = self.metadata().bytes_per_texel as usize;
+        let target_dimensions = self.metadata().texel_dimensions();
+
+        if fit == FitOperation::Resize {
+            let source_len =
+                source_dimensions.0 as usize * source_dimensions.1 as usize * bytes_per_texel;
+            let resized = byte_streams
+                .into_iter()
+                .zip(self.metadata().layers())
+                .map(|(mut byte_stream, layer)| {
+                    let mut source = vec![0; source_len];
+                    byte_stream.read_exact(&mut source)?;
+                    Ok(resize_texels(
+                        &source,
+                        source_dimensions,
+                        target_dimensions,
+                        filter_mode,
+                        self.metadata().texel_format(),
+                        layer.color_space(),
+                    ))
+                })
+                .collect::<Result<Vec<_>, TextureStorageError>>()?;
+            return self.import_layers(
+                filter_mode,
+                resized.iter().map(|texels| &texels[..]).collect(),
+            );
+        }
+
+        let fit_readers = byte_streams
+            .into_iter()
+            .map(|byte_stream| FitReader {
+                inner: byte_stream,
+                source_row_len: source_dimensions.0 as usize * bytes_per_texel,
+                source_rows_left: source_dimensions.1,
+                row: vec![0; target_dimensions.0 as usize * bytes_per_texel],
+                position: target_dimensions.0 as usize * bytes_per_texel,
+                target_rows_left: target_dimensions.1,
+            })
+            .collect();
+        self.import_layers(filter_mode, fit_readers)
+    }
+}
+
+/// Pads or crops the rows of an image as they are read, to the size of the target.
+#[cfg(feature = "image")]
+struct FitReader<R> {
+    inner: R,
+    source_row_len: usize,
+    source_rows_left: u32,
+    /// The current target row.
+    row: Vec<u8>,
+    /// Position of the next byte to read in `row`.
+    position: usize,
+    target_rows_left: u32,
+}
+
+#[cfg(feature = "image")]
+impl<R: Read> FitReader<R> {
+    fn next_row(&mut self) -> std::io::Result<()> {
+        self.row.fill(0);
+        if self.source_rows_left > 0 {
+            let kept = self.source_row_len.min(self.row.len());
+            self.inner.read_exact(&mut self.row[..kept])?;
+            // Skip the cropped part of the row.
+            std::io::copy(
+                &mut (&mut self.inner).take((self.source_row_len - kept) as u64),
+                &mut std::io::sink(),
+            )?;
+            self.source_rows_left -= 1;
+        }
+        self.target_rows_left -= 1;
+        self.position = 0;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "image")]
+impl<R: Read> Read for FitReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == self.row.len() {
+            if self.target_rows_left == 0 {
+                return Ok(0);
+            }
+            self.next_row()?;
+        }
+        let len = buf.len().min(self.row.len() - self.position);
+        buf[..len].copy_from_slice(&self.row[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use assert_fs::fixture::TempDir;
+    use vt_core::PageId;
+
+    use super::{FitOperation, FitReader};
+    use crate::{ColorSpace, PageEncoding, TextureLayer, TextureMetadata, TextureStorage};
+
+    #[test]
+    fn fit_dimensions() {
+        // 2.5 pages by 1 page, plus the outer border.
+        let texels = (300 + 8, 120 + 8);
+        let dimensions = |fit| TextureMetadata::from_texel_dimensions(texels, fit, 4).dimensions;
+        assert_eq!(dimensions(FitOperation::Pad), (4, 1));
+        assert_eq!(dimensions(FitOperation::Crop), (2, 1));
+        assert_eq!(dimensions(FitOperation::Resize), (2, 1));
+    }
+
+    #[test]
+    fn pad_and_crop_rows() {
+        // 3x2 source of 1 byte "texels", read as a 2x3 target.
+        let source = [1, 2, 3, 4, 5, 6];
+        let mut reader = FitReader {
+            inner: &source[..],
+            source_row_len: 3,
+            source_rows_left: 2,
+            row: vec![0; 2],
+            position: 2,
+            target_rows_left: 3,
+        };
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).unwrap();
+        assert_eq!(output, [1, 2, 4, 5, 0, 0]);
+    }
+
+    /// A black and white checker resized in linear light is mid-grey, which is 188 in sRGB.
+    #[test]
+    fn resize_in_layer_color_space() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().as_os_str().to_str().unwrap();
+        let metadata = TextureMetadata::from_mip(0, 4)
+            .with_page_size(32, 2)
+            .with_layers(vec![
+                TextureLayer::new("albedo", PageEncoding::Raw),
+                TextureLayer::new("mask", PageEncoding::Raw).with_color_space(ColorSpace::Linear),
+            ]);
+        let mut storage = TextureStorage::new(metadata, Some(path), None).unwrap();
+
+        let checker = (0..64 * 64)
+            .flat_map(|texel| {
+                let value = if (texel % 64 + texel / 64) % 2 == 0 {
+                    0
+                } else {
+                    255
+                };
+                [value, value, value, 255]
+            })
+            .collect::<Vec<_>>();
+        storage
+            .import_fitted_layers(
+                image::imageops::FilterType::Triangle,
+                FitOperation::Resize,
+                (64, 64),
+                vec![&checker[..], &checker[..]],
+            )
+            .unwrap();
+
+        let page = storage.read_page(PageId::new(0, 0, 0)).unwrap();
+        let layers = storage.metadata().split_layers(0, &page);
+        let center = (16 * 32 + 16) * 4;
+        let [srgb, linear] = [layers[0], layers[1]].map(|layer| layer[center]);
+        assert!(srgb.abs_diff(188) <= 1, "sRGB layer texel {srgb}");
+        assert!(linear.abs_diff(128) <= 1, "linear layer texel {linear}");
+    }
+}
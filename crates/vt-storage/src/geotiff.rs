@@ -0,0 +1,218 @@
+//! Import of GeoTIFF images, with their georeferencing and nodata value.
+
+#[cfg(feature = "image")]
+use std::{fs::File, io::BufReader, io::Read, path::Path};
+
+use miniserde::{Deserialize, MiniSerialize};
+#[cfg(feature = "image")]
+use tiff::{decoder::Decoder, tags::Tag};
+
+#[cfg(feature = "image")]
+use vt_core::ensure;
+
+use crate::TextureMetadata;
+#[cfg(feature = "image")]
+use crate::{
+    image_import::{TexelReader, TiffChunkReader},
+    FitOperation, TexelFormat, TextureStorage, TextureStorageError,
+};
+
+/// The mapping from the texels of the imported image to world coordinates, read from the
+/// GeoTIFF tags. Rotated or sheared rasters are not supported.
+#[derive(MiniSerialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct GeoTransform {
+    /// The world coordinates of the top left corner of the image.
+    pub origin: (f64, f64),
+    /// The size of a texel in world units, usually negative on y for north-up images.
+    pub texel_size: (f64, f64),
+}
+
+impl GeoTransform {
+    /// Read the transform from `ModelTransformationTag`, or from `ModelPixelScaleTag` and
+    /// `ModelTiepointTag`. Returns `None` if the image is not georeferenced.
+    #[cfg(feature = "image")]
+    fn from_tiff<R: Read + std::io::Seek>(
+        decoder: &mut Decoder<R>,
+    ) -> Result<Option<Self>, TextureStorageError> {
+        if decoder.find_tag(Tag::ModelTransformationTag)?.is_some() {
+            let matrix = decoder.get_tag_f64_vec(Tag::ModelTransformationTag)?;
+            crate::ensure!(
+                matrix.len() == 16 && matrix[1] == 0.0 && matrix[4] == 0.0,
+                TextureStorageError::UnsupportedImage("rotated geotiff".to_string())
+            );
+            return Ok(Some(Self {
+                origin: (matrix[3], matrix[7]),
+                texel_size: (matrix[0], matrix[5]),
+            }));
+        }
+        if decoder.find_tag(Tag::ModelPixelScaleTag)?.is_none()
+            || decoder.find_tag(Tag::ModelTiepointTag)?.is_none()
+        {
+            return Ok(None);
+        }
+        let scale = decoder.get_tag_f64_vec(Tag::ModelPixelScaleTag)?;
+        let tiepoint = decoder.get_tag_f64_vec(Tag::ModelTiepointTag)?;
+        crate::ensure!(
+            scale.len() >= 2 && tiepoint.len() >= 6,
+            TextureStorageError::UnsupportedImage("malformed geotiff tags".to_string())
+        );
+        // The tiepoint maps the texel (i, j) to the world coordinates (x, y).
+        let (i, j, x, y) = (tiepoint[0], tiepoint[1], tiepoint[3], tiepoint[4]);
+        Ok(Some(Self {
+            origin: (x - i * scale[0], y + j * scale[1]),
+            texel_size: (scale[0], -scale[1]),
+        }))
+    }
+
+    /// The texel coordinates of the image at `world`, fractional within a texel.
+    pub fn world_to_texel(&self, world: (f64, f64)) -> (f64, f64) {
+        (
+            (world.0 - self.origin.0) / self.texel_size.0,
+            (world.1 - self.origin.1) / self.texel_size.1,
+        )
+    }
+}
+
+impl TextureMetadata {
+    pub fn geo_transform(&self) -> Option<GeoTransform> {
+        self.geo_transform
+    }
+
+    /// The uv of the virtual texture at `world`, for textures imported from a georeferenced
+    /// image with [`FitOperation::Pad`]. Returns `None` without a [`GeoTransform`].
+    ///
+    /// Like in the import, the image is considered to include the outer border of the texture.
+    pub fn world_to_uv(&self, world: (f64, f64)) -> Option<(f32, f32)> {
+        let (x, y) = self.geo_transform?.world_to_texel(world);
+        let border_size = self.border_size() as f64;
+        let page_stride = self.page_stride() as f64;
+        Some((
+            ((x - border_size) / (self.dimensions.0 as f64 * page_stride)) as f32,
+            ((y - border_size) / (self.dimensions.1 as f64 * page_stride)) as f32,
+        ))
+    }
+}
+
+#[cfg(feature = "image")]
+impl TextureStorage {
+    /// Import a GeoTIFF (or BigTIFF) image, one strip or row of tiles at a time, padding it to the
+    /// dimensions of the texture.
+    ///
+    /// The geo transform is stored in the metadata (see [`TextureMetadata::world_to_uv`]). Texels
+    /// equal to the `GDAL_NODATA` value on every channel become transparent, and the pages of
+    /// mip level 0 made only of them are recorded as missing (see
+    /// [`TextureMetadata::is_missing`]).
+    ///
+    /// ### Errors
+    ///
+    /// - If the image does not have 8 bit channels, as is common for elevation data.
+    /// - If the texture is not [`TexelFormat::Rgba8`].
+    pub fn import_geotiff(
+        &mut self,
+        path: &Path,
+        filter_mode: image::imageops::FilterType,
+    ) -> Result<(), TextureStorageError> {
+        let texel_format = self.metadata().texel_format();
+        ensure!(
+            texel_format == TexelFormat::Rgba8,
+            TextureStorageError::UnsupportedImage(format!(
+                "geotiff in a {texel_format:?} texture, geotiff requires an RGBA8 texture"
+            ))
+        );
+        let mut decoder = Decoder::new(BufReader::new(File::open(path)?))?;
+        self.metadata_mut().geo_transform = GeoTransform::from_tiff(&mut decoder)?;
+        let nodata = match decoder.find_tag(Tag::GdalNodata)? {
+            Some(_) => {
+                let nodata = decoder.get_tag_ascii_string(Tag::GdalNodata)?;
+                let nodata = nodata.trim_end_matches('\0').trim();
+                Some(nodata.parse::<u8>().map_err(|_| {
+                    TextureStorageError::UnsupportedImage(format!("nodata value {nodata}"))
+                })?)
+            }
+            None => None,
+        };
+
+        let reader = TiffChunkReader::new(decoder)?;
+        let (dimensions, channels) = (reader.dimensions, reader.channels);
+        log::debug!(
+            "importing {} ({}x{}, nodata {nodata:?}, {:?})",
+            path.display(),
+            dimensions.0,
+            dimensions.1,
+            self.metadata().geo_transform
+        );
+        let rgba = TexelReader::new(reader, channels, 4, dimensions, nodata);
+
+        self.detect_missing_pages = nodata.is_some();
+        let result = self.import_fitted_texture(filter_mode, FitOperation::Pad, dimensions, rgba);
+        self.detect_missing_pages = false;
+        result?;
+        self.save_metadata()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::{fixture::TempDir, prelude::*};
+    use tiff::{encoder::colortype::RGB8, tags::Tag};
+    use vt_core::PageId;
+
+    use super::GeoTransform;
+    use crate::{TextureMetadata, TextureStorage};
+
+    /// A 2x2 pages texture with 32 texel pages, from a 59x59 image with nodata (7) on its bottom
+    /// right page.
+    #[test]
+    fn import_geotiff_with_nodata() {
+        let temp_dir = TempDir::new().unwrap();
+        let image_path = temp_dir.child("image.tiff");
+        let image = image::RgbImage::from_fn(59, 59, |x, y| {
+            if x >= 28 && y >= 28 {
+                image::Rgb([7; 3])
+            } else {
+                image::Rgb([x as u8, y as u8, 100])
+            }
+        });
+        let mut encoder =
+            tiff::encoder::TiffEncoder::new(std::fs::File::create(image_path.path()).unwrap())
+                .unwrap();
+        let mut tiff = encoder.new_image::<RGB8>(59, 59).unwrap();
+        tiff.encoder()
+            .write_tag(Tag::ModelPixelScaleTag, &[10.0, 10.0, 0.0][..])
+            .unwrap();
+        tiff.encoder()
+            .write_tag(
+                Tag::ModelTiepointTag,
+                &[0.0, 0.0, 0.0, 1000.0, 2000.0, 0.0][..],
+            )
+            .unwrap();
+        tiff.encoder().write_tag(Tag::GdalNodata, "7").unwrap();
+        tiff.write_data(image.as_raw()).unwrap();
+
+        let metadata = TextureMetadata::from_mip(1, 4).with_page_size(32, 2);
+        let path = temp_dir.child("texture");
+        let path = path.path().to_str().unwrap();
+        let mut storage = TextureStorage::new(metadata, Some(path), None).unwrap();
+        storage
+            .import_geotiff(image_path.path(), image::imageops::FilterType::Nearest)
+            .unwrap();
+
+        let storage = TextureStorage::load(Some(path), None).unwrap();
+        let metadata = storage.metadata();
+        assert_eq!(
+            metadata.geo_transform(),
+            Some(GeoTransform {
+                origin: (1000.0, 2000.0),
+                texel_size: (10.0, -10.0),
+            })
+        );
+        // The texel 30 (28 + the border of 2) is at the center of the texture.
+        assert_eq!(metadata.world_to_uv((1300.0, 1700.0)), Some((0.5, 0.5)));
+
+        assert!(metadata.is_missing(PageId::new(0, 1, 1)));
+        assert!(!metadata.is_missing(PageId::new(0, 1, 0)));
+        assert!(!metadata.is_missing(PageId::new(1, 0, 0)));
+        let page = storage.read_page(PageId::new(0, 1, 0)).unwrap();
+        assert_eq!(page[..4], [28, 0, 100, 255]);
+    }
+}
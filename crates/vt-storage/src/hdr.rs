@@ -0,0 +1,162 @@
+//! Half float texels, for HDR data (e.g., lightmaps, or sky and terrain radiance) whose range does
+//! not fit in 8 bit channels.
+//!
+//! The pages of HDR textures hold linear RGBA16F texels, and are filtered in f32. They are
+//! uploaded as is to `Rgba16Float` physical textures, or packed to `Rg11b10Float` ones, which
+//! take half the memory but drop the alpha channel.
+
+pub(super) fn half_to_f32(texels: &[u8]) -> Vec<f32> {
+    texels
+        .chunks_exact(2)
+        .map(|channel| half::f16::from_le_bytes([channel[0], channel[1]]).to_f32())
+        .collect()
+}
+
+pub(super) fn f32_to_half(channels: &[f32]) -> Box<[u8]> {
+    channels
+        .iter()
+        .flat_map(|&channel| half::f16::from_f32(channel).to_le_bytes())
+        .collect()
+}
+
+/// Convert RGBA8 texels to RGBA16F, from `[0, 1]`.
+pub fn rgba8_to_half(texels: &[u8]) -> Vec<u8> {
+    let channels = texels
+        .iter()
+        .map(|&channel| channel as f32 / 255.0)
+        .collect::<Vec<_>>();
+    f32_to_half(&channels).into_vec()
+}
+
+/// Resize RGBA16F `texels` of `size` to `new_size`, in f32.
+///
+/// The `image` crate clamps float texels to `[0, 1]`, so the channels are scaled down by a power
+/// of two past the largest half float while they are resized, which is exact. Negative channels
+/// are clamped to 0.
+#[cfg(feature = "image")]
+pub(super) fn resize_half_texels(
+    texels: &[u8],
+    size: (u32, u32),
+    new_size: (u32, u32),
+    filter_mode: image::imageops::FilterType,
+) -> Box<[u8]> {
+    use image::{imageops::resize, ImageBuffer, Rgba};
+
+    const SCALE: f32 = 65536.0;
+    let channels = half_to_f32(texels)
+        .into_iter()
+        .map(|channel| channel / SCALE)
+        .collect();
+    let image = ImageBuffer::<Rgba<f32>, Vec<f32>>::from_raw(size.0, size.1, channels).unwrap();
+    let resized = resize(&image, new_size.0, new_size.1, filter_mode)
+        .into_raw()
+        .into_iter()
+        .map(|channel| channel * SCALE)
+        .collect::<Vec<_>>();
+    f32_to_half(&resized)
+}
+
+/// Average the 2x2 texels of an RGBA16F page of `page_size` texels, to half its size.
+pub(super) fn downsample_half_page(page: &[u8], page_size: usize) -> Vec<u8> {
+    let channels = half_to_f32(page);
+    let half_size = page_size / 2;
+    let averaged = (0..half_size * half_size)
+        .flat_map(|index| {
+            let (x, y) = (index % half_size * 2, index / half_size * 2);
+            let channel =
+                |x: usize, y: usize, channel: usize| channels[(y * page_size + x) * 4 + channel];
+            std::array::from_fn::<f32, 4, _>(|c| {
+                (channel(x, y, c)
+                    + channel(x + 1, y, c)
+                    + channel(x, y + 1, c)
+                    + channel(x + 1, y + 1, c))
+                    / 4.0
+            })
+        })
+        .collect::<Vec<_>>();
+    f32_to_half(&averaged).into_vec()
+}
+
+/// Pack RGBA16F texels to the texels of `Rg11b10Float` textures, dropping alpha.
+///
+/// The 11 and 10 bit floats have no sign bit and the exponent of half floats, so the mantissas
+/// are truncated, and negative values become 0.
+pub fn pack_rg11b10(texels: &[u8]) -> Vec<u8> {
+    let unsigned = |channel: &[u8], mantissa_bits: u32| {
+        let half = u16::from_le_bytes([channel[0], channel[1]]);
+        if half & 0x8000 != 0 {
+            0
+        } else {
+            half as u32 >> (10 - mantissa_bits)
+        }
+    };
+    texels
+        .chunks_exact(8)
+        .flat_map(|texel| {
+            let packed = unsigned(&texel[0..2], 6)
+                | unsigned(&texel[2..4], 6) << 11
+                | unsigned(&texel[4..6], 5) << 22;
+            packed.to_le_bytes()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::fixture::TempDir;
+    use vt_core::PageId;
+
+    use super::{downsample_half_page, f32_to_half, half_to_f32, pack_rg11b10, resize_half_texels};
+    use crate::{TextureMetadata, TextureStorage};
+
+    /// Values past 1 survive the filtering, unlike with RGBA8 texels.
+    #[test]
+    fn filter_half_floats() {
+        let page = f32_to_half(
+            &[[0.0, 0.0, 0.0, 1.0], [16.0, 8.0, 4.0, 1.0]]
+                .concat()
+                .repeat(2),
+        );
+        let averaged = f32_to_half(&[8.0, 4.0, 2.0, 1.0]);
+        assert_eq!(downsample_half_page(&page, 2), averaged.to_vec());
+        let resized =
+            resize_half_texels(&page, (2, 2), (1, 1), image::imageops::FilterType::Triangle);
+        assert_eq!(resized, averaged);
+    }
+
+    #[test]
+    fn pack_half_floats() {
+        let texels = f32_to_half(&[1.0, 2.0, 0.5, 1.0, -1.0, 65504.0, 0.0, 0.0]);
+        let packed = pack_rg11b10(&texels);
+        let texel = |index: usize| u32::from_le_bytes(packed[index * 4..][..4].try_into().unwrap());
+        // The exponent of 1 is the bias, 15.
+        assert_eq!(texel(0), 15 << 6 | 16 << 17 | 14 << 27);
+        // Negative values are clamped to 0, and the largest half float keeps its exponent.
+        assert_eq!(texel(1), 0x7bf << 11);
+    }
+
+    /// The mip levels of HDR textures keep the values past 1.
+    #[test]
+    fn import_hdr_texture() {
+        let temp_dir = TempDir::new().unwrap();
+        // 2x2 pages of 12 texels and a border of 2.
+        let metadata = TextureMetadata::from_mip(1, 8).with_page_size(16, 2);
+        let (width, height) = metadata.texel_dimensions();
+        let mut storage =
+            TextureStorage::new(metadata, Some(temp_dir.path().to_str().unwrap()), None).unwrap();
+        let texels = f32_to_half(&[100.0, 0.5, 2.0, 1.0].repeat((width * height) as usize));
+        storage
+            .import_texture(image::imageops::FilterType::Triangle, &texels[..])
+            .unwrap();
+
+        let page = storage.read_page(PageId::new(1, 0, 0)).unwrap();
+        assert_eq!(page.len(), 16 * 16 * 8);
+        assert!(half_to_f32(&page)
+            .chunks_exact(4)
+            .all(|texel| texel == [100.0, 0.5, 2.0, 1.0]));
+        assert_eq!(
+            storage.export_mip(1).unwrap().get_pixel(0, 0).0,
+            [255, 128, 255, 255]
+        );
+    }
+}
@@ -0,0 +1,371 @@
+//! Streaming of packed archives over HTTP, one range request per page.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    sync::Mutex,
+    time::Duration,
+};
+
+use vt_core::{ensure, PageId};
+
+use crate::{
+    archive,
+    reader::{decompress_page, ensure_in_bounds},
+    PageSource, TextureMetadata, TextureStorageError,
+};
+
+/// A [`PageSource`] fetching the pages of an archive written by
+/// [`TextureStorage::pack`](crate::TextureStorage::pack) from an HTTP server, such as a CDN,
+/// with a range request per page.
+///
+/// The metadata and the page table of the archive are fetched on creation, and the pages on
+/// every read. The connections are kept alive between the requests, one per concurrent read.
+/// Only plain `http://` urls are supported, without redirects: TLS is left to a local proxy.
+pub struct HttpPageSource {
+    url: HttpUrl,
+    metadata: TextureMetadata,
+    /// (offset, length) of every page in the archive.
+    pages: Vec<(u64, u64)>,
+    /// The connections kept alive, used by one request at a time.
+    connections: Mutex<Vec<BufReader<TcpStream>>>,
+}
+
+impl HttpPageSource {
+    /// How long a request may wait on the server before it fails.
+    pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Fetch the index of the archive at `url`, such as `http://cdn.example.com/map.vtpk`.
+    ///
+    /// ### Errors
+    ///
+    /// - [`TextureStorageError::Http`] if `url` is not an `http://` url, or if the server does not
+    ///   answer with the ranges requested.
+    /// - [`TextureStorageError::InvalidArchive`] if the file is not a packed archive.
+    pub fn new(url: &str) -> Result<Self, TextureStorageError> {
+        let mut source = Self {
+            url: HttpUrl::parse(url)?,
+            metadata: TextureMetadata::from_dimensions((1, 1), 4),
+            pages: Vec::new(),
+            connections: Default::default(),
+        };
+        let mut offset = 0;
+        let (pages, metadata) = archive::read_index(|buffer| {
+            let bytes = source.fetch(offset, buffer.len() as u64)?;
+            ensure!(
+                bytes.len() == buffer.len(),
+                TextureStorageError::InvalidArchive
+            );
+            buffer.copy_from_slice(&bytes);
+            offset += bytes.len() as u64;
+            Ok(())
+        })?;
+        source.metadata = metadata;
+        source.pages = pages;
+        Ok(source)
+    }
+
+    /// Fetch `length` bytes of the archive from `offset`, fewer past its end.
+    fn fetch(&self, offset: u64, length: u64) -> Result<Vec<u8>, TextureStorageError> {
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nRange: bytes={}-{}\r\n\r\n",
+            self.url.path,
+            self.url.host,
+            offset,
+            offset + length - 1
+        );
+        // A connection kept alive may have been closed by the server since its last request, in
+        // which case the request is sent again on a new one.
+        let connection = self.connections.lock().unwrap().pop();
+        let (connection, response) =
+            match connection.map(|connection| Self::send(connection, &request, length)) {
+                Some(Ok(response)) => response,
+                Some(Err(_)) | None => Self::send(self.connect()?, &request, length)?,
+            };
+        if response.keep_alive {
+            self.connections.lock().unwrap().push(connection);
+        }
+        match response.status {
+            206 => Ok(response.body),
+            // The offset is past the end of the archive.
+            416 => Ok(Vec::new()),
+            status => Err(TextureStorageError::Http(format!(
+                "the server answered {status} to a range request for {}",
+                self.url.path
+            ))),
+        }
+    }
+
+    fn connect(&self) -> Result<BufReader<TcpStream>, TextureStorageError> {
+        let stream = TcpStream::connect(&self.url.address)?;
+        stream.set_read_timeout(Some(Self::TIMEOUT))?;
+        stream.set_write_timeout(Some(Self::TIMEOUT))?;
+        Ok(BufReader::new(stream))
+    }
+
+    /// Send `request`, whose response has at most `max_length` bytes of body.
+    fn send(
+        mut connection: BufReader<TcpStream>,
+        request: &str,
+        max_length: u64,
+    ) -> Result<(BufReader<TcpStream>, Response), TextureStorageError> {
+        connection.get_mut().write_all(request.as_bytes())?;
+        let response = Response::read(&mut connection, max_length)?;
+        Ok((connection, response))
+    }
+}
+
+impl PageSource for HttpPageSource {
+    fn metadata(&self) -> &TextureMetadata {
+        &self.metadata
+    }
+
+    fn read_page(&self, page: PageId) -> Result<Vec<u8>, TextureStorageError> {
+        ensure_in_bounds(&self.metadata, page)?;
+        let index = self
+            .metadata
+            .page_index(page.mip_level(), page.x(), page.y());
+        let (offset, length) = self.pages[index];
+        let stored = self.fetch(offset, length)?;
+        ensure!(
+            stored.len() as u64 == length,
+            TextureStorageError::InvalidArchive
+        );
+        decompress_page(&self.metadata, page.mip_level(), stored)
+    }
+}
+
+/// An `http://host[:port]/path` url.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HttpUrl {
+    /// The host and port connected to.
+    address: String,
+    /// The host, with the port if the url has one, for the `Host` header.
+    host: String,
+    path: String,
+}
+
+impl HttpUrl {
+    fn parse(url: &str) -> Result<Self, TextureStorageError> {
+        let invalid = || TextureStorageError::Http(format!("`{url}` is not an http:// url"));
+        let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+        let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        ensure!(!host.is_empty(), invalid());
+        let address = match host.rsplit_once(':') {
+            Some((_, port)) if port.parse::<u16>().is_ok() => host.to_owned(),
+            _ => format!("{host}:80"),
+        };
+        Ok(Self {
+            address,
+            host: host.to_owned(),
+            path: if path.is_empty() { "/" } else { path }.to_owned(),
+        })
+    }
+}
+
+/// The parts of an HTTP/1.1 response read by [`HttpPageSource`].
+struct Response {
+    status: u16,
+    /// Whether the server keeps the connection open for the next request.
+    keep_alive: bool,
+    body: Vec<u8>,
+}
+
+impl Response {
+    /// Read a response whose body has a `Content-Length`, the only kind of body of the range
+    /// responses of the usual servers. Bodies longer than `max_length`, the length of the range
+    /// requested, are rejected before they are read.
+    fn read(connection: &mut impl BufRead, max_length: u64) -> Result<Self, TextureStorageError> {
+        let malformed = || TextureStorageError::Http("malformed response".to_owned());
+        let mut line = String::new();
+        connection.read_line(&mut line)?;
+        let status = line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(malformed)?;
+
+        let mut content_length = None;
+        let mut keep_alive = true;
+        loop {
+            line.clear();
+            ensure!(connection.read_line(&mut line)? > 0, malformed());
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':').ok_or_else(malformed)?;
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = Some(value.parse::<usize>().map_err(|_| malformed())?);
+            } else if name.eq_ignore_ascii_case("connection") {
+                keep_alive = !value.eq_ignore_ascii_case("close");
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                return Err(TextureStorageError::Http(format!(
+                    "unsupported transfer encoding `{value}`"
+                )));
+            }
+        }
+
+        let content_length = content_length.ok_or_else(malformed)?;
+        ensure!(
+            content_length as u64 <= max_length,
+            TextureStorageError::Http(format!(
+                "the server answered {content_length} bytes to a range request of {max_length}"
+            ))
+        );
+        let mut body = vec![0; content_length];
+        connection.read_exact(&mut body)?;
+        Ok(Self {
+            status,
+            keep_alive,
+            body,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        path::PathBuf,
+    };
+
+    use assert_fs::{fixture::TempDir, prelude::*};
+    use vt_core::PageId;
+
+    use super::{HttpUrl, Response};
+    use crate::{
+        HttpPageSource, PageCompression, PageSource, TextureMetadata, TextureStorage,
+        TextureStorageError,
+    };
+
+    /// Serve `file` at `/texture.vtpk` on a local port, answering range requests on
+    /// connections kept alive. Returns the url of the file.
+    fn serve(file: PathBuf) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/texture.vtpk", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let contents = std::fs::read(&file).unwrap();
+                std::thread::spawn(move || {
+                    let mut stream = BufReader::new(stream.unwrap());
+                    let mut request = String::new();
+                    while stream.read_line(&mut request).unwrap_or(0) > 0 {
+                        if request.ends_with("\r\n\r\n") {
+                            let response = respond(&request, &contents);
+                            stream.get_mut().write_all(&response).unwrap();
+                            request.clear();
+                        }
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    fn respond(request: &str, contents: &[u8]) -> Vec<u8> {
+        if !request.starts_with("GET /texture.vtpk ") {
+            return b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec();
+        }
+        let range = request
+            .lines()
+            .find_map(|line| line.strip_prefix("Range: bytes="))
+            .unwrap();
+        let (start, end) = range.split_once('-').unwrap();
+        let start = start.parse::<usize>().unwrap();
+        let end = (end.parse::<usize>().unwrap() + 1).min(contents.len());
+        if start >= contents.len() {
+            return b"HTTP/1.1 416 Range Not Satisfiable\r\nContent-Length: 0\r\n\r\n".to_vec();
+        }
+        let mut response = format!(
+            "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\n\r\n",
+            end - start
+        )
+        .into_bytes();
+        response.extend_from_slice(&contents[start..end]);
+        response
+    }
+
+    #[test]
+    fn parse_urls() {
+        let url = HttpUrl::parse("http://cdn.example.com:8080/maps/a.vtpk").unwrap();
+        assert_eq!(url.address, "cdn.example.com:8080");
+        assert_eq!(url.path, "/maps/a.vtpk");
+        let url = HttpUrl::parse("http://cdn.example.com").unwrap();
+        assert_eq!(
+            (url.address.as_str(), url.path.as_str()),
+            ("cdn.example.com:80", "/")
+        );
+        assert!(HttpUrl::parse("https://cdn.example.com/a.vtpk").is_err());
+        assert!(HttpUrl::parse("http:///a.vtpk").is_err());
+    }
+
+    /// A body longer than the range requested is rejected before it is allocated.
+    #[test]
+    fn reject_oversized_bodies() {
+        let response = b"HTTP/1.1 206 Partial Content\r\nContent-Length: 1000000000000\r\n\r\n";
+        assert!(matches!(
+            Response::read(&mut &response[..], 16),
+            Err(TextureStorageError::Http(_))
+        ));
+        let response = b"HTTP/1.1 206 Partial Content\r\nContent-Length: 4\r\n\r\nabcd";
+        assert_eq!(
+            Response::read(&mut &response[..], 16).unwrap().body,
+            b"abcd"
+        );
+    }
+
+    /// The pages fetched over HTTP are the ones of the archive on disk.
+    #[test]
+    fn fetch_pages() {
+        let temp_dir = TempDir::new().unwrap();
+        let metadata = TextureMetadata::from_mip(1, 4)
+            .with_page_size(8, 1)
+            .with_compression(PageCompression::Zstd);
+        let (width, height) = metadata.texel_dimensions();
+        let texels = (0..width * height * 4)
+            .map(|index| (index % 251) as u8)
+            .collect::<Vec<_>>();
+        let mut storage = TextureStorage::new(
+            metadata,
+            Some(temp_dir.child("texture").path().to_str().unwrap()),
+            None,
+        )
+        .unwrap();
+        storage
+            .import_texture(image::imageops::FilterType::Triangle, &texels[..])
+            .unwrap();
+        let archive = temp_dir.child("texture.vtpk");
+        storage.pack(archive.path()).unwrap();
+
+        let url = serve(archive.path().to_owned());
+        let source = HttpPageSource::new(&url).unwrap();
+        assert!(source.metadata().same_page_layout(storage.metadata()));
+        [(0, 0, 0), (0, 1, 1), (1, 0, 0), (0, 1, 0)]
+            .into_iter()
+            .for_each(|(mip, x, y)| {
+                let page = PageId::new(mip, x, y);
+                assert_eq!(
+                    source.read_page(page).unwrap(),
+                    storage.read_page(page).unwrap()
+                );
+            });
+        assert!(matches!(
+            source.read_page(PageId::new(1, 1, 0)),
+            Err(TextureStorageError::PageOutOfBounds(_))
+        ));
+        // The connection is kept alive between the reads.
+        assert_eq!(source.connections.lock().unwrap().len(), 1);
+
+        let missing = url.replace("texture.vtpk", "missing.vtpk");
+        assert!(matches!(
+            HttpPageSource::new(&missing),
+            Err(TextureStorageError::Http(_))
+        ));
+    }
+}
@@ -0,0 +1,373 @@
+//! Import of image files, decoded as they are tiled.
+
+use std::{fs::File, io::BufReader, io::Read, path::Path};
+
+use image::{ImageDecoder, ImageFormat};
+use tiff::decoder::{ChunkType, DecodingResult};
+
+use crate::{hdr::f32_to_half, FitOperation, TexelFormat, TextureStorage, TextureStorageError};
+
+/// Number of texels converted to the channels of the texture at once.
+const CONVERSION_TEXELS: usize = 4096;
+
+impl TextureStorage {
+    /// Import the image file at `path`, fitting it to the dimensions of the texture with `fit`.
+    ///
+    /// PNG images are decoded one scanline at a time, and TIFF images one strip or row of tiles
+    /// at a time, so that the image never has to fit in memory (except with
+    /// [`FitOperation::Resize`]). Other formats, JPEG included, are decoded whole by the `image`
+    /// crate.
+    ///
+    /// Use [`image::image_dimensions`] with
+    /// [`TextureMetadata::from_texel_dimensions`](crate::TextureMetadata::from_texel_dimensions)
+    /// to size the texture before the import.
+    ///
+    /// Images are decoded whole to half floats for [`TexelFormat::Rgba16Float`] textures, from
+    /// any format the `image` crate decodes (e.g., Radiance HDR and OpenEXR). The images
+    /// imported in [`TexelFormat::R8`] and [`TexelFormat::Rg8`] textures keep their first
+    /// channels, grey images filling every channel and grey alpha ones filling `[grey, alpha]`.
+    ///
+    /// ### Errors
+    ///
+    /// - If the image does not have 8 bit channels, for 8 bit textures.
+    pub fn import_image(
+        &mut self,
+        path: &Path,
+        fit: FitOperation,
+        filter_mode: image::imageops::FilterType,
+    ) -> Result<(), TextureStorageError> {
+        if self.metadata().texel_format() == TexelFormat::Rgba16Float {
+            let image = image::open(path)?.into_rgba32f();
+            let dimensions = image.dimensions();
+            log::debug!(
+                "importing {} ({}x{}, half floats)",
+                path.display(),
+                dimensions.0,
+                dimensions.1
+            );
+            let texels = f32_to_half(image.as_raw());
+            return self.import_fitted_texture(filter_mode, fit, dimensions, &texels[..]);
+        }
+        let file = BufReader::new(File::open(path)?);
+        let (dimensions, channels, texels): (_, _, Box<dyn Read>) =
+            match ImageFormat::from_path(path)? {
+                ImageFormat::Png => {
+                    let decoder = image::codecs::png::PngDecoder::new(file)?;
+                    let channels = image_channels(decoder.color_type())?;
+                    (
+                        decoder.dimensions(),
+                        channels,
+                        Box::new(decoder.into_reader()?),
+                    )
+                }
+                ImageFormat::Tiff => {
+                    let reader = TiffChunkReader::new(tiff::decoder::Decoder::new(file)?)?;
+                    (reader.dimensions, reader.channels, Box::new(reader))
+                }
+                format => {
+                    let image = image::load(file, format)?.into_rgba8();
+                    let dimensions = image.dimensions();
+                    (
+                        dimensions,
+                        4,
+                        Box::new(std::io::Cursor::new(image.into_raw())),
+                    )
+                }
+            };
+        log::debug!(
+            "importing {} ({}x{}, {} channels)",
+            path.display(),
+            dimensions.0,
+            dimensions.1,
+            channels
+        );
+
+        let texel_channels = self.metadata().texel_format().channels();
+        let texels = TexelReader::new(texels, channels, texel_channels, dimensions, None);
+        self.import_fitted_texture(filter_mode, fit, dimensions, texels)
+    }
+}
+
+fn image_channels(color_type: image::ColorType) -> Result<usize, TextureStorageError> {
+    match color_type {
+        image::ColorType::L8 => Ok(1),
+        image::ColorType::La8 => Ok(2),
+        image::ColorType::Rgb8 => Ok(3),
+        image::ColorType::Rgba8 => Ok(4),
+        color_type => Err(TextureStorageError::UnsupportedImage(format!(
+            "{color_type:?} texels"
+        ))),
+    }
+}
+
+/// Converts 8 bit grey, grey alpha, RGB or RGBA texels to the channels of the texture: expanded
+/// to RGBA8, or the first channels for textures with fewer channels.
+pub(super) struct TexelReader<R> {
+    inner: R,
+    channels: usize,
+    /// The channels of the texels of the texture.
+    texel_channels: usize,
+    texels_left: u64,
+    /// Texels whose every channel has this value become transparent black.
+    nodata: Option<u8>,
+    source: Vec<u8>,
+    texels: Vec<u8>,
+    /// Position of the next byte to read in `texels`.
+    position: usize,
+}
+
+impl<R: Read> TexelReader<R> {
+    pub(super) fn new(
+        inner: R,
+        channels: usize,
+        texel_channels: usize,
+        dimensions: (u32, u32),
+        nodata: Option<u8>,
+    ) -> Self {
+        Self {
+            inner,
+            channels,
+            texel_channels,
+            texels_left: dimensions.0 as u64 * dimensions.1 as u64,
+            nodata,
+            source: vec![0; CONVERSION_TEXELS * channels],
+            texels: Vec::with_capacity(CONVERSION_TEXELS * texel_channels),
+            position: 0,
+        }
+    }
+
+    fn convert_next(&mut self) -> std::io::Result<()> {
+        let texels = (CONVERSION_TEXELS as u64).min(self.texels_left) as usize;
+        let source = &mut self.source[..texels * self.channels];
+        self.inner.read_exact(source)?;
+
+        self.texels.clear();
+        let (nodata, texel_channels) = (self.nodata, self.texel_channels);
+        self.texels
+            .extend(source.chunks_exact(self.channels).flat_map(|texel| {
+                let rgba = match *texel {
+                    _ if nodata.is_some_and(|nodata| texel.iter().all(|&c| c == nodata)) => [0; 4],
+                    [grey] => [grey, grey, grey, u8::MAX],
+                    // Grey alpha texels keep their alpha in two channel textures.
+                    [grey, alpha] if texel_channels == 2 => [grey, alpha, grey, alpha],
+                    [grey, alpha] => [grey, grey, grey, alpha],
+                    [r, g, b] => [r, g, b, u8::MAX],
+                    [r, g, b, a] => [r, g, b, a],
+                    _ => unreachable!("images have 1 to 4 channels"),
+                };
+                rgba.into_iter().take(texel_channels)
+            }));
+        self.texels_left -= texels as u64;
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for TexelReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == self.texels.len() {
+            if self.texels_left == 0 {
+                return Ok(0);
+            }
+            self.convert_next()?;
+        }
+        let len = buf.len().min(self.texels.len() - self.position);
+        buf[..len].copy_from_slice(&self.texels[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// Reads the rows of a TIFF image one strip, or one row of tiles, at a time.
+pub(super) struct TiffChunkReader<R: Read + std::io::Seek> {
+    decoder: tiff::decoder::Decoder<R>,
+    pub(super) dimensions: (u32, u32),
+    pub(super) channels: usize,
+    /// The next strip or row of tiles to decode.
+    next_band: u32,
+    rows: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read + std::io::Seek> TiffChunkReader<R> {
+    pub(super) fn new(mut decoder: tiff::decoder::Decoder<R>) -> Result<Self, TextureStorageError> {
+        let channels = match decoder.colortype()? {
+            tiff::ColorType::Gray(8) => 1,
+            tiff::ColorType::GrayA(8) => 2,
+            tiff::ColorType::RGB(8) => 3,
+            tiff::ColorType::RGBA(8) => 4,
+            color_type => {
+                return Err(TextureStorageError::UnsupportedImage(format!(
+                    "tiff {color_type:?} texels"
+                )))
+            }
+        };
+        Ok(Self {
+            dimensions: decoder.dimensions()?,
+            decoder,
+            channels,
+            next_band: 0,
+            rows: Vec::new(),
+            position: 0,
+        })
+    }
+
+    fn read_chunk(&mut self, index: u32) -> tiff::TiffResult<Vec<u8>> {
+        match self.decoder.read_chunk(index)? {
+            DecodingResult::U8(data) => Ok(data),
+            _ => unreachable!("only 8 bit images are read"),
+        }
+    }
+
+    /// Decode the next band of rows in `rows`, or leave it empty if there are none.
+    fn decode_next_band(&mut self) -> tiff::TiffResult<()> {
+        let (chunk_width, chunk_height) = self.decoder.chunk_dimensions();
+        self.rows.clear();
+        self.position = 0;
+        if self.next_band * chunk_height >= self.dimensions.1 {
+            return Ok(());
+        }
+
+        match self.decoder.get_chunk_type() {
+            ChunkType::Strip => self.rows = self.read_chunk(self.next_band)?,
+            ChunkType::Tile => {
+                let tiles_across = self.dimensions.0.div_ceil(chunk_width);
+                let row_len = self.dimensions.0 as usize * self.channels;
+                let first_tile = self.next_band * tiles_across;
+                let band_height = self.decoder.chunk_data_dimensions(first_tile).1 as usize;
+                self.rows.resize(row_len * band_height, 0);
+
+                for tile in 0..tiles_across {
+                    let data = self.read_chunk(first_tile + tile)?;
+                    let tile_row_len = self.decoder.chunk_data_dimensions(first_tile + tile).0
+                        as usize
+                        * self.channels;
+                    let start = (tile * chunk_width) as usize * self.channels;
+                    data.chunks_exact(tile_row_len)
+                        .zip(self.rows.chunks_exact_mut(row_len))
+                        .for_each(|(tile_row, row)| {
+                            row[start..start + tile_row_len].copy_from_slice(tile_row)
+                        });
+                }
+            }
+        }
+        self.next_band += 1;
+        Ok(())
+    }
+}
+
+impl<R: Read + std::io::Seek> Read for TiffChunkReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == self.rows.len() {
+            self.decode_next_band().map_err(std::io::Error::other)?;
+        }
+        let len = buf.len().min(self.rows.len() - self.position);
+        buf[..len].copy_from_slice(&self.rows[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::{fixture::TempDir, prelude::*};
+    use vt_core::PageId;
+
+    use crate::{FitOperation, TextureMetadata, TextureStorage};
+
+    /// A 2x2 pages texture with 32 texel pages, and an image one texel short on each side.
+    fn import(file_name: &str, save: impl Fn(&image::RgbImage, &std::path::Path)) -> Vec<u8> {
+        let temp_dir = TempDir::new().unwrap();
+        let texel_width = 2 * 28 + 2 * 2;
+        let image = image::RgbImage::from_fn(texel_width - 1, texel_width - 1, |x, y| {
+            image::Rgb([x as u8, y as u8, 7])
+        });
+        let image_path = temp_dir.child(file_name);
+        save(&image, image_path.path());
+
+        let metadata = TextureMetadata::from_mip(1, 4).with_page_size(32, 2);
+        let path = temp_dir.child("texture");
+        let mut storage =
+            TextureStorage::new(metadata, Some(path.path().to_str().unwrap()), None).unwrap();
+        storage
+            .import_image(
+                image_path.path(),
+                FitOperation::Pad,
+                image::imageops::FilterType::Nearest,
+            )
+            .unwrap();
+        storage.read_page(PageId::new(0, 1, 1)).unwrap()
+    }
+
+    fn assert_bottom_right_page(page: &[u8]) {
+        page.chunks_exact(4).enumerate().for_each(|(i, texel)| {
+            let (x, y) = (28 + i % 32, 28 + i / 32);
+            if x == 59 || y == 59 {
+                assert_eq!(texel, [0, 0, 0, 0], "padding at ({x}, {y})");
+            } else {
+                assert_eq!(texel, [x as u8, y as u8, 7, 255], "texel at ({x}, {y})");
+            }
+        });
+    }
+
+    #[test]
+    fn import_png() {
+        let page = import("image.png", |image, path| image.save(path).unwrap());
+        assert_bottom_right_page(&page);
+    }
+
+    /// Grey images fill single channel textures, and RGB ones keep their red and green in two
+    /// channel ones.
+    #[test]
+    fn import_narrow_textures() {
+        let temp_dir = TempDir::new().unwrap();
+        let image = image::RgbImage::from_fn(60, 60, |x, y| image::Rgb([x as u8, y as u8, 7]));
+        let image_path = temp_dir.child("image.png");
+        image.save(image_path.path()).unwrap();
+        let grey_path = temp_dir.child("grey.png");
+        image::imageops::grayscale(&image)
+            .save(grey_path.path())
+            .unwrap();
+
+        [(1, grey_path.path()), (2, image_path.path())]
+            .into_iter()
+            .for_each(|(bytes_per_texel, path)| {
+                let metadata = TextureMetadata::from_mip(1, bytes_per_texel).with_page_size(32, 2);
+                let directory = temp_dir.child(format!("texture_{bytes_per_texel}"));
+                let mut storage =
+                    TextureStorage::new(metadata, Some(directory.path().to_str().unwrap()), None)
+                        .unwrap();
+                storage
+                    .import_image(
+                        path,
+                        FitOperation::Pad,
+                        image::imageops::FilterType::Nearest,
+                    )
+                    .unwrap();
+                let page = storage.read_page(PageId::new(0, 0, 0)).unwrap();
+                assert_eq!(page.len(), 32 * 32 * bytes_per_texel as usize);
+                let texel = &page[(5 * 32 + 3) * bytes_per_texel as usize..][..2];
+                match bytes_per_texel {
+                    1 => assert_eq!(
+                        texel[0],
+                        image::imageops::grayscale(&image).get_pixel(3, 5).0[0]
+                    ),
+                    _ => assert_eq!(texel, [3, 5]),
+                }
+            });
+    }
+
+    #[test]
+    fn import_tiff_strips() {
+        let page = import("image.tiff", |image, path| {
+            let mut encoder =
+                tiff::encoder::TiffEncoder::new(std::fs::File::create(path).unwrap()).unwrap();
+            let mut tiff = encoder
+                .new_image::<tiff::encoder::colortype::RGB8>(image.width(), image.height())
+                .unwrap();
+            tiff.rows_per_strip(16).unwrap();
+            tiff.write_data(image.as_raw()).unwrap();
+        });
+        assert_bottom_right_page(&page);
+    }
+}
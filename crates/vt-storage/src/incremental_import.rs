@@ -0,0 +1,416 @@
+//! Import of textures spread over many calls, for applications baking textures at runtime
+//! without blocking their frames.
+
+use std::{
+    collections::VecDeque,
+    io::Read,
+    time::{Duration, Instant},
+};
+
+use crate::{mip_generator::MipLevelGen, TextureStorage, TextureStorageError};
+
+/// An import started by [`TextureStorage::begin_import`], which reads and writes the rows of its
+/// texture as [`IncrementalImport::step`] is called.
+///
+/// The rows of mip level 0 are written as they are read, and those of the other mip levels as
+/// soon as the rows they are downsampled from are, so the pages already written can be streamed
+/// while the import goes on (see [`TextureStorage::reader`]).
+///
+/// Imports started by [`TextureStorage::begin_push_import`] are fed their bytes instead, with
+/// [`IncrementalImport::push_rows`].
+pub struct IncrementalImport<R> {
+    byte_streams: Vec<R>,
+    /// Whether the top border of every layer is read, which pushed imports defer until its bytes
+    /// are pushed.
+    border_read: bool,
+    /// The bytes pushed that do not make up a whole row of texels of every layer yet.
+    pending: Vec<u8>,
+    /// The two rows of pages being read of every layer, with the generator of the mip levels of
+    /// the layer.
+    layers: Vec<(Vec<u8>, MipLevelGen)>,
+    /// The rows of mip level 0 written.
+    imported_rows: u16,
+    done: bool,
+}
+
+/// How far an [`IncrementalImport`] is, in rows of mip level 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImportProgress {
+    pub imported_rows: u16,
+    pub rows: u16,
+}
+
+impl ImportProgress {
+    /// Whether every mip level is written, so that the import can be dropped.
+    pub fn is_done(&self) -> bool {
+        self.imported_rows == self.rows
+    }
+}
+
+impl TextureStorage {
+    /// Start importing every layer of the texture from one [`Read`] stream of bytes per layer,
+    /// like [`TextureStorage::import_layers`], without importing any row yet.
+    ///
+    /// The storage must not be imported to by other means until the import is done.
+    pub fn begin_import<R: Read>(
+        &self,
+        filter_mode: image::imageops::FilterType,
+        byte_streams: Vec<R>,
+    ) -> Result<IncrementalImport<R>, TextureStorageError> {
+        let mut import = self.start_import(filter_mode, byte_streams);
+        import.read_border(self)?;
+        Ok(import)
+    }
+
+    /// Start importing every layer of the texture from the bytes pushed to the import with
+    /// [`IncrementalImport::push_rows`], as they are produced (e.g., by a decoder or a
+    /// download), instead of reading them from streams.
+    ///
+    /// The storage must not be imported to by other means until the import is done.
+    pub fn begin_push_import(
+        &self,
+        filter_mode: image::imageops::FilterType,
+    ) -> IncrementalImport<VecDeque<u8>> {
+        let layers = self.metadata().layers().len();
+        self.start_import(filter_mode, vec![VecDeque::new(); layers])
+    }
+
+    /// An import of `byte_streams`, its top border not read yet.
+    fn start_import<R>(
+        &self,
+        filter_mode: image::imageops::FilterType,
+        byte_streams: Vec<R>,
+    ) -> IncrementalImport<R> {
+        assert_eq!(byte_streams.len(), self.metadata().layers().len());
+        let bytes_per_texel = self.metadata().bytes_per_texel as usize;
+        let border_size = self.metadata().border_size() as usize;
+        let page_stride = self.metadata().page_stride() as usize;
+        let texture_texel_width = self.texture_texel_width();
+
+        let layers = (0..byte_streams.len())
+            .map(|layer| {
+                let buffer_len =
+                    bytes_per_texel * texture_texel_width * (page_stride + border_size) * 2;
+                let buffer = vec![0u8; buffer_len];
+                let mipmap_generator = MipLevelGen::from_mip(
+                    self.metadata().mip_levels,
+                    0,
+                    layer,
+                    self.metadata().bytes_per_texel,
+                    filter_mode,
+                );
+                (buffer, mipmap_generator)
+            })
+            .collect::<Vec<_>>();
+
+        IncrementalImport {
+            byte_streams,
+            border_read: false,
+            pending: Vec::new(),
+            layers,
+            imported_rows: 0,
+            done: false,
+        }
+    }
+
+    /// The width of mip level 0 in texels, outer border included.
+    fn texture_texel_width(&self) -> usize {
+        self.metadata().dimensions.0 as usize * self.metadata().page_stride() as usize
+            + 2 * self.metadata().border_size() as usize
+    }
+
+    /// The size of the bottom border of the rows read, moved to the top of the next ones.
+    fn buffer_border_offset(&self) -> usize {
+        self.texture_texel_width()
+            * self.metadata().border_size() as usize
+            * 2
+            * self.metadata().bytes_per_texel as usize
+    }
+}
+
+impl<R: Read> IncrementalImport<R> {
+    /// Read the top border of every layer, above the first row of pages.
+    fn read_border(&mut self, storage: &TextureStorage) -> Result<(), TextureStorageError> {
+        let buffer_border_offset = storage.buffer_border_offset();
+        self.layers
+            .iter_mut()
+            .zip(&mut self.byte_streams)
+            .try_for_each(|((buffer, _), byte_stream)| {
+                byte_stream.read_exact(&mut buffer[..buffer_border_offset])
+            })?;
+        self.border_read = true;
+        Ok(())
+    }
+
+    /// Import rows of `storage`, the storage the import was started with, for about `budget`:
+    /// rows are imported two at a time until the budget is spent, at least two being imported
+    /// per call. The mip levels are finished by the call importing the last row.
+    pub fn step(
+        &mut self,
+        storage: &mut TextureStorage,
+        budget: Duration,
+    ) -> Result<ImportProgress, TextureStorageError> {
+        let start = Instant::now();
+        let rows = storage.metadata().dimensions.1;
+        while self.imported_rows < rows {
+            self.import_next_rows(storage)?;
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+        self.finish_mips(storage)
+    }
+
+    /// Finish the mip levels once every row of mip level 0 is imported.
+    fn finish_mips(
+        &mut self,
+        storage: &mut TextureStorage,
+    ) -> Result<ImportProgress, TextureStorageError> {
+        let rows = storage.metadata().dimensions.1;
+        if self.imported_rows == rows && !self.done {
+            self.layers
+                .iter_mut()
+                .try_for_each(|(_, mipmap_generator)| mipmap_generator.finish(storage))?;
+            self.done = true;
+        }
+        Ok(ImportProgress {
+            imported_rows: self.imported_rows,
+            rows,
+        })
+    }
+
+    /// The bytes of every layer read by the next call to
+    /// [`IncrementalImport::import_next_rows`].
+    fn next_rows_len(&self, storage: &TextureStorage) -> usize {
+        let rows = storage.metadata().dimensions.1;
+        let (buffer, _) = &self.layers[0];
+        let buffer_len = if self.imported_rows + 1 == rows {
+            storage.metadata().page_size() as usize
+                * storage.texture_texel_width()
+                * storage.metadata().bytes_per_texel as usize
+        } else {
+            buffer.len()
+        };
+        buffer_len - storage.buffer_border_offset()
+    }
+
+    /// Import the next two rows of every layer, or the last row of textures one page high.
+    fn import_next_rows(
+        &mut self,
+        storage: &mut TextureStorage,
+    ) -> Result<(), TextureStorageError> {
+        let rows = storage.metadata().dimensions.1;
+        let page_size = storage.metadata().page_size() as usize;
+        let bytes_per_texel = storage.metadata().bytes_per_texel as usize;
+        let page_size_rows = page_size * storage.texture_texel_width() * bytes_per_texel;
+        let buffer_border_offset = storage.buffer_border_offset();
+        let first_row = self.imported_rows as usize;
+
+        // A texture one page high has a single row, without the row below it.
+        if self.imported_rows + 1 == rows {
+            self.layers
+                .iter_mut()
+                .zip(&mut self.byte_streams)
+                .try_for_each(|((buffer, mipmap_generator), byte_stream)| {
+                    byte_stream.read_exact(&mut buffer[buffer_border_offset..page_size_rows])?;
+                    let row = buffer[..page_size_rows].into();
+                    mipmap_generator.write_row(row, first_row, storage)
+                })?;
+            self.imported_rows += 1;
+            return Ok(());
+        }
+
+        self.layers
+            .iter_mut()
+            .zip(&mut self.byte_streams)
+            .try_for_each(|((buffer, mipmap_generator), byte_stream)| {
+                // Read in the next 2 rows
+                byte_stream.read_exact(&mut buffer[buffer_border_offset..])?;
+
+                let first_row_texels = &buffer[0..page_size_rows];
+                let second_row_start = buffer.len() - page_size_rows;
+                let second_row_texels = &buffer[second_row_start..];
+
+                // Write 2 rows
+                mipmap_generator.write_two_rows(
+                    (first_row_texels, second_row_texels),
+                    first_row,
+                    storage,
+                )?;
+
+                // Move bottom border to top border
+                let bottom_border = buffer.len() - buffer_border_offset;
+                buffer.copy_within(bottom_border.., 0);
+
+                Ok::<(), TextureStorageError>(())
+            })?;
+        self.imported_rows += 2;
+        Ok(())
+    }
+}
+
+impl IncrementalImport<VecDeque<u8>> {
+    /// Push the next `bytes` of the texture, the texels of mip level 0 with their outer border
+    /// row by row like [`TextureStorage::import_texture`] reads them, each row of texels holding
+    /// the row of every layer in turn. The bytes are buffered until they make up the next rows
+    /// of pages, which are imported right away, so `bytes` may end anywhere within a row.
+    ///
+    /// The mip levels are finished by the call pushing the last bytes of the texture.
+    pub fn push_rows(
+        &mut self,
+        storage: &mut TextureStorage,
+        bytes: &[u8],
+    ) -> Result<ImportProgress, TextureStorageError> {
+        let row_len = storage.texture_texel_width() * storage.metadata().bytes_per_texel as usize;
+        self.pending.extend_from_slice(bytes);
+        let whole_rows = self.pending.len() / (row_len * self.byte_streams.len());
+        self.pending
+            .drain(..whole_rows * row_len * self.byte_streams.len())
+            .as_slice()
+            .chunks_exact(row_len)
+            .zip((0..self.byte_streams.len()).cycle())
+            .for_each(|(row, layer)| self.byte_streams[layer].extend(row));
+
+        let rows = storage.metadata().dimensions.1;
+        if !self.border_read && self.byte_streams[0].len() >= storage.buffer_border_offset() {
+            self.read_border(storage)?;
+        }
+        while self.border_read
+            && self.imported_rows < rows
+            && self.byte_streams[0].len() >= self.next_rows_len(storage)
+        {
+            self.import_next_rows(storage)?;
+        }
+        self.finish_mips(storage)
+    }
+
+    /// Check that every byte of the texture was pushed, the import being done.
+    ///
+    /// ### Errors
+    ///
+    /// - [`TextureStorageError::IoError`] of [`std::io::ErrorKind::UnexpectedEof`] if rows of the
+    ///   texture are missing.
+    pub fn finish(self, storage: &TextureStorage) -> Result<(), TextureStorageError> {
+        if !self.done {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!(
+                    "{} rows of pages of the texture were not pushed",
+                    storage.metadata().dimensions.1 - self.imported_rows
+                ),
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use assert_fs::fixture::TempDir;
+    use vt_core::PageId;
+
+    use crate::{TextureMetadata, TextureStorage};
+
+    /// Rows imported over several steps can be read before the import is done, and the texture
+    /// ends up the same as a blocking import.
+    #[test]
+    fn import_over_steps() {
+        let metadata = TextureMetadata::from_mip(2, 4).with_page_size(32, 2);
+        let texel_width = 4 * 28 + 2 * 2;
+        let texture = (0..texel_width * texel_width)
+            .flat_map(|texel| {
+                let (x, y) = (texel % texel_width, texel / texel_width);
+                [x as u8, y as u8, 0, 0xFF]
+            })
+            .collect::<Vec<_>>();
+        let storage = |temp_dir: &TempDir| {
+            let path = temp_dir.path().to_str().unwrap();
+            TextureStorage::new(metadata.clone(), Some(path), None).unwrap()
+        };
+
+        let blocking_dir = TempDir::new().unwrap();
+        let mut blocking = storage(&blocking_dir);
+        blocking
+            .import_texture(image::imageops::FilterType::Triangle, &texture[..])
+            .unwrap();
+
+        let incremental_dir = TempDir::new().unwrap();
+        let mut incremental = storage(&incremental_dir);
+        let reader = incremental.reader();
+        let mut import = incremental
+            .begin_import(image::imageops::FilterType::Triangle, vec![&texture[..]])
+            .unwrap();
+        let progress = import.step(&mut incremental, Duration::ZERO).unwrap();
+        assert_eq!((progress.imported_rows, progress.rows), (2, 4));
+        assert!(!progress.is_done());
+        assert!(reader.read_page(PageId::new(0, 3, 1)).is_ok());
+        assert!(reader.read_page(PageId::new(0, 3, 2)).is_err());
+        assert!(import
+            .step(&mut incremental, Duration::ZERO)
+            .unwrap()
+            .is_done());
+
+        (0..=2).for_each(|mip| {
+            let (width, height) = metadata.mip_dimensions(mip);
+            (0..height).for_each(|y| {
+                (0..width).for_each(|x| {
+                    let page = PageId::new(mip, x, y);
+                    assert_eq!(
+                        incremental.read_page(page).unwrap(),
+                        blocking.read_page(page).unwrap()
+                    );
+                })
+            })
+        });
+    }
+
+    /// Bytes pushed in chunks ending anywhere within the rows import the same texture as a
+    /// blocking import, and finishing fails while rows are missing.
+    #[test]
+    fn push_rows() {
+        let metadata = TextureMetadata::from_mip(2, 4).with_page_size(32, 2);
+        let texel_width = 4 * 28 + 2 * 2;
+        let texture = (0..texel_width * texel_width)
+            .flat_map(|texel| [(texel % 251) as u8, (texel / 251) as u8, 0, 0xFF])
+            .collect::<Vec<_>>();
+        let storage = |temp_dir: &TempDir| {
+            let path = temp_dir.path().to_str().unwrap();
+            TextureStorage::new(metadata.clone(), Some(path), None).unwrap()
+        };
+
+        let blocking_dir = TempDir::new().unwrap();
+        let mut blocking = storage(&blocking_dir);
+        blocking
+            .import_texture(image::imageops::FilterType::Triangle, &texture[..])
+            .unwrap();
+
+        let pushed_dir = TempDir::new().unwrap();
+        let mut pushed = storage(&pushed_dir);
+        let mut import = pushed.begin_push_import(image::imageops::FilterType::Triangle);
+        let (first, rest) = texture.split_at(texture.len() * 2 / 3 + 13);
+        let progress = import.push_rows(&mut pushed, first).unwrap();
+        assert_eq!((progress.imported_rows, progress.rows), (2, 4));
+        let incomplete = pushed.begin_push_import(image::imageops::FilterType::Triangle);
+        assert!(incomplete.finish(&pushed).is_err());
+        rest.chunks(1000).for_each(|chunk| {
+            import.push_rows(&mut pushed, chunk).unwrap();
+        });
+        import.finish(&pushed).unwrap();
+
+        (0..=2).for_each(|mip| {
+            let (width, height) = metadata.mip_dimensions(mip);
+            (0..height).for_each(|y| {
+                (0..width).for_each(|x| {
+                    let page = PageId::new(mip, x, y);
+                    assert_eq!(
+                        pushed.read_page(page).unwrap(),
+                        blocking.read_page(page).unwrap()
+                    );
+                })
+            })
+        });
+    }
+}
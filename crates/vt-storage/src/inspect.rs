@@ -0,0 +1,253 @@
+//! Inspection of the textures written by an import, for the asset pipelines checking them.
+
+use std::io::Write;
+
+use vt_core::{ensure, PageId};
+
+use crate::{decode_page, TextureMetadata, TextureStorage, TextureStorageError};
+
+/// The size of a texture on disk, see [`TextureStorage::stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "python",
+    pyo3::pyclass(get_all, frozen, module = "virt_texture")
+)]
+pub struct StorageStats {
+    /// The pages stored for all the mip levels, see [`TextureMetadata::page_count`].
+    pub pages: usize,
+    /// The pages of mip level 0 without data, see [`TextureMetadata::is_missing`].
+    pub missing_pages: usize,
+    /// The bytes of the pages as they are stored, compressed if the texture is.
+    pub stored_bytes: u64,
+    /// The bytes of the pages once decompressed, see [`TextureMetadata::page_byte_size_at`].
+    pub page_bytes: u64,
+}
+
+impl TextureMetadata {
+    /// Whether the pages of `other` have the same ids and sizes, so that they can be compared
+    /// one by one.
+    pub fn same_page_layout(&self, other: &TextureMetadata) -> bool {
+        self.mip_levels == other.mip_levels
+            && self.layers() == other.layers()
+            && (0..=self.mip_levels).all(|mip| {
+                self.page_grid(mip) == other.page_grid(mip)
+                    && self.page_size_at(mip) == other.page_size_at(mip)
+            })
+    }
+
+    /// The size of mip level `mip` in texels, without the outer border of the texture, the size
+    /// of the images of [`TextureStorage::export_mip`].
+    pub fn mip_texel_dimensions(&self, mip: u8) -> (u32, u32) {
+        let (width, height) = self.mip_dimensions(mip);
+        let page_stride = self.page_stride() as u32;
+        (width as u32 * page_stride, height as u32 * page_stride)
+    }
+
+    /// Every page stored for mip level `mip`, row by row.
+    fn stored_pages(&self, mip: u8) -> impl Iterator<Item = PageId> {
+        let (width, height) = self.page_grid(mip);
+        (0..height).flat_map(move |y| (0..width).map(move |x| PageId::new(mip, x, y)))
+    }
+}
+
+impl TextureStorage {
+    /// Read every page as it is stored to measure the texture.
+    pub fn stats(&self) -> Result<StorageStats, TextureStorageError> {
+        let metadata = self.metadata();
+        (0..=metadata.mip_levels).try_fold(
+            StorageStats {
+                pages: metadata.page_count(),
+                missing_pages: metadata.missing_pages.as_ref().map_or(0, Vec::len),
+                ..Default::default()
+            },
+            |mut stats, mip| {
+                metadata.stored_pages(mip).try_for_each(|page| {
+                    let stored = self.reader.read_stored_page(mip, page.x(), page.y())?;
+                    stats.stored_bytes += stored.len() as u64;
+                    stats.page_bytes += metadata.page_byte_size_at(mip) as u64;
+                    Ok::<(), TextureStorageError>(())
+                })?;
+                Ok(stats)
+            },
+        )
+    }
+
+    /// The texels of the first layer of mip level `mip`, assembled from the pages without their
+    /// borders, decoded to RGBA8 (see
+    /// [`TexelFormat::to_rgba8`](crate::TexelFormat::to_rgba8)).
+    ///
+    /// The whole level is held in memory, see [`TextureStorage::write_mip`] for large textures.
+    #[cfg(feature = "image")]
+    pub fn export_mip(&self, mip: u8) -> Result<image::RgbaImage, TextureStorageError> {
+        let (width, height) = self.metadata().mip_texel_dimensions(mip);
+        let mut texels = Vec::with_capacity(width as usize * height as usize * 4);
+        self.write_mip(mip, &mut texels)?;
+        Ok(image::RgbaImage::from_raw(width, height, texels)
+            .expect("every row of the mip level to be written"))
+    }
+
+    /// Write the texels of [`TextureStorage::export_mip`] to `writer`, row by row from
+    /// the top, as RGBA8 texels without any header. A single row of pages is held in memory
+    /// at a time, so that the levels of textures that do not fit in memory can be streamed to
+    /// an encoder or a file.
+    ///
+    /// ### Errors
+    ///
+    /// - [`TextureStorageError::PageOutOfBounds`] if `mip` is past the coarsest mip level.
+    /// - If a page could not be read, or `writer` could not be written to.
+    pub fn write_mip(&self, mip: u8, mut writer: impl Write) -> Result<(), TextureStorageError> {
+        let metadata = self.metadata();
+        ensure!(
+            mip <= metadata.mip_levels,
+            TextureStorageError::PageOutOfBounds(PageId::new(mip, 0, 0))
+        );
+        let page_size = metadata.page_size_at(mip) as usize;
+        let border_size = metadata.border_size_at(mip) as usize;
+        let stride = page_size - 2 * border_size;
+        let (width, height) = metadata.mip_texel_dimensions(mip);
+        let (width, height) = (width as usize, height as usize);
+
+        let encoding = metadata.layers()[0].encoding;
+        let (row_pages, rows) = metadata.page_grid(mip);
+        let mut row_texels = vec![0; width * stride * 4];
+        (0..rows).try_for_each(|row| {
+            let pages = (0..row_pages)
+                .map(|x| PageId::new(mip, x, row))
+                .collect::<Vec<_>>();
+            let texels = self.read_pages(&pages)?;
+            pages.iter().zip(texels).for_each(|(page, texels)| {
+                let page_texels = metadata.texel_format().to_rgba8(decode_page(
+                    encoding,
+                    metadata.split_layers(mip, &texels)[0],
+                    page_size,
+                ));
+                let origin = page.x() as usize * stride;
+                let columns = stride.min(width.saturating_sub(origin));
+                (0..stride).for_each(|y| {
+                    let start = ((y + border_size) * page_size + border_size) * 4;
+                    let target = (y * width + origin) * 4;
+                    row_texels[target..target + columns * 4]
+                        .copy_from_slice(&page_texels[start..start + columns * 4]);
+                });
+            });
+            let lines = stride.min(height.saturating_sub(row as usize * stride));
+            writer.write_all(&row_texels[..lines * width * 4])?;
+            Ok::<(), TextureStorageError>(())
+        })
+    }
+
+    /// The pages whose decompressed bytes differ from the ones of `other`, by mip level then by
+    /// row, to check what a new import changed.
+    ///
+    /// ### Errors
+    ///
+    /// - [`TextureStorageError::LayoutMismatch`] if the textures do not have the same pages (see
+    ///   [`TextureMetadata::same_page_layout`]).
+    /// - If a page could not be read.
+    pub fn diff(&self, other: &TextureStorage) -> Result<Vec<PageId>, TextureStorageError> {
+        ensure!(
+            self.metadata().same_page_layout(other.metadata()),
+            TextureStorageError::LayoutMismatch
+        );
+        let mut changed = Vec::new();
+        (0..=self.metadata().mip_levels).try_for_each(|mip| {
+            self.metadata().stored_pages(mip).try_for_each(|page| {
+                if self.read_page(page)? != other.read_page(page)? {
+                    changed.push(page);
+                }
+                Ok::<(), TextureStorageError>(())
+            })
+        })?;
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::{fixture::TempDir, prelude::*};
+    use vt_core::PageId;
+
+    use crate::{PageCompression, TextureMetadata, TextureStorage, TextureStorageError};
+
+    /// The image imported by [`import`], with `changed_texel` at (20, 3).
+    fn source_image(metadata: &TextureMetadata, changed_texel: u8) -> image::RgbaImage {
+        let (width, height) = metadata.texel_dimensions();
+        image::RgbaImage::from_fn(width, height, |x, y| {
+            let value = if (x, y) == (20, 3) { changed_texel } else { 0 };
+            image::Rgba([x as u8, y as u8, value, 255])
+        })
+    }
+
+    fn import(path: &str, metadata: TextureMetadata, changed_texel: u8) -> TextureStorage {
+        let image = source_image(&metadata, changed_texel);
+        let mut storage = TextureStorage::new(metadata, Some(path), None).unwrap();
+        storage
+            .import_texture(image::imageops::FilterType::Triangle, &image.as_raw()[..])
+            .unwrap();
+        storage
+    }
+
+    #[test]
+    fn inspect_storage() {
+        let temp_dir = TempDir::new().unwrap();
+        let child = |name| temp_dir.child(name).path().to_str().unwrap().to_owned();
+        // 2x2 pages of 12 texels and a border of 2.
+        let metadata = TextureMetadata::from_mip(1, 4)
+            .with_page_size(16, 2)
+            .with_compression(PageCompression::Zstd);
+        let storage = import(&child("a"), metadata.clone(), 0);
+
+        let stats = storage.stats().unwrap();
+        assert_eq!((stats.pages, stats.missing_pages), (5, 0));
+        assert_eq!(stats.page_bytes, 5 * 16 * 16 * 4);
+        assert!(stats.stored_bytes < stats.page_bytes);
+
+        let image = storage.export_mip(0).unwrap();
+        assert_eq!(image.dimensions(), (24, 24));
+        assert_eq!(image.get_pixel(0, 0).0, [2, 2, 0, 255]);
+        assert_eq!(image.get_pixel(13, 1).0, [15, 3, 0, 255]);
+        assert_eq!(storage.export_mip(1).unwrap().dimensions(), (12, 12));
+        assert!(matches!(
+            storage.export_mip(2),
+            Err(TextureStorageError::PageOutOfBounds(_))
+        ));
+
+        // Texel (20, 3) is in page (1, 0) of mip level 0, and in the only page of mip level 1.
+        assert_eq!(storage.diff(&storage).unwrap(), vec![]);
+        let changed = import(&child("b"), metadata, 255);
+        assert_eq!(
+            storage.diff(&changed).unwrap(),
+            vec![PageId::new(0, 1, 0), PageId::new(1, 0, 0)]
+        );
+        let other_layout = import(
+            &child("c"),
+            TextureMetadata::from_mip(2, 4).with_page_size(16, 2),
+            0,
+        );
+        assert!(matches!(
+            storage.diff(&other_layout),
+            Err(TextureStorageError::LayoutMismatch)
+        ));
+    }
+
+    /// Mip level 0 is exported as it was imported, without the outer border nor any seam
+    /// between the pages, and is streamed a row of pages at a time.
+    #[test]
+    fn export_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        // 4x4 pages of 12 texels and a border of 2.
+        let metadata = TextureMetadata::from_mip(2, 4).with_page_size(16, 2);
+        assert_eq!(metadata.mip_texel_dimensions(0), (48, 48));
+        let storage = import(temp_dir.path().to_str().unwrap(), metadata, 7);
+        let source = source_image(storage.metadata(), 7);
+
+        let image = storage.export_mip(0).unwrap();
+        let interior = image::imageops::crop_imm(&source, 2, 2, 48, 48).to_image();
+        assert_eq!(image, interior);
+
+        let mut texels = Vec::new();
+        storage.write_mip(1, &mut texels).unwrap();
+        assert_eq!(texels, storage.export_mip(1).unwrap().into_raw());
+        assert_eq!(texels.len(), 24 * 24 * 4);
+    }
+}
@@ -0,0 +1,1041 @@
+#[cfg(feature = "image")]
+use std::collections::HashMap;
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+use vt_core::{ensure, PageId};
+
+mod archive;
+mod atlas;
+mod block_compression;
+#[cfg(feature = "image")]
+mod cached_import;
+mod coarse_pages;
+mod color_space;
+mod fit;
+mod geotiff;
+mod hdr;
+mod http_source;
+#[cfg(feature = "image")]
+mod image_import;
+#[cfg(feature = "image")]
+mod incremental_import;
+mod inspect;
+mod mip_borders;
+#[cfg(feature = "image")]
+mod mip_generator;
+mod overzoom;
+mod page_reader;
+mod page_source;
+#[cfg(feature = "python")]
+mod python;
+mod reader;
+mod runtime_pages;
+mod snapshot;
+mod temporal;
+mod texel_format;
+
+pub use atlas::AtlasRect;
+pub use block_compression::{decode_page, encode_page};
+#[cfg(feature = "image")]
+pub use cached_import::ImportOptions;
+pub use coarse_pages::CoarsePages;
+pub use color_space::ColorSpace;
+pub use fit::FitOperation;
+pub use geotiff::GeoTransform;
+pub use http_source::HttpPageSource;
+#[cfg(feature = "image")]
+pub use incremental_import::{ImportProgress, IncrementalImport};
+pub use inspect::StorageStats;
+pub use overzoom::OverzoomedPage;
+#[cfg(feature = "tokio")]
+pub use page_reader::TokioPageReader;
+pub use page_reader::{BlockingPageReader, PageRead, PageReader, ThreadedPageReader};
+pub use page_source::PageSource;
+pub use reader::TextureReader;
+pub use runtime_pages::RuntimePageSource;
+pub use temporal::TemporalPageSource;
+pub use texel_format::TexelFormat;
+
+pub use color_space::downsample_page;
+pub use hdr::{pack_rg11b10, rgba8_to_half};
+pub use mip_borders::pad_page;
+
+use miniserde::{Deserialize, MiniSerialize};
+use thiserror::Error;
+
+pub const DEFAULT_PAGE_SIZE: u16 = 128;
+pub const DEFAULT_PAGE_BORDER_SIZE: u16 = 4;
+#[cfg(feature = "image")]
+const ZSTD_LEVEL: i32 = 3;
+/// Size of an entry of the page offset table at the start of compressed row files.
+const PAGE_OFFSET_SIZE: usize = std::mem::size_of::<u64>();
+
+/// The narrowest border of pages sampled with up to `max_anisotropy` anisotropic filtering, whose
+/// taps spread along the major axis of the footprint, half of it on each side of the sample. A
+/// single texel is enough for bilinear filtering.
+pub const fn anisotropic_border_size(max_anisotropy: u16) -> u16 {
+    if max_anisotropy <= 1 {
+        1
+    } else {
+        max_anisotropy / 2 + 2
+    }
+}
+
+/// A texture stored on disk, which writes its pages on import. Its pages are read by
+/// [`TextureReader`]s, which can be shared with other threads.
+pub struct TextureStorage {
+    /// Holds the directory, the metadata, and the packed archive, set when the pages are read
+    /// from an archive instead of row files. The metadata is copied on write when other readers
+    /// share it.
+    reader: TextureReader,
+    /// Empty for packed textures, whose metadata is in the archive.
+    metadata_path: std::path::PathBuf,
+    /// Rows of layered textures waiting for the rows of their other layers, by (mip, row).
+    #[cfg(feature = "image")]
+    pending_rows: HashMap<(u8, u16), PendingLayers>,
+    /// Coarse rows waiting for the regular rows they are assembled from, by (mip, coarse row),
+    /// see [`TextureMetadata::with_coarse_pages`].
+    #[cfg(feature = "image")]
+    pending_coarse_rows: HashMap<(u8, u16), coarse_pages::PendingCoarseRow>,
+    /// Record the pages of mip level 0 made only of transparent texels as missing while writing,
+    /// see [`TextureMetadata::is_missing`].
+    #[cfg(feature = "image")]
+    detect_missing_pages: bool,
+}
+
+/// The row of every layer, once written.
+#[cfg(feature = "image")]
+type PendingLayers = Vec<Option<Box<[u8]>>>;
+
+impl TextureStorage {
+    /// The `texture` directory at the root of the workspace.
+    const DEFAULT_DIRECTORY: &'static str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../texture");
+    const DEFAULT_METADATA_FILE: &'static str = "meta";
+
+    /// Creates a new texture storage manager in the directory provided  with '{metadata_file}.json' as the metadata file (Default: "meta").
+    /// - `name` (Default: the `texture` directory at the root of the workspace): The directory that will contain the texture.
+    /// - `metadata_file` (Default: "meta"): The name of the metadata file for the texture.
+    ///
+    /// ### Errors
+    ///
+    /// - If the directory that will contain the texture already exists.
+    /// - If the directory could not be created
+    /// - If the metadata file could not be created/opened.
+    pub fn new(
+        metadata: TextureMetadata,
+        name: Option<&str>,
+        metadata_file: Option<&str>,
+    ) -> Result<Self, TextureStorageError> {
+        let directory = PathBuf::from(name.unwrap_or(Self::DEFAULT_DIRECTORY));
+
+        std::fs::create_dir_all(&directory)?;
+
+        let storage = Self {
+            metadata_path: directory.join(format!(
+                "{}.json",
+                metadata_file.unwrap_or(Self::DEFAULT_METADATA_FILE)
+            )),
+            reader: TextureReader {
+                directory: directory.into(),
+                metadata: Arc::new(metadata),
+                archive: None,
+                row_files: Default::default(),
+            },
+            #[cfg(feature = "image")]
+            pending_rows: HashMap::new(),
+            #[cfg(feature = "image")]
+            pending_coarse_rows: HashMap::new(),
+            #[cfg(feature = "image")]
+            detect_missing_pages: false,
+        };
+        storage.save_metadata()?;
+        Ok(storage)
+    }
+
+    /// Load an existing texture from the directory provided (Default: the `texture` directory at the root of the workspace) with
+    /// '{metadata_file}.json' as the metadata file (Default: "meta").
+    pub fn load(
+        directory: Option<&str>,
+        metadata_file: Option<&str>,
+    ) -> Result<Self, TextureStorageError> {
+        let directory = PathBuf::from(directory.unwrap_or(Self::DEFAULT_DIRECTORY));
+        let metadata_path = directory.join(format!(
+            "{}.json",
+            metadata_file.unwrap_or(Self::DEFAULT_METADATA_FILE)
+        ));
+
+        let mut meta_file = File::open(&metadata_path)?;
+
+        let mut metadata_string = String::new();
+        meta_file.read_to_string(&mut metadata_string)?;
+
+        let metadata: TextureMetadata = miniserde::json::from_str(&metadata_string)?;
+
+        Ok(Self {
+            reader: TextureReader {
+                directory: directory.into(),
+                metadata: Arc::new(metadata),
+                archive: None,
+                row_files: Default::default(),
+            },
+            metadata_path,
+            #[cfg(feature = "image")]
+            pending_rows: HashMap::new(),
+            #[cfg(feature = "image")]
+            pending_coarse_rows: HashMap::new(),
+            #[cfg(feature = "image")]
+            detect_missing_pages: false,
+        })
+    }
+
+    pub fn metadata(&self) -> &TextureMetadata {
+        &self.reader.metadata
+    }
+
+    /// The metadata, to be changed by an import. The readers created before keep the metadata
+    /// they were created with.
+    #[cfg(feature = "image")]
+    fn metadata_mut(&mut self) -> &mut TextureMetadata {
+        Arc::make_mut(&mut self.reader.metadata)
+    }
+
+    /// Write the metadata file, for the metadata changed by an import.
+    fn save_metadata(&self) -> Result<(), TextureStorageError> {
+        ensure!(self.reader.archive.is_none(), TextureStorageError::Packed);
+        let mut meta_file = File::create(&self.metadata_path)?;
+        meta_file.write_all(miniserde::json::to_string(&self.metadata()).as_bytes())?;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn write_row(&mut self, mip: u8, row: u16, data: &[u8]) -> Result<(), TextureStorageError> {
+        debug_assert!(self.metadata().layers().len() == 1);
+        self.write_layer_row(0, mip, row, data)
+    }
+
+    /// Write a row of a layer. The row file is written once every layer of the row is written.
+    #[cfg(feature = "image")]
+    fn write_layer_row(
+        &mut self,
+        layer: usize,
+        mip: u8,
+        row: u16,
+        data: &[u8],
+    ) -> Result<(), TextureStorageError> {
+        ensure!(self.reader.archive.is_none(), TextureStorageError::Packed);
+        let layer_count = self.metadata().layers().len();
+        if layer_count == 1 {
+            return self.write_row_layers(mip, row, &[data]);
+        }
+
+        let layers = self
+            .pending_rows
+            .entry((mip, row))
+            .or_insert_with(|| vec![None; layer_count]);
+        layers[layer] = Some(data.into());
+        if layers.iter().all(Option::is_some) {
+            let layers = self.pending_rows.remove(&(mip, row)).unwrap();
+            let layers = layers
+                .iter()
+                .map(|layer| layer.as_deref().unwrap())
+                .collect::<Vec<_>>();
+            self.write_row_layers(mip, row, &layers)?;
+        }
+        Ok(())
+    }
+
+    /// Write a row of every layer, or keep it for its coarse row on coarse mip levels.
+    #[cfg(feature = "image")]
+    fn write_row_layers(
+        &mut self,
+        mip: u8,
+        row: u16,
+        layers: &[&[u8]],
+    ) -> Result<(), TextureStorageError> {
+        if self.metadata().page_scale(mip) > 0 {
+            return self.write_coarse_row_layers(mip, row, layers);
+        }
+        self.write_page_row(mip, row, layers)
+    }
+
+    /// Write a row file, interleaving the pages of every layer. Each layer holds the
+    /// [`TextureMetadata::generated_page_size_at`] rows of texels of the row of pages, which are
+    /// cropped to [`TextureMetadata::page_size_at`].
+    #[cfg(feature = "image")]
+    fn write_page_row(
+        &mut self,
+        mip: u8,
+        row: u16,
+        layers: &[&[u8]],
+    ) -> Result<(), TextureStorageError> {
+        let data = layers[0];
+        let generated_page_size = self.metadata().generated_page_size_at(mip) as usize;
+        let page_size = self.metadata().page_size_at(mip) as usize;
+        let inset = self.metadata().border_inset(mip) as usize;
+        let border_size = self.metadata().border_size() as usize;
+        let page_stride = generated_page_size - 2 * border_size;
+        let texture_texel_width =
+            data.len() / self.metadata().bytes_per_texel as usize / generated_page_size;
+        // The last coarse page of a row may extend past the texture.
+        let page_count = (texture_texel_width - 2 * border_size).div_ceil(page_stride);
+        assert_eq!(page_count, self.metadata().page_grid(mip).0 as usize);
+
+        let bytes_per_texel = self.metadata().bytes_per_texel as usize;
+        let encodings = self
+            .metadata()
+            .layers()
+            .iter()
+            .map(|layer| layer.encoding)
+            .collect::<Vec<_>>();
+        // Only the pages of textures with an alpha channel can be made of transparent texels.
+        let alpha_bytes = self
+            .metadata()
+            .texel_format()
+            .alpha_bytes()
+            .filter(|_| self.detect_missing_pages && mip == 0);
+        let mut missing_pages = Vec::new();
+        let mut page_buffer = vec![0; page_size * page_size * bytes_per_texel];
+        let pages = (0..page_count)
+            .map(|page| {
+                let column_offset = page * page_stride + inset;
+                let mut page_layers = Vec::with_capacity(self.metadata().page_byte_size_at(mip));
+                let copied_texels = (texture_texel_width - column_offset).min(page_size);
+                layers
+                    .iter()
+                    .zip(&encodings)
+                    .enumerate()
+                    .for_each(|(layer, (data, &encoding))| {
+                        page_buffer
+                            .chunks_exact_mut(page_size * bytes_per_texel)
+                            .enumerate()
+                            .for_each(|(page_row, page_row_buffer)| {
+                                let start = (column_offset
+                                    + (page_row + inset) * texture_texel_width)
+                                    * bytes_per_texel;
+                                let copied = copied_texels * bytes_per_texel;
+                                page_row_buffer[..copied]
+                                    .copy_from_slice(&data[start..start + copied]);
+                                page_row_buffer[copied..].fill(0);
+                            });
+                        if layer == 0
+                            && alpha_bytes.as_ref().is_some_and(|alpha_bytes| {
+                                page_buffer.chunks_exact(bytes_per_texel).all(|texel| {
+                                    texel[alpha_bytes.clone()].iter().all(|&byte| byte == 0)
+                                })
+                            })
+                        {
+                            missing_pages.push((page as u16, row));
+                        }
+                        page_layers.extend(encode_page(encoding, &page_buffer, page_size));
+                    });
+                let page = page_layers;
+                match self.metadata().compression() {
+                    PageCompression::None => Ok(page),
+                    PageCompression::Zstd => zstd::bulk::compress(&page, ZSTD_LEVEL),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut offset_table = Vec::new();
+        if self.metadata().compression() == PageCompression::Zstd {
+            // Offsets of the start of every page, and of the end of the last one.
+            let mut offset = ((page_count + 1) * PAGE_OFFSET_SIZE) as u64;
+            offset_table.reserve((page_count + 1) * PAGE_OFFSET_SIZE);
+            offset_table.extend_from_slice(&offset.to_le_bytes());
+            pages.iter().for_each(|page| {
+                offset += page.len() as u64;
+                offset_table.extend_from_slice(&offset.to_le_bytes());
+            });
+        }
+        // The row file is replaced instead of rewritten, so that the snapshots linking to it keep
+        // the previous row (see `TextureStorage::snapshot`).
+        let path = reader::row_file_path(&self.reader.directory, (mip, row));
+        let written_path = path.with_extension("tmp");
+        self.reader.row_files.write((mip, row), || {
+            let mut file = File::create(&written_path)?;
+            file.write_all(&offset_table)?;
+            pages.iter().try_for_each(|page| file.write_all(page))?;
+            drop(file);
+            std::fs::rename(&written_path, &path).map_err(TextureStorageError::from)
+        })?;
+        log::debug!("wrote row {} of mip level {}", row, mip);
+        missing_pages
+            .into_iter()
+            .for_each(|page| self.metadata_mut().insert_missing_page(page));
+
+        Ok(())
+    }
+
+    /// Import a new texture from a [`Read`] stream of bytes
+    ///
+    /// The texture must have the dimensions of [`TextureMetadata::texel_dimensions`], use
+    /// [`TextureStorage::import_fitted_texture`] for other sizes.
+    #[cfg(feature = "image")]
+    pub fn import_texture(
+        &mut self,
+        filter_mode: image::imageops::FilterType,
+        byte_stream: impl Read,
+    ) -> Result<(), TextureStorageError> {
+        self.import_layers(filter_mode, vec![byte_stream])
+    }
+
+    /// Import every layer of a layered texture (see [`TextureMetadata::with_layers`]), from one
+    /// [`Read`] stream of bytes per layer, in the order of the layers.
+    ///
+    /// The streams are read in lockstep, two rows of pages at a time. Use
+    /// [`TextureStorage::begin_import`] to spread the import over many frames instead.
+    #[cfg(feature = "image")]
+    pub fn import_layers(
+        &mut self,
+        filter_mode: image::imageops::FilterType,
+        byte_streams: Vec<impl Read>,
+    ) -> Result<(), TextureStorageError> {
+        let mut import = self.begin_import(filter_mode, byte_streams)?;
+        import.step(self, std::time::Duration::MAX)?;
+        Ok(())
+    }
+
+    /// A reader of the texture, to read its pages from other threads while it is written (see
+    /// [`TextureReader`]).
+    pub fn reader(&self) -> TextureReader {
+        self.reader.clone()
+    }
+
+    /// See [`TextureReader::read_page`].
+    pub fn read_page(&self, page: PageId) -> Result<Vec<u8>, TextureStorageError> {
+        self.reader.read_page(page)
+    }
+
+    /// See [`TextureReader::read_pages`].
+    pub fn read_pages(&self, pages: &[PageId]) -> Result<Vec<Vec<u8>>, TextureStorageError> {
+        self.reader.read_pages(pages)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TextureStorageError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error(
+        "could not parse metadata file, this should only occur if the file was edited manually"
+    )]
+    Deserialization(#[from] miniserde::Error),
+    #[error("page {0:?} is out of the bounds of the texture")]
+    PageOutOfBounds(PageId),
+    #[error("the texture is packed, and can no longer be written to")]
+    Packed,
+    #[error("the file is not a packed texture archive, or was written by an incompatible version")]
+    InvalidArchive,
+    #[cfg(feature = "image")]
+    #[error("could not decode image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("could not decode tiff image: {0}")]
+    Tiff(#[from] tiff::TiffError),
+    #[error("unsupported image: {0}")]
+    UnsupportedImage(String),
+    #[error("the textures do not have the same pages")]
+    LayoutMismatch,
+    #[error("http error: {0}")]
+    Http(String),
+    #[error("reading page {0:?} panicked")]
+    ReadPanicked(PageId),
+}
+
+/// How the texels of a page are encoded on disk.
+#[derive(MiniSerialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PageEncoding {
+    /// Uncompressed texels.
+    #[default]
+    Raw,
+    /// BC7 blocks, for color data.
+    Bc7,
+    /// BC5 blocks holding the red and green channels, for normal maps.
+    Bc5,
+}
+
+/// How pages are compressed in their row file, on top of their [`PageEncoding`].
+#[derive(MiniSerialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PageCompression {
+    #[default]
+    None,
+    /// Every page is compressed independently with zstd, and the row file starts with a table of
+    /// page offsets, so that a single page can be read without reading the whole row.
+    Zstd,
+}
+
+/// A layer of a texture whose pages hold several materials channels (e.g., albedo and normals).
+///
+/// The pages of every layer are stored together, so that they are streamed at once.
+#[derive(MiniSerialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TextureLayer {
+    pub name: String,
+    pub encoding: PageEncoding,
+    /// See [`TextureLayer::color_space`].
+    color_space: Option<ColorSpace>,
+}
+
+impl TextureLayer {
+    pub fn new(name: impl Into<String>, encoding: PageEncoding) -> Self {
+        Self {
+            name: name.into(),
+            encoding,
+            color_space: None,
+        }
+    }
+}
+
+#[derive(MiniSerialize, Deserialize, Clone)]
+pub struct TextureMetadata {
+    dimensions: (u16, u16),
+    bytes_per_texel: u8,
+    mip_levels: u8,
+    // Optional so that metadata files written before compression support still load.
+    encoding: Option<PageEncoding>,
+    compression: Option<PageCompression>,
+    page_size: Option<u16>,
+    border_size: Option<u16>,
+    /// `None` for a single layer with `encoding`.
+    layers: Option<Vec<TextureLayer>>,
+    geo_transform: Option<GeoTransform>,
+    /// The (x, y) of the pages of mip level 0 without data, sorted by row then by column.
+    missing_pages: Option<Vec<(u16, u16)>>,
+    coarse_pages: Option<CoarsePages>,
+    /// The images packed in the texture, see [`TextureMetadata::with_atlas`].
+    atlas: Option<Vec<AtlasRect>>,
+    /// See [`TextureMetadata::with_mip_border_sizes`].
+    mip_border_sizes: Option<Vec<u16>>,
+    /// See [`TextureMetadata::source_hash`].
+    source_hash: Option<String>,
+    /// The color space of single layer textures, see [`TextureMetadata::with_color_space`].
+    color_space: Option<ColorSpace>,
+}
+
+impl TextureMetadata {
+    const MAX_TEXTURE_SIZE: u16 = 1 << 12;
+
+    /// Creates a texture from the provided number of pages per side and bytes per texel: 1 or 2
+    /// for single or two channel texels, 4 for RGBA8 texels and 8 for RGBA16F ones (see
+    /// [`TexelFormat`]).
+    ///
+    /// See [`TextureMetadata::from_texel_dimensions`] to fit an image of any size.
+    ///
+    /// ### Panics
+    ///
+    /// - If any of the sides is bigger than 4096 (2^12).
+    /// - If any of the sides is not a power of two.
+    /// - If the bytes per texel are not the ones of a [`TexelFormat`].
+    pub fn from_dimensions(dimensions: (u16, u16), bytes_per_texel: u8) -> Self {
+        assert!(dimensions.0 <= Self::MAX_TEXTURE_SIZE);
+        assert!(dimensions.1 <= Self::MAX_TEXTURE_SIZE);
+        assert!(dimensions.0.is_power_of_two());
+        assert!(dimensions.1.is_power_of_two());
+        assert!(TexelFormat::from_bytes_per_texel(bytes_per_texel).is_some());
+        let longest_side = dimensions.0.max(dimensions.1);
+        let mip_levels = longest_side.ilog2() as u8;
+
+        Self {
+            dimensions,
+            bytes_per_texel,
+            mip_levels,
+            encoding: None,
+            compression: None,
+            page_size: None,
+            border_size: None,
+            layers: None,
+            geo_transform: None,
+            missing_pages: None,
+            coarse_pages: None,
+            atlas: None,
+            mip_border_sizes: None,
+            source_hash: None,
+            color_space: None,
+        }
+    }
+
+    /// Creates a square texture from the mip level.
+    ///
+    /// ### Panics
+    ///
+    /// - If the mip level is bigger than lg(MAX_TEXTURE_SIZE).
+    /// - If the bytes per texel are not the ones of a [`TexelFormat`].
+    pub fn from_mip(mip_levels: u8, bytes_per_texel: u8) -> Self {
+        assert!(mip_levels <= Self::MAX_TEXTURE_SIZE.ilog2() as u8);
+        assert!(TexelFormat::from_bytes_per_texel(bytes_per_texel).is_some());
+        let page_size = 1 << mip_levels;
+
+        Self {
+            dimensions: (page_size, page_size),
+            bytes_per_texel,
+            mip_levels,
+            encoding: None,
+            compression: None,
+            page_size: None,
+            border_size: None,
+            layers: None,
+            geo_transform: None,
+            missing_pages: None,
+            coarse_pages: None,
+            atlas: None,
+            mip_border_sizes: None,
+            source_hash: None,
+            color_space: None,
+        }
+    }
+
+    /// Store the pages of the texture with the provided encoding.
+    ///
+    /// ### Panics
+    ///
+    /// - If the texels cannot be stored with the encoding (see
+    ///   [`TexelFormat::supports_encoding`]).
+    pub fn with_encoding(mut self, encoding: PageEncoding) -> Self {
+        assert!(self.texel_format().supports_encoding(encoding));
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// The encoding of single layer textures, see [`TextureMetadata::layers`] for layered ones.
+    pub fn encoding(&self) -> PageEncoding {
+        self.encoding.unwrap_or_default()
+    }
+
+    /// Store several layers in every page, each with its own encoding.
+    ///
+    /// ### Panics
+    ///
+    /// - If there are no layers.
+    /// - If the texels cannot be stored with the encoding of a layer (see
+    ///   [`TexelFormat::supports_encoding`]).
+    pub fn with_layers(mut self, layers: Vec<TextureLayer>) -> Self {
+        assert!(!layers.is_empty());
+        assert!(layers
+            .iter()
+            .all(|layer| self.texel_format().supports_encoding(layer.encoding)));
+        self.layers = Some(layers);
+        self
+    }
+
+    /// The layers stored in every page, a single one named "color" unless set with
+    /// [`TextureMetadata::with_layers`]. The layers of textures that are not RGBA8 are linear.
+    pub fn layers(&self) -> Vec<TextureLayer> {
+        let layers = self.layers.clone().unwrap_or_else(|| {
+            vec![TextureLayer {
+                color_space: self.color_space,
+                ..TextureLayer::new("color", self.encoding())
+            }]
+        });
+        match self.texel_format() {
+            TexelFormat::Rgba8 => layers,
+            TexelFormat::R8 | TexelFormat::Rg8 | TexelFormat::Rgba16Float => layers
+                .into_iter()
+                .map(|layer| layer.with_color_space(ColorSpace::Linear))
+                .collect(),
+        }
+    }
+
+    /// Split a page of mip level `mip` read from storage in the pages of each of its layers.
+    pub fn split_layers<'a>(&self, mip: u8, mut page: &'a [u8]) -> Vec<&'a [u8]> {
+        let page_size = self.page_size_at(mip);
+        self.layers()
+            .iter()
+            .map(|layer| {
+                let (layer_page, rest) =
+                    page.split_at(self.layer_byte_size(layer.encoding, page_size));
+                page = rest;
+                layer_page
+            })
+            .collect()
+    }
+
+    /// Compress the pages of the texture on disk.
+    pub fn with_compression(mut self, compression: PageCompression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    pub fn compression(&self) -> PageCompression {
+        self.compression.unwrap_or_default()
+    }
+
+    /// Use pages of `page_size * page_size` texels, of which `border_size` texels on each side
+    /// are copied from the neighbouring pages (Default: 128 and 4).
+    ///
+    /// ### Panics
+    ///
+    /// - If `page_size` is not a multiple of 4 (the size of compressed blocks).
+    /// - If the borders take the whole page.
+    pub fn with_page_size(mut self, page_size: u16, border_size: u16) -> Self {
+        assert!(page_size.is_multiple_of(4));
+        assert!(2 * border_size < page_size);
+        self.page_size = Some(page_size);
+        self.border_size = Some(border_size);
+        self
+    }
+
+    /// The size of the side of a page in texels, borders included.
+    pub fn page_size(&self) -> u16 {
+        self.page_size.unwrap_or(DEFAULT_PAGE_SIZE)
+    }
+
+    /// The size of the border on each side of a page in texels.
+    pub fn border_size(&self) -> u16 {
+        self.border_size.unwrap_or(DEFAULT_PAGE_BORDER_SIZE)
+    }
+
+    /// The size of the side of a page in texels, without borders.
+    pub fn page_stride(&self) -> u16 {
+        self.page_size() - 2 * self.border_size()
+    }
+
+    /// The size of the side of the pages stored for mip level `mip` in texels, borders included,
+    /// larger than [`TextureMetadata::page_size`] on coarse mip levels, and smaller on the mip
+    /// levels with a narrower border (see [`TextureMetadata::with_mip_border_sizes`]).
+    pub fn page_size_at(&self, mip: u8) -> u16 {
+        (self.page_stride() << self.page_scale(mip)) + 2 * self.border_size_at(mip)
+    }
+
+    /// The coarsest mip level, the mip levels from 0 to it are stored.
+    pub fn mip_levels(&self) -> u8 {
+        self.mip_levels
+    }
+
+    /// The number of pages on each side of a mip level.
+    pub fn mip_dimensions(&self, mip: u8) -> (u16, u16) {
+        (
+            self.dimensions
+                .0
+                .checked_shr(mip.into())
+                .unwrap_or(0)
+                .max(1),
+            self.dimensions
+                .1
+                .checked_shr(mip.into())
+                .unwrap_or(0)
+                .max(1),
+        )
+    }
+
+    /// Whether `page` holds no data, because every page of mip level 0 it covers was made only of
+    /// nodata texels (see [`TextureStorage::import_geotiff`]).
+    ///
+    /// Missing pages are still stored, but should not be streamed in: their parent holds the
+    /// same data, or they can be synthesized from it with
+    /// [`TextureStorage::read_page_overzoomed`].
+    pub fn is_missing(&self, page: PageId) -> bool {
+        let Some(missing_pages) = &self.missing_pages else {
+            return false;
+        };
+        let shift = page.mip_level() + self.page_scale(page.mip_level());
+        let (width, height) = self.mip_dimensions(0);
+        let (start_x, start_y) = (page.x() << shift, page.y() << shift);
+        let (end_x, end_y) = (
+            ((page.x() + 1) << shift).min(width),
+            ((page.y() + 1) << shift).min(height),
+        );
+        (start_y..end_y).all(|y| {
+            (start_x..end_x).all(|x| {
+                missing_pages
+                    .binary_search_by_key(&(y, x), |&(x, y)| (y, x))
+                    .is_ok()
+            })
+        })
+    }
+
+    #[cfg(feature = "image")]
+    fn insert_missing_page(&mut self, (x, y): (u16, u16)) {
+        let missing_pages = self.missing_pages.get_or_insert_with(Vec::new);
+        if let Err(index) = missing_pages.binary_search_by_key(&(y, x), |&(x, y)| (y, x)) {
+            missing_pages.insert(index, (x, y));
+        }
+    }
+
+    /// The number of pages stored for all the mip levels.
+    pub fn page_count(&self) -> usize {
+        (0..=self.mip_levels)
+            .map(|mip| {
+                let (width, height) = self.page_grid(mip);
+                width as usize * height as usize
+            })
+            .sum()
+    }
+
+    /// The index of a page when all pages are ordered by mip level, then by row, then by column.
+    fn page_index(&self, mip: u8, x: u16, y: u16) -> usize {
+        let (width, _) = self.page_grid(mip);
+        let previous_pages = (0..mip)
+            .map(|mip| {
+                let (width, height) = self.page_grid(mip);
+                width as usize * height as usize
+            })
+            .sum::<usize>();
+        previous_pages + y as usize * width as usize + x as usize
+    }
+
+    /// The size in bytes of a single page on disk, every layer included.
+    pub fn page_byte_size(&self) -> usize {
+        self.page_byte_size_at(0)
+    }
+
+    /// The size in bytes of a single page of mip level `mip` on disk, every layer included.
+    pub fn page_byte_size_at(&self, mip: u8) -> usize {
+        let page_size = self.page_size_at(mip);
+        self.layers()
+            .iter()
+            .map(|layer| self.layer_byte_size(layer.encoding, page_size))
+            .sum()
+    }
+
+    /// The size in bytes of a layer of a page of `page_size` texels with `encoding`.
+    fn layer_byte_size(&self, encoding: PageEncoding, page_size: u16) -> usize {
+        match encoding {
+            PageEncoding::Raw => (page_size as usize).pow(2) * self.bytes_per_texel as usize,
+            PageEncoding::Bc7 | PageEncoding::Bc5 => {
+                (page_size as usize / block_compression::BLOCK_SIZE).pow(2)
+                    * block_compression::BLOCK_BYTES
+            }
+        }
+    }
+}
+
+// fn next_power_of_two(mut n: u16) -> u16 {
+//     n -= 1;
+//     n |= n >> 1; // Divide by 2^k for consecutive doublings of k up to 32,
+//     n |= n >> 2; // and then or the results.
+//     n |= n >> 4;
+//     n |= n >> 8;
+//     n + 1 // The result is a number of 1 bits equal to the number
+//           // of bits in the original number, plus 1. That's the
+//           // next highest power of 2.
+// }
+
+#[cfg(test)]
+mod test {
+    use std::io::{repeat, Read};
+
+    use assert_fs::{fixture::TempDir, prelude::*};
+    use predicates::prelude::*;
+    use vt_core::PageId;
+
+    use super::{
+        decode_page, PageCompression, PageEncoding, TextureLayer, TextureMetadata, TextureStorage,
+        TextureStorageError, DEFAULT_PAGE_BORDER_SIZE, DEFAULT_PAGE_SIZE,
+    };
+
+    const PAGE_SIZE: usize = DEFAULT_PAGE_SIZE as usize;
+    const PAGE_BORDER_SIZE: usize = DEFAULT_PAGE_BORDER_SIZE as usize;
+    const PAGE_STRIDE: usize = PAGE_SIZE - 2 * PAGE_BORDER_SIZE;
+
+    #[test]
+    fn create_texture_storage() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().as_os_str().to_str().unwrap();
+        let _ = TextureStorage::new(TextureMetadata::from_mip(4, 4), Some(path), None).unwrap();
+        temp_dir
+            .child("meta.json")
+            .assert(predicate::path::exists());
+    }
+
+    #[test]
+    fn load_texture_storage() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().as_os_str().to_str().unwrap();
+        let metadata = temp_dir.child("meta.json");
+
+        metadata.touch().unwrap();
+        metadata
+            .write_str(r#"{"dimensions": [16, 16], "bytes_per_texel": 4, "mip_levels": 4}"#)
+            .unwrap();
+
+        let _ = TextureStorage::load(Some(path), None).unwrap();
+    }
+
+    #[test]
+    fn store_256_texture() -> Result<(), Box<dyn std::error::Error>> {
+        env_logger::init();
+        let (mut texture_storage, _temp_dir) = texture_storage_from_mip(256_usize.ilog2() as u8);
+        let bytes =
+            repeat(0xFF).take(((256 * PAGE_STRIDE + 2 * PAGE_BORDER_SIZE).pow(2) * 4) as u64);
+        texture_storage.import_texture(image::imageops::FilterType::Nearest, bytes)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn import_with_small_pages() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().as_os_str().to_str().unwrap();
+        let metadata = TextureMetadata::from_mip(2, 4).with_page_size(32, 2);
+        let mut storage = TextureStorage::new(metadata, Some(path), None).unwrap();
+
+        let texel_width = 4 * 28 + 2 * 2;
+        let texture = (0..texel_width * texel_width)
+            .flat_map(|texel| {
+                let (x, y) = (texel % texel_width, texel / texel_width);
+                [x as u8, y as u8, 0, 0xFF]
+            })
+            .collect::<Vec<_>>();
+        storage
+            .import_texture(image::imageops::FilterType::Nearest, &texture[..])
+            .unwrap();
+
+        let page = storage.read_page(PageId::new(0, 1, 2)).unwrap();
+        assert_eq!(page.len(), 32 * 32 * 4);
+        page.chunks_exact(32 * 4)
+            .enumerate()
+            .for_each(|(y, page_row)| {
+                let start = ((2 * 28 + y) * texel_width + 28) * 4;
+                assert_eq!(page_row, &texture[start..start + 32 * 4]);
+            });
+        assert!(storage.read_page(PageId::new(2, 0, 0)).is_ok());
+    }
+
+    #[test]
+    fn import_layered_texture() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().as_os_str().to_str().unwrap();
+        let metadata = TextureMetadata::from_mip(1, 4)
+            .with_page_size(32, 2)
+            .with_layers(vec![
+                TextureLayer::new("albedo", PageEncoding::Raw),
+                TextureLayer::new("normal", PageEncoding::Bc5),
+            ]);
+        let mut storage = TextureStorage::new(metadata, Some(path), None).unwrap();
+
+        let texels = (2 * 28 + 2 * 2) * (2 * 28 + 2 * 2);
+        let albedo = [1, 2, 3, 4].repeat(texels);
+        let normal = [128, 128, 255, 255].repeat(texels);
+        storage
+            .import_layers(
+                image::imageops::FilterType::Nearest,
+                vec![&albedo[..], &normal[..]],
+            )
+            .unwrap();
+
+        [PageId::new(0, 1, 1), PageId::new(1, 0, 0)]
+            .iter()
+            .for_each(|&page_id| {
+                let page = storage.read_page(page_id).unwrap();
+                assert_eq!(page.len(), 32 * 32 * 4 + 8 * 8 * 16);
+                let layers = storage.metadata().split_layers(page_id.mip_level(), &page);
+                assert_eq!(layers[0], &albedo[..32 * 32 * 4]);
+                let normals = decode_page(PageEncoding::Bc5, layers[1], 32);
+                assert!(normals
+                    .chunks_exact(4)
+                    .all(|texel| texel[..2] == [128, 128]));
+            });
+    }
+
+    /// Transparent pages are detected as missing, but single channel ones have no alpha, so
+    /// their black pages are kept.
+    #[test]
+    fn detect_missing_pages_by_alpha() {
+        let temp_dir = TempDir::new().unwrap();
+        let row_texel_width = PAGE_SIZE;
+        [(1, false), (4, true)]
+            .iter()
+            .for_each(|&(bytes_per_texel, missing)| {
+                let directory = temp_dir.child(bytes_per_texel.to_string());
+                let metadata = TextureMetadata::from_mip(0, bytes_per_texel);
+                let mut storage =
+                    TextureStorage::new(metadata, Some(directory.to_str().unwrap()), None).unwrap();
+                storage.detect_missing_pages = true;
+                let row = vec![0; PAGE_SIZE * row_texel_width * bytes_per_texel as usize];
+                storage.write_row(0, 0, &row).unwrap();
+                let page = PageId::new(0, 0, 0);
+                assert_eq!(storage.metadata().is_missing(page), missing);
+                assert!(storage
+                    .read_page(page)
+                    .unwrap()
+                    .iter()
+                    .all(|&byte| byte == 0));
+            });
+    }
+
+    #[test]
+    fn read_compressed_page() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().as_os_str().to_str().unwrap();
+        let metadata = TextureMetadata::from_mip(1, 4).with_compression(PageCompression::Zstd);
+        let mut storage = TextureStorage::new(metadata, Some(path), None).unwrap();
+
+        let row_texel_width = 2 * PAGE_STRIDE + 2 * PAGE_BORDER_SIZE;
+        let row = (0..PAGE_SIZE * row_texel_width)
+            .flat_map(|texel| {
+                let (x, y) = (texel % row_texel_width, texel / row_texel_width);
+                [x as u8, y as u8, (x >> 8) as u8, 0xFF]
+            })
+            .collect::<Vec<_>>();
+        storage.write_row(0, 0, &row).unwrap();
+
+        let page = storage.read_page(PageId::new(0, 1, 0)).unwrap();
+        assert_eq!(page.len(), PAGE_SIZE * PAGE_SIZE * 4);
+        page.chunks_exact(PAGE_SIZE * 4)
+            .enumerate()
+            .for_each(|(y, page_row)| {
+                let start = (y * row_texel_width + PAGE_STRIDE) * 4;
+                assert_eq!(page_row, &row[start..start + PAGE_SIZE * 4]);
+            });
+    }
+
+    #[test]
+    fn pack_texture_storage() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().as_os_str().to_str().unwrap();
+        let metadata = TextureMetadata::from_mip(1, 4).with_compression(PageCompression::Zstd);
+        let mut storage = TextureStorage::new(metadata, Some(path), None).unwrap();
+
+        let rows = [(0, 0, 2), (0, 1, 2), (1, 0, 1)];
+        rows.iter().for_each(|&(mip, row, pages)| {
+            let texels = (pages * PAGE_STRIDE + 2 * PAGE_BORDER_SIZE) * PAGE_SIZE;
+            let data = (0..texels * 4)
+                .map(|i| (i / 7 + row as usize * 3 + mip as usize * 5) as u8)
+                .collect::<Vec<_>>();
+            storage.write_row(mip, row, &data).unwrap();
+        });
+        let pages = [(0, 0, 0), (0, 1, 0), (0, 0, 1), (0, 1, 1), (1, 0, 0)]
+            .map(|(mip, x, y)| storage.read_page(PageId::new(mip, x, y)).unwrap());
+
+        let archive_path = temp_dir.child("texture.pack");
+        storage.pack(archive_path.path()).unwrap();
+        temp_dir.child("0-0").assert(predicate::path::missing());
+
+        let packed = TextureStorage::load_packed(archive_path.path()).unwrap();
+        [(0, 0, 0), (0, 1, 0), (0, 0, 1), (0, 1, 1), (1, 0, 0)]
+            .iter()
+            .zip(pages.iter())
+            .for_each(|(&(mip, x, y), page)| {
+                assert_eq!(&packed.read_page(PageId::new(mip, x, y)).unwrap(), page);
+            });
+    }
+
+    #[test]
+    fn read_page_out_of_bounds() {
+        let (storage, _temp_dir) = texture_storage_from_mip(1);
+        [
+            PageId::new(0, 2, 0),
+            PageId::new(0, 0, 2),
+            PageId::new(2, 0, 0),
+            PageId::new(40, 0, 0),
+        ]
+        .iter()
+        .for_each(|&page| {
+            assert!(matches!(
+                storage.read_page(page),
+                Err(TextureStorageError::PageOutOfBounds(_))
+            ));
+        });
+    }
+
+    fn texture_storage_from_mip(mip_levels: u8) -> (TextureStorage, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().as_os_str().to_str().unwrap();
+        let storage =
+            TextureStorage::new(TextureMetadata::from_mip(mip_levels, 4), Some(path), None)
+                .unwrap();
+        (storage, temp_dir)
+    }
+}
@@ -0,0 +1,139 @@
+//! Narrower page borders on the mip levels that do not need the widest one.
+//!
+//! Coarse mip levels are sampled with large filter footprints and benefit from wide borders,
+//! while the many pages of the fine mip levels only need a texel or two. The pages are generated
+//! with [`TextureMetadata::border_size`], the border of the slots of the physical texture, and
+//! cropped to the border of their mip level when they are written.
+
+use crate::TextureMetadata;
+
+impl TextureMetadata {
+    /// Store the pages of mip level `i` with a border of `border_sizes[i]` texels, and the mip
+    /// levels past the list with [`TextureMetadata::border_size`]. Must be called after
+    /// [`TextureMetadata::with_page_size`].
+    ///
+    /// Pages with a narrower border are smaller on disk and to upload, and are written at the
+    /// center of their slot, so that the sampling math does not depend on the mip level.
+    ///
+    /// ### Panics
+    ///
+    /// - If a border is wider than [`TextureMetadata::border_size`].
+    /// - If a border is not narrower by a multiple of 4 texels (the size of compressed blocks),
+    ///   so that the pages stay aligned to the blocks of their slot.
+    pub fn with_mip_border_sizes(mut self, border_sizes: &[u16]) -> Self {
+        let border_size = self.border_size();
+        assert!(border_sizes
+            .iter()
+            .all(|&border| border <= border_size && (border_size - border).is_multiple_of(4)));
+        self.mip_border_sizes = Some(border_sizes.to_vec());
+        self
+    }
+
+    /// The size of the border of the pages of mip level `mip` in texels, at most
+    /// [`TextureMetadata::border_size`].
+    pub fn border_size_at(&self, mip: u8) -> u16 {
+        self.mip_border_sizes
+            .as_ref()
+            .and_then(|border_sizes| border_sizes.get(mip as usize).copied())
+            .unwrap_or(self.border_size())
+    }
+
+    /// The texels between the edge of the slot and the edge of a page of mip level `mip`.
+    pub fn border_inset(&self, mip: u8) -> u16 {
+        self.border_size() - self.border_size_at(mip)
+    }
+
+    /// The size of the side of the pages of mip level `mip` before they are cropped to
+    /// [`TextureMetadata::border_size_at`], which is the height of the rows of the mip generator.
+    #[cfg(feature = "image")]
+    pub(super) fn generated_page_size_at(&self, mip: u8) -> u16 {
+        self.page_size_at(mip) + 2 * self.border_inset(mip)
+    }
+}
+
+/// Extend a page of `page_size` texels of `bytes_per_texel` by `inset` texels on each side,
+/// repeating its edges, to the size of the generated pages.
+pub fn pad_page(page: &[u8], page_size: usize, inset: usize, bytes_per_texel: usize) -> Vec<u8> {
+    let padded_size = page_size + 2 * inset;
+    (0..padded_size * padded_size)
+        .flat_map(|index| {
+            let x = (index % padded_size)
+                .saturating_sub(inset)
+                .min(page_size - 1);
+            let y = (index / padded_size)
+                .saturating_sub(inset)
+                .min(page_size - 1);
+            let start = (y * page_size + x) * bytes_per_texel;
+            &page[start..start + bytes_per_texel]
+        })
+        .copied()
+        .collect()
+}
+
+/// Remove `inset` texels on each side of an RGBA8 page of `page_size` texels.
+pub(super) fn crop_page(page: &[u8], page_size: usize, inset: usize) -> Vec<u8> {
+    let cropped_size = page_size - 2 * inset;
+    page.chunks_exact(page_size * 4)
+        .skip(inset)
+        .take(cropped_size)
+        .flat_map(|row| &row[inset * 4..(inset + cropped_size) * 4])
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::{fixture::TempDir, prelude::*};
+    use vt_core::PageId;
+
+    use super::{crop_page, pad_page};
+    use crate::{TextureMetadata, TextureStorage};
+
+    #[test]
+    fn pad_and_crop_pages() {
+        let page = (0..16u8).flat_map(|texel| [texel; 4]).collect::<Vec<_>>();
+        let padded = pad_page(&page, 4, 1, 4);
+        assert_eq!(padded.len(), 6 * 6 * 4);
+        assert_eq!(&padded[..4], [0; 4]);
+        assert_eq!(&padded[(6 * 5 + 5) * 4..], [15; 4]);
+        assert_eq!(crop_page(&padded, 6, 1), page);
+    }
+
+    /// Fine mip levels are stored with a narrower border, cropped from the generated pages.
+    #[test]
+    fn store_narrow_borders() {
+        let temp_dir = TempDir::new().unwrap();
+        let metadata = TextureMetadata::from_mip(1, 4)
+            .with_page_size(32, 4)
+            .with_mip_border_sizes(&[0]);
+        assert_eq!(
+            (metadata.border_size_at(0), metadata.border_size_at(1)),
+            (0, 4)
+        );
+        assert_eq!(
+            (metadata.page_size_at(0), metadata.page_size_at(1)),
+            (24, 32)
+        );
+        assert_eq!(metadata.page_byte_size_at(0), 24 * 24 * 4);
+
+        let (width, height) = metadata.texel_dimensions();
+        let image = image::RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([x as u8, y as u8, 0, 255])
+        });
+        let path = temp_dir.child("texture");
+        let mut storage =
+            TextureStorage::new(metadata, Some(path.path().to_str().unwrap()), None).unwrap();
+        storage
+            .import_texture(image::imageops::FilterType::Nearest, &image.as_raw()[..])
+            .unwrap();
+
+        // The pages of mip level 0 start at the first texel of their interior.
+        let page = storage.read_page(PageId::new(0, 1, 0)).unwrap();
+        assert_eq!(page.len(), 24 * 24 * 4);
+        assert_eq!(&page[..4], [4 + 24, 4, 0, 255]);
+        assert_eq!(
+            storage.read_page(PageId::new(1, 0, 0)).unwrap().len(),
+            32 * 32 * 4
+        );
+    }
+}
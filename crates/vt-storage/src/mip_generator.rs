@@ -0,0 +1,348 @@
+use crate::{color_space::resize_texels, TextureStorage, TextureStorageError};
+
+/// Generates the mip levels of a layer from the rows of its first mip level.
+///
+/// The rows are downsampled without their borders, so that every texel of a mip level covers
+/// exactly the texels of the level below it. The borders of the generated rows are then copied
+/// from the rows above and below them and from the neighbouring pages of the row, like the
+/// borders of the first mip level, so that filtering across the edges of the pages is seamless.
+/// At the edges of the texture, the borders repeat the edge texels.
+pub struct MipLevelGen {
+    next_mip: Option<Box<MipLevelGen>>,
+    /// The interior of the even row waiting for the row below it, to be downsampled with it, and
+    /// the index of the row.
+    stored_row: Option<(Box<[u8]>, usize)>,
+    /// The interior of the last row of a generated mip level, written once the row below it
+    /// fills its bottom border.
+    pending_row: Option<(Box<[u8]>, usize)>,
+    /// The interior of the row above the pending row, which fills its top border.
+    row_above: Option<Box<[u8]>>,
+    bytes_per_texel: u8,
+    mip_level: u8,
+    /// The layer of the texture the rows belong to.
+    layer: usize,
+    filter_mode: image::imageops::FilterType,
+}
+
+impl MipLevelGen {
+    /// Creates a new generator
+    pub fn from_mip(
+        mip: u8,
+        base_mip: u8,
+        layer: usize,
+        bytes_per_texel: u8,
+        filter_mode: image::imageops::FilterType,
+    ) -> Self {
+        let next_mip = (mip > base_mip).then(|| {
+            Box::new(Self::from_mip(
+                mip,
+                base_mip + 1,
+                layer,
+                bytes_per_texel,
+                filter_mode,
+            ))
+        });
+        Self {
+            stored_row: None,
+            pending_row: None,
+            row_above: None,
+            mip_level: base_mip,
+            layer,
+            next_mip,
+            bytes_per_texel,
+            filter_mode,
+        }
+    }
+
+    /// Writes a row, borders included, to the generator.
+    pub fn write_row(
+        &mut self,
+        row: Box<[u8]>,
+        index: usize,
+        storage: &mut TextureStorage,
+    ) -> Result<(), TextureStorageError> {
+        storage.write_layer_row(self.layer, self.mip_level, index as u16, &row)?;
+        let interior = self.interior(&row, storage);
+        self.downsample_row(interior, index, storage)
+    }
+
+    /// Writes two rows at once, borders included.
+    ///
+    /// This allows some checks and the allocation on the heap to be skipped for the current mip level.
+    pub fn write_two_rows(
+        &mut self,
+        rows: (&[u8], &[u8]),
+        first_index: usize,
+        storage: &mut TextureStorage,
+    ) -> Result<(), TextureStorageError> {
+        storage.write_layer_row(self.layer, self.mip_level, first_index as u16, rows.0)?;
+        storage.write_layer_row(self.layer, self.mip_level, first_index as u16 + 1, rows.1)?;
+        let interiors = (
+            self.interior(rows.0, storage),
+            self.interior(rows.1, storage),
+        );
+        self.mip_two_rows((&interiors.0, &interiors.1), first_index, storage)
+    }
+
+    /// Writes the interior of a row generated by the mip level below, and the row above it with
+    /// its borders, now that its bottom border is known.
+    fn write_interior_row(
+        &mut self,
+        interior: Box<[u8]>,
+        index: usize,
+        storage: &mut TextureStorage,
+    ) -> Result<(), TextureStorageError> {
+        let (above, current) = (self.row_above.take(), self.pending_row.take());
+        if let Some((current, current_index)) = &current {
+            self.write_bordered_row(
+                above.as_deref(),
+                current,
+                Some(&interior),
+                *current_index,
+                storage,
+            )?;
+        }
+        self.row_above = current.map(|(current, _)| current);
+        self.pending_row = Some((interior.clone(), index));
+        self.downsample_row(interior, index, storage)
+    }
+
+    /// Keep an even row for the row below it, or downsample it with the row kept.
+    fn downsample_row(
+        &mut self,
+        interior: Box<[u8]>,
+        index: usize,
+        storage: &mut TextureStorage,
+    ) -> Result<(), TextureStorageError> {
+        match self.stored_row.take() {
+            None => {
+                assert!(index.is_multiple_of(2));
+                self.stored_row = Some((interior, index));
+                Ok(())
+            }
+            Some((stored_row, index)) => {
+                self.mip_two_rows((&stored_row, &interior), index, storage)
+            }
+        }
+    }
+
+    /// The interior of a row of the first mip level, without its borders.
+    fn interior(&self, row: &[u8], storage: &TextureStorage) -> Box<[u8]> {
+        let page_size = storage.metadata().page_size() as usize;
+        let border_size = storage.metadata().border_size() as usize;
+        let bytes_per_texel = self.bytes_per_texel as usize;
+        let row_width = row.len() / page_size;
+        let border = border_size * bytes_per_texel;
+        row.chunks_exact(row_width)
+            .skip(border_size)
+            .take(page_size - 2 * border_size)
+            .flat_map(|line| &line[border..row_width - border])
+            .copied()
+            .collect()
+    }
+
+    /// Write a row of this mip level from its interior, with the borders copied from the rows
+    /// above and below it, or repeating its edges at the edges of the texture.
+    fn write_bordered_row(
+        &self,
+        above: Option<&[u8]>,
+        row: &[u8],
+        below: Option<&[u8]>,
+        index: usize,
+        storage: &mut TextureStorage,
+    ) -> Result<(), TextureStorageError> {
+        let border_size = storage.metadata().border_size() as usize;
+        let stride = storage.metadata().page_stride() as usize;
+        let bytes_per_texel = self.bytes_per_texel as usize;
+        let line_size = row.len() / stride;
+        let width = line_size / bytes_per_texel;
+        let line = |y: usize| y * line_size..(y + 1) * line_size;
+
+        let mut bordered = Vec::with_capacity(
+            (width + 2 * border_size) * (stride + 2 * border_size) * bytes_per_texel,
+        );
+        (0..stride + 2 * border_size).for_each(|y| {
+            let source = match (y.checked_sub(border_size), above, below) {
+                (None, Some(above), _) => &above[line((stride + y).saturating_sub(border_size))],
+                (None, None, _) => &row[line(0)],
+                (Some(y), _, _) if y < stride => &row[line(y)],
+                (Some(y), _, Some(below)) => &below[line((y - stride).min(stride - 1))],
+                (Some(_), _, None) => &row[line(stride - 1)],
+            };
+            let (first, last) = (
+                &source[..bytes_per_texel],
+                &source[line_size - bytes_per_texel..],
+            );
+            (0..border_size).for_each(|_| bordered.extend_from_slice(first));
+            bordered.extend_from_slice(source);
+            (0..border_size).for_each(|_| bordered.extend_from_slice(last));
+        });
+        storage.write_layer_row(self.layer, self.mip_level, index as u16, &bordered)
+    }
+
+    /// Downsample the interiors of two rows, the first one being even, into the interior of a
+    /// row of the next mip level.
+    fn mip_two_rows(
+        &mut self,
+        rows: (&[u8], &[u8]),
+        first_index: usize,
+        storage: &mut TextureStorage,
+    ) -> Result<(), TextureStorageError> {
+        let stride = storage.metadata().page_stride() as u32;
+        debug_assert!(self.stored_row.is_none());
+        debug_assert!(first_index.is_multiple_of(2));
+        debug_assert!(rows.0.len() == rows.1.len());
+
+        let Some(ref mut next_mip) = self.next_mip else {
+            return Ok(());
+        };
+        let width = (rows.0.len() / stride as usize / self.bytes_per_texel as usize) as u32;
+        // A mip level is at least one page wide.
+        let mipped_row = resize_texels(
+            &[rows.0, rows.1].concat(),
+            (width, 2 * stride),
+            ((width / 2).max(stride), stride),
+            self.filter_mode,
+            storage.metadata().texel_format(),
+            storage.metadata().layers()[self.layer].color_space(),
+        );
+        next_mip.write_interior_row(mipped_row, first_index / 2, storage)
+    }
+
+    /// Downsample a row without the row below it, which is the only row of its mip level, since
+    /// the sides of the textures are powers of two pages. The next mip level is one page high as
+    /// well, so the row is only halved horizontally.
+    fn mip_single_row(
+        &mut self,
+        row: &[u8],
+        index: usize,
+        storage: &mut TextureStorage,
+    ) -> Result<(), TextureStorageError> {
+        let stride = storage.metadata().page_stride() as u32;
+
+        let Some(ref mut next_mip) = self.next_mip else {
+            return Ok(());
+        };
+        let width = (row.len() / stride as usize / self.bytes_per_texel as usize) as u32;
+        let mipped_row = resize_texels(
+            row,
+            (width, stride),
+            ((width / 2).max(stride), stride),
+            self.filter_mode,
+            storage.metadata().texel_format(),
+            storage.metadata().layers()[self.layer].color_space(),
+        );
+        next_mip.write_interior_row(mipped_row, index / 2, storage)
+    }
+
+    /// Write the last row of every generated mip level, and flush the row kept for the row below
+    /// it, if any, so that the chain is written down to its coarsest level. To be called once
+    /// every row of the first mip level is written.
+    ///
+    /// The kept rows are only left by textures that are not square, whose coarse mip levels are
+    /// a single page high.
+    pub fn finish(&mut self, storage: &mut TextureStorage) -> Result<(), TextureStorageError> {
+        let (above, current) = (self.row_above.take(), self.pending_row.take());
+        if let Some((current, index)) = current {
+            self.write_bordered_row(above.as_deref(), &current, None, index, storage)?;
+        }
+        if let Some((row, index)) = self.stored_row.take() {
+            self.mip_single_row(&row, index, storage)?;
+        }
+        match self.next_mip {
+            Some(ref mut next_mip) => next_mip.finish(storage),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::{fixture::TempDir, prelude::*};
+    use vt_core::PageId;
+
+    use crate::{TextureMetadata, TextureStorage};
+
+    const FILTER: image::imageops::FilterType = image::imageops::FilterType::Triangle;
+
+    /// A texture of `dimensions` pages of 32 texels with 2 texel borders, with a horizontal
+    /// gradient.
+    fn import(dimensions: (u16, u16)) -> (TempDir, TextureStorage, image::RgbaImage) {
+        let temp_dir = TempDir::new().unwrap();
+        let metadata = TextureMetadata::from_dimensions(dimensions, 4).with_page_size(32, 2);
+        let (width, height) = metadata.texel_dimensions();
+        let image = image::RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([(x * 2) as u8, (y * 3) as u8, 7, 255])
+        });
+        let path = temp_dir.child("texture");
+        let mut storage =
+            TextureStorage::new(metadata, Some(path.path().to_str().unwrap()), None).unwrap();
+        storage.import_texture(FILTER, &image.as_raw()[..]).unwrap();
+        (temp_dir, storage, image)
+    }
+
+    /// The texel `(x, y)` of an RGBA8 page of 32 texels.
+    fn texel(page: &[u8], x: usize, y: usize) -> &[u8] {
+        &page[(y * 32 + x) * 4..(y * 32 + x) * 4 + 4]
+    }
+
+    /// A texture one page high has a single row, whose interior is halved horizontally like
+    /// `image` would.
+    #[test]
+    fn mip_single_row() {
+        let (_temp_dir, storage, image) = import((2, 1));
+        let interior = image::imageops::crop_imm(&image, 2, 2, 56, 28).to_image();
+        let expected = image::imageops::resize(&interior, 28, 28, FILTER);
+        let page = storage.read_page(PageId::new(1, 0, 0)).unwrap();
+        (0..28).for_each(|y| {
+            (0..28).for_each(|x| {
+                assert_eq!(
+                    texel(&page, x + 2, y + 2),
+                    expected.get_pixel(x as u32, y as u32).0
+                )
+            })
+        });
+        // The edges of the texture are repeated in the borders.
+        assert_eq!(texel(&page, 0, 0), texel(&page, 2, 2));
+        assert_eq!(texel(&page, 31, 31), texel(&page, 29, 29));
+    }
+
+    /// The borders of the generated pages hold the texels of their neighbours.
+    #[test]
+    fn neighbour_borders() {
+        let (_temp_dir, storage, _) = import((4, 4));
+        let page = |x, y| storage.read_page(PageId::new(1, x, y)).unwrap();
+        let (top_left, top_right, bottom_left) = (page(0, 0), page(1, 0), page(0, 1));
+        (0..32).for_each(|index| {
+            (0..2).for_each(|border| {
+                assert_eq!(
+                    texel(&top_left, 30 + border, index),
+                    texel(&top_right, 2 + border, index)
+                );
+                assert_eq!(
+                    texel(&top_right, border, index),
+                    texel(&top_left, 28 + border, index)
+                );
+                assert_eq!(
+                    texel(&top_left, index, 30 + border),
+                    texel(&bottom_left, index, 2 + border)
+                );
+                assert_eq!(
+                    texel(&bottom_left, index, border),
+                    texel(&top_left, index, 28 + border)
+                );
+            })
+        });
+    }
+
+    /// Every mip level is written down to a single page, past the levels one page high.
+    #[test]
+    fn finish_mip_chain() {
+        let (_temp_dir, storage, _) = import((4, 2));
+        assert_eq!(storage.metadata().mip_levels(), 2);
+        assert_eq!(storage.metadata().mip_dimensions(2), (1, 1));
+        let coarsest = storage.read_page(PageId::new(2, 0, 0)).unwrap();
+        assert_eq!(coarsest.len(), 32 * 32 * 4);
+        assert!(coarsest.chunks_exact(4).all(|texel| texel[3] == 255));
+    }
+}
@@ -0,0 +1,179 @@
+//! Synthesis of missing pages by upsampling their closest ancestor with data.
+
+use vt_core::PageId;
+
+use crate::{
+    decode_page, encode_page,
+    mip_borders::{crop_page, pad_page},
+    TextureReader, TextureStorage, TextureStorageError,
+};
+
+/// A page read with [`TextureReader::read_page_overzoomed`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverzoomedPage {
+    /// The page, in the same format as [`TextureReader::read_page`].
+    pub data: Vec<u8>,
+    /// The page is upsampled from a coarser one, and should be replaced once real data for it
+    /// is available.
+    pub synthetic: bool,
+}
+
+impl TextureStorage {
+    /// See [`TextureReader::read_page_overzoomed`].
+    pub fn read_page_overzoomed(
+        &self,
+        page: PageId,
+    ) -> Result<OverzoomedPage, TextureStorageError> {
+        self.reader.read_page_overzoomed(page)
+    }
+}
+
+impl TextureReader {
+    /// Read a page, or synthesize it from its closest ancestor with data when it is missing (see
+    /// [`TextureMetadata::is_missing`](crate::TextureMetadata::is_missing)).
+    ///
+    /// Synthetic pages are upsampled with bilinear filtering, one mip level at a time, so that
+    /// the borders match the ones of the neighbouring synthetic pages. Pages are only synthesized
+    /// from regular pages, see [`TextureMetadata::with_coarse_pages`](crate::TextureMetadata::with_coarse_pages).
+    pub fn read_page_overzoomed(
+        &self,
+        page: PageId,
+    ) -> Result<OverzoomedPage, TextureStorageError> {
+        let mut ancestors = vec![page];
+        while let Some(&finest) = ancestors.last() {
+            // Coarse pages do not have a single parent.
+            if !self.metadata.is_missing(finest)
+                || finest.mip_level() == self.metadata.mip_levels
+                || self.metadata.page_scale(finest.mip_level() + 1) > 0
+            {
+                break;
+            }
+            ancestors.push(
+                finest
+                    .parent()
+                    .expect("the mip level to be below the coarsest"),
+            );
+        }
+
+        let source = ancestors.pop().expect("the page itself to be in the list");
+        let mut data = self.read_page(source)?;
+        let synthetic = !ancestors.is_empty();
+        while let Some(child) = ancestors.pop() {
+            data = self.overzoom_page(&data, child);
+        }
+        Ok(OverzoomedPage { data, synthetic })
+    }
+
+    /// Upsample the quadrant of `parent` covering `child`, every layer included.
+    ///
+    /// The pages are upsampled with the widest border, see
+    /// [`TextureMetadata::with_mip_border_sizes`]. Only RGBA8 textures have missing pages, see
+    /// [`TextureStorage::import_geotiff`].
+    fn overzoom_page(&self, parent: &[u8], child: PageId) -> Vec<u8> {
+        let page_size = self.metadata.page_size() as usize;
+        let parent_mip = child.mip_level() + 1;
+        let parent_size = self.metadata.page_size_at(parent_mip) as usize;
+        let parent_inset = self.metadata.border_inset(parent_mip) as usize;
+        let child_size = self.metadata.page_size_at(child.mip_level()) as usize;
+        let child_inset = self.metadata.border_inset(child.mip_level()) as usize;
+        self.metadata
+            .layers()
+            .iter()
+            .zip(self.metadata.split_layers(parent_mip, parent))
+            .flat_map(|(layer, parent)| {
+                let parent = decode_page(layer.encoding, parent, parent_size);
+                let child_page = upsample_quadrant(
+                    &pad_page(&parent, parent_size, parent_inset, 4),
+                    page_size,
+                    self.metadata.border_size() as usize,
+                    (child.x() as usize % 2, child.y() as usize % 2),
+                );
+                let child_page = crop_page(&child_page, page_size, child_inset);
+                encode_page(layer.encoding, &child_page, child_size)
+            })
+            .collect()
+    }
+}
+
+/// Upsample the `quadrant` (0 or 1 on each axis) of an RGBA8 page, borders included, to a page of
+/// the same size.
+fn upsample_quadrant(
+    parent: &[u8],
+    page_size: usize,
+    border_size: usize,
+    quadrant: (usize, usize),
+) -> Vec<u8> {
+    let page_stride = page_size - 2 * border_size;
+    // The position in the parent page of the center of the texel `i` of the child page.
+    let parent_position = |i: usize, quadrant: usize| {
+        let position = ((quadrant * page_stride + i) as f32 + 0.5 - border_size as f32) / 2.0
+            + border_size as f32
+            - 0.5;
+        position.clamp(0.0, (page_size - 1) as f32)
+    };
+    let texel = |x: usize, y: usize| &parent[(y * page_size + x) * 4..(y * page_size + x) * 4 + 4];
+
+    (0..page_size * page_size)
+        .flat_map(|index| {
+            let x = parent_position(index % page_size, quadrant.0);
+            let y = parent_position(index / page_size, quadrant.1);
+            let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+            let (x1, y1) = ((x0 + 1).min(page_size - 1), (y0 + 1).min(page_size - 1));
+            let (fx, fy) = (x.fract(), y.fract());
+            std::array::from_fn::<u8, 4, _>(|channel| {
+                let top =
+                    texel(x0, y0)[channel] as f32 * (1.0 - fx) + texel(x1, y0)[channel] as f32 * fx;
+                let bottom =
+                    texel(x0, y1)[channel] as f32 * (1.0 - fx) + texel(x1, y1)[channel] as f32 * fx;
+                (top * (1.0 - fy) + bottom * fy).round() as u8
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::{fixture::TempDir, prelude::*};
+    use vt_core::PageId;
+
+    use super::upsample_quadrant;
+    use crate::{TextureMetadata, TextureStorage};
+
+    #[test]
+    fn upsample_gradient() {
+        // 8 texel pages with a border of 2, with a horizontal gradient of 10 per texel.
+        let parent = (0..64)
+            .flat_map(|i| [(i % 8 * 10) as u8, 0, 0, 255])
+            .collect::<Vec<_>>();
+        let child = upsample_quadrant(&parent, 8, 2, (1, 0));
+        // The center of the child texel 2 (the first without border) is at 4.25 in the parent, between
+        // the centers of the texels 3 and 4.
+        let first_row = child[..32]
+            .chunks_exact(4)
+            .map(|t| t[0])
+            .collect::<Vec<_>>();
+        assert_eq!(first_row, [28, 33, 38, 43, 48, 53, 58, 63]);
+        assert!(child.chunks_exact(4).all(|texel| texel[3] == 255));
+    }
+
+    #[test]
+    fn overzoom_missing_page() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.child("texture");
+        let metadata = TextureMetadata::from_mip(1, 4).with_page_size(32, 2);
+        let texels = vec![200; 60 * 60 * 4];
+        let mut storage =
+            TextureStorage::new(metadata, Some(path.path().to_str().unwrap()), None).unwrap();
+        storage
+            .import_texture(image::imageops::FilterType::Nearest, &texels[..])
+            .unwrap();
+        storage.metadata_mut().insert_missing_page((1, 1));
+
+        let real = storage.read_page_overzoomed(PageId::new(0, 0, 1)).unwrap();
+        assert!(!real.synthetic);
+        let synthetic = storage.read_page_overzoomed(PageId::new(0, 1, 1)).unwrap();
+        assert!(synthetic.synthetic);
+        assert_eq!(synthetic.data.len(), storage.metadata().page_byte_size());
+        assert!(synthetic.data.iter().all(|&byte| byte == 200));
+    }
+}
@@ -0,0 +1,418 @@
+//! Backends keeping many page reads in flight, so that the streaming loop does not wait on the
+//! disk one page at a time.
+
+use std::{
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use vt_core::PageId;
+
+use crate::{PageSource, TextureMetadata, TextureStorageError};
+
+/// A page read by a [`PageReader`].
+#[derive(Debug)]
+pub struct PageRead {
+    pub page: PageId,
+    /// The page, see [`PageSource::read_page`].
+    pub data: Result<Vec<u8>, TextureStorageError>,
+}
+
+/// Reads pages in the background: the reads are submitted at once, and collected as they
+/// complete, in any order.
+///
+/// [`BlockingPageReader`] reads them one after the other on submission, [`ThreadedPageReader`]
+/// on a pool of threads, and `TokioPageReader` (with the `tokio` feature) on the blocking pool of
+/// a tokio runtime. Other backends, such as an `io_uring` one, only have to implement this trait.
+pub trait PageReader: Send + Sync {
+    /// The metadata of the pages read.
+    fn metadata(&self) -> &TextureMetadata;
+
+    /// Start reading `pages`.
+    fn submit(&self, pages: &[PageId]);
+
+    /// The reads completed since the previous call, without blocking.
+    fn completed(&self) -> Vec<PageRead>;
+
+    /// The reads completed since the previous call, blocking until at least one completes if
+    /// any is in flight.
+    fn wait_completed(&self) -> Vec<PageRead>;
+
+    /// The reads completed since the previous call, blocking until at least one completes if
+    /// any is in flight, or until `timeout` runs out.
+    fn wait_completed_timeout(&self, timeout: Duration) -> Vec<PageRead>;
+
+    /// The reads submitted and not yet returned.
+    fn in_flight(&self) -> usize;
+}
+
+/// A [`PageReader`] reading the pages on the thread submitting them, with the blocking reads
+/// of its source.
+pub struct BlockingPageReader<S> {
+    source: S,
+    completed: Mutex<Vec<PageRead>>,
+}
+
+impl<S: PageSource> BlockingPageReader<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            completed: Default::default(),
+        }
+    }
+}
+
+impl<S: PageSource> PageReader for BlockingPageReader<S> {
+    fn metadata(&self) -> &TextureMetadata {
+        self.source.metadata()
+    }
+
+    fn submit(&self, pages: &[PageId]) {
+        let reads = pages.iter().map(|&page| PageRead {
+            page,
+            data: self.source.read_page(page),
+        });
+        self.completed.lock().unwrap().extend(reads);
+    }
+
+    fn completed(&self) -> Vec<PageRead> {
+        std::mem::take(&mut self.completed.lock().unwrap())
+    }
+
+    fn wait_completed(&self) -> Vec<PageRead> {
+        self.completed()
+    }
+
+    fn wait_completed_timeout(&self, _timeout: Duration) -> Vec<PageRead> {
+        self.completed()
+    }
+
+    fn in_flight(&self) -> usize {
+        self.completed.lock().unwrap().len()
+    }
+}
+
+/// A [`PageReader`] reading the pages of a shared source on a pool of threads, so that as many
+/// reads as there are threads wait on the disk at once.
+///
+/// The row files of a [`TextureReader`](crate::TextureReader) are locked while they are read,
+/// so the pages of different rows are read concurrently, and the ones of a row one after the
+/// other. The threads stop once the reader is dropped, after the reads in flight. A read
+/// panicking completes with [`TextureStorageError::ReadPanicked`].
+pub struct ThreadedPageReader {
+    source: Arc<dyn PageSource>,
+    /// `None` once dropped, to stop the threads.
+    requests: Option<Sender<PageId>>,
+    completions: Mutex<Receiver<PageRead>>,
+    in_flight: AtomicUsize,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ThreadedPageReader {
+    /// A reader of `source` on `threads` threads.
+    ///
+    /// ### Panics
+    ///
+    /// - If `threads` is 0.
+    pub fn new(source: impl PageSource + 'static, threads: usize) -> Self {
+        assert!(threads > 0);
+        let source = Arc::new(source) as Arc<dyn PageSource>;
+        let (requests, request_receiver) = std::sync::mpsc::channel::<PageId>();
+        let (completion_sender, completions) = std::sync::mpsc::channel();
+        let request_receiver = Arc::new(Mutex::new(request_receiver));
+        let threads = (0..threads)
+            .map(|_| {
+                let source = Arc::clone(&source);
+                let request_receiver = Arc::clone(&request_receiver);
+                let completion_sender = completion_sender.clone();
+                std::thread::spawn(move || loop {
+                    // The lock is released before the read, for the other threads to take the
+                    // next requests.
+                    let Ok(page) = request_receiver.lock().unwrap().recv() else {
+                        break;
+                    };
+                    // A panicking read is returned as an error, for the read to be counted out
+                    // of `in_flight` and the thread to keep serving the next ones.
+                    let data =
+                        std::panic::catch_unwind(AssertUnwindSafe(|| source.read_page(page)))
+                            .unwrap_or(Err(TextureStorageError::ReadPanicked(page)));
+                    if completion_sender.send(PageRead { page, data }).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            source,
+            requests: Some(requests),
+            completions: Mutex::new(completions),
+            in_flight: AtomicUsize::new(0),
+            threads,
+        }
+    }
+
+    /// Count the reads returned.
+    fn returned(&self, reads: Vec<PageRead>) -> Vec<PageRead> {
+        self.in_flight.fetch_sub(reads.len(), Ordering::AcqRel);
+        reads
+    }
+}
+
+impl PageReader for ThreadedPageReader {
+    fn metadata(&self) -> &TextureMetadata {
+        self.source.metadata()
+    }
+
+    fn submit(&self, pages: &[PageId]) {
+        let requests = self
+            .requests
+            .as_ref()
+            .expect("the reader not to be dropped");
+        self.in_flight.fetch_add(pages.len(), Ordering::AcqRel);
+        pages.iter().for_each(|&page| {
+            requests
+                .send(page)
+                .expect("the threads to run while the reader is alive");
+        });
+    }
+
+    fn completed(&self) -> Vec<PageRead> {
+        let reads = self.completions.lock().unwrap().try_iter().collect();
+        self.returned(reads)
+    }
+
+    fn wait_completed(&self) -> Vec<PageRead> {
+        let completions = self.completions.lock().unwrap();
+        if self.in_flight.load(Ordering::Acquire) == 0 {
+            return Vec::new();
+        }
+        let first = completions
+            .recv()
+            .expect("the threads to run while reads are in flight");
+        let reads = std::iter::once(first)
+            .chain(completions.try_iter())
+            .collect();
+        self.returned(reads)
+    }
+
+    fn wait_completed_timeout(&self, timeout: Duration) -> Vec<PageRead> {
+        let completions = self.completions.lock().unwrap();
+        if self.in_flight.load(Ordering::Acquire) == 0 {
+            return Vec::new();
+        }
+        let Ok(first) = completions.recv_timeout(timeout) else {
+            return Vec::new();
+        };
+        let reads = std::iter::once(first)
+            .chain(completions.try_iter())
+            .collect();
+        self.returned(reads)
+    }
+
+    fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for ThreadedPageReader {
+    fn drop(&mut self) {
+        self.requests = None;
+        self.threads.drain(..).for_each(|thread| {
+            thread.join().unwrap_or_default();
+        });
+    }
+}
+
+/// A [`PageReader`] reading the pages of a shared source on the blocking pool of a tokio
+/// runtime, which grows to hundreds of threads, so that hundreds of reads wait on the disk at
+/// once without a pool of their own. The reads already spawned complete after the reader is
+/// dropped, as long as the runtime runs.
+///
+/// [`PageReader::wait_completed`] blocks the calling thread, so it must not be called from
+/// the asynchronous tasks of the runtime.
+#[cfg(feature = "tokio")]
+pub struct TokioPageReader {
+    source: Arc<dyn PageSource>,
+    runtime: tokio::runtime::Handle,
+    completion_sender: Sender<PageRead>,
+    completions: Mutex<Receiver<PageRead>>,
+    in_flight: AtomicUsize,
+}
+
+#[cfg(feature = "tokio")]
+impl TokioPageReader {
+    /// A reader of `source` on the runtime of `runtime`.
+    pub fn new(source: impl PageSource + 'static, runtime: tokio::runtime::Handle) -> Self {
+        let (completion_sender, completions) = std::sync::mpsc::channel();
+        Self {
+            source: Arc::new(source),
+            runtime,
+            completion_sender,
+            completions: Mutex::new(completions),
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    /// Count the reads returned.
+    fn returned(&self, reads: Vec<PageRead>) -> Vec<PageRead> {
+        self.in_flight.fetch_sub(reads.len(), Ordering::AcqRel);
+        reads
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl PageReader for TokioPageReader {
+    fn metadata(&self) -> &TextureMetadata {
+        self.source.metadata()
+    }
+
+    fn submit(&self, pages: &[PageId]) {
+        self.in_flight.fetch_add(pages.len(), Ordering::AcqRel);
+        pages.iter().for_each(|&page| {
+            let source = Arc::clone(&self.source);
+            let completion_sender = self.completion_sender.clone();
+            self.runtime.spawn_blocking(move || {
+                let data = std::panic::catch_unwind(AssertUnwindSafe(|| source.read_page(page)))
+                    .unwrap_or(Err(TextureStorageError::ReadPanicked(page)));
+                // The reader is dropped otherwise.
+                completion_sender.send(PageRead { page, data }).ok();
+            });
+        });
+    }
+
+    fn completed(&self) -> Vec<PageRead> {
+        let reads = self.completions.lock().unwrap().try_iter().collect();
+        self.returned(reads)
+    }
+
+    fn wait_completed(&self) -> Vec<PageRead> {
+        let completions = self.completions.lock().unwrap();
+        if self.in_flight.load(Ordering::Acquire) == 0 {
+            return Vec::new();
+        }
+        let first = completions
+            .recv()
+            .expect("the reader to hold a sender of its completions");
+        let reads = std::iter::once(first)
+            .chain(completions.try_iter())
+            .collect();
+        self.returned(reads)
+    }
+
+    fn wait_completed_timeout(&self, timeout: Duration) -> Vec<PageRead> {
+        let completions = self.completions.lock().unwrap();
+        if self.in_flight.load(Ordering::Acquire) == 0 {
+            return Vec::new();
+        }
+        let Ok(first) = completions.recv_timeout(timeout) else {
+            return Vec::new();
+        };
+        let reads = std::iter::once(first)
+            .chain(completions.try_iter())
+            .collect();
+        self.returned(reads)
+    }
+
+    fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::fixture::TempDir;
+    use vt_core::PageId;
+
+    use crate::{
+        BlockingPageReader, PageReader, PageSource, TextureMetadata, TextureStorage,
+        TextureStorageError, ThreadedPageReader,
+    };
+
+    /// Every backend reads every page as the source does, and reports the failed reads.
+    #[test]
+    fn read_pages_in_flight() {
+        let temp_dir = TempDir::new().unwrap();
+        let metadata = TextureMetadata::from_mip(2, 4).with_page_size(8, 1);
+        let (width, height) = metadata.texel_dimensions();
+        let texels = (0..width * height * 4)
+            .map(|index| index as u8)
+            .collect::<Vec<_>>();
+        let mut storage =
+            TextureStorage::new(metadata, Some(temp_dir.path().to_str().unwrap()), None).unwrap();
+        storage
+            .import_texture(image::imageops::FilterType::Triangle, &texels[..])
+            .unwrap();
+        let mut pages = (0..4)
+            .flat_map(|y| (0..4).map(move |x| PageId::new(0, x, y)))
+            .collect::<Vec<_>>();
+        pages.push(PageId::new(2, 0, 0));
+        let out_of_bounds = PageId::new(1, 2, 0);
+
+        #[cfg(feature = "tokio")]
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let readers: Vec<Box<dyn PageReader>> = vec![
+            Box::new(BlockingPageReader::new(storage.reader())),
+            Box::new(ThreadedPageReader::new(storage.reader(), 4)),
+            #[cfg(feature = "tokio")]
+            Box::new(crate::TokioPageReader::new(
+                storage.reader(),
+                runtime.handle().clone(),
+            )),
+        ];
+        for reader in readers {
+            reader.submit(&pages);
+            reader.submit(&[out_of_bounds]);
+            let mut reads = Vec::new();
+            while reader.in_flight() > 0 {
+                reads.extend(reader.wait_completed());
+            }
+            assert!(reader.completed().is_empty());
+            assert!(reader.wait_completed().is_empty());
+            assert_eq!(reads.len(), pages.len() + 1);
+            reads.into_iter().for_each(|read| match read.data {
+                Ok(data) => assert_eq!(data, storage.read_page(read.page).unwrap()),
+                Err(error) => {
+                    assert_eq!(read.page, out_of_bounds);
+                    assert!(matches!(error, TextureStorageError::PageOutOfBounds(_)));
+                }
+            });
+        }
+    }
+
+    struct PanickingSource(TextureMetadata);
+
+    impl PageSource for PanickingSource {
+        fn metadata(&self) -> &TextureMetadata {
+            &self.0
+        }
+
+        fn read_page(&self, page: PageId) -> Result<Vec<u8>, TextureStorageError> {
+            panic!("could not read {page:?}");
+        }
+    }
+
+    /// A panicking read completes with an error instead of staying in flight forever.
+    #[test]
+    fn panicking_read() {
+        let reader = ThreadedPageReader::new(PanickingSource(TextureMetadata::from_mip(1, 4)), 1);
+        let pages = [PageId::new(0, 0, 0), PageId::new(0, 1, 0)];
+        reader.submit(&pages);
+        let mut reads = Vec::new();
+        while reader.in_flight() > 0 {
+            reads.extend(reader.wait_completed());
+        }
+        assert_eq!(reads.len(), 2);
+        assert!(reads
+            .iter()
+            .all(|read| matches!(read.data, Err(TextureStorageError::ReadPanicked(page)) if page == read.page)));
+    }
+}
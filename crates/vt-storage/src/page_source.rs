@@ -0,0 +1,74 @@
+//! The sources of the pages streamed to the physical textures.
+
+use std::sync::Arc;
+
+use vt_core::PageId;
+
+use crate::{OverzoomedPage, TextureMetadata, TextureReader, TextureStorage, TextureStorageError};
+
+/// Where the pages of a virtual texture are read from: a texture on disk through its
+/// [`TextureReader`], an archive on an HTTP server with an
+/// [`HttpPageSource`](crate::HttpPageSource), or an adapter producing them at runtime such as a
+/// [`TemporalPageSource`](crate::TemporalPageSource).
+///
+/// Page sources are read from the streaming thread, and shared with the application.
+pub trait PageSource: Send + Sync {
+    /// The metadata of the pages, whose layout must not change once streamed.
+    fn metadata(&self) -> &TextureMetadata;
+
+    /// Read a page, see [`TextureReader::read_page`].
+    fn read_page(&self, page: PageId) -> Result<Vec<u8>, TextureStorageError>;
+
+    /// Read a page, or synthesize it when it is missing, see
+    /// [`TextureReader::read_page_overzoomed`]. Reads the page itself by default.
+    fn read_page_overzoomed(&self, page: PageId) -> Result<OverzoomedPage, TextureStorageError> {
+        Ok(OverzoomedPage {
+            data: self.read_page(page)?,
+            synthetic: false,
+        })
+    }
+}
+
+impl PageSource for TextureReader {
+    fn metadata(&self) -> &TextureMetadata {
+        TextureReader::metadata(self)
+    }
+
+    fn read_page(&self, page: PageId) -> Result<Vec<u8>, TextureStorageError> {
+        TextureReader::read_page(self, page)
+    }
+
+    fn read_page_overzoomed(&self, page: PageId) -> Result<OverzoomedPage, TextureStorageError> {
+        TextureReader::read_page_overzoomed(self, page)
+    }
+}
+
+/// Reads the pages through the reader of the texture, see [`TextureStorage::reader`].
+impl PageSource for TextureStorage {
+    fn metadata(&self) -> &TextureMetadata {
+        TextureStorage::metadata(self)
+    }
+
+    fn read_page(&self, page: PageId) -> Result<Vec<u8>, TextureStorageError> {
+        TextureStorage::read_page(self, page)
+    }
+
+    fn read_page_overzoomed(&self, page: PageId) -> Result<OverzoomedPage, TextureStorageError> {
+        self.reader.read_page_overzoomed(page)
+    }
+}
+
+/// A source shared with the application, to change it while it is streamed.
+impl<S: PageSource + ?Sized> PageSource for Arc<S> {
+    fn metadata(&self) -> &TextureMetadata {
+        S::metadata(self)
+    }
+
+    fn read_page(&self, page: PageId) -> Result<Vec<u8>, TextureStorageError> {
+        S::read_page(self, page)
+    }
+
+    fn read_page_overzoomed(&self, page: PageId) -> Result<OverzoomedPage, TextureStorageError> {
+        S::read_page_overzoomed(self, page)
+    }
+}
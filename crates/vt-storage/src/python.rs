@@ -0,0 +1,210 @@
+//! The `virt_texture` Python module, binding the import, export, stats and diffs of the storage
+//! for the asset pipelines written in Python. Enabled by the `python` feature.
+//!
+//! The module is built with
+//! `cargo rustc -p vt-storage --release --lib --features python,pyo3/extension-module --crate-type cdylib`,
+//! and `target/release/libvt_storage.so` (`vt_storage.dll` on Windows) is then copied as
+//! `virt_texture.so` (`virt_texture.pyd`) to a directory of the Python path:
+//!
+//! ```python
+//! from virt_texture import Storage
+//!
+//! storage = Storage.import_or_load("terrain.png", "baked/terrain", page_size=128, border_size=4)
+//! print(storage.stats().stored_bytes)
+//! storage.export_mip(3, "terrain-3.png")
+//! changed = storage.diff(Storage.load("previous/terrain"))
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyValueError},
+    prelude::*,
+};
+
+use crate::{
+    FitOperation, ImportOptions, StorageStats, TextureMetadata, TextureStorage, TextureStorageError,
+};
+
+create_exception!(
+    virt_texture,
+    VirtTextureError,
+    PyException,
+    "A failed storage operation, see `TextureStorageError`."
+);
+
+impl From<TextureStorageError> for PyErr {
+    fn from(error: TextureStorageError) -> Self {
+        VirtTextureError::new_err(error.to_string())
+    }
+}
+
+/// A texture stored on disk, see [`TextureStorage`].
+#[pyclass(name = "Storage", module = "virt_texture")]
+struct PyStorage(TextureStorage);
+
+#[pymethods]
+impl PyStorage {
+    /// Load the texture in `directory`, see [`TextureStorage::load`].
+    #[staticmethod]
+    fn load(directory: PathBuf) -> PyResult<Self> {
+        Ok(Self(TextureStorage::load(Some(utf8(&directory)?), None)?))
+    }
+
+    /// Import the image at `source_path` to `directory` with pages of `page_size` texels,
+    /// borders of `border_size` included, or load it if it is unchanged since the last import,
+    /// see [`TextureStorage::import_or_load`]. `page_size` must be a multiple of 4, and above
+    /// twice `border_size`. `fit` is "pad", "crop" or "resize".
+    #[staticmethod]
+    #[pyo3(signature = (source_path, directory, page_size = 128, border_size = 4, fit = "pad"))]
+    fn import_or_load(
+        source_path: PathBuf,
+        directory: PathBuf,
+        page_size: u16,
+        border_size: u16,
+        fit: &str,
+    ) -> PyResult<Self> {
+        if !page_size.is_multiple_of(4) || 2 * border_size as u32 >= page_size as u32 {
+            return Err(PyValueError::new_err(
+                "the page size must be a multiple of 4, and above twice the border",
+            ));
+        }
+        let fit = match fit {
+            "pad" => FitOperation::Pad,
+            "crop" => FitOperation::Crop,
+            "resize" => FitOperation::Resize,
+            _ => return Err(PyValueError::new_err(format!("unknown fit {fit:?}"))),
+        };
+        let options = ImportOptions::new(
+            TextureMetadata::from_mip(0, 4).with_page_size(page_size, border_size),
+            fit,
+            image::imageops::FilterType::Triangle,
+        )
+        .with_directory(utf8(&directory)?);
+        Ok(Self(TextureStorage::import_or_load(
+            &source_path,
+            &options,
+        )?))
+    }
+
+    /// The size of the texture in texels, outer border included.
+    fn texel_dimensions(&self) -> (u32, u32) {
+        self.0.metadata().texel_dimensions()
+    }
+
+    /// See [`TextureStorage::stats`].
+    fn stats(&self) -> PyResult<StorageStats> {
+        Ok(self.0.stats()?)
+    }
+
+    /// Save mip level `mip` to the image at `path`, in the format of its extension (see
+    /// [`TextureStorage::export_mip`]).
+    fn export_mip(&self, mip: u8, path: PathBuf) -> PyResult<()> {
+        self.0
+            .export_mip(mip)?
+            .save(path)
+            .map_err(TextureStorageError::from)?;
+        Ok(())
+    }
+
+    /// The `(mip_level, x, y)` of the pages that differ from the ones of `other`, see
+    /// [`TextureStorage::diff`].
+    fn diff(&self, other: &PyStorage) -> PyResult<Vec<(u8, u16, u16)>> {
+        Ok(self
+            .0
+            .diff(&other.0)?
+            .into_iter()
+            .map(|page| (page.mip_level(), page.x(), page.y()))
+            .collect())
+    }
+}
+
+fn utf8(path: &Path) -> PyResult<&str> {
+    path.to_str()
+        .ok_or_else(|| PyValueError::new_err(format!("{} is not valid UTF-8", path.display())))
+}
+
+#[pymodule]
+#[pyo3(name = "virt_texture")]
+fn python_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyStorage>()?;
+    module.add_class::<StorageStats>()?;
+    module.add(
+        "VirtTextureError",
+        module.py().get_type::<VirtTextureError>(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::fixture::TempDir;
+    use pyo3::{prelude::*, types::PyDict};
+
+    /// A texture imported from Python is measured, exported and compared to another import of an
+    /// image differing in a single page, and the errors of the storage raise `VirtTextureError`.
+    #[test]
+    fn python_module() {
+        let temp_dir = TempDir::new().unwrap();
+        let image = |name: &str, corner: [u8; 4]| {
+            let path = temp_dir.path().join(name);
+            image::RgbaImage::from_fn(96, 96, |x, y| match x < 16 && y < 16 {
+                true => image::Rgba(corner),
+                false => image::Rgba([10, 20, 30, 255]),
+            })
+            .save(&path)
+            .unwrap();
+            path
+        };
+        let (source, changed) = (image("a.png", [10, 20, 30, 255]), image("b.png", [255; 4]));
+
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let globals = PyDict::new(py);
+            globals
+                .set_item(
+                    "virt_texture",
+                    pyo3::wrap_pymodule!(super::python_module)(py),
+                )
+                .unwrap();
+            globals.set_item("source", &source).unwrap();
+            globals.set_item("changed", &changed).unwrap();
+            globals.set_item("directory", temp_dir.path()).unwrap();
+            py.run(
+                cr#"
+import pathlib
+
+Storage = virt_texture.Storage
+directory = pathlib.Path(directory)
+storage = Storage.import_or_load(source, directory / "a", page_size=32, border_size=4)
+width, height = storage.texel_dimensions()
+assert width >= 96 and height >= 96
+stats = storage.stats()
+assert stats.pages > 0 and stats.missing_pages == 0 and stats.page_bytes > 0
+storage.export_mip(0, directory / "exported.png")
+assert (directory / "exported.png").exists()
+
+assert storage.diff(Storage.load(directory / "a")) == []
+other = Storage.import_or_load(changed, directory / "b", page_size=32, border_size=4)
+assert (0, 0, 0) in storage.diff(other)
+
+for call in [
+    lambda: Storage.load(directory / "missing"),
+    lambda: Storage.import_or_load(source, directory / "c", fit="stretch"),
+    lambda: Storage.import_or_load(source, directory / "c", page_size=10),
+    lambda: Storage.import_or_load(source, directory / "c", page_size=16, border_size=8),
+]:
+    try:
+        call()
+    except (virt_texture.VirtTextureError, ValueError):
+        pass
+    else:
+        raise AssertionError("the call did not fail")
+"#,
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+        });
+    }
+}
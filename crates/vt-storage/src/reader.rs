@@ -0,0 +1,268 @@
+//! Read-only access to a texture, shared between the streaming thread and tools reading the
+//! texture while it is written.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use vt_core::{ensure, PageId};
+
+use crate::{
+    archive::PackedArchive, PageCompression, TextureMetadata, TextureStorageError, PAGE_OFFSET_SIZE,
+};
+
+/// A read-only view of a texture, cheap to clone and to send to other threads. Created with
+/// [`TextureStorage::reader`](crate::TextureStorage::reader).
+///
+/// The metadata is the one of the texture when the reader was created, so the pages imported
+/// after it may be marked as missing (see [`TextureMetadata::is_missing`]). Row files are shared
+/// with the [`TextureStorage`](crate::TextureStorage) writing them, which never rewrites
+/// a row file while a reader reads it. A texture packed after the reader was created can not be
+/// read by it anymore, since its row files are deleted.
+#[derive(Clone)]
+pub struct TextureReader {
+    pub(super) directory: Arc<Path>,
+    pub(super) metadata: Arc<TextureMetadata>,
+    pub(super) archive: Option<Arc<PackedArchive>>,
+    pub(super) row_files: Arc<RowFiles>,
+}
+
+/// The row files open for reading, shared by a texture storage and its readers.
+///
+/// Every row file has a lock, held while it is read or written. At most
+/// [`RowFiles::MAX_OPEN_FILES`] are kept open, the ones opened first are closed once they are
+/// not read anymore.
+#[derive(Default)]
+pub(super) struct RowFiles {
+    state: Mutex<RowFilesState>,
+}
+
+#[derive(Default)]
+struct RowFilesState {
+    /// The lock and the open file of every row file used so far, by (mip, row).
+    files: HashMap<(u8, u16), Arc<Mutex<Option<File>>>>,
+    /// The row files open, in the order they were opened.
+    open: VecDeque<(u8, u16)>,
+}
+
+impl RowFiles {
+    const MAX_OPEN_FILES: usize = 64;
+
+    fn lock(&self, row: (u8, u16)) -> Arc<Mutex<Option<File>>> {
+        Arc::clone(self.state.lock().unwrap().files.entry(row).or_default())
+    }
+
+    /// Run `read` with the row file of `row` in `directory`, opening it if needed.
+    fn read<T>(
+        &self,
+        directory: &Path,
+        row: (u8, u16),
+        read: impl FnOnce(&mut File) -> Result<T, TextureStorageError>,
+    ) -> Result<T, TextureStorageError> {
+        let lock = self.lock(row);
+        let mut file = lock.lock().unwrap();
+        if file.is_none() {
+            *file = Some(File::open(row_file_path(directory, row))?);
+            self.opened(row);
+        }
+        read(file.as_mut().expect("the row file to be open"))
+    }
+
+    /// Record that the row file of `row` was opened, and close the oldest open files past
+    /// [`RowFiles::MAX_OPEN_FILES`]. The files being read are skipped, and closed later.
+    fn opened(&self, row: (u8, u16)) {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        state.open.push_back(row);
+        let mut skipped = 0;
+        while state.open.len() - skipped > Self::MAX_OPEN_FILES {
+            let oldest = state.open[skipped];
+            if let Ok(mut file) = state.files[&oldest].try_lock() {
+                *file = None;
+                state.open.remove(skipped);
+            } else {
+                skipped += 1;
+            }
+        }
+    }
+
+    /// Run `write`, which rewrites the row file of `row`, while no reader reads it. The file
+    /// is reopened by the next read.
+    #[cfg(feature = "image")]
+    pub(super) fn write<T>(&self, row: (u8, u16), write: impl FnOnce() -> T) -> T {
+        let lock = self.lock(row);
+        let mut file = lock.lock().unwrap();
+        *file = None;
+        write()
+    }
+}
+
+/// The path of the row file of `row`, a (mip, row) pair.
+pub(super) fn row_file_path(directory: &Path, (mip, row): (u8, u16)) -> std::path::PathBuf {
+    directory.join(format!("{}-{}", mip, row))
+}
+
+/// [`TextureStorageError::PageOutOfBounds`] if `page` is not stored in a texture of `metadata`.
+pub(super) fn ensure_in_bounds(
+    metadata: &TextureMetadata,
+    page: PageId,
+) -> Result<(), TextureStorageError> {
+    ensure!(
+        page.mip_level() <= metadata.mip_levels,
+        TextureStorageError::PageOutOfBounds(page)
+    );
+    let (width, height) = metadata.page_grid(page.mip_level());
+    ensure!(
+        page.x() < width && page.y() < height,
+        TextureStorageError::PageOutOfBounds(page)
+    );
+    Ok(())
+}
+
+/// Decompress a page of mip level `mip` as it is stored in a texture of `metadata`.
+pub(super) fn decompress_page(
+    metadata: &TextureMetadata,
+    mip: u8,
+    stored: Vec<u8>,
+) -> Result<Vec<u8>, TextureStorageError> {
+    Ok(match metadata.compression() {
+        PageCompression::None => stored,
+        PageCompression::Zstd => zstd::bulk::decompress(&stored, metadata.page_byte_size_at(mip))?,
+    })
+}
+
+impl TextureReader {
+    pub fn metadata(&self) -> &TextureMetadata {
+        &self.metadata
+    }
+
+    /// Read a page of the texture, with its borders.
+    ///
+    /// The page is returned with the encoding of the texture (see [`TextureMetadata::encoding`]),
+    /// but decompressed. The pages of layered textures hold every layer one after the other, see
+    /// [`TextureMetadata::split_layers`].
+    ///
+    /// ### Errors
+    ///
+    /// - If the page is out of the bounds of the texture.
+    /// - If the page could not be read or decompressed.
+    pub fn read_page(&self, page: PageId) -> Result<Vec<u8>, TextureStorageError> {
+        self.read_pages(&[page])
+            .map(|mut pages| pages.pop().expect("one page to be read"))
+    }
+
+    /// Read many pages at once, returned in the same order as `pages`.
+    ///
+    /// The pages are read row by row, so that the row files stay open between the pages of a
+    /// row. See [`TextureReader::read_page`].
+    pub fn read_pages(&self, pages: &[PageId]) -> Result<Vec<Vec<u8>>, TextureStorageError> {
+        pages
+            .iter()
+            .try_for_each(|&page| ensure_in_bounds(&self.metadata, page))?;
+
+        let mut order = (0..pages.len()).collect::<Vec<_>>();
+        order.sort_unstable_by_key(|&index| (pages[index].mip_level(), pages[index].y()));
+
+        let mut output = vec![Vec::new(); pages.len()];
+        for index in order {
+            let page = pages[index];
+            let stored = self.read_stored_page(page.mip_level(), page.x(), page.y())?;
+            output[index] = decompress_page(&self.metadata, page.mip_level(), stored)?;
+        }
+
+        Ok(output)
+    }
+
+    /// Read the bytes of a page as they are stored, which are compressed if the texture is.
+    pub(super) fn read_stored_page(
+        &self,
+        mip: u8,
+        x: u16,
+        y: u16,
+    ) -> Result<Vec<u8>, TextureStorageError> {
+        if let Some(archive) = &self.archive {
+            return archive.read_page(self.metadata.page_index(mip, x, y));
+        }
+
+        self.row_files.read(&self.directory, (mip, y), |file| {
+            self.read_row_file_page(file, mip, x)
+        })
+    }
+
+    /// Read the bytes of the page in column `x` of a row file of mip level `mip`, as they are
+    /// stored.
+    fn read_row_file_page(
+        &self,
+        file: &mut File,
+        mip: u8,
+        x: u16,
+    ) -> Result<Vec<u8>, TextureStorageError> {
+        let (start, end) = match self.metadata.compression() {
+            PageCompression::None => {
+                let page_byte_size = self.metadata.page_byte_size_at(mip) as u64;
+                (x as u64 * page_byte_size, (x as u64 + 1) * page_byte_size)
+            }
+            PageCompression::Zstd => {
+                let mut offsets = [0; 2 * PAGE_OFFSET_SIZE];
+                file.seek(SeekFrom::Start((x as usize * PAGE_OFFSET_SIZE) as u64))?;
+                file.read_exact(&mut offsets)?;
+                (
+                    u64::from_le_bytes(offsets[..PAGE_OFFSET_SIZE].try_into().unwrap()),
+                    u64::from_le_bytes(offsets[PAGE_OFFSET_SIZE..].try_into().unwrap()),
+                )
+            }
+        };
+
+        let mut page = vec![0; (end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut page)?;
+        Ok(page)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::fixture::TempDir;
+    use vt_core::PageId;
+
+    use crate::{TextureMetadata, TextureStorage};
+
+    /// Readers see the rows written after they were created, and can be used from other
+    /// threads while the texture is written.
+    #[test]
+    fn read_while_writing() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage = TextureStorage::new(
+            TextureMetadata::from_mip(1, 4).with_page_size(8, 1),
+            Some(temp_dir.path().to_str().unwrap()),
+            None,
+        )
+        .unwrap();
+        let row = |value| vec![value; 14 * 8 * 4];
+        storage.write_row(0, 0, &row(1)).unwrap();
+        let reader = storage.reader();
+        assert_eq!(reader.read_page(PageId::new(0, 1, 0)).unwrap()[0], 1);
+
+        let readers = (0..4)
+            .map(|_| {
+                let reader = reader.clone();
+                std::thread::spawn(move || {
+                    (0..16).for_each(|_| {
+                        let page = reader.read_page(PageId::new(0, 0, 0)).unwrap();
+                        assert!(page.iter().all(|&byte| byte == page[0]));
+                    });
+                })
+            })
+            .collect::<Vec<_>>();
+        (2..18).for_each(|value| storage.write_row(0, 0, &row(value)).unwrap());
+        readers
+            .into_iter()
+            .for_each(|reader| reader.join().unwrap());
+
+        assert_eq!(reader.read_page(PageId::new(0, 0, 0)).unwrap()[0], 17);
+    }
+}
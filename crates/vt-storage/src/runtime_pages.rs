@@ -0,0 +1,105 @@
+//! Page sources with pages written at runtime, such as decal bakes and user images.
+
+use std::{collections::HashMap, sync::RwLock};
+
+use vt_core::PageId;
+
+use crate::{OverzoomedPage, PageSource, TextureMetadata, TextureStorageError};
+
+/// A [`PageSource`] reading the pages written with [`RuntimePageSource::insert_page`] instead of
+/// the ones of `S`, so that content added at runtime is streamed in again once evicted.
+///
+/// Shared with the streaming thread through an `Arc`, pages can be inserted while it is
+/// streamed. Resident pages keep their old content until they are streamed in again.
+pub struct RuntimePageSource<S> {
+    source: S,
+    /// In the layout of the pages of `source`, see [`TextureMetadata::page_byte_size_at`].
+    pages: RwLock<HashMap<PageId, Vec<u8>>>,
+}
+
+impl<S: PageSource> RuntimePageSource<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            pages: Default::default(),
+        }
+    }
+
+    /// Read `data` for `page` from now on, in place of the page of the source.
+    ///
+    /// ### Panics
+    ///
+    /// - If `data` is not [`TextureMetadata::page_byte_size_at`] the mip level of `page` long.
+    pub fn insert_page(&self, page: PageId, data: Vec<u8>) {
+        assert_eq!(
+            data.len(),
+            self.source.metadata().page_byte_size_at(page.mip_level())
+        );
+        self.pages.write().unwrap().insert(page, data);
+    }
+
+    /// Read `page` from the source again, returning whether it was inserted.
+    pub fn remove_page(&self, page: PageId) -> bool {
+        self.pages.write().unwrap().remove(&page).is_some()
+    }
+
+    /// The pages inserted, in no particular order.
+    pub fn inserted_pages(&self) -> Vec<PageId> {
+        self.pages.read().unwrap().keys().copied().collect()
+    }
+}
+
+impl<S: PageSource> PageSource for RuntimePageSource<S> {
+    fn metadata(&self) -> &TextureMetadata {
+        self.source.metadata()
+    }
+
+    fn read_page(&self, page: PageId) -> Result<Vec<u8>, TextureStorageError> {
+        match self.pages.read().unwrap().get(&page) {
+            Some(data) => Ok(data.clone()),
+            None => self.source.read_page(page),
+        }
+    }
+
+    /// Inserted pages are never synthesized, even if they are missing from the source.
+    fn read_page_overzoomed(&self, page: PageId) -> Result<OverzoomedPage, TextureStorageError> {
+        match self.pages.read().unwrap().get(&page) {
+            Some(data) => Ok(OverzoomedPage {
+                data: data.clone(),
+                synthetic: false,
+            }),
+            None => self.source.read_page_overzoomed(page),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::fixture::TempDir;
+    use vt_core::PageId;
+
+    use crate::{PageSource, RuntimePageSource, TextureMetadata, TextureStorage};
+
+    /// Inserted pages are read instead of the ones of the source until they are removed.
+    #[test]
+    fn read_inserted_pages() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = TextureStorage::new(
+            TextureMetadata::from_mip(1, 4).with_page_size(8, 1),
+            Some(temp_dir.path().to_str().unwrap()),
+            None,
+        )
+        .unwrap();
+        let source = RuntimePageSource::new(storage.reader());
+        let page = PageId::new(1, 0, 0);
+        assert!(source.read_page(page).is_err());
+
+        source.insert_page(page, vec![7; 8 * 8 * 4]);
+        assert_eq!(source.read_page(page).unwrap()[0], 7);
+        assert!(!source.read_page_overzoomed(page).unwrap().synthetic);
+        assert_eq!(source.inserted_pages(), [page]);
+        assert!(source.remove_page(page));
+        assert!(!source.remove_page(page));
+        assert!(source.read_page(page).is_err());
+    }
+}
@@ -0,0 +1,93 @@
+//! Copy-on-write snapshots of textures, for replays and photo modes sampling a texture as it was
+//! while the live texture keeps being written.
+
+use std::{io::Write, path::Path, sync::Arc};
+
+use crate::{reader::row_file_path, TextureReader, TextureStorage, TextureStorageError};
+
+impl TextureStorage {
+    /// A reader of the texture as it is now, which keeps reading the same pages while the
+    /// texture is written to.
+    ///
+    /// The row files are hard linked into `directory`, created if needed, and copied on file
+    /// systems without hard links. Since row files are replaced instead of rewritten, the rows
+    /// written after the snapshot do not change it, and the rows never written again are shared
+    /// with the texture. The metadata is written to the directory too, so that the snapshot can
+    /// be loaded later with [`TextureStorage::load`]. The directory is not removed when the
+    /// readers of the snapshot are dropped.
+    ///
+    /// Packed textures can not be written to, so their snapshot is a reader of the archive.
+    pub fn snapshot(&self, directory: &Path) -> Result<TextureReader, TextureStorageError> {
+        if self.reader.archive.is_some() {
+            return Ok(self.reader());
+        }
+
+        std::fs::create_dir_all(directory)?;
+        for mip in 0..=self.metadata().mip_levels {
+            let (_, height) = self.metadata().page_grid(mip);
+            for row in 0..height {
+                let source = row_file_path(&self.reader.directory, (mip, row));
+                let target = row_file_path(directory, (mip, row));
+                if !source.exists() {
+                    continue;
+                }
+                if target.exists() {
+                    std::fs::remove_file(&target)?;
+                }
+                if std::fs::hard_link(&source, &target).is_err() {
+                    std::fs::copy(&source, &target)?;
+                }
+            }
+        }
+        let metadata_path = directory.join(format!("{}.json", Self::DEFAULT_METADATA_FILE));
+        std::fs::File::create(metadata_path)?
+            .write_all(miniserde::json::to_string(self.metadata()).as_bytes())?;
+
+        log::debug!(
+            "snapshot of {} in {}",
+            self.reader.directory.display(),
+            directory.display()
+        );
+        Ok(TextureReader {
+            directory: directory.into(),
+            metadata: Arc::clone(&self.reader.metadata),
+            archive: None,
+            row_files: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::fixture::TempDir;
+    use vt_core::PageId;
+
+    use crate::{TextureMetadata, TextureStorage};
+
+    /// The rows written after a snapshot change the texture but not the snapshot, which can be
+    /// loaded back.
+    #[test]
+    fn snapshot_keeps_rows() {
+        let (texture_dir, snapshot_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let mut storage = TextureStorage::new(
+            TextureMetadata::from_mip(1, 4).with_page_size(8, 1),
+            Some(texture_dir.path().to_str().unwrap()),
+            None,
+        )
+        .unwrap();
+        let row = |value| vec![value; 14 * 8 * 4];
+        storage.write_row(0, 0, &row(1)).unwrap();
+        let snapshot = storage.snapshot(snapshot_dir.path()).unwrap();
+        let page = PageId::new(0, 1, 0);
+        assert_eq!(snapshot.read_page(page).unwrap()[0], 1);
+
+        storage.write_row(0, 0, &row(2)).unwrap();
+        storage.write_row(0, 1, &row(3)).unwrap();
+        assert_eq!(storage.read_page(page).unwrap()[0], 2);
+        assert_eq!(snapshot.read_page(page).unwrap()[0], 1);
+        assert!(snapshot.read_page(PageId::new(0, 0, 1)).is_err());
+
+        let loaded = TextureStorage::load(snapshot_dir.path().to_str(), None).unwrap();
+        assert_eq!(loaded.read_page(page).unwrap()[0], 1);
+    }
+}
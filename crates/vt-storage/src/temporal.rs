@@ -0,0 +1,194 @@
+//! Page sources whose content changes over time, such as video walls and animated flipbooks.
+
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use vt_core::{ensure, PageId};
+
+use crate::{OverzoomedPage, PageSource, TextureMetadata, TextureStorageError};
+
+/// A [`PageSource`] reading the pages of its current frame, one source per frame.
+///
+/// Advancing the time with [`TemporalPageSource::set_frame`] returns the resident pages whose
+/// content changed, to be streamed in again (see `StreamingHandle::refresh_pages` in the
+/// runtime). The other pages keep their slot: pages are compared by the hash of their content,
+/// computed once per frame and page, so that static regions of a video are never streamed twice.
+pub struct TemporalPageSource<S> {
+    frames: Vec<S>,
+    frame: AtomicUsize,
+    /// The hash of the content of the pages compared so far, by frame and page, for the current
+    /// and previous frames.
+    hashes: Mutex<HashMap<(usize, PageId), u64>>,
+}
+
+impl<S: PageSource> TemporalPageSource<S> {
+    /// A source starting at the first of `frames`.
+    ///
+    /// ### Errors
+    ///
+    /// - [`TextureStorageError::LayoutMismatch`] if the frames do not all have the pages of the
+    ///   first one (see [`TextureMetadata::same_page_layout`]).
+    ///
+    /// ### Panics
+    ///
+    /// - If `frames` is empty.
+    pub fn new(frames: Vec<S>) -> Result<Self, TextureStorageError> {
+        let first = frames.first().expect("at least one frame").metadata();
+        ensure!(
+            frames
+                .iter()
+                .all(|frame| frame.metadata().same_page_layout(first)),
+            TextureStorageError::LayoutMismatch
+        );
+        Ok(Self {
+            frames,
+            frame: AtomicUsize::new(0),
+            hashes: Default::default(),
+        })
+    }
+
+    /// The index of the current frame.
+    pub fn frame(&self) -> usize {
+        self.frame.load(Ordering::Acquire)
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Read the pages of `frame` from now on, looping past the last frame, and return the ones of
+    /// `resident` whose content differs from the one of the previous frame.
+    pub fn set_frame(
+        &self,
+        frame: usize,
+        resident: impl IntoIterator<Item = PageId>,
+    ) -> Result<Vec<PageId>, TextureStorageError> {
+        let frame = frame % self.frames.len();
+        let previous = self.frame.swap(frame, Ordering::AcqRel);
+        if previous == frame {
+            return Ok(Vec::new());
+        }
+        let changed = resident
+            .into_iter()
+            .try_fold(Vec::new(), |mut changed, page| {
+                if self.page_hash(previous, page)? != self.page_hash(frame, page)? {
+                    changed.push(page);
+                }
+                Ok(changed)
+            });
+        self.hashes
+            .lock()
+            .unwrap()
+            .retain(|&(hashed_frame, _), _| hashed_frame == frame || hashed_frame == previous);
+        changed
+    }
+
+    /// Read the pages of the next frame, see [`TemporalPageSource::set_frame`].
+    pub fn advance(
+        &self,
+        resident: impl IntoIterator<Item = PageId>,
+    ) -> Result<Vec<PageId>, TextureStorageError> {
+        self.set_frame(self.frame() + 1, resident)
+    }
+
+    fn page_hash(&self, frame: usize, page: PageId) -> Result<u64, TextureStorageError> {
+        if let Some(&hash) = self.hashes.lock().unwrap().get(&(frame, page)) {
+            return Ok(hash);
+        }
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.frames[frame].read_page(page)?.hash(&mut hasher);
+        let hash = hasher.finish();
+        self.hashes.lock().unwrap().insert((frame, page), hash);
+        Ok(hash)
+    }
+
+    fn current(&self) -> &S {
+        &self.frames[self.frame()]
+    }
+}
+
+impl<S: PageSource> PageSource for TemporalPageSource<S> {
+    /// The metadata of the current frame, the frames all having the same pages.
+    fn metadata(&self) -> &TextureMetadata {
+        self.current().metadata()
+    }
+
+    fn read_page(&self, page: PageId) -> Result<Vec<u8>, TextureStorageError> {
+        self.current().read_page(page)
+    }
+
+    fn read_page_overzoomed(&self, page: PageId) -> Result<OverzoomedPage, TextureStorageError> {
+        self.current().read_page_overzoomed(page)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_fs::fixture::TempDir;
+    use vt_core::PageId;
+
+    use crate::{PageSource, TemporalPageSource, TextureMetadata, TextureReader, TextureStorage};
+
+    /// Frames whose second row of pages is filled with each of `values`.
+    fn frames(temp_dirs: &[TempDir], values: &[u8]) -> Vec<TextureReader> {
+        let row = |value| vec![value; 14 * 8 * 4];
+        temp_dirs
+            .iter()
+            .zip(values)
+            .map(|(temp_dir, &value)| {
+                let mut storage = TextureStorage::new(
+                    TextureMetadata::from_mip(1, 4).with_page_size(8, 1),
+                    Some(temp_dir.path().to_str().unwrap()),
+                    None,
+                )
+                .unwrap();
+                storage.write_row(0, 0, &row(0)).unwrap();
+                storage.write_row(0, 1, &row(value)).unwrap();
+                storage.reader()
+            })
+            .collect()
+    }
+
+    /// Only the pages whose content changed between two frames are returned when the time
+    /// advances.
+    #[test]
+    fn changed_pages() {
+        let temp_dirs = [TempDir::new().unwrap(), TempDir::new().unwrap()];
+        let source = TemporalPageSource::new(frames(&temp_dirs, &[1, 2])).unwrap();
+        let pages = [PageId::new(0, 0, 0), PageId::new(0, 1, 1)];
+        assert_eq!(source.read_page(pages[1]).unwrap()[0], 1);
+
+        assert_eq!(source.advance(pages).unwrap(), [pages[1]]);
+        assert_eq!(source.read_page(pages[1]).unwrap()[0], 2);
+        assert_eq!(source.set_frame(3, pages).unwrap(), []);
+        assert_eq!(source.advance(pages).unwrap(), [pages[1]]);
+        assert_eq!(source.frame(), 0);
+    }
+
+    /// Only the hashes of the current and previous frames are kept.
+    #[test]
+    fn bounded_hashes() {
+        let temp_dirs = [(); 4].map(|_| TempDir::new().unwrap());
+        let source = TemporalPageSource::new(frames(&temp_dirs, &[1, 2, 2, 3])).unwrap();
+        let pages = [PageId::new(0, 0, 0), PageId::new(0, 1, 1)];
+        assert_eq!(source.advance(pages).unwrap(), [pages[1]]);
+        assert_eq!(source.advance(pages).unwrap(), []);
+        assert_eq!(source.advance(pages).unwrap(), [pages[1]]);
+        let mut hashed_frames = source
+            .hashes
+            .lock()
+            .unwrap()
+            .keys()
+            .map(|&(frame, _)| frame)
+            .collect::<Vec<_>>();
+        hashed_frames.sort_unstable();
+        hashed_frames.dedup();
+        assert_eq!(hashed_frames, [2, 3]);
+    }
+}
@@ -0,0 +1,144 @@
+//! The formats of the texels of the pages, from single channel heightmaps and masks to HDR colors.
+
+use std::ops::Range;
+
+use miniserde::{Deserialize, MiniSerialize};
+
+use crate::{hdr::half_to_f32, PageEncoding, TextureMetadata};
+
+/// The format of the texels of a texture, set by its bytes per texel (see
+/// [`TextureMetadata::from_dimensions`]).
+///
+/// Single and two channel textures hold data (e.g., heights or masks), so their layers are always
+/// [`ColorSpace::Linear`](crate::ColorSpace::Linear).
+#[derive(MiniSerialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TexelFormat {
+    /// 1 byte per texel, sampled from `R8Unorm` physical textures.
+    R8,
+    /// 2 bytes per texel, sampled from `Rg8Unorm` physical textures.
+    Rg8,
+    /// 4 bytes per texel.
+    #[default]
+    Rgba8,
+    /// Linear half floats, 8 bytes per texel.
+    Rgba16Float,
+}
+
+impl TexelFormat {
+    pub fn from_bytes_per_texel(bytes_per_texel: u8) -> Option<Self> {
+        match bytes_per_texel {
+            1 => Some(Self::R8),
+            2 => Some(Self::Rg8),
+            4 => Some(Self::Rgba8),
+            8 => Some(Self::Rgba16Float),
+            _ => None,
+        }
+    }
+
+    pub fn bytes_per_texel(self) -> u8 {
+        match self {
+            Self::R8 => 1,
+            Self::Rg8 => 2,
+            Self::Rgba8 => 4,
+            Self::Rgba16Float => 8,
+        }
+    }
+
+    /// The number of channels of a texel.
+    pub fn channels(self) -> usize {
+        match self {
+            Self::R8 => 1,
+            Self::Rg8 => 2,
+            Self::Rgba8 | Self::Rgba16Float => 4,
+        }
+    }
+
+    /// The bytes of the alpha channel in a texel, `None` for the formats without one.
+    pub fn alpha_bytes(self) -> Option<Range<usize>> {
+        match self {
+            Self::R8 | Self::Rg8 => None,
+            Self::Rgba8 => Some(3..4),
+            Self::Rgba16Float => Some(6..8),
+        }
+    }
+
+    /// Whether layers of texels of this format can be stored with `encoding`. The block
+    /// compressed encodings take RGBA8 texels only.
+    pub fn supports_encoding(self, encoding: PageEncoding) -> bool {
+        self == Self::Rgba8 || encoding == PageEncoding::Raw
+    }
+
+    /// Convert decoded texels of this format to RGBA8, clamping half floats to `[0, 1]`.
+    ///
+    /// Single channel texels become grey, and two channel ones keep a blue of 0. Both are opaque.
+    pub fn to_rgba8(self, texels: Vec<u8>) -> Vec<u8> {
+        match self {
+            Self::R8 => texels
+                .into_iter()
+                .flat_map(|value| [value, value, value, u8::MAX])
+                .collect(),
+            Self::Rg8 => texels
+                .chunks_exact(2)
+                .flat_map(|texel| [texel[0], texel[1], 0, u8::MAX])
+                .collect(),
+            Self::Rgba8 => texels,
+            Self::Rgba16Float => half_to_f32(&texels)
+                .into_iter()
+                .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+                .collect(),
+        }
+    }
+}
+
+impl TextureMetadata {
+    pub fn texel_format(&self) -> TexelFormat {
+        TexelFormat::from_bytes_per_texel(self.bytes_per_texel)
+            .expect("the bytes per texel to be checked on creation")
+    }
+
+    pub fn bytes_per_texel(&self) -> u8 {
+        self.bytes_per_texel
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TexelFormat;
+    use crate::{hdr::f32_to_half, PageEncoding, TextureMetadata};
+
+    #[test]
+    fn texel_formats() {
+        let metadata = TextureMetadata::from_mip(0, 8);
+        assert_eq!(metadata.texel_format(), TexelFormat::Rgba16Float);
+        assert_eq!(metadata.page_byte_size(), 128 * 128 * 8);
+        assert!(!TexelFormat::Rgba16Float.supports_encoding(PageEncoding::Bc7));
+        assert!(!TexelFormat::Rg8.supports_encoding(PageEncoding::Bc5));
+        assert_eq!(TexelFormat::from_bytes_per_texel(3), None);
+        assert_eq!(
+            TexelFormat::Rgba16Float.to_rgba8(f32_to_half(&[0.5, 4.0, -1.0, 1.0]).into_vec()),
+            [128, 255, 0, 255]
+        );
+        assert_eq!(TexelFormat::R8.to_rgba8(vec![7]), [7, 7, 7, 255]);
+        assert_eq!(TexelFormat::Rg8.to_rgba8(vec![7, 9]), [7, 9, 0, 255]);
+        assert_eq!(TexelFormat::Rg8.alpha_bytes(), None);
+        assert_eq!(TexelFormat::Rgba16Float.alpha_bytes(), Some(6..8));
+    }
+
+    /// Single and two channel textures take a quarter and half the space of RGBA8 ones on disk.
+    #[test]
+    fn narrow_texel_formats() {
+        let page_bytes = |bytes_per_texel| {
+            TextureMetadata::from_mip(0, bytes_per_texel)
+                .with_page_size(16, 1)
+                .page_byte_size()
+        };
+        assert_eq!(page_bytes(1), 16 * 16);
+        assert_eq!(page_bytes(2), 16 * 16 * 2);
+        let metadata = TextureMetadata::from_mip(0, 1);
+        assert_eq!(metadata.texel_format(), TexelFormat::R8);
+        assert_eq!(
+            metadata.layers()[0].color_space(),
+            crate::ColorSpace::Linear
+        );
+    }
+}
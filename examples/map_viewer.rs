@@ -0,0 +1,461 @@
+//! Pan and zoom over a large map streamed from a virtual texture.
+//!
+//! ```sh
+//! cargo run --release --example map_viewer [image]
+//! ```
+//!
+//! The optional argument is an image to view, such as a satellite mosaic from the public domain
+//! NASA Blue Marble collection. It is imported once and reused on the next runs while it does
+//! not change. Without it, a procedural map of 64 by 64 pages is baked instead.
+//!
+//! The arrow keys (or WASD) pan the map and the mouse wheel zooms in and out. Only the coarsest
+//! mip levels are loaded up front: the rest is streamed in from the feedback of the frames, and
+//! evicted when the physical texture is full.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+
+use virt_texture::{
+    camera::{Camera, CameraController, CameraModule, CameraProjection},
+    config::{PhysicalTextureConfig, VirtualTexturingConfig},
+    draw::{DrawItem, Mesh},
+    setup::{VirtualTexturingContext, WgpuContext},
+    storage::{FitOperation, ImportOptions, TextureMetadata, TextureStorage},
+    streaming::{PageId, SlotAllocator, StreamingHandle},
+    textures::{CacheTier, TextureHandle},
+    vertex::Vertex,
+};
+use winit::{
+    event::{ElementState, Event, KeyEvent, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+/// The side of the procedural map in pages.
+const PROCEDURAL_PAGES: u16 = 64;
+/// The slots of the physical texture, fewer than the pages of mip level 0 so that pages have to
+/// be evicted when panning around.
+const PHYSICAL_TEXTURE: PhysicalTextureConfig = PhysicalTextureConfig {
+    page_slots_x: 32,
+    page_slots_y: 32,
+    layers: 2,
+};
+/// The number of mip levels loaded up front, from the coarsest one.
+const MIP_TAIL_LEVELS: u8 = 4;
+/// The distance of the camera to the map, the map spanning [-1, 1].
+const ZOOM_RANGE: std::ops::RangeInclusive<f32> = 0.01..=3.0;
+/// The pan speed in screens per second.
+const PAN_SPEED: f32 = 0.8;
+
+fn main() {
+    let storage = match std::env::args().nth(1) {
+        Some(path) => import_image(Path::new(&path)),
+        None => bake_procedural_map(),
+    };
+    let metadata = storage.metadata().clone();
+    let (width, height) = metadata.mip_dimensions(0);
+    let config = VirtualTexturingConfig {
+        page_table_size: width.max(height) as u32,
+        page_size: metadata.page_size() as u32,
+        border_size: metadata.border_size() as u32,
+        physical_texture: PHYSICAL_TEXTURE,
+        ..Default::default()
+    };
+
+    let event_loop = winit::event_loop::EventLoop::new()
+        .expect("the event loop creation to succeed since we are on the main thread");
+    let window = winit::window::WindowBuilder::new()
+        .with_title("Map Viewer")
+        .build(&event_loop)
+        .unwrap();
+
+    let wgpu_context = Arc::new(pollster::block_on(WgpuContext::new(window)));
+    let mut context =
+        VirtualTexturingContext::from_metadata(Arc::clone(&wgpu_context), config, &[&metadata])
+            .expect("the configuration to be made from the metadata");
+    let mut streaming = StreamingHandle::new(
+        Arc::clone(&wgpu_context),
+        context.textures(),
+        storage.reader(),
+    );
+    let mut cache = PageCache::new(&streaming, context.textures());
+
+    // The map keeps the aspect ratio of the texture, the longer side spanning [-1, 1].
+    let aspect = width as f32 / height as f32;
+    let items = [
+        DrawItem::new(Arc::new(Mesh::new(&wgpu_context, &QUAD))).with_transform(
+            nalgebra::Matrix4::new_nonuniform_scaling(&nalgebra::Vector3::new(
+                aspect.min(1.0),
+                aspect.recip().min(1.0),
+                1.0,
+            )),
+        ),
+    ];
+    let mut view = MapView::default();
+    let mut last_frame = Instant::now();
+
+    event_loop
+        .run(|event, target| {
+            let Event::WindowEvent { event, .. } = event else {
+                if let Event::AboutToWait = event {
+                    wgpu_context.window.as_ref().unwrap().request_redraw();
+                }
+                return;
+            };
+            match event {
+                WindowEvent::CloseRequested => target.exit(),
+                WindowEvent::Resized(size) => context.resize(size),
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            physical_key: PhysicalKey::Code(key),
+                            state,
+                            ..
+                        },
+                    ..
+                } => view.process_keyboard(key, state),
+                WindowEvent::MouseWheel { delta, .. } => view.zoom(match delta {
+                    MouseScrollDelta::LineDelta(_, lines) => lines,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 100.0,
+                }),
+                WindowEvent::RedrawRequested => {
+                    let now = Instant::now();
+                    view.update((now - last_frame).as_secs_f32());
+                    last_frame = now;
+                    let size = context.target_size();
+                    context.update_camera(&view.camera(size.width as f32 / size.height as f32));
+
+                    cache.stream_requested_pages(&streaming);
+                    let frame = context.begin_frame(&items);
+                    let output = context
+                        .end_frame(frame, Some(&mut streaming))
+                        .expect("the context to have a surface");
+                    output.present();
+                }
+                _ => (),
+            }
+        })
+        .unwrap();
+}
+
+/// A quad spanning [-1, 1] on the x and y axes, facing the camera.
+const QUAD: [Vertex; 6] = {
+    const fn corner(x: f32, y: f32) -> Vertex {
+        Vertex::new(
+            [x, y, 0.0],
+            [0.0, 0.0, 1.0],
+            [(x + 1.0) / 2.0, (1.0 - y) / 2.0],
+        )
+    }
+    [
+        corner(-1.0, 1.0),
+        corner(-1.0, -1.0),
+        corner(1.0, 1.0),
+        corner(1.0, 1.0),
+        corner(-1.0, -1.0),
+        corner(1.0, -1.0),
+    ]
+};
+
+/// The directory of the textures of the example, in the target directory.
+fn texture_directory(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("target/map_viewer")
+        .join(name)
+}
+
+fn import_image(path: &Path) -> TextureStorage {
+    let directory = texture_directory("image");
+    println!("importing {} to {}", path.display(), directory.display());
+    let options = ImportOptions::new(
+        TextureMetadata::from_dimensions((1, 1), 4),
+        FitOperation::Pad,
+        image::imageops::FilterType::Triangle,
+    )
+    .with_directory(directory.to_string_lossy());
+    TextureStorage::import_or_load(path, &options).expect("the image to be imported")
+}
+
+/// Bake the procedural map on the first run, and load it on the next ones.
+fn bake_procedural_map() -> TextureStorage {
+    let directory = texture_directory("procedural");
+    let directory_name = directory.to_string_lossy();
+    // Written once the bake completes, so that an interrupted bake is done again.
+    let baked_marker = directory.join("baked");
+    if baked_marker.exists() {
+        if let Ok(storage) = TextureStorage::load(Some(&directory_name), None) {
+            return storage;
+        }
+    }
+    if directory.exists() {
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    let metadata = TextureMetadata::from_dimensions((PROCEDURAL_PAGES, PROCEDURAL_PAGES), 4);
+    let (width, height) = metadata.texel_dimensions();
+    println!(
+        "baking a procedural map of {width}x{height} texels to {}",
+        directory.display()
+    );
+    let start = Instant::now();
+    let mut storage = TextureStorage::new(metadata, Some(&directory_name), None).unwrap();
+    storage
+        .import_texture(
+            image::imageops::FilterType::Triangle,
+            ProceduralMap::new(width, height),
+        )
+        .expect("the procedural map to be baked");
+    std::fs::write(baked_marker, []).unwrap();
+    println!("baked in {:.1}s", start.elapsed().as_secs_f32());
+    storage
+}
+
+/// The pages streamed in by the example, on top of the preloaded mip tail.
+///
+/// Every frame, the pages requested by the latest feedback are queued, and streamed in within the
+/// frame budget of the streaming handle. When the physical texture is full, the page requested
+/// the longest time ago is evicted, its page table entries falling back to the finest mapped page
+/// covering it.
+struct PageCache {
+    slots: SlotAllocator,
+    /// The streamed pages, with their slot and the frame they were last requested on.
+    pages: HashMap<PageId, (u32, u32, u64)>,
+    frame: u64,
+}
+
+impl PageCache {
+    fn new(streaming: &StreamingHandle, textures: TextureHandle) -> Self {
+        let metadata = streaming.metadata();
+        let first_tail_mip = metadata.mip_levels().saturating_sub(MIP_TAIL_LEVELS - 1);
+        let mut slots = SlotAllocator::with_layout(textures.slot_layout(CacheTier::Cold));
+        let preloaded = streaming
+            .preload_mip_tail(0, first_tail_mip, &mut slots)
+            .expect("the mip tail to fit in the physical texture");
+        println!("preloaded {preloaded} pages from mip level {first_tail_mip}");
+        Self {
+            slots,
+            pages: HashMap::new(),
+            frame: 0,
+        }
+    }
+
+    fn stream_requested_pages(&mut self, streaming: &StreamingHandle) {
+        let Some(requested) = streaming.request_traces().pop() else {
+            return;
+        };
+        self.frame += 1;
+        requested.iter().for_each(|page| {
+            if let Some((_, _, frame)) = self.pages.get_mut(page) {
+                *frame = self.frame;
+            }
+        });
+        // The preloaded mip tail is resident, and skipped. The pages left over by the budget of
+        // the frame are streamed in by the next ones.
+        streaming.queue_requests(&requested);
+        let streamed = streaming.stream_queued(|_| self.allocate(streaming));
+        streamed
+            .into_iter()
+            .for_each(|streamed| match streamed.result {
+                Ok(()) => {
+                    let (x, y) = streamed.slot;
+                    self.pages.insert(streamed.page, (x, y, self.frame));
+                }
+                Err(_) => self.slots.free(streamed.slot, 0),
+            });
+    }
+
+    /// A free slot, evicting the page requested the longest time ago if there is none. Pages
+    /// requested by the latest feedback are never evicted.
+    fn allocate(&mut self, streaming: &StreamingHandle) -> Option<(u32, u32)> {
+        if let Some(slot) = self.slots.allocate(0) {
+            return Some(slot);
+        }
+        let (&evicted, &(x, y, _)) = self
+            .pages
+            .iter()
+            .filter(|(_, &(_, _, frame))| frame < self.frame)
+            .min_by_key(|(_, &(_, _, frame))| frame)?;
+        self.pages.remove(&evicted);
+        streaming.evict_page(evicted);
+        Some((x, y))
+    }
+}
+
+/// The top down camera over the map, moved by the keyboard and the mouse wheel.
+struct MapView {
+    center: nalgebra::Vector2<f32>,
+    distance: f32,
+    /// The pan speed of the keys held down to the left, right, top and bottom.
+    pan: [f32; 4],
+}
+
+impl Default for MapView {
+    fn default() -> Self {
+        Self {
+            center: nalgebra::Vector2::zeros(),
+            distance: 2.5,
+            pan: [0.0; 4],
+        }
+    }
+}
+
+impl MapView {
+    const FOVY: f32 = std::f32::consts::FRAC_PI_4;
+
+    fn process_keyboard(&mut self, key: KeyCode, state: ElementState) {
+        let index = match key {
+            KeyCode::ArrowLeft | KeyCode::KeyA => 0,
+            KeyCode::ArrowRight | KeyCode::KeyD => 1,
+            KeyCode::ArrowUp | KeyCode::KeyW => 2,
+            KeyCode::ArrowDown | KeyCode::KeyS => 3,
+            _ => return,
+        };
+        self.pan[index] = if state == ElementState::Pressed {
+            PAN_SPEED
+        } else {
+            0.0
+        };
+    }
+
+    /// Zoom in by `steps` wheel steps, or out if negative.
+    fn zoom(&mut self, steps: f32) {
+        self.distance =
+            (self.distance * 0.85f32.powf(steps)).clamp(*ZOOM_RANGE.start(), *ZOOM_RANGE.end());
+    }
+
+    fn update(&mut self, delta_time: f32) {
+        // The height of the screen on the map, so that panning has the same speed at every zoom.
+        let screen = 2.0 * self.distance * (Self::FOVY / 2.0).tan();
+        let [left, right, up, down] = self.pan;
+        self.center += nalgebra::Vector2::new(right - left, up - down) * screen * delta_time;
+        self.center = self.center.map(|coordinate| coordinate.clamp(-1.0, 1.0));
+    }
+
+    fn camera(&self, aspect: f32) -> CameraModule {
+        CameraModule::from_parts(
+            // Looking down the z axis, with y up.
+            Camera::new(
+                nalgebra::Point3::new(self.center.x, self.center.y, self.distance),
+                -std::f32::consts::FRAC_PI_2,
+                0.0,
+            ),
+            CameraProjection::new(
+                aspect,
+                Self::FOVY,
+                *ZOOM_RANGE.start() / 2.0,
+                ZOOM_RANGE.end() * 2.0,
+            ),
+            CameraController::default(),
+        )
+    }
+}
+
+/// The rows of a procedural satellite map in Rgba8, generated as they are read so that maps
+/// larger than the memory can be baked.
+///
+/// The elevation is fractal value noise, shaded from deep water to snow.
+struct ProceduralMap {
+    width: u32,
+    height: u32,
+    row: Vec<u8>,
+    next_row: u32,
+    offset: usize,
+}
+
+impl ProceduralMap {
+    const OCTAVES: u32 = 7;
+    /// The period of the first octave, in texels.
+    const BASE_PERIOD: f32 = 2048.0;
+
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            row: Vec::new(),
+            next_row: 0,
+            offset: 0,
+        }
+    }
+
+    fn generate_row(&mut self) {
+        let y = self.next_row as f32;
+        self.row.clear();
+        self.row.extend((0..self.width).flat_map(|x| {
+            let (x, mut frequency, mut amplitude) = (x as f32, Self::BASE_PERIOD.recip(), 0.5);
+            let mut elevation = 0.0;
+            for octave in 0..Self::OCTAVES {
+                elevation += amplitude * value_noise(x * frequency, y * frequency, octave);
+                frequency *= 2.0;
+                amplitude /= 2.0;
+            }
+            shade(elevation)
+        }));
+        self.next_row += 1;
+        self.offset = 0;
+    }
+}
+
+impl std::io::Read for ProceduralMap {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.offset == self.row.len() {
+            if self.next_row == self.height {
+                return Ok(0);
+            }
+            self.generate_row();
+        }
+        let read = buf.len().min(self.row.len() - self.offset);
+        buf[..read].copy_from_slice(&self.row[self.offset..self.offset + read]);
+        self.offset += read;
+        Ok(read)
+    }
+}
+
+/// Smoothly interpolated noise in [0, 1] with a lattice of period 1.
+fn value_noise(x: f32, y: f32, seed: u32) -> f32 {
+    let lattice = |x: i32, y: i32| {
+        let mut hash = (x as u32).wrapping_mul(0x8da6b343)
+            ^ (y as u32).wrapping_mul(0xd8163841)
+            ^ seed.wrapping_mul(0xcb1ab31f);
+        hash ^= hash >> 13;
+        hash = hash.wrapping_mul(0x5bd1e995);
+        hash ^= hash >> 15;
+        hash as f32 / u32::MAX as f32
+    };
+    let (x0, y0) = (x.floor(), y.floor());
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (tx, ty) = (smooth(x - x0), smooth(y - y0));
+    let (x0, y0) = (x0 as i32, y0 as i32);
+    let top = lattice(x0, y0) + (lattice(x0 + 1, y0) - lattice(x0, y0)) * tx;
+    let bottom = lattice(x0, y0 + 1) + (lattice(x0 + 1, y0 + 1) - lattice(x0, y0 + 1)) * tx;
+    top + (bottom - top) * ty
+}
+
+/// The color of the ground at `elevation`, from deep water to snow.
+fn shade(elevation: f32) -> [u8; 4] {
+    const STOPS: [(f32, [f32; 3]); 7] = [
+        (0.0, [10.0, 30.0, 80.0]),
+        (0.45, [30.0, 80.0, 150.0]),
+        (0.5, [210.0, 200.0, 150.0]),
+        (0.55, [90.0, 140.0, 60.0]),
+        (0.68, [40.0, 90.0, 40.0]),
+        (0.8, [120.0, 110.0, 100.0]),
+        (0.9, [245.0, 245.0, 250.0]),
+    ];
+    let elevation = elevation.clamp(0.0, 1.0);
+    let upper = STOPS
+        .iter()
+        .position(|&(stop, _)| stop > elevation)
+        .unwrap_or(STOPS.len() - 1)
+        .max(1);
+    let ((low, low_color), (high, high_color)) = (STOPS[upper - 1], STOPS[upper]);
+    let t = ((elevation - low) / (high - low)).clamp(0.0, 1.0);
+    let mut color = [255; 4];
+    color[..3]
+        .iter_mut()
+        .zip(low_color.iter().zip(high_color))
+        .for_each(|(channel, (low, high))| *channel = (low + (high - low) * t) as u8);
+    color
+}
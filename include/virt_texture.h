@@ -0,0 +1,412 @@
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#ifndef VIRT_TEXTURE_H
+#define VIRT_TEXTURE_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The outcome of a call, with the details in [`vt_last_error_message`] on failure.
+ */
+typedef enum VtResult {
+  VT_RESULT_OK = 0,
+  /**
+   * A handle or an output pointer is null.
+   */
+  VT_RESULT_NULL_POINTER,
+  /**
+   * A string is not valid UTF-8, or a size does not match.
+   */
+  VT_RESULT_INVALID_ARGUMENT,
+  /**
+   * The configuration json could not be parsed.
+   */
+  VT_RESULT_CONFIG,
+  /**
+   * A texture could not be read, imported or written.
+   */
+  VT_RESULT_STORAGE,
+  /**
+   * No graphics adapter is available.
+   */
+  VT_RESULT_NO_ADAPTER,
+  /**
+   * The call panicked, and the objects it was given should be destroyed.
+   */
+  VT_RESULT_PANIC,
+} VtResult;
+
+/**
+ * How [`vt_storage_import_or_load`] fits an image, see [`FitOperation`].
+ */
+typedef enum VtFitOperation {
+  VT_FIT_OPERATION_PAD,
+  VT_FIT_OPERATION_CROP,
+  VT_FIT_OPERATION_RESIZE,
+} VtFitOperation;
+
+/**
+ * The windowing system of a [`VtWindowHandle`].
+ */
+typedef enum VtWindowSystem {
+  VT_WINDOW_SYSTEM_WIN32,
+  VT_WINDOW_SYSTEM_XLIB,
+  VT_WINDOW_SYSTEM_XCB,
+  VT_WINDOW_SYSTEM_WAYLAND,
+  VT_WINDOW_SYSTEM_APP_KIT,
+} VtWindowSystem;
+
+typedef struct VtContext VtContext;
+
+typedef struct VtMesh VtMesh;
+
+typedef struct VtStorage VtStorage;
+
+typedef struct VtStreaming VtStreaming;
+
+/**
+ * See [`Vertex`].
+ */
+typedef struct VtVertex {
+  float position[3];
+  float normal[3];
+  float tex_coords[2];
+} VtVertex;
+
+/**
+ * A mesh drawn by [`vt_context_render_frame`], see [`DrawItem`].
+ */
+typedef struct VtDrawItem {
+  const VtMesh *mesh;
+  /**
+   * Column major, from the space of the mesh to world space.
+   */
+  float transform[16];
+  uint8_t texture_id;
+  /**
+   * See [`DrawItem::mip_range`], with `min_mip <= max_mip`. Set `max_mip` to 255 to request
+   * down to the coarsest mip level.
+   */
+  uint8_t min_mip;
+  uint8_t max_mip;
+} VtDrawItem;
+
+/**
+ * See [`PageId`].
+ */
+typedef struct VtPageId {
+  uint8_t texture_id;
+  uint8_t mip_level;
+  uint16_t x;
+  uint16_t y;
+} VtPageId;
+
+/**
+ * A native window of the engine, presented to by [`vt_context_create_with_window`].
+ */
+typedef struct VtWindowHandle {
+  VtWindowSystem system;
+  /**
+   * The `HWND` (Win32), `wl_surface *` (Wayland) or `NSView *` (AppKit) of the window, unused
+   * by Xlib and Xcb.
+   */
+  void *window;
+  /**
+   * The `HINSTANCE` (Win32), `Display *` (Xlib), `xcb_connection_t *` (Xcb) or `wl_display *`
+   * (Wayland), unused by AppKit.
+   */
+  void *display;
+  /**
+   * The `Window` (Xlib) or `xcb_window_t` (Xcb) of the window.
+   */
+  uint64_t window_id;
+  /**
+   * The X11 screen (Xlib and Xcb).
+   */
+  int screen;
+} VtWindowHandle;
+
+/**
+ * See [`StreamingStats`](crate::streaming::StreamingStats).
+ */
+typedef struct VtStreamingStats {
+  uint64_t invalid_feedback_texels;
+  uint64_t dropped_feedback_requests;
+  uint64_t skipped_feedback_frames;
+  uint64_t uploaded_pages;
+  uint64_t requested_pages;
+  uint64_t missed_pages;
+  uint64_t evicted_pages;
+  uint64_t read_bytes;
+  uint64_t uploaded_bytes;
+  uint64_t staging_stalls;
+  uint64_t staging_stall_micros;
+  uint64_t feedback_in_flight;
+} VtStreamingStats;
+
+/**
+ * See [`StorageStats`](crate::storage::StorageStats).
+ */
+typedef struct VtStorageStats {
+  uint64_t pages;
+  uint64_t missing_pages;
+  uint64_t stored_bytes;
+  uint64_t page_bytes;
+} VtStorageStats;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * The message of the last failed call of the thread, or null if none failed. Valid until the
+ * next failed call of the thread.
+ */
+const char *vt_last_error_message(void);
+
+/**
+ * Create a context rendering to an offscreen target of `width` by `height` texels, configured
+ * by the json `config` (see [`VirtualTexturingConfig`]), or the default configuration if null.
+ *
+ * # Safety
+ *
+ * `config` must be null or a nul terminated string, and `out` valid for writes.
+ */
+VtResult vt_context_create_headless(uint32_t width,
+                                    uint32_t height,
+                                    const char *config,
+                                    VtContext **out);
+
+/**
+ * Create a context presenting to `window`, whose surface is `width` by `height` texels until
+ * [`vt_context_resize`] is called, configured by the json `config` (see
+ * [`VirtualTexturingConfig`]), or the default configuration if null. The frames rendered by
+ * [`vt_context_render_frame`] are presented to the window.
+ *
+ * # Safety
+ *
+ * `window` must point to a window handle whose window stays valid until the context is
+ * destroyed, `config` must be null or a nul terminated string, and `out` valid for writes.
+ */
+VtResult vt_context_create_with_window(const VtWindowHandle *window,
+                                       uint32_t width,
+                                       uint32_t height,
+                                       const char *config,
+                                       VtContext **out);
+
+/**
+ * Resize the surface of a context created by [`vt_context_create_with_window`], see
+ * [`VirtualTexturingContext::resize`].
+ *
+ * # Safety
+ *
+ * `context` must be a live context.
+ */
+VtResult vt_context_resize(VtContext *context, uint32_t width, uint32_t height);
+
+/**
+ * # Safety
+ *
+ * `context` must be null or created by [`vt_context_create_headless`] or
+ * [`vt_context_create_with_window`], and not used afterwards.
+ */
+void vt_context_destroy(VtContext *context);
+
+/**
+ * Set the column major view projection matrix of the following frames.
+ *
+ * # Safety
+ *
+ * `context` must be a live context, and `view_proj` point to 16 floats.
+ */
+VtResult vt_context_set_view_proj(VtContext *context, const float *view_proj);
+
+/**
+ * Render a frame of the `item_count` items of `items`, and read back its feedback with
+ * `streaming` if not null (see [`VirtualTexturingContext::begin_frame`]). The frame is presented
+ * if the context has a window.
+ *
+ * # Safety
+ *
+ * `context` must be a live context, `items` point to `item_count` items whose meshes are live
+ * (or be null if `item_count` is 0), and `streaming` be null or a live streaming handle of
+ * `context`.
+ */
+VtResult vt_context_render_frame(VtContext *context,
+                                 const VtDrawItem *items,
+                                 uintptr_t item_count,
+                                 VtStreaming *streaming);
+
+/**
+ * Copy the RGBA8 texels of the last frame of a headless context to `texels`,
+ * `width * height * 4` bytes long, blocking until the frame is rendered.
+ *
+ * # Safety
+ *
+ * `context` must be a live context, and `texels` valid for writes of `len` bytes.
+ */
+VtResult vt_context_read_target(VtContext *context, uint8_t *texels, uintptr_t len);
+
+/**
+ * Upload a triangle list of `vertex_count` vertices.
+ *
+ * # Safety
+ *
+ * `context` must be a live context, `vertices` point to `vertex_count` vertices, and `out` be
+ * valid for writes.
+ */
+VtResult vt_mesh_create(VtContext *context,
+                        const VtVertex *vertices,
+                        uintptr_t vertex_count,
+                        VtMesh **out);
+
+/**
+ * The frames rendered before keep the mesh alive until they are done.
+ *
+ * # Safety
+ *
+ * `mesh` must be null or created by [`vt_mesh_create`], and not used afterwards.
+ */
+void vt_mesh_destroy(VtMesh *mesh);
+
+/**
+ * Load the texture in `directory`, see [`TextureStorage::load`].
+ *
+ * # Safety
+ *
+ * `directory` must be a nul terminated string, and `out` valid for writes.
+ */
+VtResult vt_storage_load(const char *directory, VtStorage **out);
+
+/**
+ * Import the image at `source_path` to `directory` with pages of `page_size` texels, borders
+ * of `border_size` included, or load it if it is unchanged since the last import (see
+ * [`TextureStorage::import_or_load`]).
+ *
+ * # Safety
+ *
+ * `source_path` and `directory` must be nul terminated strings, and `out` valid for writes.
+ */
+VtResult vt_storage_import_or_load(const char *source_path,
+                                   const char *directory,
+                                   uint16_t page_size,
+                                   uint16_t border_size,
+                                   VtFitOperation fit,
+                                   VtStorage **out);
+
+/**
+ * The size of the texture in texels, outer border included.
+ *
+ * # Safety
+ *
+ * `storage` must be a live storage, and `width` and `height` valid for writes.
+ */
+VtResult vt_storage_texel_dimensions(VtStorage *storage, uint32_t *width, uint32_t *height);
+
+/**
+ * # Safety
+ *
+ * `storage` must be a live storage, and `stats` valid for writes.
+ */
+VtResult vt_storage_stats(VtStorage *storage, VtStorageStats *stats);
+
+/**
+ * Save mip level `mip` to the image at `path`, in the format of its extension (see
+ * [`TextureStorage::export_mip`]).
+ *
+ * # Safety
+ *
+ * `storage` must be a live storage, and `path` a nul terminated string.
+ */
+VtResult vt_storage_export_mip(VtStorage *storage, uint8_t mip, const char *path);
+
+/**
+ * Copy the pages of `storage` that differ from the ones of `other` (see
+ * [`TextureStorage::diff`]) to `pages`, at most `capacity` of them, and write their number to
+ * `count`, which may exceed `capacity`.
+ *
+ * # Safety
+ *
+ * `storage` and `other` must be live storages, which may be the same, `pages` valid for writes
+ * of `capacity` pages (or null if `capacity` is 0), and `count` valid for writes.
+ */
+VtResult vt_storage_diff(const VtStorage *storage,
+                         const VtStorage *other,
+                         VtPageId *pages,
+                         uintptr_t capacity,
+                         uintptr_t *count);
+
+/**
+ * The streaming handles created from the storage keep reading its pages.
+ *
+ * # Safety
+ *
+ * `storage` must be null or created by a `vt_storage_*` function, and not used afterwards.
+ */
+void vt_storage_destroy(VtStorage *storage);
+
+/**
+ * Stream the pages of `storage` to the physical textures of `context`, see
+ * [`StreamingHandle::new`].
+ *
+ * # Safety
+ *
+ * `context` and `storage` must be live, and `out` valid for writes.
+ */
+VtResult vt_streaming_create(VtContext *context, VtStorage *storage, VtStreaming **out);
+
+/**
+ * # Safety
+ *
+ * `streaming` must be a live streaming handle, and `stats` valid for writes.
+ */
+VtResult vt_streaming_stats(VtStreaming *streaming, VtStreamingStats *stats);
+
+/**
+ * Read `page` from storage, upload it to `slot` and point the page table to it (see
+ * [`StreamingHandle::stream_page`] and [`StreamingHandle::map_page`]).
+ *
+ * Returns [`VtResult::InvalidArgument`] if `slot` is outside of the slots of the physical
+ * textures holding the pages of its mip level (see [`StreamingHandle::slot_layout`]).
+ *
+ * # Safety
+ *
+ * `streaming` must be a live streaming handle.
+ */
+VtResult vt_streaming_stream_page(VtStreaming *streaming,
+                                  VtPageId page,
+                                  uint32_t slot_x,
+                                  uint32_t slot_y);
+
+/**
+ * Copy the pages requested by the last feedback read back (see
+ * [`StreamingHandle::request_traces`]) to `pages`, at most `capacity` of them, and write their
+ * number to `count`, which may exceed `capacity`.
+ *
+ * # Safety
+ *
+ * `streaming` must be a live streaming handle, `pages` valid for writes of `capacity` pages (or
+ * null if `capacity` is 0), and `count` valid for writes.
+ */
+VtResult vt_streaming_requested_pages(VtStreaming *streaming,
+                                      VtPageId *pages,
+                                      uintptr_t capacity,
+                                      uintptr_t *count);
+
+/**
+ * Stops the streaming thread.
+ *
+ * # Safety
+ *
+ * `streaming` must be null or created by [`vt_streaming_create`], and not used afterwards.
+ */
+void vt_streaming_destroy(VtStreaming *streaming);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* VIRT_TEXTURE_H */
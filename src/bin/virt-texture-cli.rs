@@ -0,0 +1,333 @@
+//! Build and inspect virtual textures offline, without writing any Rust code.
+//!
+//! Run `virt-texture-cli help` for the subcommands and their options.
+
+use std::{error::Error, path::Path, process::ExitCode, str::FromStr};
+
+use image::imageops::FilterType;
+use virt_texture::storage::{
+    decode_page, FitOperation, ImportOptions, PageCompression, PageEncoding, TexelFormat,
+    TextureMetadata, TextureStorage,
+};
+use vt_core::PageId;
+
+const USAGE: &str = "\
+usage: virt-texture-cli <subcommand> [arguments]
+
+subcommands:
+  import <image> <directory> [options]
+      Tile an image into a virtual texture, or keep the texture of the directory if it was
+      already imported from the same image with the same options.
+      --page-size <texels>    the side of the pages, borders included (default 128)
+      --border <texels>       the border of the pages (default 4)
+      --encoding <encoding>   raw, bc7 or bc5 (default raw)
+      --zstd                  compress the pages with zstd
+      --fit <fit>             pad, crop or resize the image to a power of two pages (default pad)
+      --filter <filter>       nearest, triangle or lanczos3 for the mip levels (default triangle)
+  info <directory>
+      Print the metadata of a texture and its size on disk.
+  verify <directory>
+      Read every page of a texture, and report the ones that are corrupted.
+  extract-page <directory> <mip> <x> <y> <output.png> [--layer <index>]
+      Decode a page, borders included, to an image.
+  help
+      Print this message.";
+
+type CliResult<T> = Result<T, Box<dyn Error>>;
+
+fn main() -> ExitCode {
+    let arguments = std::env::args().skip(1).collect::<Vec<_>>();
+    let Some((subcommand, arguments)) = arguments.split_first() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    let result = match subcommand.as_str() {
+        "import" => import(arguments),
+        "info" => info(arguments),
+        "verify" => verify(arguments),
+        "extract-page" => extract_page(arguments),
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => Err(format!("unknown subcommand `{subcommand}`").into()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The arguments of a subcommand: the positional ones, and the `--name value` and `--name`
+/// options.
+#[derive(Debug, Default, PartialEq)]
+struct Arguments {
+    positional: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Arguments {
+    /// Parse `arguments`, with `options` taking a value and `flags` taking none.
+    fn parse(arguments: &[String], options: &[&str], flags: &[&str]) -> CliResult<Self> {
+        let mut parsed = Self::default();
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            let Some(name) = argument.strip_prefix("--") else {
+                parsed.positional.push(argument.clone());
+                continue;
+            };
+            let value = if options.contains(&name) {
+                let value = arguments
+                    .next()
+                    .ok_or(format!("`--{name}` needs a value"))?;
+                Some(value.clone())
+            } else if flags.contains(&name) {
+                None
+            } else {
+                return Err(format!("unknown option `--{name}`").into());
+            };
+            parsed.options.push((name.to_owned(), value));
+        }
+        Ok(parsed)
+    }
+
+    /// The positional arguments, named by `names` in the error when there are not as many.
+    fn positional<const N: usize>(&self, names: [&str; N]) -> CliResult<[&str; N]> {
+        let positional = self
+            .positional
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        positional
+            .try_into()
+            .map_err(|_| format!("expected the arguments {}", names.join(" ")).into())
+    }
+
+    /// The value of the last `--name` option, if any.
+    fn value(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(option, _)| option == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(option, _)| option == name)
+    }
+}
+
+/// Parse `value`, named `name` in the error.
+fn parse<T: FromStr>(name: &str, value: &str) -> CliResult<T> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid {name} `{value}`").into())
+}
+
+fn import(arguments: &[String]) -> CliResult<()> {
+    let arguments = Arguments::parse(
+        arguments,
+        &["page-size", "border", "encoding", "fit", "filter"],
+        &["zstd"],
+    )?;
+    let [image, directory] = arguments.positional(["<image>", "<directory>"])?;
+
+    let defaults = TextureMetadata::from_dimensions((1, 1), 4);
+    let page_size = match arguments.value("page-size") {
+        Some(value) => parse("page size", value)?,
+        None => defaults.page_size_at(0),
+    };
+    let border_size = match arguments.value("border") {
+        Some(value) => parse("border", value)?,
+        None => defaults.border_size(),
+    };
+    if page_size % 4 != 0 || 2 * border_size >= page_size {
+        return Err("the page size must be a multiple of 4, and above twice the border".into());
+    }
+    let encoding = match arguments.value("encoding").unwrap_or("raw") {
+        "raw" => PageEncoding::Raw,
+        "bc7" => PageEncoding::Bc7,
+        "bc5" => PageEncoding::Bc5,
+        encoding => return Err(format!("invalid encoding `{encoding}`").into()),
+    };
+    let fit = match arguments.value("fit").unwrap_or("pad") {
+        "pad" => FitOperation::Pad,
+        "crop" => FitOperation::Crop,
+        "resize" => FitOperation::Resize,
+        fit => return Err(format!("invalid fit `{fit}`").into()),
+    };
+    let filter_mode = match arguments.value("filter").unwrap_or("triangle") {
+        "nearest" => FilterType::Nearest,
+        "triangle" => FilterType::Triangle,
+        "lanczos3" => FilterType::Lanczos3,
+        filter => return Err(format!("invalid filter `{filter}`").into()),
+    };
+    let compression = match arguments.flag("zstd") {
+        true => PageCompression::Zstd,
+        false => PageCompression::None,
+    };
+
+    let metadata = defaults
+        .with_page_size(page_size, border_size)
+        .with_encoding(encoding)
+        .with_compression(compression);
+    let options = ImportOptions::new(metadata, fit, filter_mode).with_directory(directory);
+    println!("importing {image} to {directory}");
+    let storage = TextureStorage::import_or_load(Path::new(image), &options)?;
+    print_metadata(storage.metadata());
+    Ok(())
+}
+
+fn info(arguments: &[String]) -> CliResult<()> {
+    let arguments = Arguments::parse(arguments, &[], &[])?;
+    let [directory] = arguments.positional(["<directory>"])?;
+    let storage = TextureStorage::load(Some(directory), None)?;
+    print_metadata(storage.metadata());
+
+    let stats = storage.stats()?;
+    println!(
+        "pages: {} ({} missing), {} bytes stored, {} bytes decompressed",
+        stats.pages, stats.missing_pages, stats.stored_bytes, stats.page_bytes
+    );
+    Ok(())
+}
+
+fn print_metadata(metadata: &TextureMetadata) {
+    let (width, height) = metadata.texel_dimensions();
+    let (pages_x, pages_y) = metadata.mip_dimensions(0);
+    println!("texels: {width}x{height}, {pages_x}x{pages_y} pages of mip level 0");
+    println!(
+        "pages: {} texels, borders of {}, {:?} texels, {:?} compression",
+        metadata.page_size_at(0),
+        metadata.border_size(),
+        metadata.texel_format(),
+        metadata.compression(),
+    );
+    metadata.layers().iter().for_each(|layer| {
+        println!(
+            "layer {}: {:?} encoding, {:?}",
+            layer.name,
+            layer.encoding,
+            layer.color_space()
+        );
+    });
+    (0..=metadata.mip_levels()).for_each(|mip| {
+        let (columns, rows) = metadata.page_grid(mip);
+        println!(
+            "mip level {mip}: {columns}x{rows} pages of {} texels",
+            metadata.page_size_at(mip)
+        );
+    });
+}
+
+fn verify(arguments: &[String]) -> CliResult<()> {
+    let arguments = Arguments::parse(arguments, &[], &[])?;
+    let [directory] = arguments.positional(["<directory>"])?;
+    let storage = TextureStorage::load(Some(directory), None)?;
+    let metadata = storage.metadata();
+
+    let mut corrupted = 0;
+    (0..=metadata.mip_levels()).for_each(|mip| {
+        let (columns, rows) = metadata.page_grid(mip);
+        (0..rows)
+            .flat_map(|y| (0..columns).map(move |x| PageId::new(mip, x, y)))
+            .for_each(|page| {
+                let error = match storage.read_page(page) {
+                    Ok(data) if data.len() == metadata.page_byte_size_at(mip) => return,
+                    Ok(data) => format!(
+                        "{} bytes instead of {}",
+                        data.len(),
+                        metadata.page_byte_size_at(mip)
+                    ),
+                    Err(error) => error.to_string(),
+                };
+                eprintln!("page {page:?}: {error}");
+                corrupted += 1;
+            });
+    });
+
+    match corrupted {
+        0 => {
+            println!("{} pages verified", metadata.page_count());
+            Ok(())
+        }
+        _ => Err(format!(
+            "{corrupted} of {} pages are corrupted",
+            metadata.page_count()
+        )
+        .into()),
+    }
+}
+
+fn extract_page(arguments: &[String]) -> CliResult<()> {
+    let arguments = Arguments::parse(arguments, &["layer"], &[])?;
+    let [directory, mip, x, y, output] =
+        arguments.positional(["<directory>", "<mip>", "<x>", "<y>", "<output.png>"])?;
+    let page = PageId::new(parse("mip level", mip)?, parse("x", x)?, parse("y", y)?);
+    let layer = match arguments.value("layer") {
+        Some(value) => parse("layer", value)?,
+        None => 0,
+    };
+    let storage = TextureStorage::load(Some(directory), None)?;
+    let metadata = storage.metadata();
+    let layers = metadata.layers();
+    let encoding = layers
+        .get(layer)
+        .ok_or(format!("the texture has {} layers", layers.len()))?
+        .encoding;
+
+    let data = storage.read_page(page)?;
+    let page_size = metadata.page_size_at(page.mip_level());
+    let texels = decode_page(
+        encoding,
+        metadata.split_layers(page.mip_level(), &data)[layer],
+        page_size as usize,
+    );
+    // Block compressed layers are decoded to RGBA8 whatever the format of the texture.
+    let texels = match encoding {
+        PageEncoding::Raw => metadata.texel_format().to_rgba8(texels),
+        PageEncoding::Bc7 | PageEncoding::Bc5 => TexelFormat::Rgba8.to_rgba8(texels),
+    };
+    image::RgbaImage::from_raw(page_size as u32, page_size as u32, texels)
+        .expect("the page to hold every texel")
+        .save(output)?;
+    println!("wrote page {page:?} to {output}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::Arguments;
+
+    fn strings(arguments: &[&str]) -> Vec<String> {
+        arguments
+            .iter()
+            .map(|&argument| argument.to_owned())
+            .collect()
+    }
+
+    /// Options are parsed wherever they are, and their values are not taken as positional
+    /// arguments.
+    #[test]
+    fn parse_arguments() {
+        let arguments = Arguments::parse(
+            &strings(&["map.png", "--page-size", "256", "--zstd", "out"]),
+            &["page-size"],
+            &["zstd"],
+        )
+        .unwrap();
+        assert_eq!(
+            arguments.positional(["a", "b"]).unwrap(),
+            ["map.png", "out"]
+        );
+        assert_eq!(arguments.value("page-size"), Some("256"));
+        assert!(arguments.flag("zstd"));
+        assert!(arguments.positional(["a"]).is_err());
+
+        assert!(Arguments::parse(&strings(&["--page-size"]), &["page-size"], &[]).is_err());
+        assert!(Arguments::parse(&strings(&["--unknown"]), &[], &[]).is_err());
+    }
+}
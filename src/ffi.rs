@@ -0,0 +1,1154 @@
+//! A C ABI over the context, storage, streaming and draw APIs, so that engines written in other
+//! languages embed the virtual texturing system. Enabled by the `ffi` feature.
+//!
+//! Every object is an opaque handle, created by a `vt_*_create` (or `vt_storage_*`) function and
+//! freed by its `vt_*_destroy` function. The functions return a [`VtResult`], and the message of
+//! the last error of the thread is kept for [`vt_last_error_message`]. Panics are caught at the
+//! boundary and reported as [`VtResult::Panic`].
+//!
+//! The devices of other renderers can not cross the boundary, so contexts either present to a
+//! native window of the engine (see [`vt_context_create_with_window`]), or are headless (see
+//! [`WgpuContext::headless`]) and their frames are read back with [`vt_context_read_target`].
+//!
+//! The header is generated in `include/virt_texture.h` with
+//! `cbindgen --config cbindgen.toml --output include/virt_texture.h`, and the library is built
+//! with `cargo rustc --release --lib --features ffi --crate-type cdylib` (or `staticlib`).
+//!
+//! The asset pipelines written in Python use the `virt_texture` module of `vt-storage` instead
+//! (see its `python` feature).
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    panic::AssertUnwindSafe,
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{
+    config::{ConfigError, VirtualTexturingConfig},
+    draw::{DrawItem, Mesh},
+    pipelines::CameraUniforms,
+    setup::{VirtualTexturingContext, WgpuContext},
+    storage::{FitOperation, ImportOptions, TextureMetadata, TextureStorage, TextureStorageError},
+    streaming::{PageId, StreamingHandle},
+    vertex::Vertex,
+};
+
+/// The outcome of a call, with the details in [`vt_last_error_message`] on failure.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VtResult {
+    Ok = 0,
+    /// A handle or an output pointer is null.
+    NullPointer,
+    /// A string is not valid UTF-8, or a size does not match.
+    InvalidArgument,
+    /// The configuration json could not be parsed.
+    Config,
+    /// A texture could not be read, imported or written.
+    Storage,
+    /// No graphics adapter is available.
+    NoAdapter,
+    /// The call panicked, and the objects it was given should be destroyed.
+    Panic,
+}
+
+/// How [`vt_storage_import_or_load`] fits an image, see [`FitOperation`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VtFitOperation {
+    Pad,
+    Crop,
+    Resize,
+}
+
+/// See [`Vertex`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VtVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tex_coords: [f32; 2],
+}
+
+/// A mesh drawn by [`vt_context_render_frame`], see [`DrawItem`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VtDrawItem {
+    pub mesh: *const VtMesh,
+    /// Column major, from the space of the mesh to world space.
+    pub transform: [f32; 16],
+    pub texture_id: u8,
+    /// See [`DrawItem::mip_range`], with `min_mip <= max_mip`. Set `max_mip` to 255 to request
+    /// down to the coarsest mip level.
+    pub min_mip: u8,
+    pub max_mip: u8,
+}
+
+/// The windowing system of a [`VtWindowHandle`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VtWindowSystem {
+    Win32,
+    Xlib,
+    Xcb,
+    Wayland,
+    AppKit,
+}
+
+/// A native window of the engine, presented to by [`vt_context_create_with_window`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VtWindowHandle {
+    pub system: VtWindowSystem,
+    /// The `HWND` (Win32), `wl_surface *` (Wayland) or `NSView *` (AppKit) of the window, unused
+    /// by Xlib and Xcb.
+    pub window: *mut c_void,
+    /// The `HINSTANCE` (Win32), `Display *` (Xlib), `xcb_connection_t *` (Xcb) or `wl_display *`
+    /// (Wayland), unused by AppKit.
+    pub display: *mut c_void,
+    /// The `Window` (Xlib) or `xcb_window_t` (Xcb) of the window.
+    pub window_id: u64,
+    /// The X11 screen (Xlib and Xcb).
+    pub screen: c_int,
+}
+
+unsafe impl raw_window_handle::HasRawWindowHandle for VtWindowHandle {
+    fn raw_window_handle(&self) -> raw_window_handle::RawWindowHandle {
+        use raw_window_handle::*;
+        match self.system {
+            VtWindowSystem::Win32 => {
+                let mut handle = Win32WindowHandle::empty();
+                handle.hwnd = self.window;
+                handle.hinstance = self.display;
+                RawWindowHandle::Win32(handle)
+            }
+            VtWindowSystem::Xlib => {
+                let mut handle = XlibWindowHandle::empty();
+                handle.window = self.window_id as _;
+                RawWindowHandle::Xlib(handle)
+            }
+            VtWindowSystem::Xcb => {
+                let mut handle = XcbWindowHandle::empty();
+                handle.window = self.window_id as u32;
+                RawWindowHandle::Xcb(handle)
+            }
+            VtWindowSystem::Wayland => {
+                let mut handle = WaylandWindowHandle::empty();
+                handle.surface = self.window;
+                RawWindowHandle::Wayland(handle)
+            }
+            VtWindowSystem::AppKit => {
+                let mut handle = AppKitWindowHandle::empty();
+                handle.ns_view = self.window;
+                RawWindowHandle::AppKit(handle)
+            }
+        }
+    }
+}
+
+unsafe impl raw_window_handle::HasRawDisplayHandle for VtWindowHandle {
+    fn raw_display_handle(&self) -> raw_window_handle::RawDisplayHandle {
+        use raw_window_handle::*;
+        match self.system {
+            VtWindowSystem::Win32 => RawDisplayHandle::Windows(WindowsDisplayHandle::empty()),
+            VtWindowSystem::Xlib => {
+                let mut handle = XlibDisplayHandle::empty();
+                handle.display = self.display;
+                handle.screen = self.screen;
+                RawDisplayHandle::Xlib(handle)
+            }
+            VtWindowSystem::Xcb => {
+                let mut handle = XcbDisplayHandle::empty();
+                handle.connection = self.display;
+                handle.screen = self.screen;
+                RawDisplayHandle::Xcb(handle)
+            }
+            VtWindowSystem::Wayland => {
+                let mut handle = WaylandDisplayHandle::empty();
+                handle.display = self.display;
+                RawDisplayHandle::Wayland(handle)
+            }
+            VtWindowSystem::AppKit => RawDisplayHandle::AppKit(AppKitDisplayHandle::empty()),
+        }
+    }
+}
+
+/// See [`PageId`].
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VtPageId {
+    pub texture_id: u8,
+    pub mip_level: u8,
+    pub x: u16,
+    pub y: u16,
+}
+
+impl From<PageId> for VtPageId {
+    fn from(page: PageId) -> Self {
+        Self {
+            texture_id: page.texture_id(),
+            mip_level: page.mip_level(),
+            x: page.x(),
+            y: page.y(),
+        }
+    }
+}
+
+/// See [`StreamingStats`](crate::streaming::StreamingStats).
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VtStreamingStats {
+    pub invalid_feedback_texels: u64,
+    pub dropped_feedback_requests: u64,
+    pub skipped_feedback_frames: u64,
+    pub uploaded_pages: u64,
+    pub requested_pages: u64,
+    pub missed_pages: u64,
+    pub evicted_pages: u64,
+    pub read_bytes: u64,
+    pub uploaded_bytes: u64,
+    pub staging_stalls: u64,
+    pub staging_stall_micros: u64,
+    pub feedback_in_flight: u64,
+}
+
+/// See [`StorageStats`](crate::storage::StorageStats).
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VtStorageStats {
+    pub pages: u64,
+    pub missing_pages: u64,
+    pub stored_bytes: u64,
+    pub page_bytes: u64,
+}
+
+pub struct VtContext(VirtualTexturingContext);
+
+pub struct VtMesh(Arc<Mesh>);
+
+pub struct VtStorage(TextureStorage);
+
+pub struct VtStreaming(StreamingHandle);
+
+/// A failed call, recorded for [`vt_last_error_message`].
+struct FfiError {
+    result: VtResult,
+    message: String,
+}
+
+impl FfiError {
+    fn new(result: VtResult, message: impl Into<String>) -> Self {
+        Self {
+            result,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<TextureStorageError> for FfiError {
+    fn from(error: TextureStorageError) -> Self {
+        Self::new(VtResult::Storage, error.to_string())
+    }
+}
+
+impl From<ConfigError> for FfiError {
+    fn from(error: ConfigError) -> Self {
+        Self::new(VtResult::Config, error.to_string())
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Run `call`, recording its error or panic for [`vt_last_error_message`].
+fn ffi_call(call: impl FnOnce() -> Result<(), FfiError>) -> VtResult {
+    let error = match std::panic::catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(())) => return VtResult::Ok,
+        Ok(Err(error)) => error,
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            FfiError::new(VtResult::Panic, format!("panicked: {message}"))
+        }
+    };
+    log::error!("{}", error.message);
+    let message = CString::new(error.message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+    error.result
+}
+
+/// The object behind a handle.
+///
+/// ### Safety
+///
+/// `handle` must be null or point to a live object, not aliased for `'a`.
+unsafe fn handle<'a, T>(handle: *mut T) -> Result<&'a mut T, FfiError> {
+    handle
+        .as_mut()
+        .ok_or_else(|| FfiError::new(VtResult::NullPointer, "null handle"))
+}
+
+/// The object behind a handle only read from, which may be passed several times to a call.
+///
+/// ### Safety
+///
+/// `handle` must be null or point to a live object, not mutated for `'a`.
+unsafe fn shared_handle<'a, T>(handle: *const T) -> Result<&'a T, FfiError> {
+    handle
+        .as_ref()
+        .ok_or_else(|| FfiError::new(VtResult::NullPointer, "null handle"))
+}
+
+/// Write a new object to the output pointer `out`.
+///
+/// ### Safety
+///
+/// `out` must be null or valid for writes.
+unsafe fn write_handle<T>(out: *mut *mut T, object: T) -> Result<(), FfiError> {
+    if out.is_null() {
+        return Err(FfiError::new(VtResult::NullPointer, "null output pointer"));
+    }
+    *out = Box::into_raw(Box::new(object));
+    Ok(())
+}
+
+/// ### Safety
+///
+/// `string` must be null or a nul terminated string.
+unsafe fn optional_str<'a>(string: *const c_char) -> Result<Option<&'a str>, FfiError> {
+    if string.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map(Some)
+        .map_err(|error| FfiError::new(VtResult::InvalidArgument, error.to_string()))
+}
+
+/// Write the first `capacity` of `list` to `pages`, and the length of `list` to `count`.
+///
+/// ### Safety
+///
+/// `pages` must be valid for writes of `capacity` pages (or null if `capacity` is 0), and
+/// `count` null or valid for writes.
+unsafe fn write_pages(
+    list: Vec<PageId>,
+    pages: *mut VtPageId,
+    capacity: usize,
+    count: *mut usize,
+) -> Result<(), FfiError> {
+    *handle(count)? = list.len();
+    if capacity > 0 {
+        if pages.is_null() {
+            return Err(FfiError::new(VtResult::NullPointer, "null pages"));
+        }
+        std::slice::from_raw_parts_mut(pages, capacity)
+            .iter_mut()
+            .zip(list)
+            .for_each(|(out, page)| *out = page.into());
+    }
+    Ok(())
+}
+
+/// Free an object created by the functions of this module.
+///
+/// ### Safety
+///
+/// `handle` must be null or created by this module, and not used afterwards.
+unsafe fn destroy<T>(handle: *mut T) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// The message of the last failed call of the thread, or null if none failed. Valid until the
+/// next failed call of the thread.
+#[no_mangle]
+pub extern "C" fn vt_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Create a context rendering to an offscreen target of `width` by `height` texels, configured
+/// by the json `config` (see [`VirtualTexturingConfig`]), or the default configuration if null.
+///
+/// # Safety
+///
+/// `config` must be null or a nul terminated string, and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vt_context_create_headless(
+    width: u32,
+    height: u32,
+    config: *const c_char,
+    out: *mut *mut VtContext,
+) -> VtResult {
+    ffi_call(|| {
+        let config = match optional_str(config)? {
+            Some(json) => VirtualTexturingConfig::from_json(json)?,
+            None => VirtualTexturingConfig::default(),
+        };
+        let wgpu_context = pollster::block_on(WgpuContext::headless(width, height))
+            .ok_or_else(|| FfiError::new(VtResult::NoAdapter, "no adapter available"))?;
+        let context = VirtualTexturingContext::from_config(Arc::new(wgpu_context), config);
+        write_handle(out, VtContext(context))
+    })
+}
+
+/// Create a context presenting to `window`, whose surface is `width` by `height` texels until
+/// [`vt_context_resize`] is called, configured by the json `config` (see
+/// [`VirtualTexturingConfig`]), or the default configuration if null. The frames rendered by
+/// [`vt_context_render_frame`] are presented to the window.
+///
+/// # Safety
+///
+/// `window` must point to a window handle whose window stays valid until the context is
+/// destroyed, `config` must be null or a nul terminated string, and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vt_context_create_with_window(
+    window: *const VtWindowHandle,
+    width: u32,
+    height: u32,
+    config: *const c_char,
+    out: *mut *mut VtContext,
+) -> VtResult {
+    ffi_call(|| {
+        let window = shared_handle(window)?;
+        let config = match optional_str(config)? {
+            Some(json) => VirtualTexturingConfig::from_json(json)?,
+            None => VirtualTexturingConfig::default(),
+        };
+        let size = winit::dpi::PhysicalSize::new(width, height);
+        let wgpu_context = pollster::block_on(WgpuContext::from_window_handle(window, size))
+            .ok_or_else(|| {
+                FfiError::new(VtResult::NoAdapter, "no adapter can present to the window")
+            })?;
+        let context = VirtualTexturingContext::from_config(Arc::new(wgpu_context), config);
+        write_handle(out, VtContext(context))
+    })
+}
+
+/// Resize the surface of a context created by [`vt_context_create_with_window`], see
+/// [`VirtualTexturingContext::resize`].
+///
+/// # Safety
+///
+/// `context` must be a live context.
+#[no_mangle]
+pub unsafe extern "C" fn vt_context_resize(
+    context: *mut VtContext,
+    width: u32,
+    height: u32,
+) -> VtResult {
+    ffi_call(|| {
+        let VtContext(context) = handle(context)?;
+        if context.wgpu_context.offscreen_target.is_some() {
+            return Err(FfiError::new(
+                VtResult::InvalidArgument,
+                "headless contexts can not be resized",
+            ));
+        }
+        context.resize(winit::dpi::PhysicalSize::new(width, height));
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// `context` must be null or created by [`vt_context_create_headless`] or
+/// [`vt_context_create_with_window`], and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn vt_context_destroy(context: *mut VtContext) {
+    destroy(context)
+}
+
+/// Set the column major view projection matrix of the following frames.
+///
+/// # Safety
+///
+/// `context` must be a live context, and `view_proj` point to 16 floats.
+#[no_mangle]
+pub unsafe extern "C" fn vt_context_set_view_proj(
+    context: *mut VtContext,
+    view_proj: *const f32,
+) -> VtResult {
+    ffi_call(|| {
+        let VtContext(context) = handle(context)?;
+        if view_proj.is_null() {
+            return Err(FfiError::new(VtResult::NullPointer, "null matrix"));
+        }
+        let view_proj =
+            nalgebra::Matrix4::from_column_slice(std::slice::from_raw_parts(view_proj, 16));
+        context.wgpu_context.queue.write_buffer(
+            &context.pipelines.camera_buffer,
+            0,
+            bytemuck::bytes_of(&CameraUniforms {
+                view_proj: view_proj.into(),
+            }),
+        );
+        Ok(())
+    })
+}
+
+/// Render a frame of the `item_count` items of `items`, and read back its feedback with
+/// `streaming` if not null (see [`VirtualTexturingContext::begin_frame`]). The frame is presented
+/// if the context has a window.
+///
+/// # Safety
+///
+/// `context` must be a live context, `items` point to `item_count` items whose meshes are live
+/// (or be null if `item_count` is 0), and `streaming` be null or a live streaming handle of
+/// `context`.
+#[no_mangle]
+pub unsafe extern "C" fn vt_context_render_frame(
+    context: *mut VtContext,
+    items: *const VtDrawItem,
+    item_count: usize,
+    streaming: *mut VtStreaming,
+) -> VtResult {
+    ffi_call(|| {
+        let VtContext(context) = handle(context)?;
+        let items = match item_count {
+            0 => &[],
+            _ if items.is_null() => return Err(FfiError::new(VtResult::NullPointer, "null items")),
+            _ => std::slice::from_raw_parts(items, item_count),
+        };
+        let items = items
+            .iter()
+            .map(|item| {
+                let VtMesh(mesh) = shared_handle(item.mesh)?;
+                if item.min_mip > item.max_mip {
+                    return Err(FfiError::new(
+                        VtResult::InvalidArgument,
+                        "min_mip is past max_mip",
+                    ));
+                }
+                Ok(DrawItem::new(Arc::clone(mesh))
+                    .with_transform(nalgebra::Matrix4::from_column_slice(&item.transform))
+                    .with_texture_id(item.texture_id)
+                    .with_mip_range(item.min_mip..=item.max_mip))
+            })
+            .collect::<Result<Vec<_>, FfiError>>()?;
+        let streaming = streaming.as_mut().map(|VtStreaming(streaming)| streaming);
+        let frame = context.begin_frame(&items);
+        if let Some(output) = context.end_frame(frame, streaming) {
+            output.present();
+        }
+        Ok(())
+    })
+}
+
+/// Copy the RGBA8 texels of the last frame of a headless context to `texels`,
+/// `width * height * 4` bytes long, blocking until the frame is rendered.
+///
+/// # Safety
+///
+/// `context` must be a live context, and `texels` valid for writes of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn vt_context_read_target(
+    context: *mut VtContext,
+    texels: *mut u8,
+    len: usize,
+) -> VtResult {
+    ffi_call(|| {
+        let VtContext(context) = handle(context)?;
+        if context.wgpu_context.offscreen_target.is_none() {
+            return Err(FfiError::new(
+                VtResult::InvalidArgument,
+                "the frames of contexts with a window are presented, not read back",
+            ));
+        }
+        let image = context
+            .wgpu_context
+            .read_offscreen_target()
+            .map_err(|error| FfiError::new(VtResult::InvalidArgument, error.to_string()))?;
+        if texels.is_null() {
+            return Err(FfiError::new(VtResult::NullPointer, "null texels"));
+        }
+        if len != image.len() {
+            return Err(FfiError::new(
+                VtResult::InvalidArgument,
+                format!("the target takes {} bytes, not {len}", image.len()),
+            ));
+        }
+        std::slice::from_raw_parts_mut(texels, len).copy_from_slice(&image);
+        Ok(())
+    })
+}
+
+/// Upload a triangle list of `vertex_count` vertices.
+///
+/// # Safety
+///
+/// `context` must be a live context, `vertices` point to `vertex_count` vertices, and `out` be
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vt_mesh_create(
+    context: *mut VtContext,
+    vertices: *const VtVertex,
+    vertex_count: usize,
+    out: *mut *mut VtMesh,
+) -> VtResult {
+    ffi_call(|| {
+        let VtContext(context) = handle(context)?;
+        if vertices.is_null() {
+            return Err(FfiError::new(VtResult::NullPointer, "null vertices"));
+        }
+        let vertices = std::slice::from_raw_parts(vertices, vertex_count)
+            .iter()
+            .map(|vertex| Vertex::new(vertex.position, vertex.normal, vertex.tex_coords))
+            .collect::<Vec<_>>();
+        write_handle(
+            out,
+            VtMesh(Arc::new(Mesh::new(&context.wgpu_context, &vertices))),
+        )
+    })
+}
+
+/// The frames rendered before keep the mesh alive until they are done.
+///
+/// # Safety
+///
+/// `mesh` must be null or created by [`vt_mesh_create`], and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn vt_mesh_destroy(mesh: *mut VtMesh) {
+    destroy(mesh)
+}
+
+/// Load the texture in `directory`, see [`TextureStorage::load`].
+///
+/// # Safety
+///
+/// `directory` must be a nul terminated string, and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vt_storage_load(
+    directory: *const c_char,
+    out: *mut *mut VtStorage,
+) -> VtResult {
+    ffi_call(|| {
+        let directory = optional_str(directory)?
+            .ok_or_else(|| FfiError::new(VtResult::NullPointer, "null directory"))?;
+        write_handle(out, VtStorage(TextureStorage::load(Some(directory), None)?))
+    })
+}
+
+/// Import the image at `source_path` to `directory` with pages of `page_size` texels, borders
+/// of `border_size` included, or load it if it is unchanged since the last import (see
+/// [`TextureStorage::import_or_load`]). `page_size` must be a multiple of 4, and above twice
+/// `border_size`.
+///
+/// # Safety
+///
+/// `source_path` and `directory` must be nul terminated strings, and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vt_storage_import_or_load(
+    source_path: *const c_char,
+    directory: *const c_char,
+    page_size: u16,
+    border_size: u16,
+    fit: VtFitOperation,
+    out: *mut *mut VtStorage,
+) -> VtResult {
+    ffi_call(|| {
+        let null = || FfiError::new(VtResult::NullPointer, "null path");
+        let source_path = optional_str(source_path)?.ok_or_else(null)?;
+        let directory = optional_str(directory)?.ok_or_else(null)?;
+        if !page_size.is_multiple_of(4) || 2 * border_size as u32 >= page_size as u32 {
+            return Err(FfiError::new(
+                VtResult::InvalidArgument,
+                "the page size must be a multiple of 4, and above twice the border",
+            ));
+        }
+        let fit = match fit {
+            VtFitOperation::Pad => FitOperation::Pad,
+            VtFitOperation::Crop => FitOperation::Crop,
+            VtFitOperation::Resize => FitOperation::Resize,
+        };
+        let options = ImportOptions::new(
+            TextureMetadata::from_mip(0, 4).with_page_size(page_size, border_size),
+            fit,
+            image::imageops::FilterType::Triangle,
+        )
+        .with_directory(directory);
+        let storage = TextureStorage::import_or_load(Path::new(source_path), &options)?;
+        write_handle(out, VtStorage(storage))
+    })
+}
+
+/// The size of the texture in texels, outer border included.
+///
+/// # Safety
+///
+/// `storage` must be a live storage, and `width` and `height` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vt_storage_texel_dimensions(
+    storage: *mut VtStorage,
+    width: *mut u32,
+    height: *mut u32,
+) -> VtResult {
+    ffi_call(|| {
+        let VtStorage(storage) = handle(storage)?;
+        let (texel_width, texel_height) = storage.metadata().texel_dimensions();
+        *handle(width)? = texel_width;
+        *handle(height)? = texel_height;
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// `storage` must be a live storage, and `stats` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vt_storage_stats(
+    storage: *mut VtStorage,
+    stats: *mut VtStorageStats,
+) -> VtResult {
+    ffi_call(|| {
+        let VtStorage(storage) = handle(storage)?;
+        let storage_stats = storage.stats()?;
+        *handle(stats)? = VtStorageStats {
+            pages: storage_stats.pages as u64,
+            missing_pages: storage_stats.missing_pages as u64,
+            stored_bytes: storage_stats.stored_bytes,
+            page_bytes: storage_stats.page_bytes,
+        };
+        Ok(())
+    })
+}
+
+/// Save mip level `mip` to the image at `path`, in the format of its extension (see
+/// [`TextureStorage::export_mip`]).
+///
+/// # Safety
+///
+/// `storage` must be a live storage, and `path` a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn vt_storage_export_mip(
+    storage: *mut VtStorage,
+    mip: u8,
+    path: *const c_char,
+) -> VtResult {
+    ffi_call(|| {
+        let VtStorage(storage) = handle(storage)?;
+        let path =
+            optional_str(path)?.ok_or_else(|| FfiError::new(VtResult::NullPointer, "null path"))?;
+        storage
+            .export_mip(mip)?
+            .save(path)
+            .map_err(TextureStorageError::from)?;
+        Ok(())
+    })
+}
+
+/// Copy the pages of `storage` that differ from the ones of `other` (see
+/// [`TextureStorage::diff`]) to `pages`, at most `capacity` of them, and write their number to
+/// `count`, which may exceed `capacity`.
+///
+/// # Safety
+///
+/// `storage` and `other` must be live storages, which may be the same, `pages` valid for writes
+/// of `capacity` pages (or null if `capacity` is 0), and `count` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vt_storage_diff(
+    storage: *const VtStorage,
+    other: *const VtStorage,
+    pages: *mut VtPageId,
+    capacity: usize,
+    count: *mut usize,
+) -> VtResult {
+    ffi_call(|| {
+        let VtStorage(storage) = shared_handle(storage)?;
+        let VtStorage(other) = shared_handle(other)?;
+        let changed = storage.diff(other)?;
+        write_pages(changed, pages, capacity, count)
+    })
+}
+
+/// The streaming handles created from the storage keep reading its pages.
+///
+/// # Safety
+///
+/// `storage` must be null or created by a `vt_storage_*` function, and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn vt_storage_destroy(storage: *mut VtStorage) {
+    destroy(storage)
+}
+
+/// Stream the pages of `storage` to the physical textures of `context`, see
+/// [`StreamingHandle::new`].
+///
+/// # Safety
+///
+/// `context` and `storage` must be live, and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vt_streaming_create(
+    context: *mut VtContext,
+    storage: *mut VtStorage,
+    out: *mut *mut VtStreaming,
+) -> VtResult {
+    ffi_call(|| {
+        let VtContext(context) = handle(context)?;
+        let VtStorage(storage) = handle(storage)?;
+        let streaming = StreamingHandle::new(
+            Arc::clone(&context.wgpu_context),
+            context.textures(),
+            storage.reader(),
+        );
+        write_handle(out, VtStreaming(streaming))
+    })
+}
+
+/// # Safety
+///
+/// `streaming` must be a live streaming handle, and `stats` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vt_streaming_stats(
+    streaming: *mut VtStreaming,
+    stats: *mut VtStreamingStats,
+) -> VtResult {
+    ffi_call(|| {
+        let VtStreaming(streaming) = handle(streaming)?;
+        let streaming_stats = streaming.stats();
+        *handle(stats)? = VtStreamingStats {
+            invalid_feedback_texels: streaming_stats.invalid_feedback_texels,
+            dropped_feedback_requests: streaming_stats.dropped_feedback_requests,
+            skipped_feedback_frames: streaming_stats.skipped_feedback_frames,
+            uploaded_pages: streaming_stats.uploaded_pages,
+            requested_pages: streaming_stats.requested_pages,
+            missed_pages: streaming_stats.missed_pages,
+            evicted_pages: streaming_stats.evicted_pages,
+            read_bytes: streaming_stats.read_bytes,
+            uploaded_bytes: streaming_stats.uploaded_bytes,
+            staging_stalls: streaming_stats.staging_stalls,
+            staging_stall_micros: streaming_stats.staging_stall_micros,
+            feedback_in_flight: streaming_stats.feedback_in_flight,
+        };
+        Ok(())
+    })
+}
+
+/// Read `page` from storage, upload it to `slot` and point the page table to it (see
+/// [`StreamingHandle::stream_page`] and [`StreamingHandle::map_page`]).
+///
+/// Returns [`VtResult::InvalidArgument`] if `slot` is outside of the slots of the physical
+/// textures holding the pages of its mip level (see [`StreamingHandle::slot_layout`]).
+///
+/// # Safety
+///
+/// `streaming` must be a live streaming handle.
+#[no_mangle]
+pub unsafe extern "C" fn vt_streaming_stream_page(
+    streaming: *mut VtStreaming,
+    page: VtPageId,
+    slot_x: u32,
+    slot_y: u32,
+) -> VtResult {
+    ffi_call(|| {
+        let VtStreaming(streaming) = handle(streaming)?;
+        let page = PageId::with_texture_id(page.texture_id, page.mip_level, page.x, page.y);
+        let layout = streaming.slot_layout(page.mip_level());
+        if slot_x >= layout.page_slots_x || slot_y >= layout.page_slots_y * layout.layers {
+            return Err(FfiError::new(
+                VtResult::InvalidArgument,
+                format!(
+                    "slot ({slot_x}, {slot_y}) outside of the {}x{} slots",
+                    layout.page_slots_x,
+                    layout.page_slots_y * layout.layers
+                ),
+            ));
+        }
+        streaming.stream_page(page, (slot_x, slot_y))?;
+        streaming.map_page(page, (slot_x, slot_y));
+        Ok(())
+    })
+}
+
+/// Copy the pages requested by the last feedback read back (see
+/// [`StreamingHandle::request_traces`]) to `pages`, at most `capacity` of them, and write their
+/// number to `count`, which may exceed `capacity`.
+///
+/// # Safety
+///
+/// `streaming` must be a live streaming handle, `pages` valid for writes of `capacity` pages (or
+/// null if `capacity` is 0), and `count` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vt_streaming_requested_pages(
+    streaming: *mut VtStreaming,
+    pages: *mut VtPageId,
+    capacity: usize,
+    count: *mut usize,
+) -> VtResult {
+    ffi_call(|| {
+        let VtStreaming(streaming) = handle(streaming)?;
+        let requests = streaming.request_traces().pop().unwrap_or_default();
+        write_pages(requests, pages, capacity, count)
+    })
+}
+
+/// Stops the streaming thread.
+///
+/// # Safety
+///
+/// `streaming` must be null or created by [`vt_streaming_create`], and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn vt_streaming_destroy(streaming: *mut VtStreaming) {
+    destroy(streaming)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{ffi::CStr, ptr::null_mut};
+
+    use assert_fs::{fixture::TempDir, prelude::*};
+
+    use super::*;
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(vt_last_error_message()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn errors() {
+        let mut storage = null_mut();
+        let result = unsafe { vt_storage_load(c"/no/such/texture".as_ptr(), &mut storage) };
+        assert_eq!(result, VtResult::Storage);
+        assert!(storage.is_null());
+        assert!(last_error().contains("io error"));
+        for (page_size, border_size) in [(10, 2), (16, 8)] {
+            let result = unsafe {
+                vt_storage_import_or_load(
+                    c"source.png".as_ptr(),
+                    c"texture".as_ptr(),
+                    page_size,
+                    border_size,
+                    VtFitOperation::Pad,
+                    &mut storage,
+                )
+            };
+            assert_eq!(result, VtResult::InvalidArgument);
+            assert!(storage.is_null());
+        }
+
+        let mut context = null_mut();
+        let result =
+            unsafe { vt_context_create_headless(64, 64, c"{\"page_size\"".as_ptr(), &mut context) };
+        assert_eq!(result, VtResult::Config);
+        assert_eq!(
+            unsafe { vt_context_set_view_proj(null_mut(), [0.0; 16].as_ptr()) },
+            VtResult::NullPointer
+        );
+        assert_eq!(last_error(), "null handle");
+        let result = unsafe {
+            vt_context_create_with_window(std::ptr::null(), 64, 64, std::ptr::null(), &mut context)
+        };
+        assert_eq!(result, VtResult::NullPointer);
+    }
+
+    #[test]
+    fn stream_through_ffi() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.child("source.png");
+        // 2x2 pages of 128 texels, mapped by a page table of the same size.
+        image::RgbaImage::from_pixel(248, 248, image::Rgba([255, 0, 0, 255]))
+            .save(source.path())
+            .unwrap();
+        let source_path = CString::new(source.path().to_str().unwrap()).unwrap();
+        let directory = CString::new(temp_dir.child("texture").path().to_str().unwrap()).unwrap();
+        let config = CString::new(
+            VirtualTexturingConfig {
+                page_table_size: 2,
+                // Single layer textures are not arrays on GL, where array views of them read zeros.
+                virtual_textures: 2,
+                physical_texture: crate::config::PhysicalTextureConfig {
+                    page_slots_x: 2,
+                    page_slots_y: 2,
+                    layers: 2,
+                },
+                ..Default::default()
+            }
+            .to_json(),
+        )
+        .unwrap();
+
+        unsafe {
+            let mut context = null_mut();
+            match vt_context_create_headless(64, 64, config.as_ptr(), &mut context) {
+                VtResult::NoAdapter => {
+                    vt_runtime::test_support::skip_without_adapter();
+                    return;
+                }
+                result => assert_eq!(result, VtResult::Ok),
+            }
+            let mut storage = null_mut();
+            let result = vt_storage_import_or_load(
+                source_path.as_ptr(),
+                directory.as_ptr(),
+                128,
+                4,
+                VtFitOperation::Pad,
+                &mut storage,
+            );
+            assert_eq!(result, VtResult::Ok);
+            let (mut width, mut height) = (0, 0);
+            assert_eq!(
+                vt_storage_texel_dimensions(storage, &mut width, &mut height),
+                VtResult::Ok
+            );
+            assert_eq!((width, height), (248, 248));
+            assert_eq!(
+                vt_context_resize(context, 32, 32),
+                VtResult::InvalidArgument
+            );
+
+            let mut streaming = null_mut();
+            assert_eq!(
+                vt_streaming_create(context, storage, &mut streaming),
+                VtResult::Ok
+            );
+            let vertices = crate::vertex::FOUR_TRIANGLES.map(|vertex| {
+                let [x, y, z, nx, ny, nz, u, v] = bytemuck::cast::<_, [f32; 8]>(vertex);
+                VtVertex {
+                    position: [x, y, z],
+                    normal: [nx, ny, nz],
+                    tex_coords: [u, v],
+                }
+            });
+            let mut mesh = null_mut();
+            assert_eq!(
+                vt_mesh_create(context, vertices.as_ptr(), vertices.len(), &mut mesh),
+                VtResult::Ok
+            );
+            let identity: [f32; 16] = nalgebra::Matrix4::identity().as_slice().try_into().unwrap();
+            assert_eq!(
+                vt_context_set_view_proj(context, identity.as_ptr()),
+                VtResult::Ok
+            );
+            let item = VtDrawItem {
+                mesh,
+                transform: identity,
+                texture_id: 0,
+                min_mip: 0,
+                max_mip: u8::MAX,
+            };
+
+            let page = VtPageId::default();
+            assert_eq!(
+                vt_streaming_stream_page(streaming, page, 0, 0),
+                VtResult::Ok
+            );
+            let outside = VtPageId { x: 7, ..page };
+            assert_eq!(
+                vt_streaming_stream_page(streaming, outside, 1, 0),
+                VtResult::Storage
+            );
+            assert_eq!(
+                vt_streaming_stream_page(streaming, page, 0, u32::MAX),
+                VtResult::InvalidArgument
+            );
+            (0..3).for_each(|_| {
+                assert_eq!(
+                    vt_context_render_frame(context, &item, 1, streaming),
+                    VtResult::Ok
+                )
+            });
+            let mut stats = VtStreamingStats::default();
+            assert_eq!(vt_streaming_stats(streaming, &mut stats), VtResult::Ok);
+            assert_eq!(stats.uploaded_pages, 1);
+            let mut count = usize::MAX;
+            assert_eq!(
+                vt_streaming_requested_pages(streaming, null_mut(), 0, &mut count),
+                VtResult::Ok
+            );
+            assert_ne!(count, usize::MAX);
+
+            let mut texels = vec![0; 64 * 64 * 4];
+            assert_eq!(
+                vt_context_read_target(context, texels.as_mut_ptr(), 16),
+                VtResult::InvalidArgument
+            );
+            assert_eq!(
+                vt_context_read_target(context, texels.as_mut_ptr(), texels.len()),
+                VtResult::Ok
+            );
+            // The page covers the top left quarter of the texture, in the top left triangle.
+            let texel = (8 * 64 + 8) * 4;
+            assert_eq!(texels[texel..texel + 4], [255, 0, 0, 255]);
+
+            vt_mesh_destroy(mesh);
+            vt_streaming_destroy(streaming);
+            vt_storage_destroy(storage);
+            vt_context_destroy(context);
+        }
+    }
+
+    #[test]
+    fn inspect_through_ffi() {
+        let temp_dir = TempDir::new().unwrap();
+        let import = |name: &str, color| {
+            let source = temp_dir.child(format!("{name}.png"));
+            image::RgbaImage::from_pixel(120, 120, image::Rgba(color))
+                .save(source.path())
+                .unwrap();
+            let source_path = CString::new(source.path().to_str().unwrap()).unwrap();
+            let directory = CString::new(temp_dir.child(name).path().to_str().unwrap()).unwrap();
+            let mut storage = null_mut();
+            let result = unsafe {
+                vt_storage_import_or_load(
+                    source_path.as_ptr(),
+                    directory.as_ptr(),
+                    128,
+                    4,
+                    VtFitOperation::Pad,
+                    &mut storage,
+                )
+            };
+            assert_eq!(result, VtResult::Ok);
+            storage
+        };
+        let red = import("red", [255, 0, 0, 255]);
+        let blue = import("blue", [0, 0, 255, 255]);
+
+        unsafe {
+            let mut stats = VtStorageStats::default();
+            assert_eq!(vt_storage_stats(red, &mut stats), VtResult::Ok);
+            assert_eq!(stats.pages, 1);
+            assert_eq!(stats.page_bytes, 128 * 128 * 4);
+
+            let exported = temp_dir.child("red-0.png");
+            let path = CString::new(exported.path().to_str().unwrap()).unwrap();
+            assert_eq!(vt_storage_export_mip(red, 0, path.as_ptr()), VtResult::Ok);
+            let image = image::open(exported.path()).unwrap().into_rgba8();
+            assert_eq!(image.get_pixel(60, 60).0, [255, 0, 0, 255]);
+            assert_eq!(
+                vt_storage_export_mip(red, 1, path.as_ptr()),
+                VtResult::Storage
+            );
+
+            let mut pages = [VtPageId::default(); 4];
+            let mut count = 0;
+            let result = vt_storage_diff(red, blue, pages.as_mut_ptr(), pages.len(), &mut count);
+            assert_eq!(result, VtResult::Ok);
+            assert_eq!(count, 1);
+            assert_eq!(pages[0], VtPageId::default());
+            assert_eq!(
+                vt_storage_diff(red, red, null_mut(), 0, &mut count),
+                VtResult::Ok
+            );
+            assert_eq!(count, 0);
+
+            vt_storage_destroy(red);
+            vt_storage_destroy(blue);
+        }
+    }
+}
@@ -1,16 +1,8 @@
-pub mod camera;
-pub mod pipelines;
-pub mod setup;
-pub mod storage;
-pub mod streaming;
-pub mod textures;
-pub mod vertex;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 
-#[macro_export]
-macro_rules! ensure {
-    ($cond:expr, $err:expr) => {
-        if !$cond {
-            return Err($err.into());
-        }
-    }
-}
+pub use vt_core::ensure;
+pub use vt_runtime::{
+    camera, config, debug, draw, pipelines, power, profiler, scene, setup, shader_constants,
+    storage, streaming, texture_generation, textures, vertex,
+};